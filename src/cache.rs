@@ -0,0 +1,484 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, parse_color, ColorConfig, ConfigSource};
+use crate::context::Context;
+use crate::error::TermtintError;
+use crate::explain::Explanation;
+use crate::state;
+use crate::user_config::{AutoHashSource, UserConfig};
+
+/// Number of directories remembered; the least recently resolved are dropped.
+const CACHE_LIMIT: usize = 256;
+
+/// Resolved colors for a trigger directory, and the fingerprint of the
+/// generation parameters they were computed with.
+#[derive(Debug, Clone, PartialEq)]
+struct CacheEntry {
+    fingerprint: u64,
+    /// The directory, as written by `state::encode_path`
+    dir: String,
+    colors: ColorConfig,
+}
+
+/// Get the color cache file path within a state directory.
+fn cache_path_in(root: &Path) -> PathBuf {
+    root.join("color_cache")
+}
+
+/// Fingerprint everything besides the directory that an auto color is generated
+/// from: the termtint version, the user config, the hostname when it is hashed in,
+/// and the matched trigger (whose options pick the color or hue band).
+/// Returns None for sources whose colors also depend on file contents (`.termtint`
+/// files, VS Code settings, package-name hashing, parent blending) and for
+/// environment and kube context triggers, which have no directory; those are
+/// never cached.
+fn generation_fingerprint(source: &ConfigSource, user_config: &UserConfig) -> Option<u64> {
+    if matches!(
+        source,
+        ConfigSource::Termtint(_)
+            | ConfigSource::VsCode(_)
+            | ConfigSource::Environment { .. }
+            | ConfigSource::KubeContext { .. }
+    ) || user_config.blend_with_parent > 0.0
+        || user_config.auto_hash_source == AutoHashSource::PackageName
+    {
+        return None;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    hash_generation_settings(user_config, &mut hasher);
+    format!("{:?}", config::source_trigger(source, user_config)).hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Hash what every generated color depends on besides its source: the termtint
+/// version, the user config, and the hostname when it is hashed in.
+fn hash_generation_settings(user_config: &UserConfig, hasher: &mut DefaultHasher) {
+    env!("CARGO_PKG_VERSION").hash(hasher);
+    // The Debug form covers every setting, so new ones invalidate the cache
+    // without having to be listed here
+    format!("{:?}", user_config).hash(hasher);
+    if user_config.hash_include_hostname {
+        config::hostname().hash(hasher);
+    }
+}
+
+/// Fingerprint a `.termtint` file's contents together with its path and the
+/// generation settings, which together decide how it parses, and the modification
+/// time of any `.itermcolors` preset it references. Returns None when parsing can
+/// read other files (package-name hashing, parent blending).
+fn termtint_fingerprint(path: &Path, content: &str, user_config: &UserConfig) -> Option<u64> {
+    if user_config.blend_with_parent > 0.0
+        || user_config.auto_hash_source == AutoHashSource::PackageName
+    {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    hash_generation_settings(user_config, &mut hasher);
+    path.hash(&mut hasher);
+    content.hash(&mut hasher);
+    if let Some(preset) = config::preset_reference(path, content, user_config) {
+        state::get_file_mtime(&preset).hash(&mut hasher);
+    }
+    Some(hasher.finish())
+}
+
+/// The file in the session's state directory holding the last parsed `.termtint`.
+fn parsed_termtint_path(ctx: &Context) -> PathBuf {
+    state::state_file_path(ctx).with_file_name("parsed_termtint")
+}
+
+/// Read a `.termtint` and parse it, reusing the colors this session last parsed
+/// when the contents (and everything else they parse with) hash the same.
+pub fn parse_termtint_cached(
+    ctx: &Context,
+    path: &Path,
+    user_config: &UserConfig,
+    explain: &mut Explanation,
+) -> Result<ColorConfig, TermtintError> {
+    let content = config::read_config(path)?;
+    if config::is_empty_config(&content) {
+        explain.note(|| format!("{} is empty: using an auto color", path.display()));
+    }
+    if !state::state_writable(ctx) {
+        explain.note(|| "parsed .termtint cache: state directory not writable".to_string());
+        return config::parse_config_content(path, &content, user_config);
+    }
+    parse_termtint_cached_at(
+        &parsed_termtint_path(ctx),
+        path,
+        &content,
+        user_config,
+        explain,
+    )
+}
+
+/// Parse `content`, read from the `.termtint` at `path`, through the single-entry
+/// cache file at `cache_path`: `fingerprint<TAB>tab<TAB>background`.
+fn parse_termtint_cached_at(
+    cache_path: &Path,
+    path: &Path,
+    content: &str,
+    user_config: &UserConfig,
+    explain: &mut Explanation,
+) -> Result<ColorConfig, TermtintError> {
+    let Some(fingerprint) = termtint_fingerprint(path, content, user_config) else {
+        explain.note(|| "parsed .termtint cache: not used with these settings".to_string());
+        return config::parse_config_content(path, content, user_config);
+    };
+    let cached = fs::read_to_string(cache_path).ok().and_then(|cached| {
+        let mut fields = cached.trim_end().splitn(3, '\t');
+        if u64::from_str_radix(fields.next()?, 16).ok()? != fingerprint {
+            return None;
+        }
+        let tab = parse_color(fields.next()?).ok()?;
+        let background = parse_color(fields.next()?).ok()?;
+        Some(ColorConfig { tab, background })
+    });
+    if let Some(colors) = cached {
+        explain.note(|| "parsed .termtint cache: hit".to_string());
+        return Ok(colors);
+    }
+
+    let colors = config::parse_config_content(path, content, user_config)?;
+    if let Some(dir) = cache_path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let entry = format!(
+        "{:016x}\t{}\t{}\n",
+        fingerprint, colors.tab, colors.background
+    );
+    let _ = state::write_atomic(cache_path, entry.as_bytes());
+    explain.note(|| "parsed .termtint cache: miss, parsed and stored".to_string());
+    Ok(colors)
+}
+
+/// Read the cache entries, skipping malformed lines.
+fn read_cache(path: &Path) -> Vec<CacheEntry> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let fingerprint = u64::from_str_radix(fields.next()?, 16).ok()?;
+            let tab = parse_color(fields.next()?).ok()?;
+            let background = parse_color(fields.next()?).ok()?;
+            let dir = fields.next()?.to_string();
+            Some(CacheEntry {
+                fingerprint,
+                dir,
+                colors: ColorConfig { tab, background },
+            })
+        })
+        .collect()
+}
+
+fn write_cache(path: &Path, entries: &[CacheEntry]) {
+    let content: String = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "{:016x}\t{}\t{}\t{}\n",
+                entry.fingerprint, entry.colors.tab, entry.colors.background, entry.dir
+            )
+        })
+        .collect();
+    let _ = state::write_atomic(path, content.as_bytes());
+}
+
+/// Parse a config source like `config::parse_config_source`, reusing colors
+/// cached for the source's directory when `color_cache` is enabled and the
+/// generation parameters haven't changed since they were stored.
+pub fn parse_config_source_cached(
+    ctx: &Context,
+    source: &ConfigSource,
+    user_config: &UserConfig,
+    explain: &mut Explanation,
+) -> Result<ColorConfig, TermtintError> {
+    let fingerprint = match generation_fingerprint(source, user_config) {
+        Some(fingerprint) if user_config.color_cache && state::state_writable(ctx) => fingerprint,
+        fingerprint => {
+            explain.note(|| match (fingerprint, user_config.color_cache) {
+                (None, _) => "color cache: not used for this source".to_string(),
+                (Some(_), false) => "color cache: disabled (color_cache = false)".to_string(),
+                (Some(_), true) => "color cache: state directory not writable".to_string(),
+            });
+            return config::parse_config_source(source, user_config);
+        }
+    };
+    let mut missed = false;
+    let resolved = resolve_cached_at(
+        &cache_path_in(&state::state_root(ctx)),
+        &state::encode_path(&source.root_dir()),
+        fingerprint,
+        || {
+            missed = true;
+            config::parse_config_source(source, user_config)
+        },
+    );
+    explain.note(|| match missed {
+        true => "color cache: miss, generated and stored".to_string(),
+        false => "color cache: hit".to_string(),
+    });
+    resolved
+}
+
+/// Look up a directory in the cache file, calling `resolve` and storing the result
+/// on a miss. An entry with a different fingerprint is replaced.
+fn resolve_cached_at(
+    path: &Path,
+    dir: &str,
+    fingerprint: u64,
+    resolve: impl FnOnce() -> Result<ColorConfig, TermtintError>,
+) -> Result<ColorConfig, TermtintError> {
+    let mut entries = read_cache(path);
+    if let Some(entry) = entries
+        .iter()
+        .find(|entry| entry.dir == dir && entry.fingerprint == fingerprint)
+    {
+        return Ok(entry.colors.clone());
+    }
+
+    let colors = resolve()?;
+    entries.retain(|entry| entry.dir != dir);
+    entries.push(CacheEntry {
+        fingerprint,
+        dir: dir.to_string(),
+        colors: colors.clone(),
+    });
+    if entries.len() > CACHE_LIMIT {
+        entries.drain(..entries.len() - CACHE_LIMIT);
+    }
+    write_cache(path, &entries);
+    Ok(colors)
+}
+
+/// Delete a cache file, returning how many entries it held.
+fn clear_cache_at(path: &Path) -> Result<usize, String> {
+    let count = read_cache(path).len();
+    match fs::remove_file(path) {
+        Ok(()) => Ok(count),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(format!("Error removing {}: {}", path.display(), e)),
+    }
+}
+
+/// Delete the resolved-color cache.
+///
+/// # Returns
+/// * `Ok(())` on success
+/// * `Err(String)` if the cache file exists but can't be removed
+pub fn cmd_cache_clear(ctx: &Context) -> Result<(), String> {
+    let path = cache_path_in(&state::state_root(ctx));
+    match clear_cache_at(&path)? {
+        0 => println!("Color cache is already empty."),
+        1 => println!("Cleared the color cache (1 entry)."),
+        count => println!("Cleared the color cache ({} entries).", count),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RGB;
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    fn colors(tab: u8) -> ColorConfig {
+        ColorConfig {
+            tab: RGB { r: tab, g: 0, b: 0 },
+            background: RGB { r: 0, g: 0, b: tab },
+        }
+    }
+
+    #[test]
+    fn test_resolve_cached_hit_and_miss() {
+        let temp = TempDir::new().unwrap();
+        let path = cache_path_in(temp.path());
+
+        let first = resolve_cached_at(&path, "/code/app", 1, || Ok(colors(10))).unwrap();
+        assert_eq!(first, colors(10));
+
+        // A hit doesn't resolve again
+        let hit = resolve_cached_at(&path, "/code/app", 1, || panic!("should be cached"));
+        assert_eq!(hit, Ok(colors(10)));
+
+        // A changed fingerprint replaces the entry
+        let changed = resolve_cached_at(&path, "/code/app", 2, || Ok(colors(20))).unwrap();
+        assert_eq!(changed, colors(20));
+        assert_eq!(read_cache(&path).len(), 1);
+    }
+
+    #[test]
+    fn test_resolve_cached_errors_not_stored() {
+        let temp = TempDir::new().unwrap();
+        let path = cache_path_in(temp.path());
+
+        let result = resolve_cached_at(&path, "/code/app", 1, || {
+            Err(TermtintError::Config("bad".to_string()))
+        });
+        assert!(result.is_err());
+        assert!(read_cache(&path).is_empty());
+    }
+
+    #[test]
+    fn test_cache_limit_drops_oldest() {
+        let temp = TempDir::new().unwrap();
+        let path = cache_path_in(temp.path());
+
+        for i in 0..=CACHE_LIMIT {
+            let dir = format!("/code/project {}", i);
+            resolve_cached_at(&path, &dir, 1, || Ok(colors(1))).unwrap();
+        }
+
+        let entries = read_cache(&path);
+        assert_eq!(entries.len(), CACHE_LIMIT);
+        assert_eq!(entries[0].dir, "/code/project 1");
+    }
+
+    #[test]
+    fn test_generation_fingerprint() {
+        let user_config = UserConfig::default();
+        let trigger = ConfigSource::TriggerPath(PathBuf::from("/code/app"));
+        let fingerprint = generation_fingerprint(&trigger, &user_config);
+        assert!(fingerprint.is_some());
+
+        let changed = UserConfig {
+            lightness: 0.6,
+            ..UserConfig::default()
+        };
+        assert_ne!(generation_fingerprint(&trigger, &changed), fingerprint);
+
+        let explicit = ConfigSource::Termtint(PathBuf::from("/code/app/.termtint"));
+        assert_eq!(generation_fingerprint(&explicit, &user_config), None);
+        let blending = UserConfig {
+            blend_with_parent: 0.3,
+            ..UserConfig::default()
+        };
+        assert_eq!(generation_fingerprint(&trigger, &blending), None);
+    }
+
+    #[test]
+    fn test_parsed_termtint_cache_hit_and_miss() {
+        let temp = TempDir::new().unwrap();
+        let cache_path = temp.path().join("parsed_termtint");
+        let path = temp.path().join(".termtint");
+        let user_config = UserConfig::default();
+        let parse = |content: &str, user_config: &UserConfig| {
+            parse_termtint_cached_at(
+                &cache_path,
+                &path,
+                content,
+                user_config,
+                &mut Explanation::disabled(),
+            )
+            .unwrap()
+        };
+
+        let parsed = parse("#336699", &user_config);
+        assert_eq!(parsed.tab, parse_color("#336699").unwrap());
+        assert!(cache_path.exists());
+
+        // A hit returns the stored colors without parsing: overwrite them to tell
+        let fingerprint = termtint_fingerprint(&path, "#336699", &user_config).unwrap();
+        fs::write(
+            &cache_path,
+            format!("{:016x}\t#010203\t#040506\n", fingerprint),
+        )
+        .unwrap();
+        assert_eq!(parse("#336699", &user_config).tab, RGB { r: 1, g: 2, b: 3 });
+
+        // Changed contents or settings miss and replace the entry
+        assert_eq!(
+            parse("#ff0000", &user_config).tab,
+            RGB { r: 255, g: 0, b: 0 }
+        );
+        let changed = UserConfig {
+            background_lightness: 0.2,
+            ..UserConfig::default()
+        };
+        let reparsed = parse("#ff0000", &changed);
+        assert_ne!(
+            reparsed.background,
+            parse("#ff0000", &user_config).background
+        );
+        assert_eq!(parse("#336699", &user_config), parsed);
+    }
+
+    #[test]
+    fn test_parsed_termtint_errors_not_stored() {
+        let temp = TempDir::new().unwrap();
+        let cache_path = temp.path().join("parsed_termtint");
+        let path = temp.path().join(".termtint");
+        let result = parse_termtint_cached_at(
+            &cache_path,
+            &path,
+            "tab = 5\n",
+            &UserConfig::default(),
+            &mut Explanation::disabled(),
+        );
+        assert!(result.is_err());
+        assert!(!cache_path.exists());
+    }
+
+    #[test]
+    fn test_clear_cache() {
+        let temp = TempDir::new().unwrap();
+        let path = cache_path_in(temp.path());
+        assert_eq!(clear_cache_at(&path), Ok(0));
+
+        resolve_cached_at(&path, "/code/a", 1, || Ok(colors(1))).unwrap();
+        resolve_cached_at(&path, "/code/b", 1, || Ok(colors(2))).unwrap();
+        assert_eq!(clear_cache_at(&path), Ok(2));
+        assert!(!path.exists());
+    }
+
+    /// Compare resolving a deeply nested trigger directory with and without the
+    /// cache. Run with `cargo test bench_color_cache -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_color_cache() {
+        const ITERATIONS: u32 = 2000;
+        let temp = TempDir::new().unwrap();
+        let dir = (0..12).fold(temp.path().to_path_buf(), |dir, i| {
+            dir.join(format!("d{}", i))
+        });
+        fs::create_dir_all(&dir).unwrap();
+        let source = ConfigSource::TriggerPath(dir.to_path_buf());
+        let user_config = UserConfig {
+            reserved_colors: vec![RGB {
+                r: 0xff,
+                g: 0x00,
+                b: 0x00,
+            }],
+            ..UserConfig::default()
+        };
+        let path = cache_path_in(temp.path());
+        let fingerprint = generation_fingerprint(&source, &user_config).unwrap();
+        let dir = state::encode_path(&dir);
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            config::parse_config_source(&source, &user_config).unwrap();
+        }
+        let uncached = start.elapsed() / ITERATIONS;
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            resolve_cached_at(&path, &dir, fingerprint, || {
+                config::parse_config_source(&source, &user_config)
+            })
+            .unwrap();
+        }
+        let cached = start.elapsed() / ITERATIONS;
+
+        println!("uncached: {:?} per resolve", uncached);
+        println!("cached:   {:?} per resolve", cached);
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::context::Context;
+
+/// Where emitted escape sequences are captured, when `--capture` or
+/// `TERMTINT_CAPTURE` asks for it. Set once by `init`.
+static CAPTURE: OnceLock<Capture> = OnceLock::new();
+
+struct Capture {
+    file: Mutex<File>,
+    /// Whether the sequences go only to the capture file, not the terminal
+    only: bool,
+}
+
+/// Start capturing escape sequences to `path` (from `--capture`), or to the file
+/// named by `TERMTINT_CAPTURE` when no path is given. A capture file that can't be
+/// opened is reported and sequences go to the terminal as usual.
+///
+/// # Arguments
+/// * `ctx` - The invocation context, for the environment
+/// * `path` - The `--capture` file, if given
+/// * `only` - Whether `--capture-only` was given
+pub fn init(ctx: &Context, path: Option<&Path>, only: bool) {
+    let path = path.map(Path::to_path_buf).or_else(|| {
+        ctx.var_os("TERMTINT_CAPTURE")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    });
+    let Some(path) = path else {
+        if only {
+            tracing::warn!("--capture-only without --capture or TERMTINT_CAPTURE, ignoring it");
+        }
+        return;
+    };
+    match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(file) => {
+            let _ = CAPTURE.set(Capture {
+                file: Mutex::new(file),
+                only,
+            });
+        }
+        Err(e) => tracing::warn!(
+            "can't open capture file {}: {}, not capturing",
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// The terminal escape sequences are emitted to: stdout, with everything written
+/// also appended to the capture file when capturing (or written only there with
+/// `--capture-only`).
+pub struct Terminal;
+
+impl Write for Terminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(capture) = CAPTURE.get() {
+            if let Ok(mut file) = capture.file.lock() {
+                // One write per record, so concurrent runs appending to the same
+                // file don't interleave within it
+                let _ = file.write_all(&record(buf));
+            }
+            if capture.only {
+                return Ok(buf.len());
+            }
+        }
+        io::stdout().write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// The capture file record for emitted bytes: each escape sequence as written,
+/// on a line of its own, followed by a `# ` line spelling it out with `\xNN`
+/// escapes (as `printf` accepts them).
+fn record(bytes: &[u8]) -> Vec<u8> {
+    let mut record = Vec::new();
+    for sequence in sequences(bytes) {
+        record.extend_from_slice(sequence);
+        record.extend_from_slice(b"\n# ");
+        record.extend_from_slice(escape(sequence).as_bytes());
+        record.push(b'\n');
+    }
+    record
+}
+
+/// Split emitted bytes after each sequence terminator: BEL, or ST (`ESC \`).
+fn sequences(bytes: &[u8]) -> Vec<&[u8]> {
+    let mut sequences = Vec::new();
+    let mut start = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        let terminated = byte == 0x07 || (byte == b'\\' && i > start && bytes[i - 1] == 0x1b);
+        if terminated {
+            sequences.push(&bytes[start..=i]);
+            start = i + 1;
+        }
+    }
+    if start < bytes.len() {
+        sequences.push(&bytes[start..]);
+    }
+    sequences
+}
+
+/// Printable ASCII as is (with `\` doubled), everything else as `\xNN`.
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            b'\\' => "\\\\".to_string(),
+            b' '..=b'~' => (byte as char).to_string(),
+            _ => format!("\\x{:02x}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let emitted = b"\x1b]6;1;bg;red;brightness;51\x07\x1b]11;rgb:1a/2b/3c\x07";
+        assert_eq!(
+            String::from_utf8(record(emitted)).unwrap(),
+            "\x1b]6;1;bg;red;brightness;51\x07\n# \\x1b]6;1;bg;red;brightness;51\\x07\n\
+             \x1b]11;rgb:1a/2b/3c\x07\n# \\x1b]11;rgb:1a/2b/3c\\x07\n"
+        );
+    }
+
+    #[test]
+    fn test_sequences_split_on_bel_and_st() {
+        let emitted = b"\x1b]111\x07\x1b]1337;SetBadgeFormat=aGk=\x1b\\partial";
+        assert_eq!(
+            sequences(emitted),
+            [
+                &b"\x1b]111\x07"[..],
+                &b"\x1b]1337;SetBadgeFormat=aGk=\x1b\\"[..],
+                &b"partial"[..],
+            ]
+        );
+        assert_eq!(escape(b"\x1b\\ok\n"), "\\x1b\\\\ok\\x0a");
+    }
+}
@@ -1,24 +1,49 @@
-use crate::config::RGB;
-use crate::user_config::UserConfig;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, ColorVisionDeficiency, RGB};
+use crate::user_config::{Trigger, TriggerKind, UserConfig};
+
+/// Maximum number of trigger directories scanned by `colors --distribution`.
+const MAX_DISTRIBUTION_DIRS: usize = 500;
+
+/// Number of hue histogram buckets (10° each).
+const HISTOGRAM_BUCKETS: usize = 36;
+
+/// Number of closest-colored directory pairs listed.
+const CLOSEST_PAIRS: usize = 5;
+
+/// Longest histogram bar, in characters.
+const MAX_BAR_WIDTH: usize = 40;
 
 /// Display a visual color palette showing available auto-generated colors.
 ///
 /// # Arguments
 /// * `user_config` - User configuration containing color generation parameters
-pub fn cmd_colors(user_config: &UserConfig) {
+/// * `simulate` - Render swatches as seen with this color vision deficiency
+pub fn cmd_colors(user_config: &UserConfig, simulate: Option<ColorVisionDeficiency>) {
     // Print header
     println!("termtint color palette\n");
+    if let Some(deficiency) = simulate {
+        println!(
+            "Simulating {} (preview only; applied colors are unchanged)\n",
+            deficiency.name()
+        );
+    }
 
     // Print current configuration values
     println!("Configuration:");
     println!(
         "  Background lightness:   {:.0}%",
-        user_config.background_lightness * 100.0
+        user_config.derived_background_lightness() * 100.0
     );
     println!(
         "  Background saturation:  {:.0}%",
         user_config.background_saturation * 100.0
     );
+    println!(
+        "  Temperature shift:      {:+.2}",
+        user_config.temperature_shift
+    );
     println!(
         "  Color format:           {}",
         match user_config.color_format {
@@ -28,6 +53,13 @@ pub fn cmd_colors(user_config: &UserConfig) {
         }
     );
     println!("\nAuto color generation:");
+    if let Some(preset) = user_config.preset {
+        println!("  Preset:                 {}", preset);
+    }
+    if user_config.monochrome {
+        print_monochrome_overview(user_config, simulate);
+        return;
+    }
     println!(
         "  Hue range:              {:.0}° - {:.0}°",
         user_config.hue_min, user_config.hue_max
@@ -72,18 +104,220 @@ pub fn cmd_colors(user_config: &UserConfig) {
 
     // Print hue spectrum
     println!("\nHue spectrum:");
-    print_hue_spectrum(user_config);
+    print_hue_spectrum(user_config, simulate);
 
     // Print sample tab/background pairs
     println!("\nSample tab/background pairs:");
-    print_sample_pairs(user_config);
+    print_sample_pairs(user_config, simulate);
+
+    // Print samples for triggers with their own hue band
+    if user_config
+        .triggers
+        .iter()
+        .any(|trigger| trigger.has_hue_band())
+    {
+        println!("\nTrigger hue bands:");
+        print_trigger_bands(user_config, simulate);
+    }
+}
+
+/// The monochrome counterpart of the `colors` overview: the gray ramp and sample pairs.
+fn print_monochrome_overview(user_config: &UserConfig, simulate: Option<ColorVisionDeficiency>) {
+    println!("  Mode:                   monochrome");
+    println!(
+        "  Lightness range:        {:.0}% - {:.0}%",
+        user_config.monochrome_lightness_min * 100.0,
+        user_config.monochrome_lightness_max * 100.0
+    );
+
+    println!("\nHow colors are selected:");
+    println!("  Monochrome mode generates neutral gray tab colors. For auto-generated colors,");
+    println!("  the lightness is derived from a hash of the directory path within the");
+    println!("  configured range, so directories are told apart by shade alone.");
+    println!();
+    println!("  Background colors are the tab gray darkened to the configured background");
+    println!("  lightness.");
+
+    println!("\nGray ramp:");
+    print_gray_ramp(user_config, simulate);
+
+    println!("\nSample tab/background pairs:");
+    print_sample_pairs(user_config, simulate);
+}
+
+/// Display how the auto colors of all directories matched by `trigger_paths` are
+/// distributed around the hue wheel, and which directories look most alike.
+///
+/// # Arguments
+/// * `user_config` - User configuration containing trigger paths and color parameters
+pub fn cmd_colors_distribution(user_config: &UserConfig) {
+    println!("termtint hue distribution\n");
+
+    let patterns: Vec<&str> = user_config.trigger_patterns(TriggerKind::Path).collect();
+    if patterns.is_empty() {
+        println!("No trigger_paths configured.");
+        println!("Add some with 'termtint trigger add \"~/Code/*\"'.");
+        return;
+    }
+
+    let home = user_config.home.as_deref();
+    let (dirs, truncated) = trigger_directories(&patterns, home, MAX_DISTRIBUTION_DIRS);
+    println!("Trigger paths: {}", patterns.join(", "));
+    println!("Directories:   {}", dirs.len());
+    if truncated {
+        println!(
+            "  (stopped after {} directories; results are partial)",
+            MAX_DISTRIBUTION_DIRS
+        );
+    }
+    if dirs.is_empty() {
+        return;
+    }
+
+    let entries: Vec<(PathBuf, f32)> = dirs
+        .into_iter()
+        .map(|dir| {
+            let hue = config::auto_hue(&dir, user_config);
+            (dir, hue)
+        })
+        .collect();
+    let hues: Vec<f32> = entries.iter().map(|(_, hue)| *hue).collect();
+
+    println!("\nHue histogram:");
+    print_histogram(&hue_histogram(&hues), user_config);
+
+    println!("\nClosest pairs:");
+    let pairs = closest_pairs(&hues, CLOSEST_PAIRS);
+    if pairs.is_empty() {
+        println!("  (need at least two directories)");
+    }
+    for (a, b, distance) in pairs {
+        println!(
+            "  {:>5.1}°  {} ({:.0}°)  ↔  {} ({:.0}°)",
+            distance,
+            entries[a].0.display(),
+            entries[a].1,
+            entries[b].0.display(),
+            entries[b].1
+        );
+    }
+}
+
+/// Expand trigger path globs to the directories they match, stopping after `limit`.
+/// Returns the sorted, de-duplicated directories and whether the limit was hit.
+fn trigger_directories(
+    patterns: &[&str],
+    home: Option<&Path>,
+    limit: usize,
+) -> (Vec<PathBuf>, bool) {
+    let mut dirs = Vec::new();
+    for pattern in patterns {
+        let Ok(paths) = glob::glob(&config::expand_home(pattern, home)) else {
+            continue;
+        };
+        for path in paths.flatten() {
+            if !path.is_dir() || dirs.contains(&path) {
+                continue;
+            }
+            if dirs.len() == limit {
+                dirs.sort();
+                return (dirs, true);
+            }
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    (dirs, false)
+}
+
+/// Count hues into 10° buckets.
+fn hue_histogram(hues: &[f32]) -> [usize; HISTOGRAM_BUCKETS] {
+    let bucket_size = 360.0 / HISTOGRAM_BUCKETS as f32;
+    let mut buckets = [0; HISTOGRAM_BUCKETS];
+    for hue in hues {
+        let bucket = (hue.rem_euclid(360.0) / bucket_size) as usize;
+        buckets[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+    }
+    buckets
+}
+
+/// Find the `count` pairs of hues closest to each other on the hue circle.
+/// Returns (index, index, distance in degrees), closest first.
+fn closest_pairs(hues: &[f32], count: usize) -> Vec<(usize, usize, f32)> {
+    let mut pairs = Vec::new();
+    for i in 0..hues.len() {
+        for j in (i + 1)..hues.len() {
+            pairs.push((i, j, config::hue_distance(hues[i], hues[j])));
+        }
+    }
+    pairs.sort_by(|a, b| a.2.total_cmp(&b.2));
+    pairs.truncate(count);
+    pairs
+}
+
+/// Print one colored bar per histogram bucket with its count.
+fn print_histogram(buckets: &[usize; HISTOGRAM_BUCKETS], user_config: &UserConfig) {
+    let bucket_size = 360 / HISTOGRAM_BUCKETS;
+    let max = buckets.iter().copied().max().unwrap_or(0).max(1);
+    let saturation = (user_config.saturation_min + user_config.saturation_max) / 2.0;
+
+    for (i, &count) in buckets.iter().enumerate() {
+        let start = i * bucket_size;
+        let center = start as f32 + bucket_size as f32 / 2.0;
+        let color =
+            csscolorparser::Color::from_hsla(center, saturation, user_config.lightness, 1.0);
+        let [r, g, b, _a] = color.to_rgba8();
+        let rgb = RGB { r, g, b }.shift_temperature(user_config.temperature_shift);
+
+        // Scale bars down only when the largest bucket would not fit
+        let width = if max > MAX_BAR_WIDTH {
+            (count * MAX_BAR_WIDTH).div_ceil(max)
+        } else {
+            count
+        };
+
+        let range = format!("{}°-{}°", start, start + bucket_size);
+        print!("  {:>9} ", range);
+        print!(
+            "\x1b[38;2;{};{};{}m{}\x1b[0m",
+            rgb.r,
+            rgb.g,
+            rgb.b,
+            "█".repeat(width)
+        );
+        if count > 0 {
+            print!(" {}", count);
+        }
+        println!();
+    }
+}
+
+/// The color a swatch should show: the emitted color (after the temperature shift),
+/// optionally as seen with a color vision deficiency.
+fn preview(rgb: RGB, user_config: &UserConfig, simulate: Option<ColorVisionDeficiency>) -> RGB {
+    let emitted = rgb.shift_temperature(user_config.temperature_shift);
+    match simulate {
+        Some(deficiency) => emitted.simulate_cvd(deficiency),
+        None => emitted,
+    }
+}
+
+/// Print the range of monochrome tab grays, darkest to lightest.
+fn print_gray_ramp(user_config: &UserConfig, simulate: Option<ColorVisionDeficiency>) {
+    let steps = 36;
+    print!("       ");
+    for i in 0..steps {
+        let gray = config::monochrome_tab_color(i as f32 / (steps - 1) as f32, user_config);
+        let rgb = preview(gray, user_config, simulate);
+        print!("\x1b[48;2;{};{};{}m \x1b[0m", rgb.r, rgb.g, rgb.b);
+    }
+    println!();
 }
 
 /// Print a visual hue spectrum using ANSI true color and Unicode blocks.
 /// Displays a 2D grid with hue on the X-axis and saturation on the Y-axis.
-fn print_hue_spectrum(user_config: &UserConfig) {
+fn print_hue_spectrum(user_config: &UserConfig, simulate: Option<ColorVisionDeficiency>) {
     let steps = 36;
-    let hue_range = user_config.hue_max - user_config.hue_min;
     let lightness = user_config.lightness;
 
     // Calculate 4 evenly distributed saturation values from high to low
@@ -101,33 +335,40 @@ fn print_hue_spectrum(user_config: &UserConfig) {
 
         // Print colored blocks for each hue value at this saturation
         for i in 0..steps {
-            let hue = user_config.hue_min + (i as f32 / steps as f32) * hue_range;
+            let hue = user_config.hue_at(i as f32 / steps as f32);
             let color = csscolorparser::Color::from_hsla(hue, saturation, lightness, 1.0);
             let [r, g, b, _a] = color.to_rgba8();
+            let rgb = preview(RGB { r, g, b }, user_config, simulate);
 
             // Print colored block using ANSI true color
-            print!("\x1b[48;2;{};{};{}m \x1b[0m", r, g, b);
+            print!("\x1b[48;2;{};{};{}m \x1b[0m", rgb.r, rgb.g, rgb.b);
         }
         println!();
     }
 }
 
 /// Print sample tab/background color pairs.
-fn print_sample_pairs(user_config: &UserConfig) {
+fn print_sample_pairs(user_config: &UserConfig, simulate: Option<ColorVisionDeficiency>) {
     let samples = 12;
-    let hue_range = user_config.hue_max - user_config.hue_min;
 
     // Use midpoint value for saturation and configured lightness
     let saturation = (user_config.saturation_min + user_config.saturation_max) / 2.0;
     let lightness = user_config.lightness;
 
     for i in 0..samples {
-        let hue = user_config.hue_min + (i as f32 / samples as f32) * hue_range;
-        let color = csscolorparser::Color::from_hsla(hue, saturation, lightness, 1.0);
-        let [r, g, b, _a] = color.to_rgba8();
+        let tab = if user_config.monochrome {
+            config::monochrome_tab_color(i as f32 / (samples - 1) as f32, user_config)
+        } else {
+            let hue = user_config.hue_at(i as f32 / samples as f32);
+            let color = csscolorparser::Color::from_hsla(hue, saturation, lightness, 1.0);
+            let [r, g, b, _a] = color.to_rgba8();
+            RGB { r, g, b }
+        };
+        let background = crate::config::derive_background(&tab, user_config);
 
-        let tab = RGB { r, g, b };
-        let background = tab.with_lightness(user_config.background_lightness);
+        // Preview what will actually be emitted
+        let tab = preview(tab, user_config, simulate);
+        let background = preview(background, user_config, simulate);
 
         // Print colored blocks with formatted color values
         print!("  Tab: ");
@@ -145,6 +386,35 @@ fn print_sample_pairs(user_config: &UserConfig) {
     }
 }
 
+/// Print a row of sample tab colors for each trigger's hue band.
+fn print_trigger_bands(user_config: &UserConfig, simulate: Option<ColorVisionDeficiency>) {
+    let samples = 12;
+    let saturation = (user_config.saturation_min + user_config.saturation_max) / 2.0;
+    let bands: Vec<&Trigger> = user_config
+        .triggers
+        .iter()
+        .filter(|trigger| trigger.has_hue_band())
+        .collect();
+    let name_width = bands
+        .iter()
+        .map(|trigger| trigger.pattern.len())
+        .max()
+        .unwrap_or(0);
+
+    for trigger in bands {
+        let banded = user_config.with_hue_band(trigger);
+        print!("  {:<width$} ", trigger.pattern, width = name_width);
+        for i in 0..samples {
+            let hue = banded.hue_at(i as f32 / (samples - 1) as f32);
+            let color = csscolorparser::Color::from_hsla(hue, saturation, banded.lightness, 1.0);
+            let [r, g, b, _a] = color.to_rgba8();
+            let rgb = preview(RGB { r, g, b }, user_config, simulate);
+            print!("\x1b[48;2;{};{};{}m  \x1b[0m", rgb.r, rgb.g, rgb.b);
+        }
+        println!(" {:.0}° - {:.0}°", banded.hue_min, banded.hue_max);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -153,7 +423,7 @@ mod tests {
     fn test_cmd_colors_runs_without_panic() {
         let user_config = UserConfig::default();
         // Just verify it doesn't panic
-        cmd_colors(&user_config);
+        cmd_colors(&user_config, None);
     }
 
     #[test]
@@ -166,11 +436,125 @@ mod tests {
             lightness: 0.45,
             background_lightness: 0.08,
             background_saturation: 1.0,
-            trigger_files: Vec::new(),
-            trigger_paths: Vec::new(),
-            color_format: crate::user_config::ColorFormat::default(),
+            ..UserConfig::default()
         };
         // Just verify it doesn't panic with custom config
-        cmd_colors(&user_config);
+        cmd_colors(&user_config, None);
+    }
+
+    #[test]
+    fn test_cmd_colors_with_trigger_bands() {
+        let user_config = UserConfig {
+            triggers: vec![Trigger {
+                hue_min: Some(15.0),
+                hue_max: Some(45.0),
+                ..Trigger::new(TriggerKind::File, "Cargo.toml")
+            }],
+            ..UserConfig::default()
+        };
+        // Just verify the band rows render without panicking
+        cmd_colors(&user_config, None);
+    }
+
+    #[test]
+    fn test_cmd_colors_monochrome() {
+        let user_config = UserConfig {
+            monochrome: true,
+            ..UserConfig::default()
+        };
+        // Just verify the gray ramp renders without panicking
+        cmd_colors(&user_config, None);
+    }
+
+    #[test]
+    fn test_cmd_colors_with_simulation() {
+        let user_config = UserConfig::default();
+        cmd_colors(&user_config, Some(ColorVisionDeficiency::Tritanopia));
+    }
+
+    #[test]
+    fn test_preview_simulates_after_temperature() {
+        let user_config = UserConfig {
+            temperature_shift: 0.5,
+            ..UserConfig::default()
+        };
+        let tab = RGB {
+            r: 0x34,
+            g: 0x78,
+            b: 0xf6,
+        };
+
+        assert_eq!(
+            preview(tab, &user_config, Some(ColorVisionDeficiency::Deuteranopia)),
+            tab.shift_temperature(0.5)
+                .simulate_cvd(ColorVisionDeficiency::Deuteranopia)
+        );
+        assert_eq!(preview(tab, &user_config, None), tab.shift_temperature(0.5));
+    }
+
+    #[test]
+    fn test_hue_histogram_buckets() {
+        let buckets = hue_histogram(&[0.0, 5.0, 9.99, 10.0, 359.9, 360.0]);
+
+        assert_eq!(buckets[0], 4); // 0, 5, 9.99 and 360 (wraps to 0)
+        assert_eq!(buckets[1], 1);
+        assert_eq!(buckets[35], 1);
+        assert_eq!(buckets.iter().sum::<usize>(), 6);
+    }
+
+    #[test]
+    fn test_closest_pairs_wraps_around() {
+        let hues = [5.0, 120.0, 355.0, 240.0, 125.0];
+        let pairs = closest_pairs(&hues, 2);
+
+        assert_eq!(pairs.len(), 2);
+        assert_eq!((pairs[0].0, pairs[0].1), (1, 4));
+        assert_eq!(pairs[0].2, 5.0);
+        assert_eq!((pairs[1].0, pairs[1].1), (0, 2));
+        assert_eq!(pairs[1].2, 10.0);
+    }
+
+    #[test]
+    fn test_trigger_directories_expands_globs_and_caps() {
+        use tempfile::TempDir;
+
+        let temp = TempDir::new().unwrap();
+        for name in ["a", "b", "c"] {
+            std::fs::create_dir(temp.path().join(name)).unwrap();
+        }
+        std::fs::write(temp.path().join("file.txt"), "").unwrap();
+        let pattern = format!("{}/*", temp.path().display());
+
+        let (dirs, truncated) = trigger_directories(&[pattern.as_str()], None, 10);
+        assert_eq!(dirs.len(), 3);
+        assert!(!truncated);
+        assert_eq!(dirs[0], temp.path().join("a"));
+
+        // Duplicate patterns don't double count
+        let (dirs, _) = trigger_directories(&[pattern.as_str(), pattern.as_str()], None, 10);
+        assert_eq!(dirs.len(), 3);
+
+        let (dirs, truncated) = trigger_directories(&[pattern.as_str()], None, 2);
+        assert_eq!(dirs.len(), 2);
+        assert!(truncated);
+    }
+
+    #[test]
+    fn test_cmd_colors_distribution_runs_without_panic() {
+        use tempfile::TempDir;
+
+        let temp = TempDir::new().unwrap();
+        for name in ["api", "web", "cli"] {
+            std::fs::create_dir(temp.path().join(name)).unwrap();
+        }
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(
+                TriggerKind::Path,
+                format!("{}/*", temp.path().display()),
+            )],
+            ..UserConfig::default()
+        };
+        cmd_colors_distribution(&user_config);
+        cmd_colors_distribution(&UserConfig::default());
     }
 }
@@ -1,11 +1,27 @@
-use crate::user_config::UserConfig;
+use crate::context::Context;
+use crate::error::TermtintError;
+use crate::explain::Explanation;
+use crate::export;
+#[cfg(feature = "itermcolors")]
+use crate::itermcolors;
+use crate::user_config::{
+    AutoHashSource, BackgroundMode, DimmingConfig, EnvTriggerPriority, SourceKind, Trigger,
+    TriggerKind, UserConfig, WalkBoundary,
+};
 use oklab::{oklab_to_srgb, srgb_to_oklab, Oklab, Rgb};
+#[cfg(feature = "reroll")]
 use rand::Rng;
+use std::borrow::Cow;
 use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs;
 use std::hash::{Hash, Hasher};
+use std::io;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -18,6 +34,7 @@ pub struct RGB {
 impl RGB {
     /// Create a version with a fixed lightness using perceptually uniform Oklab color space.
     /// Preserves hue and chroma while setting the lightness to the target value.
+    #[cfg(test)]
     pub fn with_lightness(&self, target_lightness: f32) -> RGB {
         self.with_lightness_and_saturation(target_lightness, 1.0)
     }
@@ -56,6 +73,164 @@ impl RGB {
         }
     }
 
+    /// Scale the Oklab lightness by a factor while preserving hue and chroma.
+    pub fn scale_lightness(&self, factor: f32) -> RGB {
+        let oklab = srgb_to_oklab(Rgb {
+            r: self.r,
+            g: self.g,
+            b: self.b,
+        });
+        let scaled = oklab_to_srgb(Oklab {
+            l: (oklab.l * factor).clamp(0.0, 1.0),
+            a: oklab.a,
+            b: oklab.b,
+        });
+        RGB {
+            r: scaled.r,
+            g: scaled.g,
+            b: scaled.b,
+        }
+    }
+
+    /// Composite this color with the given alpha over a base color (source-over blending).
+    pub fn composite_over(&self, alpha: f32, base: &RGB) -> RGB {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let blend = |src: u8, dst: u8| -> u8 {
+            (src as f32 * alpha + dst as f32 * (1.0 - alpha)).round() as u8
+        };
+        RGB {
+            r: blend(self.r, base.r),
+            g: blend(self.g, base.g),
+            b: blend(self.b, base.b),
+        }
+    }
+
+    /// Format as hex, appending an alpha byte when the color is translucent.
+    pub fn hex_with_alpha(&self, alpha: f32) -> String {
+        if alpha >= 1.0 {
+            self.to_string()
+        } else {
+            format!(
+                "{}{:02x}",
+                self,
+                (alpha.clamp(0.0, 1.0) * 255.0).round() as u8
+            )
+        }
+    }
+
+    /// Offset the Oklab lightness while preserving hue and chroma.
+    pub fn offset_lightness(&self, delta: f32) -> RGB {
+        if delta == 0.0 {
+            return *self;
+        }
+        let oklab = srgb_to_oklab(Rgb {
+            r: self.r,
+            g: self.g,
+            b: self.b,
+        });
+        let offset = oklab_to_srgb(Oklab {
+            l: (oklab.l + delta).clamp(0.0, 1.0),
+            a: oklab.a,
+            b: oklab.b,
+        });
+        RGB {
+            r: offset.r,
+            g: offset.g,
+            b: offset.b,
+        }
+    }
+
+    /// Shift the color along the blue–orange axis by moving the Oklab b component.
+    /// Positive values warm the color, negative values cool it; 0.0 returns it unchanged.
+    pub fn shift_temperature(&self, shift: f32) -> RGB {
+        if shift == 0.0 {
+            return *self;
+        }
+        let oklab = srgb_to_oklab(Rgb {
+            r: self.r,
+            g: self.g,
+            b: self.b,
+        });
+        let shifted = oklab_to_srgb(Oklab {
+            l: oklab.l,
+            a: oklab.a,
+            b: oklab.b + shift.clamp(-1.0, 1.0) * TEMPERATURE_B_RANGE,
+        });
+        RGB {
+            r: shifted.r,
+            g: shifted.g,
+            b: shifted.b,
+        }
+    }
+
+    /// Build a color from Oklch components (lightness, chroma, hue in degrees).
+    pub fn from_oklch(l: f32, c: f32, hue: f32) -> RGB {
+        let radians = hue.to_radians();
+        let srgb = oklab_to_srgb(Oklab {
+            l: l.clamp(0.0, 1.0),
+            a: c * radians.cos(),
+            b: c * radians.sin(),
+        });
+        RGB {
+            r: srgb.r,
+            g: srgb.g,
+            b: srgb.b,
+        }
+    }
+
+    /// Convert to Oklch components (lightness, chroma, hue in degrees).
+    pub fn to_oklch(self) -> (f32, f32, f32) {
+        let lab = srgb_to_oklab(Rgb {
+            r: self.r,
+            g: self.g,
+            b: self.b,
+        });
+        let chroma = (lab.a * lab.a + lab.b * lab.b).sqrt();
+        let hue = lab.b.atan2(lab.a).to_degrees().rem_euclid(360.0);
+        (lab.l, chroma, hue)
+    }
+
+    /// Interpolate towards another color in Oklch, taking the shorter way around the hue circle.
+    /// A factor of 0.0 returns this color unchanged, 1.0 returns `other`.
+    pub fn blend_oklch(&self, other: &RGB, factor: f32) -> RGB {
+        let factor = factor.clamp(0.0, 1.0);
+        if factor == 0.0 {
+            return *self;
+        }
+        if factor == 1.0 {
+            return *other;
+        }
+
+        let (l1, c1, h1) = self.to_oklch();
+        let (l2, c2, h2) = other.to_oklch();
+        let mut dh = h2 - h1;
+        if dh > 180.0 {
+            dh -= 360.0;
+        } else if dh < -180.0 {
+            dh += 360.0;
+        }
+        RGB::from_oklch(
+            l1 + (l2 - l1) * factor,
+            c1 + (c2 - c1) * factor,
+            h1 + dh * factor,
+        )
+    }
+
+    /// Perceptual difference (Euclidean distance in Oklab) between two colors.
+    pub fn delta_e(&self, other: &RGB) -> f32 {
+        let a = srgb_to_oklab(Rgb {
+            r: self.r,
+            g: self.g,
+            b: self.b,
+        });
+        let b = srgb_to_oklab(Rgb {
+            r: other.r,
+            g: other.g,
+            b: other.b,
+        });
+        ((a.l - b.l).powi(2) + (a.a - b.a).powi(2) + (a.b - b.b).powi(2)).sqrt()
+    }
+
     /// Format the color in the specified format.
     pub fn format_as(&self, format: crate::user_config::ColorFormat) -> String {
         use crate::user_config::ColorFormat;
@@ -81,32 +256,271 @@ impl fmt::Display for RGB {
 
 impl RGB {
     /// Format as a colored unicode block using ANSI true color escape sequences.
+    #[cfg(feature = "reroll")]
     pub fn as_color_block(&self) -> String {
         format!("\x1b[48;2;{};{};{}m  \x1b[0m", self.r, self.g, self.b)
     }
+
+    /// SGR sequence setting this as the text background, approximated to the
+    /// 256-color palette when truecolor is unavailable.
+    pub fn ansi_background(&self, truecolor: bool) -> String {
+        if truecolor {
+            format!("\x1b[48;2;{};{};{}m", self.r, self.g, self.b)
+        } else {
+            format!("\x1b[48;5;{}m", self.to_ansi256())
+        }
+    }
+
+    /// Nearest xterm-256 palette index, from the 6x6x6 color cube (16-231) or the
+    /// grayscale ramp (232-255). The 16 system colors are skipped since themes redefine them.
+    pub fn to_ansi256(self) -> u8 {
+        let cube_index = |v: u8| -> u8 {
+            match v {
+                0..=47 => 0,
+                48..=114 => 1,
+                _ => (v - 35) / 40,
+            }
+        };
+        let (r, g, b) = (cube_index(self.r), cube_index(self.g), cube_index(self.b));
+        let cube = 16 + 36 * r + 6 * g + b;
+
+        let average = (self.r as u32 + self.g as u32 + self.b as u32) / 3;
+        let gray = if average > 238 {
+            255
+        } else {
+            232 + (average.saturating_sub(3) / 10) as u8
+        };
+
+        let distance = |index: u8| -> u32 {
+            let c = RGB::from_ansi256(index);
+            let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+            d(self.r, c.r) + d(self.g, c.g) + d(self.b, c.b)
+        };
+        if distance(gray) < distance(cube) {
+            gray
+        } else {
+            cube
+        }
+    }
+
+    /// The color of an xterm-256 palette index (system colors use xterm's defaults).
+    pub fn from_ansi256(index: u8) -> RGB {
+        const SYSTEM: [(u8, u8, u8); 16] = [
+            (0, 0, 0),
+            (205, 0, 0),
+            (0, 205, 0),
+            (205, 205, 0),
+            (0, 0, 238),
+            (205, 0, 205),
+            (0, 205, 205),
+            (229, 229, 229),
+            (127, 127, 127),
+            (255, 0, 0),
+            (0, 255, 0),
+            (255, 255, 0),
+            (92, 92, 255),
+            (255, 0, 255),
+            (0, 255, 255),
+            (255, 255, 255),
+        ];
+        const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+        match index {
+            0..=15 => {
+                let (r, g, b) = SYSTEM[index as usize];
+                RGB { r, g, b }
+            }
+            16..=231 => {
+                let i = index - 16;
+                RGB {
+                    r: CUBE_LEVELS[(i / 36) as usize],
+                    g: CUBE_LEVELS[(i / 6 % 6) as usize],
+                    b: CUBE_LEVELS[(i % 6) as usize],
+                }
+            }
+            _ => {
+                let v = 8 + 10 * (index - 232);
+                RGB { r: v, g: v, b: v }
+            }
+        }
+    }
+
+    /// Snap to the nearest xterm-256 palette color.
+    pub fn quantize_256(&self) -> RGB {
+        RGB::from_ansi256(self.to_ansi256())
+    }
+
+    /// Approximate how this color appears with a color vision deficiency.
+    /// For previews only; emitted colors are never simulated.
+    #[cfg(feature = "colors")]
+    pub fn simulate_cvd(&self, deficiency: ColorVisionDeficiency) -> RGB {
+        let matrix = deficiency.matrix();
+        let linear = [
+            srgb_to_linear(self.r),
+            srgb_to_linear(self.g),
+            srgb_to_linear(self.b),
+        ];
+        let channel = |row: [f32; 3]| {
+            linear_to_srgb(row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2])
+        };
+        RGB {
+            r: channel(matrix[0]),
+            g: channel(matrix[1]),
+            b: channel(matrix[2]),
+        }
+    }
+}
+
+/// Decode an sRGB channel to linear light (0.0 to 1.0).
+#[cfg(feature = "colors")]
+fn srgb_to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode linear light as an sRGB channel, clamping out-of-gamut values.
+#[cfg(feature = "colors")]
+fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let c = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+/// Color vision deficiencies that `colors --simulate` can preview.
+#[cfg(feature = "colors")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorVisionDeficiency {
+    /// Missing L (long-wavelength, "red") cones
+    Protanopia,
+    /// Missing M (medium-wavelength, "green") cones
+    Deuteranopia,
+    /// Missing S (short-wavelength, "blue") cones
+    Tritanopia,
+}
+
+#[cfg(feature = "colors")]
+impl ColorVisionDeficiency {
+    /// Parse a deficiency name (case-insensitive).
+    pub fn parse(name: &str) -> Option<ColorVisionDeficiency> {
+        match name.to_lowercase().as_str() {
+            "protanopia" => Some(ColorVisionDeficiency::Protanopia),
+            "deuteranopia" => Some(ColorVisionDeficiency::Deuteranopia),
+            "tritanopia" => Some(ColorVisionDeficiency::Tritanopia),
+            _ => None,
+        }
+    }
+
+    /// Lowercase name, as accepted by `parse`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorVisionDeficiency::Protanopia => "protanopia",
+            ColorVisionDeficiency::Deuteranopia => "deuteranopia",
+            ColorVisionDeficiency::Tritanopia => "tritanopia",
+        }
+    }
+
+    /// Simulation matrix in linear RGB from Machado, Oliveira & Fernandes (2009) at
+    /// severity 1.0, derived from their LMS cone-response model.
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            ColorVisionDeficiency::Protanopia => [
+                [0.152_286, 1.052_583, -0.204_868],
+                [0.114_503, 0.786_281, 0.099_216],
+                [-0.003_882, -0.048_116, 1.051_998],
+            ],
+            ColorVisionDeficiency::Deuteranopia => [
+                [0.367_322, 0.860_646, -0.227_968],
+                [0.280_085, 0.672_501, 0.047_413],
+                [-0.011_820, 0.042_940, 0.968_881],
+            ],
+            ColorVisionDeficiency::Tritanopia => [
+                [1.255_528, -0.076_749, -0.178_779],
+                [-0.078_411, 0.930_809, 0.147_602],
+                [0.004_733, 0.691_367, 0.303_900],
+            ],
+        }
+    }
 }
 
+/// Accepted color syntaxes, shown when a color fails to parse.
+#[cfg(feature = "named-colors")]
+const ACCEPTED_COLOR_SYNTAXES: &str = "expected hex (#ff5500, #f50), rgb(255 85 0), \
+     hsl(20 100% 50%), hwb(), lab(), lch(), oklab(), oklch(62% 0.2 255), or a named color";
+#[cfg(not(feature = "named-colors"))]
+const ACCEPTED_COLOR_SYNTAXES: &str = "expected hex (#ff5500, #f50), rgb(255 85 0), \
+     hsl(20 100% 50%), hwb(), lab(), lch(), oklab(), or oklch(62% 0.2 255) \
+     (named colors are not available in this build)";
+
 /// Parse a color string in any supported format:
 /// - 6-digit hex: "#ff5500" or "ff5500"
 /// - 3-digit hex: "#f50"
-/// - RGB function: "rgb(255, 85, 0)"
-/// - HSL function: "hsl(20, 100%, 50%)"
+/// - RGB function: "rgb(255, 85, 0)" or "rgb(255 85 0)"
+/// - HSL function: "hsl(20, 100%, 50%)" or "hsl(20 100% 50%)"
+/// - Perceptual functions: "oklch(62% 0.2 255)", "oklab()", "lab()", "lch()"
 /// - Named colors: "red", "tomato", etc.
-pub fn parse_color(s: &str) -> Result<RGB, String> {
+///
+/// Any alpha channel is ignored; use `parse_color_with_alpha` to honor it.
+pub fn parse_color(s: &str) -> Result<RGB, TermtintError> {
+    parse_color_with_alpha(s).map(|(rgb, _alpha)| rgb)
+}
+
+/// Parse a color string, also returning its alpha (1.0 when opaque).
+/// Accepts everything `parse_color` does plus translucent forms:
+/// - 8-digit hex: "#3366ff80" or "3366ff80"
+/// - 4-digit hex: "#36f8"
+/// - RGBA/HSLA functions: "rgba(51, 102, 255, 0.5)", "hsla(225, 100%, 60%, 0.5)"
+pub fn parse_color_with_alpha(s: &str) -> Result<(RGB, f32), TermtintError> {
     let s = s.trim();
+    if let Some(rgb) = parse_hex_fast(s) {
+        return Ok((rgb, 1.0));
+    }
 
-    // Handle bare 6-digit hex without # prefix for backwards compatibility
-    let normalized = if s.chars().all(|c| c.is_ascii_hexdigit()) && s.len() == 6 {
+    // Handle bare 6- or 8-digit hex without # prefix for backwards compatibility
+    let normalized = if s.chars().all(|c| c.is_ascii_hexdigit()) && (s.len() == 6 || s.len() == 8) {
         format!("#{}", s)
     } else {
         s.to_string()
     };
 
-    let color =
-        csscolorparser::parse(&normalized).map_err(|e| format!("Invalid color '{}': {}", s, e))?;
+    let color = csscolorparser::parse(&normalized).map_err(|e| {
+        TermtintError::InvalidColor(format!(
+            "Invalid color '{}': {} ({})",
+            s, e, ACCEPTED_COLOR_SYNTAXES
+        ))
+    })?;
 
     let [r, g, b, _a] = color.to_rgba8();
-    Ok(RGB { r, g, b })
+    Ok((RGB { r, g, b }, color.a.clamp(0.0, 1.0)))
+}
+
+/// Parse `#rrggbb`, bare `rrggbb`, or `#rgb` directly, the forms almost every
+/// `.termtint` uses, without going through csscolorparser. Returns None for
+/// anything else, including invalid digits, which the general parser then reports.
+fn parse_hex_fast(s: &str) -> Option<RGB> {
+    let hash = s.starts_with('#');
+    let digits = s.strip_prefix('#').unwrap_or(s).as_bytes();
+    let nibble = |i: usize| (digits[i] as char).to_digit(16).map(|digit| digit as u8);
+    match (digits.len(), hash) {
+        (6, _) => Some(RGB {
+            r: nibble(0)? << 4 | nibble(1)?,
+            g: nibble(2)? << 4 | nibble(3)?,
+            b: nibble(4)? << 4 | nibble(5)?,
+        }),
+        (3, true) => Some(RGB {
+            r: nibble(0)? * 0x11,
+            g: nibble(1)? * 0x11,
+            b: nibble(2)? * 0x11,
+        }),
+        _ => None,
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -120,10 +534,75 @@ pub struct ColorConfig {
 pub enum ConfigSource {
     /// Explicit .termtint file found
     Termtint(PathBuf),
+    /// `.vscode/settings.json` with a Peacock color, used with `auto_from_vscode`
+    VsCode(PathBuf),
     /// Directory matching a trigger path glob pattern (auto-generated color)
-    TriggerPath(String),
+    TriggerPath(PathBuf),
     /// Directory with a trigger file (e.g., Cargo.toml, package.json)
-    TriggerFile(String),
+    TriggerFile(PathBuf),
+    /// Directory whose name matches `trigger_dirnames` (auto-generated color)
+    TriggerDirname(PathBuf),
+    /// An `[env_triggers]` rule matching the environment
+    Environment {
+        /// The rule as configured, e.g. `AWS_PROFILE=prod*`
+        rule: String,
+        /// The variable's current assignment, e.g. `AWS_PROFILE=prod-eu`
+        assignment: String,
+    },
+    /// A `kube-context` trigger matching the current kubectl context
+    KubeContext {
+        /// The trigger's pattern, e.g. `*prod*`
+        pattern: String,
+        /// The current context, e.g. `prod-eu`
+        context: String,
+    },
+}
+
+impl ConfigSource {
+    /// The directory the config applies from: the `.termtint` file's directory,
+    /// or the matched trigger directory. Environment and kube context sources have
+    /// no directory, and use the matched assignment (`kube-context=NAME` for a
+    /// context) in its place.
+    pub fn root_dir(&self) -> PathBuf {
+        match self {
+            ConfigSource::Termtint(path) => path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| path.clone()),
+            ConfigSource::VsCode(path) => vscode_settings_root(path),
+            ConfigSource::TriggerPath(dir_path)
+            | ConfigSource::TriggerFile(dir_path)
+            | ConfigSource::TriggerDirname(dir_path) => dir_path.clone(),
+            ConfigSource::Environment { assignment, .. } => PathBuf::from(assignment),
+            ConfigSource::KubeContext { context, .. } => {
+                PathBuf::from(format!("kube-context={}", context))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Termtint(path) => write!(f, ".termtint {}", path.display()),
+            ConfigSource::VsCode(path) => write!(f, "vscode settings {}", path.display()),
+            ConfigSource::TriggerPath(dir_path) => {
+                write!(f, "trigger path {}", dir_path.display())
+            }
+            ConfigSource::TriggerFile(dir_path) => {
+                write!(f, "trigger file in {}", dir_path.display())
+            }
+            ConfigSource::TriggerDirname(dir_path) => {
+                write!(f, "directory name trigger {}", dir_path.display())
+            }
+            ConfigSource::Environment { rule, assignment } => {
+                write!(f, "env trigger {} ({})", rule, assignment)
+            }
+            ConfigSource::KubeContext { pattern, context } => {
+                write!(f, "kube context trigger {} ({})", pattern, context)
+            }
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -133,191 +612,1397 @@ pub enum ConfigFormat {
     Auto,
 }
 
-/// Detect the format of a config file based on its content.
+/// Detect the format of a config file based on its content. An empty file (or one
+/// holding only whitespace and a byte order mark) is `auto`, so `touch .termtint`
+/// gives a directory a color without picking one.
+///
+/// Content is TOML only when it parses as a TOML table with a `tab` (or `preset`)
+/// key; anything else, including `color=red`, is read from its first non-empty
+/// line as `auto` or a simple color.
 pub fn detect_format(content: &str) -> ConfigFormat {
+    let content = normalize_content(content);
     let trimmed = content.trim();
-    if trimmed == "auto" {
+    if trimmed.is_empty() || first_line(trimmed) == "auto" {
         ConfigFormat::Auto
-    } else if trimmed.contains('=') {
+    } else if is_toml_config(trimmed) {
         ConfigFormat::Toml
     } else {
         ConfigFormat::SimpleColor
     }
 }
 
+/// Whether `content` is a TOML table setting `tab` or `preset`. Content without an
+/// `=` can't assign either, so simple colors skip the TOML parser.
+fn is_toml_config(content: &str) -> bool {
+    content.contains('=')
+        && content
+            .parse::<toml::Table>()
+            .is_ok_and(|table| table.contains_key("tab") || table.contains_key("preset"))
+}
+
+/// Whether a config file has no content besides whitespace and a byte order mark.
+pub fn is_empty_config(content: &str) -> bool {
+    strip_bom(content).trim().is_empty()
+}
+
+/// Drop the byte order mark some editors write at the start of a file.
+fn strip_bom(content: &str) -> &str {
+    content.strip_prefix('\u{feff}').unwrap_or(content)
+}
+
+/// A config file's content without its byte order mark, and with Windows (CRLF)
+/// line endings turned into `\n`.
+fn normalize_content(content: &str) -> Cow<'_, str> {
+    let content = strip_bom(content);
+    if content.contains('\r') {
+        Cow::Owned(content.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(content)
+    }
+}
+
+/// The first line of `content` with anything on it, trimmed.
+fn first_line(content: &str) -> &str {
+    content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("")
+}
+
+/// Chroma multiplier applied to lightened backgrounds so the hue tint stays subtle
+/// on light terminal themes.
+const LIGHTEN_CHROMA_SCALE: f32 = 0.35;
+
+/// Derive a background color from a tab color using the configured background mode.
+/// In darken mode the background takes `background_lightness`; in lighten mode it takes
+/// `background_lightness_light` with reduced chroma.
+pub fn derive_background(tab: &RGB, user_config: &UserConfig) -> RGB {
+    match user_config.background_mode {
+        BackgroundMode::Darken => tab.with_lightness_and_saturation(
+            user_config.background_lightness,
+            user_config.background_saturation,
+        ),
+        BackgroundMode::Lighten => tab.with_lightness_and_saturation(
+            user_config.background_lightness_light,
+            user_config.background_saturation * LIGHTEN_CHROMA_SCALE,
+        ),
+    }
+}
+
 /// Parse a simple color file. Derives background using configured lightness and saturation.
-fn parse_simple_color(content: &str, user_config: &UserConfig) -> Result<ColorConfig, String> {
-    let tab = parse_color(content)?;
-    let background = tab.with_lightness_and_saturation(
-        user_config.background_lightness,
-        user_config.background_saturation,
-    );
+/// A translucent color is composited over the derived background. Only the first
+/// non-empty line is read.
+pub fn parse_simple_color(
+    content: &str,
+    user_config: &UserConfig,
+) -> Result<ColorConfig, TermtintError> {
+    let (tab, alpha) = parse_color_with_alpha(first_line(content))?;
+    let background = derive_background(&tab, user_config);
+    let tab = tab.composite_over(alpha, &background);
     Ok(ColorConfig { tab, background })
 }
 
 /// Parse a TOML config file.
-fn parse_toml(content: &str, user_config: &UserConfig) -> Result<ColorConfig, String> {
+fn parse_toml(
+    path: &Path,
+    content: &str,
+    user_config: &UserConfig,
+) -> Result<ColorConfig, TermtintError> {
+    let invalid = |reason: &str| TermtintError::parse(Some(path.to_path_buf()), reason);
     let table: toml::Table = content
         .parse()
-        .map_err(|e| format!("Failed to parse TOML: {}", e))?;
-
-    let tab_str = table
-        .get("tab")
-        .and_then(|v| v.as_str())
-        .ok_or("Missing 'tab' key in TOML config")?;
+        .map_err(|e| invalid(&format!("Failed to parse TOML: {}", e)))?;
+
+    let tab_str = table.get("tab").and_then(|v| v.as_str());
+    let preset = match table.get("preset") {
+        Some(reference) => {
+            let reference = reference
+                .as_str()
+                .ok_or_else(|| invalid("'preset' must be the path to an .itermcolors file"))?;
+            let tab_key = match table.get("preset_tab") {
+                Some(key) => Some(
+                    key.as_str()
+                        .ok_or_else(|| invalid("'preset_tab' must be a string"))?,
+                ),
+                None => None,
+            };
+            let preset_path = preset_path(path, reference, user_config);
+            Some(read_preset(&preset_path, tab_str.is_none(), tab_key)?)
+        }
+        None => None,
+    };
 
-    let tab = parse_color(tab_str)?;
+    let (tab, tab_alpha) = match (tab_str, &preset) {
+        (Some(tab_str), _) => parse_color_with_alpha(tab_str)?,
+        (None, Some(preset)) => (
+            preset
+                .tab
+                .ok_or_else(|| invalid("Missing 'tab' key in TOML config"))?,
+            1.0,
+        ),
+        (None, None) => return Err(invalid("Missing 'tab' key in TOML config")),
+    };
 
+    // A translucent background is composited over black; a translucent tab over the background
     let background = if let Some(bg_str) = table.get("background").and_then(|v| v.as_str()) {
-        parse_color(bg_str)?
+        let (background, bg_alpha) = parse_color_with_alpha(bg_str)?;
+        background.composite_over(bg_alpha, &RGB { r: 0, g: 0, b: 0 })
+    } else if let Some(preset) = &preset {
+        preset.background
     } else {
-        tab.with_lightness_and_saturation(
-            user_config.background_lightness,
-            user_config.background_saturation,
-        )
+        derive_background(&tab, user_config)
     };
+    let tab = tab.composite_over(tab_alpha, &background);
 
     Ok(ColorConfig { tab, background })
 }
 
+/// Colors read from the `.itermcolors` preset a TOML config references.
+#[derive(Debug, Clone, PartialEq)]
+struct PresetColors {
+    background: RGB,
+    /// Read only when the config doesn't set `tab` itself
+    tab: Option<RGB>,
+}
+
+/// Resolve a `preset` reference: `~/` expands to the home directory, and relative
+/// paths are relative to the directory holding the `.termtint`.
+fn preset_path(termtint: &Path, reference: &str, user_config: &UserConfig) -> PathBuf {
+    let expanded = PathBuf::from(expand_home(reference, user_config.home.as_deref()));
+    match termtint.parent() {
+        Some(dir) if expanded.is_relative() => dir.join(expanded),
+        _ => expanded,
+    }
+}
+
+/// The `.itermcolors` preset the TOML `.termtint` at `path` references, if any,
+/// so callers can notice when the preset changes without the `.termtint` changing.
+pub fn preset_reference(path: &Path, content: &str, user_config: &UserConfig) -> Option<PathBuf> {
+    if detect_format(content) != ConfigFormat::Toml {
+        return None;
+    }
+    let table: toml::Table = normalize_content(content).parse().ok()?;
+    let reference = table.get("preset")?.as_str()?;
+    Some(preset_path(path, reference, user_config))
+}
+
+/// The `.itermcolors` preset an explicit `.termtint` source references, if any.
+pub fn source_preset(source: &ConfigSource, user_config: &UserConfig) -> Option<PathBuf> {
+    let ConfigSource::Termtint(path) = source else {
+        return None;
+    };
+    preset_reference(path, &read_config(path).ok()?, user_config)
+}
+
+/// Read a preset's background and, when `read_tab` is set, its tab color from the
+/// `tab_key` entry (the preset's own tab color by default).
+#[cfg(feature = "itermcolors")]
+fn read_preset(
+    path: &Path,
+    read_tab: bool,
+    tab_key: Option<&str>,
+) -> Result<PresetColors, TermtintError> {
+    let preset = itermcolors::read_itermcolors_cached(path)?;
+    let background = itermcolors::preset_color(&preset, itermcolors::BACKGROUND_KEY, path)?;
+    let tab = match (read_tab, tab_key) {
+        (false, _) => None,
+        (true, Some(key)) => Some(itermcolors::preset_color(&preset, key, path)?),
+        (true, None) => Some(
+            itermcolors::preset_color(&preset, itermcolors::DEFAULT_TAB_KEY, path).map_err(
+                |e| {
+                    TermtintError::parse(
+                        Some(path.to_path_buf()),
+                        format!("{}; set 'tab' or 'preset_tab' in .termtint", e),
+                    )
+                },
+            )?,
+        ),
+    };
+    Ok(PresetColors { background, tab })
+}
+
+#[cfg(not(feature = "itermcolors"))]
+fn read_preset(
+    _path: &Path,
+    _read_tab: bool,
+    _tab_key: Option<&str>,
+) -> Result<PresetColors, TermtintError> {
+    Err(TermtintError::Config(
+        "'preset' requires termtint built with the itermcolors feature".to_string(),
+    ))
+}
+
+/// Values mixed into the hash that determines an auto-generated color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoHashInputs {
+    /// Canonicalized config file or trigger directory path
+    pub path: PathBuf,
+    /// Hostname, when `hash_include_hostname` is enabled
+    pub hostname: Option<String>,
+    /// Package name from the trigger file, when `auto_hash_source = "package-name"`.
+    /// Hashed instead of the path so the color survives renaming or re-cloning.
+    pub package_name: Option<String>,
+}
+
+impl AutoHashInputs {
+    /// Collect the hash inputs for a path according to the user config.
+    pub fn for_path(path: &Path, user_config: &UserConfig) -> AutoHashInputs {
+        AutoHashInputs::for_canonical_path(&canonical_path(path), user_config)
+    }
+
+    /// Collect the hash inputs for a path that is already canonical, such as the
+    /// paths a `ConfigSource` from the directory walk holds, without resolving it again.
+    fn for_canonical_path(path: &Path, user_config: &UserConfig) -> AutoHashInputs {
+        AutoHashInputs {
+            path: path.to_path_buf(),
+            hostname: if user_config.hash_include_hostname {
+                hostname()
+            } else {
+                None
+            },
+            package_name: None,
+        }
+    }
+
+    /// Collect the hash inputs for a config source.
+    /// Trigger file sources use the package name from the matched file when configured,
+    /// falling back to the path when it can't be extracted.
+    pub fn for_source(source: &ConfigSource, user_config: &UserConfig) -> AutoHashInputs {
+        match source {
+            ConfigSource::Termtint(path) | ConfigSource::VsCode(path) => {
+                AutoHashInputs::for_canonical_path(path, user_config)
+            }
+            ConfigSource::TriggerPath(dir) | ConfigSource::TriggerDirname(dir) => {
+                AutoHashInputs::for_canonical_path(dir, user_config)
+            }
+            ConfigSource::TriggerFile(dir) => {
+                let mut inputs = AutoHashInputs::for_canonical_path(dir, user_config);
+                if user_config.auto_hash_source == AutoHashSource::PackageName {
+                    inputs.package_name = matched_trigger_file(dir, user_config)
+                        .and_then(|file| crate::manifest::package_name(&file));
+                }
+                inputs
+            }
+            ConfigSource::Environment { assignment, .. } => {
+                AutoHashInputs::for_path(Path::new(assignment), user_config)
+            }
+            ConfigSource::KubeContext { .. } => {
+                AutoHashInputs::for_path(&source.root_dir(), user_config)
+            }
+        }
+    }
+
+    fn hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match &self.package_name {
+            Some(name) => name.hash(&mut hasher),
+            None => self.path.hash(&mut hasher),
+        }
+        if let Some(hostname) = &self.hostname {
+            hostname.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+/// Get the machine's hostname.
+pub fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for writes of buf.len() bytes.
+    let result = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if result != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
 /// Generate a deterministic color from the config file path using user-configured parameters.
 fn parse_auto(path: &Path, user_config: &UserConfig) -> ColorConfig {
-    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
-    let mut hasher = DefaultHasher::new();
-    canonical.hash(&mut hasher);
-    let hash = hasher.finish();
+    auto_color(
+        &AutoHashInputs::for_canonical_path(path, user_config),
+        user_config,
+    )
+}
+
+/// Generate a deterministic color from the given hash inputs.
+fn auto_color(inputs: &AutoHashInputs, user_config: &UserConfig) -> ColorConfig {
+    color_from_hash(inputs.hash(), user_config)
+}
+
+/// Generate a deterministic color from a hash value using user-configured parameters.
+pub fn color_from_hash(hash: u64, user_config: &UserConfig) -> ColorConfig {
+    if user_config.monochrome {
+        let tab = monochrome_tab_color((hash & 0xFFFF) as f32 / 0xFFFF as f32, user_config);
+        let background = derive_background(&tab, user_config);
+        return ColorConfig { tab, background };
+    }
 
     // Use HSL color space for vibrant colors
     // Derive hue from hash within configured range
-    let hue_range = user_config.hue_max - user_config.hue_min;
-    let hue = user_config.hue_min + ((hash & 0xFFFF) as f32 / 0xFFFF as f32) * hue_range;
+    let hue = hue_from_hash(hash, user_config);
 
     // Use configured saturation range
     let saturation_range = user_config.saturation_max - user_config.saturation_min;
     let saturation =
         user_config.saturation_min + ((hash >> 16) & 0xFF) as f32 / 0xFF as f32 * saturation_range;
 
+    // Keep clear of reserved colors without breaking determinism
+    let hue = avoid_reserved(hue, saturation, user_config);
+
     // Use configured fixed lightness
-    let lightness = user_config.lightness;
+    let tab = hsl_to_tab_color(hue, saturation, user_config);
+    let background = derive_background(&tab, user_config);
 
-    // Create color using HSL and convert to RGB
-    let color = csscolorparser::Color::from_hsla(hue, saturation, lightness, 1.0);
-    let [r, g, b, _a] = color.to_rgba8();
+    ColorConfig { tab, background }
+}
 
-    let tab = RGB { r, g, b };
-    let background = tab.with_lightness_and_saturation(
-        user_config.background_lightness,
-        user_config.background_saturation,
-    );
+/// Derive the hue (degrees) for a hash within the configured hue range.
+fn hue_from_hash(hash: u64, user_config: &UserConfig) -> f32 {
+    user_config.hue_at((hash & 0xFFFF) as f32 / 0xFFFF as f32)
+}
 
-    ColorConfig { tab, background }
+/// The hue (degrees) of the auto-generated color for a path.
+#[cfg(feature = "colors")]
+pub fn auto_hue(path: &Path, user_config: &UserConfig) -> f32 {
+    hue_from_hash(
+        AutoHashInputs::for_path(path, user_config).hash(),
+        user_config,
+    )
 }
 
-/// Generate a random color using user-configured parameters.
-pub fn generate_random_color(user_config: &UserConfig) -> RGB {
-    let mut rng = rand::thread_rng();
+/// The golden angle as a fraction of a full turn (1 - 1/φ).
+/// Stepping by this fraction of the hue range visits hues that are maximally spread out.
+#[cfg(feature = "reroll")]
+const GOLDEN_ANGLE_FRACTION: f32 = 0.381_966;
+
+/// Maximum number of golden-angle steps to try when a random hue lands too close
+/// to a recently generated one.
+#[cfg(feature = "reroll")]
+const MAX_SPREAD_ATTEMPTS: usize = 16;
+
+/// Angular distance between two hues in degrees, accounting for wraparound.
+#[cfg(any(feature = "colors", feature = "reroll", test))]
+pub fn hue_distance(a: f32, b: f32) -> f32 {
+    let diff = (a - b).rem_euclid(360.0);
+    diff.min(360.0 - diff)
+}
+
+/// Draw a random hue and saturation within the configured ranges.
+#[cfg(feature = "reroll")]
+fn random_hue_and_saturation<R: Rng>(rng: &mut R, user_config: &UserConfig) -> (f32, f32) {
     let random_value = rng.gen::<u64>();
 
-    // Use HSL color space for vibrant colors
     // Derive hue from random value within configured range
-    let hue_range = user_config.hue_max - user_config.hue_min;
-    let hue = user_config.hue_min + ((random_value & 0xFFFF) as f32 / 0xFFFF as f32) * hue_range;
+    let hue = user_config.hue_at((random_value & 0xFFFF) as f32 / 0xFFFF as f32);
 
     // Use configured saturation range
     let saturation_range = user_config.saturation_max - user_config.saturation_min;
     let saturation = user_config.saturation_min
         + ((random_value >> 16) & 0xFF) as f32 / 0xFF as f32 * saturation_range;
 
-    // Use configured fixed lightness
-    let lightness = user_config.lightness;
-
-    // Create color using HSL and convert to RGB
-    let color = csscolorparser::Color::from_hsla(hue, saturation, lightness, 1.0);
-    let [r, g, b, _a] = color.to_rgba8();
-
-    RGB { r, g, b }
+    (hue, saturation)
 }
 
-/// Parse a config file at the given path.
-pub fn parse_config(path: &Path, user_config: &UserConfig) -> Result<ColorConfig, String> {
-    let content =
-        fs::read_to_string(path).map_err(|e| format!("Failed to read config file: {}", e))?;
+/// Move a hue away from recently generated hues using golden-angle stepping.
+/// Returns the first candidate at least `hue_spread` degrees from every recent hue,
+/// or the best candidate seen if none qualifies within the attempt limit.
+#[cfg(feature = "reroll")]
+fn spread_hue(hue: f32, recent_hues: &[f32], user_config: &UserConfig) -> f32 {
+    if recent_hues.is_empty() || user_config.hue_spread <= 0.0 {
+        return hue;
+    }
 
-    match detect_format(&content) {
-        ConfigFormat::SimpleColor => parse_simple_color(&content, user_config),
-        ConfigFormat::Toml => parse_toml(&content, user_config),
-        ConfigFormat::Auto => Ok(parse_auto(path, user_config)),
+    let hue_arc = user_config.hue_arc();
+    let step = hue_arc * GOLDEN_ANGLE_FRACTION;
+    let min_distance = |h: f32| {
+        recent_hues
+            .iter()
+            .map(|&r| hue_distance(h, r))
+            .fold(f32::MAX, f32::min)
+    };
+
+    let mut candidate = hue;
+    let mut best = (hue, min_distance(hue));
+    for _ in 0..MAX_SPREAD_ATTEMPTS {
+        let distance = min_distance(candidate);
+        if distance >= user_config.hue_spread {
+            return candidate;
+        }
+        if distance > best.1 {
+            best = (candidate, distance);
+        }
+        if hue_arc <= 0.0 {
+            break;
+        }
+        // Step along the arc (which may wrap through 360°) from the current position
+        let position = (candidate - user_config.hue_min).rem_euclid(360.0);
+        candidate = user_config.hue_at((position + step).rem_euclid(hue_arc) / hue_arc);
     }
+    best.0
 }
 
-/// Check if a directory matches any of the configured path glob patterns.
-fn matches_path_glob(dir: &Path, patterns: &[String]) -> bool {
-    let dir_str = dir.to_string_lossy();
+/// Number of evenly spaced positions along the hue arc tried when nudging a hue
+/// away from reserved colors.
+const RESERVED_NUDGE_STEPS: usize = 72;
+
+/// Nudge a hue away from the user's reserved colors.
+/// Tries positions alternating on either side of the hue, nearest first, and returns the
+/// first whose tab color is more than `reserved_min_delta` from every reserved color,
+/// or the clearest candidate if none qualifies. Deterministic for a given input.
+fn avoid_reserved(hue: f32, saturation: f32, user_config: &UserConfig) -> f32 {
+    if user_config.reserved_colors.is_empty() {
+        return hue;
+    }
 
-    // Use shell-like glob matching where * doesn't match path separators
-    let match_options = glob::MatchOptions {
-        require_literal_separator: true,
-        ..Default::default()
+    let clearance = |h: f32| {
+        let tab = hsl_to_tab_color(h, saturation, user_config);
+        user_config
+            .reserved_colors
+            .iter()
+            .map(|reserved| tab.delta_e(reserved))
+            .fold(f32::MAX, f32::min)
     };
 
-    for pattern in patterns {
-        // Expand ~ to home directory
-        let expanded = if pattern.starts_with("~/") {
-            if let Ok(home) = std::env::var("HOME") {
-                pattern.replacen("~", &home, 1)
-            } else {
-                pattern.clone()
-            }
-        } else {
-            pattern.clone()
-        };
+    let hue_arc = user_config.hue_arc();
+    let step = hue_arc / RESERVED_NUDGE_STEPS as f32;
+    let position = (hue - user_config.hue_min).rem_euclid(360.0);
+    let mut best = (hue, clearance(hue));
+    if best.1 > user_config.reserved_min_delta || hue_arc <= 0.0 {
+        return hue;
+    }
 
-        if let Ok(glob_pattern) = glob::Pattern::new(&expanded) {
-            if glob_pattern.matches_with(&dir_str, match_options) {
-                return true;
+    for k in 1..=RESERVED_NUDGE_STEPS / 2 {
+        for direction in [1.0, -1.0] {
+            let offset = direction * k as f32 * step;
+            let candidate = user_config.hue_at((position + offset).rem_euclid(hue_arc) / hue_arc);
+            let distance = clearance(candidate);
+            if distance > user_config.reserved_min_delta {
+                return candidate;
+            }
+            if distance > best.1 {
+                best = (candidate, distance);
             }
         }
     }
-    false
+    best.0
 }
 
-/// Find a configuration source by walking up from start_dir.
-/// First checks for explicit `.termtint` files (highest priority),
-/// then checks for trigger paths (second priority),
-/// then checks for trigger files defined in user_config.
-/// Returns ConfigSource describing where the config comes from, or None if nothing found.
-pub fn find_config_source(start_dir: &Path, user_config: &UserConfig) -> Option<ConfigSource> {
-    let mut current = start_dir.to_path_buf();
-
-    loop {
-        // First priority: check for explicit .termtint file
-        let termtint_path = current.join(".termtint");
-        if termtint_path.exists() {
-            return Some(ConfigSource::Termtint(termtint_path));
-        }
+/// Build an RGB tab color from HSL components using the configured lightness.
+fn hsl_to_tab_color(hue: f32, saturation: f32, user_config: &UserConfig) -> RGB {
+    let color = csscolorparser::Color::from_hsla(hue, saturation, user_config.lightness, 1.0);
+    let [r, g, b, _a] = color.to_rgba8();
+    RGB { r, g, b }
+}
 
-        // Second priority: check for trigger path matches
-        if matches_path_glob(&current, &user_config.trigger_paths) {
-            return Some(ConfigSource::TriggerPath(
-                current.to_string_lossy().to_string(),
-            ));
-        }
+/// Build a neutral gray tab color at a fraction (0.0 to 1.0) of the monochrome lightness range.
+pub fn monochrome_tab_color(fraction: f32, user_config: &UserConfig) -> RGB {
+    let lightness = user_config.monochrome_lightness_min
+        + fraction * (user_config.monochrome_lightness_max - user_config.monochrome_lightness_min);
+    let color = csscolorparser::Color::from_hsla(0.0, 0.0, lightness, 1.0);
+    let [r, g, b, _a] = color.to_rgba8();
+    RGB { r, g, b }
+}
 
-        // Third priority: check for any trigger files
-        for trigger_file in &user_config.trigger_files {
-            let trigger_path = current.join(trigger_file);
-            if trigger_path.exists() {
-                return Some(ConfigSource::TriggerFile(
-                    current.to_string_lossy().to_string(),
-                ));
-            }
-        }
+/// Generate a random color using user-configured parameters, as a reroll with no
+/// recent hues to spread from (`reroll --no-spread`) draws them.
+#[cfg(all(test, feature = "reroll"))]
+pub fn generate_random_color(user_config: &UserConfig) -> RGB {
+    generate_spread_color_with_rng(&mut rand::thread_rng(), user_config, &[]).0
+}
 
-        if !current.pop() {
-            // Reached root, no config found
-            return None;
-        }
-    }
+/// Maximum number of candidates drawn when rerolling away from the current color.
+#[cfg(feature = "reroll")]
+pub const MAX_REROLL_ATTEMPTS: usize = 20;
+
+/// Result of rerolling a color.
+#[cfg(feature = "reroll")]
+#[derive(Debug, Clone, Copy)]
+pub struct Reroll {
+    pub color: RGB,
+    pub hue: f32,
+    /// Number of candidates drawn before one was accepted
+    pub attempts: usize,
+}
+
+/// Generate a new random color that differs from `current` by more than `min_delta` (Oklab ΔE).
+/// Draws at most MAX_REROLL_ATTEMPTS candidates, keeping the most distinct one if none qualify.
+#[cfg(feature = "reroll")]
+pub fn reroll_color(
+    user_config: &UserConfig,
+    recent_hues: &[f32],
+    current: Option<&RGB>,
+    min_delta: f32,
+) -> Reroll {
+    reroll_color_with_rng(
+        &mut rand::thread_rng(),
+        user_config,
+        recent_hues,
+        current,
+        min_delta,
+    )
+}
+
+#[cfg(feature = "reroll")]
+fn reroll_color_with_rng<R: Rng>(
+    rng: &mut R,
+    user_config: &UserConfig,
+    recent_hues: &[f32],
+    current: Option<&RGB>,
+    min_delta: f32,
+) -> Reroll {
+    let mut best: Option<(Reroll, f32)> = None;
+
+    for attempts in 1..=MAX_REROLL_ATTEMPTS {
+        let (color, hue) = generate_spread_color_with_rng(rng, user_config, recent_hues);
+        let candidate = Reroll {
+            color,
+            hue,
+            attempts,
+        };
+        let Some(current) = current else {
+            return candidate;
+        };
+
+        let delta = color.delta_e(current);
+        if delta > min_delta {
+            return candidate;
+        }
+        if best
+            .map(|(_, best_delta)| delta > best_delta)
+            .unwrap_or(true)
+        {
+            best = Some((candidate, delta));
+        }
+    }
+
+    // No candidate was distinct enough; fall back to the most distinct one
+    let (mut reroll, _) = best.expect("at least one reroll attempt");
+    reroll.attempts = MAX_REROLL_ATTEMPTS;
+    reroll
+}
+
+/// Generate a random color whose hue is kept at least `hue_spread` degrees away
+/// from the given recently generated hues.
+/// Returns the color and the hue it was generated from (0 for monochrome grays).
+#[cfg(feature = "reroll")]
+fn generate_spread_color_with_rng<R: Rng>(
+    rng: &mut R,
+    user_config: &UserConfig,
+    recent_hues: &[f32],
+) -> (RGB, f32) {
+    if user_config.monochrome {
+        return (monochrome_tab_color(rng.gen(), user_config), 0.0);
+    }
+    let (hue, saturation) = random_hue_and_saturation(rng, user_config);
+    let hue = spread_hue(hue, recent_hues, user_config);
+    let hue = avoid_reserved(hue, saturation, user_config);
+    (hsl_to_tab_color(hue, saturation, user_config), hue)
+}
+
+/// Dim a resolved color config according to the dimming settings.
+/// The background lightness is always scaled; the tab only when `dim_tab` is set.
+pub fn apply_dimming(color_config: &ColorConfig, dimming: &DimmingConfig) -> ColorConfig {
+    let tab = if dimming.dim_tab {
+        color_config.tab.scale_lightness(dimming.lightness_scale)
+    } else {
+        color_config.tab
+    };
+    ColorConfig {
+        tab,
+        background: color_config
+            .background
+            .scale_lightness(dimming.lightness_scale),
+    }
+}
+
+/// Number of directory levels `dir` is below `root` (0 when it is not below it).
+pub fn depth_below(root: &Path, dir: &Path) -> u32 {
+    canonical_path(dir)
+        .strip_prefix(canonical_path(root))
+        .map(|relative| relative.components().count() as u32)
+        .unwrap_or(0)
+}
+
+/// Darken the background by `ramp` Oklab lightness per directory level below the config source.
+/// The lightness never drops below zero.
+pub fn apply_depth_ramp(color_config: &ColorConfig, depth: u32, ramp: f32) -> ColorConfig {
+    ColorConfig {
+        tab: color_config.tab,
+        background: color_config
+            .background
+            .offset_lightness(-(ramp * depth as f32)),
+    }
+}
+
+/// WCAG relative luminance of a color (0.0 for black to 1.0 for white).
+pub fn relative_luminance(rgb: &RGB) -> f32 {
+    let linear = |channel: u8| {
+        let c = channel as f32 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(rgb.r) + 0.7152 * linear(rgb.g) + 0.0722 * linear(rgb.b)
+}
+
+/// WCAG contrast ratio between two colors, from 1.0 (identical) to 21.0 (black on white).
+pub fn contrast_ratio(a: &RGB, b: &RGB) -> f32 {
+    let (la, lb) = (relative_luminance(a), relative_luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+/// Minimum contrast ratio for normal text under WCAG AA.
+pub const WCAG_AA_CONTRAST: f32 = 4.5;
+
+/// Candidate foregrounds for a background with their contrast ratios:
+/// white, black, and light and dark variants tinted with the background's hue.
+pub fn foreground_options(background: &RGB) -> Vec<(&'static str, RGB, f32)> {
+    let (_, _, hue) = background.to_oklch();
+    [
+        (
+            "white",
+            RGB {
+                r: 255,
+                g: 255,
+                b: 255,
+            },
+        ),
+        ("black", RGB { r: 0, g: 0, b: 0 }),
+        ("tinted light", RGB::from_oklch(0.95, 0.03, hue)),
+        ("tinted dark", RGB::from_oklch(0.25, 0.04, hue)),
+    ]
+    .into_iter()
+    .map(|(name, fg)| (name, fg, contrast_ratio(&fg, background)))
+    .collect()
+}
+
+/// Suggest a readable foreground for a background, returning it with its contrast ratio.
+/// Picks light or dark text by whichever of white and black contrasts more, then prefers
+/// the tinted variant when it still meets WCAG AA.
+pub fn suggest_foreground(background: &RGB) -> (RGB, f32) {
+    let options = foreground_options(background);
+    let (_, white, white_ratio) = options[0];
+    let (_, black, black_ratio) = options[1];
+    let (plain, plain_ratio, (_, tinted, tinted_ratio)) = if white_ratio >= black_ratio {
+        (white, white_ratio, options[2])
+    } else {
+        (black, black_ratio, options[3])
+    };
+
+    if tinted_ratio >= WCAG_AA_CONTRAST {
+        (tinted, tinted_ratio)
+    } else {
+        (plain, plain_ratio)
+    }
+}
+
+/// Shift of the Oklab b component at a temperature_shift of ±1.0.
+const TEMPERATURE_B_RANGE: f32 = 0.06;
+
+/// Apply the user's color temperature shift to both colors.
+pub fn apply_temperature(color_config: &ColorConfig, shift: f32) -> ColorConfig {
+    ColorConfig {
+        tab: color_config.tab.shift_temperature(shift),
+        background: color_config.background.shift_temperature(shift),
+    }
+}
+
+/// Get a time as local minutes after midnight.
+pub fn local_minute_of_day(time: SystemTime) -> u32 {
+    let now = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as libc::time_t);
+    // SAFETY: localtime_r() with valid pointers to stack values is always safe to call.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return 0;
+        }
+        (tm.tm_hour * 60 + tm.tm_min) as u32
+    }
+}
+
+/// Parse a config file at the given path.
+pub fn parse_config(path: &Path, user_config: &UserConfig) -> Result<ColorConfig, TermtintError> {
+    let content = read_config(path)?;
+    parse_config_content(&canonical_path(path), &content, user_config)
+}
+
+/// Read a `.termtint` file's contents.
+pub fn read_config(path: &Path) -> Result<String, TermtintError> {
+    fs::read_to_string(path).map_err(|e| TermtintError::io("Failed to read config file", &e))
+}
+
+/// Parse the already-read contents of the config file at `path`, which must be
+/// canonical (as a `ConfigSource` holds it) since auto colors hash it as is.
+pub fn parse_config_content(
+    path: &Path,
+    content: &str,
+    user_config: &UserConfig,
+) -> Result<ColorConfig, TermtintError> {
+    let content = &normalize_content(content);
+    match detect_format(content) {
+        // Content with an `=` that isn't a color was most likely meant as TOML, so
+        // the TOML parser's complaint is the more useful one
+        ConfigFormat::SimpleColor if content.contains('=') => {
+            parse_simple_color(content, user_config)
+                .or_else(|_| parse_toml(path, content, user_config))
+        }
+        ConfigFormat::SimpleColor => parse_simple_color(content, user_config),
+        ConfigFormat::Toml => parse_toml(path, content, user_config),
+        ConfigFormat::Auto => Ok(parse_auto(path, user_config)),
+    }
+}
+
+/// Resolve symlinks and `.`/`..` components so every way of reaching a directory
+/// yields the same path. For files, only the containing directory is resolved, so a
+/// `.termtint` symlinked from elsewhere still belongs to the directory it sits in.
+/// Paths that can't be resolved are returned unchanged.
+pub fn canonical_path(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        return canonicalize_searchable(path).unwrap_or_else(|| path.to_path_buf());
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            canonicalize_searchable(parent)
+                .map(|parent| parent.join(name))
+                .unwrap_or_else(|| path.to_path_buf())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Canonicalize a path. When a directory on the way can't be searched (EACCES),
+/// the nearest ancestor that resolves is canonicalized instead and the rest of the
+/// path appended as is, so the path hashes the same way whichever symlink led to
+/// it, as it would without the locked directory.
+fn canonicalize_searchable(path: &Path) -> Option<PathBuf> {
+    canonicalize_searchable_with(path, &|path| path.canonicalize())
+}
+
+/// `canonicalize_searchable` with the canonicalization supplied by the caller.
+fn canonicalize_searchable_with(
+    path: &Path,
+    canonicalize: &dyn Fn(&Path) -> io::Result<PathBuf>,
+) -> Option<PathBuf> {
+    match canonicalize(path) {
+        Ok(canonical) => Some(canonical),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied && path.is_absolute() => path
+            .ancestors()
+            .skip(1)
+            .find_map(|ancestor| Some((ancestor, canonicalize(ancestor).ok()?)))
+            .and_then(|(ancestor, canonical)| {
+                Some(canonical.join(path.strip_prefix(ancestor).ok()?))
+            }),
+        Err(_) => None,
+    }
+}
+
+/// Expand a leading `~/` in a path pattern to the home directory, if there is one.
+pub fn expand_home(pattern: &str, home: Option<&Path>) -> String {
+    match (pattern.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => format!("{}/{}", home.to_string_lossy(), rest),
+        _ => pattern.to_string(),
+    }
+}
+
+/// Compile trigger path patterns, expanding `~/`, so a walk compiles them once
+/// rather than at every level. Patterns that aren't valid globs are skipped.
+fn compile_path_globs(patterns: &[String], home: Option<&Path>) -> Vec<glob::Pattern> {
+    patterns
+        .iter()
+        .filter_map(|pattern| glob::Pattern::new(&expand_home(pattern, home)).ok())
+        .collect()
+}
+
+/// Check if a directory matches any of the compiled trigger path globs.
+fn matches_path_glob(dir: &Path, globs: &[glob::Pattern]) -> bool {
+    let dir_str = dir.to_string_lossy();
+
+    // Use shell-like glob matching where * doesn't match path separators
+    // (`**` as a whole component still matches any number of levels)
+    let match_options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+
+    globs
+        .iter()
+        .any(|glob| glob.matches_with(&dir_str, match_options))
+}
+
+/// Globs compiled from a user config's path and dirname triggers, kept with the
+/// config so each pattern is compiled once per process rather than on every walk
+/// and lookup. A clone starts empty, and an entry whose pattern no longer matches
+/// the config's (triggers edited in place) is compiled afresh instead of used.
+#[derive(Default)]
+pub struct TriggerGlobCache(OnceLock<Vec<CompiledTriggerGlob>>);
+
+/// A trigger pattern and its compiled glob (None when it isn't a valid glob).
+type CompiledTriggerGlob = (TriggerKind, String, Option<glob::Pattern>);
+
+impl Clone for TriggerGlobCache {
+    fn clone(&self) -> Self {
+        TriggerGlobCache::default()
+    }
+}
+
+// Kept out of the config's Debug form, which fingerprints the color cache
+impl fmt::Debug for TriggerGlobCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TriggerGlobCache")
+    }
+}
+
+/// Compile a trigger pattern of a glob kind. Path patterns have a leading `!`
+/// stripped and `~/` expanded first.
+fn compile_trigger_glob(
+    kind: TriggerKind,
+    pattern: &str,
+    home: Option<&Path>,
+) -> Option<glob::Pattern> {
+    match kind {
+        TriggerKind::Path => {
+            let body = pattern.strip_prefix('!').unwrap_or(pattern);
+            glob::Pattern::new(&expand_home(body, home)).ok()
+        }
+        _ => glob::Pattern::new(pattern).ok(),
+    }
+}
+
+/// The compiled glob for each `kind` trigger pattern, in configured order, skipping
+/// patterns that aren't valid globs. Globs come from the config's cache when its
+/// entry is for the same pattern.
+fn trigger_globs(
+    user_config: &UserConfig,
+    kind: TriggerKind,
+) -> Vec<(&str, Cow<'_, glob::Pattern>)> {
+    let cached = user_config.trigger_globs.0.get_or_init(|| {
+        user_config
+            .triggers
+            .iter()
+            .filter(|trigger| matches!(trigger.kind, TriggerKind::Path | TriggerKind::Dirname))
+            .map(|trigger| {
+                let glob = compile_trigger_glob(
+                    trigger.kind,
+                    &trigger.pattern,
+                    user_config.home.as_deref(),
+                );
+                (trigger.kind, trigger.pattern.clone(), glob)
+            })
+            .collect()
+    });
+    let mut cached = cached
+        .iter()
+        .filter(|(cached_kind, ..)| *cached_kind == kind);
+    user_config
+        .trigger_patterns(kind)
+        .filter_map(|pattern| {
+            let glob = match cached.next() {
+                Some((_, cached_pattern, glob)) if cached_pattern == pattern => {
+                    Cow::Borrowed(glob.as_ref()?)
+                }
+                _ => Cow::Owned(compile_trigger_glob(
+                    kind,
+                    pattern,
+                    user_config.home.as_deref(),
+                )?),
+            };
+            Some((pattern, glob))
+        })
+        .collect()
+}
+
+/// A compiled `trigger_paths` entry. Entries starting with `!` are negations.
+struct TriggerPathGlob<'a> {
+    pattern: &'a str,
+    glob: Cow<'a, glob::Pattern>,
+    negated: bool,
+}
+
+/// The compiled `trigger_paths` in order. Patterns that aren't valid globs are skipped.
+fn compile_trigger_paths(user_config: &UserConfig) -> Vec<TriggerPathGlob<'_>> {
+    trigger_globs(user_config, TriggerKind::Path)
+        .into_iter()
+        .map(|(pattern, glob)| TriggerPathGlob {
+            pattern,
+            glob,
+            negated: pattern.starts_with('!'),
+        })
+        .collect()
+}
+
+/// How `trigger_paths` decided a directory: the positive pattern that matched it
+/// and, if a later `!` pattern also matched, the negation that vetoed it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerPathMatch<'a> {
+    pub pattern: &'a str,
+    pub vetoed_by: Option<&'a str>,
+}
+
+/// Evaluate compiled trigger paths against a directory. As in `.gitignore`, the
+/// last matching pattern decides: a negation vetoes earlier positive matches and
+/// a later positive pattern matches again. Returns None if no positive pattern
+/// matched.
+fn match_trigger_paths<'a>(
+    dir: &Path,
+    globs: &[TriggerPathGlob<'a>],
+) -> Option<TriggerPathMatch<'a>> {
+    let dir_str = dir.to_string_lossy();
+    let match_options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+
+    let mut decision: Option<TriggerPathMatch> = None;
+    for entry in globs {
+        if !entry.glob.matches_with(&dir_str, match_options) {
+            continue;
+        }
+        if !entry.negated {
+            decision = Some(TriggerPathMatch {
+                pattern: entry.pattern,
+                vetoed_by: None,
+            });
+        } else if let Some(decision) = &mut decision {
+            decision.vetoed_by = Some(entry.pattern);
+        }
+    }
+    decision
+}
+
+/// The trigger path decisions along the walk from `start_dir`, nearest first:
+/// every directory a positive pattern matched, up to and including the first
+/// match no negation vetoed. Stops at a `.termtint` file.
+pub fn trigger_path_matches<'a>(
+    start_dir: &Path,
+    user_config: &'a UserConfig,
+) -> Vec<(PathBuf, TriggerPathMatch<'a>)> {
+    let globs = compile_trigger_paths(user_config);
+    let mut matches = Vec::new();
+    for dir in config_walk(start_dir, user_config) {
+        if dir.join(".termtint").exists() {
+            break;
+        }
+        if let Some(found) = match_trigger_paths(dir, &globs) {
+            let done = found.vetoed_by.is_none();
+            matches.push((dir.to_path_buf(), found));
+            if done {
+                break;
+            }
+        }
+    }
+    matches
+}
+
+/// Compile an `exclude_paths` pattern. A trailing `/**` also covers the directory
+/// itself, so `~/Code/archive/**` excludes `~/Code/archive` and everything below it.
+fn compile_exclusion(pattern: &str, home: Option<&Path>) -> Vec<glob::Pattern> {
+    let mut patterns = vec![pattern.to_string()];
+    if let Some(base) = pattern.strip_suffix("/**") {
+        patterns.push(base.to_string());
+    }
+    compile_path_globs(&patterns, home)
+}
+
+/// The first `exclude_paths` pattern matching a directory, if any.
+fn matching_exclusion<'a>(
+    dir: &Path,
+    patterns: &'a [String],
+    home: Option<&Path>,
+) -> Option<&'a str> {
+    patterns
+        .iter()
+        .map(String::as_str)
+        .find(|pattern| matches_path_glob(dir, &compile_exclusion(pattern, home)))
+}
+
+/// The device a directory lives on, or None if it can't be read.
+fn device_id(dir: &Path) -> Option<u64> {
+    fs::metadata(dir).ok().map(|metadata| metadata.dev())
+}
+
+/// The directories a config search visits, from `start_dir` upward, stopping at
+/// `boundary`. The home boundary is inclusive and only applies to directories
+/// inside `home` (given both as entered and canonicalized, so either spelling of
+/// the path stops the walk). The device boundary stops before the first ancestor
+/// on another filesystem, which also avoids stat-ing slow network mounts above it.
+fn walk_dirs<'a>(
+    start_dir: &'a Path,
+    boundary: WalkBoundary,
+    home: Option<&Path>,
+) -> impl Iterator<Item = &'a Path> {
+    let homes: Vec<PathBuf> = match (boundary, home) {
+        (WalkBoundary::Home, Some(home)) => std::iter::once(home.to_path_buf())
+            .chain(home.canonicalize().ok())
+            .collect(),
+        _ => Vec::new(),
+    };
+    let start_device = match boundary {
+        WalkBoundary::Device => device_id(start_dir),
+        _ => None,
+    };
+    let mut stopped = false;
+    start_dir.ancestors().take_while(move |dir| {
+        if stopped || start_device.is_some_and(|device| device_id(dir) != Some(device)) {
+            return false;
+        }
+        stopped = homes.iter().any(|home| home == dir);
+        true
+    })
+}
+
+/// Whether a directory is the root of a git repository. Worktrees and submodules
+/// have a `.git` file pointing at the real git directory, so files count too.
+fn is_git_root(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
+
+/// The root of the git repository containing `dir`, if any.
+pub fn git_root(dir: &Path) -> Option<&Path> {
+    dir.ancestors().find(|ancestor| is_git_root(ancestor))
+}
+
+/// The directories `find_config_source` visits for `start_dir` under the user's
+/// `walk_boundary`, `max_walk_depth`, and `respect_git_root`.
+fn config_walk<'a>(
+    start_dir: &'a Path,
+    user_config: &UserConfig,
+) -> impl Iterator<Item = &'a Path> {
+    let home = user_config.home.as_deref();
+    let levels = user_config
+        .max_walk_depth
+        .map_or(usize::MAX, |depth| depth + 1);
+    let respect_git_root = user_config.respect_git_root;
+    let mut past_git_root = false;
+    walk_dirs(start_dir, user_config.walk_boundary, home)
+        .take_while(move |dir| {
+            let visit = !past_git_root;
+            past_git_root = respect_git_root && is_git_root(dir);
+            visit
+        })
+        .take(levels)
+}
+
+/// Whether `max_walk_depth` stops the search for `start_dir` before its walk
+/// boundary, leaving ancestors unchecked.
+pub fn walk_depth_exceeded(start_dir: &Path, user_config: &UserConfig) -> bool {
+    let Some(depth) = user_config.max_walk_depth else {
+        return false;
+    };
+    let unlimited = UserConfig {
+        max_walk_depth: None,
+        ..user_config.clone()
+    };
+    config_walk(start_dir, &unlimited).nth(depth + 1).is_some()
+}
+
+/// The `exclude_paths` pattern that suppresses trigger sources for `start_dir`, if
+/// any: the first pattern matching a directory the walk visits before it finds a
+/// `.termtint` file.
+pub fn matched_exclusion<'a>(start_dir: &Path, user_config: &'a UserConfig) -> Option<&'a str> {
+    for dir in config_walk(start_dir, user_config) {
+        if dir.join(".termtint").exists() {
+            return None;
+        }
+        if let Some(pattern) =
+            matching_exclusion(dir, &user_config.exclude_paths, user_config.home.as_deref())
+        {
+            return Some(pattern);
+        }
+    }
+    None
+}
+
+/// Find a configuration source by walking up from start_dir.
+/// At each directory the source kinds are checked in `trigger_priority` order:
+/// by default explicit `.termtint` files, then trigger paths, then trigger files.
+/// The nearest directory with any match wins. Trigger paths are evaluated in
+/// order and the last matching pattern decides, so a later `!pattern` vetoes an
+/// earlier positive match. Within trigger files the first
+/// entry in the configured list that is present wins, and a glob entry matches
+/// the alphabetically first file.
+/// Once the walk reaches a directory matching `exclude_paths`, trigger paths and
+/// trigger files are ignored there and above; `.termtint` files still apply.
+/// The walk stops at the configured `walk_boundary`, or after `max_walk_depth`
+/// parent levels. With `respect_git_root` it also stops at the repository root,
+/// and trigger files inside the repository resolve to the root directory.
+/// Returns ConfigSource describing where the config comes from, or None if nothing found.
+#[cfg(test)]
+pub fn find_config_source(start_dir: &Path, user_config: &UserConfig) -> Option<ConfigSource> {
+    find_config_source_with(
+        start_dir,
+        user_config,
+        &mut list_dir_names,
+        &mut Explanation::disabled(),
+    )
+}
+
+/// `find_config_source` with the directory listing used for glob trigger files
+/// (and, with `explain`, to tell unreadable levels) supplied by the caller,
+/// recording what each directory was checked for.
+fn find_config_source_with(
+    start_dir: &Path,
+    user_config: &UserConfig,
+    list_dir: &mut dyn FnMut(&Path) -> io::Result<Vec<String>>,
+    explain: &mut Explanation,
+) -> Option<ConfigSource> {
+    explain.step("Directory walk");
+    explain.note(|| {
+        let priority: Vec<&str> = user_config
+            .trigger_priority
+            .iter()
+            .map(|kind| kind.name())
+            .collect();
+        format!(
+            "from {}, checking {} in each directory",
+            start_dir.display(),
+            priority.join(", ")
+        )
+    });
+    let path_globs = compile_trigger_paths(user_config);
+    let dirname_globs = trigger_globs(user_config, TriggerKind::Dirname);
+    let exclude_globs: Vec<glob::Pattern> = user_config
+        .exclude_paths
+        .iter()
+        .flat_map(|pattern| compile_exclusion(pattern, user_config.home.as_deref()))
+        .collect();
+    let mut excluded = false;
+
+    for current in config_walk(start_dir, user_config) {
+        // Excluded directories (and their ancestors, whose trigger colors would
+        // otherwise cover them) only honor .termtint files
+        if !excluded && matches_path_glob(current, &exclude_globs) {
+            excluded = true;
+            explain.note(|| {
+                format!(
+                    "{}: excluded by exclude_paths, only .termtint files count from here",
+                    current.display()
+                )
+            });
+        }
+
+        let mut checked = Vec::new();
+        for kind in &user_config.trigger_priority {
+            let source = match kind {
+                SourceKind::Termtint => {
+                    let termtint_path = current.join(".termtint");
+                    if termtint_path.exists() {
+                        Some(ConfigSource::Termtint(canonical_path(&termtint_path)))
+                    } else if user_config.auto_from_vscode {
+                        vscode_source(current)
+                    } else {
+                        None
+                    }
+                }
+                _ if excluded => None,
+                // Globs match the path as entered, but the stored directory is
+                // canonical so it hashes the same way regardless of which symlink
+                // led here
+                SourceKind::Paths => match_trigger_paths(current, &path_globs)
+                    .filter(|found| found.vetoed_by.is_none())
+                    .map(|_| ConfigSource::TriggerPath(canonical_path(current))),
+                SourceKind::Files => match_trigger_entry(
+                    current,
+                    user_config.triggers_of(TriggerKind::File),
+                    user_config.trigger_files_case_sensitive,
+                    list_dir,
+                )
+                .map(|_| trigger_file_source(current, user_config)),
+                SourceKind::Dirnames => {
+                    let name = current.file_name().map(|name| name.to_string_lossy());
+                    name.is_some_and(|name| {
+                        dirname_globs.iter().any(|(_, glob)| glob.matches(&name))
+                    })
+                    .then(|| ConfigSource::TriggerDirname(canonical_path(current)))
+                }
+            };
+            if explain.is_enabled() {
+                checked.push(describe_check(
+                    *kind,
+                    current,
+                    source.as_ref(),
+                    excluded,
+                    user_config,
+                ));
+            }
+            if source.is_some() {
+                explain.note(|| format!("{}: {}", current.display(), checked.join("; ")));
+                return source;
+            }
+        }
+        let unreadable = match explain.is_enabled() {
+            true => list_dir(current).err(),
+            false => None,
+        };
+        explain.note(|| match unreadable.as_ref().map(unreadable_reason) {
+            Some(reason) => format!(
+                "{}: {}; can't be read ({}), so skipped as empty",
+                current.display(),
+                checked.join("; "),
+                reason
+            ),
+            None => format!("{}: {}", current.display(), checked.join("; ")),
+        });
+    }
+
+    // Reached the walk boundary, no config found
+    explain.note(|| match walk_depth_exceeded(start_dir, user_config) {
+        true => "stopped at max_walk_depth without a source".to_string(),
+        false => "reached the end of the walk without a source".to_string(),
+    });
+    None
+}
+
+/// Why a directory in the walk can't be read, from the error listing it: a
+/// permission error (common for parents in containers), or a directory removed
+/// while the shell was in it. Lookups there find nothing, so the level counts as
+/// empty and the walk carries on to its parent; this only describes it for
+/// `apply --explain`.
+fn unreadable_reason(error: &io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+        io::ErrorKind::NotFound => "no longer exists".to_string(),
+        _ => error.to_string(),
+    }
+}
+
+/// What checking one source kind in a directory found, for `apply --explain`.
+fn describe_check(
+    kind: SourceKind,
+    dir: &Path,
+    source: Option<&ConfigSource>,
+    excluded: bool,
+    user_config: &UserConfig,
+) -> String {
+    match (kind, source) {
+        (SourceKind::Termtint, None) if user_config.auto_from_vscode => {
+            "no .termtint or Peacock color".to_string()
+        }
+        (SourceKind::Termtint, None) => "no .termtint".to_string(),
+        (_, None) if excluded => format!("{} skipped (excluded)", kind.name()),
+        (SourceKind::Paths, None) => {
+            let globs = compile_trigger_paths(user_config);
+            match match_trigger_paths(dir, &globs) {
+                Some(found) => format!(
+                    "trigger path {} vetoed by {}",
+                    found.pattern,
+                    found.vetoed_by.unwrap_or("?")
+                ),
+                None => "no trigger path".to_string(),
+            }
+        }
+        (SourceKind::Files, None) => "no trigger file".to_string(),
+        (SourceKind::Dirnames, None) => "no directory name match".to_string(),
+        (SourceKind::Paths, Some(source)) => {
+            let globs = compile_trigger_paths(user_config);
+            let pattern = match_trigger_paths(dir, &globs).map_or("?", |found| found.pattern);
+            format!("trigger path {} matched, using {}", pattern, source)
+        }
+        (SourceKind::Files, Some(source)) => format!(
+            "trigger file {} found, using {}",
+            matched_trigger_name(dir, user_config).unwrap_or("?"),
+            source
+        ),
+        (SourceKind::Dirnames, Some(source)) => format!(
+            "directory name matches {}, using {}",
+            matched_dirname(dir, user_config).unwrap_or("?"),
+            source
+        ),
+        (SourceKind::Termtint, Some(source)) => format!("found, using {}", source),
+    }
+}
+
+/// The `trigger_dirnames` entry matching a directory's own name, if any.
+pub fn matched_dirname<'a>(dir: &Path, user_config: &'a UserConfig) -> Option<&'a str> {
+    let name = dir.file_name()?.to_string_lossy();
+    trigger_globs(user_config, TriggerKind::Dirname)
+        .into_iter()
+        .find(|(_, glob)| glob.matches(&name))
+        .map(|(pattern, _)| pattern)
+}
+
+/// The project directory of a `.vscode/settings.json` path.
+fn vscode_settings_root(path: &Path) -> PathBuf {
+    path.parent()
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Read the Peacock color (`"peacock.color"`) from a VS Code settings file, which
+/// may contain comments and trailing commas.
+///
+/// # Returns
+/// * `Ok(Some(color))` - The color string as written
+/// * `Ok(None)` - If the settings don't set a Peacock color
+/// * `Err(TermtintError)` - If the file can't be read or isn't a JSON object
+pub fn read_peacock_color(path: &Path) -> Result<Option<String>, TermtintError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| TermtintError::io(format!("Failed to read {}", path.display()), &e))?;
+    let (json, _) = crate::jsonc::strip_jsonc(&content);
+    let invalid = |reason: String| TermtintError::parse(Some(path.to_path_buf()), reason);
+    let settings: serde_json::Value = serde_json::from_str(&json)
+        .map_err(|e| invalid(format!("Failed to parse {}: {}", path.display(), e)))?;
+    if !settings.is_object() {
+        return Err(invalid(format!("{} is not a JSON object", path.display())));
+    }
+    Ok(settings
+        .get("peacock.color")
+        .and_then(|color| color.as_str())
+        .map(str::to_string))
+}
+
+/// A VS Code settings source for a directory whose `.vscode/settings.json` sets a
+/// Peacock color. Unreadable or malformed settings are skipped, like a missing file.
+fn vscode_source(dir: &Path) -> Option<ConfigSource> {
+    let path = export::vscode_settings_path(dir);
+    if !path.is_file() {
+        return None;
+    }
+    read_peacock_color(&path)
+        .ok()
+        .flatten()
+        .map(|_| ConfigSource::VsCode(canonical_path(&path)))
+}
+
+/// Parse the colors of a VS Code settings source: the Peacock color is the tab
+/// color, and the background is derived from it as for a simple `.termtint`.
+fn parse_vscode_settings(
+    path: &Path,
+    user_config: &UserConfig,
+) -> Result<ColorConfig, TermtintError> {
+    let color = read_peacock_color(path)?.ok_or_else(|| {
+        TermtintError::parse(
+            Some(path.to_path_buf()),
+            format!("No peacock.color in {}", path.display()),
+        )
+    })?;
+    parse_simple_color(&color, user_config)
+}
+
+/// The source for a directory containing a trigger file. With `respect_git_root`
+/// the repository root stands in for the directory, and a `.termtint` between the
+/// two is the repository's own color, so it still wins over the generated one.
+fn trigger_file_source(dir: &Path, user_config: &UserConfig) -> ConfigSource {
+    let project = match user_config.respect_git_root {
+        true => git_root(dir).unwrap_or(dir),
+        false => dir,
+    };
+    let termtint_path = dir
+        .ancestors()
+        .skip(1)
+        .take_while(|ancestor| ancestor.starts_with(project))
+        .map(|ancestor| ancestor.join(".termtint"))
+        .find(|path| path.exists());
+    match termtint_path {
+        Some(termtint_path) => ConfigSource::Termtint(canonical_path(&termtint_path)),
+        None => ConfigSource::TriggerFile(canonical_path(project)),
+    }
 }
 
 /// Parse a config from a ConfigSource.
@@ -326,966 +2011,4780 @@ pub fn find_config_source(start_dir: &Path, user_config: &UserConfig) -> Option<
 pub fn parse_config_source(
     source: &ConfigSource,
     user_config: &UserConfig,
-) -> Result<ColorConfig, String> {
+) -> Result<ColorConfig, TermtintError> {
     match source {
-        ConfigSource::Termtint(path) => parse_config(path, user_config),
-        ConfigSource::TriggerPath(dir_path) | ConfigSource::TriggerFile(dir_path) => {
-            // Generate auto color based on directory path
-            let dir = PathBuf::from(dir_path);
-            Ok(parse_auto(&dir, user_config))
+        ConfigSource::Termtint(path) => {
+            parse_config_content(path, &read_config(path)?, user_config)
+        }
+        ConfigSource::VsCode(path) => parse_vscode_settings(path, user_config),
+        ConfigSource::TriggerPath(_)
+        | ConfigSource::TriggerFile(_)
+        | ConfigSource::TriggerDirname(_)
+        | ConfigSource::KubeContext { .. } => {
+            // A trigger with a color of its own uses it as is
+            if let Some(color) = source_trigger(source, user_config).and_then(|t| t.color.as_ref())
+            {
+                return parse_simple_color(color, &trigger_user_config(source, user_config));
+            }
+            // Generate auto color based on directory path (or package name)
+            match parent_blend(source, user_config) {
+                Some(blend) => Ok(blend.blended()),
+                None => Ok(trigger_auto_color(source, user_config)),
+            }
+        }
+        ConfigSource::Environment { rule, assignment } => {
+            let color = user_config
+                .env_triggers
+                .iter()
+                .find(|(configured, _)| configured == rule)
+                .map(|(_, color)| color.as_str())
+                .ok_or_else(|| {
+                    TermtintError::Config(format!("env trigger '{}' is no longer configured", rule))
+                })?;
+            if color.eq_ignore_ascii_case("auto") {
+                // Hash the assignment, so each matching value gets its own color
+                let inputs = AutoHashInputs::for_path(Path::new(assignment), user_config);
+                Ok(auto_color(&inputs, user_config))
+            } else {
+                parse_simple_color(color, user_config)
+            }
+        }
+    }
+}
+
+/// Whether an `[env_triggers]` rule matches the environment, returning the
+/// variable's assignment (`VAR=value`) when it does. A rule is `VAR=value-glob`,
+/// or a bare `VAR` matching any non-empty value.
+fn env_rule_matches(rule: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    let (var, pattern) = rule.split_once('=').unwrap_or((rule, "?*"));
+    let value = lookup(var.trim())?;
+    let matches = glob::Pattern::new(pattern.trim()).is_ok_and(|glob| glob.matches(&value));
+    matches.then(|| format!("{}={}", var.trim(), value))
+}
+
+/// The first `[env_triggers]` rule, in key order, matching the environment.
+fn matched_env_trigger_with(
+    user_config: &UserConfig,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    explain: &mut Explanation,
+) -> Option<ConfigSource> {
+    user_config.env_triggers.iter().find_map(|(rule, _)| {
+        let assignment = env_rule_matches(rule, lookup);
+        explain.note(|| match &assignment {
+            Some(assignment) => format!("[env_triggers] {}: matched {}", rule, assignment),
+            None => format!("[env_triggers] {}: no match", rule),
+        });
+        assignment.map(|assignment| ConfigSource::Environment {
+            rule: rule.clone(),
+            assignment,
+        })
+    })
+}
+
+/// The first `kube-context` trigger matching the current kubectl context, which
+/// is only looked up when such a trigger is configured.
+fn matched_kube_context_with(
+    user_config: &UserConfig,
+    current_context: &dyn Fn() -> Option<String>,
+    explain: &mut Explanation,
+) -> Option<ConfigSource> {
+    let mut triggers = user_config.triggers_of(TriggerKind::KubeContext).peekable();
+    triggers.peek()?;
+    let Some(context) = current_context() else {
+        explain.note(|| "kube context: none set".to_string());
+        return None;
+    };
+    let trigger = triggers.find(|trigger| {
+        glob::Pattern::new(&trigger.pattern).is_ok_and(|glob| glob.matches(&context))
+    });
+    explain.note(|| match trigger {
+        Some(trigger) => format!("kube context {}: matched {}", context, trigger.pattern),
+        None => format!("kube context {}: no trigger matches", context),
+    });
+    Some(ConfigSource::KubeContext {
+        pattern: trigger?.pattern.clone(),
+        context,
+    })
+}
+
+/// The environment and kube context sources, checked together as one step.
+fn matched_environment_source(
+    user_config: &UserConfig,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    current_context: &dyn Fn() -> Option<String>,
+    explain: &mut Explanation,
+) -> Option<ConfigSource> {
+    let has_kube_triggers = user_config
+        .triggers_of(TriggerKind::KubeContext)
+        .next()
+        .is_some();
+    if user_config.env_triggers.is_empty() && !has_kube_triggers {
+        return None;
+    }
+    explain.step("Environment triggers");
+    matched_env_trigger_with(user_config, lookup, explain)
+        .or_else(|| matched_kube_context_with(user_config, current_context, explain))
+}
+
+/// Find the config source for a directory: `[env_triggers]` rules matching the
+/// context's environment and `kube-context` triggers, then the directory walk of
+/// `find_config_source`, or the other way around with `env_trigger_priority = "after"`.
+pub fn find_source(
+    ctx: &Context,
+    start_dir: &Path,
+    user_config: &UserConfig,
+) -> Option<ConfigSource> {
+    find_source_explained(ctx, start_dir, user_config, &mut Explanation::disabled())
+}
+
+/// `find_source`, recording each rule checked and directory walked in `explain`.
+pub fn find_source_explained(
+    ctx: &Context,
+    start_dir: &Path,
+    user_config: &UserConfig,
+    explain: &mut Explanation,
+) -> Option<ConfigSource> {
+    find_source_with(
+        start_dir,
+        user_config,
+        &|var| ctx.var(var).map(str::to_string),
+        &|| crate::kube::current_context(ctx),
+        explain,
+    )
+}
+
+fn find_source_with(
+    start_dir: &Path,
+    user_config: &UserConfig,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    current_context: &dyn Fn() -> Option<String>,
+    explain: &mut Explanation,
+) -> Option<ConfigSource> {
+    let walk = |explain: &mut Explanation| {
+        find_config_source_with(start_dir, user_config, &mut list_dir_names, explain)
+    };
+    match user_config.env_trigger_priority {
+        EnvTriggerPriority::Before => {
+            matched_environment_source(user_config, lookup, current_context, explain)
+                .or_else(|| walk(explain))
+        }
+        EnvTriggerPriority::After => walk(explain)
+            .or_else(|| matched_environment_source(user_config, lookup, current_context, explain)),
+    }
+}
+
+/// Whether a trigger file entry is a glob pattern (e.g. `*.cabal`) rather than a file name.
+pub fn is_glob_pattern(entry: &str) -> bool {
+    entry.contains(['*', '?', '['])
+}
+
+/// The names of the entries in a directory.
+fn list_dir_names(dir: &Path) -> io::Result<Vec<String>> {
+    fs::read_dir(dir).map(|entries| {
+        entries
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect()
+    })
+}
+
+/// Largest trigger file read to check a `contains` or `toml_key` condition.
+const CONTENT_MATCH_LIMIT: u64 = 256 * 1024;
+
+/// A trigger file and the content condition checked against it.
+type ContentCheckKey = (PathBuf, String);
+
+/// A content check's result and the trigger file's modification time when it was made.
+type ContentCheck = (Option<SystemTime>, Result<(), String>);
+
+/// Content checks made by the current resolution, so a file isn't read again for
+/// every lookup of its trigger. A check made before the file was last modified
+/// (a `Cargo.toml` gaining a `[workspace]`) is replaced rather than kept beside
+/// the new one. `serve` clears them between requests.
+static CONTENT_CHECKS: Mutex<BTreeMap<ContentCheckKey, ContentCheck>> = Mutex::new(BTreeMap::new());
+
+/// Forget the content checks made so far, so the next resolution reads trigger
+/// files afresh.
+pub fn clear_content_checks() {
+    CONTENT_CHECKS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clear();
+}
+
+/// Whether a dotted key such as `workspace` or `package.metadata.docs` is defined.
+fn has_toml_key(table: &toml::Table, key: &str) -> bool {
+    let mut value: Option<&toml::Value> = None;
+    for part in key.split('.') {
+        let table = match value {
+            None => table,
+            Some(toml::Value::Table(nested)) => nested,
+            Some(_) => return false,
+        };
+        let Some(next) = table.get(part.trim()) else {
+            return false;
+        };
+        value = Some(next);
+    }
+    value.is_some()
+}
+
+/// Check a file against a trigger's `contains` and `toml_key` conditions,
+/// explaining why it fails. Files over `CONTENT_MATCH_LIMIT` always fail.
+fn read_content_condition(trigger: &Trigger, file: &Path) -> Result<(), String> {
+    let metadata = fs::metadata(file).map_err(|e| format!("can't be read: {}", e))?;
+    if metadata.len() > CONTENT_MATCH_LIMIT {
+        return Err(format!(
+            "is larger than {} KiB, so its contents aren't checked",
+            CONTENT_MATCH_LIMIT / 1024
+        ));
+    }
+    let content = fs::read_to_string(file).map_err(|e| format!("can't be read: {}", e))?;
+    if let Some(text) = &trigger.contains {
+        if !content.contains(text.as_str()) {
+            return Err(format!("doesn't contain {:?}", text));
+        }
+    }
+    if let Some(key) = &trigger.toml_key {
+        let table: toml::Table = content
+            .parse()
+            .map_err(|_| format!("isn't valid TOML, so it has no key '{}'", key))?;
+        if !has_toml_key(&table, key) {
+            return Err(format!("has no key '{}'", key));
+        }
+    }
+    Ok(())
+}
+
+/// Check a trigger's content conditions against the file it matched. Triggers
+/// without conditions accept any file without reading it.
+pub fn check_trigger_content(trigger: &Trigger, file: &Path) -> Result<(), String> {
+    if !trigger.has_content_condition() {
+        return Ok(());
+    }
+    let modified = fs::metadata(file).and_then(|m| m.modified()).ok();
+    let condition = format!("{:?} {:?}", trigger.contains, trigger.toml_key);
+    let key = (file.to_path_buf(), condition);
+    let mut checks = CONTENT_CHECKS.lock().unwrap_or_else(|e| e.into_inner());
+    match checks.get(&key) {
+        Some((checked_mtime, result)) if *checked_mtime == modified => result.clone(),
+        _ => {
+            let result = read_content_condition(trigger, file);
+            checks.insert(key, (modified, result.clone()));
+            result
+        }
+    }
+}
+
+/// Find the first trigger file entry present in `dir` whose content conditions
+/// hold, with the path it matched. Plain names are checked directly. Glob entries
+/// are matched against the directory listing, which is read at most once, and
+/// pick the first match by name. A directory that can't be listed has no glob
+/// matches.
+fn match_trigger_entry<'a>(
+    dir: &Path,
+    entries: impl Iterator<Item = &'a Trigger>,
+    case_sensitive: bool,
+    list_dir: &mut dyn FnMut(&Path) -> io::Result<Vec<String>>,
+) -> Option<(&'a Trigger, PathBuf)> {
+    let options = glob::MatchOptions {
+        case_sensitive,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    let mut listing: Option<Vec<String>> = None;
+
+    for trigger in entries {
+        let entry = trigger.pattern.as_str();
+        if !is_glob_pattern(entry) {
+            let path = dir.join(entry);
+            if path.exists() && check_trigger_content(trigger, &path).is_ok() {
+                return Some((trigger, path));
+            }
+            continue;
+        }
+        let Ok(pattern) = glob::Pattern::new(entry) else {
+            continue;
+        };
+        let names = listing.get_or_insert_with(|| {
+            let mut names = list_dir(dir).unwrap_or_default();
+            names.sort();
+            names
+        });
+        if let Some(path) = names
+            .iter()
+            .filter(|name| pattern.matches_with(name, options))
+            .map(|name| dir.join(name))
+            .find(|path| check_trigger_content(trigger, path).is_ok())
+        {
+            return Some((trigger, path));
+        }
+    }
+    None
+}
+
+/// The first configured trigger file present in a directory whose content
+/// conditions hold, with the file it matched.
+pub fn matched_trigger<'a>(
+    dir: &Path,
+    user_config: &'a UserConfig,
+) -> Option<(&'a Trigger, PathBuf)> {
+    match_trigger_entry(
+        dir,
+        user_config.triggers_of(TriggerKind::File),
+        user_config.trigger_files_case_sensitive,
+        &mut list_dir_names,
+    )
+}
+
+/// The first configured trigger file entry (a name or glob) present in a directory.
+pub fn matched_trigger_name<'a>(dir: &Path, user_config: &'a UserConfig) -> Option<&'a str> {
+    matched_trigger(dir, user_config).map(|(trigger, _)| trigger.pattern.as_str())
+}
+
+/// Trigger files present along the walk from `start_dir` that were passed over
+/// because their content conditions failed, nearest first, with the reason. The
+/// walk stops at a `.termtint` file or the first directory with an accepted
+/// trigger file.
+pub fn rejected_trigger_files<'a>(
+    start_dir: &Path,
+    user_config: &'a UserConfig,
+) -> Vec<(&'a Trigger, PathBuf, String)> {
+    let options = glob::MatchOptions {
+        case_sensitive: user_config.trigger_files_case_sensitive,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    let mut rejected = Vec::new();
+    for dir in config_walk(start_dir, user_config) {
+        if dir.join(".termtint").exists() || matched_trigger(dir, user_config).is_some() {
+            break;
+        }
+        let mut names = list_dir_names(dir).unwrap_or_default();
+        names.sort();
+        for trigger in user_config.triggers_of(TriggerKind::File) {
+            if !trigger.has_content_condition() {
+                continue;
+            }
+            let files: Vec<PathBuf> = match is_glob_pattern(&trigger.pattern) {
+                false => vec![dir.join(&trigger.pattern)],
+                true => match glob::Pattern::new(&trigger.pattern) {
+                    Ok(pattern) => names
+                        .iter()
+                        .filter(|name| pattern.matches_with(name, options))
+                        .map(|name| dir.join(name))
+                        .collect(),
+                    Err(_) => Vec::new(),
+                },
+            };
+            for file in files.into_iter().filter(|file| file.exists()) {
+                if let Err(reason) = check_trigger_content(trigger, &file) {
+                    rejected.push((trigger, file, reason));
+                }
+            }
+        }
+    }
+    rejected
+}
+
+/// The first configured trigger file present in a directory.
+fn matched_trigger_file(dir: &Path, user_config: &UserConfig) -> Option<PathBuf> {
+    matched_trigger(dir, user_config).map(|(_, file)| file)
+}
+
+/// The configured trigger behind a trigger source: the trigger file present in
+/// its directory, or the path pattern or directory name matching it.
+pub fn source_trigger<'a>(
+    source: &ConfigSource,
+    user_config: &'a UserConfig,
+) -> Option<&'a Trigger> {
+    match source {
+        ConfigSource::TriggerFile(dir_path) => {
+            matched_trigger(Path::new(dir_path), user_config).map(|(trigger, _)| trigger)
+        }
+        ConfigSource::TriggerPath(dir_path) => {
+            let globs = compile_trigger_paths(user_config);
+            let found = match_trigger_paths(Path::new(dir_path), &globs)
+                .filter(|found| found.vetoed_by.is_none())?;
+            user_config.trigger(TriggerKind::Path, found.pattern)
+        }
+        ConfigSource::TriggerDirname(dir_path) => user_config.trigger(
+            TriggerKind::Dirname,
+            matched_dirname(Path::new(dir_path), user_config)?,
+        ),
+        ConfigSource::KubeContext { pattern, .. } => {
+            user_config.trigger(TriggerKind::KubeContext, pattern)
+        }
+        ConfigSource::Termtint(_) | ConfigSource::VsCode(_) | ConfigSource::Environment { .. } => {
+            None
+        }
+    }
+}
+
+/// The trigger behind a trigger source, if it sets its own background lightness
+/// or saturation.
+pub fn trigger_background_params<'a>(
+    source: &ConfigSource,
+    user_config: &'a UserConfig,
+) -> Option<&'a Trigger> {
+    source_trigger(source, user_config).filter(|trigger| trigger.has_background_params())
+}
+
+/// The user config colors for a trigger source are derived with: the matched
+/// trigger's hue band and background parameters replace the global ones.
+pub fn trigger_user_config<'a>(
+    source: &ConfigSource,
+    user_config: &'a UserConfig,
+) -> Cow<'a, UserConfig> {
+    match source_trigger(source, user_config) {
+        Some(trigger) if trigger.has_hue_band() || trigger.has_background_params() => Cow::Owned(
+            user_config
+                .with_hue_band(trigger)
+                .with_background_params(trigger),
+        ),
+        _ => Cow::Borrowed(user_config),
+    }
+}
+
+/// Generate the auto color for a trigger source, constraining the hue to the
+/// matched trigger's band when it has one.
+fn trigger_auto_color(source: &ConfigSource, user_config: &UserConfig) -> ColorConfig {
+    let inputs = AutoHashInputs::for_source(source, user_config);
+    auto_color(&inputs, &trigger_user_config(source, user_config))
+}
+
+/// Find the nearest `.termtint` file in `dir` or any of its ancestors.
+pub fn find_ancestor_termtint(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|ancestor| ancestor.join(".termtint"))
+        .find(|path| path.exists())
+}
+
+/// Inputs for blending a trigger-sourced color with its parent project's color.
+#[derive(Debug, Clone)]
+pub struct ParentBlend {
+    /// The ancestor `.termtint` providing the parent color
+    pub parent_path: PathBuf,
+    pub parent: ColorConfig,
+    /// The auto-generated color for the trigger directory itself
+    pub own: ColorConfig,
+    /// Weight of the parent color (0.0 = own color only, 1.0 = parent color only)
+    pub factor: f32,
+}
+
+impl ParentBlend {
+    /// Blend the tab and background colors in Oklch.
+    pub fn blended(&self) -> ColorConfig {
+        ColorConfig {
+            tab: self.own.tab.blend_oklch(&self.parent.tab, self.factor),
+            background: self
+                .own
+                .background
+                .blend_oklch(&self.parent.background, self.factor),
+        }
+    }
+}
+
+/// Resolve the parent blend for a trigger source.
+/// Returns None for `.termtint` sources, when `blend_with_parent` is disabled,
+/// or when no parent `.termtint` can be parsed.
+pub fn parent_blend(source: &ConfigSource, user_config: &UserConfig) -> Option<ParentBlend> {
+    if user_config.blend_with_parent <= 0.0
+        || matches!(
+            source,
+            ConfigSource::Termtint(_)
+                | ConfigSource::VsCode(_)
+                | ConfigSource::Environment { .. }
+                | ConfigSource::KubeContext { .. }
+        )
+        || source_trigger(source, user_config).is_some_and(|trigger| trigger.color.is_some())
+    {
+        return None;
+    }
+    let parent_path = find_ancestor_termtint(&source.root_dir())?;
+    let parent = parse_config(&parent_path, user_config).ok()?;
+    Some(ParentBlend {
+        parent_path,
+        parent,
+        own: trigger_auto_color(source, user_config),
+        factor: user_config.blend_with_parent,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_color_hex_with_hash() {
+        let rgb = parse_color("#ff5500").unwrap();
+        assert_eq!(
+            rgb,
+            RGB {
+                r: 255,
+                g: 85,
+                b: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_color_hex_without_hash() {
+        let rgb = parse_color("00ff00").unwrap();
+        assert_eq!(rgb, RGB { r: 0, g: 255, b: 0 });
+    }
+
+    #[test]
+    fn test_parse_color_hex_with_whitespace() {
+        let rgb = parse_color("  #aabbcc  ").unwrap();
+        assert_eq!(
+            rgb,
+            RGB {
+                r: 170,
+                g: 187,
+                b: 204
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_color_hex_3digit() {
+        let rgb = parse_color("#f50").unwrap();
+        assert_eq!(
+            rgb,
+            RGB {
+                r: 255,
+                g: 85,
+                b: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_color_hex_3digit_abc() {
+        let rgb = parse_color("#abc").unwrap();
+        assert_eq!(
+            rgb,
+            RGB {
+                r: 170,
+                g: 187,
+                b: 204
+            }
+        );
+    }
+
+    /// What csscolorparser alone makes of a color, normalized like `parse_color_with_alpha`.
+    fn parse_color_with_library(s: &str) -> Option<RGB> {
+        let s = s.trim();
+        let normalized =
+            if s.chars().all(|c| c.is_ascii_hexdigit()) && (s.len() == 6 || s.len() == 8) {
+                format!("#{}", s)
+            } else {
+                s.to_string()
+            };
+        let [r, g, b, _] = csscolorparser::parse(&normalized).ok()?.to_rgba8();
+        Some(RGB { r, g, b })
+    }
+
+    #[test]
+    fn test_parse_hex_fast_matches_library_for_3_digits() {
+        for value in 0..0x1000u32 {
+            for hex in [format!("#{:03x}", value), format!("#{:03X}", value)] {
+                assert_eq!(
+                    parse_hex_fast(&hex),
+                    parse_color_with_library(&hex),
+                    "{}",
+                    hex
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_hex_fast_matches_library_for_6_digits() {
+        // Every value of each channel, then a stride across the whole space
+        let channels = (0..=0xffu32).flat_map(|v| [v << 16, v << 8, v, v * 0x010101]);
+        let stride = (0..0x1000000u32).step_by(4099);
+        for value in channels.chain(stride) {
+            for hex in [
+                format!("#{:06x}", value),
+                format!("#{:06X}", value),
+                format!("{:06x}", value),
+                format!("{:06X}", value),
+            ] {
+                assert_eq!(
+                    parse_hex_fast(&hex),
+                    parse_color_with_library(&hex),
+                    "{}",
+                    hex
+                );
+                assert_eq!(parse_color(&hex).ok(), parse_color_with_library(&hex));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_hex_fast_leaves_invalid_digits_to_library() {
+        for valid in ["#3366ff", "3366ff", "#36f"] {
+            let start = usize::from(valid.starts_with('#'));
+            for position in start..valid.len() {
+                for bad in ["g", "z", "G", " ", "#", "-", "é", "0x"] {
+                    let hex = format!("{}{}{}", &valid[..position], bad, &valid[position + 1..]);
+                    assert_eq!(parse_hex_fast(&hex), None, "{:?}", hex);
+                    assert_eq!(
+                        parse_color(&hex).ok(),
+                        parse_color_with_library(&hex),
+                        "{:?}",
+                        hex
+                    );
+                }
+            }
+        }
+        // Other lengths and bare 3 digits go to the library too
+        for hex in ["#3366f", "#3366ff0", "3366f", "36f", "#", ""] {
+            assert_eq!(parse_hex_fast(hex), None, "{:?}", hex);
+        }
+    }
+
+    #[test]
+    fn test_parse_color_rgb_function() {
+        let rgb = parse_color("rgb(255, 85, 0)").unwrap();
+        assert_eq!(
+            rgb,
+            RGB {
+                r: 255,
+                g: 85,
+                b: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_color_hsl_function() {
+        let rgb = parse_color("hsl(20, 100%, 50%)").unwrap();
+        assert_eq!(
+            rgb,
+            RGB {
+                r: 255,
+                g: 85,
+                b: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_color_hsl_red() {
+        let rgb = parse_color("hsl(0, 100%, 50%)").unwrap();
+        assert_eq!(rgb, RGB { r: 255, g: 0, b: 0 });
+    }
+
+    #[test]
+    fn test_parse_color_hsl_green() {
+        let rgb = parse_color("hsl(120, 100%, 50%)").unwrap();
+        assert_eq!(rgb, RGB { r: 0, g: 255, b: 0 });
+    }
+
+    #[cfg(feature = "named-colors")]
+    #[test]
+    fn test_parse_color_named_red() {
+        let rgb = parse_color("red").unwrap();
+        assert_eq!(rgb, RGB { r: 255, g: 0, b: 0 });
+    }
+
+    #[cfg(feature = "named-colors")]
+    #[test]
+    fn test_parse_color_named_tomato() {
+        let rgb = parse_color("tomato").unwrap();
+        assert_eq!(
+            rgb,
+            RGB {
+                r: 255,
+                g: 99,
+                b: 71
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_color_invalid() {
+        let result = parse_color("#gggggg");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_color_invalid_notacolor() {
+        let result = parse_color("notacolor");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_rgb_with_lightness() {
+        let rgb = RGB {
+            r: 100,
+            g: 200,
+            b: 50,
+        };
+        let darkened = rgb.with_lightness(0.10);
+        // Setting Oklab lightness to 0.10 preserves hue
+        assert_eq!(darkened, RGB { r: 0, g: 9, b: 0 });
+    }
+
+    #[test]
+    fn test_rgb_with_lightness_different_value() {
+        let rgb = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+        let darkened = rgb.with_lightness(0.15);
+        // Setting Oklab lightness to 0.15 preserves hue
+        assert_eq!(darkened, RGB { r: 66, g: 0, b: 0 });
+    }
+
+    #[test]
+    fn test_rgb_with_lightness_and_saturation_full_saturation() {
+        let rgb = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+        // Full saturation (1.0) should produce the same result as with_lightness
+        let result = rgb.with_lightness_and_saturation(0.15, 1.0);
+        let expected = rgb.with_lightness(0.15);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_rgb_with_lightness_and_saturation_zero_saturation() {
+        let rgb = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+        // Zero saturation should produce grayscale
+        let result = rgb.with_lightness_and_saturation(0.15, 0.0);
+        // Grayscale means r == g == b
+        assert_eq!(result.r, result.g);
+        assert_eq!(result.g, result.b);
+    }
+
+    #[test]
+    fn test_rgb_with_lightness_and_saturation_half_saturation() {
+        let rgb = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+        // Half saturation should be somewhere between full and grayscale
+        let full = rgb.with_lightness_and_saturation(0.15, 1.0);
+        let half = rgb.with_lightness_and_saturation(0.15, 0.5);
+        let zero = rgb.with_lightness_and_saturation(0.15, 0.0);
+
+        // Half saturation result should have less color difference than full
+        let full_diff =
+            (full.r as i32 - full.g as i32).abs() + (full.g as i32 - full.b as i32).abs();
+        let half_diff =
+            (half.r as i32 - half.g as i32).abs() + (half.g as i32 - half.b as i32).abs();
+        let zero_diff =
+            (zero.r as i32 - zero.g as i32).abs() + (zero.g as i32 - zero.b as i32).abs();
+
+        assert!(
+            half_diff < full_diff,
+            "Half saturation should have less chroma than full"
+        );
+        assert!(
+            half_diff > zero_diff,
+            "Half saturation should have more chroma than zero"
+        );
+    }
+
+    #[test]
+    fn test_detect_format_simple_color_with_hash() {
+        assert_eq!(detect_format("#ff5500"), ConfigFormat::SimpleColor);
+    }
+
+    #[test]
+    fn test_detect_format_simple_color_without_hash() {
+        assert_eq!(detect_format("ff5500"), ConfigFormat::SimpleColor);
+    }
+
+    #[test]
+    fn test_detect_format_auto() {
+        assert_eq!(detect_format("auto"), ConfigFormat::Auto);
+        assert_eq!(detect_format("  auto  "), ConfigFormat::Auto);
+        assert_eq!(detect_format("\u{feff}auto\n"), ConfigFormat::Auto);
+    }
+
+    #[test]
+    fn test_detect_format_empty_is_auto() {
+        for content in ["", " \n\t\n", "\u{feff}", "\u{feff}\r\n"] {
+            assert_eq!(detect_format(content), ConfigFormat::Auto, "{:?}", content);
+            assert!(is_empty_config(content), "{:?}", content);
+        }
+        assert!(!is_empty_config("auto"));
+        assert!(!is_empty_config("\u{feff}#ff5500"));
+    }
+
+    #[test]
+    fn test_detect_format_toml() {
+        assert_eq!(detect_format("tab = \"#ff5500\""), ConfigFormat::Toml);
+        assert_eq!(
+            detect_format("preset = \"~/presets/Dracula.itermcolors\""),
+            ConfigFormat::Toml
+        );
+    }
+
+    #[test]
+    fn test_detect_format_toml_with_leading_comments() {
+        let content = "# Colors for the API server\n# (see the team wiki)\n\ntab = \"#336699\"\nbackground = \"#001122\"\n";
+        assert_eq!(detect_format(content), ConfigFormat::Toml);
+        assert_eq!(
+            detect_format("\u{feff}# x = y\ntab = \"#336699\""),
+            ConfigFormat::Toml
+        );
+    }
+
+    #[test]
+    fn test_detect_format_ambiguous_equals() {
+        // An `=` alone doesn't make a file TOML
+        assert_eq!(detect_format("color=red"), ConfigFormat::SimpleColor);
+        assert_eq!(detect_format("#ff5500 # a=b"), ConfigFormat::SimpleColor);
+        assert_eq!(
+            detect_format("background = \"#000000\""),
+            ConfigFormat::SimpleColor
+        );
+        assert_eq!(detect_format("tab = \"#ff5500"), ConfigFormat::SimpleColor);
+        assert_eq!(detect_format("# tab = red"), ConfigFormat::SimpleColor);
+    }
+
+    #[test]
+    fn test_parse_config_content_equals_reports_toml_errors() {
+        let user_config = UserConfig::default();
+        let path = Path::new("/work/.termtint");
+        let error = |content: &str| {
+            parse_config_content(path, content, &user_config)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert!(
+            error("color=red").contains("Failed to parse TOML"),
+            "{}",
+            error("color=red")
+        );
+        assert!(
+            error("color = \"red\"").contains("Missing 'tab' key in TOML config"),
+            "{}",
+            error("color = \"red\"")
+        );
+        assert!(
+            error("tab = \"#ff5500").contains("Failed to parse TOML"),
+            "{}",
+            error("tab = \"#ff5500")
+        );
+        assert!(!error("notacolor").contains("TOML"));
+    }
+
+    #[test]
+    fn test_detect_format_named_color() {
+        assert_eq!(detect_format("red"), ConfigFormat::SimpleColor);
+        assert_eq!(detect_format("tomato"), ConfigFormat::SimpleColor);
+    }
+
+    #[test]
+    fn test_parse_simple_color_config() {
+        let user_config = UserConfig::default();
+        let config = parse_simple_color("#ff5500", &user_config).unwrap();
+        assert_eq!(
+            config.tab,
+            RGB {
+                r: 255,
+                g: 85,
+                b: 0
+            }
+        );
+        // Background uses fixed lightness (0.18 by default)
+        assert_eq!(config.background, RGB { r: 77, g: 0, b: 0 });
+    }
+
+    #[test]
+    fn test_parse_toml_with_tab_only() {
+        let user_config = UserConfig::default();
+        let config =
+            parse_toml(Path::new("/.termtint"), "tab = \"#00ff00\"", &user_config).unwrap();
+        assert_eq!(config.tab, RGB { r: 0, g: 255, b: 0 });
+        // Background uses fixed lightness (0.18 by default)
+        assert_eq!(config.background, RGB { r: 0, g: 34, b: 0 });
+    }
+
+    #[test]
+    fn test_parse_toml_with_background() {
+        let user_config = UserConfig::default();
+        let config = parse_toml(
+            Path::new("/.termtint"),
+            "tab = \"#00ff00\"\nbackground = \"#001100\"",
+            &user_config,
+        )
+        .unwrap();
+        assert_eq!(config.tab, RGB { r: 0, g: 255, b: 0 });
+        assert_eq!(config.background, RGB { r: 0, g: 17, b: 0 });
+    }
+
+    #[test]
+    fn test_parse_toml_missing_tab() {
+        let user_config = UserConfig::default();
+        let result = parse_toml(
+            Path::new("/.termtint"),
+            "background = \"#001100\"",
+            &user_config,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_toml_with_hsl() {
+        let user_config = UserConfig::default();
+        let config = parse_toml(
+            Path::new("/.termtint"),
+            "tab = \"hsl(0, 100%, 50%)\"",
+            &user_config,
+        )
+        .unwrap();
+        assert_eq!(config.tab, RGB { r: 255, g: 0, b: 0 });
+        // Background uses fixed lightness (0.18 by default)
+        assert_eq!(config.background, RGB { r: 87, g: 0, b: 0 });
+    }
+
+    #[cfg(feature = "named-colors")]
+    #[test]
+    fn test_parse_toml_with_named_color() {
+        let user_config = UserConfig::default();
+        let config = parse_toml(Path::new("/.termtint"), "tab = \"tomato\"", &user_config).unwrap();
+        assert_eq!(
+            config.tab,
+            RGB {
+                r: 255,
+                g: 99,
+                b: 71
+            }
+        );
+        // Background uses fixed lightness (0.18 by default)
+        assert_eq!(config.background, RGB { r: 73, g: 0, b: 0 });
+    }
+
+    #[test]
+    fn test_parse_auto_deterministic() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+        fs::write(&config_path, "auto").unwrap();
+
+        let config1 = parse_auto(&config_path, &user_config);
+        let config2 = parse_auto(&config_path, &user_config);
+        assert_eq!(config1, config2);
+    }
+
+    #[test]
+    fn test_parse_auto_produces_vibrant_colors() {
+        // Test multiple different paths to ensure vibrancy constraints hold
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+
+        let test_paths = vec![
+            temp.path().join("project1").join(".termtint"),
+            temp.path().join("project2").join(".termtint"),
+            temp.path().join("project3").join(".termtint"),
+            temp.path()
+                .join("deeply")
+                .join("nested")
+                .join("path")
+                .join(".termtint"),
+        ];
+
+        for path in test_paths {
+            // Create parent directories
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).unwrap();
+            }
+            fs::write(&path, "auto").unwrap();
+
+            let config = parse_auto(&path, &user_config);
+            let rgb = config.tab;
+
+            // Convert RGB back to HSL to verify constraints
+            // Using csscolorparser to convert back
+            let color_str = format!("#{:02x}{:02x}{:02x}", rgb.r, rgb.g, rgb.b);
+            let color = csscolorparser::parse(&color_str).unwrap();
+            let [_hue, saturation, lightness, _alpha] = color.to_hsla();
+
+            // Verify saturation is >= 0.7 (high saturation for vibrant colors)
+            assert!(
+                saturation >= 0.7,
+                "Path {:?} generated color {} with saturation {}, expected >= 0.7",
+                path,
+                color_str,
+                saturation
+            );
+
+            // Verify saturation is <= 0.9 (upper bound from implementation)
+            assert!(
+                saturation <= 0.9,
+                "Path {:?} generated color {} with saturation {}, expected <= 0.9",
+                path,
+                color_str,
+                saturation
+            );
+
+            // Verify lightness is approximately the configured value (0.55 default)
+            // Allow small tolerance for floating point/color space conversion
+            let expected_lightness = user_config.lightness;
+            assert!(
+                (lightness - expected_lightness).abs() < 0.02,
+                "Path {:?} generated color {} with lightness {}, expected ~{}",
+                path,
+                color_str,
+                lightness,
+                expected_lightness
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_config_hex_file() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+        fs::write(&config_path, "#ff5500").unwrap();
+
+        let config = parse_config(&config_path, &user_config).unwrap();
+        assert_eq!(
+            config.tab,
+            RGB {
+                r: 255,
+                g: 85,
+                b: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_config_toml_file() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+        fs::write(&config_path, "tab = \"#00ff00\"").unwrap();
+
+        let config = parse_config(&config_path, &user_config).unwrap();
+        assert_eq!(config.tab, RGB { r: 0, g: 255, b: 0 });
+    }
+
+    #[test]
+    fn test_parse_config_auto_file() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+        fs::write(&config_path, "auto").unwrap();
+
+        let config = parse_config(&config_path, &user_config).unwrap();
+        // Auto generates vibrant colors using HSL, verify at least one channel is bright
+        let max_channel = config.tab.r.max(config.tab.g).max(config.tab.b);
+        assert!(
+            max_channel >= 128,
+            "Generated color should be vibrant with at least one bright channel"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_empty_file_is_auto() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+        fs::write(&config_path, "auto").unwrap();
+        let auto = parse_config(&config_path, &user_config).unwrap();
+
+        for content in ["", "  \n\n", "\u{feff}", "\u{feff}\n"] {
+            fs::write(&config_path, content).unwrap();
+            assert_eq!(
+                parse_config(&config_path, &user_config).unwrap(),
+                auto,
+                "{:?}",
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_config_strips_bom() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+
+        fs::write(&config_path, "\u{feff}#ff5500\n").unwrap();
+        assert_eq!(
+            parse_config(&config_path, &user_config).unwrap().tab,
+            parse_color("#ff5500").unwrap()
+        );
+        fs::write(&config_path, "\u{feff}tab = \"#336699\"\n").unwrap();
+        assert_eq!(
+            parse_config(&config_path, &user_config).unwrap().tab,
+            parse_color("#336699").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_config_bom_and_crlf() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+        let parse = |content: &str| {
+            fs::write(&config_path, content).unwrap();
+            parse_config(&config_path, &user_config)
+        };
+        let auto = parse("auto\n").unwrap();
+        let simple = parse("#ff5500\n").unwrap();
+        let toml = parse("tab = \"#336699\"\nbackground = \"#001122\"\n").unwrap();
+
+        for (bom, newline) in [("\u{feff}", "\n"), ("", "\r\n"), ("\u{feff}", "\r\n")] {
+            let file = |lines: &[&str]| format!("{}{}{}", bom, lines.join(newline), newline);
+            assert_eq!(parse(&file(&["auto"])).unwrap(), auto);
+            assert_eq!(parse(&file(&["", "  auto  ", ""])).unwrap(), auto);
+            assert_eq!(parse(&file(&["#ff5500"])).unwrap(), simple);
+            assert_eq!(parse(&file(&["", "#ff5500 ", "more"])).unwrap(), simple);
+            assert_eq!(
+                parse(&file(&[
+                    "# project colors",
+                    "tab = \"#336699\"",
+                    "background = \"#001122\"",
+                ]))
+                .unwrap(),
+                toml
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_format_crlf() {
+        assert_eq!(detect_format("auto\r\n"), ConfigFormat::Auto);
+        assert_eq!(detect_format("\u{feff}auto\r\n"), ConfigFormat::Auto);
+        assert_eq!(detect_format("#ff5500\r\n"), ConfigFormat::SimpleColor);
+        assert_eq!(
+            detect_format("\u{feff}tab = \"#ff5500\"\r\npreset_tab = \"Ansi 4 Color\"\r\n"),
+            ConfigFormat::Toml
+        );
+    }
+
+    #[test]
+    fn test_preset_reference_bom_and_crlf() {
+        assert_eq!(
+            preset_reference(
+                Path::new("/work/.termtint"),
+                "\u{feff}preset = \"Dark.itermcolors\"\r\n",
+                &UserConfig::default()
+            ),
+            Some(PathBuf::from("/work/Dark.itermcolors"))
+        );
+    }
+
+    #[test]
+    fn test_parse_simple_color_reads_first_line() {
+        let user_config = UserConfig::default();
+        assert_eq!(
+            parse_simple_color("\n  #ff5500\r\nignored\n", &user_config).unwrap(),
+            parse_simple_color("#ff5500", &user_config).unwrap()
+        );
+        assert!(parse_simple_color("\n\n", &user_config).is_err());
+    }
+
+    #[test]
+    fn test_rgb_display() {
+        let rgb = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+        assert_eq!(format!("{}", rgb), "#ff5500");
+
+        let rgb = RGB {
+            r: 0,
+            g: 17,
+            b: 255,
+        };
+        assert_eq!(format!("{}", rgb), "#0011ff");
+    }
+
+    #[test]
+    fn test_rgb_format_as_hex() {
+        let rgb = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+        assert_eq!(
+            rgb.format_as(crate::user_config::ColorFormat::Hex),
+            "#ff5500"
+        );
+    }
+
+    #[test]
+    fn test_rgb_format_as_rgb() {
+        let rgb = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+        assert_eq!(
+            rgb.format_as(crate::user_config::ColorFormat::Rgb),
+            "rgb(255, 85, 0)"
+        );
+    }
+
+    #[test]
+    fn test_rgb_format_as_hsl() {
+        let rgb = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+        let result = rgb.format_as(crate::user_config::ColorFormat::Hsl);
+        // HSL for #ff5500 is approximately hsl(20, 100%, 50%)
+        assert!(result.contains("hsl("));
+        assert!(result.contains("20"));
+        assert!(result.contains("100%"));
+        assert!(result.contains("50%"));
+    }
+
+    #[test]
+    fn test_config_source_termtint() {
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+        File::create(&config_path).unwrap();
+
+        let user_config = UserConfig::default();
+        let result = find_config_source(temp.path(), &user_config);
+
+        assert_eq!(result, Some(ConfigSource::Termtint(config_path)));
+    }
+
+    #[test]
+    fn test_config_source_trigger_file() {
+        let temp = TempDir::new().unwrap();
+        let trigger_path = temp.path().join("Cargo.toml");
+        File::create(&trigger_path).unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
+
+        let result = find_config_source(temp.path(), &user_config);
+
+        assert_eq!(
+            result,
+            Some(ConfigSource::TriggerFile(temp.path().to_path_buf()))
+        );
+    }
+
+    #[test]
+    fn test_config_source_keeps_non_utf8_trigger_dir() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp = TempDir::new().unwrap();
+        let dir = temp
+            .path()
+            .canonicalize()
+            .unwrap()
+            .join(OsStr::from_bytes(b"caf\xe9"));
+        fs::create_dir(&dir).unwrap();
+        File::create(dir.join("Cargo.toml")).unwrap();
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
+
+        let source = find_config_source(&dir, &user_config).unwrap();
+        assert_eq!(source, ConfigSource::TriggerFile(dir.clone()));
+        assert_eq!(
+            AutoHashInputs::for_source(&source, &user_config),
+            AutoHashInputs::for_path(&dir, &user_config)
+        );
+    }
+
+    /// Sets a directory's mode for the test's duration, restoring it on drop so
+    /// the temporary directory can be removed.
+    struct ModeGuard(PathBuf);
+
+    impl ModeGuard {
+        /// Set `dir`'s mode, or return None when permissions don't apply (tests
+        /// running as root).
+        fn set(dir: &Path, mode: u32) -> Option<ModeGuard> {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(dir, fs::Permissions::from_mode(mode)).unwrap();
+            let guard = ModeGuard(dir.to_path_buf());
+            fs::read_dir(dir).is_err().then_some(guard)
+        }
+    }
+
+    impl Drop for ModeGuard {
+        fn drop(&mut self) {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(&self.0, fs::Permissions::from_mode(0o755));
+        }
+    }
+
+    #[test]
+    fn test_walk_skips_unreadable_levels() {
+        let temp = TempDir::new().unwrap();
+        let project = temp.path().canonicalize().unwrap().join("project");
+        let locked = project.join("locked");
+        let inner = locked.join("inner");
+        fs::create_dir_all(&inner).unwrap();
+        fs::write(project.join(".termtint"), "#336699\n").unwrap();
+        fs::write(inner.join(".termtint"), "#cc0000\n").unwrap();
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "*.cabal")],
+            ..UserConfig::default()
+        };
+        let Some(_guard) = ModeGuard::set(&locked, 0o000) else {
+            return;
+        };
+
+        // Nothing below the locked directory can be looked at, so the walk passes
+        // over both levels to the project's .termtint
+        let mut explain = Explanation::new();
+        let source =
+            find_config_source_with(&inner, &user_config, &mut list_dir_names, &mut explain);
+        assert_eq!(
+            source,
+            Some(ConfigSource::Termtint(project.join(".termtint")))
+        );
+        let explanation = explain.to_string();
+        for dir in [&inner, &locked] {
+            assert!(
+                explanation.contains(&format!(
+                    "{}: no .termtint; no trigger path; no trigger file; no directory name match; can't be read (permission denied), so skipped as empty",
+                    dir.display()
+                )),
+                "{}",
+                explanation
+            );
+        }
+        assert!(explanation.contains(&format!("{}: found", project.display())));
+    }
+
+    /// A listing that fails with a permission error under `locked`, as it would
+    /// for a user without access, whatever user the tests run as.
+    fn list_dir_denied_under(locked: &Path) -> impl FnMut(&Path) -> io::Result<Vec<String>> + '_ {
+        move |dir| match dir.starts_with(locked) {
+            true => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+            false => list_dir_names(dir),
+        }
+    }
+
+    #[test]
+    fn test_walk_skips_levels_that_fail_to_list() {
+        let temp = TempDir::new().unwrap();
+        let project = temp.path().canonicalize().unwrap().join("project");
+        let locked = project.join("locked");
+        let inner = locked.join("inner");
+        fs::create_dir_all(&inner).unwrap();
+        fs::write(project.join(".termtint"), "#336699\n").unwrap();
+        fs::write(locked.join("app.cabal"), "").unwrap();
+        fs::write(inner.join("app.cabal"), "").unwrap();
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "*.cabal")],
+            ..UserConfig::default()
+        };
+
+        // The glob trigger files can't be seen in the levels that can't be
+        // listed, so the walk passes over both to the project's .termtint
+        let mut explain = Explanation::new();
+        let source = find_config_source_with(
+            &inner,
+            &user_config,
+            &mut list_dir_denied_under(&locked),
+            &mut explain,
+        );
+        assert_eq!(
+            source,
+            Some(ConfigSource::Termtint(project.join(".termtint")))
+        );
+        let explanation = explain.to_string();
+        for dir in [&inner, &locked] {
+            assert!(
+                explanation.contains(&format!(
+                    "{}: no .termtint; no trigger path; no trigger file; no directory name match; can't be read (permission denied), so skipped as empty",
+                    dir.display()
+                )),
+                "{}",
+                explanation
+            );
+        }
+        assert!(explanation.contains(&format!("{}: found", project.display())));
+        assert_eq!(
+            unreadable_reason(&io::Error::from(io::ErrorKind::NotFound)),
+            "no longer exists"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_searchable_through_denied_directory() {
+        let temp = TempDir::new().unwrap();
+        let real = temp.path().canonicalize().unwrap().join("real");
+        let inner = real.join("locked").join("inner");
+        fs::create_dir_all(&inner).unwrap();
+        let link = temp.path().join("link");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        // Resolving anything inside `locked` fails, as it would without search
+        // permission on it
+        let denied = |path: &Path| -> io::Result<PathBuf> {
+            let canonical = fs::canonicalize(path)?;
+            match canonical.starts_with(real.join("locked").join("inner")) {
+                true => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+                false => Ok(canonical),
+            }
+        };
+
+        let via_link = link.join("locked").join("inner");
+        assert_eq!(
+            canonicalize_searchable_with(&via_link, &denied),
+            Some(inner.clone())
+        );
+        assert_eq!(
+            canonicalize_searchable_with(&inner, &denied),
+            Some(inner.clone())
+        );
+        // Other errors leave the path unresolved
+        let missing =
+            |_: &Path| -> io::Result<PathBuf> { Err(io::Error::from(io::ErrorKind::NotFound)) };
+        assert_eq!(canonicalize_searchable_with(&via_link, &missing), None);
+    }
+
+    #[test]
+    fn test_canonical_path_through_locked_directory() {
+        let temp = TempDir::new().unwrap();
+        let real = temp.path().canonicalize().unwrap().join("real");
+        let inner = real.join("locked").join("inner");
+        fs::create_dir_all(&inner).unwrap();
+        let link = temp.path().join("link");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        let Some(_guard) = ModeGuard::set(&real.join("locked"), 0o000) else {
+            return;
+        };
+
+        // The symlinked and real paths still resolve to the same directory, and so
+        // hash to the same color
+        let via_link = link.join("locked").join("inner");
+        assert_eq!(canonical_path(&via_link), inner);
+        assert_eq!(canonical_path(&inner), inner);
+        assert_eq!(
+            canonical_path(&via_link.join(".termtint")),
+            inner.join(".termtint")
+        );
+        let user_config = UserConfig::default();
+        assert_eq!(
+            AutoHashInputs::for_path(&via_link, &user_config),
+            AutoHashInputs::for_path(&inner, &user_config)
+        );
+    }
+
+    #[test]
+    fn test_trigger_globs_skip_unlistable_directory() {
+        let temp = TempDir::new().unwrap();
+        let project = temp.path().canonicalize().unwrap().join("project");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join("Cargo.toml"), "").unwrap();
+        fs::write(project.join("app.cabal"), "").unwrap();
+        let glob_only = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "*.cabal")],
+            ..UserConfig::default()
+        };
+        let named = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
+        // Searchable but not listable: named files can still be found
+        let Some(_guard) = ModeGuard::set(&project, 0o311) else {
+            return;
+        };
+
+        assert_eq!(find_config_source(&project, &glob_only), None);
+        assert_eq!(
+            find_config_source(&project, &named),
+            Some(ConfigSource::TriggerFile(project.clone()))
+        );
+    }
+
+    #[test]
+    fn test_trigger_globs_skip_directory_that_fails_to_list() {
+        let temp = TempDir::new().unwrap();
+        let project = temp.path().join("project");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join("Cargo.toml"), "").unwrap();
+        fs::write(project.join("app.cabal"), "").unwrap();
+        let triggers = [
+            Trigger::new(TriggerKind::File, "*.cabal"),
+            Trigger::new(TriggerKind::File, "Cargo.toml"),
+        ];
+        let mut list_dir = list_dir_denied_under(&project);
+
+        // Named files don't need the listing, so they can still be found
+        assert!(match_trigger_entry(&project, triggers[..1].iter(), true, &mut list_dir).is_none());
+        assert_eq!(
+            match_trigger_entry(&project, triggers.iter(), true, &mut list_dir),
+            Some((&triggers[1], project.join("Cargo.toml")))
+        );
+    }
+
+    #[test]
+    fn test_config_source_termtint_priority() {
+        // .termtint should take priority over trigger files
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+        File::create(&config_path).unwrap();
+        let trigger_path = temp.path().join("package.json");
+        File::create(&trigger_path).unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "package.json")],
+            ..UserConfig::default()
+        };
+
+        let result = find_config_source(temp.path(), &user_config);
+
+        assert_eq!(result, Some(ConfigSource::Termtint(config_path)));
+    }
+
+    #[test]
+    fn test_config_source_multiple_trigger_files() {
+        let temp = TempDir::new().unwrap();
+        let trigger1 = temp.path().join("Cargo.toml");
+        File::create(&trigger1).unwrap();
+        let trigger2 = temp.path().join("package.json");
+        File::create(&trigger2).unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::File, "pyproject.toml"),
+                Trigger::new(TriggerKind::File, "Cargo.toml"),
+                Trigger::new(TriggerKind::File, "package.json"),
+            ],
+            ..UserConfig::default()
+        };
+
+        let result = find_config_source(temp.path(), &user_config);
+
+        // Should match first trigger file in the list that exists
+        assert_eq!(
+            result,
+            Some(ConfigSource::TriggerFile(temp.path().to_path_buf()))
+        );
+    }
+
+    #[test]
+    fn test_config_source_glob_trigger_file() {
+        let temp = TempDir::new().unwrap();
+        File::create(temp.path().join("demo.cabal")).unwrap();
+        File::create(temp.path().join("README.md")).unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "*.cabal")],
+            ..UserConfig::default()
+        };
+
+        assert_eq!(
+            find_config_source(temp.path(), &user_config),
+            Some(ConfigSource::TriggerFile(temp.path().to_path_buf()))
+        );
+        assert_eq!(
+            matched_trigger_name(temp.path(), &user_config),
+            Some("*.cabal")
+        );
+        assert_eq!(
+            matched_trigger_file(temp.path(), &user_config),
+            Some(temp.path().join("demo.cabal"))
+        );
+    }
+
+    #[test]
+    fn test_content_trigger_skips_member_crates() {
+        let temp = TempDir::new().unwrap();
+        let member = temp.path().join("crates/core");
+        fs::create_dir_all(&member).unwrap();
+        fs::write(
+            temp.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        )
+        .unwrap();
+        fs::write(member.join("Cargo.toml"), "[package]\nname = \"core\"\n").unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![Trigger {
+                toml_key: Some("workspace".to_string()),
+                ..Trigger::new(TriggerKind::File, "Cargo.toml")
+            }],
+            ..UserConfig::default()
+        };
+
+        assert_eq!(
+            find_config_source(&member, &user_config),
+            Some(ConfigSource::TriggerFile(temp.path().to_path_buf()))
+        );
+        let rejected = rejected_trigger_files(&member, &user_config);
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].1, member.join("Cargo.toml"));
+        assert_eq!(rejected[0].2, "has no key 'workspace'");
+        assert!(rejected_trigger_files(temp.path(), &user_config).is_empty());
+    }
+
+    #[test]
+    fn test_check_trigger_content() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.path().join("package.json");
+        fs::write(&file, "{\"workspaces\": [\"packages/*\"]}").unwrap();
+        let contains = |text: &str| Trigger {
+            contains: Some(text.to_string()),
+            ..Trigger::new(TriggerKind::File, "package.json")
+        };
+        assert_eq!(
+            check_trigger_content(&contains("\"workspaces\""), &file),
+            Ok(())
+        );
+        assert_eq!(
+            check_trigger_content(&contains("\"private\""), &file),
+            Err("doesn't contain \"\\\"private\\\"\"".to_string())
+        );
+        assert_eq!(
+            check_trigger_content(&Trigger::new(TriggerKind::File, "package.json"), &file),
+            Ok(())
+        );
+
+        let toml_key = |key: &str| Trigger {
+            toml_key: Some(key.to_string()),
+            ..Trigger::new(TriggerKind::File, "Cargo.toml")
+        };
+        assert!(check_trigger_content(&toml_key("workspaces"), &file)
+            .unwrap_err()
+            .starts_with("isn't valid TOML"));
+
+        let cargo = temp.path().join("Cargo.toml");
+        fs::write(
+            &cargo,
+            "[package]\nname = \"demo\"\n[package.metadata.docs]\nall-features = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            check_trigger_content(&toml_key("package.metadata.docs"), &cargo),
+            Ok(())
+        );
+        assert_eq!(
+            check_trigger_content(&toml_key("package.name.first"), &cargo),
+            Err("has no key 'package.name.first'".to_string())
+        );
+
+        let large = temp.path().join("large.toml");
+        let mut content = "[workspace]\n".to_string();
+        content.push_str(&"# padding\n".repeat(30_000));
+        fs::write(&large, content).unwrap();
+        assert!(check_trigger_content(&toml_key("workspace"), &large)
+            .unwrap_err()
+            .starts_with("is larger than 256 KiB"));
+    }
+
+    #[test]
+    fn test_check_trigger_content_rereads_modified_files() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.path().join("Cargo.toml");
+        let trigger = Trigger {
+            toml_key: Some("workspace".to_string()),
+            ..Trigger::new(TriggerKind::File, "Cargo.toml")
+        };
+        fs::write(&file, "[package]\n").unwrap();
+        filetime::set_file_mtime(&file, filetime::FileTime::from_unix_time(1_000_000, 0)).unwrap();
+        assert!(check_trigger_content(&trigger, &file).is_err());
+
+        // The cached result stands while the modification time is unchanged
+        fs::write(&file, "[workspace]\n").unwrap();
+        filetime::set_file_mtime(&file, filetime::FileTime::from_unix_time(1_000_000, 0)).unwrap();
+        assert!(check_trigger_content(&trigger, &file).is_err());
+
+        filetime::set_file_mtime(&file, filetime::FileTime::from_unix_time(2_000_000, 0)).unwrap();
+        assert_eq!(check_trigger_content(&trigger, &file), Ok(()));
+
+        // The check made before the edit is replaced, not kept beside the new one
+        let checks = CONTENT_CHECKS.lock().unwrap();
+        assert_eq!(checks.keys().filter(|(path, _)| *path == file).count(), 1);
+    }
+
+    #[test]
+    fn test_glob_trigger_question_mark_and_case() {
+        let temp = TempDir::new().unwrap();
+        File::create(temp.path().join("App.SLN")).unwrap();
+
+        let pattern = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "App.?ln")],
+            ..UserConfig::default()
+        };
+        assert_eq!(matched_trigger_name(temp.path(), &pattern), None);
+
+        let insensitive = UserConfig {
+            trigger_files_case_sensitive: false,
+            ..pattern
+        };
+        assert_eq!(
+            matched_trigger_name(temp.path(), &insensitive),
+            Some("App.?ln")
+        );
+    }
+
+    #[test]
+    fn test_glob_triggers_list_each_directory_once() {
+        let temp = TempDir::new().unwrap();
+        let nested = temp.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        File::create(temp.path().join("Cargo.toml")).unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::File, "*.cabal"),
+                Trigger::new(TriggerKind::File, "flake.nix"),
+                Trigger::new(TriggerKind::File, "*.sln"),
+                Trigger::new(TriggerKind::File, "Cargo.toml"),
+            ],
+            ..UserConfig::default()
+        };
+        let mut listed: Vec<PathBuf> = Vec::new();
+        let mut list_dir = |dir: &Path| {
+            listed.push(dir.to_path_buf());
+            list_dir_names(dir)
+        };
+
+        let result = find_config_source_with(
+            &nested,
+            &user_config,
+            &mut list_dir,
+            &mut Explanation::disabled(),
+        );
+
+        assert_eq!(
+            result,
+            Some(ConfigSource::TriggerFile(temp.path().to_path_buf()))
+        );
+        assert_eq!(
+            listed,
+            vec![
+                nested.clone(),
+                temp.path().join("a"),
+                temp.path().to_path_buf()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plain_trigger_files_skip_directory_listing() {
+        let temp = TempDir::new().unwrap();
+        File::create(temp.path().join("Cargo.toml")).unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
+        let mut list_dir =
+            |_: &Path| -> io::Result<Vec<String>> { panic!("plain names need no listing") };
+
+        assert!(find_config_source_with(
+            temp.path(),
+            &user_config,
+            &mut list_dir,
+            &mut Explanation::disabled()
+        )
+        .is_some());
+    }
+
+    #[test]
+    fn test_config_source_parent_dir() {
+        let temp = TempDir::new().unwrap();
+        let trigger_path = temp.path().join("Cargo.toml");
+        File::create(&trigger_path).unwrap();
+
+        let child_dir = temp.path().join("child");
+        fs::create_dir(&child_dir).unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
+
+        let result = find_config_source(&child_dir, &user_config);
+
+        assert_eq!(
+            result,
+            Some(ConfigSource::TriggerFile(temp.path().to_path_buf()))
+        );
+    }
+
+    #[test]
+    fn test_config_source_none() {
+        let temp = TempDir::new().unwrap();
+        let child_dir = temp.path().join("child");
+        fs::create_dir(&child_dir).unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
+
+        let result = find_config_source(&child_dir, &user_config);
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_config_source_empty_trigger_list() {
+        let temp = TempDir::new().unwrap();
+        let trigger_path = temp.path().join("Cargo.toml");
+        File::create(&trigger_path).unwrap();
+
+        let user_config = UserConfig::default(); // empty trigger_files
+
+        let result = find_config_source(temp.path(), &user_config);
+
+        // Should find nothing since trigger_files is empty
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_parse_config_source_termtint() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+        fs::write(&config_path, "#ff5500").unwrap();
+
+        let source = ConfigSource::Termtint(config_path);
+        let config = parse_config_source(&source, &user_config).unwrap();
+
+        assert_eq!(
+            config.tab,
+            RGB {
+                r: 255,
+                g: 85,
+                b: 0
+            }
+        );
+        // Background uses fixed lightness (0.18 by default)
+        assert_eq!(config.background, RGB { r: 77, g: 0, b: 0 });
+    }
+
+    #[test]
+    fn test_parse_config_source_trigger_file() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+
+        let source = ConfigSource::TriggerFile(temp.path().to_path_buf());
+        let config = parse_config_source(&source, &user_config).unwrap();
+
+        // Should generate auto color based on directory path
+        // Verify it's a valid color with at least one bright channel
+        let max_channel = config.tab.r.max(config.tab.g).max(config.tab.b);
+        assert!(max_channel >= 128, "Generated color should be vibrant");
+    }
+
+    #[test]
+    fn test_parse_config_source_trigger_file_deterministic() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+
+        let source = ConfigSource::TriggerFile(temp.path().to_path_buf());
+        let config1 = parse_config_source(&source, &user_config).unwrap();
+        let config2 = parse_config_source(&source, &user_config).unwrap();
+
+        // Should generate the same color for the same directory
+        assert_eq!(config1.tab, config2.tab);
+        assert_eq!(config1.background, config2.background);
+    }
+
+    #[test]
+    fn test_parse_config_source_trigger_file_different_dirs() {
+        let user_config = UserConfig::default();
+        let temp1 = TempDir::new().unwrap();
+        let temp2 = TempDir::new().unwrap();
+
+        let source1 = ConfigSource::TriggerFile(temp1.path().to_path_buf());
+        let source2 = ConfigSource::TriggerFile(temp2.path().to_path_buf());
+
+        let config1 = parse_config_source(&source1, &user_config).unwrap();
+        let config2 = parse_config_source(&source2, &user_config).unwrap();
+
+        // Different directories should generate different colors
+        assert_ne!(config1.tab, config2.tab);
+    }
+
+    #[test]
+    fn test_parse_config_source_uses_user_config() {
+        let user_config = UserConfig {
+            background_lightness: 0.20,
+            ..UserConfig::default()
+        };
+
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join(".termtint");
+        fs::write(&config_path, "#ff5500").unwrap();
+
+        let source = ConfigSource::Termtint(config_path);
+        let config = parse_config_source(&source, &user_config).unwrap();
+
+        // Should use custom background lightness value
+        assert_eq!(
+            config.tab,
+            RGB {
+                r: 255,
+                g: 85,
+                b: 0
+            }
+        );
+        // Background should use fixed lightness of 0.20
+        assert_eq!(config.background, RGB { r: 84, g: 0, b: 0 });
+    }
+
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_generate_random_color() {
+        let user_config = UserConfig::default();
+        let color = generate_random_color(&user_config);
+
+        // Convert back to HSL to verify constraints
+        let color_str = format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b);
+        let parsed_color = csscolorparser::parse(&color_str).unwrap();
+        let [_hue, saturation, lightness, _alpha] = parsed_color.to_hsla();
+
+        // Verify saturation is within configured range (with tolerance for conversion)
+        assert!(
+            saturation >= user_config.saturation_min - 0.05,
+            "Saturation {} should be >= {}",
+            saturation,
+            user_config.saturation_min
+        );
+        assert!(
+            saturation <= user_config.saturation_max + 0.05,
+            "Saturation {} should be <= {}",
+            saturation,
+            user_config.saturation_max
+        );
+
+        // Verify lightness matches configured value (with tolerance)
+        assert!(
+            (lightness - user_config.lightness).abs() < 0.02,
+            "Lightness {} should be approximately {}",
+            lightness,
+            user_config.lightness
+        );
+    }
+
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_generate_random_color_produces_different_colors() {
+        let user_config = UserConfig::default();
+
+        // Generate multiple random colors
+        let colors: Vec<RGB> = (0..10)
+            .map(|_| generate_random_color(&user_config))
+            .collect();
+
+        // At least some should be different (highly unlikely all 10 are the same)
+        let first_color = colors[0];
+        let has_different_color = colors.iter().any(|c| *c != first_color);
+        assert!(
+            has_different_color,
+            "Should generate different random colors, but all were {:?}",
+            first_color
+        );
+    }
+
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_generate_random_color_respects_custom_ranges() {
+        let user_config = UserConfig {
+            hue_min: 120.0, // Green range
+            hue_max: 180.0, // Cyan range
+            saturation_min: 0.8,
+            saturation_max: 0.9,
+            lightness: 0.6,
+            ..UserConfig::default()
+        };
+
+        let color = generate_random_color(&user_config);
+
+        // Convert back to HSL to verify
+        let color_str = format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b);
+        let parsed_color = csscolorparser::parse(&color_str).unwrap();
+        let [hue, saturation, lightness, _alpha] = parsed_color.to_hsla();
+
+        // Verify hue is within configured range (with some tolerance for conversion)
+        assert!(
+            hue >= user_config.hue_min - 5.0 && hue <= user_config.hue_max + 5.0,
+            "Hue {} should be within {} to {}",
+            hue,
+            user_config.hue_min,
+            user_config.hue_max
+        );
+
+        // Verify saturation is within configured range
+        assert!(
+            saturation >= user_config.saturation_min - 0.05,
+            "Saturation {} should be >= {}",
+            saturation,
+            user_config.saturation_min
+        );
+
+        // Verify lightness matches configured value
+        assert!(
+            (lightness - user_config.lightness).abs() < 0.02,
+            "Lightness {} should be approximately {}",
+            lightness,
+            user_config.lightness
+        );
+    }
+
+    #[test]
+    fn test_matches_path_glob_basic() {
+        let temp = TempDir::new().unwrap();
+        let project_dir = temp.path().join("project");
+        fs::create_dir(&project_dir).unwrap();
+
+        let pattern = format!("{}/*", temp.path().to_string_lossy());
+        let patterns = vec![pattern];
+
+        assert!(matches_path_glob(
+            &project_dir,
+            &compile_path_globs(&patterns, None)
+        ));
+    }
+
+    #[test]
+    fn test_matches_path_glob_no_match() {
+        let temp = TempDir::new().unwrap();
+        let project_dir = temp.path().join("project");
+        fs::create_dir(&project_dir).unwrap();
+
+        let patterns = vec!["/some/other/path/*".to_string()];
+
+        assert!(!matches_path_glob(
+            &project_dir,
+            &compile_path_globs(&patterns, None)
+        ));
+    }
+
+    #[test]
+    fn test_matches_path_glob_tilde_expansion() {
+        let temp = TempDir::new().unwrap();
+
+        let project_dir = temp.path().join("Code").join("project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let patterns = vec!["~/Code/*".to_string()];
+
+        assert!(matches_path_glob(
+            &project_dir,
+            &compile_path_globs(&patterns, Some(temp.path()))
+        ));
+        assert!(!matches_path_glob(
+            &project_dir,
+            &compile_path_globs(&patterns, None)
+        ));
+    }
+
+    #[test]
+    fn test_matches_path_glob_double_star() {
+        let temp = TempDir::new().unwrap();
+        let code = temp.path().join("Code");
+        let globs = compile_path_globs(&[format!("{}/**", code.to_string_lossy())], None);
+
+        assert!(matches_path_glob(&code.join("app"), &globs));
+        assert!(matches_path_glob(&code.join("app").join("src"), &globs));
+        assert!(!matches_path_glob(
+            &temp.path().join("Other").join("app"),
+            &globs
+        ));
+    }
+
+    #[test]
+    fn test_compile_path_globs_skips_invalid() {
+        let globs = compile_path_globs(&["/code/[".to_string(), "/code/*".to_string()], None);
+
+        assert_eq!(globs.len(), 1);
+        assert!(matches_path_glob(Path::new("/code/app"), &globs));
+    }
+
+    #[test]
+    fn test_matches_path_glob_empty_patterns() {
+        let temp = TempDir::new().unwrap();
+        let patterns: Vec<String> = vec![];
+
+        assert!(!matches_path_glob(
+            temp.path(),
+            &compile_path_globs(&patterns, None)
+        ));
+    }
+
+    /// A user config with path triggers for these patterns.
+    fn path_triggers(patterns: &[&str]) -> UserConfig {
+        UserConfig {
+            triggers: patterns
+                .iter()
+                .map(|pattern| Trigger::new(TriggerKind::Path, *pattern))
+                .collect(),
+            ..UserConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_match_trigger_paths_negation() {
+        let user_config = path_triggers(&[
+            "/code/*",
+            "!/code/tmp",
+            "!/code/scratch-*",
+            "/code/scratch-keep",
+        ]);
+        let globs = compile_trigger_paths(&user_config);
+
+        assert_eq!(
+            match_trigger_paths(Path::new("/code/app"), &globs),
+            Some(TriggerPathMatch {
+                pattern: "/code/*",
+                vetoed_by: None,
+            })
+        );
+        assert_eq!(
+            match_trigger_paths(Path::new("/code/tmp"), &globs),
+            Some(TriggerPathMatch {
+                pattern: "/code/*",
+                vetoed_by: Some("!/code/tmp"),
+            })
+        );
+        assert_eq!(
+            match_trigger_paths(Path::new("/code/scratch-1"), &globs).and_then(|m| m.vetoed_by),
+            Some("!/code/scratch-*")
+        );
+        // A later positive pattern overrides the negation before it
+        assert_eq!(
+            match_trigger_paths(Path::new("/code/scratch-keep"), &globs),
+            Some(TriggerPathMatch {
+                pattern: "/code/scratch-keep",
+                vetoed_by: None,
+            })
+        );
+        assert_eq!(match_trigger_paths(Path::new("/other/app"), &globs), None);
+    }
+
+    #[test]
+    fn test_match_trigger_paths_negation_before_positive_has_no_effect() {
+        let user_config = path_triggers(&["!/code/tmp", "/code/*"]);
+        let globs = compile_trigger_paths(&user_config);
+
+        assert_eq!(
+            match_trigger_paths(Path::new("/code/tmp"), &globs),
+            Some(TriggerPathMatch {
+                pattern: "/code/*",
+                vetoed_by: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_compile_trigger_paths_strips_negation() {
+        let user_config = path_triggers(&["!~/Code/tmp/*", "!/code/["]);
+        let globs = compile_trigger_paths(&user_config);
+
+        assert_eq!(globs.len(), 1);
+        assert!(globs[0].negated);
+        assert_eq!(globs[0].pattern, "!~/Code/tmp/*");
+        assert!(!globs[0].glob.as_str().starts_with('!'));
+        assert!(globs[0].glob.as_str().ends_with("/Code/tmp/*"));
+    }
+
+    #[test]
+    fn test_cached_trigger_globs_match_fresh_compilation() {
+        let patterns = [
+            "~/Code/*",
+            "!~/Code/tmp",
+            "/srv/**/app",
+            "/code/[",
+            "!/code/scratch-*",
+        ];
+        let mut user_config = path_triggers(&patterns);
+        user_config
+            .triggers
+            .push(Trigger::new(TriggerKind::Dirname, "*-service"));
+        user_config
+            .triggers
+            .push(Trigger::new(TriggerKind::Dirname, "[bad"));
+        let fresh = |kind: TriggerKind, user_config: &UserConfig| -> Vec<(String, glob::Pattern)> {
+            user_config
+                .trigger_patterns(kind)
+                .filter_map(|pattern| {
+                    Some((
+                        pattern.to_string(),
+                        compile_trigger_glob(kind, pattern, None)?,
+                    ))
+                })
+                .collect()
+        };
+        let cached =
+            |kind: TriggerKind, user_config: &UserConfig| -> Vec<(String, glob::Pattern)> {
+                trigger_globs(user_config, kind)
+                    .into_iter()
+                    .map(|(pattern, glob)| (pattern.to_string(), glob.into_owned()))
+                    .collect()
+            };
+
+        for kind in [TriggerKind::Path, TriggerKind::Dirname] {
+            // First use fills the cache, the second reads it
+            assert_eq!(cached(kind, &user_config), fresh(kind, &user_config));
+            assert_eq!(cached(kind, &user_config), fresh(kind, &user_config));
+        }
+        assert_eq!(cached(TriggerKind::Path, &user_config).len(), 4);
+        assert!(matches!(
+            trigger_globs(&user_config, TriggerKind::Path)[0].1,
+            Cow::Borrowed(_)
+        ));
+
+        // Triggers edited after the cache filled are compiled afresh
+        user_config.triggers[0].pattern = "/elsewhere/*".to_string();
+        assert_eq!(
+            cached(TriggerKind::Path, &user_config),
+            fresh(TriggerKind::Path, &user_config)
+        );
+        assert_eq!(
+            matched_dirname(Path::new("/srv/billing-service"), &user_config),
+            Some("*-service")
+        );
+    }
+
+    /// Compare compiling trigger globs on every lookup with the per-config cache.
+    /// Run with `cargo test bench_trigger_globs -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_trigger_globs() {
+        const ITERATIONS: u32 = 2000;
+        let patterns: Vec<String> = (0..12)
+            .map(|i| format!("~/Code/team-{}/**/src", i))
+            .collect();
+        let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+        let user_config = path_triggers(&patterns);
+        let dir = Path::new("/home/user/Code/team-11/app/src");
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            let globs: Vec<TriggerPathGlob> = user_config
+                .trigger_patterns(TriggerKind::Path)
+                .filter_map(|pattern| {
+                    Some(TriggerPathGlob {
+                        pattern,
+                        glob: Cow::Owned(compile_trigger_glob(TriggerKind::Path, pattern, None)?),
+                        negated: pattern.starts_with('!'),
+                    })
+                })
+                .collect();
+            match_trigger_paths(dir, &globs);
+        }
+        let uncached = start.elapsed() / ITERATIONS;
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            match_trigger_paths(dir, &compile_trigger_paths(&user_config));
+        }
+        let cached = start.elapsed() / ITERATIONS;
+
+        println!("compiled per lookup: {:?} per lookup", uncached);
+        println!("cached:              {:?} per lookup", cached);
+    }
+
+    #[test]
+    fn test_trigger_path_negation_falls_through_to_parent() {
+        let temp = TempDir::new().unwrap();
+        let code = temp.path().join("Code");
+        let tmp = code.join("tmp");
+        let nested = tmp.join("experiment");
+        fs::create_dir_all(&nested).unwrap();
+
+        let root = code.to_string_lossy();
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::Path, format!("{}/**", root)),
+                Trigger::new(TriggerKind::Path, format!("!{}/tmp/*", root)),
+            ],
+            ..UserConfig::default()
+        };
+
+        // The vetoed directory falls through to the next match up the walk
+        assert_eq!(
+            find_config_source(&nested, &user_config),
+            Some(ConfigSource::TriggerPath(canonical_path(&tmp)))
+        );
+        let matches = trigger_path_matches(&nested, &user_config);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].0, nested);
+        assert_eq!(
+            matches[0].1.vetoed_by,
+            Some(format!("!{}/tmp/*", root).as_str())
+        );
+        assert_eq!(matches[1].0, tmp);
+        assert_eq!(matches[1].1.vetoed_by, None);
+    }
+
+    #[test]
+    fn test_config_source_trigger_path() {
+        let temp = TempDir::new().unwrap();
+        let project_dir = temp.path().join("project");
+        fs::create_dir(&project_dir).unwrap();
+
+        let pattern = format!("{}/*", temp.path().to_string_lossy());
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::Path, pattern)],
+            ..UserConfig::default()
+        };
+
+        let result = find_config_source(&project_dir, &user_config);
+
+        assert_eq!(
+            result,
+            Some(ConfigSource::TriggerPath(project_dir.to_path_buf()))
+        );
+    }
+
+    #[test]
+    fn test_config_source_trigger_path_priority_over_trigger_files() {
+        // Trigger path should take priority over trigger files
+        let temp = TempDir::new().unwrap();
+        let project_dir = temp.path().join("project");
+        fs::create_dir(&project_dir).unwrap();
+        let trigger_path = project_dir.join("Cargo.toml");
+        File::create(&trigger_path).unwrap();
+
+        let pattern = format!("{}/*", temp.path().to_string_lossy());
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::Path, pattern),
+                Trigger::new(TriggerKind::File, "Cargo.toml"),
+            ],
+            ..UserConfig::default()
+        };
+
+        let result = find_config_source(&project_dir, &user_config);
+
+        assert_eq!(
+            result,
+            Some(ConfigSource::TriggerPath(project_dir.to_path_buf()))
+        );
+    }
+
+    #[test]
+    fn test_config_source_termtint_priority_over_trigger_path() {
+        // .termtint should take priority over trigger path
+        let temp = TempDir::new().unwrap();
+        let project_dir = temp.path().join("project");
+        fs::create_dir(&project_dir).unwrap();
+        let config_path = project_dir.join(".termtint");
+        File::create(&config_path).unwrap();
+
+        let pattern = format!("{}/*", temp.path().to_string_lossy());
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::Path, pattern)],
+            ..UserConfig::default()
+        };
+
+        let result = find_config_source(&project_dir, &user_config);
+
+        assert_eq!(result, Some(ConfigSource::Termtint(config_path)));
+    }
+
+    #[test]
+    fn test_config_source_trigger_path_walks_up_tree() {
+        // Subdirectory should inherit trigger path from parent
+        let temp = TempDir::new().unwrap();
+        let project_dir = temp.path().join("project");
+        let sub_dir = project_dir.join("src");
+        fs::create_dir_all(&sub_dir).unwrap();
+
+        let pattern = format!("{}/*", temp.path().to_string_lossy());
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::Path, pattern)],
+            ..UserConfig::default()
+        };
+
+        let result = find_config_source(&sub_dir, &user_config);
+
+        // Should find parent directory (project_dir) matching the glob
+        assert_eq!(
+            result,
+            Some(ConfigSource::TriggerPath(project_dir.to_path_buf()))
+        );
+    }
+
+    #[test]
+    fn test_config_source_trigger_path_ignores_siblings() {
+        let temp = TempDir::new().unwrap();
+        let matching = temp.path().join("Code").join("app");
+        let sibling = temp.path().join("Other").join("app");
+        fs::create_dir_all(&matching).unwrap();
+        fs::create_dir_all(&sibling).unwrap();
+
+        let pattern = format!("{}/Code/*", temp.path().to_string_lossy());
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::Path, pattern)],
+            ..UserConfig::default()
+        };
+
+        assert!(find_config_source(&matching, &user_config).is_some());
+        assert_eq!(find_config_source(&sibling, &user_config), None);
+        // The parent of the matched directories doesn't match `*` itself
+        assert_eq!(
+            find_config_source(&temp.path().join("Code"), &user_config),
+            None
+        );
+    }
+
+    #[test]
+    fn test_config_source_trigger_path_double_star_matches_nearest() {
+        // `**` matches every level, so the starting directory itself is the source
+        let temp = TempDir::new().unwrap();
+        let nested = temp.path().join("Code").join("app").join("src");
+        fs::create_dir_all(&nested).unwrap();
+
+        let pattern = format!("{}/Code/**", temp.path().to_string_lossy());
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::Path, pattern)],
+            ..UserConfig::default()
+        };
+
+        assert_eq!(
+            find_config_source(&nested, &user_config),
+            Some(ConfigSource::TriggerPath(nested.canonicalize().unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_exclude_paths_suppress_trigger_sources() {
+        let temp = TempDir::new().unwrap();
+        let code = temp.path().join("Code");
+        let scratch = code.join("scratch");
+        let archived = code.join("archive").join("old").join("src");
+        fs::create_dir_all(&scratch).unwrap();
+        fs::create_dir_all(&archived).unwrap();
+        File::create(archived.join("Cargo.toml")).unwrap();
+
+        let root = code.to_string_lossy();
+        let scratch_pattern = format!("{}/scratch", root);
+        let archive_pattern = format!("{}/archive/**", root);
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::Path, format!("{}/*", root)),
+                Trigger::new(TriggerKind::File, "Cargo.toml"),
+            ],
+            exclude_paths: vec![scratch_pattern.clone(), archive_pattern.clone()],
+            ..UserConfig::default()
+        };
+
+        assert_eq!(find_config_source(&scratch, &user_config), None);
+        assert_eq!(find_config_source(&archived, &user_config), None);
+        assert!(find_config_source(&code.join("archive"), &user_config).is_none());
+        assert_eq!(
+            matched_exclusion(&scratch, &user_config),
+            Some(scratch_pattern.as_str())
+        );
+        assert_eq!(
+            matched_exclusion(&archived, &user_config),
+            Some(archive_pattern.as_str())
+        );
+
+        // Directories outside the exclusions are unaffected
+        let app = code.join("app");
+        fs::create_dir_all(&app).unwrap();
+        assert!(find_config_source(&app, &user_config).is_some());
+        assert_eq!(matched_exclusion(&app, &user_config), None);
+    }
+
+    #[test]
+    fn test_exclude_paths_keep_explicit_termtint() {
+        let temp = TempDir::new().unwrap();
+        let scratch = temp.path().join("Code").join("scratch");
+        let nested = scratch.join("notes");
+        fs::create_dir_all(&nested).unwrap();
+        let config_path = scratch.join(".termtint");
+        File::create(&config_path).unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(
+                TriggerKind::Path,
+                format!("{}/Code/*", temp.path().to_string_lossy()),
+            )],
+            exclude_paths: vec![format!("{}/Code/scratch/**", temp.path().to_string_lossy())],
+            ..UserConfig::default()
+        };
+
+        assert_eq!(
+            find_config_source(&nested, &user_config),
+            Some(ConfigSource::Termtint(config_path))
+        );
+        // The walk passed through an excluded directory before finding the .termtint
+        assert!(matched_exclusion(&nested, &user_config).is_some());
+        assert_eq!(matched_exclusion(&scratch, &user_config), None);
+    }
+
+    #[test]
+    fn test_walk_dirs_root_boundary() {
+        let temp = TempDir::new().unwrap();
+        let nested = temp.path().join("home").join("code").join("app");
+        fs::create_dir_all(&nested).unwrap();
+
+        let dirs: Vec<&Path> = walk_dirs(&nested, WalkBoundary::Root, None).collect();
+        assert_eq!(dirs.first(), Some(&nested.as_path()));
+        assert_eq!(dirs.last(), Some(&Path::new("/")));
+        assert_eq!(dirs.len(), nested.ancestors().count());
+    }
+
+    #[test]
+    fn test_walk_dirs_home_boundary() {
+        let temp = TempDir::new().unwrap();
+        let home = temp.path().join("home");
+        let nested = home.join("code").join("app");
+        let outside = temp.path().join("tmp").join("build");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+
+        // HOME itself is still checked, but nothing above it
+        let dirs: Vec<&Path> = walk_dirs(&nested, WalkBoundary::Home, Some(&home)).collect();
+        assert_eq!(
+            dirs,
+            vec![
+                nested.as_path(),
+                home.join("code").as_path(),
+                home.as_path()
+            ]
+        );
+
+        // Directories outside HOME walk to the root
+        let dirs: Vec<&Path> = walk_dirs(&outside, WalkBoundary::Home, Some(&home)).collect();
+        assert_eq!(dirs.last(), Some(&Path::new("/")));
+
+        // Without a HOME the boundary has nothing to stop at
+        let dirs: Vec<&Path> = walk_dirs(&nested, WalkBoundary::Home, None).collect();
+        assert_eq!(dirs.last(), Some(&Path::new("/")));
+    }
+
+    #[test]
+    fn test_walk_dirs_device_boundary() {
+        let temp = TempDir::new().unwrap();
+        let nested = temp.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+
+        // Every level of the tempdir is on one device, so the walk covers them all
+        let dirs: Vec<&Path> = walk_dirs(&nested, WalkBoundary::Device, None).collect();
+        assert!(dirs.len() >= 3);
+        assert!(dirs.contains(&temp.path()));
+        let device = device_id(&nested);
+        assert!(dirs.iter().all(|dir| device_id(dir) == device));
+
+        // /proc is its own filesystem wherever it's mounted, so the walk stops there
+        let proc_dir = Path::new("/proc/self");
+        if device_id(proc_dir).is_some() && device_id(proc_dir) != device_id(Path::new("/")) {
+            let dirs: Vec<&Path> = walk_dirs(proc_dir, WalkBoundary::Device, None).collect();
+            assert!(!dirs.contains(&Path::new("/")));
+        }
+    }
+
+    #[test]
+    fn test_find_config_source_stops_at_home() {
+        let temp = TempDir::new().unwrap();
+        let home = temp.path().join("home");
+        let nested = home.join("code").join("app");
+        fs::create_dir_all(&nested).unwrap();
+        // A stray trigger file above the home directory
+        File::create(temp.path().join("Cargo.toml")).unwrap();
+
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
+        let mut list_dir = |_: &Path| Ok(Vec::new());
+        let mut found = |boundary| {
+            walk_dirs(&nested, boundary, Some(&home)).any(|dir| {
+                match_trigger_entry(
+                    dir,
+                    user_config.triggers_of(TriggerKind::File),
+                    true,
+                    &mut list_dir,
+                )
+                .is_some()
+            })
+        };
+
+        assert!(found(WalkBoundary::Root));
+        assert!(!found(WalkBoundary::Home));
+    }
+
+    #[test]
+    fn test_max_walk_depth() {
+        let temp = TempDir::new().unwrap();
+        let project = temp.path().join("project");
+        let nested = project.join("a").join("b").join("c");
+        fs::create_dir_all(&nested).unwrap();
+        File::create(project.join("Cargo.toml")).unwrap();
+
+        let limited = |depth| UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            max_walk_depth: depth,
+            ..UserConfig::default()
+        };
+        let found = Some(ConfigSource::TriggerFile(project.canonicalize().unwrap()));
+
+        // The project is three levels up
+        assert_eq!(find_config_source(&nested, &limited(Some(3))), found);
+        assert_eq!(find_config_source(&nested, &limited(Some(2))), None);
+        assert_eq!(find_config_source(&nested, &limited(None)), found);
+
+        assert!(walk_depth_exceeded(&nested, &limited(Some(2))));
+        assert!(!walk_depth_exceeded(&nested, &limited(None)));
+        // A limit reaching past the root never cuts the walk short
+        assert!(!walk_depth_exceeded(&nested, &limited(Some(1000))));
+    }
+
+    /// A repository at `app` with a vendored library carrying its own trigger file,
+    /// below a directory with a stray trigger file.
+    fn nested_repo(temp: &TempDir) -> (PathBuf, PathBuf) {
+        let app = temp.path().join("code").join("app");
+        let lib = app.join("vendor").join("somelib");
+        fs::create_dir_all(app.join(".git")).unwrap();
+        fs::create_dir_all(lib.join("src")).unwrap();
+        File::create(lib.join("Cargo.toml")).unwrap();
+        File::create(temp.path().join("Cargo.toml")).unwrap();
+        (app, lib)
+    }
+
+    fn git_root_config(respect_git_root: bool) -> UserConfig {
+        UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            respect_git_root,
+            ..UserConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_respect_git_root_colors_by_repository() {
+        let temp = TempDir::new().unwrap();
+        let (app, lib) = nested_repo(&temp);
+        let trigger_file =
+            |dir: &Path| Some(ConfigSource::TriggerFile(dir.canonicalize().unwrap()));
+
+        // Off: the vendored library gets its own color
+        assert_eq!(
+            find_config_source(&lib.join("src"), &git_root_config(false)),
+            trigger_file(&lib)
+        );
+        // On: it shares the repository's
+        assert_eq!(
+            find_config_source(&lib.join("src"), &git_root_config(true)),
+            trigger_file(&app)
+        );
+
+        // Including an explicit color set at the repository root
+        let config_path = app.join(".termtint");
+        File::create(&config_path).unwrap();
+        assert_eq!(
+            find_config_source(&lib.join("src"), &git_root_config(true)),
+            Some(ConfigSource::Termtint(config_path))
+        );
+    }
+
+    #[test]
+    fn test_respect_git_root_stops_walk() {
+        let temp = TempDir::new().unwrap();
+        let (app, _) = nested_repo(&temp);
+        let docs = app.join("docs");
+        fs::create_dir_all(&docs).unwrap();
+
+        // The stray trigger file above the repository only leaks in when off
+        assert!(find_config_source(&docs, &git_root_config(false)).is_some());
+        assert_eq!(find_config_source(&docs, &git_root_config(true)), None);
+    }
+
+    #[test]
+    fn test_respect_git_root_worktree_file() {
+        let temp = TempDir::new().unwrap();
+        let worktree = temp.path().join("app-feature");
+        let src = worktree.join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(
+            worktree.join(".git"),
+            "gitdir: /code/app/.git/worktrees/app-feature\n",
+        )
+        .unwrap();
+        File::create(worktree.join("Cargo.toml")).unwrap();
+        File::create(temp.path().join("Cargo.toml")).unwrap();
+
+        assert_eq!(git_root(&src), Some(worktree.as_path()));
+        assert_eq!(
+            find_config_source(&src, &git_root_config(true)),
+            Some(ConfigSource::TriggerFile(worktree.canonicalize().unwrap()))
+        );
+    }
+
+    /// A directory matched by all three source kinds.
+    fn triple_source_dir(temp: &TempDir) -> (PathBuf, UserConfig) {
+        let project = temp.path().join("code").join("app");
+        fs::create_dir_all(&project).unwrap();
+        File::create(project.join(".termtint")).unwrap();
+        File::create(project.join("Cargo.toml")).unwrap();
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::File, "Cargo.toml"),
+                Trigger::new(
+                    TriggerKind::Path,
+                    format!("{}/code/*", temp.path().to_string_lossy()),
+                ),
+            ],
+            ..UserConfig::default()
+        };
+        (project, user_config)
+    }
+
+    #[test]
+    fn test_trigger_priority_default_order() {
+        let temp = TempDir::new().unwrap();
+        let (project, user_config) = triple_source_dir(&temp);
+        assert_eq!(
+            user_config.trigger_priority,
+            vec![
+                SourceKind::Termtint,
+                SourceKind::Paths,
+                SourceKind::Files,
+                SourceKind::Dirnames
+            ]
+        );
+
+        assert_eq!(
+            find_config_source(&project, &user_config),
+            Some(ConfigSource::Termtint(project.join(".termtint")))
+        );
+        fs::remove_file(project.join(".termtint")).unwrap();
+        assert!(matches!(
+            find_config_source(&project, &user_config),
+            Some(ConfigSource::TriggerPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_vscode_peacock_source() {
+        let temp = TempDir::new().unwrap();
+        let project = temp.path().join("app");
+        let nested = project.join("src");
+        fs::create_dir_all(project.join(".vscode")).unwrap();
+        fs::create_dir_all(&nested).unwrap();
+        let settings = project.join(".vscode").join("settings.json");
+        fs::write(
+            &settings,
+            "{\n  // Project colors\n  \"peacock.color\": \"#3478f6\",\n  \"editor.tabSize\": 2,\n}\n",
+        )
+        .unwrap();
+        let user_config = UserConfig {
+            auto_from_vscode: true,
+            ..UserConfig::default()
+        };
+
+        // Off by default
+        assert_eq!(find_config_source(&nested, &UserConfig::default()), None);
+
+        let source = find_config_source(&nested, &user_config);
+        assert_eq!(source, Some(ConfigSource::VsCode(settings.clone())));
+        let source = source.unwrap();
+        assert_eq!(source.root_dir(), project);
+        let colors = parse_config_source(&source, &user_config).unwrap();
+        assert_eq!(colors.tab.to_string(), "#3478f6");
+        assert_eq!(
+            colors.background,
+            derive_background(&colors.tab, &user_config)
+        );
+
+        // A .termtint in the same directory wins
+        fs::write(project.join(".termtint"), "#ff5500").unwrap();
+        assert_eq!(
+            find_config_source(&nested, &user_config),
+            Some(ConfigSource::Termtint(project.join(".termtint")))
+        );
+        fs::remove_file(project.join(".termtint")).unwrap();
+
+        // Settings without a Peacock color, or that don't parse, aren't a source
+        fs::write(&settings, r#"{"editor.tabSize": 2}"#).unwrap();
+        assert_eq!(find_config_source(&nested, &user_config), None);
+        fs::write(&settings, "{ not json").unwrap();
+        assert_eq!(find_config_source(&nested, &user_config), None);
+        assert!(parse_config_source(&source, &user_config).is_err());
+    }
+
+    #[test]
+    fn test_trigger_priority_custom_order() {
+        let temp = TempDir::new().unwrap();
+        let (project, user_config) = triple_source_dir(&temp);
+
+        let files_first = UserConfig {
+            trigger_priority: vec![SourceKind::Files, SourceKind::Paths, SourceKind::Termtint],
+            ..user_config.clone()
+        };
+        assert!(matches!(
+            find_config_source(&project, &files_first),
+            Some(ConfigSource::TriggerFile(_))
+        ));
+
+        let paths_first = UserConfig {
+            trigger_priority: vec![SourceKind::Paths, SourceKind::Termtint, SourceKind::Files],
+            ..user_config
+        };
+        assert!(matches!(
+            find_config_source(&project, &paths_first),
+            Some(ConfigSource::TriggerPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_trigger_priority_nearest_directory_first() {
+        let temp = TempDir::new().unwrap();
+        let (project, user_config) = triple_source_dir(&temp);
+        let nested = project.join("crates").join("core");
+        fs::create_dir_all(&nested).unwrap();
+        File::create(nested.join("Cargo.toml")).unwrap();
+
+        // The priority only orders kinds within a directory; a nearer trigger file
+        // still beats the .termtint above it
+        assert!(matches!(
+            find_config_source(&nested, &user_config),
+            Some(ConfigSource::TriggerFile(_))
+        ));
+    }
+
+    #[test]
+    fn test_trigger_dirnames() {
+        let temp = TempDir::new().unwrap();
+        let prod = temp.path().join("deploy").join("prod");
+        let nested = prod.join("k8s");
+        let release = temp.path().join("release-2.1");
+        let production = temp.path().join("production");
+        for dir in [&nested, &release, &production] {
+            fs::create_dir_all(dir).unwrap();
         }
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::Dirname, "prod"),
+                Trigger::new(TriggerKind::Dirname, "release-*"),
+            ],
+            ..UserConfig::default()
+        };
+        let dirname = |dir: &Path| Some(ConfigSource::TriggerDirname(dir.canonicalize().unwrap()));
+
+        // Exact names match the directory and everything below it
+        assert_eq!(find_config_source(&prod, &user_config), dirname(&prod));
+        assert_eq!(find_config_source(&nested, &user_config), dirname(&prod));
+        assert_eq!(matched_dirname(&prod, &user_config), Some("prod"));
+
+        // Globs match the name only
+        assert_eq!(
+            find_config_source(&release, &user_config),
+            dirname(&release)
+        );
+        assert_eq!(matched_dirname(&release, &user_config), Some("release-*"));
+        assert_eq!(find_config_source(&production, &user_config), None);
+        assert_eq!(matched_dirname(&nested, &user_config), None);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::{self, File};
-    use tempfile::TempDir;
+    #[test]
+    fn test_trigger_dirnames_after_other_kinds() {
+        let temp = TempDir::new().unwrap();
+        let prod = temp.path().join("prod");
+        fs::create_dir_all(&prod).unwrap();
+        File::create(prod.join("Cargo.toml")).unwrap();
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::File, "Cargo.toml"),
+                Trigger::new(TriggerKind::Dirname, "prod"),
+            ],
+            ..UserConfig::default()
+        };
+
+        // Directory names come last by default
+        assert!(matches!(
+            find_config_source(&prod, &user_config),
+            Some(ConfigSource::TriggerFile(_))
+        ));
+        let dirnames_first = UserConfig {
+            trigger_priority: vec![SourceKind::Dirnames, SourceKind::Files],
+            ..user_config
+        };
+        assert!(matches!(
+            find_config_source(&prod, &dirnames_first),
+            Some(ConfigSource::TriggerDirname(_))
+        ));
+    }
+
+    fn env_config(rules: &[(&str, &str)], priority: EnvTriggerPriority) -> UserConfig {
+        UserConfig {
+            env_triggers: rules
+                .iter()
+                .map(|(rule, color)| (rule.to_string(), color.to_string()))
+                .collect(),
+            env_trigger_priority: priority,
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        }
+    }
+
+    fn fake_env(var: &str) -> Option<String> {
+        match var {
+            "AWS_PROFILE" => Some("prod-eu".to_string()),
+            "KUBECONFIG" => Some("/home/me/.kube/staging".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
 
     #[test]
-    fn test_parse_color_hex_with_hash() {
-        let rgb = parse_color("#ff5500").unwrap();
+    fn test_env_rule_matches() {
         assert_eq!(
-            rgb,
-            RGB {
-                r: 255,
-                g: 85,
-                b: 0
-            }
+            env_rule_matches("AWS_PROFILE=prod*", &fake_env),
+            Some("AWS_PROFILE=prod-eu".to_string())
+        );
+        assert_eq!(env_rule_matches("AWS_PROFILE=prod", &fake_env), None);
+        assert_eq!(env_rule_matches("KUBECONFIG=*prod*", &fake_env), None);
+        assert_eq!(env_rule_matches("UNSET=*", &fake_env), None);
+
+        // A bare variable name needs a non-empty value
+        assert_eq!(
+            env_rule_matches("AWS_PROFILE", &fake_env),
+            Some("AWS_PROFILE=prod-eu".to_string())
         );
+        assert_eq!(env_rule_matches("EMPTY", &fake_env), None);
     }
 
     #[test]
-    fn test_parse_color_hex_without_hash() {
-        let rgb = parse_color("00ff00").unwrap();
-        assert_eq!(rgb, RGB { r: 0, g: 255, b: 0 });
+    fn test_env_trigger_priority() {
+        let temp = TempDir::new().unwrap();
+        File::create(temp.path().join("Cargo.toml")).unwrap();
+        let env_source = ConfigSource::Environment {
+            rule: "AWS_PROFILE=prod*".to_string(),
+            assignment: "AWS_PROFILE=prod-eu".to_string(),
+        };
+        let rules = [("AWS_PROFILE=prod*", "#cc0000")];
+
+        // Before directory sources by default
+        let before = env_config(&rules, EnvTriggerPriority::Before);
+        assert_eq!(
+            find_source_with(
+                temp.path(),
+                &before,
+                &fake_env,
+                &|| None,
+                &mut Explanation::disabled()
+            ),
+            Some(env_source.clone())
+        );
+
+        // After: only where no directory source is found
+        let after = env_config(&rules, EnvTriggerPriority::After);
+        assert!(matches!(
+            find_source_with(
+                temp.path(),
+                &after,
+                &fake_env,
+                &|| None,
+                &mut Explanation::disabled()
+            ),
+            Some(ConfigSource::TriggerFile(_))
+        ));
+        let empty = TempDir::new().unwrap();
+        assert_eq!(
+            find_source_with(
+                empty.path(),
+                &after,
+                &fake_env,
+                &|| None,
+                &mut Explanation::disabled()
+            ),
+            Some(env_source)
+        );
+
+        // No matching rule leaves the directory source
+        assert!(matches!(
+            find_source_with(
+                temp.path(),
+                &before,
+                &|_| None,
+                &|| None,
+                &mut Explanation::disabled()
+            ),
+            Some(ConfigSource::TriggerFile(_))
+        ));
     }
 
     #[test]
-    fn test_parse_color_hex_with_whitespace() {
-        let rgb = parse_color("  #aabbcc  ").unwrap();
+    fn test_env_trigger_first_rule_in_key_order_wins() {
+        let user_config = env_config(
+            &[("AWS_PROFILE", "auto"), ("AWS_PROFILE=prod*", "#cc0000")],
+            EnvTriggerPriority::Before,
+        );
         assert_eq!(
-            rgb,
-            RGB {
-                r: 170,
-                g: 187,
-                b: 204
-            }
+            matched_env_trigger_with(&user_config, &fake_env, &mut Explanation::disabled()),
+            Some(ConfigSource::Environment {
+                rule: "AWS_PROFILE".to_string(),
+                assignment: "AWS_PROFILE=prod-eu".to_string(),
+            })
         );
     }
 
     #[test]
-    fn test_parse_color_hex_3digit() {
-        let rgb = parse_color("#f50").unwrap();
+    fn test_kube_context_trigger() {
+        let temp = TempDir::new().unwrap();
+        File::create(temp.path().join("Cargo.toml")).unwrap();
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::File, "Cargo.toml"),
+                Trigger {
+                    color: Some("#cc0000".to_string()),
+                    ..Trigger::new(TriggerKind::KubeContext, "*prod*")
+                },
+                Trigger::new(TriggerKind::KubeContext, "staging-*"),
+            ],
+            ..UserConfig::default()
+        };
+        let context = |name: &'static str| move || Some(name.to_string());
+
+        let source = find_source_with(
+            temp.path(),
+            &user_config,
+            &|_| None,
+            &context("eu-prod-1"),
+            &mut Explanation::disabled(),
+        );
         assert_eq!(
-            rgb,
-            RGB {
-                r: 255,
-                g: 85,
-                b: 0
-            }
+            source,
+            Some(ConfigSource::KubeContext {
+                pattern: "*prod*".to_string(),
+                context: "eu-prod-1".to_string(),
+            })
         );
+        let source = source.unwrap();
+        assert_eq!(source.root_dir(), PathBuf::from("kube-context=eu-prod-1"));
+        assert_eq!(
+            parse_config_source(&source, &user_config).unwrap(),
+            parse_simple_color("#cc0000", &user_config).unwrap()
+        );
+
+        // A trigger without a color hashes the context, so each gets its own
+        let staging = |name: &'static str| {
+            let source = find_source_with(
+                temp.path(),
+                &user_config,
+                &|_| None,
+                &context(name),
+                &mut Explanation::disabled(),
+            );
+            parse_config_source(&source.unwrap(), &user_config).unwrap()
+        };
+        assert_eq!(staging("staging-eu"), staging("staging-eu"));
+        assert_ne!(staging("staging-eu"), staging("staging-us"));
+
+        // Other contexts, or none, leave the directory source
+        assert!(matches!(
+            find_source_with(
+                temp.path(),
+                &user_config,
+                &|_| None,
+                &context("dev"),
+                &mut Explanation::disabled()
+            ),
+            Some(ConfigSource::TriggerFile(_))
+        ));
+        assert!(matches!(
+            find_source_with(
+                temp.path(),
+                &user_config,
+                &|_| None,
+                &|| None,
+                &mut Explanation::disabled()
+            ),
+            Some(ConfigSource::TriggerFile(_))
+        ));
     }
 
     #[test]
-    fn test_parse_color_hex_3digit_abc() {
-        let rgb = parse_color("#abc").unwrap();
+    fn test_kube_context_read_only_with_kube_triggers() {
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
+        let read = std::cell::Cell::new(false);
+        let current_context = || {
+            read.set(true);
+            Some("prod".to_string())
+        };
         assert_eq!(
-            rgb,
-            RGB {
-                r: 170,
-                g: 187,
-                b: 204
-            }
+            matched_kube_context_with(&user_config, &current_context, &mut Explanation::disabled()),
+            None
         );
+        assert!(!read.get());
     }
 
     #[test]
-    fn test_parse_color_rgb_function() {
-        let rgb = parse_color("rgb(255, 85, 0)").unwrap();
+    fn test_parse_config_source_environment() {
+        let user_config = env_config(
+            &[("AWS_PROFILE=prod*", "#cc0000"), ("KUBECONFIG", "auto")],
+            EnvTriggerPriority::Before,
+        );
+        let explicit = ConfigSource::Environment {
+            rule: "AWS_PROFILE=prod*".to_string(),
+            assignment: "AWS_PROFILE=prod-eu".to_string(),
+        };
+        let colors = parse_config_source(&explicit, &user_config).unwrap();
+        assert_eq!(colors.tab, parse_color("#cc0000").unwrap());
+
+        // Auto colors follow the variable's value
+        let auto = |value: &str| ConfigSource::Environment {
+            rule: "KUBECONFIG".to_string(),
+            assignment: format!("KUBECONFIG={}", value),
+        };
+        let staging = parse_config_source(&auto("staging"), &user_config).unwrap();
         assert_eq!(
-            rgb,
-            RGB {
-                r: 255,
-                g: 85,
-                b: 0
-            }
+            parse_config_source(&auto("staging"), &user_config).unwrap(),
+            staging
+        );
+        assert_ne!(
+            parse_config_source(&auto("prod"), &user_config).unwrap(),
+            staging
         );
     }
 
     #[test]
-    fn test_parse_color_hsl_function() {
-        let rgb = parse_color("hsl(20, 100%, 50%)").unwrap();
+    fn test_parse_config_source_trigger_path() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+
+        let source = ConfigSource::TriggerPath(temp.path().to_path_buf());
+        let config = parse_config_source(&source, &user_config).unwrap();
+
+        // Should generate auto color based on directory path
+        let max_channel = config.tab.r.max(config.tab.g).max(config.tab.b);
+        assert!(max_channel >= 128, "Generated color should be vibrant");
+    }
+
+    #[test]
+    fn test_parse_config_source_trigger_path_deterministic() {
+        let user_config = UserConfig::default();
+        let temp = TempDir::new().unwrap();
+
+        let source = ConfigSource::TriggerPath(temp.path().to_path_buf());
+        let config1 = parse_config_source(&source, &user_config).unwrap();
+        let config2 = parse_config_source(&source, &user_config).unwrap();
+
+        // Should generate the same color for the same directory
+        assert_eq!(config1.tab, config2.tab);
+        assert_eq!(config1.background, config2.background);
+    }
+
+    #[test]
+    fn test_hue_distance_wraps_around() {
+        assert_eq!(hue_distance(10.0, 350.0), 20.0);
+        assert_eq!(hue_distance(350.0, 10.0), 20.0);
+        assert_eq!(hue_distance(0.0, 180.0), 180.0);
+        assert_eq!(hue_distance(90.0, 90.0), 0.0);
+    }
+
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_generate_spread_color_keeps_min_distance() {
+        use rand::SeedableRng;
+
+        let user_config = UserConfig::default();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let mut recent: Vec<f32> = Vec::new();
+
+        for _ in 0..50 {
+            let (_, hue) = generate_spread_color_with_rng(&mut rng, &user_config, &recent);
+            for &previous in &recent {
+                assert!(
+                    hue_distance(hue, previous) >= user_config.hue_spread,
+                    "Hue {} is within {} degrees of recent hue {}",
+                    hue,
+                    user_config.hue_spread,
+                    previous
+                );
+            }
+            recent.push(hue);
+            if recent.len() > 4 {
+                recent.remove(0);
+            }
+        }
+    }
+
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_generate_spread_color_disabled_matches_random_draw() {
+        use rand::SeedableRng;
+
+        let user_config = UserConfig {
+            hue_spread: 0.0,
+            ..UserConfig::default()
+        };
+        let recent = vec![0.0, 120.0, 240.0];
+
+        let mut rng1 = rand::rngs::StdRng::seed_from_u64(7);
+        let mut rng2 = rand::rngs::StdRng::seed_from_u64(7);
+        let (spread, _) = generate_spread_color_with_rng(&mut rng1, &user_config, &recent);
+        let (plain, _) = generate_spread_color_with_rng(&mut rng2, &user_config, &[]);
+
+        assert_eq!(spread, plain);
+    }
+
+    #[test]
+    fn test_derive_background_darken_mode() {
+        let user_config = UserConfig::default();
+        let tab = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+
+        let background = derive_background(&tab, &user_config);
+
         assert_eq!(
-            rgb,
-            RGB {
+            background,
+            tab.with_lightness(user_config.background_lightness)
+        );
+        let oklab = srgb_to_oklab(Rgb {
+            r: background.r,
+            g: background.g,
+            b: background.b,
+        });
+        // Gamut clipping moves the result slightly, but it stays dark
+        assert!(
+            oklab.l < 0.3,
+            "Darkened lightness {} should be low",
+            oklab.l
+        );
+    }
+
+    #[test]
+    fn test_derive_background_lighten_mode() {
+        let user_config = UserConfig {
+            background_mode: BackgroundMode::Lighten,
+            ..UserConfig::default()
+        };
+        let tab = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+
+        let background = derive_background(&tab, &user_config);
+        let oklab = srgb_to_oklab(Rgb {
+            r: background.r,
+            g: background.g,
+            b: background.b,
+        });
+        let tab_oklab = srgb_to_oklab(Rgb {
+            r: tab.r,
+            g: tab.g,
+            b: tab.b,
+        });
+
+        // Lightness targets the light-mode value (allowing for gamut clipping)
+        assert!(
+            oklab.l > 0.85,
+            "Lightened lightness {} should be high",
+            oklab.l
+        );
+        // Tint is kept subtle but still leans toward the tab hue
+        let chroma = (oklab.a * oklab.a + oklab.b * oklab.b).sqrt();
+        let tab_chroma = (tab_oklab.a * tab_oklab.a + tab_oklab.b * tab_oklab.b).sqrt();
+        assert!(chroma < tab_chroma * 0.5);
+        assert!(background.r > background.b);
+    }
+
+    #[test]
+    fn test_parse_simple_color_lighten_mode() {
+        let user_config = UserConfig {
+            background_mode: BackgroundMode::Lighten,
+            ..UserConfig::default()
+        };
+        let config = parse_simple_color("#ff5500", &user_config).unwrap();
+
+        // Lightened background should be much brighter than the darkened default
+        let darkened = parse_simple_color("#ff5500", &UserConfig::default()).unwrap();
+        assert!(config.background.r > 200 && config.background.g > 200);
+        assert!(darkened.background.r < 100);
+    }
+
+    #[test]
+    fn test_apply_dimming_scales_background_only() {
+        let color_config = ColorConfig {
+            tab: RGB {
                 r: 255,
                 g: 85,
-                b: 0
-            }
-        );
+                b: 0,
+            },
+            background: RGB { r: 77, g: 0, b: 0 },
+        };
+        let dimming = DimmingConfig {
+            start: 21 * 60,
+            end: 7 * 60,
+            lightness_scale: 0.5,
+            dim_tab: false,
+        };
+
+        let dimmed = apply_dimming(&color_config, &dimming);
+
+        assert_eq!(dimmed.tab, color_config.tab);
+        assert!(dimmed.background.r < color_config.background.r);
     }
 
     #[test]
-    fn test_parse_color_hsl_red() {
-        let rgb = parse_color("hsl(0, 100%, 50%)").unwrap();
-        assert_eq!(rgb, RGB { r: 255, g: 0, b: 0 });
+    fn test_apply_dimming_with_tab() {
+        let color_config = ColorConfig {
+            tab: RGB {
+                r: 255,
+                g: 85,
+                b: 0,
+            },
+            background: RGB { r: 77, g: 0, b: 0 },
+        };
+        let dimming = DimmingConfig {
+            start: 21 * 60,
+            end: 7 * 60,
+            lightness_scale: 0.7,
+            dim_tab: true,
+        };
+
+        let dimmed = apply_dimming(&color_config, &dimming);
+
+        assert!(dimmed.tab.r < color_config.tab.r);
+        assert!(dimmed.background.r < color_config.background.r);
     }
 
     #[test]
-    fn test_parse_color_hsl_green() {
-        let rgb = parse_color("hsl(120, 100%, 50%)").unwrap();
-        assert_eq!(rgb, RGB { r: 0, g: 255, b: 0 });
+    fn test_scale_lightness_identity() {
+        let rgb = RGB {
+            r: 100,
+            g: 150,
+            b: 200,
+        };
+        assert_eq!(rgb.scale_lightness(1.0), rgb);
     }
 
     #[test]
-    fn test_parse_color_named_red() {
-        let rgb = parse_color("red").unwrap();
-        assert_eq!(rgb, RGB { r: 255, g: 0, b: 0 });
+    fn test_local_minute_of_day_in_range() {
+        // Fixed instants, whatever the local time zone: an hour apart is 60 local
+        // minutes apart (2023-11-14 has no DST change in any zone at 22:13 UTC)
+        use std::time::Duration;
+        let at = |secs| local_minute_of_day(UNIX_EPOCH + Duration::from_secs(secs));
+        let base = 1_700_000_000;
+        for secs in [0, base, base + 3600, u32::MAX as u64] {
+            assert!(at(secs) < 24 * 60, "{}", secs);
+        }
+        assert_eq!((at(base) + 60) % (24 * 60), at(base + 3600));
+        // Before the epoch counts as midnight UTC
+        assert_eq!(
+            local_minute_of_day(UNIX_EPOCH - Duration::from_secs(1)),
+            at(0)
+        );
+    }
+
+    fn wrapped_hue_config() -> UserConfig {
+        UserConfig {
+            hue_min: 300.0,
+            hue_max: 40.0,
+            ..UserConfig::default()
+        }
+    }
+
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_generate_spread_color_wrapped_hue_range() {
+        use rand::SeedableRng;
+
+        let user_config = wrapped_hue_config();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let mut recent: Vec<f32> = Vec::new();
+
+        for _ in 0..200 {
+            let (_, hue) = generate_spread_color_with_rng(&mut rng, &user_config, &recent);
+            assert!(
+                (300.0..360.0).contains(&hue) || (0.0..=40.0).contains(&hue),
+                "Hue {} outside wrapped range 300-40",
+                hue
+            );
+            recent.push(hue);
+            if recent.len() > 2 {
+                recent.remove(0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_auto_wrapped_hue_range() {
+        let user_config = wrapped_hue_config();
+        let temp = TempDir::new().unwrap();
+
+        for i in 0..100 {
+            let path = temp.path().join(format!("project{}", i));
+            let config = parse_auto(&path, &user_config);
+
+            let color_str = format!("{}", config.tab);
+            let color = csscolorparser::parse(&color_str).unwrap();
+            let [hue, _saturation, _lightness, _alpha] = color.to_hsla();
+
+            // Allow a small tolerance for RGB quantization
+            assert!(
+                hue >= 298.0 || hue <= 42.0,
+                "Path {:?} generated hue {} outside wrapped range 300-40",
+                path,
+                hue
+            );
+        }
     }
 
     #[test]
-    fn test_parse_color_named_tomato() {
-        let rgb = parse_color("tomato").unwrap();
+    fn test_parse_color_with_alpha_8_digit_hex() {
+        let (rgb, alpha) = parse_color_with_alpha("#3366ff80").unwrap();
         assert_eq!(
             rgb,
             RGB {
-                r: 255,
-                g: 99,
-                b: 71
+                r: 51,
+                g: 102,
+                b: 255
             }
         );
-    }
+        assert!((alpha - 128.0 / 255.0).abs() < 0.001);
 
-    #[test]
-    fn test_parse_color_invalid() {
-        let result = parse_color("#gggggg");
-        assert!(result.is_err());
+        // Bare 8-digit hex is accepted like bare 6-digit hex
+        let (bare, bare_alpha) = parse_color_with_alpha("3366ff80").unwrap();
+        assert_eq!(bare, rgb);
+        assert_eq!(bare_alpha, alpha);
     }
 
     #[test]
-    fn test_parse_color_invalid_notacolor() {
-        let result = parse_color("notacolor");
-        assert!(result.is_err());
+    fn test_parse_color_with_alpha_4_digit_hex() {
+        let (rgb, alpha) = parse_color_with_alpha("#36f8").unwrap();
+        assert_eq!(
+            rgb,
+            RGB {
+                r: 51,
+                g: 102,
+                b: 255
+            }
+        );
+        assert!((alpha - 136.0 / 255.0).abs() < 0.001);
     }
 
     #[test]
-    fn test_rgb_with_lightness() {
-        let rgb = RGB {
-            r: 100,
-            g: 200,
-            b: 50,
-        };
-        let darkened = rgb.with_lightness(0.10);
-        // Setting Oklab lightness to 0.10 preserves hue
-        assert_eq!(darkened, RGB { r: 0, g: 9, b: 0 });
-    }
+    fn test_parse_color_with_alpha_functional() {
+        let (rgb, alpha) = parse_color_with_alpha("rgba(51, 102, 255, 0.5)").unwrap();
+        assert_eq!(
+            rgb,
+            RGB {
+                r: 51,
+                g: 102,
+                b: 255
+            }
+        );
+        assert_eq!(alpha, 0.5);
 
-    #[test]
-    fn test_rgb_with_lightness_different_value() {
-        let rgb = RGB {
-            r: 255,
-            g: 85,
-            b: 0,
-        };
-        let darkened = rgb.with_lightness(0.15);
-        // Setting Oklab lightness to 0.15 preserves hue
-        assert_eq!(darkened, RGB { r: 66, g: 0, b: 0 });
+        let (rgb, alpha) = parse_color_with_alpha("hsla(225, 100%, 60%, 0.5)").unwrap();
+        assert_eq!(
+            rgb,
+            RGB {
+                r: 51,
+                g: 102,
+                b: 255
+            }
+        );
+        assert_eq!(alpha, 0.5);
     }
 
     #[test]
-    fn test_rgb_with_lightness_and_saturation_full_saturation() {
-        let rgb = RGB {
-            r: 255,
-            g: 85,
-            b: 0,
-        };
-        // Full saturation (1.0) should produce the same result as with_lightness
-        let result = rgb.with_lightness_and_saturation(0.15, 1.0);
-        let expected = rgb.with_lightness(0.15);
-        assert_eq!(result, expected);
+    fn test_parse_color_with_alpha_opaque() {
+        let (_, alpha) = parse_color_with_alpha("#ff5500").unwrap();
+        assert_eq!(alpha, 1.0);
     }
 
     #[test]
-    fn test_rgb_with_lightness_and_saturation_zero_saturation() {
-        let rgb = RGB {
+    fn test_composite_over() {
+        let white = RGB {
             r: 255,
-            g: 85,
-            b: 0,
+            g: 255,
+            b: 255,
         };
-        // Zero saturation should produce grayscale
-        let result = rgb.with_lightness_and_saturation(0.15, 0.0);
-        // Grayscale means r == g == b
-        assert_eq!(result.r, result.g);
-        assert_eq!(result.g, result.b);
+        let black = RGB { r: 0, g: 0, b: 0 };
+
+        assert_eq!(white.composite_over(1.0, &black), white);
+        assert_eq!(white.composite_over(0.0, &black), black);
+        assert_eq!(
+            white.composite_over(0.5, &black),
+            RGB {
+                r: 128,
+                g: 128,
+                b: 128
+            }
+        );
     }
 
     #[test]
-    fn test_rgb_with_lightness_and_saturation_half_saturation() {
-        let rgb = RGB {
-            r: 255,
-            g: 85,
-            b: 0,
-        };
-        // Half saturation should be somewhere between full and grayscale
-        let full = rgb.with_lightness_and_saturation(0.15, 1.0);
-        let half = rgb.with_lightness_and_saturation(0.15, 0.5);
-        let zero = rgb.with_lightness_and_saturation(0.15, 0.0);
-
-        // Half saturation result should have less color difference than full
-        let full_diff =
-            (full.r as i32 - full.g as i32).abs() + (full.g as i32 - full.b as i32).abs();
-        let half_diff =
-            (half.r as i32 - half.g as i32).abs() + (half.g as i32 - half.b as i32).abs();
-        let zero_diff =
-            (zero.r as i32 - zero.g as i32).abs() + (zero.g as i32 - zero.b as i32).abs();
+    fn test_parse_simple_color_composites_alpha_over_background() {
+        let user_config = UserConfig::default();
+        let config = parse_simple_color("#ff550080", &user_config).unwrap();
+        let opaque = parse_simple_color("#ff5500", &user_config).unwrap();
 
-        assert!(
-            half_diff < full_diff,
-            "Half saturation should have less chroma than full"
-        );
-        assert!(
-            half_diff > zero_diff,
-            "Half saturation should have more chroma than zero"
+        // Background is derived from the opaque color, tab is flattened over it
+        assert_eq!(config.background, opaque.background);
+        let alpha = 128.0 / 255.0;
+        assert_eq!(
+            config.tab,
+            opaque.tab.composite_over(alpha, &opaque.background)
         );
     }
 
     #[test]
-    fn test_detect_format_simple_color_with_hash() {
-        assert_eq!(detect_format("#ff5500"), ConfigFormat::SimpleColor);
-    }
+    fn test_parse_toml_translucent_background_over_black() {
+        let user_config = UserConfig::default();
+        let config = parse_toml(
+            Path::new("/.termtint"),
+            "tab = \"#00ff00\"\nbackground = \"rgba(0, 0, 255, 0.5)\"",
+            &user_config,
+        )
+        .unwrap();
 
-    #[test]
-    fn test_detect_format_simple_color_without_hash() {
-        assert_eq!(detect_format("ff5500"), ConfigFormat::SimpleColor);
+        assert_eq!(config.background, RGB { r: 0, g: 0, b: 128 });
+        assert_eq!(config.tab, RGB { r: 0, g: 255, b: 0 });
     }
 
     #[test]
-    fn test_detect_format_auto() {
-        assert_eq!(detect_format("auto"), ConfigFormat::Auto);
-        assert_eq!(detect_format("  auto  "), ConfigFormat::Auto);
+    fn test_rgb_hex_with_alpha() {
+        let rgb = RGB {
+            r: 51,
+            g: 102,
+            b: 255,
+        };
+        assert_eq!(rgb.hex_with_alpha(1.0), "#3366ff");
+        assert_eq!(rgb.hex_with_alpha(0.5), "#3366ff80");
     }
 
     #[test]
-    fn test_detect_format_toml() {
-        assert_eq!(detect_format("tab = \"#ff5500\""), ConfigFormat::Toml);
+    fn test_parse_color_space_separated_rgb() {
+        let expected = RGB {
+            r: 51,
+            g: 102,
+            b: 255,
+        };
+        assert_eq!(parse_color("rgb(51 102 255)").unwrap(), expected);
+        assert_eq!(parse_color("rgb(20% 40% 100%)").unwrap(), expected);
+
+        let (rgb, alpha) = parse_color_with_alpha("rgb(51 102 255 / 50%)").unwrap();
+        assert_eq!(rgb, expected);
+        assert_eq!(alpha, 0.5);
     }
 
     #[test]
-    fn test_detect_format_named_color() {
-        assert_eq!(detect_format("red"), ConfigFormat::SimpleColor);
-        assert_eq!(detect_format("tomato"), ConfigFormat::SimpleColor);
+    fn test_parse_color_space_separated_hsl() {
+        let expected = RGB {
+            r: 51,
+            g: 102,
+            b: 255,
+        };
+        assert_eq!(parse_color("hsl(225 100% 60%)").unwrap(), expected);
+        assert_eq!(parse_color("hsl(225deg 100% 60%)").unwrap(), expected);
     }
 
     #[test]
-    fn test_parse_simple_color_config() {
-        let user_config = UserConfig::default();
-        let config = parse_simple_color("#ff5500", &user_config).unwrap();
+    fn test_parse_color_oklch() {
         assert_eq!(
-            config.tab,
+            parse_color("oklch(62% 0.2 255)").unwrap(),
             RGB {
-                r: 255,
-                g: 85,
-                b: 0
+                r: 12,
+                g: 132,
+                b: 250
             }
         );
-        // Background uses fixed lightness (0.18 by default)
-        assert_eq!(config.background, RGB { r: 77, g: 0, b: 0 });
-    }
-
-    #[test]
-    fn test_parse_toml_with_tab_only() {
-        let user_config = UserConfig::default();
-        let config = parse_toml("tab = \"#00ff00\"", &user_config).unwrap();
-        assert_eq!(config.tab, RGB { r: 0, g: 255, b: 0 });
-        // Background uses fixed lightness (0.18 by default)
-        assert_eq!(config.background, RGB { r: 0, g: 34, b: 0 });
-    }
-
-    #[test]
-    fn test_parse_toml_with_background() {
-        let user_config = UserConfig::default();
-        let config =
-            parse_toml("tab = \"#00ff00\"\nbackground = \"#001100\"", &user_config).unwrap();
-        assert_eq!(config.tab, RGB { r: 0, g: 255, b: 0 });
-        assert_eq!(config.background, RGB { r: 0, g: 17, b: 0 });
     }
 
     #[test]
-    fn test_parse_toml_missing_tab() {
-        let user_config = UserConfig::default();
-        let result = parse_toml("background = \"#001100\"", &user_config);
-        assert!(result.is_err());
+    fn test_parse_color_lab() {
+        assert_eq!(
+            parse_color("lab(50% 40 59.5)").unwrap(),
+            RGB {
+                r: 195,
+                g: 87,
+                b: 0
+            }
+        );
+        assert_eq!(
+            parse_color("lch(50% 72 56)").unwrap(),
+            RGB {
+                r: 196,
+                g: 86,
+                b: 0
+            }
+        );
     }
 
     #[test]
-    fn test_parse_toml_with_hsl() {
-        let user_config = UserConfig::default();
-        let config = parse_toml("tab = \"hsl(0, 100%, 50%)\"", &user_config).unwrap();
-        assert_eq!(config.tab, RGB { r: 255, g: 0, b: 0 });
-        // Background uses fixed lightness (0.18 by default)
-        assert_eq!(config.background, RGB { r: 87, g: 0, b: 0 });
+    fn test_parse_color_error_lists_syntaxes() {
+        let err = parse_color("blah(1)").unwrap_err();
+        assert!(matches!(err, TermtintError::InvalidColor(_)));
+        let err = err.to_string();
+        assert!(err.contains("Invalid color 'blah(1)'"));
+        assert!(err.contains("oklch("));
+        assert!(err.contains("named color"));
     }
 
     #[test]
-    fn test_parse_toml_with_named_color() {
+    fn test_parse_toml_with_oklch() {
         let user_config = UserConfig::default();
-        let config = parse_toml("tab = \"tomato\"", &user_config).unwrap();
+        let config = parse_toml(
+            Path::new("/.termtint"),
+            "tab = \"oklch(62% 0.2 255)\"",
+            &user_config,
+        )
+        .unwrap();
         assert_eq!(
             config.tab,
             RGB {
-                r: 255,
-                g: 99,
-                b: 71
+                r: 12,
+                g: 132,
+                b: 250
             }
         );
-        // Background uses fixed lightness (0.18 by default)
-        assert_eq!(config.background, RGB { r: 73, g: 0, b: 0 });
     }
 
     #[test]
-    fn test_parse_auto_deterministic() {
-        let user_config = UserConfig::default();
-        let temp = TempDir::new().unwrap();
-        let config_path = temp.path().join(".termtint");
-        fs::write(&config_path, "auto").unwrap();
+    fn test_delta_e() {
+        let red = RGB { r: 255, g: 0, b: 0 };
+        let black = RGB { r: 0, g: 0, b: 0 };
 
-        let config1 = parse_auto(&config_path, &user_config);
-        let config2 = parse_auto(&config_path, &user_config);
-        assert_eq!(config1, config2);
+        assert_eq!(red.delta_e(&red), 0.0);
+        assert!(red.delta_e(&black) > 0.5);
+        assert_eq!(red.delta_e(&black), black.delta_e(&red));
     }
 
+    #[cfg(feature = "reroll")]
     #[test]
-    fn test_parse_auto_produces_vibrant_colors() {
-        // Test multiple different paths to ensure vibrancy constraints hold
+    fn test_reroll_color_exceeds_min_delta() {
+        use rand::SeedableRng;
+
         let user_config = UserConfig::default();
-        let temp = TempDir::new().unwrap();
+        let current = RGB {
+            r: 230,
+            g: 90,
+            b: 40,
+        };
 
-        let test_paths = vec![
-            temp.path().join("project1").join(".termtint"),
-            temp.path().join("project2").join(".termtint"),
-            temp.path().join("project3").join(".termtint"),
-            temp.path()
-                .join("deeply")
-                .join("nested")
-                .join("path")
-                .join(".termtint"),
-        ];
+        for seed in 0..20 {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            let reroll = reroll_color_with_rng(&mut rng, &user_config, &[], Some(&current), 0.12);
+            assert!(
+                reroll.color.delta_e(&current) > 0.12,
+                "Seed {} produced {} too close to {}",
+                seed,
+                reroll.color,
+                current
+            );
+            assert!(reroll.attempts >= 1 && reroll.attempts <= MAX_REROLL_ATTEMPTS);
+        }
+    }
 
-        for path in test_paths {
-            // Create parent directories
-            if let Some(parent) = path.parent() {
-                fs::create_dir_all(parent).unwrap();
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_reroll_color_retries_until_distinct() {
+        use rand::SeedableRng;
+
+        // A narrow hue band makes most candidates too similar, forcing retries
+        let user_config = UserConfig {
+            hue_min: 0.0,
+            hue_max: 40.0,
+            hue_spread: 0.0,
+            ..UserConfig::default()
+        };
+        let current = hsl_to_tab_color(5.0, 0.8, &user_config);
+
+        let mut retried = false;
+        for seed in 0..50 {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            let reroll = reroll_color_with_rng(&mut rng, &user_config, &[], Some(&current), 0.1);
+            if reroll.attempts > 1 {
+                retried = true;
             }
-            fs::write(&path, "auto").unwrap();
+            if reroll.attempts < MAX_REROLL_ATTEMPTS {
+                assert!(reroll.color.delta_e(&current) > 0.1);
+            }
+        }
+        assert!(retried, "Expected at least one seed to need a retry");
+    }
 
-            let config = parse_auto(&path, &user_config);
-            let rgb = config.tab;
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_reroll_color_attempts_are_bounded() {
+        use rand::SeedableRng;
 
-            // Convert RGB back to HSL to verify constraints
-            // Using csscolorparser to convert back
-            let color_str = format!("#{:02x}{:02x}{:02x}", rgb.r, rgb.g, rgb.b);
-            let color = csscolorparser::parse(&color_str).unwrap();
-            let [_hue, saturation, lightness, _alpha] = color.to_hsla();
+        let user_config = UserConfig::default();
+        let current = RGB {
+            r: 230,
+            g: 90,
+            b: 40,
+        };
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
 
-            // Verify saturation is >= 0.7 (high saturation for vibrant colors)
-            assert!(
-                saturation >= 0.7,
-                "Path {:?} generated color {} with saturation {}, expected >= 0.7",
-                path,
-                color_str,
-                saturation
-            );
+        // An impossible threshold exhausts the attempts and keeps the most distinct candidate
+        let reroll = reroll_color_with_rng(&mut rng, &user_config, &[], Some(&current), 10.0);
+        assert_eq!(reroll.attempts, MAX_REROLL_ATTEMPTS);
+        assert_ne!(reroll.color, current);
+    }
 
-            // Verify saturation is <= 0.9 (upper bound from implementation)
-            assert!(
-                saturation <= 0.9,
-                "Path {:?} generated color {} with saturation {}, expected <= 0.9",
-                path,
-                color_str,
-                saturation
-            );
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_reroll_color_without_current_accepts_first() {
+        use rand::SeedableRng;
 
-            // Verify lightness is approximately the configured value (0.55 default)
-            // Allow small tolerance for floating point/color space conversion
-            let expected_lightness = user_config.lightness;
-            assert!(
-                (lightness - expected_lightness).abs() < 0.02,
-                "Path {:?} generated color {} with lightness {}, expected ~{}",
-                path,
-                color_str,
-                lightness,
-                expected_lightness
-            );
+        let user_config = UserConfig::default();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let reroll = reroll_color_with_rng(&mut rng, &user_config, &[], None, 0.12);
+        assert_eq!(reroll.attempts, 1);
+    }
+
+    /// Create a monorepo with a root `.termtint` and a subpackage containing a trigger file.
+    fn create_monorepo(temp: &TempDir) -> PathBuf {
+        fs::write(temp.path().join(".termtint"), "#ff0000").unwrap();
+        let package = temp.path().join("packages").join("web");
+        fs::create_dir_all(&package).unwrap();
+        File::create(package.join("package.json")).unwrap();
+        package
+    }
+
+    fn blend_config(factor: f32) -> UserConfig {
+        UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "package.json")],
+            blend_with_parent: factor,
+            ..UserConfig::default()
         }
     }
 
     #[test]
-    fn test_parse_config_hex_file() {
-        let user_config = UserConfig::default();
+    fn test_blend_oklch_endpoints() {
+        let red = RGB { r: 255, g: 0, b: 0 };
+        let blue = RGB { r: 0, g: 0, b: 255 };
+
+        assert_eq!(red.blend_oklch(&blue, 0.0), red);
+        assert_eq!(red.blend_oklch(&blue, 1.0), blue);
+    }
+
+    #[test]
+    fn test_blend_oklch_takes_shorter_hue_arc() {
+        // Hues 350 and 10 should meet near 0, not near 180
+        let a = RGB::from_oklch(0.6, 0.15, 350.0);
+        let b = RGB::from_oklch(0.6, 0.15, 10.0);
+        let (_, _, hue) = a.blend_oklch(&b, 0.5).to_oklch();
+
+        assert!(hue_distance(hue, 0.0) < 5.0, "Got hue {}", hue);
+    }
+
+    #[test]
+    fn test_blend_with_parent_zero_is_unchanged() {
         let temp = TempDir::new().unwrap();
-        let config_path = temp.path().join(".termtint");
-        fs::write(&config_path, "#ff5500").unwrap();
+        let package = create_monorepo(&temp);
+        let user_config = blend_config(0.0);
 
-        let config = parse_config(&config_path, &user_config).unwrap();
-        assert_eq!(
-            config.tab,
-            RGB {
-                r: 255,
-                g: 85,
-                b: 0
-            }
-        );
+        let source = find_config_source(&package, &user_config).unwrap();
+        let blended = parse_config_source(&source, &user_config).unwrap();
+
+        assert_eq!(blended, parse_auto(&package, &user_config));
+        assert!(parent_blend(&source, &user_config).is_none());
     }
 
     #[test]
-    fn test_parse_config_toml_file() {
-        let user_config = UserConfig::default();
+    fn test_blend_with_parent_one_is_parent() {
         let temp = TempDir::new().unwrap();
-        let config_path = temp.path().join(".termtint");
-        fs::write(&config_path, "tab = \"#00ff00\"").unwrap();
+        let package = create_monorepo(&temp);
+        let user_config = blend_config(1.0);
 
-        let config = parse_config(&config_path, &user_config).unwrap();
-        assert_eq!(config.tab, RGB { r: 0, g: 255, b: 0 });
+        let source = find_config_source(&package, &user_config).unwrap();
+        let blended = parse_config_source(&source, &user_config).unwrap();
+        let parent = parse_config(&temp.path().join(".termtint"), &user_config).unwrap();
+
+        assert_eq!(blended, parent);
     }
 
     #[test]
-    fn test_parse_config_auto_file() {
-        let user_config = UserConfig::default();
+    fn test_blend_with_parent_midpoint() {
         let temp = TempDir::new().unwrap();
-        let config_path = temp.path().join(".termtint");
-        fs::write(&config_path, "auto").unwrap();
+        let package = create_monorepo(&temp);
+        let user_config = blend_config(0.5);
+
+        let source = find_config_source(&package, &user_config).unwrap();
+        let blend = parent_blend(&source, &user_config).unwrap();
+        assert_eq!(blend.parent_path, temp.path().join(".termtint"));
+        assert_eq!(blend.factor, 0.5);
+
+        let blended = blend.blended();
+        assert_ne!(blended.tab, blend.own.tab);
+        assert_ne!(blended.tab, blend.parent.tab);
+
+        // The blended hue lies between the two inputs
+        let (_, _, own_hue) = blend.own.tab.to_oklch();
+        let (_, _, parent_hue) = blend.parent.tab.to_oklch();
+        let (_, _, hue) = blended.tab.to_oklch();
+        let total = hue_distance(own_hue, parent_hue);
+        assert!(hue_distance(hue, own_hue) <= total + 1.0);
+        assert!(hue_distance(hue, parent_hue) <= total + 1.0);
+
+        // Deterministic
+        let source = find_config_source(&package, &user_config).unwrap();
+        assert_eq!(parse_config_source(&source, &user_config).unwrap(), blended);
+    }
 
-        let config = parse_config(&config_path, &user_config).unwrap();
-        // Auto generates vibrant colors using HSL, verify at least one channel is bright
-        let max_channel = config.tab.r.max(config.tab.g).max(config.tab.b);
-        assert!(
-            max_channel >= 128,
-            "Generated color should be vibrant with at least one bright channel"
-        );
+    #[test]
+    fn test_blend_with_parent_without_ancestor_termtint() {
+        let temp = TempDir::new().unwrap();
+        File::create(temp.path().join("package.json")).unwrap();
+        let user_config = blend_config(0.6);
+
+        let source = find_config_source(temp.path(), &user_config).unwrap();
+        assert!(parent_blend(&source, &user_config).is_none());
     }
 
     #[test]
-    fn test_rgb_display() {
-        let rgb = RGB {
-            r: 255,
-            g: 85,
-            b: 0,
-        };
-        assert_eq!(format!("{}", rgb), "#ff5500");
+    fn test_auto_hash_inputs_without_hostname_match_path_hash() {
+        let temp = TempDir::new().unwrap();
+        let inputs = AutoHashInputs::for_path(temp.path(), &UserConfig::default());
 
-        let rgb = RGB {
-            r: 0,
-            g: 17,
-            b: 255,
-        };
-        assert_eq!(format!("{}", rgb), "#0011ff");
+        assert_eq!(inputs.hostname, None);
+        assert_eq!(inputs.path, temp.path().canonicalize().unwrap());
+
+        // Without a hostname the hash is the plain path hash, keeping colors stable
+        let mut hasher = DefaultHasher::new();
+        inputs.path.hash(&mut hasher);
+        assert_eq!(inputs.hash(), hasher.finish());
     }
 
     #[test]
-    fn test_rgb_format_as_hex() {
-        let rgb = RGB {
-            r: 255,
-            g: 85,
-            b: 0,
+    fn test_auto_hash_inputs_include_hostname() {
+        let temp = TempDir::new().unwrap();
+        let user_config = UserConfig {
+            hash_include_hostname: true,
+            ..UserConfig::default()
         };
-        assert_eq!(
-            rgb.format_as(crate::user_config::ColorFormat::Hex),
-            "#ff5500"
-        );
+        let inputs = AutoHashInputs::for_path(temp.path(), &user_config);
+
+        assert_eq!(inputs.hostname, hostname());
     }
 
     #[test]
-    fn test_rgb_format_as_rgb() {
-        let rgb = RGB {
-            r: 255,
-            g: 85,
-            b: 0,
+    fn test_auto_color_differs_per_hostname() {
+        let user_config = UserConfig::default();
+        let path = PathBuf::from("/home/user/project");
+        let on_host = |hostname: &str| {
+            auto_color(
+                &AutoHashInputs {
+                    path: path.clone(),
+                    hostname: Some(hostname.to_string()),
+                    package_name: None,
+                },
+                &user_config,
+            )
         };
-        assert_eq!(
-            rgb.format_as(crate::user_config::ColorFormat::Rgb),
-            "rgb(255, 85, 0)"
+
+        assert_eq!(on_host("laptop"), on_host("laptop"));
+        assert_ne!(on_host("laptop"), on_host("desktop"));
+        assert_ne!(
+            on_host("laptop"),
+            auto_color(
+                &AutoHashInputs {
+                    path: path.clone(),
+                    hostname: None,
+                    package_name: None,
+                },
+                &user_config
+            )
         );
     }
 
-    #[test]
-    fn test_rgb_format_as_hsl() {
-        let rgb = RGB {
-            r: 255,
-            g: 85,
-            b: 0,
-        };
-        let result = rgb.format_as(crate::user_config::ColorFormat::Hsl);
-        // HSL for #ff5500 is approximately hsl(20, 100%, 50%)
-        assert!(result.contains("hsl("));
-        assert!(result.contains("20"));
-        assert!(result.contains("100%"));
-        assert!(result.contains("50%"));
+    /// Create `real/project` and a symlink `alias` -> `real`, returning both entry points
+    /// to the project directory.
+    fn create_symlinked_project(temp: &TempDir) -> (PathBuf, PathBuf) {
+        let real = temp.path().join("real");
+        let project = real.join("project");
+        fs::create_dir_all(&project).unwrap();
+        let alias = temp.path().join("alias");
+        std::os::unix::fs::symlink(&real, &alias).unwrap();
+        (project, alias.join("project"))
     }
 
     #[test]
-    fn test_config_source_termtint() {
+    fn test_canonical_path_resolves_symlinks() {
         let temp = TempDir::new().unwrap();
-        let config_path = temp.path().join(".termtint");
-        File::create(&config_path).unwrap();
-
-        let user_config = UserConfig::default();
-        let result = find_config_source(temp.path(), &user_config);
+        let (project, aliased) = create_symlinked_project(&temp);
+        let canonical = project.canonicalize().unwrap();
 
-        assert_eq!(result, Some(ConfigSource::Termtint(config_path)));
+        assert_eq!(canonical_path(&aliased), canonical);
+        assert_eq!(
+            canonical_path(&aliased.join("..").join("project")),
+            canonical
+        );
+        // Files resolve through their directory, even if they don't exist yet
+        assert_eq!(
+            canonical_path(&aliased.join(".termtint")),
+            canonical.join(".termtint")
+        );
     }
 
     #[test]
-    fn test_config_source_trigger_file() {
+    fn test_trigger_file_color_stable_through_symlink() {
         let temp = TempDir::new().unwrap();
-        let trigger_path = temp.path().join("Cargo.toml");
-        File::create(&trigger_path).unwrap();
-
-        let mut user_config = UserConfig::default();
-        user_config.trigger_files = vec!["Cargo.toml".to_string()];
+        let (project, aliased) = create_symlinked_project(&temp);
+        File::create(project.join("Cargo.toml")).unwrap();
+        let user_config = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
 
-        let result = find_config_source(temp.path(), &user_config);
+        let direct = find_config_source(&project, &user_config).unwrap();
+        let via_alias = find_config_source(&aliased, &user_config).unwrap();
 
+        assert_eq!(direct, via_alias);
         assert_eq!(
-            result,
-            Some(ConfigSource::TriggerFile(
-                temp.path().to_string_lossy().to_string()
-            ))
+            parse_config_source(&direct, &user_config).unwrap(),
+            parse_config_source(&via_alias, &user_config).unwrap()
         );
     }
 
     #[test]
-    fn test_config_source_termtint_priority() {
-        // .termtint should take priority over trigger files
+    fn test_trigger_path_color_stable_through_symlink() {
         let temp = TempDir::new().unwrap();
-        let config_path = temp.path().join(".termtint");
-        File::create(&config_path).unwrap();
-        let trigger_path = temp.path().join("package.json");
-        File::create(&trigger_path).unwrap();
-
-        let mut user_config = UserConfig::default();
-        user_config.trigger_files = vec!["package.json".to_string()];
+        let (project, aliased) = create_symlinked_project(&temp);
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger::new(
+                    TriggerKind::Path,
+                    format!("{}/real/*", temp.path().display()),
+                ),
+                Trigger::new(
+                    TriggerKind::Path,
+                    format!("{}/alias/*", temp.path().display()),
+                ),
+            ],
+            ..UserConfig::default()
+        };
 
-        let result = find_config_source(temp.path(), &user_config);
+        let direct = find_config_source(&project, &user_config).unwrap();
+        let via_alias = find_config_source(&aliased, &user_config).unwrap();
 
-        assert_eq!(result, Some(ConfigSource::Termtint(config_path)));
+        assert_eq!(
+            parse_config_source(&direct, &user_config).unwrap(),
+            parse_config_source(&via_alias, &user_config).unwrap()
+        );
     }
 
     #[test]
-    fn test_config_source_multiple_trigger_files() {
+    fn test_auto_termtint_color_stable_through_symlink() {
         let temp = TempDir::new().unwrap();
-        let trigger1 = temp.path().join("Cargo.toml");
-        File::create(&trigger1).unwrap();
-        let trigger2 = temp.path().join("package.json");
-        File::create(&trigger2).unwrap();
-
-        let mut user_config = UserConfig::default();
-        user_config.trigger_files = vec![
-            "pyproject.toml".to_string(),
-            "Cargo.toml".to_string(),
-            "package.json".to_string(),
-        ];
+        let (project, aliased) = create_symlinked_project(&temp);
+        fs::write(project.join(".termtint"), "auto\n").unwrap();
+        let user_config = UserConfig::default();
 
-        let result = find_config_source(temp.path(), &user_config);
+        let direct = find_config_source(&project, &user_config).unwrap();
+        let via_alias = find_config_source(&aliased, &user_config).unwrap();
 
-        // Should match first trigger file in the list that exists
         assert_eq!(
-            result,
-            Some(ConfigSource::TriggerFile(
-                temp.path().to_string_lossy().to_string()
-            ))
+            parse_config_source(&direct, &user_config).unwrap(),
+            parse_config_source(&via_alias, &user_config).unwrap()
+        );
+        assert_eq!(
+            parse_config(&project.join(".termtint"), &user_config).unwrap(),
+            parse_config(&aliased.join(".termtint"), &user_config).unwrap()
         );
     }
 
     #[test]
-    fn test_config_source_parent_dir() {
+    fn test_depth_below_through_symlink() {
         let temp = TempDir::new().unwrap();
-        let trigger_path = temp.path().join("Cargo.toml");
-        File::create(&trigger_path).unwrap();
+        let (project, aliased) = create_symlinked_project(&temp);
+        let nested = project.join("src/bin");
+        fs::create_dir_all(&nested).unwrap();
 
-        let child_dir = temp.path().join("child");
-        fs::create_dir(&child_dir).unwrap();
+        assert_eq!(depth_below(&project, &aliased.join("src/bin")), 2);
+        assert_eq!(depth_below(&aliased, &nested), 2);
+    }
 
-        let mut user_config = UserConfig::default();
-        user_config.trigger_files = vec!["Cargo.toml".to_string()];
+    fn monochrome_config() -> UserConfig {
+        UserConfig {
+            monochrome: true,
+            ..UserConfig::default()
+        }
+    }
 
-        let result = find_config_source(&child_dir, &user_config);
+    fn is_gray(rgb: &RGB) -> bool {
+        rgb.r == rgb.g && rgb.g == rgb.b
+    }
 
-        assert_eq!(
-            result,
-            Some(ConfigSource::TriggerFile(
-                temp.path().to_string_lossy().to_string()
-            ))
-        );
+    #[test]
+    fn test_monochrome_auto_colors_are_gray() {
+        let user_config = monochrome_config();
+        let mut lightnesses = Vec::new();
+
+        for i in 0..20 {
+            let path = PathBuf::from(format!("/code/project-{}", i));
+            let config = parse_auto(&path, &user_config);
+            assert!(is_gray(&config.tab), "{} is not gray", config.tab);
+            assert!(
+                is_gray(&config.background),
+                "{} is not gray",
+                config.background
+            );
+            lightnesses.push(config.tab.r);
+        }
+
+        // Directories remain distinguishable by shade
+        lightnesses.sort();
+        lightnesses.dedup();
+        assert!(lightnesses.len() > 10);
     }
 
     #[test]
-    fn test_config_source_none() {
-        let temp = TempDir::new().unwrap();
-        let child_dir = temp.path().join("child");
-        fs::create_dir(&child_dir).unwrap();
+    fn test_monochrome_respects_lightness_range() {
+        let user_config = UserConfig {
+            monochrome_lightness_min: 0.4,
+            monochrome_lightness_max: 0.6,
+            ..monochrome_config()
+        };
 
-        let mut user_config = UserConfig::default();
-        user_config.trigger_files = vec!["Cargo.toml".to_string()];
+        assert_eq!(monochrome_tab_color(0.0, &user_config).r, 102);
+        assert_eq!(monochrome_tab_color(1.0, &user_config).r, 153);
+        for hash in [0u64, 0x1234, 0xFFFF, u64::MAX] {
+            let tab = color_from_hash(hash, &user_config).tab;
+            assert!((102..=153).contains(&tab.r), "Got {}", tab);
+        }
+    }
 
-        let result = find_config_source(&child_dir, &user_config);
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_monochrome_reroll_is_gray() {
+        use rand::SeedableRng;
+        let user_config = monochrome_config();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let current = monochrome_tab_color(0.5, &user_config);
 
-        assert_eq!(result, None);
+        let reroll = reroll_color_with_rng(&mut rng, &user_config, &[], Some(&current), 0.05);
+
+        assert!(is_gray(&reroll.color));
+        assert!(is_gray(&generate_random_color(&user_config)));
+    }
+
+    #[cfg(feature = "colors")]
+    fn simulated(hex: &str, deficiency: ColorVisionDeficiency) -> String {
+        parse_color(hex)
+            .unwrap()
+            .simulate_cvd(deficiency)
+            .to_string()
     }
 
+    #[cfg(feature = "colors")]
     #[test]
-    fn test_config_source_empty_trigger_list() {
-        let temp = TempDir::new().unwrap();
-        let trigger_path = temp.path().join("Cargo.toml");
-        File::create(&trigger_path).unwrap();
+    fn test_simulate_cvd_reference_values() {
+        // Expected values from the Machado et al. (2009) severity 1.0 matrices
+        use ColorVisionDeficiency::*;
+        assert_eq!(simulated("#ff0000", Protanopia), "#6d5f00");
+        assert_eq!(simulated("#0000ff", Protanopia), "#0059ff");
+        assert_eq!(simulated("#ff0000", Deuteranopia), "#a39000");
+        assert_eq!(simulated("#00ff00", Deuteranopia), "#efd63a");
+        assert_eq!(simulated("#3478f6", Deuteranopia), "#0076f4");
+        assert_eq!(simulated("#00ff00", Tritanopia), "#00f7d9");
+        assert_eq!(simulated("#0000ff", Tritanopia), "#006b96");
+    }
 
-        let user_config = UserConfig::default(); // empty trigger_files
+    #[cfg(feature = "colors")]
+    #[test]
+    fn test_simulate_cvd_preserves_neutrals() {
+        for deficiency in [
+            ColorVisionDeficiency::Protanopia,
+            ColorVisionDeficiency::Deuteranopia,
+            ColorVisionDeficiency::Tritanopia,
+        ] {
+            for hex in ["#000000", "#808080", "#ffffff"] {
+                assert_eq!(simulated(hex, deficiency), hex, "{:?}", deficiency);
+            }
+        }
+    }
 
-        let result = find_config_source(temp.path(), &user_config);
+    #[cfg(feature = "colors")]
+    #[test]
+    fn test_color_vision_deficiency_parse() {
+        assert_eq!(
+            ColorVisionDeficiency::parse("Deuteranopia"),
+            Some(ColorVisionDeficiency::Deuteranopia)
+        );
+        assert_eq!(ColorVisionDeficiency::parse("colorblind"), None);
+        for deficiency in [
+            ColorVisionDeficiency::Protanopia,
+            ColorVisionDeficiency::Deuteranopia,
+            ColorVisionDeficiency::Tritanopia,
+        ] {
+            assert_eq!(
+                ColorVisionDeficiency::parse(deficiency.name()),
+                Some(deficiency)
+            );
+        }
+    }
 
-        // Should find nothing since trigger_files is empty
-        assert_eq!(result, None);
+    fn banded_config() -> UserConfig {
+        UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::File, "package.json"),
+                Trigger {
+                    hue_min: Some(15.0),
+                    hue_max: Some(45.0),
+                    ..Trigger::new(TriggerKind::File, "Cargo.toml")
+                },
+            ],
+            ..UserConfig::default()
+        }
     }
 
     #[test]
-    fn test_parse_config_source_termtint() {
-        let user_config = UserConfig::default();
+    fn test_trigger_band_constrains_hue() {
         let temp = TempDir::new().unwrap();
-        let config_path = temp.path().join(".termtint");
-        fs::write(&config_path, "#ff5500").unwrap();
+        let user_config = banded_config();
+
+        for i in 0..20 {
+            let dir = temp.path().join(format!("crate-{}", i));
+            fs::create_dir_all(&dir).unwrap();
+            File::create(dir.join("Cargo.toml")).unwrap();
+
+            let source = find_config_source(&dir, &user_config).unwrap();
+            let tab = parse_config_source(&source, &user_config).unwrap().tab;
+            let hue = csscolorparser::Color::new(
+                tab.r as f32 / 255.0,
+                tab.g as f32 / 255.0,
+                tab.b as f32 / 255.0,
+                1.0,
+            )
+            .to_hsla()[0];
+            assert!(
+                (14.0..=46.0).contains(&hue),
+                "Hue {} of {} outside the Cargo.toml band",
+                hue,
+                dir.display()
+            );
+        }
+    }
 
-        let source = ConfigSource::Termtint(config_path);
-        let config = parse_config_source(&source, &user_config).unwrap();
+    #[test]
+    fn test_plain_trigger_keeps_full_range() {
+        let temp = TempDir::new().unwrap();
+        File::create(temp.path().join("package.json")).unwrap();
+        let user_config = banded_config();
 
+        let source = find_config_source(temp.path(), &user_config).unwrap();
+
+        assert!(source_trigger(&source, &user_config).is_none_or(|t| !t.has_hue_band()));
         assert_eq!(
-            config.tab,
-            RGB {
-                r: 255,
-                g: 85,
-                b: 0
-            }
+            parse_config_source(&source, &user_config).unwrap(),
+            parse_auto(temp.path(), &user_config)
         );
-        // Background uses fixed lightness (0.18 by default)
-        assert_eq!(config.background, RGB { r: 77, g: 0, b: 0 });
     }
 
     #[test]
-    fn test_parse_config_source_trigger_file() {
-        let user_config = UserConfig::default();
+    fn test_trigger_color_replaces_auto_color() {
         let temp = TempDir::new().unwrap();
+        let work = temp.path().join("work");
+        let project = work.join("client");
+        fs::create_dir_all(&project).unwrap();
+        let user_config = UserConfig {
+            triggers: vec![Trigger {
+                color: Some("#cc0000".to_string()),
+                ..Trigger::new(TriggerKind::Path, format!("{}/*", work.display()))
+            }],
+            blend_with_parent: 0.5,
+            ..UserConfig::default()
+        };
+        fs::write(temp.path().join(".termtint"), "#0000cc").unwrap();
 
-        let source = ConfigSource::TriggerFile(temp.path().to_string_lossy().to_string());
-        let config = parse_config_source(&source, &user_config).unwrap();
+        let source = find_config_source(&project, &user_config).unwrap();
 
-        // Should generate auto color based on directory path
-        // Verify it's a valid color with at least one bright channel
-        let max_channel = config.tab.r.max(config.tab.g).max(config.tab.b);
-        assert!(max_channel >= 128, "Generated color should be vibrant");
+        assert_eq!(
+            source_trigger(&source, &user_config).and_then(|t| t.color.as_deref()),
+            Some("#cc0000")
+        );
+        assert!(parent_blend(&source, &user_config).is_none());
+        assert_eq!(
+            parse_config_source(&source, &user_config).unwrap(),
+            parse_simple_color("#cc0000", &user_config).unwrap()
+        );
     }
 
     #[test]
-    fn test_parse_config_source_trigger_file_deterministic() {
-        let user_config = UserConfig::default();
+    fn test_hue_band_applies_to_paths_and_dirnames() {
         let temp = TempDir::new().unwrap();
+        let code = temp.path().join("code");
+        let release = temp.path().join("release-1");
+        fs::create_dir_all(code.join("app")).unwrap();
+        fs::create_dir_all(&release).unwrap();
+        let path_pattern = format!("{}/*", code.display());
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger {
+                    hue_min: Some(100.0),
+                    hue_max: Some(140.0),
+                    ..Trigger::new(TriggerKind::Path, path_pattern.as_str())
+                },
+                Trigger {
+                    hue_min: Some(200.0),
+                    hue_max: Some(240.0),
+                    ..Trigger::new(TriggerKind::Dirname, "release-*")
+                },
+            ],
+            ..UserConfig::default()
+        };
 
-        let source = ConfigSource::TriggerFile(temp.path().to_string_lossy().to_string());
-        let config1 = parse_config_source(&source, &user_config).unwrap();
-        let config2 = parse_config_source(&source, &user_config).unwrap();
+        let path_source = find_config_source(&code.join("app"), &user_config).unwrap();
+        let band = source_trigger(&path_source, &user_config).unwrap();
+        assert!(band.has_hue_band());
+        assert_eq!(band.pattern, path_pattern);
 
-        // Should generate the same color for the same directory
-        assert_eq!(config1.tab, config2.tab);
-        assert_eq!(config1.background, config2.background);
+        let dirname_source = find_config_source(&release, &user_config).unwrap();
+        let band = source_trigger(&dirname_source, &user_config).unwrap();
+        assert!(band.has_hue_band());
+        assert_eq!(band.pattern, "release-*");
+        assert_eq!(
+            parse_config_source(&dirname_source, &user_config).unwrap(),
+            parse_auto(&release, &user_config.with_hue_band(band))
+        );
     }
 
     #[test]
-    fn test_parse_config_source_trigger_file_different_dirs() {
-        let user_config = UserConfig::default();
-        let temp1 = TempDir::new().unwrap();
-        let temp2 = TempDir::new().unwrap();
+    fn test_trigger_background_params_precedence() {
+        let temp = TempDir::new().unwrap();
+        let go = temp.path().join("go");
+        let rust = temp.path().join("rust");
+        let pinned = temp.path().join("pinned");
+        for dir in [&go, &rust, &pinned] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        File::create(go.join("go.mod")).unwrap();
+        File::create(rust.join("Cargo.toml")).unwrap();
+        File::create(pinned.join("go.mod")).unwrap();
+        fs::write(
+            pinned.join(".termtint"),
+            "tab = \"#2266cc\"\nbackground = \"#101020\"\n",
+        )
+        .unwrap();
+        let user_config = UserConfig {
+            triggers: vec![
+                Trigger {
+                    background_lightness: Some(0.26),
+                    background_saturation: Some(0.5),
+                    ..Trigger::new(TriggerKind::File, "go.mod")
+                },
+                Trigger::new(TriggerKind::File, "Cargo.toml"),
+            ],
+            ..UserConfig::default()
+        };
+        let go_params = user_config.with_background_params(&user_config.triggers[0]);
+
+        // Per-trigger parameters replace the global ones
+        let go_source = find_config_source(&go, &user_config).unwrap();
+        assert_eq!(
+            trigger_background_params(&go_source, &user_config).map(|t| t.pattern.as_str()),
+            Some("go.mod")
+        );
+        let go_color = parse_config_source(&go_source, &user_config).unwrap();
+        assert_eq!(
+            go_color.background,
+            derive_background(&go_color.tab, &go_params)
+        );
+        assert_ne!(
+            go_color.background,
+            derive_background(&go_color.tab, &user_config)
+        );
 
-        let source1 = ConfigSource::TriggerFile(temp1.path().to_string_lossy().to_string());
-        let source2 = ConfigSource::TriggerFile(temp2.path().to_string_lossy().to_string());
+        // Triggers without them use the global values
+        let rust_source = find_config_source(&rust, &user_config).unwrap();
+        assert!(trigger_background_params(&rust_source, &user_config).is_none());
+        let rust_color = parse_config_source(&rust_source, &user_config).unwrap();
+        assert_eq!(
+            rust_color.background,
+            derive_background(&rust_color.tab, &user_config)
+        );
 
-        let config1 = parse_config_source(&source1, &user_config).unwrap();
-        let config2 = parse_config_source(&source2, &user_config).unwrap();
+        // A .termtint TOML background beats the trigger in its directory
+        let pinned_source = find_config_source(&pinned, &user_config).unwrap();
+        assert!(trigger_background_params(&pinned_source, &user_config).is_none());
+        assert_eq!(
+            parse_config_source(&pinned_source, &user_config)
+                .unwrap()
+                .background,
+            parse_color("#101020").unwrap()
+        );
+    }
 
-        // Different directories should generate different colors
-        assert_ne!(config1.tab, config2.tab);
+    fn package_name_config() -> UserConfig {
+        UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            auto_hash_source: AutoHashSource::PackageName,
+            ..UserConfig::default()
+        }
     }
 
     #[test]
-    fn test_parse_config_source_uses_user_config() {
-        let mut user_config = UserConfig::default();
-        user_config.background_lightness = 0.20;
-
+    fn test_package_name_hash_survives_move() {
         let temp = TempDir::new().unwrap();
-        let config_path = temp.path().join(".termtint");
-        fs::write(&config_path, "#ff5500").unwrap();
+        let user_config = package_name_config();
+        let checkout_a = temp.path().join("a");
+        let checkout_b = temp.path().join("elsewhere/b");
+        for dir in [&checkout_a, &checkout_b] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("Cargo.toml"), "[package]\nname = \"widget\"\n").unwrap();
+        }
 
-        let source = ConfigSource::Termtint(config_path);
-        let config = parse_config_source(&source, &user_config).unwrap();
+        let source_a = find_config_source(&checkout_a, &user_config).unwrap();
+        let source_b = find_config_source(&checkout_b, &user_config).unwrap();
+        let inputs = AutoHashInputs::for_source(&source_a, &user_config);
 
-        // Should use custom background lightness value
+        assert_eq!(inputs.package_name.as_deref(), Some("widget"));
         assert_eq!(
-            config.tab,
-            RGB {
-                r: 255,
-                g: 85,
-                b: 0
-            }
+            parse_config_source(&source_a, &user_config).unwrap(),
+            parse_config_source(&source_b, &user_config).unwrap()
         );
-        // Background should use fixed lightness of 0.20
-        assert_eq!(config.background, RGB { r: 84, g: 0, b: 0 });
     }
 
     #[test]
-    fn test_generate_random_color() {
-        let user_config = UserConfig::default();
-        let color = generate_random_color(&user_config);
+    fn test_package_name_hash_falls_back_to_path() {
+        let temp = TempDir::new().unwrap();
+        let user_config = package_name_config();
+        fs::write(temp.path().join("Cargo.toml"), "[workspace]\n").unwrap();
 
-        // Convert back to HSL to verify constraints
-        let color_str = format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b);
-        let parsed_color = csscolorparser::parse(&color_str).unwrap();
-        let [_hue, saturation, lightness, _alpha] = parsed_color.to_hsla();
+        let source = find_config_source(temp.path(), &user_config).unwrap();
+        let inputs = AutoHashInputs::for_source(&source, &user_config);
 
-        // Verify saturation is within configured range (with tolerance for conversion)
-        assert!(
-            saturation >= user_config.saturation_min - 0.05,
-            "Saturation {} should be >= {}",
-            saturation,
-            user_config.saturation_min
-        );
-        assert!(
-            saturation <= user_config.saturation_max + 0.05,
-            "Saturation {} should be <= {}",
-            saturation,
-            user_config.saturation_max
+        assert_eq!(inputs.package_name, None);
+        assert_eq!(
+            parse_config_source(&source, &user_config).unwrap(),
+            parse_auto(temp.path(), &user_config)
         );
+    }
 
-        // Verify lightness matches configured value (with tolerance)
-        assert!(
-            (lightness - user_config.lightness).abs() < 0.02,
-            "Lightness {} should be approximately {}",
-            lightness,
-            user_config.lightness
+    #[test]
+    fn test_package_name_ignored_with_path_source() {
+        let temp = TempDir::new().unwrap();
+        let user_config = UserConfig {
+            auto_hash_source: AutoHashSource::Path,
+            ..package_name_config()
+        };
+        fs::write(
+            temp.path().join("Cargo.toml"),
+            "[package]\nname = \"widget\"\n",
+        )
+        .unwrap();
+
+        let source = find_config_source(temp.path(), &user_config).unwrap();
+        assert_eq!(
+            AutoHashInputs::for_source(&source, &user_config).package_name,
+            None
         );
     }
 
     #[test]
-    fn test_generate_random_color_produces_different_colors() {
+    fn test_shift_temperature_zero_is_noop() {
         let user_config = UserConfig::default();
-
-        // Generate multiple random colors
-        let colors: Vec<RGB> = (0..10)
-            .map(|_| generate_random_color(&user_config))
-            .collect();
-
-        // At least some should be different (highly unlikely all 10 are the same)
-        let first_color = colors[0];
-        let has_different_color = colors.iter().any(|c| *c != first_color);
-        assert!(
-            has_different_color,
-            "Should generate different random colors, but all were {:?}",
-            first_color
-        );
+        for hue in (0..360).step_by(15) {
+            let tab = hsl_to_tab_color(hue as f32, 0.8, &user_config);
+            let colors = ColorConfig {
+                tab,
+                background: derive_background(&tab, &user_config),
+            };
+
+            assert_eq!(tab.shift_temperature(0.0), tab);
+            assert_eq!(apply_temperature(&colors, 0.0), colors);
+        }
     }
 
     #[test]
-    fn test_generate_random_color_respects_custom_ranges() {
-        let mut user_config = UserConfig::default();
-        user_config.hue_min = 120.0; // Green range
-        user_config.hue_max = 180.0; // Cyan range
-        user_config.saturation_min = 0.8;
-        user_config.saturation_max = 0.9;
-        user_config.lightness = 0.6;
+    fn test_shift_temperature_moves_oklab_b() {
+        let blue = RGB {
+            r: 0x34,
+            g: 0x78,
+            b: 0xf6,
+        };
+        let b_of = |rgb: RGB| {
+            srgb_to_oklab(Rgb {
+                r: rgb.r,
+                g: rgb.g,
+                b: rgb.b,
+            })
+            .b
+        };
 
-        let color = generate_random_color(&user_config);
+        assert!(b_of(blue.shift_temperature(0.5)) > b_of(blue));
+        assert!(b_of(blue.shift_temperature(-0.5)) < b_of(blue));
+        assert!(b_of(blue.shift_temperature(1.0)) > b_of(blue.shift_temperature(0.5)));
+    }
 
-        // Convert back to HSL to verify
-        let color_str = format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b);
-        let parsed_color = csscolorparser::parse(&color_str).unwrap();
-        let [hue, saturation, lightness, _alpha] = parsed_color.to_hsla();
+    #[test]
+    fn test_shift_temperature_clamps_range() {
+        let gray = RGB {
+            r: 128,
+            g: 128,
+            b: 128,
+        };
 
-        // Verify hue is within configured range (with some tolerance for conversion)
-        assert!(
-            hue >= user_config.hue_min - 5.0 && hue <= user_config.hue_max + 5.0,
-            "Hue {} should be within {} to {}",
-            hue,
-            user_config.hue_min,
-            user_config.hue_max
-        );
+        assert_eq!(gray.shift_temperature(5.0), gray.shift_temperature(1.0));
+        assert_eq!(gray.shift_temperature(-5.0), gray.shift_temperature(-1.0));
+    }
 
-        // Verify saturation is within configured range
-        assert!(
-            saturation >= user_config.saturation_min - 0.05,
-            "Saturation {} should be >= {}",
-            saturation,
-            user_config.saturation_min
+    #[test]
+    fn test_config_source_root_dir() {
+        assert_eq!(
+            ConfigSource::Termtint(PathBuf::from("/code/app/.termtint")).root_dir(),
+            PathBuf::from("/code/app")
         );
-
-        // Verify lightness matches configured value
-        assert!(
-            (lightness - user_config.lightness).abs() < 0.02,
-            "Lightness {} should be approximately {}",
-            lightness,
-            user_config.lightness
+        assert_eq!(
+            ConfigSource::TriggerFile(PathBuf::from("/code/lib")).root_dir(),
+            PathBuf::from("/code/lib")
         );
     }
 
     #[test]
-    fn test_matches_path_glob_basic() {
-        let temp = TempDir::new().unwrap();
-        let project_dir = temp.path().join("project");
-        fs::create_dir(&project_dir).unwrap();
-
-        let pattern = format!("{}/*", temp.path().to_string_lossy());
-        let patterns = vec![pattern];
+    fn test_depth_below() {
+        let root = Path::new("/code/app");
 
-        assert!(matches_path_glob(&project_dir, &patterns));
+        assert_eq!(depth_below(root, Path::new("/code/app")), 0);
+        assert_eq!(depth_below(root, Path::new("/code/app/src")), 1);
+        assert_eq!(depth_below(root, Path::new("/code/app/src/bin/tool")), 3);
+        assert_eq!(depth_below(root, Path::new("/elsewhere")), 0);
     }
 
     #[test]
-    fn test_matches_path_glob_no_match() {
-        let temp = TempDir::new().unwrap();
-        let project_dir = temp.path().join("project");
-        fs::create_dir(&project_dir).unwrap();
+    fn test_depth_ramp_darkens_background_per_level() {
+        let user_config = UserConfig::default();
+        let tab = hsl_to_tab_color(200.0, 0.8, &user_config);
+        let colors = ColorConfig {
+            tab,
+            background: derive_background(&tab, &user_config),
+        };
+        let lightness = |rgb: RGB| {
+            srgb_to_oklab(Rgb {
+                r: rgb.r,
+                g: rgb.g,
+                b: rgb.b,
+            })
+            .l
+        };
 
-        let patterns = vec!["/some/other/path/*".to_string()];
+        assert_eq!(apply_depth_ramp(&colors, 0, 0.02), colors);
+        assert_eq!(apply_depth_ramp(&colors, 3, 0.0), colors);
 
-        assert!(!matches_path_glob(&project_dir, &patterns));
+        let one = apply_depth_ramp(&colors, 1, 0.02);
+        let two = apply_depth_ramp(&colors, 2, 0.02);
+        assert_eq!(one.tab, colors.tab);
+        assert!(lightness(one.background) < lightness(colors.background));
+        assert!(lightness(two.background) < lightness(one.background));
+
+        // Very deep directories clamp at zero lightness instead of wrapping
+        let deep = apply_depth_ramp(&colors, 100, 0.02);
+        assert!(lightness(deep.background) < 0.1);
     }
 
     #[test]
-    fn test_matches_path_glob_tilde_expansion() {
-        let temp = TempDir::new().unwrap();
-        std::env::set_var("HOME", temp.path());
+    fn test_to_ansi256_exact_cube_and_gray_colors() {
+        assert_eq!(RGB { r: 0, g: 0, b: 0 }.to_ansi256(), 16);
+        assert_eq!(
+            RGB {
+                r: 255,
+                g: 255,
+                b: 255
+            }
+            .to_ansi256(),
+            231
+        );
+        assert_eq!(RGB { r: 255, g: 0, b: 0 }.to_ansi256(), 196);
+        assert_eq!(
+            RGB {
+                r: 95,
+                g: 135,
+                b: 175
+            }
+            .to_ansi256(),
+            67
+        );
+        assert_eq!(
+            RGB {
+                r: 128,
+                g: 128,
+                b: 128
+            }
+            .to_ansi256(),
+            244
+        );
+    }
 
-        let project_dir = temp.path().join("Code").join("project");
-        fs::create_dir_all(&project_dir).unwrap();
+    #[test]
+    fn test_ansi256_round_trips_palette_colors() {
+        for index in 16..=255u8 {
+            let rgb = RGB::from_ansi256(index);
+            assert_eq!(
+                rgb.quantize_256(),
+                rgb,
+                "Index {} did not round-trip",
+                index
+            );
+        }
+    }
 
-        let patterns = vec!["~/Code/*".to_string()];
+    #[test]
+    fn test_quantize_256_picks_nearest_palette_color() {
+        let distance = |a: RGB, b: RGB| {
+            let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2);
+            d(a.r, b.r) + d(a.g, b.g) + d(a.b, b.b)
+        };
+        let user_config = UserConfig::default();
 
-        assert!(matches_path_glob(&project_dir, &patterns));
+        for hue in (0..360).step_by(20) {
+            let tab = hsl_to_tab_color(hue as f32, 0.8, &user_config);
+            let background = derive_background(&tab, &user_config);
+            for color in [tab, background] {
+                let best = (16..=255u8)
+                    .map(|i| distance(color, RGB::from_ansi256(i)))
+                    .min()
+                    .unwrap();
+                assert_eq!(distance(color, color.quantize_256()), best);
+            }
+        }
     }
 
     #[test]
-    fn test_matches_path_glob_empty_patterns() {
-        let temp = TempDir::new().unwrap();
-        let patterns: Vec<String> = vec![];
-
-        assert!(!matches_path_glob(temp.path(), &patterns));
+    fn test_ansi_background() {
+        let color = RGB {
+            r: 255,
+            g: 85,
+            b: 0,
+        };
+        assert_eq!(color.ansi_background(true), "\x1b[48;2;255;85;0m");
+        assert_eq!(color.ansi_background(false), "\x1b[48;5;202m");
     }
 
     #[test]
-    fn test_config_source_trigger_path() {
-        let temp = TempDir::new().unwrap();
-        let project_dir = temp.path().join("project");
-        fs::create_dir(&project_dir).unwrap();
+    fn test_contrast_ratio_extremes() {
+        let white = RGB {
+            r: 255,
+            g: 255,
+            b: 255,
+        };
+        let black = RGB { r: 0, g: 0, b: 0 };
 
-        let pattern = format!("{}/*", temp.path().to_string_lossy());
-        let mut user_config = UserConfig::default();
-        user_config.trigger_paths = vec![pattern];
+        assert!((contrast_ratio(&white, &black) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio(&black, &white) - 21.0).abs() < 0.01);
+        assert_eq!(contrast_ratio(&white, &white), 1.0);
+    }
 
-        let result = find_config_source(&project_dir, &user_config);
+    #[test]
+    fn test_suggest_foreground_switches_around_mid_gray() {
+        let gray = |v: u8| RGB { r: v, g: v, b: v };
 
-        assert_eq!(
-            result,
-            Some(ConfigSource::TriggerPath(
-                project_dir.to_string_lossy().to_string()
-            ))
+        // White and black contrast equally at a luminance of ~0.179 (sRGB ~118)
+        let (fg, _) = suggest_foreground(&gray(110));
+        assert!(
+            relative_luminance(&fg) > 0.5,
+            "Expected light text on #6e6e6e"
+        );
+        let (fg, _) = suggest_foreground(&gray(125));
+        assert!(
+            relative_luminance(&fg) < 0.1,
+            "Expected dark text on #7d7d7d"
         );
     }
 
     #[test]
-    fn test_config_source_trigger_path_priority_over_trigger_files() {
-        // Trigger path should take priority over trigger files
-        let temp = TempDir::new().unwrap();
-        let project_dir = temp.path().join("project");
-        fs::create_dir(&project_dir).unwrap();
-        let trigger_path = project_dir.join("Cargo.toml");
-        File::create(&trigger_path).unwrap();
-
-        let pattern = format!("{}/*", temp.path().to_string_lossy());
-        let mut user_config = UserConfig::default();
-        user_config.trigger_paths = vec![pattern];
-        user_config.trigger_files = vec!["Cargo.toml".to_string()];
-
-        let result = find_config_source(&project_dir, &user_config);
+    fn test_suggest_foreground_falls_back_to_plain_near_threshold() {
+        // Near mid-gray the tinted variants lose too much contrast to meet AA
+        let background = RGB {
+            r: 118,
+            g: 118,
+            b: 118,
+        };
+        let (fg, ratio) = suggest_foreground(&background);
+        let options = foreground_options(&background);
 
-        assert_eq!(
-            result,
-            Some(ConfigSource::TriggerPath(
-                project_dir.to_string_lossy().to_string()
-            ))
-        );
+        assert!(fg == options[0].1 || fg == options[1].1);
+        assert!(ratio >= options[0].2.min(options[1].2));
     }
 
     #[test]
-    fn test_config_source_termtint_priority_over_trigger_path() {
-        // .termtint should take priority over trigger path
-        let temp = TempDir::new().unwrap();
-        let project_dir = temp.path().join("project");
-        fs::create_dir(&project_dir).unwrap();
-        let config_path = project_dir.join(".termtint");
-        File::create(&config_path).unwrap();
+    fn test_suggest_foreground_prefers_tint_on_dark_background() {
+        let user_config = UserConfig::default();
+        let tab = hsl_to_tab_color(200.0, 0.8, &user_config);
+        let background = derive_background(&tab, &user_config);
+        let (fg, ratio) = suggest_foreground(&background);
 
-        let pattern = format!("{}/*", temp.path().to_string_lossy());
-        let mut user_config = UserConfig::default();
-        user_config.trigger_paths = vec![pattern];
+        assert_eq!(fg, foreground_options(&background)[2].1);
+        assert!(ratio >= WCAG_AA_CONTRAST);
+    }
 
-        let result = find_config_source(&project_dir, &user_config);
+    fn reserved_config() -> UserConfig {
+        UserConfig {
+            reserved_colors: vec![
+                parse_color("#ff0000").unwrap(),
+                parse_color("#ffcc00").unwrap(),
+            ],
+            reserved_min_delta: 0.15,
+            ..UserConfig::default()
+        }
+    }
 
-        assert_eq!(result, Some(ConfigSource::Termtint(config_path)));
+    fn min_reserved_delta(tab: &RGB, user_config: &UserConfig) -> f32 {
+        user_config
+            .reserved_colors
+            .iter()
+            .map(|reserved| tab.delta_e(reserved))
+            .fold(f32::MAX, f32::min)
     }
 
     #[test]
-    fn test_config_source_trigger_path_walks_up_tree() {
-        // Subdirectory should inherit trigger path from parent
-        let temp = TempDir::new().unwrap();
-        let project_dir = temp.path().join("project");
-        let sub_dir = project_dir.join("src");
-        fs::create_dir_all(&sub_dir).unwrap();
+    fn test_auto_colors_avoid_reserved_colors() {
+        let user_config = reserved_config();
 
-        let pattern = format!("{}/*", temp.path().to_string_lossy());
-        let mut user_config = UserConfig::default();
-        user_config.trigger_paths = vec![pattern];
+        for i in 0..500 {
+            let path = PathBuf::from(format!("/home/user/Code/project-{}", i));
+            let tab = parse_auto(&path, &user_config).tab;
+            assert!(
+                min_reserved_delta(&tab, &user_config) > 0.15,
+                "{} generated {} too close to a reserved color",
+                path.display(),
+                tab
+            );
+        }
+    }
 
-        let result = find_config_source(&sub_dir, &user_config);
+    #[test]
+    fn test_reserved_colors_keep_auto_colors_deterministic() {
+        let user_config = reserved_config();
+        let path = PathBuf::from("/home/user/Code/project");
 
-        // Should find parent directory (project_dir) matching the glob
         assert_eq!(
-            result,
-            Some(ConfigSource::TriggerPath(
-                project_dir.to_string_lossy().to_string()
-            ))
+            parse_auto(&path, &user_config),
+            parse_auto(&path, &user_config)
         );
     }
 
     #[test]
-    fn test_parse_config_source_trigger_path() {
-        let user_config = UserConfig::default();
-        let temp = TempDir::new().unwrap();
-
-        let source = ConfigSource::TriggerPath(temp.path().to_string_lossy().to_string());
-        let config = parse_config_source(&source, &user_config).unwrap();
+    fn test_reserved_colors_leave_distant_colors_unchanged() {
+        let user_config = reserved_config();
+        let defaults = UserConfig::default();
+
+        // A blue far from red and yellow is not nudged
+        assert_eq!(avoid_reserved(220.0, 0.8, &user_config), 220.0);
+        assert_eq!(avoid_reserved(0.0, 0.8, &defaults), 0.0);
+        assert_ne!(avoid_reserved(0.0, 0.8, &user_config), 0.0);
+    }
 
-        // Should generate auto color based on directory path
-        let max_channel = config.tab.r.max(config.tab.g).max(config.tab.b);
-        assert!(max_channel >= 128, "Generated color should be vibrant");
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_random_colors_avoid_reserved_colors() {
+        use rand::SeedableRng;
+
+        let user_config = reserved_config();
+        assert!(min_reserved_delta(&generate_random_color(&user_config), &user_config) > 0.15);
+        for seed in 0..200 {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            let (tab, _) = generate_spread_color_with_rng(&mut rng, &user_config, &[]);
+            assert!(min_reserved_delta(&tab, &user_config) > 0.15);
+        }
     }
 
+    #[cfg(feature = "itermcolors")]
     #[test]
-    fn test_parse_config_source_trigger_path_deterministic() {
-        let user_config = UserConfig::default();
+    fn test_toml_preset() {
+        use crate::itermcolors::preset_xml;
+
         let temp = TempDir::new().unwrap();
+        let project = temp.path().join("app");
+        fs::create_dir_all(temp.path().join("themes")).unwrap();
+        fs::create_dir_all(&project).unwrap();
+        let background = RGB {
+            r: 0x00,
+            g: 0x2b,
+            b: 0x36,
+        };
+        let blue = RGB {
+            r: 0x26,
+            g: 0x8b,
+            b: 0xd2,
+        };
+        fs::write(
+            temp.path().join("themes").join("solarized.itermcolors"),
+            preset_xml(&[("Background Color", background), ("Ansi 4 Color", blue)]),
+        )
+        .unwrap();
+        let user_config = UserConfig {
+            home: Some(temp.path().to_path_buf()),
+            ..UserConfig::default()
+        };
+        let path = project.join(".termtint");
+        let parse = |content: &str| parse_toml(&path, content, &user_config);
 
-        let source = ConfigSource::TriggerPath(temp.path().to_string_lossy().to_string());
-        let config1 = parse_config_source(&source, &user_config).unwrap();
-        let config2 = parse_config_source(&source, &user_config).unwrap();
+        // A tab color from a named entry, via ~/ or a path relative to the project
+        let colors =
+            parse("preset = \"~/themes/solarized.itermcolors\"\npreset_tab = \"Ansi 4 Color\"")
+                .unwrap();
+        assert_eq!(
+            colors,
+            ColorConfig {
+                tab: blue,
+                background
+            }
+        );
+        assert_eq!(
+            parse("preset = \"../themes/solarized.itermcolors\"\npreset_tab = \"Ansi 4 Color\""),
+            Ok(colors)
+        );
 
-        // Should generate the same color for the same directory
-        assert_eq!(config1.tab, config2.tab);
-        assert_eq!(config1.background, config2.background);
+        // Explicit keys win over the preset
+        let colors = parse(
+            "preset = \"~/themes/solarized.itermcolors\"\ntab = \"#ff0000\"\nbackground = \"#111111\"",
+        )
+        .unwrap();
+        assert_eq!(
+            colors.tab,
+            RGB {
+                r: 0xff,
+                g: 0,
+                b: 0
+            }
+        );
+        assert_eq!(
+            colors.background,
+            RGB {
+                r: 0x11,
+                g: 0x11,
+                b: 0x11
+            }
+        );
+
+        // Missing entries and files name what's missing
+        let error = parse("preset = \"~/themes/solarized.itermcolors\"")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("has no 'Tab Color' entry; set 'tab' or 'preset_tab'"));
+        let error =
+            parse("preset = \"~/themes/solarized.itermcolors\"\npreset_tab = \"Ansi 9 Color\"")
+                .unwrap_err()
+                .to_string();
+        assert!(error.contains("has no 'Ansi 9 Color' entry"));
+        let error = parse("preset = \"~/themes/missing.itermcolors\"\ntab = \"red\"").unwrap_err();
+        assert!(error.is_not_found());
+        assert!(error.to_string().starts_with("Failed to read preset"));
     }
 }
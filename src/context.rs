@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use crate::error::TermtintError;
+
+/// Everything termtint reads from its process: the home and working directories,
+/// the environment, and the clock.
+///
+/// `main` resolves one per invocation and hands it to the commands, so nothing
+/// else calls `std::env` or `SystemTime::now`. Tests build their own pointing at
+/// temporary directories instead of mutating process-global state.
+#[derive(Debug)]
+pub struct Context {
+    /// `$HOME`, if set
+    pub home: Option<PathBuf>,
+    /// The working directory, or why it couldn't be read
+    pub cwd: Result<PathBuf, TermtintError>,
+    /// The shell's working directory when another process has removed it; `cwd` is
+    /// then its nearest remaining ancestor
+    pub removed_cwd: Option<PathBuf>,
+    pub env: HashMap<OsString, OsString>,
+    /// The user config file given with `--config`, which overrides `TERMTINT_CONFIG`
+    pub config_file: Option<PathBuf>,
+    /// When the invocation started
+    pub now: SystemTime,
+    /// The state directory, resolved on first use by `state::state_root`
+    pub(crate) state_root: OnceLock<PathBuf>,
+    /// Whether the state directory is writable, checked on first use
+    pub(crate) state_writable: OnceLock<bool>,
+}
+
+impl Context {
+    /// Capture the running process's directories, environment, and clock.
+    pub fn from_process() -> Context {
+        let env: HashMap<OsString, OsString> = std::env::vars_os().collect();
+        let (cwd, removed_cwd) = resolve_cwd(std::env::current_dir(), &env);
+        Context {
+            home: env.get(OsStr::new("HOME")).map(PathBuf::from),
+            cwd,
+            removed_cwd,
+            env,
+            config_file: None,
+            now: SystemTime::now(),
+            state_root: OnceLock::new(),
+            state_writable: OnceLock::new(),
+        }
+    }
+
+    /// The context for a `termtint serve` client's request: this process's home
+    /// and environment, with the client's working directory and terminal session,
+    /// at the current time.
+    pub fn for_client(&self, cwd: &Path, session: &str) -> Context {
+        let mut env = self.env.clone();
+        // The session id is already resolved, so nothing should resolve it again
+        // from the daemon's own iTerm2 session or tty
+        env.remove(OsStr::new("ITERM_SESSION_ID"));
+        env.insert("TERMTINT_SESSION".into(), session.into());
+        Context {
+            home: self.home.clone(),
+            cwd: Ok(cwd.to_path_buf()),
+            removed_cwd: None,
+            env,
+            config_file: self.config_file.clone(),
+            now: SystemTime::now(),
+            state_root: OnceLock::new(),
+            state_writable: OnceLock::new(),
+        }
+    }
+
+    /// A context with the given home and working directories, an empty
+    /// environment, and the current time.
+    #[cfg(test)]
+    pub fn new(home: &Path, cwd: &Path) -> Context {
+        Context {
+            home: Some(home.to_path_buf()),
+            cwd: Ok(cwd.to_path_buf()),
+            removed_cwd: None,
+            env: HashMap::new(),
+            config_file: None,
+            now: SystemTime::now(),
+            state_root: OnceLock::new(),
+            state_writable: OnceLock::new(),
+        }
+    }
+
+    /// Set an environment variable.
+    #[cfg(test)]
+    pub fn with_var(mut self, name: &str, value: &str) -> Context {
+        self.env.insert(name.into(), value.into());
+        self
+    }
+
+    /// An environment variable, if set to valid Unicode (like `std::env::var`).
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.var_os(name).and_then(OsStr::to_str)
+    }
+
+    /// An environment variable, if set.
+    pub fn var_os(&self, name: &str) -> Option<&OsStr> {
+        self.env.get(OsStr::new(name)).map(OsString::as_os_str)
+    }
+
+    /// The home directory, or `.` when `$HOME` is unset.
+    pub fn home_dir(&self) -> &Path {
+        self.home.as_deref().unwrap_or(Path::new("."))
+    }
+
+    /// The working directory, with the error commands report when it can't be read.
+    pub fn current_dir(&self) -> Result<&Path, TermtintError> {
+        self.cwd.as_deref().map_err(Clone::clone)
+    }
+}
+
+/// The working directory from `getcwd`'s result, falling back to the shell's
+/// `$PWD` when it fails: some systems refuse it when an ancestor of the working
+/// directory isn't readable, and it always fails once the directory is deleted.
+/// A deleted directory resolves to its nearest remaining ancestor, returned with
+/// the directory that's gone.
+fn resolve_cwd(
+    getcwd: std::io::Result<PathBuf>,
+    env: &HashMap<OsString, OsString>,
+) -> (Result<PathBuf, TermtintError>, Option<PathBuf>) {
+    let error = match getcwd {
+        Ok(dir) => return (Ok(dir), None),
+        Err(e) => e,
+    };
+    match shell_pwd(env) {
+        Some(pwd) if is_removed(&pwd) => {
+            let ancestor = pwd.ancestors().find(|dir| !is_removed(dir));
+            (
+                Ok(ancestor.unwrap_or(Path::new("/")).to_path_buf()),
+                Some(pwd),
+            )
+        }
+        Some(pwd) => (Ok(pwd), None),
+        None => (
+            Err(TermtintError::io("Error getting current directory", &error)),
+            None,
+        ),
+    }
+}
+
+/// The shell's `$PWD`, if it's an absolute path.
+fn shell_pwd(env: &HashMap<OsString, OsString>) -> Option<PathBuf> {
+    let pwd = PathBuf::from(env.get(OsStr::new("PWD"))?);
+    pwd.is_absolute().then_some(pwd)
+}
+
+/// Whether `path` no longer exists (as opposed to being unreadable).
+pub fn is_removed(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_var_reads_only_the_context_environment() {
+        let context = Context::new(Path::new("/home/me"), Path::new("/work"))
+            .with_var("TERMTINT_SESSION", "tab-1");
+
+        assert_eq!(context.var("TERMTINT_SESSION"), Some("tab-1"));
+        assert_eq!(context.var("PATH"), None);
+        assert_eq!(context.home_dir(), Path::new("/home/me"));
+        assert_eq!(context.current_dir(), Ok(Path::new("/work")));
+    }
+
+    #[test]
+    fn test_for_client() {
+        let daemon = Context::new(Path::new("/home/me"), Path::new("/"))
+            .with_var("ITERM_SESSION_ID", "w0t0p0:daemon")
+            .with_var("TERMTINT_STATE_DIR", "/state");
+        let client = daemon.for_client(Path::new("/work/project"), "w1t2p0");
+
+        assert_eq!(client.current_dir(), Ok(Path::new("/work/project")));
+        assert_eq!(client.var("TERMTINT_SESSION"), Some("w1t2p0"));
+        assert_eq!(client.var("ITERM_SESSION_ID"), None);
+        assert_eq!(client.var("TERMTINT_STATE_DIR"), Some("/state"));
+        assert_eq!(client.home_dir(), Path::new("/home/me"));
+    }
+
+    #[test]
+    fn test_shell_pwd() {
+        let env = |pwd: &str| HashMap::from([(OsString::from("PWD"), OsString::from(pwd))]);
+
+        assert_eq!(shell_pwd(&env("/work")), Some(PathBuf::from("/work")));
+        assert_eq!(shell_pwd(&env("work")), None);
+        assert_eq!(shell_pwd(&HashMap::new()), None);
+    }
+
+    #[test]
+    fn test_resolve_cwd_after_removal() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let project = temp.path().join("project");
+        let removed = project.join("build").join("out");
+        std::fs::create_dir_all(&removed).unwrap();
+        std::fs::remove_dir_all(project.join("build")).unwrap();
+        let env = HashMap::from([(OsString::from("PWD"), removed.clone().into_os_string())]);
+        let gone = || Err(std::io::Error::from(std::io::ErrorKind::NotFound));
+
+        assert_eq!(
+            resolve_cwd(gone(), &env),
+            (Ok(project.clone()), Some(removed.clone()))
+        );
+        // getcwd is trusted when it works
+        assert_eq!(
+            resolve_cwd(Ok(temp.path().to_path_buf()), &env),
+            (Ok(temp.path().to_path_buf()), None)
+        );
+        // An existing $PWD is used as is
+        let env = HashMap::from([(OsString::from("PWD"), project.clone().into_os_string())]);
+        assert_eq!(resolve_cwd(gone(), &env), (Ok(project), None));
+
+        let (cwd, removed_cwd) = resolve_cwd(gone(), &HashMap::new());
+        assert!(cwd.unwrap_err().is_not_found());
+        assert_eq!(removed_cwd, None);
+    }
+
+    #[test]
+    fn test_missing_home_and_cwd() {
+        let mut context = Context::new(Path::new("/home/me"), Path::new("/work"));
+        context.home = None;
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        context.cwd = Err(TermtintError::io(
+            "Error getting current directory",
+            &missing,
+        ));
+
+        assert_eq!(context.home_dir(), Path::new("."));
+        assert_eq!(
+            context.current_dir().map_err(|e| e.to_string()),
+            Err("Error getting current directory: entity not found".to_string())
+        );
+    }
+}
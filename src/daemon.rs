@@ -0,0 +1,736 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crate::context::Context;
+use crate::error::TermtintError;
+use crate::state;
+use crate::user_config::{self, TriggerKind, UserConfig};
+
+/// How long a client waits for the daemon before resolving in-process instead,
+/// so a wedged daemon delays a prompt by at most this much.
+pub const CLIENT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long the daemon waits for a client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often the accept loop wakes to check for shutdown.
+const POLL_INTERVAL_MS: libc::c_int = 1000;
+
+/// Requests longer than this are rejected rather than buffered.
+const MAX_REQUEST_LEN: u64 = 16 * 1024;
+
+/// Set by SIGINT and SIGTERM to stop the accept loop.
+static SIGNALED: AtomicBool = AtomicBool::new(false);
+
+/// Where `termtint serve` listens: `TERMTINT_SOCKET`, or `termtint.sock` in
+/// the runtime directory.
+pub fn socket_path(ctx: &Context) -> PathBuf {
+    if let Some(path) = ctx
+        .var_os("TERMTINT_SOCKET")
+        .filter(|path| !path.is_empty())
+    {
+        return PathBuf::from(path);
+    }
+    runtime_dir(ctx).join("termtint.sock")
+}
+
+/// The directory for files that only matter while the user is logged in:
+/// `$XDG_RUNTIME_DIR`, or a per-user directory under `$TMPDIR` (or /tmp) on
+/// systems without one, like macOS.
+pub fn runtime_dir(ctx: &Context) -> PathBuf {
+    match ctx.var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let tmp = ctx
+                .var_os("TMPDIR")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from("/tmp"));
+            // SAFETY: getuid has no preconditions and can't fail.
+            tmp.join(format!("termtint-{}", unsafe { libc::getuid() }))
+        }
+    }
+}
+
+/// A request from a client, sent as one line of tab-separated fields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// Apply colors for `cwd` in a terminal session, as `apply` would
+    Apply {
+        cwd: PathBuf,
+        session: String,
+        /// The client's controlling terminal, recorded with the applied state
+        tty: Option<String>,
+        /// The user config file the client would load; the daemon only answers
+        /// when it's the one it has loaded
+        config: PathBuf,
+    },
+    /// Reset a session's colors, as `reset` would
+    Reset { session: String },
+    /// Describe the running daemon
+    Status,
+    /// Stop the daemon
+    Shutdown,
+}
+
+impl Request {
+    /// The request's line, or None when a field can't be sent (a path that isn't
+    /// UTF-8, or contains a tab or newline); the client resolves in-process then.
+    pub fn to_line(&self) -> Option<String> {
+        let fields = match self {
+            Request::Apply {
+                cwd,
+                session,
+                tty,
+                config,
+            } => vec![
+                "apply",
+                cwd.to_str()?,
+                session,
+                tty.as_deref().unwrap_or(""),
+                config.to_str()?,
+            ],
+            Request::Reset { session } => vec!["reset", session],
+            Request::Status => vec!["status"],
+            Request::Shutdown => vec!["shutdown"],
+        };
+        if fields.iter().any(|field| field.contains(['\t', '\n'])) {
+            return None;
+        }
+        Some(fields.join("\t") + "\n")
+    }
+
+    /// Parse a request line, without its trailing newline.
+    pub fn parse(line: &str) -> Result<Request, String> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields.as_slice() {
+            ["apply", cwd, session, tty, config]
+                if !cwd.is_empty() && !session.is_empty() && !config.is_empty() =>
+            {
+                Ok(Request::Apply {
+                    cwd: PathBuf::from(cwd),
+                    session: session.to_string(),
+                    tty: Some(tty.to_string()).filter(|tty| !tty.is_empty()),
+                    config: PathBuf::from(config),
+                })
+            }
+            ["reset", session] if !session.is_empty() => Ok(Request::Reset {
+                session: session.to_string(),
+            }),
+            ["status"] => Ok(Request::Status),
+            ["shutdown"] => Ok(Request::Shutdown),
+            _ => Err(format!("malformed request '{}'", line.escape_debug())),
+        }
+    }
+}
+
+/// The daemon's answer: a header line (`ok`, `fallback <reason>`, or
+/// `error <message>`), then for `ok` the body until the connection closes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// Escape sequences for the client to write to its terminal, or the text of
+    /// a status report
+    Ok(String),
+    /// The daemon can't answer for this client; it should resolve in-process
+    Fallback(String),
+    /// The request failed
+    Error(String),
+}
+
+impl Response {
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Response::Ok(body) => write!(writer, "ok\n{}", body),
+            Response::Fallback(reason) => writeln!(writer, "fallback {}", one_line(reason)),
+            Response::Error(message) => writeln!(writer, "error {}", one_line(message)),
+        }
+    }
+
+    /// Parse a whole response as read from the socket.
+    pub fn parse(response: &str) -> Result<Response, String> {
+        let (header, body) = response.split_once('\n').unwrap_or((response, ""));
+        match header.split_once(' ').unwrap_or((header, "")) {
+            ("ok", _) => Ok(Response::Ok(body.to_string())),
+            ("fallback", reason) => Ok(Response::Fallback(reason.to_string())),
+            ("error", message) => Ok(Response::Error(message.to_string())),
+            _ => Err(format!("malformed response '{}'", header.escape_debug())),
+        }
+    }
+}
+
+fn one_line(text: &str) -> String {
+    text.replace('\n', " ")
+}
+
+/// The directory holding the socket at `path`.
+fn socket_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Check that a directory, like the socket's, is private to this user: a
+/// directory, not a symlink, owned by this user, with no access for group or
+/// others. Otherwise another user could have created it (the /tmp fallback is
+/// predictable) and listen there, answering applies with escape sequences of
+/// their choosing.
+pub fn check_private_dir(dir: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(dir)?;
+    // SAFETY: getuid has no preconditions and can't fail.
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} isn't a directory only this user can access (owner {}, mode {:o})",
+                dir.display(),
+                metadata.uid(),
+                metadata.mode() & 0o7777
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Send a request to the daemon at `path` and read its response, giving up after
+/// `CLIENT_TIMEOUT`. Refuses a socket whose directory other users can access.
+pub fn send(path: &Path, request: &Request) -> io::Result<Response> {
+    let line = request.to_line().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "request can't be sent over the socket",
+        )
+    })?;
+    check_private_dir(socket_dir(path))?;
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.write_all(line.as_bytes())?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Response::parse(&response).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Whether resolving colors with this config reads the client's environment
+/// (`[env_triggers]`, or kube-context triggers through `KUBECONFIG`), which the
+/// daemon doesn't have.
+pub fn needs_client_environment(user_config: &UserConfig) -> bool {
+    !user_config.env_triggers.is_empty()
+        || user_config
+            .triggers
+            .iter()
+            .any(|trigger| trigger.kind == TriggerKind::KubeContext)
+}
+
+/// The user config, kept loaded between requests and reloaded when the file's
+/// modification time or size changes.
+pub struct WarmConfig {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    pub config: UserConfig,
+    /// Why the file's current contents didn't load (`strict = true` rejecting
+    /// them), while `config` stays the one loaded before
+    pub error: Option<TermtintError>,
+    /// Number of times the file has been loaded
+    pub loads: u32,
+}
+
+impl WarmConfig {
+    /// Load the user config for `ctx`.
+    pub fn load(ctx: &Context) -> Result<WarmConfig, TermtintError> {
+        let path = user_config::config_file_path(ctx);
+        Ok(WarmConfig {
+            stamp: file_stamp(&path),
+            config: user_config::load_user_config(ctx)?,
+            error: None,
+            path,
+            loads: 1,
+        })
+    }
+
+    /// The config file's path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The context for a client's request (see `Context::for_client`), with the
+    /// state directory the loaded config sets.
+    pub fn client_context(&self, ctx: &Context, cwd: &Path, session: &str) -> Context {
+        let client = ctx.for_client(cwd, session);
+        state::use_configured_state_dir(&client, self.config.state_dir.as_deref());
+        client
+    }
+
+    /// Reload the config if the file changed (or appeared, or was removed) since
+    /// it was last loaded. Returns whether it was read again, setting `error`
+    /// instead of `config` if its new contents are rejected.
+    pub fn refresh(&mut self, ctx: &Context) -> bool {
+        let stamp = file_stamp(&self.path);
+        if stamp == self.stamp {
+            return false;
+        }
+        tracing::debug!(path = %self.path.display(), "user config changed, reloading");
+        self.stamp = stamp;
+        match user_config::load_user_config(ctx) {
+            Ok(config) => {
+                self.config = config;
+                self.error = None;
+            }
+            Err(e) => {
+                tracing::warn!("{}, keeping the config loaded before", e);
+                self.error = Some(e);
+            }
+        }
+        self.loads += 1;
+        true
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// A bound daemon socket. The socket file is removed when it's dropped.
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    stop: AtomicBool,
+}
+
+impl Server {
+    /// Listen at `path`, replacing a stale socket left by a daemon that didn't
+    /// shut down cleanly. Fails if another daemon is answering there.
+    pub fn bind(path: &Path) -> Result<Server, TermtintError> {
+        let dir = socket_dir(path);
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(|e| TermtintError::io(format!("Error creating {}", dir.display()), &e))?;
+        check_private_dir(dir).map_err(|e| TermtintError::io("Refusing to listen", &e))?;
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(TermtintError::State(format!(
+                    "termtint serve is already running on {}",
+                    path.display()
+                )));
+            }
+            fs::remove_file(path).map_err(|e| {
+                TermtintError::io(
+                    format!("Error removing stale socket {}", path.display()),
+                    &e,
+                )
+            })?;
+        }
+        let listener = UnixListener::bind(path)
+            .map_err(|e| TermtintError::io(format!("Error listening on {}", path.display()), &e))?;
+        let _ = fs::set_permissions(path, fs::Permissions::from_mode(0o600));
+        Ok(Server {
+            listener,
+            path: path.to_path_buf(),
+            stop: AtomicBool::new(false),
+        })
+    }
+
+    /// Stop the accept loop after the current request.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    fn stopping(&self) -> bool {
+        self.stop.load(Ordering::SeqCst) || SIGNALED.load(Ordering::SeqCst)
+    }
+
+    /// Answer requests one at a time until a shutdown request, `stop`, or SIGINT
+    /// or SIGTERM. `Shutdown` is answered here; everything else goes to `handle`.
+    pub fn run(&self, mut handle: impl FnMut(Request) -> Response) -> io::Result<()> {
+        while !self.stopping() {
+            let mut poll_fd = libc::pollfd {
+                fd: self.listener.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: poll_fd is a valid pollfd for the duration of the call.
+            let ready = unsafe { libc::poll(&mut poll_fd, 1, POLL_INTERVAL_MS) };
+            if ready < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+            if ready == 0 {
+                continue;
+            }
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = self.answer(stream, &mut handle) {
+                        tracing::debug!(error = %e, "client connection failed");
+                    }
+                }
+                Err(e) => tracing::warn!("accepting a connection failed: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    fn answer(
+        &self,
+        mut stream: UnixStream,
+        handle: &mut impl FnMut(Request) -> Response,
+    ) -> io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut line = String::new();
+        BufReader::new((&stream).take(MAX_REQUEST_LEN)).read_line(&mut line)?;
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let response = match Request::parse(line) {
+            Ok(Request::Shutdown) => {
+                self.stop();
+                Response::Ok("stopping\n".to_string())
+            }
+            Ok(request) => {
+                let _span = tracing::debug_span!("serve").entered();
+                tracing::debug!(?request, "request");
+                handle(request)
+            }
+            Err(e) => Response::Error(e),
+        };
+        response.write_to(&mut stream)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+extern "C" fn on_signal(_: libc::c_int) {
+    SIGNALED.store(true, Ordering::SeqCst);
+}
+
+/// Stop `Server::run` on SIGINT and SIGTERM, so the socket is removed on the way out.
+pub fn stop_on_signals() {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe,
+        // and the sigaction struct is fully initialized before the call.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_request_round_trip() {
+        let requests = [
+            Request::Apply {
+                cwd: PathBuf::from("/home/me/my project"),
+                session: "w0t1p0".to_string(),
+                tty: Some("/dev/ttys003".to_string()),
+                config: PathBuf::from("/home/me/.config/termtint/config.toml"),
+            },
+            Request::Apply {
+                cwd: PathBuf::from("/work"),
+                session: "pts_3".to_string(),
+                tty: None,
+                config: PathBuf::from("/work/demo.toml"),
+            },
+            Request::Reset {
+                session: "pts_3".to_string(),
+            },
+            Request::Status,
+            Request::Shutdown,
+        ];
+        for request in requests {
+            let line = request.to_line().unwrap();
+            assert_eq!(line.matches('\n').count(), 1, "{:?}", line);
+            assert_eq!(Request::parse(line.trim_end_matches('\n')), Ok(request));
+        }
+
+        let tabbed = Request::Apply {
+            cwd: PathBuf::from("/work/a\tb"),
+            session: "s".to_string(),
+            tty: None,
+            config: PathBuf::from("/work/config.toml"),
+        };
+        assert_eq!(tabbed.to_line(), None);
+        assert!(Request::parse("apply\t/work").is_err());
+        assert!(Request::parse("apply\t\ts\t\t/c.toml").is_err());
+        // From a client that predates the config field
+        assert!(Request::parse("apply\t/work\ts\t").is_err());
+        assert!(Request::parse("launch").is_err());
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        for response in [
+            Response::Ok("\x1b]1337;SetColors=bg=1a2b3c\x07".to_string()),
+            Response::Ok("pid 42\nrequests 3\n".to_string()),
+            Response::Fallback("config has [env_triggers]".to_string()),
+            Response::Error("malformed request".to_string()),
+        ] {
+            let mut bytes = Vec::new();
+            response.write_to(&mut bytes).unwrap();
+            let text = String::from_utf8(bytes).unwrap();
+            assert_eq!(Response::parse(&text), Ok(response));
+        }
+        assert!(Response::parse("maybe\n").is_err());
+    }
+
+    #[test]
+    fn test_socket_path() {
+        let ctx = Context::new(Path::new("/home/me"), Path::new("/work"))
+            .with_var("XDG_RUNTIME_DIR", "/run/user/501");
+        assert_eq!(socket_path(&ctx), Path::new("/run/user/501/termtint.sock"));
+
+        let ctx = ctx.with_var("TERMTINT_SOCKET", "/tmp/t.sock");
+        assert_eq!(socket_path(&ctx), Path::new("/tmp/t.sock"));
+
+        let ctx = Context::new(Path::new("/home/me"), Path::new("/work"))
+            .with_var("TMPDIR", "/var/folders/xy/T");
+        let path = socket_path(&ctx);
+        assert!(path.starts_with("/var/folders/xy/T"), "{}", path.display());
+        assert!(path.parent().unwrap().ends_with(format!(
+            "termtint-{}",
+            // SAFETY: getuid has no preconditions and can't fail.
+            unsafe { libc::getuid() }
+        )));
+    }
+
+    /// A server on a socket in `temp`, answering in a background thread with
+    /// `handle` until it's shut down.
+    fn spawn_server(
+        temp: &TempDir,
+        handle: impl FnMut(Request) -> Response + Send + 'static,
+    ) -> (PathBuf, thread::JoinHandle<()>) {
+        let path = temp.path().join("run").join("termtint.sock");
+        let server = Server::bind(&path).unwrap();
+        let thread = thread::spawn(move || server.run(handle).unwrap());
+        (path, thread)
+    }
+
+    #[test]
+    fn test_socket_dir_must_be_private() {
+        let temp = TempDir::new().unwrap();
+        let (path, thread) = spawn_server(&temp, |_| Response::Ok("up\n".to_string()));
+        let dir = path.parent().unwrap();
+        assert_eq!(
+            fs::metadata(dir).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+        assert!(send(&path, &Request::Status).is_ok());
+
+        // A directory others can get into is refused by the client and the server
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o755)).unwrap();
+        let error = send(&path, &Request::Status).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(Server::bind(&temp.path().join("run").join("other.sock")).is_err());
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700)).unwrap();
+        send(&path, &Request::Shutdown).unwrap();
+        thread.join().unwrap();
+
+        // As is a symlink to a private directory
+        let link = temp.path().join("link");
+        std::os::unix::fs::symlink(dir, &link).unwrap();
+        assert_eq!(
+            check_private_dir(&link).unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        assert!(Server::bind(&link.join("termtint.sock")).is_err());
+    }
+
+    #[test]
+    fn test_serves_requests_until_shutdown() {
+        let temp = TempDir::new().unwrap();
+        let (path, thread) = spawn_server(&temp, |request| match request {
+            Request::Apply {
+                cwd, session, tty, ..
+            } => Response::Ok(format!(
+                "{}|{}|{}",
+                cwd.display(),
+                session,
+                tty.unwrap_or_default()
+            )),
+            Request::Reset { .. } => Response::Fallback("no".to_string()),
+            _ => Response::Ok("up\n".to_string()),
+        });
+
+        let apply = Request::Apply {
+            cwd: PathBuf::from("/work/project"),
+            session: "tab-1".to_string(),
+            tty: Some("/dev/pts/3".to_string()),
+            config: PathBuf::from("/home/me/.config/termtint/config.toml"),
+        };
+        assert_eq!(
+            send(&path, &apply).unwrap(),
+            Response::Ok("/work/project|tab-1|/dev/pts/3".to_string())
+        );
+        assert_eq!(
+            send(
+                &path,
+                &Request::Reset {
+                    session: "tab-1".to_string()
+                }
+            )
+            .unwrap(),
+            Response::Fallback("no".to_string())
+        );
+
+        // A second daemon on the same socket is refused while this one answers
+        assert_eq!(
+            Server::bind(&path).err().map(|e| e.exit_code()),
+            Some(crate::error::EXIT_STATE)
+        );
+
+        // A malformed request gets an error without stopping the server
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"launch\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with("error malformed request"),
+            "{}",
+            response
+        );
+
+        assert_eq!(
+            send(&path, &Request::Shutdown).unwrap(),
+            Response::Ok("stopping\n".to_string())
+        );
+        thread.join().unwrap();
+        assert!(!path.exists(), "socket not removed on shutdown");
+        assert!(send(&path, &Request::Status).is_err());
+    }
+
+    #[test]
+    fn test_stop_ends_the_accept_loop() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("run").join("termtint.sock");
+        let server = Arc::new(Server::bind(&path).unwrap());
+        let running = Arc::clone(&server);
+        let thread = thread::spawn(move || running.run(|_| Response::Ok(String::new())));
+        server.stop();
+        thread.join().unwrap().unwrap();
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_bind_replaces_stale_socket() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("run").join("termtint.sock");
+        // A socket file nobody is listening on, as a killed daemon leaves behind
+        fs::DirBuilder::new()
+            .mode(0o700)
+            .create(path.parent().unwrap())
+            .unwrap();
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let server = Server::bind(&path).unwrap();
+        assert!(UnixStream::connect(&path).is_ok());
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_warm_config_reloads_on_change() {
+        let temp = TempDir::new().unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
+        let mut warm = WarmConfig::load(&ctx).unwrap();
+        assert!(!warm.config.generate_palette);
+        assert!(!warm.refresh(&ctx));
+        assert_eq!(warm.loads, 1);
+
+        // Created after the daemon started
+        fs::create_dir_all(warm.path().parent().unwrap()).unwrap();
+        fs::write(warm.path(), "generate_palette = true\n").unwrap();
+        assert!(warm.refresh(&ctx));
+        assert!(warm.config.generate_palette);
+        assert!(!warm.refresh(&ctx));
+
+        // Rewritten with the same size, so only the modification time changes
+        fs::write(warm.path(), "generate_palette = true\n").unwrap();
+        let earlier = filetime::FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(warm.path(), earlier).unwrap();
+        assert!(warm.refresh(&ctx));
+        assert_eq!(warm.loads, 3);
+
+        fs::remove_file(warm.path()).unwrap();
+        assert!(warm.refresh(&ctx));
+        assert!(!warm.config.generate_palette);
+    }
+
+    #[test]
+    fn test_client_context_follows_reloaded_state_dir() {
+        let temp = TempDir::new().unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
+        let mut warm = WarmConfig::load(&ctx).unwrap();
+        let client = warm.client_context(&ctx, temp.path(), "tab-1");
+        assert_eq!(
+            state::state_root(&client),
+            temp.path().join(".cache").join("termtint")
+        );
+
+        fs::create_dir_all(warm.path().parent().unwrap()).unwrap();
+        fs::write(warm.path(), "state_dir = \"~/state\"\n").unwrap();
+        assert!(warm.refresh(&ctx));
+        let client = warm.client_context(&ctx, temp.path(), "tab-1");
+        assert_eq!(state::state_root(&client), temp.path().join("state"));
+    }
+
+    #[test]
+    fn test_warm_config_keeps_config_strict_rejects() {
+        let temp = TempDir::new().unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
+        let path = user_config::config_file_path(&ctx);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "strict = true\ngenerate_palette = true\n").unwrap();
+        let mut warm = WarmConfig::load(&ctx).unwrap();
+
+        fs::write(&path, "strict = true\n\n[auto]\nlightness = 5.5\n").unwrap();
+        assert!(warm.refresh(&ctx));
+        assert!(warm.config.generate_palette);
+        assert_eq!(
+            warm.error.as_ref().map(TermtintError::exit_code),
+            Some(crate::error::EXIT_CONFIG)
+        );
+
+        fs::write(&path, "strict = true\n").unwrap();
+        assert!(warm.refresh(&ctx));
+        assert!(!warm.config.generate_palette);
+        assert_eq!(warm.error, None);
+
+        // A daemon doesn't start with a config it can't load
+        fs::write(&path, "strict = true\n\n[auto]\nlightness = 5.5\n").unwrap();
+        assert!(WarmConfig::load(&ctx).is_err());
+    }
+
+    #[test]
+    fn test_needs_client_environment() {
+        let mut config = UserConfig::default();
+        assert!(!needs_client_environment(&config));
+        config.env_triggers = vec![("AWS_PROFILE=prod*".to_string(), "#cc0000".to_string())];
+        assert!(needs_client_environment(&config));
+    }
+}
@@ -0,0 +1,145 @@
+use std::io;
+use std::path::PathBuf;
+
+/// Exit codes `main` uses for each kind of error, documented in the README.
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_IO: i32 = 3;
+pub const EXIT_PARSE: i32 = 4;
+pub const EXIT_INVALID_COLOR: i32 = 5;
+pub const EXIT_CONFIG: i32 = 6;
+pub const EXIT_STATE: i32 = 7;
+
+/// An error from reading, parsing, or writing termtint's files.
+///
+/// The messages are the ones commands print, so a variant's Display is the
+/// whole message; the variants let callers tell failures apart and let `main`
+/// exit with a code per kind.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum TermtintError {
+    /// A file couldn't be read or written
+    #[error("{context}: {message}")]
+    Io {
+        /// What was being done, e.g. "Failed to read config file"
+        context: String,
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// A file's contents couldn't be parsed
+    #[error("{reason}")]
+    Parse {
+        file: Option<PathBuf>,
+        reason: String,
+    },
+    /// A color string in none of the accepted syntaxes
+    #[error("{0}")]
+    InvalidColor(String),
+    /// An invalid user config value or trigger
+    #[error("{0}")]
+    Config(String),
+    /// The state directory couldn't be used
+    #[error("{0}")]
+    State(String),
+    /// A command was invoked with arguments that don't work together
+    #[error("{0}")]
+    Usage(String),
+}
+
+impl TermtintError {
+    /// An I/O error, described as `context: error`.
+    pub fn io(context: impl Into<String>, error: &io::Error) -> TermtintError {
+        TermtintError::Io {
+            context: context.into(),
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+
+    /// A parse error in `file` (when it came from one).
+    pub fn parse(file: Option<PathBuf>, reason: impl Into<String>) -> TermtintError {
+        TermtintError::Parse {
+            file,
+            reason: reason.into(),
+        }
+    }
+
+    /// The same error with `prefix` (e.g. "Error: ") at the start of its message.
+    pub fn with_prefix(self, prefix: &str) -> TermtintError {
+        match self {
+            TermtintError::Io {
+                context,
+                kind,
+                message,
+            } => TermtintError::Io {
+                context: format!("{}{}", prefix, context),
+                kind,
+                message,
+            },
+            TermtintError::Parse { file, reason } => TermtintError::Parse {
+                file,
+                reason: format!("{}{}", prefix, reason),
+            },
+            TermtintError::InvalidColor(m) => {
+                TermtintError::InvalidColor(format!("{}{}", prefix, m))
+            }
+            TermtintError::Config(m) => TermtintError::Config(format!("{}{}", prefix, m)),
+            TermtintError::State(m) => TermtintError::State(format!("{}{}", prefix, m)),
+            TermtintError::Usage(m) => TermtintError::Usage(format!("{}{}", prefix, m)),
+        }
+    }
+
+    /// Whether this is an I/O error for a file that doesn't exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, TermtintError::Io { kind, .. } if *kind == io::ErrorKind::NotFound)
+    }
+
+    /// The process exit code for this kind of error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TermtintError::Io { .. } => EXIT_IO,
+            TermtintError::Parse { .. } => EXIT_PARSE,
+            TermtintError::InvalidColor(_) => EXIT_INVALID_COLOR,
+            TermtintError::Config(_) => EXIT_CONFIG,
+            TermtintError::State(_) => EXIT_STATE,
+            TermtintError::Usage(_) => EXIT_USAGE,
+        }
+    }
+}
+
+/// Modules that still report errors as strings take typed errors through `?`.
+impl From<TermtintError> for String {
+    fn from(error: TermtintError) -> String {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_and_exit_codes() {
+        let missing = io::Error::new(io::ErrorKind::NotFound, "No such file or directory");
+        let error = TermtintError::io("Failed to read config file", &missing);
+        assert_eq!(
+            error.to_string(),
+            "Failed to read config file: No such file or directory"
+        );
+        assert!(error.is_not_found());
+        assert_eq!(error.exit_code(), EXIT_IO);
+
+        let error = TermtintError::parse(Some(PathBuf::from("/a/.termtint")), "Missing 'tab'");
+        assert_eq!(error.to_string(), "Missing 'tab'");
+        assert!(!error.is_not_found());
+        assert_eq!(error.exit_code(), EXIT_PARSE);
+        let error = error.with_prefix("Error: ");
+        assert_eq!(error.to_string(), "Error: Missing 'tab'");
+        assert_eq!(error.exit_code(), EXIT_PARSE);
+
+        assert_eq!(
+            TermtintError::InvalidColor("bad".to_string()).exit_code(),
+            EXIT_INVALID_COLOR
+        );
+        assert_eq!(String::from(TermtintError::Config("x".to_string())), "x");
+    }
+}
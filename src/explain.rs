@@ -0,0 +1,103 @@
+use std::fmt;
+
+/// A trace of the decisions `apply` makes, rendered for `apply --explain`.
+///
+/// The resolution pipeline takes one unconditionally; a disabled trace records
+/// nothing and never calls the closures that format its notes.
+#[derive(Debug, Default)]
+pub struct Explanation {
+    enabled: bool,
+    steps: Vec<Step>,
+}
+
+/// One stage of the pipeline, such as the directory walk, with what it found.
+#[derive(Debug)]
+struct Step {
+    title: String,
+    notes: Vec<String>,
+}
+
+impl Explanation {
+    /// A trace that records every step and note.
+    pub fn new() -> Explanation {
+        Explanation {
+            enabled: true,
+            steps: Vec::new(),
+        }
+    }
+
+    /// A trace that records nothing, for callers that don't explain.
+    pub fn disabled() -> Explanation {
+        Explanation::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Start a new step; following notes belong to it.
+    pub fn step(&mut self, title: &str) {
+        if self.enabled {
+            self.steps.push(Step {
+                title: title.to_string(),
+                notes: Vec::new(),
+            });
+        }
+    }
+
+    /// Add a note to the current step, starting an untitled one if there is none.
+    pub fn note(&mut self, note: impl FnOnce() -> String) {
+        if !self.enabled {
+            return;
+        }
+        if self.steps.is_empty() {
+            self.step("Start");
+        }
+        if let Some(step) = self.steps.last_mut() {
+            step.notes.push(note());
+        }
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Explanation:")?;
+        for (number, step) in self.steps.iter().enumerate() {
+            writeln!(f, "{}. {}", number + 1, step.title)?;
+            for note in &step.notes {
+                writeln!(f, "   {}", note)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explanation_renders_numbered_steps() {
+        let mut explain = Explanation::new();
+        explain.step("Cleanup");
+        explain.note(|| "skipped".to_string());
+        explain.step("Directory walk");
+        explain.note(|| "/a: no .termtint".to_string());
+        explain.note(|| "/: no .termtint".to_string());
+
+        assert_eq!(
+            explain.to_string(),
+            "Explanation:\n1. Cleanup\n   skipped\n2. Directory walk\n   /a: no .termtint\n   /: no .termtint\n"
+        );
+    }
+
+    #[test]
+    fn test_disabled_explanation_records_nothing() {
+        let mut explain = Explanation::disabled();
+        explain.step("Cleanup");
+        explain.note(|| unreachable!("notes aren't formatted when disabled"));
+
+        assert!(!explain.is_enabled());
+        assert_eq!(explain.to_string(), "Explanation:\n");
+    }
+}
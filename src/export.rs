@@ -0,0 +1,765 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, ColorConfig, RGB};
+use crate::jsonc;
+use crate::palette::{self, Palette};
+use crate::state;
+
+/// The formats `termtint export` can write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    ItermProfile,
+    Kitty,
+    /// A Windows Terminal color scheme; `full` wraps it in a `"schemes"` entry
+    WindowsTerminal {
+        full: bool,
+    },
+    Alacritty,
+    /// VS Code workspace settings, merged into `.vscode/settings.json`
+    VsCode,
+    /// A base16 scheme (YAML), with the tab color in slot `tab_slot` (0x1-0xF)
+    Base16 {
+        tab_slot: usize,
+    },
+}
+
+/// A project's resolved colors, ready to be written out in another tool's format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    /// The project directory: the `.termtint` file's directory or the matched
+    /// trigger directory
+    pub dir: PathBuf,
+    /// Display name, the directory's base name
+    pub name: String,
+    pub colors: ColorConfig,
+    /// The derived ANSI palette, when `generate_palette` is enabled
+    pub palette: Option<Palette>,
+}
+
+impl Project {
+    pub fn new(dir: PathBuf, colors: ColorConfig, generate_palette: bool) -> Project {
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| dir.display().to_string());
+        let palette = generate_palette.then(|| palette::generate_palette(&colors));
+        Project {
+            dir,
+            name,
+            colors,
+            palette,
+        }
+    }
+}
+
+/// Render a project's colors in `format`.
+pub fn render(format: Format, project: &Project) -> String {
+    match format {
+        Format::ItermProfile => iterm_profile(project),
+        Format::Kitty => kitty_theme(project),
+        Format::WindowsTerminal { full } => windows_terminal(project, full),
+        Format::Alacritty => alacritty_theme(project),
+        Format::VsCode => vscode_document(&vscode_settings(project), "    "),
+        Format::Base16 { tab_slot } => base16_scheme(project, tab_slot),
+    }
+}
+
+/// Write an export to `path`, replacing any existing file.
+pub fn write_export(path: &Path, content: &str) -> Result<(), String> {
+    state::write_atomic_synced(path, content.as_bytes())
+        .map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+/// A color channel as the 0-1 float iTerm2 stores in profiles.
+fn iterm_component(value: u8) -> f64 {
+    f64::from(value) / 255.0
+}
+
+/// A color in iTerm2's profile format.
+fn iterm_color(rgb: RGB) -> serde_json::Value {
+    serde_json::json!({
+        "Red Component": iterm_component(rgb.r),
+        "Green Component": iterm_component(rgb.g),
+        "Blue Component": iterm_component(rgb.b),
+        "Alpha Component": 1.0,
+        "Color Space": "sRGB",
+    })
+}
+
+/// A stable GUID for a project's dynamic profile, derived from its directory, so
+/// re-exporting updates the same profile instead of adding another one.
+fn profile_guid(dir: &Path) -> String {
+    let half = |salt: &str| {
+        let mut hasher = DefaultHasher::new();
+        salt.hash(&mut hasher);
+        dir.hash(&mut hasher);
+        hasher.finish()
+    };
+    let hex = format!("{:016X}{:016X}", half("termtint-profile"), half("guid"));
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// An iTerm2 Dynamic Profile document holding one profile with the project's
+/// background and tab colors.
+pub fn iterm_profile(project: &Project) -> String {
+    let profile = serde_json::json!({
+        "Profiles": [{
+            "Name": project.name,
+            "Guid": profile_guid(&project.dir),
+            "Background Color": iterm_color(project.colors.background),
+            "Use Tab Color": true,
+            "Tab Color": iterm_color(project.colors.tab),
+        }]
+    });
+    let mut output = serde_json::to_string_pretty(&profile).unwrap_or_default();
+    output.push('\n');
+    output
+}
+
+/// A kitty theme fragment setting the window and tab bar backgrounds, the active
+/// tab's color, and the 16 ANSI colors when the palette is enabled. kitty takes
+/// colors as `#rrggbb`, which is how `RGB` displays.
+fn kitty_theme(project: &Project) -> String {
+    let colors = &project.colors;
+    let mut output = format!("# termtint: {}\n", project.name);
+    output.push_str(&format!("background {}\n", colors.background));
+    output.push_str(&format!("tab_bar_background {}\n", colors.background));
+    output.push_str(&format!("active_tab_background {}\n", colors.tab));
+    if let Some(palette) = &project.palette {
+        for (i, color) in palette.iter().enumerate() {
+            output.push_str(&format!("color{} {}\n", i, color));
+        }
+    }
+    output
+}
+
+/// Alacritty's names for the 8 colors in each of `[colors.normal]` and `[colors.bright]`.
+const ALACRITTY_COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// An Alacritty theme in its TOML config format: `[colors.primary]` with the
+/// background, and `[colors.normal]`/`[colors.bright]` when the palette is enabled.
+/// Alacritty only reads it through an `import` in its main config.
+fn alacritty_theme(project: &Project) -> String {
+    let mut output = format!(
+        "# termtint: {}\n\
+         # Add this file to `import` under [general] in alacritty.toml\n\
+         \n\
+         [colors.primary]\n\
+         background = \"{}\"\n",
+        project.name, project.colors.background
+    );
+    if let Some(palette) = &project.palette {
+        for (table, colors) in [("normal", &palette[..8]), ("bright", &palette[8..])] {
+            output.push_str(&format!("\n[colors.{}]\n", table));
+            for (name, color) in ALACRITTY_COLOR_NAMES.iter().zip(colors) {
+                output.push_str(&format!("{} = \"{}\"\n", name, color));
+            }
+        }
+    }
+    output
+}
+
+/// The slot base16 styling gives to blue (functions), where the tab color goes
+/// unless another is chosen.
+pub const BASE16_DEFAULT_TAB_SLOT: usize = 0xD;
+
+/// The name of a base16 slot, as in the spec (`base0A`, not `base0a`).
+fn base16_slot_name(slot: usize) -> String {
+    format!("base{:02X}", slot)
+}
+
+/// Parse a slot name the tab color can take, `base01` through `base0F` (base00
+/// is always the background). Case-insensitive.
+pub fn parse_base16_slot(name: &str) -> Option<usize> {
+    let digits = name.to_ascii_lowercase().strip_prefix("base0")?.to_string();
+    match usize::from_str_radix(&digits, 16) {
+        Ok(slot @ 0x1..=0xF) if digits.len() == 1 => Some(slot),
+        _ => None,
+    }
+}
+
+/// A base16 scheme in the spec's YAML format: `scheme` named after the project,
+/// `author`, and base00-base0F as quoted hex without `#`. base00 is the background,
+/// the tab color takes `tab_slot`, and the other slots come from the palette.
+fn base16_scheme(project: &Project, tab_slot: usize) -> String {
+    let mut colors = palette::generate_base16(&project.colors);
+    colors[tab_slot] = project.colors.tab;
+    // JSON strings are valid YAML double-quoted scalars, escapes included
+    let quote = |value: &str| serde_json::Value::from(value).to_string();
+    let mut output = format!(
+        "scheme: {}\nauthor: {}\n",
+        quote(&format!("termtint {}", project.name)),
+        quote("termtint")
+    );
+    for (slot, color) in colors.iter().enumerate() {
+        let hex = color.to_string();
+        output.push_str(&format!(
+            "{}: {}\n",
+            base16_slot_name(slot),
+            quote(hex.trim_start_matches('#'))
+        ));
+    }
+    output
+}
+
+/// Windows Terminal's names for the 16 ANSI colors, in palette order.
+const WINDOWS_TERMINAL_COLOR_NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "purple",
+    "cyan",
+    "white",
+    "brightBlack",
+    "brightRed",
+    "brightGreen",
+    "brightYellow",
+    "brightBlue",
+    "brightPurple",
+    "brightCyan",
+    "brightWhite",
+];
+
+/// A Windows Terminal color scheme with the project's background, and the 16
+/// ANSI colors when the palette is enabled.
+fn windows_terminal_scheme(project: &Project) -> serde_json::Value {
+    let mut scheme = serde_json::json!({
+        "name": format!("termtint {}", project.name),
+        "background": project.colors.background.to_string(),
+    });
+    if let (Some(palette), Some(object)) = (&project.palette, scheme.as_object_mut()) {
+        for (name, color) in WINDOWS_TERMINAL_COLOR_NAMES.iter().zip(palette) {
+            object.insert(name.to_string(), color.to_string().into());
+        }
+    }
+    scheme
+}
+
+/// A Windows Terminal scheme to paste into the `"schemes"` list of settings.json,
+/// or with `full` the whole `"schemes"` entry. Schemes can't carry a tab color
+/// (it is a profile setting), so it is given in a leading comment, which
+/// settings.json allows.
+fn windows_terminal(project: &Project, full: bool) -> String {
+    let scheme = windows_terminal_scheme(project);
+    let json = if full {
+        format!(
+            "\"schemes\": {}",
+            serde_json::to_string_pretty(&[scheme]).unwrap_or_default()
+        )
+    } else {
+        serde_json::to_string_pretty(&scheme).unwrap_or_default()
+    };
+    format!(
+        "// Profile tab color: \"tabColor\": \"{}\"\n{}\n",
+        project.colors.tab, json
+    )
+}
+
+/// The workspace settings file VS Code reads in a project directory.
+pub fn vscode_settings_path(dir: &Path) -> PathBuf {
+    dir.join(".vscode").join("settings.json")
+}
+
+/// The VS Code settings for a project's tab color: `peacock.color` for the
+/// Peacock extension, and title and activity bar colors for everyone else.
+fn vscode_settings(project: &Project) -> serde_json::Map<String, serde_json::Value> {
+    let tab = project.colors.tab;
+    let (foreground, _) = config::suggest_foreground(&tab);
+    let settings = serde_json::json!({
+        "peacock.color": tab.to_string(),
+        "workbench.colorCustomizations": {
+            "titleBar.activeBackground": tab.to_string(),
+            "titleBar.activeForeground": foreground.to_string(),
+            "titleBar.inactiveBackground": tab.to_string(),
+            "activityBar.background": tab.to_string(),
+            "activityBar.foreground": foreground.to_string(),
+        },
+    });
+    match settings {
+        serde_json::Value::Object(settings) => settings,
+        _ => serde_json::Map::new(),
+    }
+}
+
+/// Pretty-print a settings object with `indent`, ending in a newline.
+fn vscode_document(settings: &serde_json::Map<String, serde_json::Value>, indent: &str) -> String {
+    use serde::Serialize;
+    let mut buffer = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+    if settings.serialize(&mut serializer).is_err() {
+        return String::new();
+    }
+    let mut output = String::from_utf8(buffer).unwrap_or_default();
+    output.push('\n');
+    output
+}
+
+/// The indentation of the first indented line, so a rewritten file keeps its style.
+fn detect_indent(text: &str) -> Option<&str> {
+    text.lines().find_map(|line| {
+        let content = line.trim_start();
+        let indent = &line[..line.len() - content.len()];
+        (!indent.is_empty() && !content.is_empty()).then_some(indent)
+    })
+}
+
+/// The result of merging a project's colors into a settings file.
+#[derive(Debug, PartialEq)]
+pub struct VsCodeMerge {
+    /// The merged file contents
+    pub content: String,
+    /// Whether the existing file had comments, which the merge drops
+    pub dropped_comments: bool,
+}
+
+/// Merge a project's color settings into an existing settings file (JSON with
+/// comments, as VS Code allows). Other settings are kept in order, and other
+/// `workbench.colorCustomizations` entries are kept alongside the new ones.
+///
+/// # Returns
+/// * `Ok(VsCodeMerge)` - The merged contents
+/// * `Err(String)` - If the existing file isn't a JSON object
+pub fn merge_vscode_settings(
+    existing: Option<&str>,
+    project: &Project,
+) -> Result<VsCodeMerge, String> {
+    let existing = existing.filter(|text| !text.trim().is_empty());
+    let (mut settings, dropped_comments) = match existing {
+        Some(text) => {
+            let (json, had_comments) = jsonc::strip_jsonc(text);
+            match serde_json::from_str(&json) {
+                Ok(serde_json::Value::Object(settings)) => (settings, had_comments),
+                Ok(_) => return Err("settings file is not a JSON object".to_string()),
+                Err(e) => return Err(format!("invalid JSON: {}", e)),
+            }
+        }
+        None => (serde_json::Map::new(), false),
+    };
+
+    for (key, value) in vscode_settings(project) {
+        match (settings.get_mut(&key), value) {
+            (Some(serde_json::Value::Object(current)), serde_json::Value::Object(new)) => {
+                current.extend(new);
+            }
+            (_, value) => {
+                settings.insert(key, value);
+            }
+        }
+    }
+
+    let indent = existing.and_then(detect_indent).unwrap_or("    ");
+    Ok(VsCodeMerge {
+        content: vscode_document(&settings, indent),
+        dropped_comments,
+    })
+}
+
+/// A line diff between two texts: unchanged lines are indented by two spaces,
+/// removed lines start with `-` and added lines with `+`.
+pub fn line_diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence lengths of every pair of suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut output = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            output.push_str(&format!("  {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            output.push_str(&format!("- {}\n", old[i]));
+            i += 1;
+        } else {
+            output.push_str(&format!("+ {}\n", new[j]));
+            j += 1;
+        }
+    }
+    output
+}
+
+/// Where iTerm2 picks up dynamic profiles.
+fn iterm_profiles_dir(home: &Path) -> PathBuf {
+    home.join("Library")
+        .join("Application Support")
+        .join("iTerm2")
+        .join("DynamicProfiles")
+}
+
+/// Write the project's dynamic profile into iTerm2's DynamicProfiles folder,
+/// named after its GUID so each project keeps a single file.
+///
+/// # Returns
+/// * `Ok(PathBuf)` - The profile file that was written
+/// * `Err(String)` - If the folder or file can't be written
+pub fn install_iterm_profile(home: &Path, project: &Project) -> Result<PathBuf, String> {
+    let dir = iterm_profiles_dir(home);
+    fs::create_dir_all(&dir).map_err(|e| format!("Error creating {}: {}", dir.display(), e))?;
+    let path = dir.join(format!("termtint-{}.json", profile_guid(&project.dir)));
+    write_export(&path, &iterm_profile(project))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn project() -> Project {
+        Project::new(
+            PathBuf::from("/code/app"),
+            ColorConfig {
+                tab: RGB {
+                    r: 0xff,
+                    g: 0x33,
+                    b: 0x00,
+                },
+                background: RGB {
+                    r: 0x00,
+                    g: 0x80,
+                    b: 0xcc,
+                },
+            },
+            false,
+        )
+    }
+
+    #[test]
+    fn test_iterm_component() {
+        assert_eq!(iterm_component(0), 0.0);
+        assert_eq!(iterm_component(255), 1.0);
+        assert_eq!(iterm_component(51), 0.2);
+        assert_eq!(iterm_component(204), 0.8);
+        assert!((iterm_component(128) - 0.501_960_784).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_iterm_profile() {
+        let project = project();
+        let profile: serde_json::Value = serde_json::from_str(&iterm_profile(&project)).unwrap();
+        let profile = &profile["Profiles"][0];
+
+        assert_eq!(profile["Name"], "app");
+        assert_eq!(profile["Guid"], profile_guid(Path::new("/code/app")));
+        assert_eq!(profile["Use Tab Color"], true);
+        assert_eq!(profile["Tab Color"]["Red Component"], 1.0);
+        assert_eq!(profile["Tab Color"]["Green Component"], 0.2);
+        assert_eq!(profile["Tab Color"]["Blue Component"], 0.0);
+        assert_eq!(profile["Background Color"]["Blue Component"], 0.8);
+        assert_eq!(profile["Background Color"]["Color Space"], "sRGB");
+    }
+
+    #[test]
+    fn test_profile_guid_is_stable_per_directory() {
+        let guid = profile_guid(Path::new("/code/app"));
+        assert_eq!(guid, profile_guid(Path::new("/code/app")));
+        assert_ne!(guid, profile_guid(Path::new("/code/lib")));
+        let groups: Vec<usize> = guid.split('-').map(str::len).collect();
+        assert_eq!(groups, [8, 4, 4, 4, 12]);
+    }
+
+    #[test]
+    fn test_install_iterm_profile() {
+        let temp = TempDir::new().unwrap();
+        let project = project();
+
+        let path = install_iterm_profile(temp.path(), &project).unwrap();
+
+        assert!(path.starts_with(iterm_profiles_dir(temp.path())));
+        assert_eq!(fs::read_to_string(&path).unwrap(), iterm_profile(&project));
+        assert_eq!(install_iterm_profile(temp.path(), &project).unwrap(), path);
+    }
+
+    #[test]
+    fn test_kitty_theme_snapshot() {
+        assert_eq!(
+            render(Format::Kitty, &project()),
+            "# termtint: app\n\
+             background #0080cc\n\
+             tab_bar_background #0080cc\n\
+             active_tab_background #ff3300\n"
+        );
+    }
+
+    #[test]
+    fn test_kitty_theme_with_palette() {
+        let mut project = project();
+        project.palette = Some(palette::generate_palette(&project.colors));
+
+        let theme = kitty_theme(&project);
+        let lines: Vec<&str> = theme.lines().collect();
+
+        assert_eq!(lines.len(), 4 + 16);
+        assert_eq!(lines[4], "color0 #0080cc");
+        assert!(lines[19].starts_with("color15 #"));
+        for line in &lines[1..] {
+            let value = line.split_once(' ').unwrap().1;
+            assert!(
+                value.len() == 7 && value.starts_with('#') && !value[1..].contains('#'),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_export() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("theme.conf");
+        fs::write(&path, "old").unwrap();
+
+        write_export(&path, "background #0080cc\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "background #0080cc\n");
+        assert!(write_export(&temp.path().join("missing").join("theme.conf"), "").is_err());
+    }
+
+    #[test]
+    fn test_windows_terminal_snapshot() {
+        assert_eq!(
+            render(Format::WindowsTerminal { full: false }, &project()),
+            r##"// Profile tab color: "tabColor": "#ff3300"
+{
+  "name": "termtint app",
+  "background": "#0080cc"
+}
+"##
+        );
+        assert_eq!(
+            render(Format::WindowsTerminal { full: true }, &project()),
+            r##"// Profile tab color: "tabColor": "#ff3300"
+"schemes": [
+  {
+    "name": "termtint app",
+    "background": "#0080cc"
+  }
+]
+"##
+        );
+    }
+
+    #[test]
+    fn test_windows_terminal_scheme_escapes_name_and_adds_palette() {
+        let mut project = project();
+        project.name = r#"my "app"\"#.to_string();
+        project.palette = Some(palette::generate_palette(&project.colors));
+
+        let output = windows_terminal(&project, false);
+        let json = output.split_once('\n').unwrap().1;
+        let scheme: serde_json::Value = serde_json::from_str(json).unwrap();
+
+        assert_eq!(scheme["name"], r#"termtint my "app"\"#);
+        assert_eq!(scheme["black"], "#0080cc");
+        assert_eq!(scheme.as_object().unwrap().len(), 2 + 16);
+    }
+
+    #[test]
+    fn test_alacritty_theme_snapshot() {
+        assert_eq!(
+            render(Format::Alacritty, &project()),
+            "# termtint: app\n\
+             # Add this file to `import` under [general] in alacritty.toml\n\
+             \n\
+             [colors.primary]\n\
+             background = \"#0080cc\"\n"
+        );
+    }
+
+    #[test]
+    fn test_alacritty_theme_with_palette_is_valid_toml() {
+        let mut project = project();
+        let palette = palette::generate_palette(&project.colors);
+        project.palette = Some(palette);
+
+        let theme: toml::Table = alacritty_theme(&project).parse().unwrap();
+        let colors = &theme["colors"];
+
+        assert_eq!(colors["primary"]["background"].as_str(), Some("#0080cc"));
+        assert_eq!(colors["normal"]["black"].as_str(), Some("#0080cc"));
+        assert_eq!(
+            colors["bright"]["white"].as_str(),
+            Some(palette[15].to_string().as_str())
+        );
+        assert_eq!(colors["normal"].as_table().unwrap().len(), 8);
+    }
+
+    #[test]
+    fn test_vscode_settings_new_file() {
+        let merge = merge_vscode_settings(None, &project()).unwrap();
+        let settings: serde_json::Value = serde_json::from_str(&merge.content).unwrap();
+
+        assert!(!merge.dropped_comments);
+        assert_eq!(settings["peacock.color"], "#ff3300");
+        let customizations = &settings["workbench.colorCustomizations"];
+        assert_eq!(customizations["titleBar.activeBackground"], "#ff3300");
+        assert_eq!(customizations["activityBar.background"], "#ff3300");
+        assert_eq!(
+            customizations["titleBar.activeForeground"],
+            customizations["activityBar.foreground"]
+        );
+        assert!(merge.content.contains("\n    \"peacock.color\""));
+        assert_eq!(merge.content, render(Format::VsCode, &project()));
+    }
+
+    #[test]
+    fn test_vscode_settings_merge_preserves_existing_keys() {
+        let existing = r##"{
+  "editor.tabSize": 2,
+  "peacock.color": "#000000",
+  "workbench.colorCustomizations": {
+    "statusBar.background": "#123456",
+    "titleBar.activeBackground": "#000000"
+  },
+  "files.exclude": {"target": true}
+}
+"##;
+        let merge = merge_vscode_settings(Some(existing), &project()).unwrap();
+        let settings: serde_json::Value = serde_json::from_str(&merge.content).unwrap();
+
+        assert_eq!(settings["editor.tabSize"], 2);
+        assert_eq!(settings["files.exclude"]["target"], true);
+        assert_eq!(settings["peacock.color"], "#ff3300");
+        let customizations = &settings["workbench.colorCustomizations"];
+        assert_eq!(customizations["statusBar.background"], "#123456");
+        assert_eq!(customizations["titleBar.activeBackground"], "#ff3300");
+
+        // Key order and two-space indentation survive the rewrite
+        let keys: Vec<&String> = settings.as_object().unwrap().keys().collect();
+        assert_eq!(
+            keys,
+            [
+                "editor.tabSize",
+                "peacock.color",
+                "workbench.colorCustomizations",
+                "files.exclude"
+            ]
+        );
+        assert!(merge.content.starts_with("{\n  \"editor.tabSize\": 2,\n"));
+
+        // Merging again changes nothing
+        let again = merge_vscode_settings(Some(&merge.content), &project()).unwrap();
+        assert_eq!(again.content, merge.content);
+    }
+
+    #[test]
+    fn test_vscode_settings_merge_jsonc() {
+        let existing = "{\n    // Keep tabs\n    \"editor.insertSpaces\": false,\n}\n";
+        let merge = merge_vscode_settings(Some(existing), &project()).unwrap();
+        let settings: serde_json::Value = serde_json::from_str(&merge.content).unwrap();
+
+        assert!(merge.dropped_comments);
+        assert_eq!(settings["editor.insertSpaces"], false);
+        assert_eq!(settings["peacock.color"], "#ff3300");
+    }
+
+    #[test]
+    fn test_vscode_settings_merge_rejects_non_objects() {
+        assert!(merge_vscode_settings(Some("[1, 2]"), &project()).is_err());
+        assert!(merge_vscode_settings(Some("{\"a\": "), &project()).is_err());
+        assert!(merge_vscode_settings(Some("  \n"), &project()).is_ok());
+    }
+
+    #[test]
+    fn test_line_diff() {
+        assert_eq!(
+            line_diff("{\n  \"a\": 1\n}\n", "{\n  \"a\": 1,\n  \"b\": 2\n}\n"),
+            "  {\n-   \"a\": 1\n+   \"a\": 1,\n+   \"b\": 2\n  }\n"
+        );
+        assert_eq!(line_diff("", "x\n"), "+ x\n");
+    }
+
+    /// The fields the base16 spec requires in a scheme file.
+    const BASE16_SPEC_FIELDS: [&str; 18] = [
+        "scheme", "author", "base00", "base01", "base02", "base03", "base04", "base05", "base06",
+        "base07", "base08", "base09", "base0A", "base0B", "base0C", "base0D", "base0E", "base0F",
+    ];
+
+    #[test]
+    fn test_base16_scheme_snapshot() {
+        assert_eq!(
+            render(
+                Format::Base16 {
+                    tab_slot: BASE16_DEFAULT_TAB_SLOT
+                },
+                &project()
+            ),
+            "scheme: \"termtint app\"\n\
+             author: \"termtint\"\n\
+             base00: \"0080cc\"\n\
+             base01: \"1c679e\"\n\
+             base02: \"244f72\"\n\
+             base03: \"243849\"\n\
+             base04: \"877a8b\"\n\
+             base05: \"decdc9\"\n\
+             base06: \"ede0dd\"\n\
+             base07: \"fcf3f1\"\n\
+             base08: \"e3645e\"\n\
+             base09: \"ee9138\"\n\
+             base0A: \"e1c34b\"\n\
+             base0B: \"61bd67\"\n\
+             base0C: \"e18db1\"\n\
+             base0D: \"ff3300\"\n\
+             base0E: \"c98a2b\"\n\
+             base0F: \"93581f\"\n"
+        );
+    }
+
+    #[test]
+    fn test_base16_scheme_matches_spec_fields() {
+        let mut project = project();
+        project.name = "my \"app\"".to_string();
+        let scheme = base16_scheme(&project, 0xE);
+
+        let fields: Vec<(&str, &str)> = scheme
+            .lines()
+            .map(|line| line.split_once(": ").unwrap())
+            .collect();
+        let keys: Vec<&str> = fields.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, BASE16_SPEC_FIELDS);
+        assert_eq!(fields[0].1, r#""termtint my \"app\"""#);
+        for (_, value) in &fields[2..] {
+            let hex = value.trim_matches('"');
+            assert!(hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+        }
+        assert_eq!(fields[2 + 0xE].1, "\"ff3300\"");
+    }
+
+    #[test]
+    fn test_parse_base16_slot() {
+        assert_eq!(parse_base16_slot("base0D"), Some(0xD));
+        assert_eq!(parse_base16_slot("BASE0a"), Some(0xA));
+        assert_eq!(parse_base16_slot("base01"), Some(0x1));
+        assert_eq!(parse_base16_slot("base00"), None);
+        assert_eq!(parse_base16_slot("base10"), None);
+        assert_eq!(parse_base16_slot("base0"), None);
+        assert_eq!(parse_base16_slot("0D"), None);
+        assert_eq!(base16_slot_name(0xA), "base0A");
+    }
+}
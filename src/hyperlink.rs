@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use crate::config;
+use crate::context::Context;
+use crate::user_config::Hyperlinks;
+
+/// `TERM_PROGRAM` values of terminals that render OSC 8 hyperlinks.
+const SUPPORTING_TERMINALS: &[&str] = &["iTerm.app", "WezTerm", "vscode", "ghostty", "Hyper"];
+
+/// Whether output should contain hyperlinks. `auto` links only when stdout is a
+/// terminal whose `TERM_PROGRAM` is known to support them, so piped output and
+/// terminals that would print the escapes literally stay plain.
+///
+/// # Arguments
+/// * `ctx` - The invocation context, for `TERM_PROGRAM`
+/// * `setting` - The `hyperlinks` user config value
+/// * `stdout_is_terminal` - Whether stdout is a terminal
+pub fn enabled(ctx: &Context, setting: Hyperlinks, stdout_is_terminal: bool) -> bool {
+    match setting {
+        Hyperlinks::Always => true,
+        Hyperlinks::Never => false,
+        Hyperlinks::Auto => {
+            stdout_is_terminal
+                && ctx
+                    .var("TERM_PROGRAM")
+                    .is_some_and(|program| SUPPORTING_TERMINALS.contains(&program))
+        }
+    }
+}
+
+/// Wrap `text` in an OSC 8 hyperlink to `url`.
+pub fn link(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// A `file://` URL for an absolute path, percent-encoding everything but
+/// unreserved characters and separators. The hostname is included so terminals
+/// don't open a path from an SSH session on the local machine.
+fn file_url(path: &Path, host: &str) -> String {
+    let mut url = format!("file://{}", host);
+    for &byte in path.to_string_lossy().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+/// Display a path, as a hyperlink to the file when `enabled`.
+pub fn path(path: &Path, enabled: bool) -> String {
+    let text = path.display().to_string();
+    if !enabled {
+        return text;
+    }
+    link(
+        &file_url(path, &config::hostname().unwrap_or_default()),
+        &text,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_framing() {
+        assert_eq!(
+            link("file://host/tmp/a", "a"),
+            "\x1b]8;;file://host/tmp/a\x1b\\a\x1b]8;;\x1b\\"
+        );
+        assert_eq!(
+            file_url(Path::new("/code/my app/.termtint"), "mac"),
+            "file://mac/code/my%20app/.termtint"
+        );
+        assert_eq!(
+            file_url(Path::new("/code/café"), ""),
+            "file:///code/caf%C3%A9"
+        );
+    }
+
+    #[test]
+    fn test_plain_fallback() {
+        let file = Path::new("/code/app/.termtint");
+        assert_eq!(path(file, false), "/code/app/.termtint");
+        assert!(path(file, true).starts_with("\x1b]8;;file://"));
+
+        let home = Path::new("/home/me");
+        let iterm = Context::new(home, home).with_var("TERM_PROGRAM", "iTerm.app");
+        let plain = Context::new(home, home).with_var("TERM_PROGRAM", "Apple_Terminal");
+        assert!(enabled(&iterm, Hyperlinks::Auto, true));
+        assert!(!enabled(&iterm, Hyperlinks::Auto, false));
+        assert!(!enabled(&plain, Hyperlinks::Auto, true));
+        assert!(enabled(&plain, Hyperlinks::Always, false));
+        assert!(!enabled(&iterm, Hyperlinks::Never, true));
+    }
+}
@@ -1,14 +1,24 @@
-use std::env;
 use std::fs;
+use std::io;
+use std::path::Path;
 
+#[cfg(feature = "reroll")]
 use rand::Rng;
 
 use crate::config;
+use crate::context::Context;
+use crate::error::TermtintError;
 use crate::iterm;
+#[cfg(feature = "itermcolors")]
+use crate::itermcolors;
+use crate::palette;
+#[cfg(feature = "reroll")]
+use crate::state;
 use crate::user_config::UserConfig;
 
 /// Render a die with the given value (1-6) using Unicode box-drawing characters.
 /// The die is rendered with the background color as the die face and the tab color for the dots.
+#[cfg(feature = "reroll")]
 fn render_die(value: u8, tab_color: &config::RGB, bg_color: &config::RGB) -> String {
     let bg = format!("\x1b[48;2;{};{};{}m", bg_color.r, bg_color.g, bg_color.b);
     let fg = format!("\x1b[38;2;{};{};{}m", tab_color.r, tab_color.g, tab_color.b);
@@ -48,28 +58,54 @@ fn render_die(value: u8, tab_color: &config::RGB, bg_color: &config::RGB) -> Str
 /// Creates .termtint if it doesn't exist.
 ///
 /// # Arguments
+/// * `ctx` - The invocation context, for the working directory and recent hues
 /// * `verbose` - If true, print directory path
+/// * `spread` - If true, keep the new hue away from recently generated hues
+/// * `min_delta` - Override for the minimum Oklab ΔE from the current color
 /// * `user_config` - User configuration for color generation
 ///
 /// # Returns
 /// * `Ok(())` if successful
-/// * `Err(String)` with error message if failed
-pub fn cmd_reroll(verbose: bool, user_config: &UserConfig) -> Result<(), String> {
+/// * `Err(TermtintError)` with error message if failed
+#[cfg(feature = "reroll")]
+pub fn cmd_reroll(
+    ctx: &Context,
+    verbose: bool,
+    spread: bool,
+    min_delta: Option<f32>,
+    user_config: &UserConfig,
+) -> Result<(), TermtintError> {
     // 1. Get current directory
-    let current_dir =
-        env::current_dir().map_err(|e| format!("Error getting current directory: {}", e))?;
+    let current_dir = ctx.current_dir()?;
 
     let config_path = current_dir.join(".termtint");
 
-    // 2. Generate random color
-    let rgb = config::generate_random_color(user_config);
+    // 2. Generate a random color distinct from the current one, spread away from
+    //    recent hues unless disabled
+    let current = config::parse_config(&config_path, user_config)
+        .ok()
+        .map(|c| c.tab);
+    let recent_hues = if spread {
+        state::read_recent_hues(ctx)
+    } else {
+        Vec::new()
+    };
+    let reroll = config::reroll_color(
+        user_config,
+        &recent_hues,
+        current.as_ref(),
+        min_delta.unwrap_or(user_config.reroll_min_delta),
+    );
+    if spread {
+        state::record_recent_hue(ctx, reroll.hue);
+    }
+    let rgb = reroll.color;
 
     // 4. Format as hex string (RGB has Display trait that outputs #rrggbb)
     let hex_color = format!("{}\n", rgb);
 
     // 5. Write hex color + newline to .termtint
-    fs::write(&config_path, &hex_color)
-        .map_err(|e| format!("Error writing .termtint file: {}", e))?;
+    fs::write(&config_path, &hex_color).map_err(|e| write_error(current_dir, &hex_color, &e))?;
 
     // 6. Print success message (directory only with verbose)
     if verbose {
@@ -108,7 +144,11 @@ pub fn cmd_reroll(verbose: bool, user_config: &UserConfig) -> Result<(), String>
 
     // 7. Apply colors immediately
     if let Ok(color_config) = config::parse_config(&config_path, user_config) {
-        iterm::apply_colors(&color_config);
+        let color_config = config::apply_temperature(&color_config, user_config.temperature_shift);
+        iterm::apply_colors(&color_config, iterm::Capabilities::for_config(user_config));
+        if user_config.generate_palette {
+            iterm::set_palette(&palette::generate_palette(&color_config));
+        }
     }
 
     Ok(())
@@ -117,49 +157,52 @@ pub fn cmd_reroll(verbose: bool, user_config: &UserConfig) -> Result<(), String>
 /// Initialize a .termtint file in the current directory.
 ///
 /// # Arguments
+/// * `ctx` - The invocation context, whose working directory gets the file
 /// * `color` - Optional hex color for the tab (e.g., "#ff5500")
 /// * `background` - Optional custom background color (hex)
 /// * `force` - If true, overwrite existing .termtint file
+/// * `keep_literal` - Write the colors as given instead of as hex
 /// * `user_config` - User configuration for color generation
 ///
 /// # Returns
 /// * `Ok(())` if successful
-/// * `Err(String)` with error message if failed
+/// * `Err(TermtintError)` with error message if failed
 pub fn cmd_init(
+    ctx: &Context,
     color: Option<String>,
     background: Option<String>,
     force: bool,
+    keep_literal: bool,
     user_config: &UserConfig,
-) -> Result<(), String> {
+) -> Result<(), TermtintError> {
     // 1. Get current directory
-    let current_dir =
-        env::current_dir().map_err(|e| format!("Error getting current directory: {}", e))?;
+    let current_dir = ctx.current_dir()?;
 
     let config_path = current_dir.join(".termtint");
 
     // 2. Check if .termtint exists
     if config_path.exists() && !force {
-        return Err(
+        return Err(TermtintError::Usage(
             "Error: .termtint already exists in this directory\nUse --force to overwrite"
                 .to_string(),
-        );
+        ));
     }
 
     // 3. Validate color arg if provided
     if let Some(ref color_str) = color {
-        config::parse_color(color_str).map_err(|e| format!("Invalid color: {}", e))?;
+        config::parse_color(color_str).map_err(|e| e.with_prefix("Invalid color: "))?;
     }
 
     // 4. Validate background arg - requires color
     if background.is_some() && color.is_none() {
-        return Err(
+        return Err(TermtintError::Usage(
             "Error: --background requires an explicit tab color\nProvide a color argument or omit --background".to_string()
-        );
+        ));
     }
 
     // Validate background hex if provided
     if let Some(ref bg_str) = background {
-        config::parse_color(bg_str).map_err(|e| format!("Invalid background color: {}", e))?;
+        config::parse_color(bg_str).map_err(|e| e.with_prefix("Invalid background color: "))?;
     }
 
     // 5. Generate file content based on arguments
@@ -167,102 +210,208 @@ pub fn cmd_init(
         // No color: write "auto"
         (None, None) => "auto\n".to_string(),
 
-        // Color only: write the hex color
-        (Some(c), None) => {
-            // Parse color to RGB and use Display trait to format as hex
-            let rgb = config::parse_color(&c).map_err(|e| format!("Invalid color: {}", e))?;
-            format!("{}\n", rgb)
-        }
+        // Color only: write the color alone
+        (Some(c), None) => format!("{}\n", written_color(&c, keep_literal, "Invalid color: ")?),
 
         // Color + background: write TOML format
         (Some(c), Some(bg)) => {
-            // Parse colors to RGB and use Display trait to format as hex
-            let rgb_color = config::parse_color(&c).map_err(|e| format!("Invalid color: {}", e))?;
-            let rgb_bg =
-                config::parse_color(&bg).map_err(|e| format!("Invalid background color: {}", e))?;
-            format!("tab = \"{}\"\nbackground = \"{}\"\n", rgb_color, rgb_bg)
+            let tab = written_color(&c, keep_literal, "Invalid color: ")?;
+            let background = written_color(&bg, keep_literal, "Invalid background color: ")?;
+            format!(
+                "tab = {}\nbackground = {}\n",
+                toml::Value::from(tab),
+                toml::Value::from(background)
+            )
         }
 
         // This case is already handled by validation above
         (None, Some(_)) => unreachable!(),
     };
 
-    // 6. Write to .termtint file
-    fs::write(&config_path, content).map_err(|e| format!("Error writing .termtint file: {}", e))?;
+    // 6. Write the file and apply its colors
+    create_config(current_dir, &content, user_config)
+}
+
+/// A color argument as `init` writes it: the hex it parses to (8-digit when
+/// translucent), or with `keep_literal` the argument itself, so `tomato` or
+/// `hsl(...)` stays readable in the file. `prefix` starts the error for an
+/// invalid color.
+fn written_color(color: &str, keep_literal: bool, prefix: &str) -> Result<String, TermtintError> {
+    let (rgb, alpha) = config::parse_color_with_alpha(color).map_err(|e| e.with_prefix(prefix))?;
+    if keep_literal {
+        Ok(color.trim().to_string())
+    } else {
+        Ok(rgb.hex_with_alpha(alpha))
+    }
+}
 
-    // 7. Print success message
-    println!("Created .termtint in {}", current_dir.display());
+/// Write a new `.termtint` in `dir`, report it, and apply its colors immediately.
+fn create_config(dir: &Path, content: &str, user_config: &UserConfig) -> Result<(), TermtintError> {
+    let config_path = dir.join(".termtint");
+    fs::write(&config_path, content).map_err(|e| write_error(dir, content, &e))?;
+
+    println!("Created .termtint in {}", dir.display());
 
-    // 8. Apply colors immediately
     if let Ok(color_config) = config::parse_config(&config_path, user_config) {
-        iterm::apply_colors(&color_config);
+        let color_config = config::apply_temperature(&color_config, user_config.temperature_shift);
+        iterm::apply_colors(&color_config, iterm::Capabilities::for_config(user_config));
+        if user_config.generate_palette {
+            iterm::set_palette(&palette::generate_palette(&color_config));
+        }
     }
 
-    // 9. Return Ok
     Ok(())
 }
 
+/// The error for a `.termtint` with `content` that couldn't be written in `dir`.
+/// When the directory is read-only (a read-only checkout or mounted volume), it
+/// says how to color the directory from the user config instead.
+fn write_error(dir: &Path, content: &str, error: &io::Error) -> TermtintError {
+    let problem = if error.raw_os_error() == Some(libc::EROFS) {
+        "is on a read-only filesystem"
+    } else if error.kind() == io::ErrorKind::PermissionDenied {
+        "isn't writable"
+    } else {
+        return TermtintError::io("Error writing .termtint file", error);
+    };
+    let color = match config::detect_format(content) {
+        config::ConfigFormat::SimpleColor => format!(" --color '{}'", content.trim()),
+        _ => String::new(),
+    };
+    TermtintError::Io {
+        context: "Error writing .termtint file".to_string(),
+        kind: error.kind(),
+        message: format!(
+            "{}\n{} {}; to color it without a .termtint, add it as a trigger path:\n  termtint trigger add '{}'{}",
+            error,
+            dir.display(),
+            problem,
+            dir.display(),
+            color
+        ),
+    }
+}
+
+/// Initialize a .termtint in the current directory that takes its background
+/// (and, without `color`, its tab color) from an `.itermcolors` preset.
+///
+/// The preset is referenced rather than copied, so later edits to it apply. It
+/// is written as an absolute path, starting with `~/` when inside the home
+/// directory.
+///
+/// # Arguments
+/// * `ctx` - The invocation context, for the working and home directories
+/// * `preset` - The `.itermcolors` file, relative to the working directory
+/// * `color` - Optional tab color, required when the preset has no tab color
+/// * `force` - If true, overwrite existing .termtint file
+/// * `keep_literal` - Write the tab color as given instead of as hex
+/// * `user_config` - User configuration for color generation
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(TermtintError)` if the preset can't be read or lacks a needed color
+#[cfg(feature = "itermcolors")]
+pub fn cmd_init_from_itermcolors(
+    ctx: &Context,
+    preset: &Path,
+    color: Option<String>,
+    force: bool,
+    keep_literal: bool,
+    user_config: &UserConfig,
+) -> Result<(), TermtintError> {
+    let current_dir = ctx.current_dir()?;
+    if current_dir.join(".termtint").exists() && !force {
+        return Err(TermtintError::Usage(
+            "Error: .termtint already exists in this directory\nUse --force to overwrite"
+                .to_string(),
+        ));
+    }
+
+    let preset = config::canonical_path(&current_dir.join(preset));
+    let colors = itermcolors::read_itermcolors(&preset).map_err(|e| e.with_prefix("Error: "))?;
+    itermcolors::preset_color(&colors, itermcolors::BACKGROUND_KEY, &preset)
+        .map_err(|e| e.with_prefix("Error: "))?;
+    let tab = match color {
+        Some(c) => Some(written_color(&c, keep_literal, "Invalid color: ")?),
+        None => {
+            itermcolors::preset_color(&colors, itermcolors::DEFAULT_TAB_KEY, &preset).map_err(
+                |e| {
+                    TermtintError::parse(
+                        Some(preset.clone()),
+                        format!("Error: {}\nProvide a tab color argument to use with it", e),
+                    )
+                },
+            )?;
+            None
+        }
+    };
+
+    let reference = match (preset.strip_prefix(ctx.home_dir()), &ctx.home) {
+        (Ok(rest), Some(_)) => format!("~/{}", rest.display()),
+        _ => preset.display().to_string(),
+    };
+    let mut content = format!("preset = {}\n", toml::Value::from(reference));
+    if let Some(tab) = tab {
+        content.push_str(&format!("tab = {}\n", toml::Value::from(tab)));
+    }
+    create_config(current_dir, &content, user_config)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
     use std::fs;
-    use std::sync::Mutex;
     use tempfile::TempDir;
 
-    // Mutex to ensure tests that change current directory run serially
-    static TEST_MUTEX: Mutex<()> = Mutex::new(());
-
     #[test]
     fn test_init_creates_auto_file() {
-        let _lock = TEST_MUTEX.lock().unwrap();
         let temp = TempDir::new().unwrap();
-        let original_dir = env::current_dir().unwrap();
-        env::set_current_dir(temp.path()).unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
         let user_config = UserConfig::default();
-        let result = cmd_init(None, None, false, &user_config);
+        let result = cmd_init(&ctx, None, None, false, false, &user_config);
         assert!(result.is_ok());
 
         let config_path = temp.path().join(".termtint");
         assert!(config_path.exists());
         let content = fs::read_to_string(&config_path).unwrap();
         assert_eq!(content, "auto\n");
-
-        env::set_current_dir(original_dir).unwrap();
     }
 
     #[test]
     fn test_init_creates_hex_file() {
-        let _lock = TEST_MUTEX.lock().unwrap();
         let temp = TempDir::new().unwrap();
-        let original_dir = env::current_dir().unwrap();
-        env::set_current_dir(temp.path()).unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
         let user_config = UserConfig::default();
-        let result = cmd_init(Some("#ff5500".to_string()), None, false, &user_config);
+        let result = cmd_init(
+            &ctx,
+            Some("#ff5500".to_string()),
+            None,
+            false,
+            false,
+            &user_config,
+        );
         assert!(result.is_ok());
 
         let config_path = temp.path().join(".termtint");
         assert!(config_path.exists());
         let content = fs::read_to_string(&config_path).unwrap();
         assert_eq!(content, "#ff5500\n");
-
-        env::set_current_dir(original_dir).unwrap();
     }
 
     #[test]
     fn test_init_creates_toml_file() {
-        let _lock = TEST_MUTEX.lock().unwrap();
         let temp = TempDir::new().unwrap();
-        let original_dir = env::current_dir().unwrap();
-        env::set_current_dir(temp.path()).unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
         let user_config = UserConfig::default();
         let result = cmd_init(
+            &ctx,
             Some("#00ff00".to_string()),
             Some("#001100".to_string()),
             false,
+            false,
             &user_config,
         );
         assert!(result.is_ok());
@@ -271,16 +420,12 @@ mod tests {
         assert!(config_path.exists());
         let content = fs::read_to_string(&config_path).unwrap();
         assert_eq!(content, "tab = \"#00ff00\"\nbackground = \"#001100\"\n");
-
-        env::set_current_dir(original_dir).unwrap();
     }
 
     #[test]
     fn test_init_fails_when_file_exists() {
-        let _lock = TEST_MUTEX.lock().unwrap();
         let temp = TempDir::new().unwrap();
-        let original_dir = env::current_dir().unwrap();
-        env::set_current_dir(temp.path()).unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
         // Create .termtint first
         let config_path = temp.path().join(".termtint");
@@ -288,19 +433,17 @@ mod tests {
 
         // Try to init without force
         let user_config = UserConfig::default();
-        let result = cmd_init(None, None, false, &user_config);
+        let result = cmd_init(&ctx, None, None, false, false, &user_config);
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("already exists"));
-
-        env::set_current_dir(original_dir).unwrap();
+        let error = result.unwrap_err();
+        assert_eq!(error.exit_code(), crate::error::EXIT_USAGE);
+        assert!(error.to_string().contains("already exists"));
     }
 
     #[test]
     fn test_init_force_overwrites() {
-        let _lock = TEST_MUTEX.lock().unwrap();
         let temp = TempDir::new().unwrap();
-        let original_dir = env::current_dir().unwrap();
-        env::set_current_dir(temp.path()).unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
         // Create .termtint first
         let config_path = temp.path().join(".termtint");
@@ -308,61 +451,160 @@ mod tests {
 
         // Init with force should succeed
         let user_config = UserConfig::default();
-        let result = cmd_init(Some("#ff5500".to_string()), None, true, &user_config);
+        let result = cmd_init(
+            &ctx,
+            Some("#ff5500".to_string()),
+            None,
+            true,
+            false,
+            &user_config,
+        );
         assert!(result.is_ok());
 
         // Verify content was overwritten
         let content = fs::read_to_string(&config_path).unwrap();
         assert_eq!(content, "#ff5500\n");
+    }
+
+    #[test]
+    fn test_init_force_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = TempDir::new().unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
+        let config_path = temp.path().join(".termtint");
+        let user_config = UserConfig::default();
+        let mode = || fs::metadata(&config_path).unwrap().permissions().mode() & 0o777;
+
+        fs::write(&config_path, "auto\n").unwrap();
+        fs::set_permissions(&config_path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        cmd_init(
+            &ctx,
+            Some("#ff5500".to_string()),
+            None,
+            true,
+            false,
+            &user_config,
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), "#ff5500\n");
+        assert_eq!(mode(), 0o640);
+    }
+
+    #[test]
+    fn test_write_error_suggests_trigger_path() {
+        let dir = Path::new("/mnt/checkout");
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let read_only = io::Error::from_raw_os_error(libc::EROFS);
+
+        let error = write_error(dir, "#ff5500\n", &denied);
+        assert_eq!(error.exit_code(), crate::error::EXIT_IO);
+        assert_eq!(
+            error.to_string(),
+            "Error writing .termtint file: permission denied\n/mnt/checkout isn't writable; to color it without a .termtint, add it as a trigger path:\n  termtint trigger add '/mnt/checkout' --color '#ff5500'"
+        );
+        let error = write_error(dir, "auto\n", &read_only).to_string();
+        assert!(
+            error.contains("/mnt/checkout is on a read-only filesystem"),
+            "{}",
+            error
+        );
+        assert!(
+            error.ends_with("termtint trigger add '/mnt/checkout'"),
+            "{}",
+            error
+        );
+        assert!(!write_error(dir, "tab = \"#00ff00\"\n", &denied)
+            .to_string()
+            .contains("--color"));
+
+        let full = io::Error::from_raw_os_error(libc::ENOSPC);
+        assert!(!write_error(dir, "auto\n", &full)
+            .to_string()
+            .contains("trigger add"));
+    }
 
-        env::set_current_dir(original_dir).unwrap();
+    #[test]
+    fn test_init_in_read_only_directory() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = TempDir::new().unwrap();
+        let checkout = temp.path().join("checkout");
+        fs::create_dir(&checkout).unwrap();
+        fs::set_permissions(&checkout, fs::Permissions::from_mode(0o555)).unwrap();
+        // Permissions don't apply to root
+        let writable = fs::write(checkout.join("probe"), "").is_ok();
+        let ctx = Context::new(temp.path(), &checkout);
+
+        let result = cmd_init(&ctx, None, None, false, false, &UserConfig::default());
+        fs::set_permissions(&checkout, fs::Permissions::from_mode(0o755)).unwrap();
+        if writable {
+            return;
+        }
+        let error = result.unwrap_err().to_string();
+        assert!(
+            error.contains(&format!(
+                "{} isn't writable; to color it without a .termtint",
+                checkout.display()
+            )),
+            "{}",
+            error
+        );
+        assert!(!checkout.join(".termtint").exists());
     }
 
     #[test]
     fn test_init_rejects_invalid_color() {
-        let _lock = TEST_MUTEX.lock().unwrap();
         let temp = TempDir::new().unwrap();
-        let original_dir = env::current_dir().unwrap();
-        env::set_current_dir(temp.path()).unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
         let user_config = UserConfig::default();
-        let result = cmd_init(Some("notacolor".to_string()), None, false, &user_config);
+        let result = cmd_init(
+            &ctx,
+            Some("notacolor".to_string()),
+            None,
+            false,
+            false,
+            &user_config,
+        );
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Invalid color"));
-
-        env::set_current_dir(original_dir).unwrap();
+        let error = result.unwrap_err();
+        assert!(matches!(error, TermtintError::InvalidColor(_)));
+        assert!(error.to_string().contains("Invalid color"));
     }
 
     #[test]
     fn test_init_rejects_background_without_color() {
-        let _lock = TEST_MUTEX.lock().unwrap();
         let temp = TempDir::new().unwrap();
-        let original_dir = env::current_dir().unwrap();
-        env::set_current_dir(temp.path()).unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
         let user_config = UserConfig::default();
-        let result = cmd_init(None, Some("#001100".to_string()), false, &user_config);
+        let result = cmd_init(
+            &ctx,
+            None,
+            Some("#001100".to_string()),
+            false,
+            false,
+            &user_config,
+        );
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
+            .to_string()
             .contains("--background requires an explicit tab color"));
-
-        env::set_current_dir(original_dir).unwrap();
     }
 
+    #[cfg(feature = "reroll")]
     #[test]
     fn test_reroll_creates_hex_file() {
-        let _lock = TEST_MUTEX.lock().unwrap();
         let temp = TempDir::new().unwrap();
-        let original_dir = env::current_dir().unwrap();
-        env::set_current_dir(temp.path()).unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
         // Create initial .termtint file
         let config_path = temp.path().join(".termtint");
         fs::write(&config_path, "#ff5500\n").unwrap();
 
         let user_config = UserConfig::default();
-        let result = cmd_reroll(false, &user_config);
+        let result = cmd_reroll(&ctx, false, false, None, &user_config);
         assert!(result.is_ok());
 
         // Verify file exists and contains a valid hex color
@@ -373,23 +615,20 @@ mod tests {
 
         // Verify the color changed from the original
         assert_ne!(content, "#ff5500\n");
-
-        env::set_current_dir(original_dir).unwrap();
     }
 
+    #[cfg(feature = "reroll")]
     #[test]
     fn test_reroll_creates_file_if_missing() {
-        let _lock = TEST_MUTEX.lock().unwrap();
         let temp = TempDir::new().unwrap();
-        let original_dir = env::current_dir().unwrap();
-        env::set_current_dir(temp.path()).unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
         // No .termtint file exists
         let config_path = temp.path().join(".termtint");
         assert!(!config_path.exists());
 
         let user_config = UserConfig::default();
-        let result = cmd_reroll(false, &user_config);
+        let result = cmd_reroll(&ctx, false, false, None, &user_config);
         assert!(result.is_ok());
 
         // Verify file was created with a valid hex color
@@ -397,16 +636,13 @@ mod tests {
         let content = fs::read_to_string(&config_path).unwrap();
         assert!(content.starts_with('#'));
         assert_eq!(content.len(), 8); // #rrggbb\n
-
-        env::set_current_dir(original_dir).unwrap();
     }
 
+    #[cfg(feature = "reroll")]
     #[test]
     fn test_reroll_produces_different_colors() {
-        let _lock = TEST_MUTEX.lock().unwrap();
         let temp = TempDir::new().unwrap();
-        let original_dir = env::current_dir().unwrap();
-        env::set_current_dir(temp.path()).unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
         let config_path = temp.path().join(".termtint");
         let user_config = UserConfig::default();
@@ -414,7 +650,7 @@ mod tests {
         // Generate multiple colors by re-rolling
         let mut colors = Vec::new();
         for _ in 0..5 {
-            cmd_reroll(false, &user_config).unwrap();
+            cmd_reroll(&ctx, false, false, None, &user_config).unwrap();
             let content = fs::read_to_string(&config_path).unwrap();
             colors.push(content.trim().to_string());
         }
@@ -427,7 +663,167 @@ mod tests {
             "Should generate different random colors, but all were {}",
             first_color
         );
+    }
+
+    #[test]
+    fn test_init_preserves_alpha() {
+        let temp = TempDir::new().unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
 
-        env::set_current_dir(original_dir).unwrap();
+        let user_config = UserConfig::default();
+        let result = cmd_init(
+            &ctx,
+            Some("rgba(51, 102, 255, 0.5)".to_string()),
+            None,
+            false,
+            false,
+            &user_config,
+        );
+        assert!(result.is_ok());
+
+        let content = fs::read_to_string(temp.path().join(".termtint")).unwrap();
+        assert_eq!(content, "#3366ff80\n");
+    }
+
+    #[test]
+    fn test_init_accepts_modern_css_syntax() {
+        let temp = TempDir::new().unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
+
+        let user_config = UserConfig::default();
+        let result = cmd_init(
+            &ctx,
+            Some("rgb(51 102 255)".to_string()),
+            Some("oklch(20% 0.05 255)".to_string()),
+            false,
+            false,
+            &user_config,
+        );
+        assert!(result.is_ok());
+
+        let content = fs::read_to_string(temp.path().join(".termtint")).unwrap();
+        assert!(content.starts_with("tab = \"#3366ff\"\n"));
+        assert!(content.contains("background = \"#"));
+    }
+
+    #[cfg(feature = "named-colors")]
+    #[test]
+    fn test_init_keep_literal_named() {
+        let temp = TempDir::new().unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
+        let user_config = UserConfig::default();
+        let path = temp.path().join(".termtint");
+
+        cmd_init(
+            &ctx,
+            Some(" tomato ".to_string()),
+            None,
+            false,
+            true,
+            &user_config,
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "tomato\n");
+        let parsed = config::parse_config(&path, &user_config).unwrap();
+        assert_eq!(parsed.tab.to_string(), "#ff6347");
+
+        let (tab, background) = ("Tomato".to_string(), "midnightblue".to_string());
+        cmd_init(&ctx, Some(tab), Some(background), true, true, &user_config).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "tab = \"Tomato\"\nbackground = \"midnightblue\"\n"
+        );
+        let parsed = config::parse_config(&path, &user_config).unwrap();
+        assert_eq!(parsed.tab.to_string(), "#ff6347");
+        assert_eq!(parsed.background.to_string(), "#191970");
+
+        // Still validated
+        let result = cmd_init(
+            &ctx,
+            Some("tomatoes".to_string()),
+            None,
+            true,
+            true,
+            &user_config,
+        );
+        assert_eq!(
+            result.unwrap_err().exit_code(),
+            crate::error::EXIT_INVALID_COLOR
+        );
+        assert!(fs::read_to_string(&path).unwrap().contains("Tomato"));
+    }
+
+    #[test]
+    fn test_init_keep_literal_hsl() {
+        let temp = TempDir::new().unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
+        let user_config = UserConfig::default();
+        let path = temp.path().join(".termtint");
+
+        let (tab, background) = ("hsl(200, 60%, 50%)", "hsl(20deg 40% 10%)");
+        cmd_init(&ctx, Some(tab.to_string()), None, false, true, &user_config).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", tab));
+        let parsed = config::parse_config(&path, &user_config).unwrap();
+        assert_eq!(parsed.tab, config::parse_color(tab).unwrap());
+
+        cmd_init(
+            &ctx,
+            Some(tab.to_string()),
+            Some(background.to_string()),
+            true,
+            true,
+            &user_config,
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "tab = \"hsl(200, 60%, 50%)\"\nbackground = \"hsl(20deg 40% 10%)\"\n"
+        );
+        let parsed = config::parse_config(&path, &user_config).unwrap();
+        assert_eq!(parsed.tab, config::parse_color(tab).unwrap());
+        assert_eq!(parsed.background, config::parse_color(background).unwrap());
+    }
+
+    #[cfg(feature = "itermcolors")]
+    #[test]
+    fn test_init_from_itermcolors() {
+        let temp = TempDir::new().unwrap();
+        let project = temp.path().join("app");
+        fs::create_dir_all(&project).unwrap();
+        let preset = temp.path().join("theme.itermcolors");
+        let background = config::RGB {
+            r: 0,
+            g: 0x2b,
+            b: 0x36,
+        };
+        fs::write(
+            &preset,
+            itermcolors::preset_xml(&[(itermcolors::BACKGROUND_KEY, background)]),
+        )
+        .unwrap();
+        let ctx = Context::new(temp.path(), &project);
+        let user_config = UserConfig::default();
+
+        // Without a tab color in the preset, one has to be given
+        let error =
+            cmd_init_from_itermcolors(&ctx, &preset, None, false, false, &user_config).unwrap_err();
+        assert_eq!(error.exit_code(), crate::error::EXIT_PARSE);
+        assert!(error.to_string().contains("has no 'Tab Color' entry"));
+        assert!(!project.join(".termtint").exists());
+
+        cmd_init_from_itermcolors(
+            &ctx,
+            Path::new("../theme.itermcolors"),
+            Some("#ff5500".to_string()),
+            false,
+            false,
+            &user_config,
+        )
+        .unwrap();
+        let content = fs::read_to_string(project.join(".termtint")).unwrap();
+        assert_eq!(
+            content,
+            "preset = \"~/theme.itermcolors\"\ntab = \"#ff5500\"\n"
+        );
     }
 }
@@ -1,33 +1,139 @@
+use crate::capture::Terminal;
 use crate::config::{ColorConfig, RGB};
+use crate::palette::Palette;
+use crate::user_config::UserConfig;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
 
-/// Set the iTerm2 tab color using OSC 6 escape sequences.
-pub fn set_tab_color(rgb: RGB) {
+/// What the terminal can display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+    /// Accepts arbitrary 24-bit colors; when false, colors are snapped to the 256-color palette
+    pub truecolor: bool,
+}
+
+impl Capabilities {
+    /// Capabilities for the user's configuration (`force_256` disables truecolor).
+    pub fn for_config(user_config: &UserConfig) -> Capabilities {
+        Capabilities {
+            truecolor: !user_config.force_256,
+        }
+    }
+
+    /// The colors that will actually be emitted with these capabilities.
+    pub fn emitted(&self, config: &ColorConfig) -> ColorConfig {
+        if self.truecolor {
+            config.clone()
+        } else {
+            ColorConfig {
+                tab: config.tab.quantize_256(),
+                background: config.background.quantize_256(),
+            }
+        }
+    }
+}
+
+/// The OSC 6 escape sequences setting the iTerm2 tab color.
+fn tab_color_sequence(rgb: RGB) -> String {
     // iTerm2 proprietary escape sequence for tab color
-    print!("\x1b]6;1;bg;red;brightness;{}\x07", rgb.r);
-    print!("\x1b]6;1;bg;green;brightness;{}\x07", rgb.g);
-    print!("\x1b]6;1;bg;blue;brightness;{}\x07", rgb.b);
+    format!(
+        "\x1b]6;1;bg;red;brightness;{}\x07\x1b]6;1;bg;green;brightness;{}\x07\x1b]6;1;bg;blue;brightness;{}\x07",
+        rgb.r, rgb.g, rgb.b
+    )
 }
 
-/// Set the terminal background color using OSC 11.
-pub fn set_background_color(rgb: RGB) {
+/// The OSC 11 escape sequence setting the terminal background color.
+fn background_color_sequence(rgb: RGB) -> String {
     // Standard OSC 11 for background color (hex format)
-    print!("\x1b]11;rgb:{:02x}/{:02x}/{:02x}\x07", rgb.r, rgb.g, rgb.b);
+    format!("\x1b]11;rgb:{:02x}/{:02x}/{:02x}\x07", rgb.r, rgb.g, rgb.b)
+}
+
+/// Write escape sequences to the terminal (and the capture file, if capturing).
+fn emit(sequences: &str) {
+    let _ = Terminal.write_all(sequences.as_bytes());
 }
 
 /// Reset the iTerm2 tab color to default.
 pub fn reset_tab_color() {
-    print!("\x1b]6;1;bg;*;default\x07");
+    emit("\x1b]6;1;bg;*;default\x07");
 }
 
 /// Reset the terminal background color to default.
 pub fn reset_background_color() {
-    print!("\x1b]111\x07");
+    emit("\x1b]111\x07");
+}
+
+/// Set the 16 ANSI palette colors using OSC 4.
+pub fn set_palette(palette: &Palette) {
+    emit(&palette_sequences(palette));
+}
+
+/// The OSC 4 escape sequences setting the 16 ANSI palette colors.
+pub fn palette_sequences(palette: &Palette) -> String {
+    palette
+        .iter()
+        .enumerate()
+        .map(|(index, rgb)| {
+            format!(
+                "\x1b]4;{};rgb:{:02x}/{:02x}/{:02x}\x07",
+                index, rgb.r, rgb.g, rgb.b
+            )
+        })
+        .collect()
+}
+
+/// Reset the ANSI palette to the terminal's defaults using OSC 104.
+pub fn reset_palette() {
+    emit("\x1b]104\x07");
+}
+
+/// Set the iTerm2 badge text using OSC 1337 (the text is base64-encoded).
+pub fn set_badge(text: &str) {
+    emit(&format!(
+        "\x1b]1337;SetBadgeFormat={}\x07",
+        base64_encode(text.as_bytes())
+    ));
+}
+
+/// Clear the iTerm2 badge.
+pub fn clear_badge() {
+    emit("\x1b]1337;SetBadgeFormat=\x07");
+}
+
+/// Standard base64 encoding with padding.
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
 }
 
 /// Apply both tab and background colors from a ColorConfig.
-pub fn apply_colors(config: &ColorConfig) {
-    set_tab_color(config.tab);
-    set_background_color(config.background);
+pub fn apply_colors(config: &ColorConfig, capabilities: Capabilities) {
+    emit(&color_sequences(config, capabilities));
+}
+
+/// The escape sequences `apply_colors` writes for a ColorConfig.
+pub fn color_sequences(config: &ColorConfig, capabilities: Capabilities) -> String {
+    let config = capabilities.emitted(config);
+    tab_color_sequence(config.tab) + &background_color_sequence(config.background)
 }
 
 /// Reset both tab and background colors to defaults.
@@ -43,3 +149,151 @@ pub fn get_reset_sequences() -> (String, String) {
     let bg_seq = "\x1b]111\x07".to_string();
     (tab_seq, bg_seq)
 }
+
+/// Get the escape sequence for resetting the ANSI palette (for verbose output).
+pub fn get_palette_reset_sequence() -> String {
+    "\x1b]104\x07".to_string()
+}
+
+/// Write the color resets to another terminal's tty, for sessions other than the
+/// current one. The tty is opened without blocking or becoming our controlling
+/// terminal, so a closed or hung terminal fails instead of stalling. The path comes
+/// from a state file, so anything that isn't a terminal device under /dev is
+/// refused rather than written to.
+pub fn reset_colors_on_tty(tty: &Path, palette: bool) -> io::Result<()> {
+    if !tty.starts_with("/dev/") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a device under /dev",
+        ));
+    }
+    let (tab_seq, bg_seq) = get_reset_sequences();
+    let mut sequences = tab_seq + &bg_seq;
+    if palette {
+        sequences.push_str(&get_palette_reset_sequence());
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+        .open(tty)?;
+    if !file.is_terminal() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a terminal",
+        ));
+    }
+    file.write_all(sequences.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_colors_on_tty_needs_a_terminal() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let file = temp.path().join("ttys003");
+        fs::write(&file, "").unwrap();
+        let error = reset_colors_on_tty(&file, false).unwrap_err();
+        assert_eq!(error.to_string(), "not a device under /dev");
+        assert_eq!(fs::read(&file).unwrap(), b"");
+
+        // A path that only looks like it's under /dev
+        let escaped = Path::new("/dev/..").join(file.strip_prefix("/").unwrap());
+        assert!(reset_colors_on_tty(&escaped, false).is_err());
+        assert_eq!(fs::read(&file).unwrap(), b"");
+
+        let error = reset_colors_on_tty(Path::new("/dev/null"), false).unwrap_err();
+        assert_eq!(error.to_string(), "not a terminal");
+    }
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"prod-1"), "cHJvZC0x");
+    }
+
+    #[test]
+    fn test_capabilities_emitted() {
+        let config = ColorConfig {
+            tab: RGB {
+                r: 250,
+                g: 90,
+                b: 10,
+            },
+            background: RGB { r: 40, g: 12, b: 2 },
+        };
+        let truecolor = Capabilities { truecolor: true };
+        let palette_only = Capabilities { truecolor: false };
+
+        assert_eq!(truecolor.emitted(&config), config);
+        let quantized = palette_only.emitted(&config);
+        assert_eq!(quantized.tab, config.tab.quantize_256());
+        assert_eq!(quantized.background, config.background.quantize_256());
+    }
+
+    #[test]
+    fn test_color_sequences() {
+        let config = ColorConfig {
+            tab: RGB {
+                r: 255,
+                g: 85,
+                b: 0,
+            },
+            background: RGB { r: 26, g: 8, b: 0 },
+        };
+        assert_eq!(
+            color_sequences(&config, Capabilities { truecolor: true }),
+            "\x1b]6;1;bg;red;brightness;255\x07\x1b]6;1;bg;green;brightness;85\x07\x1b]6;1;bg;blue;brightness;0\x07\x1b]11;rgb:1a/08/00\x07"
+        );
+    }
+
+    #[test]
+    fn test_capabilities_for_config() {
+        let user_config = UserConfig {
+            force_256: true,
+            ..UserConfig::default()
+        };
+
+        assert!(Capabilities::for_config(&UserConfig::default()).truecolor);
+        assert!(!Capabilities::for_config(&user_config).truecolor);
+    }
+
+    /// A new pseudo-terminal: the controlling side to read from, and the path of
+    /// the terminal side under /dev/pts.
+    fn open_pty() -> (fs::File, std::path::PathBuf) {
+        use std::os::unix::io::FromRawFd;
+        // SAFETY: posix_openpt returns a new descriptor (or -1, checked) that the
+        // File takes ownership of; ptsname's static buffer is copied before any
+        // other call.
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(fd >= 0, "{}", io::Error::last_os_error());
+            let controller = fs::File::from_raw_fd(fd);
+            assert_eq!(libc::grantpt(fd), 0);
+            assert_eq!(libc::unlockpt(fd), 0);
+            let name = std::ffi::CStr::from_ptr(libc::ptsname(fd));
+            (controller, name.to_str().unwrap().into())
+        }
+    }
+
+    #[test]
+    fn test_reset_colors_on_tty() {
+        use std::io::Read;
+        let (mut controller, tty) = open_pty();
+
+        reset_colors_on_tty(&tty, true).unwrap();
+        let mut buffer = [0; 256];
+        let read = controller.read(&mut buffer).unwrap();
+        let written = String::from_utf8_lossy(&buffer[..read]);
+        assert!(written.contains("\x1b]6;1;bg;*;default\x07"));
+        assert!(written.contains("\x1b]111\x07"));
+        assert!(written.contains("\x1b]104\x07"));
+
+        // A terminal that has gone away is an error, not a panic
+        assert!(reset_colors_on_tty(Path::new("/dev/pts/termtint-gone"), false).is_err());
+    }
+}
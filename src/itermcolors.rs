@@ -0,0 +1,386 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::config::RGB;
+use crate::error::TermtintError;
+
+/// The preset entry a project's background is read from.
+pub const BACKGROUND_KEY: &str = "Background Color";
+
+/// The preset entry the tab color is read from unless `preset_tab` names another.
+/// iTerm2 only writes it for profiles with a tab color set.
+pub const DEFAULT_TAB_KEY: &str = "Tab Color";
+
+/// The colors in an `.itermcolors` preset, by entry name ("Background Color",
+/// "Ansi 4 Color", ...).
+pub type Preset = BTreeMap<String, RGB>;
+
+/// Convert an iTerm2 color component (0.0 to 1.0) to a byte, clamping values
+/// outside the range that wide-gamut presets can contain.
+fn component_byte(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// A node in the subset of the plist format `.itermcolors` files use.
+#[derive(Debug, PartialEq)]
+enum Value {
+    Dict(Vec<(String, Value)>),
+    Scalar(String),
+}
+
+/// Tags and text read from plist XML, with the prolog, doctype, and comments skipped.
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open(String),
+    Close(String),
+    Empty(String),
+    Text(String),
+}
+
+/// Split plist XML into tokens, dropping whitespace-only text between tags.
+fn tokenize(xml: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = xml;
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").ok_or("unterminated comment")?;
+            rest = &comment[end + 3..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = rest.find('>').ok_or("unterminated declaration")?;
+            rest = &rest[end + 1..];
+        } else if let Some(tag) = rest.strip_prefix('<') {
+            let end = tag.find('>').ok_or("unterminated tag")?;
+            let body = tag[..end].trim();
+            // Attributes (like plist's version) don't matter here
+            let name = |s: &str| s.split_whitespace().next().unwrap_or("").to_string();
+            tokens.push(if let Some(name) = body.strip_prefix('/') {
+                Token::Close(name.trim().to_string())
+            } else if let Some(body) = body.strip_suffix('/') {
+                Token::Empty(name(body))
+            } else {
+                Token::Open(name(body))
+            });
+            rest = &tag[end + 1..];
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = &rest[..end];
+            if !text.trim().is_empty() {
+                tokens.push(Token::Text(unescape(text)));
+            }
+            rest = &rest[end..];
+        }
+    }
+    Ok(tokens)
+}
+
+/// Decode the predefined XML entities.
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Parse the value starting at `tokens[*pos]`, leaving `pos` after it.
+fn parse_value(tokens: &[Token], pos: &mut usize) -> Result<Value, String> {
+    match tokens.get(*pos) {
+        Some(Token::Open(tag)) if tag == "dict" => {
+            *pos += 1;
+            let mut entries = Vec::new();
+            loop {
+                match tokens.get(*pos) {
+                    Some(Token::Close(tag)) if tag == "dict" => {
+                        *pos += 1;
+                        return Ok(Value::Dict(entries));
+                    }
+                    Some(Token::Open(tag)) if tag == "key" => {
+                        *pos += 1;
+                        let key = match tokens.get(*pos) {
+                            Some(Token::Text(key)) => {
+                                *pos += 1;
+                                key.clone()
+                            }
+                            _ => String::new(),
+                        };
+                        expect_close(tokens, pos, "key")?;
+                        entries.push((key, parse_value(tokens, pos)?));
+                    }
+                    other => return Err(format!("expected <key> in <dict>, found {:?}", other)),
+                }
+            }
+        }
+        Some(Token::Open(tag)) => {
+            // Scalars (<real>, <string>, ...) and arrays, which presets don't use
+            // for colors; nested content is skipped
+            let tag = tag.clone();
+            *pos += 1;
+            let mut text = String::new();
+            let mut depth = 0;
+            loop {
+                match tokens.get(*pos) {
+                    Some(Token::Close(close)) if *close == tag && depth == 0 => {
+                        *pos += 1;
+                        return Ok(Value::Scalar(text));
+                    }
+                    Some(Token::Open(_)) => depth += 1,
+                    Some(Token::Close(_)) => depth -= 1,
+                    Some(Token::Text(t)) if depth == 0 => text.push_str(t),
+                    Some(_) => {}
+                    None => return Err(format!("unterminated <{}>", tag)),
+                }
+                *pos += 1;
+            }
+        }
+        Some(Token::Empty(_)) => {
+            *pos += 1;
+            Ok(Value::Scalar(String::new()))
+        }
+        other => Err(format!("expected a value, found {:?}", other)),
+    }
+}
+
+fn expect_close(tokens: &[Token], pos: &mut usize, tag: &str) -> Result<(), String> {
+    match tokens.get(*pos) {
+        Some(Token::Close(close)) if close == tag => {
+            *pos += 1;
+            Ok(())
+        }
+        other => Err(format!("expected </{}>, found {:?}", tag, other)),
+    }
+}
+
+/// Read the color from a preset entry's dict of components. Entries that aren't
+/// colors (no red, green, and blue components) return None.
+fn entry_color(entries: &[(String, Value)]) -> Option<RGB> {
+    let component = |name: &str| {
+        entries.iter().find_map(|(key, value)| match value {
+            Value::Scalar(text) if key == name => text.trim().parse::<f64>().ok(),
+            _ => None,
+        })
+    };
+    Some(RGB {
+        r: component_byte(component("Red Component")?),
+        g: component_byte(component("Green Component")?),
+        b: component_byte(component("Blue Component")?),
+    })
+}
+
+/// Parse the contents of an `.itermcolors` file.
+///
+/// Only the red, green, and blue components are read: alpha and the declared
+/// color space are ignored, the same way iTerm2 escape sequences take plain RGB.
+pub fn parse_itermcolors(xml: &str) -> Result<Preset, String> {
+    let tokens = tokenize(xml)?;
+    let mut pos = match tokens.first() {
+        Some(Token::Open(tag)) if tag == "plist" => 1,
+        _ => return Err("missing <plist> root element".to_string()),
+    };
+    let Value::Dict(entries) = parse_value(&tokens, &mut pos)? else {
+        return Err("the plist's root is not a <dict>".to_string());
+    };
+    Ok(entries
+        .into_iter()
+        .filter_map(|(key, value)| match value {
+            Value::Dict(components) => Some((key, entry_color(&components)?)),
+            Value::Scalar(_) => None,
+        })
+        .collect())
+}
+
+/// Read and parse the `.itermcolors` file at `path`.
+pub fn read_itermcolors(path: &Path) -> Result<Preset, TermtintError> {
+    let xml = fs::read_to_string(path)
+        .map_err(|e| TermtintError::io(format!("Failed to read preset {}", path.display()), &e))?;
+    parse_itermcolors(&xml).map_err(|e| {
+        TermtintError::parse(
+            Some(path.to_path_buf()),
+            format!(
+                "{} is not a valid .itermcolors preset: {}",
+                path.display(),
+                e
+            ),
+        )
+    })
+}
+
+/// A parsed preset and the modification time of the file it was parsed from.
+type CachedPreset = (Option<SystemTime>, Result<Preset, TermtintError>);
+
+/// The most presets `PRESETS` holds; parsing one more starts it over, so a `serve`
+/// that meets many themes doesn't keep them all.
+const MAX_CACHED_PRESETS: usize = 64;
+
+/// Presets already parsed by this process, so a preset shared by several
+/// `.termtint` files is read once. Each file keeps only its latest parse, which a
+/// newer modification time (a theme saved again while `serve` runs) replaces.
+static PRESETS: Mutex<BTreeMap<PathBuf, CachedPreset>> = Mutex::new(BTreeMap::new());
+
+/// Read an `.itermcolors` file like `read_itermcolors`, reusing the result parsed
+/// earlier in this process when its modification time hasn't changed.
+pub fn read_itermcolors_cached(path: &Path) -> Result<Preset, TermtintError> {
+    let mtime = fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut presets = PRESETS.lock().unwrap_or_else(|e| e.into_inner());
+    match presets.get(path) {
+        Some((parsed_mtime, preset)) if *parsed_mtime == mtime => preset.clone(),
+        _ => {
+            let preset = read_itermcolors(path);
+            if presets.len() >= MAX_CACHED_PRESETS && !presets.contains_key(path) {
+                presets.clear();
+            }
+            presets.insert(path.to_path_buf(), (mtime, preset.clone()));
+            preset
+        }
+    }
+}
+
+/// Look up a color entry, naming the preset and the entry when it's missing.
+pub fn preset_color(preset: &Preset, key: &str, path: &Path) -> Result<RGB, TermtintError> {
+    preset.get(key).copied().ok_or_else(|| {
+        TermtintError::parse(
+            Some(path.to_path_buf()),
+            format!("Preset {} has no '{}' entry", path.display(), key),
+        )
+    })
+}
+
+/// Render a minimal `.itermcolors` file with the given color entries.
+#[cfg(test)]
+pub fn preset_xml(entries: &[(&str, RGB)]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<plist version=\"1.0\">\n<dict>\n",
+    );
+    for (key, color) in entries {
+        xml.push_str(&format!(
+            "\t<key>{}</key>\n\t<dict>\n\t\t<key>Blue Component</key>\n\t\t<real>{}</real>\n\t\t<key>Green Component</key>\n\t\t<real>{}</real>\n\t\t<key>Red Component</key>\n\t\t<real>{}</real>\n\t</dict>\n",
+            key,
+            color.b as f64 / 255.0,
+            color.g as f64 / 255.0,
+            color.r as f64 / 255.0
+        ));
+    }
+    xml.push_str("</dict>\n</plist>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOLARIZED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Ansi 4 Color</key>
+	<dict>
+		<key>Alpha Component</key>
+		<real>1</real>
+		<key>Blue Component</key>
+		<real>0.82352941176470584</real>
+		<key>Color Space</key>
+		<string>sRGB</string>
+		<key>Green Component</key>
+		<real>0.54509803921568623</real>
+		<key>Red Component</key>
+		<real>0.14901960784313725</real>
+	</dict>
+	<!-- Solarized Dark base03 -->
+	<key>Background Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.21176470588235294</real>
+		<key>Green Component</key>
+		<real>0.16862745098039217</real>
+		<key>Red Component</key>
+		<integer>0</integer>
+	</dict>
+	<key>Use Bright Bold</key>
+	<true/>
+</dict>
+</plist>
+"#;
+
+    #[test]
+    fn test_parse_itermcolors() {
+        let preset = parse_itermcolors(SOLARIZED).unwrap();
+
+        assert_eq!(preset.len(), 2);
+        assert_eq!(
+            preset[BACKGROUND_KEY],
+            RGB {
+                r: 0x00,
+                g: 0x2b,
+                b: 0x36
+            }
+        );
+        assert_eq!(
+            preset["Ansi 4 Color"],
+            RGB {
+                r: 0x26,
+                g: 0x8b,
+                b: 0xd2
+            }
+        );
+    }
+
+    #[test]
+    fn test_preset_errors() {
+        assert!(parse_itermcolors("not a plist").is_err());
+        assert!(parse_itermcolors("<plist><dict><key>A</key>").is_err());
+
+        let preset = parse_itermcolors(SOLARIZED).unwrap();
+        assert_eq!(
+            preset_color(
+                &preset,
+                DEFAULT_TAB_KEY,
+                Path::new("/themes/solarized.itermcolors")
+            )
+            .map_err(|e| e.to_string()),
+            Err("Preset /themes/solarized.itermcolors has no 'Tab Color' entry".to_string())
+        );
+    }
+
+    #[test]
+    fn test_preset_xml_round_trips() {
+        let color = RGB {
+            r: 0x12,
+            g: 0x34,
+            b: 0x56,
+        };
+        let preset = parse_itermcolors(&preset_xml(&[(BACKGROUND_KEY, color)])).unwrap();
+        assert_eq!(preset[BACKGROUND_KEY], color);
+    }
+
+    #[test]
+    fn test_cached_preset_replaced_when_modified() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("theme.itermcolors");
+        let first = RGB { r: 1, g: 2, b: 3 };
+        let second = RGB { r: 4, g: 5, b: 6 };
+
+        fs::write(&path, preset_xml(&[(BACKGROUND_KEY, first)])).unwrap();
+        filetime::set_file_mtime(&path, filetime::FileTime::from_unix_time(1_000_000, 0)).unwrap();
+        assert_eq!(
+            read_itermcolors_cached(&path).unwrap()[BACKGROUND_KEY],
+            first
+        );
+
+        fs::write(&path, preset_xml(&[(BACKGROUND_KEY, second)])).unwrap();
+        filetime::set_file_mtime(&path, filetime::FileTime::from_unix_time(2_000_000, 0)).unwrap();
+        assert_eq!(
+            read_itermcolors_cached(&path).unwrap()[BACKGROUND_KEY],
+            second
+        );
+
+        // Only the latest parse is kept for the path
+        let edited = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(2_000_000);
+        let presets = PRESETS.lock().unwrap();
+        assert_eq!(
+            presets.get(&path).map(|(mtime, _)| *mtime),
+            Some(Some(edited))
+        );
+    }
+}
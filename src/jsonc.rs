@@ -0,0 +1,108 @@
+/// Strip the JSONC extensions VS Code allows in its settings files: `//` and
+/// `/* */` comments, and trailing commas before `}` or `]`. Text inside strings is
+/// left alone. Newlines in comments are kept so parse errors report the right line.
+///
+/// # Returns
+/// The plain JSON text, and whether any comments were removed
+pub fn strip_jsonc(text: &str) -> (String, bool) {
+    let mut output = String::with_capacity(text.len());
+    let mut had_comments = false;
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            output.push(c);
+            match c {
+                '\\' => output.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                output.push(c);
+            }
+            ('/', Some('/')) => {
+                had_comments = true;
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            ('/', Some('*')) => {
+                had_comments = true;
+                chars.next();
+                let mut last = '\0';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        output.push(c);
+                    }
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+
+    (remove_trailing_commas(&output), had_comments)
+}
+
+/// Remove commas followed only by whitespace before a closing `}` or `]`.
+fn remove_trailing_commas(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = text[i + 1..].trim_start().chars().next();
+            if matches!(next, Some('}' | ']')) {
+                continue;
+            }
+        }
+        output.push(c);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_jsonc() {
+        let text = r#"{
+    // Editor settings
+    "editor.fontSize": 14, /* inline */
+    "url": "https://example.com//path",
+    "glob": "src/*.rs", // trailing
+    "list": [1, 2,],
+}"#;
+        let (json, had_comments) = strip_jsonc(text);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert!(had_comments);
+        assert_eq!(value["editor.fontSize"], 14);
+        assert_eq!(value["url"], "https://example.com//path");
+        assert_eq!(value["glob"], "src/*.rs");
+        assert_eq!(value["list"], serde_json::json!([1, 2]));
+        assert_eq!(json.lines().count(), text.lines().count());
+    }
+
+    #[test]
+    fn test_strip_jsonc_plain_json_is_unchanged() {
+        let text = r#"{"a": "quote \" // not a comment, }", "b": [1, 2]}"#;
+        assert_eq!(strip_jsonc(text), (text.to_string(), false));
+    }
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::context::Context;
+use crate::state;
+
+/// The kubeconfig files kubectl reads: each entry of `$KUBECONFIG`, or
+/// `~/.kube/config` when it is unset or empty.
+fn kubeconfig_paths(ctx: &Context) -> Vec<PathBuf> {
+    let configured: Vec<PathBuf> = ctx
+        .var_os("KUBECONFIG")
+        .map(|value| {
+            std::env::split_paths(value)
+                .filter(|path| !path.as_os_str().is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    if !configured.is_empty() {
+        return configured;
+    }
+    match &ctx.home {
+        Some(home) => vec![home.join(".kube").join("config")],
+        None => Vec::new(),
+    }
+}
+
+/// The `current-context` value from a kubeconfig's top level. The file is scanned
+/// line by line rather than parsed as YAML; quotes and trailing comments are
+/// stripped, and an empty value counts as unset.
+fn scan_current_context(content: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let value = line.strip_prefix("current-context:")?;
+        let value = match value.find(" #") {
+            Some(comment) => &value[..comment],
+            None => value,
+        }
+        .trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+            .unwrap_or(value);
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// Identify the kubeconfig files as they are on disk, by path, size, and
+/// modification time, so a cached context is only reused while none changed.
+fn kubeconfig_fingerprint(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| match fs::metadata(path) {
+            Ok(metadata) => format!(
+                "{}:{}:{}.{}",
+                path.display(),
+                metadata.len(),
+                metadata.mtime(),
+                metadata.mtime_nsec()
+            ),
+            Err(_) => format!("{}:-", path.display()),
+        })
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+/// Read the current context from `paths`, reusing the context cached at
+/// `cache_path` while the files' fingerprint matches. Like kubectl, the first
+/// file that sets `current-context` wins.
+fn current_context_in(paths: &[PathBuf], cache_path: Option<&Path>) -> Option<String> {
+    let fingerprint = kubeconfig_fingerprint(paths);
+    if let Some(content) = cache_path.and_then(|path| fs::read_to_string(path).ok()) {
+        let mut lines = content.lines();
+        if lines.next() == Some(fingerprint.as_str()) {
+            return lines
+                .next()
+                .filter(|context| !context.is_empty())
+                .map(String::from);
+        }
+    }
+
+    let context = paths.iter().find_map(|path| {
+        let content = fs::read_to_string(path).ok()?;
+        scan_current_context(&content)
+    });
+    if let Some(cache_path) = cache_path {
+        let content = format!("{}\n{}\n", fingerprint, context.as_deref().unwrap_or(""));
+        let _ = state::write_atomic(cache_path, content.as_bytes());
+    }
+    context
+}
+
+/// The current kubectl context, if one is set. The result is cached in the state
+/// directory and re-read only when a kubeconfig file changes.
+pub fn current_context(ctx: &Context) -> Option<String> {
+    let cache_path = state::state_root(ctx).join("kube_context");
+    let cache_path = state::state_writable(ctx).then_some(cache_path.as_path());
+    current_context_in(&kubeconfig_paths(ctx), cache_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_current_context() {
+        let content = "apiVersion: v1\nclusters: []\ncurrent-context: prod-eu\nkind: Config\n";
+        assert_eq!(scan_current_context(content), Some("prod-eu".to_string()));
+        assert_eq!(
+            scan_current_context("current-context: \"arn:aws:eks:cluster/prod\" # main\n"),
+            Some("arn:aws:eks:cluster/prod".to_string())
+        );
+        assert_eq!(
+            scan_current_context("current-context: 'staging'\n"),
+            Some("staging".to_string())
+        );
+        assert_eq!(scan_current_context("current-context: \"\"\n"), None);
+        // Only the top-level key counts
+        assert_eq!(
+            scan_current_context("contexts:\n- name: x\n  current-context: nested\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_first_kubeconfig_with_context_wins() {
+        let temp = TempDir::new().unwrap();
+        let first = temp.path().join("first");
+        let second = temp.path().join("second");
+        fs::write(&first, "kind: Config\n").unwrap();
+        fs::write(&second, "current-context: dev\n").unwrap();
+        let missing = temp.path().join("missing");
+
+        assert_eq!(
+            current_context_in(&[missing, first.clone(), second.clone()], None),
+            Some("dev".to_string())
+        );
+        fs::write(&first, "current-context: prod\n").unwrap();
+        assert_eq!(
+            current_context_in(&[first, second], None),
+            Some("prod".to_string())
+        );
+    }
+
+    #[test]
+    fn test_current_context_cached_until_kubeconfig_changes() {
+        let temp = TempDir::new().unwrap();
+        let kubeconfig = temp.path().join("config");
+        let cache = temp.path().join("kube_context");
+        let paths = [kubeconfig.clone()];
+        let set_mtime = |secs| {
+            filetime::set_file_mtime(&kubeconfig, filetime::FileTime::from_unix_time(secs, 0))
+                .unwrap()
+        };
+
+        fs::write(&kubeconfig, "current-context: dev\n").unwrap();
+        set_mtime(1_000_000);
+        assert_eq!(
+            current_context_in(&paths, Some(&cache)),
+            Some("dev".to_string())
+        );
+
+        // Same size and modification time: the cached context is used
+        fs::write(&kubeconfig, "current-context: xyz\n").unwrap();
+        set_mtime(1_000_000);
+        assert_eq!(
+            current_context_in(&paths, Some(&cache)),
+            Some("dev".to_string())
+        );
+
+        set_mtime(1_000_060);
+        assert_eq!(
+            current_context_in(&paths, Some(&cache)),
+            Some("xyz".to_string())
+        );
+        fs::remove_file(&kubeconfig).unwrap();
+        assert_eq!(current_context_in(&paths, Some(&cache)), None);
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Instant, UNIX_EPOCH};
+
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::context::Context;
+
+/// The level logged without `TERMTINT_LOG` or `--debug`: warnings about the user
+/// config and other problems the user should fix.
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::WARN;
+
+/// Formats events as `termtint: <level>: <spans>: <message> <fields>`, so warnings
+/// read the same as the messages printed before logging existed. Log files get a
+/// prefix with the invocation's start time, process id, and time since start, since
+/// several shells' hooks can append to the same file.
+struct TermtintFormat {
+    /// Unix time the invocation started, and when, for the log file prefix
+    start: Option<(u64, Instant)>,
+}
+
+impl<S, N> FormatEvent<S, N> for TermtintFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        if let Some((started, instant)) = self.start {
+            write!(
+                writer,
+                "{} [{}] +{:.3}ms ",
+                started,
+                std::process::id(),
+                instant.elapsed().as_secs_f64() * 1000.0
+            )?;
+        }
+        let level = match *event.metadata().level() {
+            Level::ERROR => "error",
+            Level::WARN => "warning",
+            Level::INFO => "info",
+            Level::DEBUG => "debug",
+            Level::TRACE => "trace",
+        };
+        write!(writer, "termtint: {}: ", level)?;
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                write!(writer, "{}", span.name())?;
+                let extensions = span.extensions();
+                if let Some(fields) = extensions.get::<FormattedFields<N>>() {
+                    if !fields.is_empty() {
+                        write!(writer, "{{{}}}", fields)?;
+                    }
+                }
+                write!(writer, ": ")?;
+            }
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Parse a `TERMTINT_LOG` value: off, error, warn, info, debug, or trace.
+fn parse_level(value: &str) -> Option<LevelFilter> {
+    value.trim().parse().ok()
+}
+
+/// Install the logger for this invocation.
+///
+/// The level comes from `TERMTINT_LOG` (warnings by default), raised to debug by
+/// `--debug`. Output goes to stderr, or is appended to `TERMTINT_LOG_FILE` so
+/// shell hook runs can be traced without printing into the prompt.
+///
+/// # Arguments
+/// * `ctx` - The invocation context, for the environment and start time
+/// * `debug` - Whether `--debug` was given
+pub fn init(ctx: &Context, debug: bool) {
+    let configured = ctx.var("TERMTINT_LOG").filter(|value| !value.is_empty());
+    let parsed = configured.map(|value| parse_level(value).ok_or(value));
+    let mut level = match parsed {
+        Some(Ok(level)) => level,
+        _ => DEFAULT_LEVEL,
+    };
+    if debug {
+        level = level.max(LevelFilter::DEBUG);
+    }
+
+    let log_file = ctx
+        .var_os("TERMTINT_LOG_FILE")
+        .filter(|path| !path.is_empty());
+    let mut open_error = None;
+    let opened = log_file.map(|path| {
+        (
+            path,
+            OpenOptions::new().create(true).append(true).open(path),
+        )
+    });
+    let (writer, start) = match opened {
+        Some((_, Ok(file))) => {
+            let started = ctx
+                .now
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            (
+                BoxMakeWriter::new(Mutex::new(file)),
+                Some((started, Instant::now())),
+            )
+        }
+        Some((path, Err(e))) => {
+            open_error = Some(format!(
+                "can't open TERMTINT_LOG_FILE {}: {}, logging to stderr",
+                path.to_string_lossy(),
+                e
+            ));
+            (BoxMakeWriter::new(io::stderr), None)
+        }
+        None => (BoxMakeWriter::new(io::stderr), None),
+    };
+    let _ = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(writer)
+        .event_format(TermtintFormat { start })
+        .try_init();
+
+    if let Some(Err(value)) = parsed {
+        tracing::warn!(
+            "invalid TERMTINT_LOG '{}' (expected off, error, warn, info, debug, or trace), using warn",
+            value
+        );
+    }
+    if let Some(message) = open_error {
+        tracing::warn!("{}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level("debug"), Some(LevelFilter::DEBUG));
+        assert_eq!(parse_level(" WARN "), Some(LevelFilter::WARN));
+        assert_eq!(parse_level("off"), Some(LevelFilter::OFF));
+        assert_eq!(parse_level("loud"), None);
+    }
+}
@@ -1,28 +1,93 @@
+#[cfg(feature = "man")]
+use clap::CommandFactory;
 use clap::{Parser, Subcommand};
-
+use context::Context;
+use explain::Explanation;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+mod cache;
+mod capture;
+#[cfg(feature = "colors")]
 mod colors;
 mod config;
+mod context;
+mod daemon;
+mod error;
+mod explain;
+mod export;
+mod hyperlink;
 mod init;
 mod iterm;
+#[cfg(feature = "itermcolors")]
+mod itermcolors;
+mod jsonc;
+mod kube;
+mod logging;
+#[cfg(feature = "man")]
+mod man;
+mod manifest;
+mod palette;
+mod schema;
+mod sessions;
+mod ssh;
+mod starship;
 mod state;
 mod user_config;
 
 #[derive(Parser)]
 #[command(name = "termtint")]
 #[command(about = "Terminal color theming based on directory")]
+#[command(long_about = "Terminal color theming based on directory.\n\n\
+termtint gives each project its own iTerm2 tab color and a matching darkened \
+background. Colors come from a .termtint file in the directory or one of its \
+parents, from trigger files and path globs in the user config, or from \
+environment and kubectl context rules. A shell hook runs `termtint apply` on \
+every prompt, and only emits escape sequences when the colors change.\n\n\
+The user config lives in ~/.config/termtint/config.toml (or the file given with \
+--config or TERMTINT_CONFIG); see `termtint config`.")]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Use FILE as the user config instead of ~/.config/termtint/config.toml, like
+    /// TERMTINT_CONFIG=FILE
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Log debug details to stderr (or TERMTINT_LOG_FILE), like TERMTINT_LOG=debug
+    #[arg(long, global = true)]
+    debug: bool,
+    /// Also append emitted escape sequences to FILE, like TERMTINT_CAPTURE=FILE
+    #[arg(long, global = true, value_name = "FILE")]
+    capture: Option<PathBuf>,
+    /// Write escape sequences to the capture file only, not the terminal
+    #[arg(long, global = true)]
+    capture_only: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Print shell hook code
+    ///
+    /// Prints a snippet that runs `termtint apply` before each prompt. Add
+    /// `eval "$(termtint hook zsh)"` to ~/.zshrc, the bash equivalent to ~/.bashrc,
+    /// or `termtint hook fish | source` to config.fish.
     Hook {
         /// Shell type (zsh, bash, or fish)
         shell: String,
+        /// Run `apply --fast` from the hook
+        #[arg(long)]
+        fast: bool,
+        /// Run `apply --via-daemon` from the hook, for use with `termtint serve`
+        #[arg(long, conflicts_with = "fast")]
+        daemon: bool,
     },
     /// Apply colors from config in current directory
+    ///
+    /// Finds the config source for the working directory (the nearest .termtint,
+    /// trigger, or environment rule), resolves its tab and background colors, and
+    /// emits the escape sequences to set them. The last applied config is recorded
+    /// per terminal session, so nothing is emitted while it's unchanged; without a
+    /// source, colors are reset. The shell hook runs this on every prompt.
     Apply {
         /// Show detailed config info, color swatches, and status messages
         #[arg(short, long)]
@@ -30,14 +95,46 @@ enum Commands {
         /// Force apply even if config is unchanged
         #[arg(short, long)]
         force: bool,
+        /// Always resolve and emit, without reading or writing state (or TERMTINT_NO_STATE=1)
+        #[arg(long)]
+        no_state: bool,
+        /// Print a trace of every decision: cleanup, state, directories walked, source,
+        /// colors, change detection, and what was emitted
+        #[arg(long)]
+        explain: bool,
+        /// Minimal mode for latency-sensitive hooks: skip the user config (using
+        /// defaults, or the file in TERMTINT_FAST_CONFIG) and session cleanup.
+        /// May ignore some config options
+        #[arg(long, conflicts_with_all = ["verbose", "explain"])]
+        fast: bool,
+        /// Ask a running `termtint serve` to resolve the colors, applying in-process
+        /// when it isn't running, uses another user config, or with --verbose,
+        /// --explain, --force, or --no-state
+        #[arg(long, conflicts_with = "fast")]
+        via_daemon: bool,
     },
     /// Reset terminal colors to default
+    ///
+    /// Emits the sequences that restore the profile's tab color, background, and
+    /// (with generate_palette) ANSI palette, and records the reset so the next apply
+    /// emits colors again.
     Reset {
         /// Show escape sequences, state file info, and previous configuration
         #[arg(short, long)]
         verbose: bool,
+        /// Also reset every other recorded session, on ttys that can still be opened
+        #[arg(long, conflicts_with = "no_state")]
+        all_sessions: bool,
+        /// Emit resets without reading or writing state (or TERMTINT_NO_STATE=1)
+        #[arg(long)]
+        no_state: bool,
     },
     /// Initialize a .termtint file in the current directory
+    ///
+    /// Without a color, writes "auto", which derives a stable color from the
+    /// directory's path. A color in any supported syntax (hex, rgb(), hsl(), oklch(),
+    /// or a CSS name) is normalized to hex; with --background the file is written
+    /// as TOML with both colors. The colors are applied immediately.
     Init {
         /// Hex color for the tab (e.g., #ff5500)
         color: Option<String>,
@@ -47,16 +144,54 @@ enum Commands {
         /// Overwrite existing .termtint file
         #[arg(short, long)]
         force: bool,
+        /// Write colors as given (e.g. tomato or hsl(...)) instead of as hex
+        #[arg(long, requires = "color")]
+        keep_literal: bool,
+        /// Take the background (and tab color, unless given) from an .itermcolors preset
+        #[cfg(feature = "itermcolors")]
+        #[arg(long, value_name = "FILE", conflicts_with = "background")]
+        from_itermcolors: Option<PathBuf>,
     },
     /// Re-roll to a new random color, updating .termtint in current directory
+    ///
+    /// Picks a random color within the [auto] hue, saturation, and lightness
+    /// ranges that differs visibly from the current one and stays away from
+    /// recently rolled hues, writes it to .termtint, and applies it.
+    #[cfg(feature = "reroll")]
     Reroll {
         /// Show directory path
         #[arg(short, long)]
         verbose: bool,
+        /// Pick a purely random hue instead of spreading away from recent ones
+        #[arg(long)]
+        no_spread: bool,
+        /// Minimum perceptual difference (Oklab ΔE) from the current color
+        #[arg(long)]
+        min_delta: Option<f32>,
     },
     /// Display visual color palette and configuration
-    Colors,
+    ///
+    /// Shows swatches of the colors auto generation produces with the current
+    /// settings, with their backgrounds, to preview config changes.
+    #[cfg(feature = "colors")]
+    Colors {
+        /// Show how trigger path directories are spread around the hue wheel
+        #[arg(long)]
+        distribution: bool,
+        /// Preview swatches as seen with a color vision deficiency
+        /// (protanopia, deuteranopia, tritanopia)
+        #[arg(long, value_name = "TYPE", conflicts_with = "distribution")]
+        simulate: Option<String>,
+        /// Preview a preset (vivid, pastel, muted, neon) without editing config
+        #[arg(long, value_name = "NAME")]
+        preset: Option<String>,
+    },
     /// Show current configuration and config file path
+    ///
+    /// Lists every user config key with its current value, description, and
+    /// default. --edit opens the file in $EDITOR, creating it with commented
+    /// defaults or adding keys newer versions introduced, and listing keys it
+    /// doesn't recognize. --check reports those keys and exits non-zero.
     Config {
         /// Open config file in editor
         #[arg(short, long)]
@@ -64,29 +199,222 @@ enum Commands {
         /// Print config file path only
         #[arg(short, long)]
         path: bool,
+        /// Print a JSON Schema for the config file, for editor completion and validation
+        #[arg(long, conflicts_with_all = ["edit", "path"])]
+        schema: bool,
+        /// Check the config file for unrecognized keys (exit code 6 if any)
+        #[arg(long, conflicts_with_all = ["edit", "path", "schema"])]
+        check: bool,
     },
     /// Show color configuration details for current directory
+    ///
+    /// Prints the config source for the working directory, the file it came from,
+    /// the resolved tab and background colors with swatches, and a readable
+    /// foreground for the background.
     Inspect,
+    /// Run ssh with colors derived from the remote host, resetting them on exit
+    ///
+    /// The color comes from the [hosts] table (an exact host wins, then the longest
+    /// glob) or is hashed from the host name. ssh's exit status is passed through.
+    Ssh {
+        /// Also show the host name as an iTerm2 badge
+        #[arg(long)]
+        badge: bool,
+        /// Arguments passed through to ssh (destination, options, command)
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        args: Vec<String>,
+    },
     /// Manage triggers for auto-generated colors
+    ///
+    /// Triggers give directories colors without a .termtint: trigger files
+    /// (Cargo.toml), path globs (~/Code/*), directory names (prod), or kubectl
+    /// contexts. They're stored as [[triggers]] in the user config.
     Trigger {
         #[command(subcommand)]
         action: TriggerAction,
     },
+    /// Print the current session's applied state without resolving anything
+    ///
+    /// Reads the state file the last apply wrote, so it's cheap enough for status
+    /// lines and prompts.
+    State {
+        /// Print JSON for status lines and scripts
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a colored dot for the active project, for a starship custom module
+    ///
+    /// Uses the current session's state, printing nothing when no colors are
+    /// applied.
+    Starship {
+        /// Print the [custom.termtint] block to paste into starship.toml
+        #[arg(long)]
+        config: bool,
+    },
+    /// Export the current project's colors for other terminals and tools
+    ///
+    /// Resolves the working directory's colors as apply would and writes them in
+    /// another program's format: an iTerm2 Dynamic Profile, a kitty, Windows
+    /// Terminal, or Alacritty theme, VS Code workspace settings, or a base16 scheme.
+    #[command(group(clap::ArgGroup::new("format").required(true)))]
+    Export {
+        /// Print an iTerm2 Dynamic Profile (JSON)
+        #[arg(long, group = "format")]
+        iterm_profile: bool,
+        /// Print a kitty theme (.conf) with the background, tab bar, and palette colors
+        #[arg(long, group = "format")]
+        kitty: bool,
+        /// Print a Windows Terminal color scheme to paste into settings.json
+        #[arg(long, group = "format")]
+        windows_terminal: bool,
+        /// Print an Alacritty theme (TOML) to import from alacritty.toml
+        #[arg(long, group = "format")]
+        alacritty: bool,
+        /// Merge peacock.color and title/activity bar colors into .vscode/settings.json
+        #[arg(long, group = "format")]
+        vscode: bool,
+        /// Print the merged settings instead of writing them (with --vscode)
+        #[arg(long, conflicts_with = "dry_run")]
+        print: bool,
+        /// Show the changes to the settings file without writing it (with --vscode)
+        #[arg(long)]
+        dry_run: bool,
+        /// Print a base16 scheme (YAML) built from the derived palette
+        #[arg(long, group = "format")]
+        base16: bool,
+        /// The base16 slot for the tab color, base01 to base0F (with --base16)
+        #[arg(long, value_name = "SLOT")]
+        tab_slot: Option<String>,
+        /// Print the whole "schemes" entry instead of a single scheme (with --windows-terminal)
+        #[arg(long)]
+        full: bool,
+        /// Write the profile into iTerm2's DynamicProfiles folder instead of printing it
+        /// (with --iterm-profile)
+        #[arg(long, conflicts_with = "out")]
+        install: bool,
+        /// Write the export to this file instead of printing it
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Inspect per-terminal-session state
+    ///
+    /// Each terminal session (TERMTINT_SESSION, or the tty) keeps its own record of
+    /// the last applied colors. Sessions idle for longer than session_ttl are
+    /// cleaned up automatically.
+    Sessions {
+        #[command(subcommand)]
+        action: SessionsAction,
+    },
+    /// Manage the resolved-color cache
+    ///
+    /// With color_cache enabled, auto colors resolved for trigger directories are
+    /// cached in the state directory.
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Run a daemon that resolves colors for `apply --via-daemon`
+    ///
+    /// Listens on a unix socket (TERMTINT_SOCKET, or termtint.sock in
+    /// $XDG_RUNTIME_DIR or a per-user directory under $TMPDIR) and answers apply and
+    /// reset requests with the escape sequences for the client to write, keeping
+    /// the user config loaded and reloading it when the file changes. Configs with
+    /// [env_triggers] or kube-context triggers depend on each shell's environment,
+    /// so clients apply those in-process. Stops on SIGINT, SIGTERM, or --stop.
+    Serve {
+        /// Describe the running daemon
+        #[arg(long, conflicts_with = "stop")]
+        status: bool,
+        /// Stop the running daemon
+        #[arg(long)]
+        stop: bool,
+    },
+    /// Generate man pages
+    ///
+    /// Prints the termtint(1) page, or with --out writes termtint.1 and a page for
+    /// each subcommand (termtint-init.1, termtint-trigger-add.1, ...) into a
+    /// directory.
+    #[cfg(feature = "man")]
+    Man {
+        /// Directory to write every page into
+        #[arg(long, value_name = "DIR")]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Remove all cached colors
+    ///
+    /// Colors are resolved again on the next apply.
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum SessionsAction {
+    /// List sessions with their age, last applied directory, and tab color
+    ///
+    /// Sessions older than session_ttl are marked stale.
+    List {
+        /// Print JSON for scripts
+        #[arg(long)]
+        json: bool,
+    },
+    /// Remove session state (dry run unless --force is given)
+    ///
+    /// Without --older-than or --all, selects the sessions older than session_ttl.
+    Clean {
+        /// Remove sessions older than this age, e.g. 2h or 3d (default: session_ttl)
+        #[arg(long, value_name = "AGE", conflicts_with = "all")]
+        older_than: Option<String>,
+        /// Remove every session
+        #[arg(long)]
+        all: bool,
+        /// Actually remove the sessions instead of listing them
+        #[arg(short, long)]
+        force: bool,
+    },
 }
 
 #[derive(Subcommand)]
 enum TriggerAction {
-    /// Add a trigger (file name or path glob)
+    /// Add a trigger (file name, path glob, or directory name)
+    ///
+    /// Patterns containing / or ~ are path globs; other patterns are trigger file
+    /// names, matched in each directory searched.
     Add {
         /// Pattern to add - file name (e.g., Cargo.toml) or path glob (e.g., ~/Code/*)
         pattern: String,
+        /// Add a directory name (e.g., prod or release-*) matched wherever it is
+        #[arg(long)]
+        dirname: bool,
+        /// Add a glob on the current kubectl context (e.g., *prod*)
+        #[arg(long, conflicts_with = "dirname")]
+        kube_context: bool,
+        /// Color for matched directories instead of a generated one (or "auto")
+        #[arg(long)]
+        color: Option<String>,
+        /// Hue band for generated colors, as MIN,MAX in degrees (e.g., 15,45)
+        #[arg(long)]
+        hue: Option<String>,
+        /// Only match trigger files containing this text
+        #[arg(long)]
+        contains: Option<String>,
+        /// Only match trigger files that parse as TOML and define this dotted key
+        #[arg(long)]
+        toml_key: Option<String>,
     },
-    /// Remove a trigger (file name or path glob)
+    /// Remove a trigger (file name, path glob, or directory name)
+    ///
+    /// The pattern must match the one added exactly.
     Remove {
         /// Pattern to remove
         pattern: String,
     },
     /// List all triggers
+    ///
+    /// Shows each trigger's kind and options, including those from the legacy
+    /// trigger_files, trigger_paths, and trigger_dirnames keys.
     List,
 }
 
@@ -117,6 +445,7 @@ fn print_color_swatches_stdout(
     // Format color strings for display
     let tab_str = tab.format_as(user_config.color_format);
     let bg_str = background.format_as(user_config.color_format);
+    let truecolor = iterm::Capabilities::for_config(user_config).truecolor;
 
     // Top border
     print!("{}", top_left);
@@ -161,7 +490,7 @@ fn print_color_swatches_stdout(
         }
 
         // Tab color swatch
-        print!("\x1b[48;2;{};{};{}m", tab.r, tab.g, tab.b);
+        print!("{}", tab.ansi_background(truecolor));
         for _ in 0..swatch_width {
             print!(" ");
         }
@@ -173,10 +502,7 @@ fn print_color_swatches_stdout(
         }
 
         // Background color swatch
-        print!(
-            "\x1b[48;2;{};{};{}m",
-            background.r, background.g, background.b
-        );
+        print!("{}", background.ansi_background(truecolor));
         for _ in 0..swatch_width {
             print!(" ");
         }
@@ -246,6 +572,7 @@ fn print_color_swatches(
     // Format color strings for display
     let tab_str = tab.format_as(user_config.color_format);
     let bg_str = background.format_as(user_config.color_format);
+    let truecolor = iterm::Capabilities::for_config(user_config).truecolor;
 
     eprintln!();
 
@@ -292,7 +619,7 @@ fn print_color_swatches(
         }
 
         // Tab color swatch
-        eprint!("\x1b[48;2;{};{};{}m", tab.r, tab.g, tab.b);
+        eprint!("{}", tab.ansi_background(truecolor));
         for _ in 0..swatch_width {
             eprint!(" ");
         }
@@ -304,10 +631,7 @@ fn print_color_swatches(
         }
 
         // Background color swatch
-        eprint!(
-            "\x1b[48;2;{};{};{}m",
-            background.r, background.g, background.b
-        );
+        eprint!("{}", background.ansi_background(truecolor));
         for _ in 0..swatch_width {
             eprint!(" ");
         }
@@ -352,6 +676,23 @@ fn print_color_swatches(
     eprintln!("{}", bottom_right);
 }
 
+/// Print how the background of a trigger source is derived, naming the trigger
+/// when it sets the lightness or saturation.
+fn print_trigger_background(source: &config::ConfigSource, user_config: &user_config::UserConfig) {
+    let lightness = config::trigger_user_config(source, user_config).derived_background_lightness();
+    match config::trigger_background_params(source, user_config) {
+        Some(trigger) => eprintln!(
+            "Background:      Auto-generated ({}% lightness, from trigger {})",
+            (lightness * 100.0) as u8,
+            trigger.pattern
+        ),
+        None => eprintln!(
+            "Background:      Auto-generated ({}% lightness)",
+            (lightness * 100.0) as u8
+        ),
+    }
+}
+
 fn print_config_info(
     source: &config::ConfigSource,
     color_config: &config::ColorConfig,
@@ -370,6 +711,9 @@ fn print_config_info(
             if let Ok(content) = std::fs::read_to_string(path) {
                 let format = config::detect_format(&content);
                 let format_str = match format {
+                    config::ConfigFormat::Auto if config::is_empty_config(&content) => {
+                        "auto (empty file)"
+                    }
                     config::ConfigFormat::Auto => "auto",
                     config::ConfigFormat::SimpleColor => "simple (hex color)",
                     config::ConfigFormat::Toml => "toml",
@@ -397,35 +741,67 @@ fn print_config_info(
                 } else {
                     eprintln!(
                         "Background:      Auto-generated ({}% lightness)",
-                        (user_config.background_lightness * 100.0) as u8
+                        (user_config.derived_background_lightness() * 100.0) as u8
                     );
                 }
             }
         }
+        config::ConfigSource::VsCode(path) => {
+            eprintln!("Source type:     VS Code settings (Peacock color)");
+            eprintln!("Source path:     {}", path.display());
+            if let Ok(Some(color)) = config::read_peacock_color(path) {
+                eprintln!("Peacock color:   {}", color);
+            }
+            eprintln!();
+            eprintln!(
+                "Background:      Auto-generated ({}% lightness)",
+                (user_config.derived_background_lightness() * 100.0) as u8
+            );
+        }
         config::ConfigSource::TriggerFile(dir_path) => {
             eprintln!("Source type:     Trigger file (auto-generated color)");
-            eprintln!("Source path:     {}", dir_path);
+            eprintln!("Source path:     {}", dir_path.display());
             eprintln!("Config format:   auto (hash-based)");
             eprintln!();
             eprintln!("Raw config:      <auto-generated from directory path>");
             eprintln!();
-            eprintln!(
-                "Background:      Auto-generated ({}% lightness)",
-                (user_config.background_lightness * 100.0) as u8
-            );
+            print_trigger_background(source, user_config);
         }
         config::ConfigSource::TriggerPath(dir_path) => {
             eprintln!("Source type:     Trigger path (auto-generated color)");
-            eprintln!("Source path:     {}", dir_path);
+            eprintln!("Source path:     {}", dir_path.display());
+            eprintln!("Config format:   auto (hash-based)");
+            eprintln!();
+            eprintln!("Raw config:      <auto-generated from directory path>");
+            eprintln!();
+            print_trigger_background(source, user_config);
+        }
+        config::ConfigSource::TriggerDirname(dir_path) => {
+            eprintln!("Source type:     Trigger directory name (auto-generated color)");
+            eprintln!("Source path:     {}", dir_path.display());
             eprintln!("Config format:   auto (hash-based)");
             eprintln!();
             eprintln!("Raw config:      <auto-generated from directory path>");
             eprintln!();
+            print_trigger_background(source, user_config);
+        }
+        config::ConfigSource::Environment { rule, assignment } => {
+            eprintln!("Source type:     Environment trigger");
+            eprintln!("Source rule:     {}", rule);
+            eprintln!("Matched:         {}", assignment);
+            eprintln!();
             eprintln!(
                 "Background:      Auto-generated ({}% lightness)",
-                (user_config.background_lightness * 100.0) as u8
+                (user_config.derived_background_lightness() * 100.0) as u8
             );
         }
+        config::ConfigSource::KubeContext { pattern, context } => {
+            eprintln!("Source type:     Kube context trigger");
+            eprintln!("Source pattern:  {}", pattern);
+            eprintln!("Context:         {}", context);
+            eprintln!();
+            print_trigger_background(source, user_config);
+        }
     }
 
     eprintln!();
@@ -441,63 +817,649 @@ fn print_config_info(
     eprintln!();
 }
 
-fn cmd_apply(verbose: bool, force: bool) {
-    state::cleanup_stale_sessions();
+/// Get the dimming settings if the current local time falls inside the dimming window.
+fn active_dimming(
+    ctx: &Context,
+    user_config: &user_config::UserConfig,
+) -> Option<user_config::DimmingConfig> {
+    user_config
+        .dimming
+        .filter(|dimming| dimming.is_active_at(config::local_minute_of_day(ctx.now)))
+}
+
+/// Parse a config source, darken it by directory depth, and apply time-of-day dimming if active.
+/// With `cached`, auto colors come from the color cache when `color_cache` is enabled.
+fn resolve_colors(
+    ctx: &Context,
+    source: &config::ConfigSource,
+    user_config: &user_config::UserConfig,
+    depth: u32,
+    dimming: Option<&user_config::DimmingConfig>,
+    cached: bool,
+) -> Result<config::ColorConfig, String> {
+    resolve_colors_explained(
+        ctx,
+        source,
+        user_config,
+        depth,
+        dimming,
+        cached,
+        &mut Explanation::disabled(),
+    )
+}
+
+/// `resolve_colors`, recording the parsed colors and each adjustment in `explain`.
+fn resolve_colors_explained(
+    ctx: &Context,
+    source: &config::ConfigSource,
+    user_config: &user_config::UserConfig,
+    depth: u32,
+    dimming: Option<&user_config::DimmingConfig>,
+    cached: bool,
+    explain: &mut Explanation,
+) -> Result<config::ColorConfig, String> {
+    let _span = tracing::debug_span!("resolve", %source).entered();
+    explain.step("Colors");
+    let parsed = match source {
+        config::ConfigSource::Termtint(path) => {
+            cache::parse_termtint_cached(ctx, path, user_config, explain)
+        }
+        _ if cached => cache::parse_config_source_cached(ctx, source, user_config, explain),
+        _ => config::parse_config_source(source, user_config),
+    };
+    let parsed = parsed.inspect_err(|e| {
+        tracing::debug!(error = %e, "parse failed");
+        explain.note(|| format!("parse failed: {}", e))
+    })?;
+    tracing::debug!(tab = %parsed.tab, background = %parsed.background, "parsed");
+    explain.note(|| {
+        format!(
+            "parsed {}: tab {}, background {}",
+            source, parsed.tab, parsed.background
+        )
+    });
+    let color_config = config::apply_depth_ramp(&parsed, depth, user_config.depth_ramp);
+    if user_config.depth_ramp > 0.0 {
+        explain.note(|| {
+            format!(
+                "depth ramp: {} level{} below the source, background {}",
+                depth,
+                if depth == 1 { "" } else { "s" },
+                color_config.background
+            )
+        });
+    }
+    let color_config = config::apply_temperature(&color_config, user_config.temperature_shift);
+    if user_config.temperature_shift != 0.0 {
+        explain.note(|| {
+            format!(
+                "temperature shift {:+.2}: tab {}, background {}",
+                user_config.temperature_shift, color_config.tab, color_config.background
+            )
+        });
+    }
+    Ok(match dimming {
+        Some(dimming) => {
+            let dimmed = config::apply_dimming(&color_config, dimming);
+            explain.note(|| {
+                format!(
+                    "dimming active ({:.0}% lightness): tab {}, background {}",
+                    dimming.lightness_scale * 100.0,
+                    dimmed.tab,
+                    dimmed.background
+                )
+            });
+            dimmed
+        }
+        None => color_config,
+    })
+}
+
+/// The escape sequences for resolved colors, and the derived ANSI palette when enabled.
+fn color_output(
+    color_config: &config::ColorConfig,
+    capabilities: iterm::Capabilities,
+    user_config: &user_config::UserConfig,
+) -> String {
+    let mut output = iterm::color_sequences(color_config, capabilities);
+    if user_config.generate_palette {
+        output.push_str(&iterm::palette_sequences(&palette::generate_palette(
+            color_config,
+        )));
+    }
+    output
+}
+
+/// The escape sequences for the color resets, including the palette reset when
+/// palettes are enabled.
+fn reset_output(user_config: &user_config::UserConfig) -> String {
+    let (tab_seq, bg_seq) = iterm::get_reset_sequences();
+    let mut output = tab_seq + &bg_seq;
+    if user_config.generate_palette {
+        output.push_str(&iterm::get_palette_reset_sequence());
+    }
+    output
+}
+
+/// Emit resolved colors, and the derived ANSI palette when enabled, to `out`
+/// (stdout, or a `termtint serve` client).
+fn emit_colors(
+    out: &mut dyn Write,
+    color_config: &config::ColorConfig,
+    capabilities: iterm::Capabilities,
+    user_config: &user_config::UserConfig,
+) {
+    let _span = tracing::debug_span!("emit").entered();
+    let output = color_output(color_config, capabilities, user_config);
+    tracing::debug!(tab = %color_config.tab, background = %color_config.background, bytes = output.len(), "emitting colors");
+    let _ = out.write_all(output.as_bytes());
+}
+
+/// Emit the color resets, including the palette reset when palettes are enabled.
+fn emit_reset(out: &mut dyn Write, user_config: &user_config::UserConfig) {
+    let _span = tracing::debug_span!("emit").entered();
+    tracing::debug!("emitting resets");
+    let _ = out.write_all(reset_output(user_config).as_bytes());
+}
+
+/// Print verbose details about colors that are about to be emitted.
+fn print_applying(
+    color_config: &config::ColorConfig,
+    capabilities: iterm::Capabilities,
+    user_config: &user_config::UserConfig,
+    dimming: Option<&user_config::DimmingConfig>,
+) {
+    if let Some(dimming) = dimming {
+        eprintln!(
+            "termtint: dimming active ({:.0}% lightness)",
+            dimming.lightness_scale * 100.0
+        );
+    }
+    eprintln!("termtint: applying colors");
+    print_color_swatches(&color_config.tab, &color_config.background, user_config);
+    if !capabilities.truecolor {
+        let emitted = capabilities.emitted(color_config);
+        eprintln!(
+            "termtint: approximated to 256-color palette (tab {}, background {})",
+            emitted.tab, emitted.background
+        );
+    }
+}
+
+/// Depth of the current directory below the config source. Only matters (and is
+/// only tracked) with a depth ramp; environment and kube context triggers have no
+/// directory to be below.
+fn source_depth(
+    source: Option<&config::ConfigSource>,
+    current_dir: &Path,
+    user_config: &user_config::UserConfig,
+) -> u32 {
+    match source {
+        Some(
+            config::ConfigSource::Environment { .. } | config::ConfigSource::KubeContext { .. },
+        ) => 0,
+        Some(source) if user_config.depth_ramp > 0.0 => {
+            config::depth_below(&source.root_dir(), current_dir)
+        }
+        _ => 0,
+    }
+}
+
+/// Resolve and emit colors for the current directory without reading or writing
+/// any state (or the color cache), for `--no-state`.
+fn apply_without_state(
+    ctx: &Context,
+    current_dir: &Path,
+    user_config: &user_config::UserConfig,
+    capabilities: iterm::Capabilities,
+    verbose: bool,
+    explain: &mut Explanation,
+) {
+    let dimming = active_dimming(ctx, user_config);
+    let dimming = dimming.as_ref();
+    let Some(source) = config::find_source_explained(ctx, current_dir, user_config, explain) else {
+        if verbose {
+            eprintln!("termtint: reset (no config found)");
+        }
+        emit_reset(&mut capture::Terminal, user_config);
+        explain.step("Output");
+        explain.note(|| {
+            format!(
+                "emitted reset {}",
+                escape_for_display(&reset_output(user_config))
+            )
+        });
+        return;
+    };
+    let depth = source_depth(Some(&source), current_dir, user_config);
+    match resolve_colors_explained(ctx, &source, user_config, depth, dimming, false, explain) {
+        Ok(color_config) => {
+            if verbose {
+                print_config_info(&source, &color_config, user_config);
+                print_applying(&color_config, capabilities, user_config, dimming);
+            }
+            emit_colors(
+                &mut capture::Terminal,
+                &color_config,
+                capabilities,
+                user_config,
+            );
+            explain.step("Output");
+            explain.note(|| {
+                format!(
+                    "emitted {}",
+                    escape_for_display(&color_output(&color_config, capabilities, user_config))
+                )
+            });
+        }
+        Err(e) => {
+            eprintln!("Error parsing config: {}", e);
+            explain.step("Output");
+            explain.note(|| "nothing emitted".to_string());
+        }
+    }
+}
+
+/// Describe a recorded state for `apply --explain`.
+fn describe_state(state: &state::ConfigState) -> String {
+    let mut description = format!(
+        "{:?} {} (mtime {}, depth {}",
+        state.source_type,
+        state.path.display(),
+        state.mtime,
+        state.depth
+    );
+    if let Some((tab, background)) = state.applied_colors() {
+        description.push_str(&format!(", tab {}, background {}", tab, background));
+    }
+    if let Some(tty) = &state.tty {
+        description.push_str(&format!(", tty {}", tty));
+    }
+    description.push(')');
+    description
+}
+
+fn cmd_apply(
+    ctx: &Context,
+    verbose: bool,
+    force: bool,
+    no_state: bool,
+    explain: bool,
+    fast: bool,
+    via_daemon: bool,
+) {
+    // Checked before anything else so a disabled apply neither emits nor touches
+    // state, the user config, or the session sweep
+    if state::disabled_from_env(ctx) {
+        if verbose || explain {
+            eprintln!("termtint: disabled (TERMTINT_DISABLE is set), doing nothing");
+        }
+        return;
+    }
+    let _span = tracing::debug_span!("apply").entered();
+    tracing::debug!(verbose, force, no_state, explain, fast, "starting");
+    if fast {
+        apply_fast(ctx, force, no_state);
+        return;
+    }
+    // The daemon answers only the plain hook apply; anything asking for more is
+    // resolved in-process
+    if via_daemon && !(no_state || force || verbose || explain) {
+        if let Some(output) = apply_via_daemon(ctx) {
+            let _ = capture::Terminal.write_all(output.as_bytes());
+            return;
+        }
+    }
+    let mut explanation = match explain {
+        true => Explanation::new(),
+        false => Explanation::disabled(),
+    };
+    apply(ctx, verbose, force, no_state, &mut explanation);
+    if explanation.is_enabled() {
+        eprint!("{}", explanation);
+    }
+}
+
+fn apply(ctx: &Context, verbose: bool, force: bool, no_state: bool, explain: &mut Explanation) {
+    let (user_config, warnings) = config_or_exit(user_config::load_user_config_with_warnings(ctx));
+    report_config_warnings(ctx, &warnings, verbose || no_state);
 
-    let user_config = user_config::load_user_config();
+    let capabilities = iterm::Capabilities::for_config(&user_config);
 
-    let current_dir = match std::env::current_dir() {
+    let current_dir = match ctx.current_dir() {
         Ok(dir) => dir,
+        // A deleted working directory with no $PWD to recover from: leave the
+        // colors alone rather than print an error at every prompt
+        Err(e) if e.is_not_found() && !verbose => return,
         Err(e) => {
-            eprintln!("Error getting current directory: {}", e);
+            eprintln!("{}", e);
             return;
         }
     };
+    if let Some(removed) = &ctx.removed_cwd {
+        if verbose {
+            eprintln!(
+                "termtint: {} no longer exists, resolving from {}",
+                removed.display(),
+                current_dir.display()
+            );
+        }
+        explain.step("Working directory");
+        explain.note(|| {
+            format!(
+                "{} no longer exists: resolving from its nearest ancestor, {}",
+                removed.display(),
+                current_dir.display()
+            )
+        });
+    }
 
-    let config_source = config::find_config_source(&current_dir, &user_config);
-    let last_state = state::read_last_config_state();
+    if no_state {
+        if verbose {
+            eprintln!("termtint: state bypassed (--no-state), not reading or writing state");
+        }
+        explain.step("State");
+        explain.note(|| "bypassed (--no-state): not read or written".to_string());
+        apply_without_state(
+            ctx,
+            current_dir,
+            &user_config,
+            capabilities,
+            verbose,
+            explain,
+        );
+        return;
+    }
 
-    // Build current state if we have a config source
-    let current_state = config_source.as_ref().and_then(|source| match source {
-        config::ConfigSource::Termtint(path) => {
-            // For explicit .termtint files, track the file's mtime
-            state::get_file_mtime(path).map(|mtime| state::ConfigState {
-                path: path.clone(),
-                mtime,
-                source_type: state::ConfigSourceType::Explicit,
-            })
+    apply_with_state(
+        ctx,
+        current_dir,
+        &user_config,
+        Some(&user_config::config_file_path(ctx)),
+        state::tty_identity(),
+        &mut capture::Terminal,
+        verbose,
+        force,
+        explain,
+    );
+
+    // Sweep after the colors are out so a due sweep never delays the prompt; it
+    // is throttled by cleanup_interval, so most runs only stat a marker file
+    let cleanup =
+        state::cleanup_stale_sessions(ctx, user_config.session_ttl, user_config.cleanup_interval);
+    explain.step("Session cleanup");
+    explain.note(|| match cleanup {
+        state::Cleanup::Disabled => "skipped: session_ttl is never".to_string(),
+        state::Cleanup::NotWritable => "skipped: state directory not writable".to_string(),
+        state::Cleanup::Throttled => format!(
+            "skipped: last sweep was less than {} ago (cleanup_interval)",
+            state::format_duration(user_config.cleanup_interval)
+        ),
+        state::Cleanup::Swept => "ran: swept stale sessions".to_string(),
+    });
+}
+
+/// A loaded user config, or exit with the error for a config `strict = true`
+/// rejects.
+fn config_or_exit<T>(loaded: Result<T, error::TermtintError>) -> T {
+    loaded.unwrap_or_else(|e| {
+        eprintln!("termtint: error: {}", e);
+        std::process::exit(e.exit_code());
+    })
+}
+
+/// Log the user config's warnings for the prompt hook: as warnings the first time
+/// the session sees them (or every time when `always` is set), and at info level
+/// afterwards, so a broken config isn't repeated at every prompt.
+fn report_config_warnings(ctx: &Context, warnings: &[String], always: bool) {
+    if always || state::config_warnings_are_new(ctx, warnings) {
+        for warning in warnings {
+            tracing::warn!("{}", warning);
         }
-        config::ConfigSource::TriggerPath(dir_path) => {
-            // For path globs, use directory path and always mtime 0 (always apply)
-            Some(state::ConfigState {
-                path: std::path::PathBuf::from(dir_path),
-                mtime: 0,
-                source_type: state::ConfigSourceType::TriggerPath,
-            })
+    } else {
+        for warning in warnings {
+            tracing::info!("{} (already reported in this session)", warning);
         }
-        config::ConfigSource::TriggerFile(dir_path) => {
-            // For trigger files, use directory path and always mtime 0 (always apply)
-            Some(state::ConfigState {
-                path: std::path::PathBuf::from(dir_path),
-                mtime: 0,
-                source_type: state::ConfigSourceType::TriggerFile,
-            })
+    }
+}
+
+/// Ask `termtint serve` for this session's escape sequences. Returns None when the
+/// daemon isn't running or can't answer for this shell, to apply in-process instead.
+fn apply_via_daemon(ctx: &Context) -> Option<String> {
+    let request = daemon::Request::Apply {
+        cwd: ctx.current_dir().ok()?.to_path_buf(),
+        // Without a session, state is shared through the fallback file, whose
+        // tty checks need this process's terminal
+        session: state::session_id(ctx)?,
+        tty: state::tty_identity(),
+        config: user_config::config_file_path(ctx),
+    };
+    match daemon::send(&daemon::socket_path(ctx), &request) {
+        Ok(daemon::Response::Ok(output)) => Some(output),
+        Ok(daemon::Response::Fallback(reason)) => {
+            tracing::debug!(%reason, "daemon can't apply, applying in-process");
+            None
+        }
+        Ok(daemon::Response::Error(e)) => {
+            tracing::warn!("termtint serve failed: {}, applying in-process", e);
+            None
+        }
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            tracing::warn!("not using termtint serve: {}, applying in-process", e);
+            None
+        }
+        Err(e) => {
+            tracing::debug!(error = %e, "daemon unavailable, applying in-process");
+            None
+        }
+    }
+}
+
+/// `apply --fast`: the same walk, change detection, emit, and state update as
+/// `apply`, but with the built-in defaults (or `TERMTINT_FAST_CONFIG`) instead of
+/// the user config, and no session sweep, messages, or trace.
+fn apply_fast(ctx: &Context, force: bool, no_state: bool) {
+    let (user_config, config_path, warnings) =
+        config_or_exit(user_config::load_fast_user_config(ctx));
+    report_config_warnings(ctx, &warnings, no_state);
+    let current_dir = match ctx.current_dir() {
+        Ok(dir) => dir,
+        Err(e) if e.is_not_found() => return,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    if no_state {
+        apply_without_state(
+            ctx,
+            current_dir,
+            &user_config,
+            iterm::Capabilities::for_config(&user_config),
+            false,
+            &mut Explanation::disabled(),
+        );
+        return;
+    }
+    apply_with_state(
+        ctx,
+        current_dir,
+        &user_config,
+        config_path.as_deref(),
+        state::tty_identity(),
+        &mut capture::Terminal,
+        false,
+        force,
+        &mut Explanation::disabled(),
+    );
+}
+
+/// Resolve colors for the current directory and emit them to `out` unless the
+/// session's state shows they are already applied, then record what was applied.
+/// `config_path` is the file `user_config` was loaded from, if any, and `tty` the
+/// terminal the colors are for.
+#[allow(clippy::too_many_arguments)]
+fn apply_with_state(
+    ctx: &Context,
+    current_dir: &Path,
+    user_config: &user_config::UserConfig,
+    config_path: Option<&Path>,
+    tty: Option<String>,
+    out: &mut dyn Write,
+    verbose: bool,
+    force: bool,
+    explain: &mut Explanation,
+) {
+    let capabilities = iterm::Capabilities::for_config(user_config);
+    let dimming = active_dimming(ctx, user_config);
+    let dimming = dimming.as_ref();
+
+    // Without a writable state directory there is no state to lock, read, or write;
+    // colors are simply emitted on every run
+    explain.step("State");
+    let lock = if state::state_writable(ctx) {
+        match state::lock_state(ctx, state::STATE_LOCK_TIMEOUT) {
+            Ok(Some(lock)) => {
+                explain.note(|| format!("state file {}", state::state_file_path(ctx).display()));
+                Some(lock)
+            }
+            // Another apply in this session (e.g. a racing async hook) is already
+            // updating the state; skip rather than emit from stale data or hang the prompt
+            Ok(None) => {
+                if verbose {
+                    eprintln!("termtint: state is locked by another apply, skipping");
+                }
+                explain.note(|| "locked by another apply: skipped, nothing emitted".to_string());
+                return;
+            }
+            // Nothing can hold the lock (e.g. the session's directory isn't
+            // writable), so the session's state can't be kept either
+            Err(e) => {
+                if verbose {
+                    eprintln!(
+                        "termtint: warning: can't lock {} ({}), not tracking state",
+                        state::state_file_path(ctx).display(),
+                        e
+                    );
+                }
+                explain.note(|| {
+                    format!(
+                        "can't lock {} ({}): not tracking state",
+                        state::state_file_path(ctx).display(),
+                        e
+                    )
+                });
+                None
+            }
+        }
+    } else {
+        let warning = format!(
+            "state directory {} is not writable, not tracking state",
+            state::state_root(ctx).display()
+        );
+        // Once per session, rather than at every prompt of a hook run with --verbose
+        if verbose && state::unwritable_warning_is_new(ctx, &warning, &daemon::runtime_dir(ctx)) {
+            eprintln!("termtint: warning: {}", warning);
+        }
+        explain.note(|| {
+            format!(
+                "state directory {} not writable: not tracking state",
+                state::state_root(ctx).display()
+            )
+        });
+        None
+    };
+
+    // State written from another terminal (e.g. two windows sharing the fallback
+    // state file) says nothing about this one, so treat it as absent
+    let last_state = lock
+        .as_ref()
+        .and_then(|_| state::read_last_config_state(ctx));
+    let last_state = last_state.filter(|last| {
+        let foreign = last.is_from_other_tty(tty.as_deref());
+        if foreign && verbose {
+            eprintln!(
+                "termtint: ignoring state from another terminal ({})",
+                last.tty.as_deref().unwrap_or("?")
+            );
+        }
+        explain.note(|| match foreign {
+            true => format!(
+                "ignoring state from another terminal: {}",
+                describe_state(last)
+            ),
+            false => format!("last applied: {}", describe_state(last)),
+        });
+        !foreign
+    });
+    if last_state.is_none() {
+        explain.note(|| "no previous state".to_string());
+    }
+    let config_source = config::find_source_explained(ctx, current_dir, user_config, explain);
+    match &config_source {
+        Some(source) => {
+            tracing::debug!(dir = %current_dir.display(), %source, "found config source")
+        }
+        None => tracing::debug!(dir = %current_dir.display(), "no config source"),
+    }
+    // Editing the config file (including the trigger lists) changes this and forces a re-apply
+    let config_mtime = config_path.and_then(state::get_file_mtime).unwrap_or(0);
+
+    let depth = source_depth(config_source.as_ref(), current_dir, user_config);
+
+    // Build current state if we have a config source
+    let current_state = config_source.as_ref().and_then(|source| {
+        let state = state::ConfigState::for_source(source, dimming.is_some(), depth, config_mtime)?;
+        Some(match config::source_preset(source, user_config) {
+            Some(preset) => state.with_dependency(&preset),
+            None => state,
+        })
+    });
+    explain.step("Source");
+    explain.note(|| match (&config_source, &current_state) {
+        (Some(source), Some(current)) => {
+            format!("using {}, state {}", source, describe_state(current))
         }
+        (Some(source), None) => format!("using {}, which can no longer be read", source),
+        (None, _) => "none found".to_string(),
     });
 
     match (&current_state, &last_state) {
         // Same config source and unchanged, no change needed (skip if force is set)
-        (Some(current), Some(last)) if current == last && !force => {
-            if verbose {
+        (Some(current), Some(last)) if current.matches(last) && !force => {
+            if verbose || explain.is_enabled() {
                 if let Some(source) = &config_source {
-                    if let Ok(color_config) = config::parse_config_source(source, &user_config) {
-                        print_config_info(source, &color_config, &user_config);
-                        eprintln!("termtint: (unchanged)");
-                        print_color_swatches(
-                            &color_config.tab,
-                            &color_config.background,
-                            &user_config,
-                        );
+                    if let Ok(color_config) = resolve_colors_explained(
+                        ctx,
+                        source,
+                        user_config,
+                        depth,
+                        dimming,
+                        true,
+                        explain,
+                    ) {
+                        if verbose {
+                            print_config_info(source, &color_config, user_config);
+                            eprintln!("termtint: (unchanged)");
+                            print_color_swatches(
+                                &color_config.tab,
+                                &color_config.background,
+                                user_config,
+                            );
+                        }
+                        explain.step("Change detection");
+                        explain.note(|| "unchanged since the last apply".to_string());
+                        explain.step("Output");
+                        explain.note(|| {
+                            format!(
+                                "nothing emitted; would have emitted {}",
+                                escape_for_display(&color_output(
+                                    &color_config,
+                                    capabilities,
+                                    user_config
+                                ))
+                            )
+                        });
                     }
                 }
             }
@@ -506,34 +1468,153 @@ fn cmd_apply(verbose: bool, force: bool) {
         // Found a config source (new or changed)
         (Some(current), _) => {
             if let Some(source) = &config_source {
-                match config::parse_config_source(source, &user_config) {
+                match resolve_colors_explained(
+                    ctx,
+                    source,
+                    user_config,
+                    depth,
+                    dimming,
+                    true,
+                    explain,
+                ) {
                     Ok(color_config) => {
+                        let applied = state::ConfigState {
+                            tab: Some(color_config.tab),
+                            background: Some(color_config.background),
+                            tty: tty.clone(),
+                            ..current.clone()
+                        };
+                        let transition = last_state.as_ref().and_then(|last| {
+                            state::describe_transition(last, current, user_config)
+                        });
+                        explain.step("Change detection");
+                        explain.note(|| match (&last_state, &transition) {
+                            _ if force
+                                && last_state
+                                    .as_ref()
+                                    .is_some_and(|last| current.matches(last)) =>
+                            {
+                                "unchanged, but re-applying (--force)".to_string()
+                            }
+                            (None, _) => "no previous state: applying".to_string(),
+                            (Some(_), Some(transition)) => format!("changed: {}", transition),
+                            (Some(_), None) => "changed".to_string(),
+                        });
+                        // A different source that resolves to the colors already on
+                        // screen (e.g. returning from a nested override) needs no emission
+                        let already_shown = !force
+                            && last_state
+                                .as_ref()
+                                .is_some_and(|last| applied.shows_same_colors(last));
+                        if already_shown {
+                            if verbose {
+                                print_config_info(source, &color_config, user_config);
+                                eprintln!("termtint: (same colors, not re-emitted)");
+                            }
+                            state::write_last_config_state(ctx, Some(&applied));
+                            explain.note(|| "same colors as already on screen".to_string());
+                            explain.step("Output");
+                            explain.note(|| {
+                                format!(
+                                    "nothing emitted; would have emitted {}",
+                                    escape_for_display(&color_output(
+                                        &color_config,
+                                        capabilities,
+                                        user_config
+                                    ))
+                                )
+                            });
+                            explain.note(|| format!("state written: {}", describe_state(&applied)));
+                            return;
+                        }
                         if verbose {
-                            print_config_info(source, &color_config, &user_config);
-                            eprintln!("termtint: applying colors");
-                            print_color_swatches(
-                                &color_config.tab,
-                                &color_config.background,
-                                &user_config,
+                            print_config_info(source, &color_config, user_config);
+                            if let Some(transition) = &transition {
+                                eprintln!("termtint: {}", transition);
+                            }
+                            print_applying(&color_config, capabilities, user_config, dimming);
+                        }
+                        emit_colors(out, &color_config, capabilities, user_config);
+                        state::write_last_config_state(ctx, Some(&applied));
+                        explain.step("Output");
+                        explain.note(|| {
+                            format!(
+                                "emitted {}",
+                                escape_for_display(&color_output(
+                                    &color_config,
+                                    capabilities,
+                                    user_config
+                                ))
+                            )
+                        });
+                        explain.note(|| format!("state written: {}", describe_state(&applied)));
+                    }
+                    // The working directory was deleted after the walk found its
+                    // source; the next prompt resolves from what's left
+                    Err(e) if context::is_removed(current_dir) => {
+                        if verbose {
+                            eprintln!(
+                                "termtint: {} was removed while resolving ({}), leaving colors as they are",
+                                current_dir.display(),
+                                e
                             );
                         }
-                        iterm::apply_colors(&color_config);
-                        state::write_last_config_state(Some(current));
+                        explain.step("Output");
+                        explain.note(|| {
+                            format!(
+                                "nothing emitted; {} was removed while resolving",
+                                current_dir.display()
+                            )
+                        });
                     }
                     Err(e) => {
                         eprintln!("Error parsing config: {}", e);
+                        // Leave the colors on screen, and the state describing them,
+                        // untouched; only note the failure for `inspect`
+                        if let Some(last) = &last_state {
+                            state::write_last_config_state(
+                                ctx,
+                                Some(&state::ConfigState {
+                                    last_error: Some(format!("{}: {}", current.path.display(), e)),
+                                    ..last.clone()
+                                }),
+                            );
+                        }
+                        explain.step("Output");
+                        explain.note(|| {
+                            "nothing emitted; the colors on screen are left as they are".to_string()
+                        });
                     }
                 }
             }
         }
 
-        // No config found, but had one before - reset colors
-        (None, Some(_)) => {
+        // No config found, but had one before - reset colors. A recorded state
+        // always means colors are on screen (resets clear it), so this can't be skipped.
+        (None, Some(last)) => {
             if verbose {
-                eprintln!("termtint: reset (no config)");
+                match last.applied_colors() {
+                    Some((tab, background)) => eprintln!(
+                        "termtint: reset (no config; clearing tab {}, background {})",
+                        tab.format_as(user_config.color_format),
+                        background.format_as(user_config.color_format)
+                    ),
+                    None => eprintln!("termtint: reset (no config)"),
+                }
             }
-            iterm::reset_colors();
-            state::write_last_config_state(None);
+            emit_reset(out, user_config);
+            state::write_last_config_state(ctx, None);
+            state::record_reset(ctx, state::ResetReason::LeftProject);
+            explain.step("Change detection");
+            explain.note(|| "no source, but colors were applied before: resetting".to_string());
+            explain.step("Output");
+            explain.note(|| {
+                format!(
+                    "emitted reset {}",
+                    escape_for_display(&reset_output(user_config))
+                )
+            });
+            explain.note(|| "state cleared".to_string());
         }
 
         // No config found and none before - reset to ensure clean state
@@ -541,34 +1622,192 @@ fn cmd_apply(verbose: bool, force: bool) {
             if verbose {
                 eprintln!("termtint: reset (no config found)");
             }
-            iterm::reset_colors();
+            emit_reset(out, user_config);
+            state::record_no_config_reset(ctx);
+            explain.step("Change detection");
+            explain.note(|| "no source and no previous state: resetting".to_string());
+            explain.step("Output");
+            explain.note(|| {
+                format!(
+                    "emitted reset {}",
+                    escape_for_display(&reset_output(user_config))
+                )
+            });
         }
     }
 }
 
-fn cmd_reset(verbose: bool) {
-    if verbose {
-        eprintln!("termtint: resetting colors to default");
-        eprintln!();
+/// Answer a `termtint serve` request with the daemon's loaded user config.
+fn serve_request(
+    ctx: &Context,
+    warm: &mut daemon::WarmConfig,
+    requests: &mut u64,
+    started: std::time::Instant,
+    request: daemon::Request,
+) -> daemon::Response {
+    *requests += 1;
+    warm.refresh(ctx);
+    if let Some(e) = &warm.error {
+        if matches!(
+            request,
+            daemon::Request::Apply { .. } | daemon::Request::Reset { .. }
+        ) {
+            // Applying in-process reports the error the way it would without us
+            return daemon::Response::Fallback(e.to_string());
+        }
+    }
+    match request {
+        daemon::Request::Apply {
+            cwd,
+            session,
+            tty,
+            config,
+        } => {
+            if config != warm.path() {
+                return daemon::Response::Fallback(format!(
+                    "the client uses {}, not {}",
+                    config.display(),
+                    warm.path().display()
+                ));
+            }
+            if daemon::needs_client_environment(&warm.config) {
+                return daemon::Response::Fallback(
+                    "the user config has environment or kube-context triggers".to_string(),
+                );
+            }
+            // Content checks last one apply, as they do in a process of their own
+            config::clear_content_checks();
+            let client = warm.client_context(ctx, &cwd, &session);
+            let mut out = Vec::new();
+            apply_with_state(
+                &client,
+                &cwd,
+                &warm.config,
+                Some(warm.path()),
+                tty,
+                &mut out,
+                false,
+                false,
+                &mut Explanation::disabled(),
+            );
+            state::cleanup_stale_sessions(
+                &client,
+                warm.config.session_ttl,
+                warm.config.cleanup_interval,
+            );
+            daemon::Response::Ok(String::from_utf8_lossy(&out).into_owned())
+        }
+        daemon::Request::Reset { session } => {
+            let client = warm.client_context(ctx, ctx.home_dir(), &session);
+            let mut out = Vec::new();
+            emit_reset(&mut out, &warm.config);
+            state::write_last_config_state(&client, None);
+            state::record_reset(&client, state::ResetReason::Command);
+            daemon::Response::Ok(String::from_utf8_lossy(&out).into_owned())
+        }
+        daemon::Request::Status => daemon::Response::Ok(format!(
+            "pid: {}\nuptime: {}\nrequests: {}\nconfig: {} (loaded {} time{})\n",
+            std::process::id(),
+            state::format_duration(std::time::Duration::from_secs(started.elapsed().as_secs())),
+            requests,
+            warm.path().display(),
+            warm.loads,
+            if warm.loads == 1 { "" } else { "s" }
+        )),
+        daemon::Request::Shutdown => unreachable!("the server answers shutdown itself"),
+    }
+}
 
-        // Show the escape sequences being emitted
-        eprintln!("Escape sequences:");
+/// `termtint serve`: answer requests until stopped, or with `status` or `stop`,
+/// talk to the daemon that's running.
+fn cmd_serve(ctx: &Context, status: bool, stop: bool) -> Result<(), error::TermtintError> {
+    let path = daemon::socket_path(ctx);
+    if status || stop {
+        let request = match stop {
+            true => daemon::Request::Shutdown,
+            false => daemon::Request::Status,
+        };
+        return match daemon::send(&path, &request) {
+            Ok(daemon::Response::Ok(body)) => {
+                print!("{}", body);
+                Ok(())
+            }
+            Ok(daemon::Response::Fallback(message) | daemon::Response::Error(message)) => {
+                Err(error::TermtintError::State(message))
+            }
+            Err(e) => Err(error::TermtintError::State(format!(
+                "termtint serve is not running on {} ({})",
+                path.display(),
+                e
+            ))),
+        };
+    }
+
+    let server = daemon::Server::bind(&path)?;
+    daemon::stop_on_signals();
+    let mut warm = daemon::WarmConfig::load(ctx)?;
+    let mut requests = 0;
+    let started = std::time::Instant::now();
+    eprintln!("termtint: serving on {}", path.display());
+    server
+        .run(|request| serve_request(ctx, &mut warm, &mut requests, started, request))
+        .map_err(|e| error::TermtintError::io("Error accepting connections", &e))?;
+    eprintln!("termtint: stopped");
+    Ok(())
+}
+
+fn cmd_reset(ctx: &Context, verbose: bool, all_sessions: bool, no_state: bool) {
+    let user_config = config_or_exit(user_config::load_user_config(ctx));
+
+    if no_state && all_sessions {
+        eprintln!("Error: --all-sessions works on recorded session state and can't be used with --no-state");
+        std::process::exit(error::EXIT_USAGE);
+    }
+
+    if verbose {
+        eprintln!("termtint: resetting colors to default");
+        eprintln!();
+
+        // Show the escape sequences being emitted
+        eprintln!("Escape sequences:");
         let (tab_seq, bg_seq) = iterm::get_reset_sequences();
         eprintln!("  Tab color reset:        {}", escape_for_display(&tab_seq));
         eprintln!("  Background color reset: {}", escape_for_display(&bg_seq));
+        if user_config.generate_palette {
+            eprintln!(
+                "  Palette reset:          {}",
+                escape_for_display(&iterm::get_palette_reset_sequence())
+            );
+        }
         eprintln!();
 
+        if no_state {
+            eprintln!("State: bypassed (--no-state), not reading or writing state");
+            eprintln!();
+        }
+    }
+    if no_state {
+        emit_reset(&mut capture::Terminal, &user_config);
+        if verbose {
+            eprintln!("Done.");
+        }
+        return;
+    }
+
+    if verbose {
         // Show state file information
-        let state_path = state::state_file_path();
+        let state_path = state::state_file_path(ctx);
         eprintln!("State file: {}", state_path.display());
 
-        let last_state = state::read_last_config_state();
+        let last_state = state::read_last_config_state(ctx);
+        eprintln!("Previous reset: {}", describe_last_reset(ctx));
         match last_state {
             Some(state) => {
                 eprintln!("Previous state:");
                 eprintln!("  Path: {}", state.path.display());
                 eprintln!("  Modified time: {}", state.mtime);
                 eprintln!("  Source type: {:?}", state.source_type);
+                eprintln!("  Dimmed: {}", state.dimmed);
                 eprintln!();
                 eprintln!("Clearing state file...");
             }
@@ -579,25 +1818,74 @@ fn cmd_reset(verbose: bool) {
         }
     }
 
-    iterm::reset_colors();
-    state::write_last_config_state(None);
+    emit_reset(&mut capture::Terminal, &user_config);
+    state::write_last_config_state(ctx, None);
+    state::record_reset(ctx, state::ResetReason::Command);
+
+    if verbose {
+        match state::session_id(ctx) {
+            Some(session) => eprintln!("Reset session {}", session),
+            None => eprintln!("Reset the shared state (no session identified)"),
+        }
+    }
+    if all_sessions {
+        reset_other_sessions(ctx, verbose, user_config.generate_palette);
+    }
 
     if verbose {
         eprintln!("Done.");
     }
 }
 
+/// Reset every other recorded session: clear its state and, when its terminal's
+/// tty can still be opened, emit the resets there. Dead ttys are skipped.
+fn reset_other_sessions(ctx: &Context, verbose: bool, palette: bool) {
+    let current = state::session_id(ctx);
+    let cleared = state::clear_other_sessions(ctx, current.as_deref());
+
+    if verbose && cleared.is_empty() {
+        eprintln!("No other sessions with applied colors");
+    }
+    for session in &cleared {
+        let tty = match &session.state {
+            state::SessionState::Applied(applied) => applied.tty.as_deref(),
+            _ => None,
+        };
+        let outcome = match tty {
+            Some(tty) => match iterm::reset_colors_on_tty(Path::new(tty), palette) {
+                Ok(()) => format!("reset on {}", tty),
+                Err(e) => format!("state cleared; couldn't write to {} ({})", tty, e),
+            },
+            None => "state cleared; no tty recorded".to_string(),
+        };
+        if verbose {
+            eprintln!("Reset session {}: {}", session.id, outcome);
+        }
+    }
+}
+
 /// Convert escape sequences to a readable format for display
 fn escape_for_display(seq: &str) -> String {
     seq.replace('\x1b', "\\x1b").replace('\x07', "\\x07")
 }
 
-fn cmd_hook(shell: &str) {
+/// Print the shell hook. Like `state::disabled_from_env`, the hooks ignore case in
+/// `TERMTINT_DISABLE`; bash and zsh leave a value with spaces in it to `apply`.
+fn cmd_hook(shell: &str, fast: bool, daemon: bool) {
+    let apply = match (fast, daemon) {
+        (true, _) => "termtint apply --fast",
+        (false, true) => "termtint apply --via-daemon",
+        (false, false) => "termtint apply",
+    };
     match shell {
         "zsh" => {
             println!(
                 r#"_termtint_hook() {{
-  termtint apply
+  case "${{TERMTINT_DISABLE:-0}}" in
+    0|[Ff][Aa][Ll][Ss][Ee]|[Nn][Oo]|[Oo][Ff][Ff]|*[[:space:]]*) ;;
+    *) return ;;
+  esac
+  {apply}
 }}
 autoload -Uz add-zsh-hook
 add-zsh-hook chpwd _termtint_hook
@@ -607,7 +1895,11 @@ _termtint_hook"#
         "bash" => {
             println!(
                 r#"_termtint_hook() {{
-  termtint apply
+  case "${{TERMTINT_DISABLE:-0}}" in
+    0|[Ff][Aa][Ll][Ss][Ee]|[Nn][Oo]|[Oo][Ff][Ff]|*[[:space:]]*) ;;
+    *) return ;;
+  esac
+  {apply}
 }}
 _termtint_prompt_command() {{
   local _termtint_new_pwd="$PWD"
@@ -628,20 +1920,25 @@ _termtint_hook"#
         "fish" => {
             println!(
                 r#"function _termtint_hook --on-variable PWD
-  termtint apply
+  switch (string lower -- (string trim -- "$TERMTINT_DISABLE"))
+    case '' 0 false no off
+    case '*'
+      return
+  end
+  {apply}
 end
 _termtint_hook"#
             );
         }
         _ => {
             eprintln!("Error: unsupported shell '{}'. Supported shells: zsh, bash, fish", shell);
-            std::process::exit(1);
+            std::process::exit(error::EXIT_USAGE);
         }
     }
 }
 
-fn cmd_config(user_config: &user_config::UserConfig) {
-    let config_path = user_config::config_file_path();
+fn cmd_config(ctx: &Context, user_config: &user_config::UserConfig) {
+    let config_path = user_config::config_file_path(ctx);
     let exists = config_path.exists();
 
     println!("Config file: {}", config_path.display());
@@ -655,136 +1952,366 @@ fn cmd_config(user_config: &user_config::UserConfig) {
     println!("AVAILABLE SETTINGS");
     println!("{}", "=".repeat(60));
 
-    // background_lightness
-    println!(
-        "\nbackground_lightness = {:.2}",
-        user_config.background_lightness
-    );
-    println!("  Lightness for auto-darkened backgrounds.");
-    println!("  Range: 0.0 (black) to 1.0 (full brightness)");
-    println!("  Default: 0.18");
+    for field in schema::CONFIG_FIELDS {
+        if field.is_value() {
+            print_config_field(field, user_config);
+            if field.name == "state_dir" {
+                println!("  In use: {}", state::state_root(ctx).display());
+            }
+            continue;
+        }
 
-    // background_saturation
-    println!(
-        "\nbackground_saturation = {:.2}",
-        user_config.background_saturation
-    );
-    println!("  Saturation multiplier for auto-darkened backgrounds.");
-    println!("  Range: 0.0 (grayscale) to 1.0 (preserve original)");
-    println!("  Default: 1.00");
+        let header = match field.ty {
+            schema::FieldType::Tables { .. } => format!("[[{}]]", field.name),
+            _ => format!("[{}]", field.name),
+        };
+        println!("\n{}", "-".repeat(60));
+        println!("{}", header);
+        println!("{}", "-".repeat(60));
+        for line in field.doc.lines() {
+            println!("  {}", line);
+        }
 
-    // trigger_files
-    if user_config.trigger_files.is_empty() {
-        println!("\ntrigger_files = []");
-    } else {
-        println!("\ntrigger_files = {:?}", user_config.trigger_files);
+        match field.name {
+            "auto" => {
+                for auto_field in field.fields() {
+                    print_config_field(auto_field, user_config);
+                    if auto_field.name == "preset" {
+                        print_presets();
+                    }
+                }
+            }
+            "dimming" => match &user_config.dimming {
+                Some(dimming) => {
+                    println!(
+                        "\nstart = \"{:02}:{:02}\"",
+                        dimming.start / 60,
+                        dimming.start % 60
+                    );
+                    println!("end = \"{:02}:{:02}\"", dimming.end / 60, dimming.end % 60);
+                    println!("lightness_scale = {:.2}", dimming.lightness_scale);
+                    println!("dim_tab = {}", dimming.dim_tab);
+                }
+                None => println!("\n(not configured)"),
+            },
+            "hosts" | "env_triggers" => {
+                let entries = match field.name {
+                    "hosts" => &user_config.hosts,
+                    _ => &user_config.env_triggers,
+                };
+                if entries.is_empty() {
+                    println!("\n(not configured)");
+                } else {
+                    println!();
+                    for (key, color) in entries {
+                        println!("\"{}\" = \"{}\"", key, color);
+                    }
+                }
+            }
+            "triggers" => print_config_triggers(user_config),
+            _ => {}
+        }
     }
-    println!("  Files that trigger automatic color generation when found.");
-    println!("  When present in a directory, termtint generates a hash-based color.");
-    println!("  Example: [\"Cargo.toml\", \"package.json\", \"go.mod\"]");
-    println!("  Default: [] (disabled)");
-
-    // trigger_paths
-    if user_config.trigger_paths.is_empty() {
-        println!("\ntrigger_paths = []");
-    } else {
-        println!("\ntrigger_paths = {:?}", user_config.trigger_paths);
-    }
-    println!("  Path globs that trigger automatic color generation.");
-    println!("  Directories matching these patterns are treated as having 'auto' .termtint.");
-    println!("  Supports ~ for home directory. Example: [\"~/Code/*\", \"~/Projects/*\"]");
-    println!("  Default: [] (disabled)");
-
-    // color_format
-    let format_str = match user_config.color_format {
-        user_config::ColorFormat::Hex => "hex",
-        user_config::ColorFormat::Hsl => "hsl",
-        user_config::ColorFormat::Rgb => "rgb",
-    };
-    println!("\ncolor_format = \"{}\"", format_str);
-    println!("  Format for displaying colors in output.");
-    println!(
-        "  Options: \"hex\" (#ff5500), \"hsl\" (hsl(20, 100%, 50%)), \"rgb\" (rgb(255, 85, 0))"
-    );
-    println!("  Default: \"hex\"");
-
-    println!("\n{}", "-".repeat(60));
-    println!("[auto] - Auto Color Generation Parameters");
-    println!("{}", "-".repeat(60));
-
-    // hue_min / hue_max
-    println!("\nhue_min = {:.1}", user_config.hue_min);
-    println!("hue_max = {:.1}", user_config.hue_max);
-    println!("  Hue range for auto-generated colors (color wheel position).");
-    println!("  Range: 0.0 to 360.0 (degrees)");
-    println!("  0=red, 60=yellow, 120=green, 180=cyan, 240=blue, 300=magenta");
-    println!("  Default: 0.0 - 360.0 (full spectrum)");
-
-    // saturation_min / saturation_max
-    println!("\nsaturation_min = {:.2}", user_config.saturation_min);
-    println!("saturation_max = {:.2}", user_config.saturation_max);
-    println!("  Saturation range for auto-generated colors (color intensity).");
-    println!("  Range: 0.0 (gray) to 1.0 (vivid)");
-    println!("  Default: 0.7 - 0.9");
-
-    // lightness
-    println!("\nlightness = {:.2}", user_config.lightness);
-    println!("  Lightness for auto-generated tab colors.");
-    println!("  Range: 0.0 (dark) to 1.0 (bright)");
-    println!("  Default: 0.55");
 
     println!("\n{}", "=".repeat(60));
     println!("Run 'termtint config --edit' to edit your config file.");
 }
 
-fn cmd_inspect() {
-    let user_config = user_config::load_user_config();
+/// Print a config key's current value, documentation, and default.
+fn print_config_field(field: &schema::ConfigField, user_config: &user_config::UserConfig) {
+    match user_config.field_value(field.name) {
+        Some(value) => println!("\n{} = {}", field.name, value),
+        None => println!("\n{} = (not set)", field.name),
+    }
+    for line in field.doc.lines() {
+        println!("  {}", line);
+    }
+    println!("  Default: {}", field.default.unwrap_or("none"));
+}
 
-    let current_dir = match std::env::current_dir() {
+/// Print the `[auto]` presets and the values each one sets.
+fn print_presets() {
+    println!("  Available presets:");
+    for preset in user_config::PRESETS {
+        println!("    {:<8} {}", preset.name, preset.description);
+        println!(
+            "             hue {:.0}-{:.0}, saturation {:.2}-{:.2}, lightness {:.2},",
+            preset.hue_min,
+            preset.hue_max,
+            preset.saturation_min,
+            preset.saturation_max,
+            preset.lightness
+        );
+        println!(
+            "             background_lightness {:.2}, background_saturation {:.2}",
+            preset.background_lightness, preset.background_saturation
+        );
+    }
+}
+
+/// Print the `[[triggers]]` entries, including the trigger list keys'.
+fn print_config_triggers(user_config: &user_config::UserConfig) {
+    if user_config.triggers.is_empty() {
+        println!("\n(not configured)");
+        return;
+    }
+    for trigger in &user_config.triggers {
+        println!("\nmatch = \"{}\"", trigger.pattern);
+        println!("kind = \"{}\"", trigger.kind.name());
+        if let Some(color) = &trigger.color {
+            println!("color = \"{}\"", color);
+        } else if trigger.has_hue_band() {
+            println!(
+                "hue = [{:.1}, {:.1}]",
+                trigger.hue_min.unwrap_or(user_config.hue_min),
+                trigger.hue_max.unwrap_or(user_config.hue_max)
+            );
+        }
+        if let Some(text) = &trigger.contains {
+            println!("contains = {:?}", text);
+        }
+        if let Some(key) = &trigger.toml_key {
+            println!("toml_key = \"{}\"", key);
+        }
+        if let Some(lightness) = trigger.background_lightness {
+            println!("background_lightness = {:.2}", lightness);
+        }
+        if let Some(saturation) = trigger.background_saturation {
+            println!("background_saturation = {:.2}", saturation);
+        }
+    }
+}
+
+/// Print readable foreground options for a background, with contrast ratios and a sample.
+fn print_foreground_suggestion(background: &config::RGB, user_config: &user_config::UserConfig) {
+    let (suggested, ratio) = config::suggest_foreground(background);
+    let truecolor = iterm::Capabilities::for_config(user_config).truecolor;
+
+    println!("Foreground:");
+    for (name, fg, option_ratio) in config::foreground_options(background) {
+        let marker = if fg == suggested { "*" } else { " " };
+        let rating = if option_ratio >= config::WCAG_AA_CONTRAST {
+            "AA"
+        } else {
+            "  "
+        };
+        println!(
+            "  {} {:<13} {:<20} {:>5.2}:1 {}",
+            marker,
+            name,
+            fg.format_as(user_config.color_format),
+            option_ratio,
+            rating
+        );
+    }
+    println!(
+        "  {}\x1b[38;2;{};{};{}m  Suggested: {} ({:.2}:1)  \x1b[0m",
+        background.ansi_background(truecolor),
+        suggested.r,
+        suggested.g,
+        suggested.b,
+        suggested.format_as(user_config.color_format),
+        ratio
+    );
+}
+
+fn cmd_inspect(ctx: &Context) {
+    let user_config = config_or_exit(user_config::load_user_config(ctx));
+
+    let current_dir = match ctx.current_dir() {
         Ok(dir) => dir,
         Err(e) => {
-            eprintln!("Error getting current directory: {}", e);
+            eprintln!("{}", e);
             return;
         }
     };
 
     println!("Current directory: {}", current_dir.display());
+    if state::disabled_from_env(ctx) {
+        println!("Disabled: TERMTINT_DISABLE is set, so apply does nothing");
+    }
     println!();
 
     // Find config source
-    let config_source = config::find_config_source(&current_dir, &user_config);
+    let config_source = config::find_source(ctx, current_dir, &user_config);
+    let path_matches = config::trigger_path_matches(current_dir, &user_config);
+    let links = hyperlink::enabled(ctx, user_config.hyperlinks, io::stdout().is_terminal());
 
     match &config_source {
         Some(config::ConfigSource::Termtint(path)) => {
             println!("Config source: .termtint file");
-            println!("  Path: {}", path.display());
+            println!("  Path: {}", hyperlink::path(path, links));
+        }
+        Some(config::ConfigSource::VsCode(path)) => {
+            println!("Config source: vscode settings");
+            println!("  Path: {}", hyperlink::path(path, links));
+            if let Ok(Some(color)) = config::read_peacock_color(path) {
+                println!("  Peacock color: {}", color);
+            }
         }
         Some(config::ConfigSource::TriggerFile(dir_path)) => {
             println!("Config source: trigger file");
-            println!("  Directory: {}", dir_path);
+            println!("  Directory: {}", hyperlink::path(dir_path, links));
 
             // Determine which trigger file was matched
-            let dir = std::path::PathBuf::from(dir_path);
-            for trigger_file in &user_config.trigger_files {
-                if dir.join(trigger_file).exists() {
-                    println!("  Matched file: {}", trigger_file);
-                    break;
+            let dir = Path::new(dir_path);
+            if let Some((trigger, file)) = config::matched_trigger(dir, &user_config) {
+                let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+                if config::is_glob_pattern(&trigger.pattern) {
+                    println!(
+                        "  Matched file: {} (pattern {})",
+                        file_name, trigger.pattern
+                    );
+                } else {
+                    println!("  Matched file: {}", file_name);
                 }
             }
         }
         Some(config::ConfigSource::TriggerPath(dir_path)) => {
             println!("Config source: trigger path");
-            println!("  Directory: {}", dir_path);
+            println!("  Directory: {}", hyperlink::path(dir_path, links));
+            if let Some((_, found)) = path_matches.last() {
+                println!("  Matched pattern: {}", found.pattern);
+            }
+        }
+        Some(config::ConfigSource::TriggerDirname(dir_path)) => {
+            println!("Config source: trigger directory name");
+            println!("  Directory: {}", hyperlink::path(dir_path, links));
+            if let Some(pattern) = config::matched_dirname(Path::new(dir_path), &user_config) {
+                println!("  Matched name: {}", pattern);
+            }
+        }
+        Some(config::ConfigSource::Environment { rule, assignment }) => {
+            println!("Config source: environment trigger");
+            println!("  Rule: {}", rule);
+            println!("  Matched: {}", assignment);
+        }
+        Some(config::ConfigSource::KubeContext { pattern, context }) => {
+            println!("Config source: kube context trigger");
+            println!("  Pattern: {}", pattern);
+            println!("  Context: {}", context);
         }
         None => {
             println!("Config source: none found");
+            if config::walk_depth_exceeded(current_dir, &user_config) {
+                let depth = user_config.max_walk_depth.unwrap_or(0);
+                println!(
+                    "  Search stopped after {} parent level{} (max_walk_depth)",
+                    depth,
+                    if depth == 1 { "" } else { "s" }
+                );
+            }
+        }
+    }
+    // Options the matched trigger sets for its directories
+    let source_trigger = config_source
+        .as_ref()
+        .and_then(|source| config::source_trigger(source, &user_config));
+    if let Some(trigger) = source_trigger {
+        if let Some(color) = &trigger.color {
+            println!("  Trigger color: {}", color);
+        } else if trigger.has_hue_band() {
+            let banded = user_config.with_hue_band(trigger);
+            println!(
+                "  Hue band: {:.0}° - {:.0}°",
+                banded.hue_min, banded.hue_max
+            );
+        }
+    }
+    if let Some(trigger) = config_source
+        .as_ref()
+        .and_then(|source| config::trigger_background_params(source, &user_config))
+    {
+        let params = user_config.with_background_params(trigger);
+        println!(
+            "  Background: lightness {:.2}, saturation {:.2} (from trigger {})",
+            params.background_lightness, params.background_saturation, trigger.pattern
+        );
+    }
+    for (dir, found) in &path_matches {
+        if let Some(negation) = found.vetoed_by {
+            println!(
+                "  Trigger path {} matched {}, vetoed by {}",
+                found.pattern,
+                dir.display(),
+                negation
+            );
+        }
+    }
+    for (trigger, file, reason) in config::rejected_trigger_files(current_dir, &user_config) {
+        println!(
+            "  Trigger file {} skipped: {} {}",
+            trigger.pattern,
+            file.display(),
+            reason
+        );
+    }
+    if let Some(pattern) = config::matched_exclusion(current_dir, &user_config) {
+        println!(
+            "  Excluded by pattern {}: trigger paths and files are ignored",
+            pattern
+        );
+    }
+    let priority: Vec<&str> = user_config
+        .trigger_priority
+        .iter()
+        .map(|kind| kind.name())
+        .collect();
+    println!("  Checked in order: {}", priority.join(", "));
+    if user_config.respect_git_root {
+        if let Some(root) = config::git_root(current_dir) {
+            println!("  Git root: {} (search stops here)", root.display());
         }
     }
     println!();
 
+    // Show what feeds the hash for auto-generated colors
+    let hashed_source = config_source.as_ref().filter(|source| match source {
+        config::ConfigSource::Termtint(path) => std::fs::read_to_string(path)
+            .map(|content| config::detect_format(&content) == config::ConfigFormat::Auto)
+            .unwrap_or(false),
+        config::ConfigSource::VsCode(_) | config::ConfigSource::Environment { .. } => false,
+        _ => source_trigger.is_none_or(|trigger| trigger.color.is_none()),
+    });
+    if let Some(source) = hashed_source {
+        let inputs = config::AutoHashInputs::for_source(source, &user_config);
+        println!("Hash inputs:");
+        match &inputs.package_name {
+            Some(name) => println!("  Package name: {}", name),
+            None => println!("  Path: {}", inputs.path.display()),
+        }
+        match &inputs.hostname {
+            Some(hostname) => println!("  Hostname: {}", hostname),
+            None if user_config.hash_include_hostname => println!("  Hostname: (unavailable)"),
+            None => println!("  Hostname: (not included)"),
+        }
+        println!();
+    }
+
+    // Show the inputs when a trigger color is blended with its parent project
+    if let Some(source) = &config_source {
+        if let Some(blend) = config::parent_blend(source, &user_config) {
+            println!("Parent blend: {:.0}% parent", blend.factor * 100.0);
+            println!("  Parent: {}", blend.parent_path.display());
+            print_color_swatches_stdout(&blend.parent.tab, &blend.parent.background, &user_config);
+            println!("  Own (auto):");
+            print_color_swatches_stdout(&blend.own.tab, &blend.own.background, &user_config);
+            println!();
+        }
+    }
+
     // Parse and display colors if a config source was found
     if let Some(source) = &config_source {
-        match config::parse_config_source(source, &user_config) {
+        let depth = source_depth(Some(source), current_dir, &user_config);
+        if depth > 0 {
+            println!(
+                "Depth ramp: {} level(s) below source, background lightness -{:.2}",
+                depth,
+                user_config.depth_ramp * depth as f32
+            );
+            println!();
+        }
+        match resolve_colors(ctx, source, &user_config, depth, None, false) {
             Ok(color_config) => {
                 println!("Resolved colors:");
                 print_color_swatches_stdout(
@@ -792,6 +2319,8 @@ fn cmd_inspect() {
                     &color_config.background,
                     &user_config,
                 );
+                println!();
+                print_foreground_suggestion(&color_config.background, &user_config);
             }
             Err(e) => {
                 println!("Error parsing config: {}", e);
@@ -801,108 +2330,409 @@ fn cmd_inspect() {
     }
 
     // Display cached state
-    let last_state = state::read_last_config_state();
+    match state::session_id(ctx) {
+        Some(session) => println!("Session: {}", session),
+        None => println!("Session: (unidentified, using shared state)"),
+    }
+    let last_state = state::read_last_config_state(ctx);
     match last_state {
         Some(state) => {
             println!("Cached state:");
             println!("  Path: {}", state.path.display());
             println!("  Modified time: {}", state.mtime);
             println!("  Source type: {:?}", state.source_type);
+            println!("  Dimmed: {}", state.dimmed);
+            println!("  Depth: {}", state.depth);
+            if let Some(tty) = &state.tty {
+                println!("  TTY: {}", tty);
+            }
+            if let Some(error) = &state.last_error {
+                println!("  Last error: {}", error);
+            }
+            if let (Some(tab), Some(background)) = (state.tab, state.background) {
+                println!(
+                    "  Applied: tab {}, background {}",
+                    tab.format_as(user_config.color_format),
+                    background.format_as(user_config.color_format)
+                );
+            }
         }
         None => {
             println!("Cached state: none");
         }
     }
+    println!("Last reset: {}", describe_last_reset(ctx));
 }
 
-/// Returns true if the pattern looks like a path glob (contains /, *, ~, or ?)
-fn is_path_pattern(pattern: &str) -> bool {
-    pattern.contains('/') || pattern.contains('*') || pattern.contains('~') || pattern.contains('?')
+/// Describe the current session's last reset, e.g. "5m ago (left project)".
+fn describe_last_reset(ctx: &Context) -> String {
+    match state::read_last_reset(ctx) {
+        Some(record) => format!(
+            "{} ago ({})",
+            record
+                .age(ctx.now)
+                .map(sessions::format_age)
+                .unwrap_or_else(|| "?".to_string()),
+            record.reason.description()
+        ),
+        None => "none recorded".to_string(),
+    }
 }
 
-fn cmd_trigger_add(pattern: &str) -> Result<(), String> {
-    let mut user_config = user_config::load_user_config();
+/// The heading a trigger kind is listed under.
+fn trigger_kind_label(kind: user_config::TriggerKind) -> &'static str {
+    match kind {
+        user_config::TriggerKind::File => "trigger files",
+        user_config::TriggerKind::Path => "trigger paths",
+        user_config::TriggerKind::Dirname => "trigger directory names",
+        user_config::TriggerKind::KubeContext => "kube context triggers",
+    }
+}
 
-    if is_path_pattern(pattern) {
-        // It's a path glob
-        if user_config.trigger_paths.contains(&pattern.to_string()) {
-            println!("'{}' is already in trigger paths.", pattern);
-            return Ok(());
+/// Parse a `--hue` band given as "MIN,MAX" in degrees.
+fn parse_hue_band(value: &str) -> Result<(f32, f32), String> {
+    let parse = |part: &str| {
+        part.trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|hue| (0.0..=360.0).contains(hue))
+    };
+    value
+        .split_once(',')
+        .and_then(|(min, max)| Some((parse(min)?, parse(max)?)))
+        .ok_or_else(|| {
+            format!(
+                "Error: invalid hue band '{}', expected MIN,MAX in degrees (e.g. 15,45)",
+                value
+            )
+        })
+}
+
+/// Add a trigger, or update the options of an existing one. `kind` is the kind
+/// chosen by `--dirname` or `--kube-context`; otherwise it follows from the pattern.
+fn cmd_trigger_add(
+    ctx: &Context,
+    pattern: &str,
+    kind: Option<user_config::TriggerKind>,
+    color: Option<&str>,
+    hue: Option<&str>,
+    contains: Option<&str>,
+    toml_key: Option<&str>,
+) -> Result<(), String> {
+    let mut user_config = config_or_exit(user_config::load_user_config(ctx));
+
+    if let Err(e) = user_config::validate_glob(pattern) {
+        return Err(format!("Error: '{}' is not a valid glob: {}", pattern, e));
+    }
+    let kind = match kind {
+        Some(user_config::TriggerKind::Dirname) if pattern.contains('/') => {
+            return Err(format!(
+                "Error: '{}' is not a directory name; use a path glob without --dirname",
+                pattern
+            ));
         }
-        user_config.trigger_paths.push(pattern.to_string());
-        user_config::save_trigger_paths(&user_config.trigger_paths)?;
-        println!("Added '{}' to trigger paths.", pattern);
-    } else {
-        // It's a file name
-        if user_config.trigger_files.contains(&pattern.to_string()) {
-            println!("'{}' is already in trigger files.", pattern);
+        Some(kind) => kind,
+        None => user_config::TriggerKind::for_pattern(pattern),
+    };
+
+    let mut trigger = user_config::Trigger::new(kind, pattern);
+    trigger.color = color
+        .filter(|color| !color.eq_ignore_ascii_case("auto"))
+        .map(String::from);
+    if let Some(color) = &trigger.color {
+        config::parse_color(color)
+            .map_err(|e| format!("Error: invalid color '{}': {}", color, e))?;
+    }
+    if let Some(hue) = hue {
+        let (min, max) = parse_hue_band(hue)?;
+        trigger.hue_min = Some(min);
+        trigger.hue_max = Some(max);
+    }
+    if (contains.is_some() || toml_key.is_some()) && kind != user_config::TriggerKind::File {
+        return Err(format!(
+            "Error: --contains and --toml-key only apply to trigger files, not '{}'",
+            pattern
+        ));
+    }
+    trigger.contains = contains.map(String::from);
+    trigger.toml_key = toml_key.map(String::from);
+
+    let label = trigger_kind_label(kind);
+    let has_options = color.is_some() || hue.is_some() || trigger.has_content_condition();
+    let existing = user_config
+        .triggers
+        .iter_mut()
+        .find(|existing| existing.kind == kind && existing.pattern == pattern);
+    match existing {
+        Some(existing) if !has_options || *existing == trigger => {
+            println!("'{}' is already in {}.", pattern, label);
             return Ok(());
         }
-        user_config.trigger_files.push(pattern.to_string());
-        user_config::save_trigger_files(&user_config.trigger_files)?;
-        println!("Added '{}' to trigger files.", pattern);
+        Some(existing) => {
+            *existing = trigger;
+            user_config::save_triggers(ctx, &user_config.triggers)?;
+            println!("Updated '{}' in {}.", pattern, label);
+        }
+        None => {
+            user_config.triggers.push(trigger);
+            user_config::save_triggers(ctx, &user_config.triggers)?;
+            println!("Added '{}' to {}.", pattern, label);
+        }
     }
     Ok(())
 }
 
-fn cmd_trigger_remove(pattern: &str) -> Result<(), String> {
-    let mut user_config = user_config::load_user_config();
+fn cmd_trigger_remove(ctx: &Context, pattern: &str) -> Result<(), String> {
+    let mut user_config = config_or_exit(user_config::load_user_config(ctx));
 
-    // Check both lists and remove from whichever contains it
-    let in_files = user_config.trigger_files.contains(&pattern.to_string());
-    let in_paths = user_config.trigger_paths.contains(&pattern.to_string());
+    // Remove the pattern whichever kind of trigger it is
+    let removed: Vec<user_config::TriggerKind> = user_config
+        .triggers
+        .iter()
+        .filter(|trigger| trigger.pattern == pattern)
+        .map(|trigger| trigger.kind)
+        .collect();
 
-    if !in_files && !in_paths {
+    if removed.is_empty() {
         println!("'{}' is not in triggers.", pattern);
         return Ok(());
     }
 
-    if in_files {
-        user_config.trigger_files.retain(|f| f != pattern);
-        user_config::save_trigger_files(&user_config.trigger_files)?;
-        println!("Removed '{}' from trigger files.", pattern);
-    }
-
-    if in_paths {
-        user_config.trigger_paths.retain(|p| p != pattern);
-        user_config::save_trigger_paths(&user_config.trigger_paths)?;
-        println!("Removed '{}' from trigger paths.", pattern);
+    user_config
+        .triggers
+        .retain(|trigger| trigger.pattern != pattern);
+    user_config::save_triggers(ctx, &user_config.triggers)?;
+    for kind in removed {
+        println!("Removed '{}' from {}.", pattern, trigger_kind_label(kind));
     }
 
     Ok(())
 }
 
-fn cmd_trigger_list(user_config: &user_config::UserConfig) {
-    let has_files = !user_config.trigger_files.is_empty();
-    let has_paths = !user_config.trigger_paths.is_empty();
+/// A trigger's own options for `trigger list`, such as " (color #cc0000)".
+fn describe_trigger_options(
+    trigger: &user_config::Trigger,
+    user_config: &user_config::UserConfig,
+) -> String {
+    let mut options = Vec::new();
+    if let Some(color) = &trigger.color {
+        options.push(format!("color {}", color));
+    }
+    if trigger.has_hue_band() {
+        options.push(format!(
+            "hue {:.0}° - {:.0}°",
+            trigger.hue_min.unwrap_or(user_config.hue_min),
+            trigger.hue_max.unwrap_or(user_config.hue_max)
+        ));
+    }
+    if let Some(text) = &trigger.contains {
+        options.push(format!("contains {:?}", text));
+    }
+    if let Some(key) = &trigger.toml_key {
+        options.push(format!("toml_key {}", key));
+    }
+    if let Some(lightness) = trigger.background_lightness {
+        options.push(format!("background lightness {:.2}", lightness));
+    }
+    if let Some(saturation) = trigger.background_saturation {
+        options.push(format!("background saturation {:.2}", saturation));
+    }
+    match options.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", options.join(", ")),
+    }
+}
 
-    if !has_files && !has_paths {
+fn cmd_trigger_list(user_config: &user_config::UserConfig) {
+    if user_config.triggers.is_empty() {
         println!("No triggers configured.");
         return;
     }
 
-    if has_files {
-        println!("Trigger files:");
-        for file in &user_config.trigger_files {
-            println!("  {}", file);
+    let kinds = [
+        user_config::TriggerKind::File,
+        user_config::TriggerKind::Path,
+        user_config::TriggerKind::Dirname,
+        user_config::TriggerKind::KubeContext,
+    ];
+    let mut first = true;
+    for kind in kinds {
+        let mut triggers = user_config
+            .triggers
+            .iter()
+            .filter(|trigger| trigger.kind == kind)
+            .peekable();
+        if triggers.peek().is_none() {
+            continue;
         }
-    }
+        if !first {
+            println!();
+        }
+        first = false;
 
-    if has_files && has_paths {
-        println!();
+        let label = trigger_kind_label(kind);
+        println!("{}{}:", label[..1].to_uppercase(), &label[1..]);
+        for trigger in triggers {
+            println!(
+                "  {}{}",
+                trigger.pattern,
+                describe_trigger_options(trigger, user_config)
+            );
+        }
     }
+}
 
-    if has_paths {
-        println!("Trigger paths:");
-        for path in &user_config.trigger_paths {
-            println!("  {}", path);
+/// Resolve the current directory's project for `export`: the source's own colors,
+/// without the depth ramp or time-of-day dimming, so the export is the same from
+/// any subdirectory at any hour. Environment and kube context sources have no
+/// directory, so the current one names the project.
+fn export_project(
+    ctx: &Context,
+    user_config: &user_config::UserConfig,
+) -> Result<export::Project, String> {
+    let current_dir = ctx.current_dir()?;
+    let source = config::find_source(ctx, current_dir, user_config).ok_or_else(|| {
+        format!(
+            "Error: no .termtint or trigger matches {}",
+            current_dir.display()
+        )
+    })?;
+    let colors = resolve_colors(ctx, &source, user_config, 0, None, false)
+        .map_err(|e| format!("Error parsing config: {}", e))?;
+    let dir = match source {
+        config::ConfigSource::Environment { .. } | config::ConfigSource::KubeContext { .. } => {
+            current_dir.to_path_buf()
         }
+        _ => source.root_dir(),
+    };
+    Ok(export::Project::new(
+        dir,
+        colors,
+        user_config.generate_palette,
+    ))
+}
+
+/// Export the current project's colors in `format`, printed, written to `out`, or
+/// (for iTerm2 profiles) installed into iTerm2's DynamicProfiles folder.
+fn cmd_export(
+    ctx: &Context,
+    format: export::Format,
+    install: bool,
+    out: Option<&Path>,
+    user_config: &user_config::UserConfig,
+) -> Result<(), String> {
+    if install && format != export::Format::ItermProfile {
+        return Err("Error: --install only applies to --iterm-profile".to_string());
+    }
+    let project = export_project(ctx, user_config)?;
+    if install {
+        let path = export::install_iterm_profile(ctx.home_dir(), &project)?;
+        println!(
+            "Installed iTerm2 profile '{}' to {}",
+            project.name,
+            path.display()
+        );
+        return Ok(());
+    }
+    let output = export::render(format, &project);
+    match out {
+        Some(path) => {
+            export::write_export(path, &output)?;
+            println!("Wrote {}", path.display());
+        }
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+/// Merge the current project's colors into its VS Code workspace settings (or
+/// `out`), keeping every other setting. With `print` the merged file is printed,
+/// and with `dry_run` the changes are shown, instead of writing it.
+fn cmd_export_vscode(
+    ctx: &Context,
+    out: Option<&Path>,
+    print: bool,
+    dry_run: bool,
+    user_config: &user_config::UserConfig,
+) -> Result<(), String> {
+    let project = export_project(ctx, user_config)?;
+    let path = out
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| export::vscode_settings_path(&project.dir));
+    let existing = match std::fs::read_to_string(&path) {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Error reading {}: {}", path.display(), e)),
+    };
+    let merge = export::merge_vscode_settings(existing.as_deref(), &project)
+        .map_err(|e| format!("Error: can't merge into {}: {}", path.display(), e))?;
+
+    if print {
+        print!("{}", merge.content);
+        return Ok(());
     }
+    if existing.as_deref() == Some(merge.content.as_str()) {
+        println!("No changes to {}", path.display());
+        return Ok(());
+    }
+    if dry_run {
+        println!("{}:", path.display());
+        print!(
+            "{}",
+            export::line_diff(existing.as_deref().unwrap_or(""), &merge.content)
+        );
+        return Ok(());
+    }
+    if merge.dropped_comments {
+        return Err(format!(
+            "Error: {} has comments, which rewriting it would drop; \
+             use --print and copy the settings over by hand",
+            path.display()
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Error creating {}: {}", parent.display(), e))?;
+    }
+    export::write_export(&path, &merge.content)?;
+    println!("Updated {}", path.display());
+    Ok(())
 }
 
-fn cmd_config_edit() -> Result<(), String> {
+/// `config --check`: list the keys in the config file that termtint ignores
+/// because it doesn't recognize them, failing when there are any.
+fn cmd_config_check(ctx: &Context) -> Result<(), error::TermtintError> {
+    let config_path = user_config::config_file_path(ctx);
+    let check = user_config::check_config_file(&config_path)?;
+    if check.is_ok() {
+        println!("{}: ok", config_path.display());
+        return Ok(());
+    }
+    for key in &check.unknown_keys {
+        eprintln!("{}: unrecognized key {}", config_path.display(), key);
+    }
+    for fix in &check.invalid_values {
+        eprintln!("{}: invalid value: {}", config_path.display(), fix);
+    }
+    let counts = [
+        (check.unknown_keys.len(), "unrecognized key"),
+        (check.invalid_values.len(), "invalid value"),
+    ];
+    let found: Vec<String> = counts
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, what)| format!("{} {}{}", count, what, if *count == 1 { "" } else { "s" }))
+        .collect();
+    Err(error::TermtintError::Config(format!(
+        "{} in {}",
+        found.join(" and "),
+        config_path.display()
+    )))
+}
+
+fn cmd_config_edit(ctx: &Context) -> Result<(), String> {
     // 1. Get config file path
-    let config_path = user_config::config_file_path();
+    let config_path = user_config::config_file_path(ctx);
 
     // 2. Create parent directories if needed
     if let Some(parent) = config_path.parent() {
@@ -927,7 +2757,7 @@ fn cmd_config_edit() -> Result<(), String> {
     }
 
     // 4. Read EDITOR environment variable and split into command + args
-    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let editor = ctx.var("EDITOR").unwrap_or("vi");
     let mut parts = editor.split_whitespace();
     let cmd = parts.next().unwrap_or("vi");
     let args: Vec<&str> = parts.collect();
@@ -949,74 +2779,332 @@ fn cmd_config_edit() -> Result<(), String> {
 
 fn main() {
     let cli = Cli::parse();
+    let mut ctx = Context::from_process();
+    ctx.config_file = cli.config;
+    logging::init(&ctx, cli.debug);
+    capture::init(&ctx, cli.capture.as_deref(), cli.capture_only);
 
     match cli.command {
-        Commands::Hook { shell } => {
-            cmd_hook(&shell);
+        Commands::Hook {
+            shell,
+            fast,
+            daemon,
+        } => {
+            cmd_hook(&shell, fast, daemon);
         }
-        Commands::Apply { verbose, force } => {
-            cmd_apply(verbose, force);
+        Commands::Apply {
+            verbose,
+            force,
+            no_state,
+            explain,
+            fast,
+            via_daemon,
+        } => {
+            cmd_apply(
+                &ctx,
+                verbose,
+                force,
+                no_state || state::no_state_from_env(&ctx),
+                explain,
+                fast,
+                via_daemon,
+            );
         }
-        Commands::Reset { verbose } => {
-            cmd_reset(verbose);
+        Commands::Reset {
+            verbose,
+            all_sessions,
+            no_state,
+        } => {
+            cmd_reset(
+                &ctx,
+                verbose,
+                all_sessions,
+                no_state || state::no_state_from_env(&ctx),
+            );
         }
         Commands::Init {
             color,
             background,
             force,
+            keep_literal,
+            #[cfg(feature = "itermcolors")]
+            from_itermcolors,
         } => {
-            let user_config = user_config::load_user_config();
-            if let Err(e) = init::cmd_init(color, background, force, &user_config) {
+            let user_config = config_or_exit(user_config::load_user_config(&ctx));
+            #[cfg(feature = "itermcolors")]
+            if let Some(preset) = from_itermcolors {
+                if let Err(e) = init::cmd_init_from_itermcolors(
+                    &ctx,
+                    &preset,
+                    color,
+                    force,
+                    keep_literal,
+                    &user_config,
+                ) {
+                    eprintln!("{}", e);
+                    std::process::exit(e.exit_code());
+                }
+                return;
+            }
+            if let Err(e) =
+                init::cmd_init(&ctx, color, background, force, keep_literal, &user_config)
+            {
                 eprintln!("{}", e);
-                std::process::exit(1);
+                std::process::exit(e.exit_code());
             }
         }
-        Commands::Reroll { verbose } => {
-            let user_config = user_config::load_user_config();
-            if let Err(e) = init::cmd_reroll(verbose, &user_config) {
+        #[cfg(feature = "reroll")]
+        Commands::Reroll {
+            verbose,
+            no_spread,
+            min_delta,
+        } => {
+            let user_config = config_or_exit(user_config::load_user_config(&ctx));
+            if let Err(e) = init::cmd_reroll(&ctx, verbose, !no_spread, min_delta, &user_config) {
                 eprintln!("{}", e);
-                std::process::exit(1);
+                std::process::exit(e.exit_code());
             }
         }
-        Commands::Colors => {
-            let user_config = user_config::load_user_config();
-            colors::cmd_colors(&user_config);
+        #[cfg(feature = "colors")]
+        Commands::Colors {
+            distribution,
+            simulate,
+            preset,
+        } => {
+            let mut user_config = config_or_exit(user_config::load_user_config(&ctx));
+            if let Some(name) = preset {
+                match user_config::find_preset(&name) {
+                    Some(preset) => user_config.apply_preset(preset),
+                    None => {
+                        let names: Vec<&str> =
+                            user_config::PRESETS.iter().map(|p| p.name).collect();
+                        eprintln!(
+                            "Error: unknown preset '{}' (expected {})",
+                            name,
+                            names.join(", ")
+                        );
+                        std::process::exit(error::EXIT_USAGE);
+                    }
+                }
+            }
+            let simulate = match simulate
+                .as_deref()
+                .map(|name| config::ColorVisionDeficiency::parse(name).ok_or(name))
+            {
+                Some(Ok(deficiency)) => Some(deficiency),
+                Some(Err(name)) => {
+                    eprintln!(
+                        "Error: unknown deficiency '{}' (expected protanopia, deuteranopia, or tritanopia)",
+                        name
+                    );
+                    std::process::exit(error::EXIT_USAGE);
+                }
+                None => None,
+            };
+            if distribution {
+                colors::cmd_colors_distribution(&user_config);
+            } else {
+                colors::cmd_colors(&user_config, simulate);
+            }
         }
-        Commands::Config { edit, path } => {
+        Commands::Config {
+            edit,
+            path,
+            schema,
+            check,
+        } => {
+            if check {
+                if let Err(e) = cmd_config_check(&ctx) {
+                    eprintln!("{}", e);
+                    std::process::exit(e.exit_code());
+                }
+                return;
+            }
+            if schema {
+                let schema = schema::json_schema();
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&schema).unwrap_or_default()
+                );
+                return;
+            }
             if path {
-                println!("{}", user_config::config_file_path().display());
+                println!("{}", user_config::config_file_path(&ctx).display());
                 return;
             }
             if edit {
-                if let Err(e) = cmd_config_edit() {
+                if let Err(e) = cmd_config_edit(&ctx) {
                     eprintln!("{}", e);
-                    std::process::exit(1);
+                    std::process::exit(error::EXIT_FAILURE);
                 }
             } else {
-                let user_config = user_config::load_user_config();
-                cmd_config(&user_config);
+                let user_config = config_or_exit(user_config::load_user_config(&ctx));
+                cmd_config(&ctx, &user_config);
             }
         }
         Commands::Inspect => {
-            cmd_inspect();
+            cmd_inspect(&ctx);
+        }
+        Commands::Ssh { badge, args } => {
+            let user_config = config_or_exit(user_config::load_user_config(&ctx));
+            match ssh::cmd_ssh(&ctx, &args, badge, &user_config) {
+                Ok(code) => std::process::exit(code),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(255);
+                }
+            }
         }
         Commands::Trigger { action } => match action {
-            TriggerAction::Add { pattern } => {
-                if let Err(e) = cmd_trigger_add(&pattern) {
+            TriggerAction::Add {
+                pattern,
+                dirname,
+                kube_context,
+                color,
+                hue,
+                contains,
+                toml_key,
+            } => {
+                let kind = if dirname {
+                    Some(user_config::TriggerKind::Dirname)
+                } else if kube_context {
+                    Some(user_config::TriggerKind::KubeContext)
+                } else {
+                    None
+                };
+                if let Err(e) = cmd_trigger_add(
+                    &ctx,
+                    &pattern,
+                    kind,
+                    color.as_deref(),
+                    hue.as_deref(),
+                    contains.as_deref(),
+                    toml_key.as_deref(),
+                ) {
                     eprintln!("{}", e);
-                    std::process::exit(1);
+                    std::process::exit(error::EXIT_FAILURE);
                 }
             }
             TriggerAction::Remove { pattern } => {
-                if let Err(e) = cmd_trigger_remove(&pattern) {
+                if let Err(e) = cmd_trigger_remove(&ctx, &pattern) {
                     eprintln!("{}", e);
-                    std::process::exit(1);
+                    std::process::exit(error::EXIT_FAILURE);
                 }
             }
             TriggerAction::List => {
-                let user_config = user_config::load_user_config();
+                let user_config = config_or_exit(user_config::load_user_config(&ctx));
                 cmd_trigger_list(&user_config);
             }
         },
+        Commands::State { json } => {
+            sessions::cmd_state(&ctx, json);
+        }
+        Commands::Export {
+            iterm_profile: _,
+            kitty,
+            windows_terminal,
+            alacritty,
+            vscode,
+            print,
+            dry_run,
+            base16,
+            tab_slot,
+            full,
+            install,
+            out,
+        } => {
+            if full && !windows_terminal {
+                eprintln!("Error: --full only applies to --windows-terminal");
+                std::process::exit(error::EXIT_USAGE);
+            }
+            if (print || dry_run) && !vscode {
+                eprintln!("Error: --print and --dry-run only apply to --vscode");
+                std::process::exit(error::EXIT_USAGE);
+            }
+            let tab_slot = match (base16, tab_slot.as_deref()) {
+                (_, None) => export::BASE16_DEFAULT_TAB_SLOT,
+                (true, Some(name)) => match export::parse_base16_slot(name) {
+                    Some(slot) => slot,
+                    None => {
+                        eprintln!("Error: invalid slot '{}' (expected base01 to base0F)", name);
+                        std::process::exit(error::EXIT_USAGE);
+                    }
+                },
+                (false, Some(_)) => {
+                    eprintln!("Error: --tab-slot only applies to --base16");
+                    std::process::exit(error::EXIT_USAGE);
+                }
+            };
+            let format = if kitty {
+                export::Format::Kitty
+            } else if windows_terminal {
+                export::Format::WindowsTerminal { full }
+            } else if alacritty {
+                export::Format::Alacritty
+            } else if vscode {
+                export::Format::VsCode
+            } else if base16 {
+                export::Format::Base16 { tab_slot }
+            } else {
+                export::Format::ItermProfile
+            };
+            let user_config = config_or_exit(user_config::load_user_config(&ctx));
+            let result = if vscode {
+                cmd_export_vscode(&ctx, out.as_deref(), print, dry_run, &user_config)
+            } else {
+                cmd_export(&ctx, format, install, out.as_deref(), &user_config)
+            };
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                std::process::exit(error::EXIT_FAILURE);
+            }
+        }
+        Commands::Starship { config } => {
+            starship::cmd_starship(&ctx, config);
+        }
+        Commands::Sessions { action } => match action {
+            SessionsAction::List { json } => {
+                let user_config = config_or_exit(user_config::load_user_config(&ctx));
+                sessions::cmd_sessions_list(&ctx, json, &user_config);
+            }
+            SessionsAction::Clean {
+                older_than,
+                all,
+                force,
+            } => {
+                let user_config = config_or_exit(user_config::load_user_config(&ctx));
+                if let Err(e) = sessions::cmd_sessions_clean(
+                    &ctx,
+                    older_than.as_deref(),
+                    all,
+                    force,
+                    user_config.session_ttl,
+                ) {
+                    eprintln!("{}", e);
+                    std::process::exit(error::EXIT_FAILURE);
+                }
+            }
+        },
+        Commands::Serve { status, stop } => {
+            if let Err(e) = cmd_serve(&ctx, status, stop) {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+        #[cfg(feature = "man")]
+        Commands::Man { out } => {
+            if let Err(e) = man::cmd_man(Cli::command(), out.as_deref()) {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+        Commands::Cache { action } => match action {
+            CacheAction::Clear => {
+                if let Err(e) = cache::cmd_cache_clear(&ctx) {
+                    eprintln!("{}", e);
+                    std::process::exit(error::EXIT_FAILURE);
+                }
+            }
+        },
     }
 }
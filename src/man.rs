@@ -0,0 +1,101 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use clap::Command;
+use clap_mangen::Man;
+
+use crate::error::TermtintError;
+
+/// Render the man page for `cmd` and, recursively, one for each visible
+/// subcommand (`termtint-trigger-add.1`). Returns each page's file name and roff.
+pub fn pages(cmd: Command) -> io::Result<Vec<(String, Vec<u8>)>> {
+    // Building fills in subcommand display names ("termtint-trigger-add"), which
+    // the page titles and file names come from
+    let mut cmd = cmd.disable_help_subcommand(true);
+    cmd.build();
+    let mut pages = Vec::new();
+    collect_pages(&cmd, &mut pages)?;
+    Ok(pages)
+}
+
+fn collect_pages(cmd: &Command, pages: &mut Vec<(String, Vec<u8>)>) -> io::Result<()> {
+    let man = Man::new(cmd.clone())
+        .source(format!("termtint {}", env!("CARGO_PKG_VERSION")))
+        .manual("termtint Manual");
+    let mut roff = Vec::new();
+    man.render(&mut roff)?;
+    pages.push((man.get_filename(), roff));
+    for subcommand in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        collect_pages(subcommand, pages)?;
+    }
+    Ok(())
+}
+
+/// Write the man pages into `out`, or print the top-level page to stdout.
+///
+/// # Arguments
+/// * `cmd` - The CLI definition the pages are generated from
+/// * `out` - Directory to write `termtint.1` and the subcommand pages into
+pub fn cmd_man(cmd: Command, out: Option<&Path>) -> Result<(), TermtintError> {
+    let pages = pages(cmd).map_err(|e| TermtintError::io("Error rendering man page", &e))?;
+    let Some(dir) = out else {
+        let (_, roff) = &pages[0];
+        return io::stdout()
+            .write_all(roff)
+            .map_err(|e| TermtintError::io("Error writing man page", &e));
+    };
+
+    fs::create_dir_all(dir)
+        .map_err(|e| TermtintError::io(format!("Error creating {}", dir.display()), &e))?;
+    for (name, roff) in &pages {
+        let path = dir.join(name);
+        fs::write(&path, roff)
+            .map_err(|e| TermtintError::io(format!("Error writing {}", path.display()), &e))?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    /// Every visible subcommand's page name, found independently of `pages`.
+    fn page_names(cmd: &Command, prefix: &str, names: &mut Vec<String>) {
+        let name = match prefix {
+            "" => cmd.get_name().to_string(),
+            _ => format!("{}-{}", prefix, cmd.get_name()),
+        };
+        for subcommand in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+            page_names(subcommand, &name, names);
+        }
+        names.push(format!("{}.1", name));
+    }
+
+    #[test]
+    fn test_page_for_every_subcommand() {
+        let cmd = crate::Cli::command();
+        let mut expected = Vec::new();
+        page_names(&cmd, "", &mut expected);
+        expected.retain(|name| name != "termtint-help.1");
+        expected.sort();
+
+        let pages = pages(cmd).unwrap();
+        let mut names: Vec<String> = pages.iter().map(|(name, _)| name.clone()).collect();
+        names.sort();
+        assert_eq!(names, expected);
+        assert!(names.contains(&"termtint-trigger-add.1".to_string()));
+
+        for (name, roff) in &pages {
+            let roff = String::from_utf8(roff.clone()).unwrap();
+            assert!(roff.contains(".TH "), "{}", name);
+            assert!(
+                roff.contains(".SH DESCRIPTION"),
+                "{} has no description",
+                name
+            );
+        }
+    }
+}
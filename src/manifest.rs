@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::Path;
+
+/// Extracts a stable project identifier from a manifest file's contents.
+pub trait PackageNameExtractor {
+    /// Return the package or module name, or None if the manifest doesn't declare one.
+    fn extract(&self, content: &str) -> Option<String>;
+}
+
+/// `package.name` from a Cargo.toml.
+struct CargoToml;
+
+impl PackageNameExtractor for CargoToml {
+    fn extract(&self, content: &str) -> Option<String> {
+        let table: toml::Table = toml::from_str(content).ok()?;
+        let name = table.get("package")?.get("name")?.as_str()?;
+        non_empty(name)
+    }
+}
+
+/// `name` from a package.json.
+struct PackageJson;
+
+impl PackageNameExtractor for PackageJson {
+    fn extract(&self, content: &str) -> Option<String> {
+        let json: serde_json::Value = serde_json::from_str(content).ok()?;
+        non_empty(json.get("name")?.as_str()?)
+    }
+}
+
+/// The module path from a go.mod `module` directive.
+struct GoMod;
+
+impl PackageNameExtractor for GoMod {
+    fn extract(&self, content: &str) -> Option<String> {
+        content.lines().find_map(|line| {
+            let rest = line.trim().strip_prefix("module")?;
+            // Require whitespace after the keyword so e.g. "modules" doesn't match
+            if !rest.starts_with(char::is_whitespace) {
+                return None;
+            }
+            let module = rest.split("//").next()?.trim().trim_matches('"');
+            non_empty(module)
+        })
+    }
+}
+
+fn non_empty(name: &str) -> Option<String> {
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Find the extractor for a manifest file name.
+fn extractor_for(file_name: &str) -> Option<&'static dyn PackageNameExtractor> {
+    match file_name {
+        "Cargo.toml" => Some(&CargoToml),
+        "package.json" => Some(&PackageJson),
+        "go.mod" => Some(&GoMod),
+        _ => None,
+    }
+}
+
+/// Read the package name declared by a manifest file.
+/// Returns None for unsupported file types, unreadable files, or manifests without a name.
+pub fn package_name(path: &Path) -> Option<String> {
+    let extractor = extractor_for(path.file_name()?.to_str()?)?;
+    let content = fs::read_to_string(path).ok()?;
+    extractor.extract(&content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cargo_toml_package_name() {
+        let content = r#"
+[package]
+name = "termtint"
+version = "0.3.0"
+
+[dependencies]
+clap = "4"
+"#;
+        assert_eq!(CargoToml.extract(content), Some("termtint".to_string()));
+    }
+
+    #[test]
+    fn test_cargo_toml_workspace_without_package() {
+        let content = r#"
+[workspace]
+members = ["crates/*"]
+"#;
+        assert_eq!(CargoToml.extract(content), None);
+        assert_eq!(CargoToml.extract("not [valid toml"), None);
+    }
+
+    #[test]
+    fn test_package_json_name() {
+        let content = r#"{
+  "name": "@acme/web",
+  "version": "1.0.0",
+  "dependencies": { "react": "^18.0.0" }
+}"#;
+        assert_eq!(PackageJson.extract(content), Some("@acme/web".to_string()));
+    }
+
+    #[test]
+    fn test_package_json_without_name() {
+        assert_eq!(PackageJson.extract(r#"{ "private": true }"#), None);
+        assert_eq!(PackageJson.extract(r#"{ "name": "" }"#), None);
+        assert_eq!(PackageJson.extract("{ invalid"), None);
+    }
+
+    #[test]
+    fn test_go_mod_module_path() {
+        let content = "// Service module\nmodule github.com/acme/api // main service\n\ngo 1.22\n";
+        assert_eq!(
+            GoMod.extract(content),
+            Some("github.com/acme/api".to_string())
+        );
+        assert_eq!(
+            GoMod.extract("module \"example.com/quoted\"\n"),
+            Some("example.com/quoted".to_string())
+        );
+        assert_eq!(GoMod.extract("go 1.22\n"), None);
+    }
+
+    #[test]
+    fn test_package_name_from_file() {
+        let temp = TempDir::new().unwrap();
+        let cargo = temp.path().join("Cargo.toml");
+        fs::write(&cargo, "[package]\nname = \"demo\"\n").unwrap();
+        let readme = temp.path().join("README.md");
+        fs::write(&readme, "# demo").unwrap();
+
+        assert_eq!(package_name(&cargo), Some("demo".to_string()));
+        assert_eq!(package_name(&readme), None);
+        assert_eq!(package_name(&temp.path().join("package.json")), None);
+    }
+}
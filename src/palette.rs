@@ -0,0 +1,212 @@
+use crate::config::{ColorConfig, RGB};
+
+/// A 16-color ANSI palette: 8 normal colors followed by their 8 bright variants.
+pub type Palette = [RGB; 16];
+
+/// A base16 scheme's colors, base00 through base0F.
+pub type Base16 = [RGB; 16];
+
+/// Lightness scale and chroma scale turning orange into base16's brown.
+const BROWN_LIGHTNESS_SCALE: f32 = 0.7;
+const BROWN_CHROMA_SCALE: f32 = 0.7;
+
+/// Oklch hues (degrees) for the fixed, semantically loaded colors.
+const RED_HUE: f32 = 25.0;
+const GREEN_HUE: f32 = 145.0;
+const YELLOW_HUE: f32 = 95.0;
+
+/// Angular offset (degrees) of cyan and magenta from the tab hue.
+const ANALOGOUS_OFFSET: f32 = 40.0;
+
+/// Lightness added to normal colors to form their bright variants.
+const BRIGHT_LIGHTNESS_STEP: f32 = 0.08;
+
+/// Lightness distance between the background (black) and bright black.
+const BRIGHT_BLACK_STEP: f32 = 0.25;
+
+/// Maximum chroma for bright black, which is commonly used for dimmed text.
+const BRIGHT_BLACK_MAX_CHROMA: f32 = 0.04;
+
+/// Generate a 16-color ANSI palette harmonized around the tab color.
+///
+/// Black is the derived background, blue/cyan/magenta are analogous to the tab hue,
+/// and red/green/yellow stay at fixed hues so they remain recognizable.
+pub fn generate_palette(colors: &ColorConfig) -> Palette {
+    let (_, _, hue) = colors.tab.to_oklch();
+    let (bg_l, bg_c, bg_h) = colors.background.to_oklch();
+
+    // Step bright black away from the background, towards mid-gray
+    let bright_black_l = if bg_l > 0.5 {
+        bg_l - BRIGHT_BLACK_STEP
+    } else {
+        bg_l + BRIGHT_BLACK_STEP
+    };
+
+    // (lightness, chroma, hue) for red, green, yellow, blue, magenta, cyan
+    let accents = [
+        (0.66, 0.16, RED_HUE),
+        (0.72, 0.15, GREEN_HUE),
+        (0.82, 0.14, YELLOW_HUE),
+        (0.68, 0.13, hue),
+        (0.68, 0.13, hue + ANALOGOUS_OFFSET),
+        (0.74, 0.11, hue - ANALOGOUS_OFFSET),
+    ];
+
+    let mut palette = [RGB { r: 0, g: 0, b: 0 }; 16];
+    palette[0] = colors.background;
+    palette[7] = RGB::from_oklch(0.86, 0.02, hue);
+    palette[8] = RGB::from_oklch(bright_black_l, bg_c.min(BRIGHT_BLACK_MAX_CHROMA), bg_h);
+    palette[15] = RGB::from_oklch(0.97, 0.01, hue);
+    for (i, (l, c, h)) in accents.into_iter().enumerate() {
+        palette[i + 1] = RGB::from_oklch(l, c, h);
+        palette[i + 9] = RGB::from_oklch(l + BRIGHT_LIGHTNESS_STEP, c, h);
+    }
+
+    palette
+}
+
+/// Generate a base16 scheme from the ANSI palette: base00-base07 ramp from the
+/// background through bright black and white to bright white, and the accents
+/// (base08-base0F) are the palette's, with orange and brown blended from red and
+/// yellow. Every slot is derived; callers place the tab color where they want it.
+pub fn generate_base16(colors: &ColorConfig) -> Base16 {
+    let palette = generate_palette(colors);
+    let orange = palette[1].blend_oklch(&palette[3], 0.5);
+    let (orange_l, orange_c, orange_h) = orange.to_oklch();
+    [
+        palette[0],
+        palette[0].blend_oklch(&palette[8], 1.0 / 3.0),
+        palette[0].blend_oklch(&palette[8], 2.0 / 3.0),
+        palette[8],
+        palette[8].blend_oklch(&palette[7], 0.5),
+        palette[7],
+        palette[7].blend_oklch(&palette[15], 0.5),
+        palette[15],
+        palette[1],
+        orange,
+        palette[3],
+        palette[2],
+        palette[6],
+        palette[4],
+        palette[5],
+        RGB::from_oklch(
+            orange_l * BROWN_LIGHTNESS_SCALE,
+            orange_c * BROWN_CHROMA_SCALE,
+            orange_h,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::derive_background;
+    use crate::user_config::UserConfig;
+
+    fn palette_for(tab: RGB) -> Palette {
+        let background = derive_background(&tab, &UserConfig::default());
+        generate_palette(&ColorConfig { tab, background })
+    }
+
+    #[test]
+    fn test_generate_palette_snapshot() {
+        let palette = palette_for(RGB {
+            r: 0x34,
+            g: 0x78,
+            b: 0xf6,
+        });
+        let hex: Vec<String> = palette.iter().map(|c| c.to_string()).collect();
+
+        assert_eq!(
+            hex,
+            [
+                "#090069", "#e3645e", "#61bd67", "#e1c34b", "#6a97e8", "#a784db", "#4cbadc",
+                "#cad1df", "#576078", "#ff7e76", "#7bd77f", "#fbdd67", "#82b1ff", "#c09df6",
+                "#68d4f7", "#f1f5fc",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generate_palette_black_is_background() {
+        let tab = RGB {
+            r: 0xe0,
+            g: 0x40,
+            b: 0x80,
+        };
+        let background = derive_background(&tab, &UserConfig::default());
+        let palette = palette_for(tab);
+
+        assert_eq!(palette[0], background);
+        assert!(palette[8].to_oklch().0 > palette[0].to_oklch().0);
+    }
+
+    #[test]
+    fn test_generate_palette_follows_tab_hue() {
+        let blue = palette_for(RGB {
+            r: 0x34,
+            g: 0x78,
+            b: 0xf6,
+        });
+        let green = palette_for(RGB {
+            r: 0x30,
+            g: 0xc0,
+            b: 0x50,
+        });
+
+        // Harmonized slots move with the tab color, fixed ones do not
+        assert_ne!(blue[4], green[4]);
+        assert_ne!(blue[6], green[6]);
+        assert_eq!(blue[1], green[1]);
+        assert_eq!(blue[2], green[2]);
+        assert_eq!(blue[3], green[3]);
+    }
+
+    #[test]
+    fn test_generate_palette_bright_variants_are_lighter() {
+        let palette = palette_for(RGB {
+            r: 0x34,
+            g: 0x78,
+            b: 0xf6,
+        });
+
+        for i in 1..7 {
+            assert!(
+                palette[i + 8].to_oklch().0 > palette[i].to_oklch().0,
+                "Bright color {} should be lighter than normal color {}",
+                i + 8,
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_base16_ramps_from_background() {
+        let tab = RGB {
+            r: 0x34,
+            g: 0x78,
+            b: 0xf6,
+        };
+        let background = derive_background(&tab, &UserConfig::default());
+        let colors = ColorConfig { tab, background };
+        let palette = generate_palette(&colors);
+        let base16 = generate_base16(&colors);
+
+        assert_eq!(base16[0x0], background);
+        assert_eq!(base16[0x3], palette[8]);
+        assert_eq!(base16[0x8], palette[1]);
+        assert_eq!(base16[0xD], palette[4]);
+        for i in 0..7 {
+            assert!(
+                base16[i + 1].to_oklch().0 > base16[i].to_oklch().0,
+                "base0{:X} should be lighter than base0{:X}",
+                i + 1,
+                i
+            );
+        }
+        let (orange_l, _, orange_h) = base16[0x9].to_oklch();
+        let (brown_l, _, _) = base16[0xF].to_oklch();
+        assert!(orange_h > 25.0 && orange_h < 95.0);
+        assert!(brown_l < orange_l);
+    }
+}
@@ -0,0 +1,1029 @@
+use std::fmt;
+
+use serde_json::{json, Map, Value};
+
+/// The values a config key accepts.
+pub enum FieldType {
+    Bool,
+    /// A number in a range
+    Number {
+        min: f64,
+        max: f64,
+    },
+    /// A non-negative integer
+    Count,
+    /// One of the listed strings
+    Choice(&'static [&'static str]),
+    /// `true`, `false`, or one of the listed strings
+    Switch(&'static [&'static str]),
+    /// A duration such as "24h", or one of the listed words
+    Duration(&'static [&'static str]),
+    /// A local time of day, "HH:MM"
+    Time,
+    /// A color in any syntax `.termtint` accepts
+    Color,
+    /// A path, glob, or other free-form string
+    Text,
+    /// A list of values of one type
+    List(&'static FieldType),
+    /// A `[min, max]` pair of numbers in a range
+    Band {
+        min: f64,
+        max: f64,
+    },
+    /// A table with the given keys
+    Table {
+        fields: &'static [ConfigField],
+        required: &'static [&'static str],
+    },
+    /// A table mapping free-form keys (host globs, environment rules) to colors
+    ColorMap,
+    /// An array of tables with the given keys
+    Tables {
+        fields: &'static [ConfigField],
+        required: &'static [&'static str],
+    },
+}
+
+/// A user config key: its type, default, and documentation.
+///
+/// `CONFIG_FIELDS` is the one description of the config file that the comments
+/// `termtint config --edit` adds for missing keys, the `termtint config` listing,
+/// and `termtint config --schema` are all generated from.
+pub struct ConfigField {
+    pub name: &'static str,
+    pub ty: FieldType,
+    /// The default as a TOML value, for keys that have one
+    pub default: Option<&'static str>,
+    /// What the key does, one comment line per line
+    pub doc: &'static str,
+}
+
+impl ConfigField {
+    /// Whether the key holds a single value rather than a table of keys.
+    pub fn is_value(&self) -> bool {
+        !matches!(
+            self.ty,
+            FieldType::Table { .. } | FieldType::ColorMap | FieldType::Tables { .. }
+        )
+    }
+
+    /// The keys of a table field (`[auto]`, `[dimming]`, `[[triggers]]`).
+    pub fn fields(&self) -> &'static [ConfigField] {
+        match self.ty {
+            FieldType::Table { fields, .. } | FieldType::Tables { fields, .. } => fields,
+            _ => &[],
+        }
+    }
+
+    /// The value written into config files: the default, or for keys without one
+    /// the first of their choices.
+    fn example(&self) -> Option<String> {
+        match (&self.default, &self.ty) {
+            (Some(default), _) => Some(default.to_string()),
+            (None, FieldType::Choice(choices)) => Some(format!("\"{}\"", choices[0])),
+            _ => None,
+        }
+    }
+
+    /// The commented-out block added for the key when it's missing from a config
+    /// file: its documentation followed by `# name = default`.
+    pub fn template(&self) -> String {
+        let mut lines: Vec<String> = self
+            .doc
+            .lines()
+            .map(|line| format!("# {}", line).trim_end().to_string())
+            .collect();
+        if let Some(example) = self.example() {
+            lines.push(format!("# {} = {}", self.name, example));
+        }
+        lines.join("\n")
+    }
+}
+
+/// The top-level keys of the user config, in the order they're documented.
+pub const CONFIG_FIELDS: &[ConfigField] = &[
+    ConfigField {
+        name: "background_lightness",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.18"),
+        doc: "Fixed lightness for darkened backgrounds (0.0 to 1.0)",
+    },
+    ConfigField {
+        name: "background_saturation",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("1.00"),
+        doc: "Saturation multiplier for backgrounds (0.0 to 1.0)\n1.0 = preserve original saturation, 0.0 = grayscale",
+    },
+    ConfigField {
+        name: "background_mode",
+        ty: FieldType::Choice(&["darken", "lighten"]),
+        default: Some("\"darken\""),
+        doc: "Background derivation: \"darken\" for dark themes, \"lighten\" for light themes",
+    },
+    ConfigField {
+        name: "background_lightness_light",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.93"),
+        doc: "Fixed lightness for lightened backgrounds when background_mode = \"lighten\"",
+    },
+    ConfigField {
+        name: "temperature_shift",
+        ty: FieldType::Number {
+            min: -1.0,
+            max: 1.0,
+        },
+        default: Some("0.0"),
+        doc: "Shift all colors along the blue–orange axis: -1.0 (cooler) to 1.0 (warmer)",
+    },
+    ConfigField {
+        name: "force_256",
+        ty: FieldType::Bool,
+        default: Some("false"),
+        doc: "Approximate colors with the xterm 256-color palette (for terminals without truecolor)",
+    },
+    ConfigField {
+        name: "generate_palette",
+        ty: FieldType::Bool,
+        default: Some("false"),
+        doc: "Also set the 16 ANSI colors to a palette harmonized with the tab color",
+    },
+    ConfigField {
+        name: "monochrome",
+        ty: FieldType::Bool,
+        default: Some("false"),
+        doc: "Generate neutral gray tab colors, distinguished only by lightness",
+    },
+    ConfigField {
+        name: "monochrome_lightness_min",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.35"),
+        doc: "Lightness range for monochrome tab colors (0.0 to 1.0)",
+    },
+    ConfigField {
+        name: "monochrome_lightness_max",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.80"),
+        doc: "Upper end of the monochrome lightness range",
+    },
+    ConfigField {
+        name: "reserved_colors",
+        ty: FieldType::List(&FieldType::Color),
+        default: Some("[]"),
+        doc: "Colors that auto-generated colors keep away from, e.g. [\"#ff0000\"]",
+    },
+    ConfigField {
+        name: "reserved_min_delta",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.15"),
+        doc: "Minimum perceptual difference (Oklab ΔE) from reserved colors",
+    },
+    ConfigField {
+        name: "trigger_files",
+        ty: FieldType::List(&FieldType::Text),
+        default: Some("[]"),
+        doc: "Files that trigger automatic color generation when found\nExamples: [\"Cargo.toml\", \"package.json\", \"go.mod\", \"pyproject.toml\"]\nGlobs such as \"*.cabal\" match any file in the directory",
+    },
+    ConfigField {
+        name: "trigger_files_case_sensitive",
+        ty: FieldType::Bool,
+        default: Some("true"),
+        doc: "Whether glob entries in trigger_files match case-sensitively",
+    },
+    ConfigField {
+        name: "trigger_paths",
+        ty: FieldType::List(&FieldType::Text),
+        default: Some("[]"),
+        doc: "Path globs that trigger automatic color generation\nDirectories matching these patterns are treated as having 'auto' in .termtint\nSupports ~ for home directory. Example: [\"~/Code/*\", \"~/Projects/*\"]",
+    },
+    ConfigField {
+        name: "trigger_dirnames",
+        ty: FieldType::List(&FieldType::Text),
+        default: Some("[]"),
+        doc: "Directory names that trigger automatic color generation wherever they are\nMatches the name only, exactly or as a glob. Example: [\"prod\", \"release-*\"]",
+    },
+    ConfigField {
+        name: "exclude_paths",
+        ty: FieldType::List(&FieldType::Text),
+        default: Some("[]"),
+        doc: "Path globs where trigger paths and trigger files are ignored (.termtint still applies)\nExample: [\"~/Code/scratch\", \"~/Code/archive/**\"]",
+    },
+    ConfigField {
+        name: "trigger_priority",
+        ty: FieldType::List(&FieldType::Choice(&["termtint", "paths", "files", "dirnames"])),
+        default: Some("[\"termtint\", \"paths\", \"files\", \"dirnames\"]"),
+        doc: "Order sources are checked in at each directory, nearest directory first:\n\"termtint\" (.termtint files), \"paths\" (trigger_paths), \"files\" (trigger_files),\n\"dirnames\" (trigger_dirnames)",
+    },
+    ConfigField {
+        name: "walk_boundary",
+        ty: FieldType::Choice(&["root", "home", "device"]),
+        default: Some("\"root\""),
+        doc: "Where the search for .termtint and trigger files stops: \"root\" (the filesystem\nroot), \"home\" ($HOME, for directories inside it), or \"device\" (the filesystem\nthe search started on)",
+    },
+    ConfigField {
+        name: "max_walk_depth",
+        ty: FieldType::Count,
+        default: Some("0"),
+        doc: "How many parent directories the search may climb (0 = unlimited)",
+    },
+    ConfigField {
+        name: "respect_git_root",
+        ty: FieldType::Bool,
+        default: Some("false"),
+        doc: "Treat the enclosing git repository as the project: the search stops at the\nrepository root, and trigger files anywhere inside it color the whole repository",
+    },
+    ConfigField {
+        name: "auto_from_vscode",
+        ty: FieldType::Bool,
+        default: Some("false"),
+        doc: "Where a directory has no .termtint, use the Peacock color (\"peacock.color\") from\nits .vscode/settings.json as the tab color",
+    },
+    ConfigField {
+        name: "env_trigger_priority",
+        ty: FieldType::Choice(&["before", "after"]),
+        default: Some("\"before\""),
+        doc: "Whether [env_triggers] rules are checked \"before\" directory sources (so they\noverride .termtint files and triggers) or \"after\" (only where none is found)",
+    },
+    ConfigField {
+        name: "color_format",
+        ty: FieldType::Choice(&["hex", "hsl", "rgb"]),
+        default: Some("\"hex\""),
+        doc: "Color format for display: \"hex\", \"hsl\", or \"rgb\"",
+    },
+    ConfigField {
+        name: "hyperlinks",
+        ty: FieldType::Switch(&["auto"]),
+        default: Some("\"auto\""),
+        doc: "Show paths in inspect and sessions list as clickable links: true, false, or\n\"auto\" (only in terminals known to support OSC 8 hyperlinks)",
+    },
+    ConfigField {
+        name: "session_ttl",
+        ty: FieldType::Duration(&["never"]),
+        default: Some("\"24h\""),
+        doc: "How long an idle terminal session's state is kept, e.g. \"24h\" or \"7d\"\n(\"never\" disables automatic cleanup)",
+    },
+    ConfigField {
+        name: "cleanup_interval",
+        ty: FieldType::Duration(&[]),
+        default: Some("\"1h\""),
+        doc: "How often apply checks for stale sessions (\"0s\" checks on every apply)",
+    },
+    ConfigField {
+        name: "color_cache",
+        ty: FieldType::Bool,
+        default: Some("false"),
+        doc: "Cache resolved auto colors per trigger directory (clear with `termtint cache clear`)",
+    },
+    ConfigField {
+        name: "strict",
+        ty: FieldType::Bool,
+        default: Some("false"),
+        doc: "Treat problems in this file, such as invalid glob patterns, as errors\ninstead of warnings",
+    },
+    ConfigField {
+        name: "state_dir",
+        ty: FieldType::Text,
+        default: Some("\"~/.cache/termtint\""),
+        doc: "Directory for termtint's state and caches (default: ~/.cache/termtint)\nThe TERMTINT_STATE_DIR environment variable takes precedence",
+    },
+    ConfigField {
+        name: "auto",
+        ty: FieldType::Table {
+            fields: AUTO_FIELDS,
+            required: &[],
+        },
+        default: None,
+        doc: "Auto color generation parameters",
+    },
+    ConfigField {
+        name: "dimming",
+        ty: FieldType::Table {
+            fields: DIMMING_FIELDS,
+            required: &["start", "end"],
+        },
+        default: None,
+        doc: "Dim applied colors during a time-of-day window, which may wrap midnight",
+    },
+    ConfigField {
+        name: "hosts",
+        ty: FieldType::ColorMap,
+        default: None,
+        doc: "Colors for 'termtint ssh' by host glob; other hosts get a hash-based color\nAn exact match wins, then the longest glob",
+    },
+    ConfigField {
+        name: "env_triggers",
+        ty: FieldType::ColorMap,
+        default: None,
+        doc: "Colors by environment variable, as \"VAR=value-glob\" = color or \"auto\"\n(\"VAR\" alone matches any non-empty value; rules are checked in key order)",
+    },
+    ConfigField {
+        name: "triggers",
+        ty: FieldType::Tables {
+            fields: TRIGGER_FIELDS,
+            required: &["match"],
+        },
+        default: None,
+        doc: "Triggers with options of their own, checked before the trigger lists",
+    },
+];
+
+/// The keys of the `[auto]` table.
+pub const AUTO_FIELDS: &[ConfigField] = &[
+    ConfigField {
+        name: "preset",
+        ty: FieldType::Choice(&["vivid", "pastel", "muted", "neon"]),
+        default: None,
+        doc: "Start from a preset: \"vivid\", \"pastel\", \"muted\", or \"neon\"\n(explicit keys below override the preset's values)",
+    },
+    ConfigField {
+        name: "hue_min",
+        ty: FieldType::Number {
+            min: 0.0,
+            max: 360.0,
+        },
+        default: Some("0.0"),
+        doc: "Hue range in degrees (0.0 to 360.0); hue_min > hue_max wraps through 360",
+    },
+    ConfigField {
+        name: "hue_max",
+        ty: FieldType::Number {
+            min: 0.0,
+            max: 360.0,
+        },
+        default: Some("360.0"),
+        doc: "Upper end of the hue range",
+    },
+    ConfigField {
+        name: "saturation_min",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.7"),
+        doc: "Saturation range (0.0 to 1.0)",
+    },
+    ConfigField {
+        name: "saturation_max",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.9"),
+        doc: "Upper end of the saturation range",
+    },
+    ConfigField {
+        name: "lightness",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.55"),
+        doc: "Lightness for generated tab colors (0.0 to 1.0)",
+    },
+    ConfigField {
+        name: "hue_spread",
+        ty: FieldType::Number {
+            min: 0.0,
+            max: 180.0,
+        },
+        default: Some("30.0"),
+        doc: "Minimum hue distance in degrees between consecutive random colors (0 disables)",
+    },
+    ConfigField {
+        name: "reroll_min_delta",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.12"),
+        doc: "Minimum perceptual difference (Oklab ΔE) between rerolled colors (0 disables)",
+    },
+    ConfigField {
+        name: "depth_ramp",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.00"),
+        doc: "Darken the background by this much lightness per directory level below\nthe config source (0 disables, e.g. 0.02)",
+    },
+    ConfigField {
+        name: "blend_with_parent",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.0"),
+        doc: "Blend trigger-file/path colors with the nearest ancestor .termtint color\n0.0 = disabled, 0.6 = 60% parent color, 1.0 = parent color only",
+    },
+    ConfigField {
+        name: "hash_include_hostname",
+        ty: FieldType::Bool,
+        default: Some("false"),
+        doc: "Mix the hostname into auto colors so the same project differs per machine\n(intentionally breaks cross-machine color stability)",
+    },
+    ConfigField {
+        name: "auto_hash_source",
+        ty: FieldType::Choice(&["path", "package-name"]),
+        default: Some("\"path\""),
+        doc: "What trigger-file colors are derived from: \"path\" or \"package-name\"\n(\"package-name\" reads Cargo.toml, package.json, or go.mod and falls back to the path)",
+    },
+];
+
+/// The keys of the `[dimming]` table.
+const DIMMING_FIELDS: &[ConfigField] = &[
+    ConfigField {
+        name: "start",
+        ty: FieldType::Time,
+        default: None,
+        doc: "Local time the window starts, e.g. \"21:00\"",
+    },
+    ConfigField {
+        name: "end",
+        ty: FieldType::Time,
+        default: None,
+        doc: "Local time the window ends, e.g. \"07:00\"",
+    },
+    ConfigField {
+        name: "lightness_scale",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: Some("0.7"),
+        doc: "Multiplier for the background lightness inside the window",
+    },
+    ConfigField {
+        name: "dim_tab",
+        ty: FieldType::Bool,
+        default: Some("false"),
+        doc: "Dim the tab color as well",
+    },
+];
+
+/// The keys of a `[[triggers]]` entry.
+const TRIGGER_FIELDS: &[ConfigField] = &[
+    ConfigField {
+        name: "match",
+        ty: FieldType::Text,
+        default: None,
+        doc: "The file name, path glob, directory name, or kubectl context to match",
+    },
+    ConfigField {
+        name: "kind",
+        ty: FieldType::Choice(&["file", "path", "dirname", "kube-context"]),
+        default: None,
+        doc:
+            "What the pattern matches; defaults to path for patterns with a / or ~,\nfile otherwise",
+    },
+    ConfigField {
+        name: "color",
+        ty: FieldType::Color,
+        default: Some("\"auto\""),
+        doc: "The color for matching directories, or \"auto\" for a hash-based color",
+    },
+    ConfigField {
+        name: "contains",
+        ty: FieldType::Text,
+        default: None,
+        doc: "For file triggers, only files containing this text count",
+    },
+    ConfigField {
+        name: "toml_key",
+        ty: FieldType::Text,
+        default: None,
+        doc: "For file triggers, only TOML files defining this dotted key count",
+    },
+    ConfigField {
+        name: "hue",
+        ty: FieldType::Band {
+            min: 0.0,
+            max: 360.0,
+        },
+        default: None,
+        doc: "Hue band in degrees for this trigger's auto colors, as [min, max]",
+    },
+    ConfigField {
+        name: "hue_min",
+        ty: FieldType::Number {
+            min: 0.0,
+            max: 360.0,
+        },
+        default: None,
+        doc: "Lower end of the hue band, when hue isn't given",
+    },
+    ConfigField {
+        name: "hue_max",
+        ty: FieldType::Number {
+            min: 0.0,
+            max: 360.0,
+        },
+        default: None,
+        doc: "Upper end of the hue band, when hue isn't given",
+    },
+    ConfigField {
+        name: "background_lightness",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: None,
+        doc: "Background lightness for this trigger's colors",
+    },
+    ConfigField {
+        name: "background_saturation",
+        ty: FieldType::Number { min: 0.0, max: 1.0 },
+        default: None,
+        doc: "Background saturation multiplier for this trigger's colors",
+    },
+];
+
+/// Convert a TOML value literal to JSON.
+fn toml_to_json(literal: &str) -> Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", literal))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .and_then(|value| serde_json::to_value(value).ok())
+        .unwrap_or(Value::Null)
+}
+
+/// The JSON Schema for a value of type `ty`.
+fn type_schema(ty: &FieldType) -> Value {
+    match ty {
+        FieldType::Bool => json!({ "type": "boolean" }),
+        FieldType::Number { min, max } => {
+            json!({ "type": "number", "minimum": min, "maximum": max })
+        }
+        FieldType::Count => json!({ "type": "integer", "minimum": 0 }),
+        FieldType::Choice(choices) => json!({ "type": "string", "enum": choices }),
+        FieldType::Switch(choices) => {
+            let mut values = vec![json!(true), json!(false)];
+            values.extend(choices.iter().map(|choice| json!(choice)));
+            json!({ "enum": values })
+        }
+        FieldType::Duration(words) => {
+            let mut alternatives = vec![r"\d+\s*[smhdw]".to_string()];
+            alternatives.extend(words.iter().map(|word| word.to_string()));
+            json!({
+                "type": "string",
+                "pattern": format!(r"^\s*({})\s*$", alternatives.join("|")),
+            })
+        }
+        FieldType::Time => json!({ "type": "string", "pattern": r"^\d{1,2}:\d{2}$" }),
+        FieldType::Color | FieldType::Text => json!({ "type": "string" }),
+        FieldType::List(item) => json!({ "type": "array", "items": type_schema(item) }),
+        FieldType::Band { min, max } => json!({
+            "type": "array",
+            "items": { "type": "number", "minimum": min, "maximum": max },
+            "minItems": 2,
+            "maxItems": 2,
+        }),
+        FieldType::Table { fields, required } => table_schema(fields, required),
+        FieldType::ColorMap => json!({
+            "type": "object",
+            "additionalProperties": { "type": "string" },
+        }),
+        FieldType::Tables { fields, required } => {
+            json!({ "type": "array", "items": table_schema(fields, required) })
+        }
+    }
+}
+
+/// The JSON Schema for a table with the given keys.
+fn table_schema(fields: &[ConfigField], required: &[&str]) -> Value {
+    let mut properties = Map::new();
+    for field in fields {
+        let mut schema = type_schema(&field.ty);
+        if let Value::Object(schema) = &mut schema {
+            schema.insert(
+                "description".to_string(),
+                json!(field.doc.replace('\n', " ")),
+            );
+            if let Some(default) = field.default {
+                schema.insert("default".to_string(), toml_to_json(default));
+            }
+        }
+        properties.insert(field.name.to_string(), schema);
+    }
+    let mut schema = json!({
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    schema
+}
+
+/// A JSON Schema (draft 2020-12) describing the user config file, for editors
+/// to complete and validate `config.toml` with.
+pub fn json_schema() -> Value {
+    let mut schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "termtint user configuration",
+        "description": "~/.config/termtint/config.toml",
+    });
+    if let (Value::Object(schema), Value::Object(table)) =
+        (&mut schema, table_schema(CONFIG_FIELDS, &[]))
+    {
+        schema.extend(table);
+    }
+    schema
+}
+
+/// A key in a config file that isn't in the schema: a typo, or a key from a newer
+/// termtint. Loading ignores these.
+#[derive(Debug, PartialEq)]
+pub struct UnknownKey {
+    /// The key with the tables it's in, e.g. `auto.hue_mn` or `triggers[1].colour`
+    pub path: String,
+    /// A known key of the same table a couple of typos away
+    pub suggestion: Option<&'static str>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, " (did you mean {}?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// The keys of a parsed config file that the schema doesn't describe, sorted
+/// within each table. Free-form tables (`[hosts]`, `[env_triggers]`) can't have unknown keys.
+pub fn unknown_keys(config: &toml::Table) -> Vec<UnknownKey> {
+    let mut unknown = Vec::new();
+    collect_unknown_keys(config, CONFIG_FIELDS, "", &mut unknown);
+    unknown
+}
+
+fn collect_unknown_keys(
+    table: &toml::Table,
+    fields: &'static [ConfigField],
+    prefix: &str,
+    unknown: &mut Vec<UnknownKey>,
+) {
+    for (key, value) in table {
+        let path = format!("{}{}", prefix, key);
+        let Some(field) = fields.iter().find(|field| field.name == key) else {
+            unknown.push(UnknownKey {
+                suggestion: closest_key(key, fields),
+                path,
+            });
+            continue;
+        };
+        match (&field.ty, value) {
+            (FieldType::Table { fields, .. }, toml::Value::Table(table)) => {
+                collect_unknown_keys(table, fields, &format!("{}.", path), unknown);
+            }
+            (FieldType::Tables { fields, .. }, toml::Value::Array(entries)) => {
+                for (i, entry) in entries.iter().enumerate() {
+                    if let toml::Value::Table(entry) = entry {
+                        collect_unknown_keys(entry, fields, &format!("{}[{}].", path, i), unknown);
+                    }
+                }
+            }
+            // Older configs' `[[triggers.file]]` entries, which loading still reads
+            (FieldType::Tables { .. }, toml::Value::Table(legacy)) => {
+                for (key, _) in legacy.iter().filter(|(key, _)| *key != "file") {
+                    unknown.push(UnknownKey {
+                        path: format!("{}.{}", path, key),
+                        suggestion: None,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A number a config file sets outside the range its key accepts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutOfRange {
+    /// The key with the tables it's in, e.g. `auto.lightness` or `triggers[0].hue[1]`
+    pub path: String,
+    pub value: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl OutOfRange {
+    /// What's wrong, without the fix: `auto.lightness = 5.5 is outside 0 to 1`.
+    pub fn problem(&self) -> String {
+        format!(
+            "{} = {} is outside {} to {}",
+            self.path, self.value, self.min, self.max
+        )
+    }
+
+    /// The nearest value in range (the minimum for NaN).
+    pub fn clamped(&self) -> f64 {
+        if self.value.is_nan() {
+            self.min
+        } else {
+            self.value.clamp(self.min, self.max)
+        }
+    }
+}
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, using {}", self.problem(), self.clamped())
+    }
+}
+
+/// Clamp the numbers in a parsed config file that are outside their keys'
+/// ranges, returning what was out of range. Values of the wrong type are left
+/// for deserializing to reject.
+pub fn clamp_out_of_range(config: &mut toml::Table) -> Vec<OutOfRange> {
+    let mut clamped = Vec::new();
+    clamp_table(config, CONFIG_FIELDS, "", &mut clamped);
+    clamped
+}
+
+fn clamp_table(
+    table: &mut toml::Table,
+    fields: &'static [ConfigField],
+    prefix: &str,
+    clamped: &mut Vec<OutOfRange>,
+) {
+    for (key, value) in table.iter_mut() {
+        let Some(field) = fields.iter().find(|field| field.name == key) else {
+            continue;
+        };
+        let path = format!("{}{}", prefix, key);
+        match (&field.ty, value) {
+            (&FieldType::Number { min, max }, value) => {
+                clamp_number(value, path, min, max, clamped);
+            }
+            (&FieldType::Band { min, max }, toml::Value::Array(values)) => {
+                for (i, value) in values.iter_mut().enumerate() {
+                    clamp_number(value, format!("{}[{}]", path, i), min, max, clamped);
+                }
+            }
+            (FieldType::Table { fields, .. }, toml::Value::Table(table)) => {
+                clamp_table(table, fields, &format!("{}.", path), clamped);
+            }
+            (FieldType::Tables { fields, .. }, toml::Value::Array(entries)) => {
+                for (i, entry) in entries.iter_mut().enumerate() {
+                    if let toml::Value::Table(entry) = entry {
+                        clamp_table(entry, fields, &format!("{}[{}].", path, i), clamped);
+                    }
+                }
+            }
+            // Older configs' `[[triggers.file]]` entries, whose hue bounds are a
+            // trigger's
+            (FieldType::Tables { fields, .. }, toml::Value::Table(legacy)) => {
+                if let Some(toml::Value::Array(entries)) = legacy.get_mut("file") {
+                    for (i, entry) in entries.iter_mut().enumerate() {
+                        if let toml::Value::Table(entry) = entry {
+                            let prefix = format!("{}.file[{}].", path, i);
+                            clamp_table(entry, fields, &prefix, clamped);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Clamp `value` into `min..=max` if it's a number outside it.
+fn clamp_number(
+    value: &mut toml::Value,
+    path: String,
+    min: f64,
+    max: f64,
+    clamped: &mut Vec<OutOfRange>,
+) {
+    let number = match value {
+        toml::Value::Float(number) => *number,
+        toml::Value::Integer(number) => *number as f64,
+        _ => return,
+    };
+    if (min..=max).contains(&number) {
+        return;
+    }
+    let out_of_range = OutOfRange {
+        path,
+        value: number,
+        min,
+        max,
+    };
+    *value = toml::Value::Float(out_of_range.clamped());
+    clamped.push(out_of_range);
+}
+
+/// The known key closest to `key` by edit distance, if it's close enough to be a
+/// typo of it.
+fn closest_key(key: &str, fields: &'static [ConfigField]) -> Option<&'static str> {
+    fields
+        .iter()
+        .map(|field| (edit_distance(key, field.name), field.name))
+        .filter(|&(distance, _)| distance <= 2 && distance < key.chars().count())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, name)| name)
+}
+
+/// The Levenshtein distance between two strings: how many single-character
+/// insertions, deletions, and substitutions turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("hue_min", "hue_min"), 0);
+        assert_eq!(
+            edit_distance("backround_lightness", "background_lightness"),
+            1
+        );
+        assert_eq!(edit_distance("colour", "color"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_unknown_keys_typos() {
+        let config: toml::Table = toml::from_str(
+            r##"
+backround_lightness = 0.2
+trigger_paths = ["~/Code/*"]
+
+[auto]
+hue_mn = 10.0
+lightness = 0.5
+
+[[triggers]]
+match = "Cargo.toml"
+colour = "#cc0000"
+
+[hosts]
+"prod-*" = "#cc0000"
+"##,
+        )
+        .unwrap();
+
+        let unknown = unknown_keys(&config);
+        let described: Vec<String> = unknown.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            described,
+            [
+                "auto.hue_mn (did you mean hue_min?)",
+                "backround_lightness (did you mean background_lightness?)",
+                "triggers[0].colour (did you mean color?)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys_from_newer_versions() {
+        let config: toml::Table = toml::from_str(
+            r##"
+color_format = "hex"
+notify_on_change = true
+
+[themes.dark]
+tab = "#336699"
+
+[triggers]
+[[triggers.file]]
+name = "Cargo.toml"
+"##,
+        )
+        .unwrap();
+
+        assert_eq!(
+            unknown_keys(&config),
+            [
+                UnknownKey {
+                    path: "notify_on_change".to_string(),
+                    suggestion: None,
+                },
+                UnknownKey {
+                    path: "themes".to_string(),
+                    suggestion: None,
+                },
+            ]
+        );
+        let known: toml::Table =
+            toml::from_str(&crate::user_config::default_config_toml()).unwrap();
+        assert_eq!(unknown_keys(&known), []);
+    }
+
+    #[test]
+    fn test_clamp_out_of_range() {
+        let mut config: toml::Table = toml::from_str(
+            r#"
+background_lightness = 1.8
+temperature_shift = -3
+reserved_min_delta = 0.15
+max_walk_depth = 400
+color_format = "hex"
+
+[auto]
+lightness = 5.5
+hue_max = 400.0
+saturation_min = "high"
+
+[[triggers]]
+match = "Cargo.toml"
+hue = [-10.0, 45.0]
+background_saturation = nan
+"#,
+        )
+        .unwrap();
+        let clamped: Vec<String> = clamp_out_of_range(&mut config)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            clamped,
+            [
+                "auto.hue_max = 400 is outside 0 to 360, using 360",
+                "auto.lightness = 5.5 is outside 0 to 1, using 1",
+                "background_lightness = 1.8 is outside 0 to 1, using 1",
+                "temperature_shift = -3 is outside -1 to 1, using -1",
+                "triggers[0].background_saturation = NaN is outside 0 to 1, using 0",
+                "triggers[0].hue[0] = -10 is outside 0 to 360, using 0",
+            ]
+        );
+        assert_eq!(config["auto"]["lightness"], toml::Value::Float(1.0));
+        assert_eq!(config["temperature_shift"], toml::Value::Float(-1.0));
+        assert_eq!(
+            config["triggers"][0]["hue"],
+            toml::Value::Array(vec![toml::Value::Float(0.0), toml::Value::Float(45.0)])
+        );
+        // In-range numbers, counts, and values of the wrong type are left alone
+        assert_eq!(config["reserved_min_delta"], toml::Value::Float(0.15));
+        assert_eq!(config["max_walk_depth"], toml::Value::Integer(400));
+        assert_eq!(config["auto"]["saturation_min"].as_str(), Some("high"));
+    }
+
+    #[test]
+    fn test_clamp_out_of_range_legacy_triggers() {
+        let mut config: toml::Table = toml::from_str(
+            r#"
+[[triggers.file]]
+name = "go.mod"
+hue_min = 180.0
+hue_max = 720.0
+"#,
+        )
+        .unwrap();
+        let clamped = clamp_out_of_range(&mut config);
+        assert_eq!(clamped.len(), 1);
+        assert_eq!(clamped[0].path, "triggers.file[0].hue_max");
+        assert_eq!(clamped[0].clamped(), 360.0);
+    }
+
+    #[test]
+    fn test_template() {
+        let field = &CONFIG_FIELDS[1];
+        assert_eq!(
+            field.template(),
+            "# Saturation multiplier for backgrounds (0.0 to 1.0)\n# 1.0 = preserve original saturation, 0.0 = grayscale\n# background_saturation = 1.00"
+        );
+        assert_eq!(
+            AUTO_FIELDS[0].template().lines().last(),
+            Some("# preset = \"vivid\"")
+        );
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema = json_schema();
+        let properties = &schema["properties"];
+
+        assert_eq!(schema["type"], "object");
+        assert_eq!(
+            properties["background_lightness"],
+            json!({
+                "type": "number",
+                "minimum": 0.0,
+                "maximum": 1.0,
+                "description": "Fixed lightness for darkened backgrounds (0.0 to 1.0)",
+                "default": 0.18,
+            })
+        );
+        assert_eq!(
+            properties["walk_boundary"]["enum"],
+            json!(["root", "home", "device"])
+        );
+        assert_eq!(
+            properties["hyperlinks"]["enum"],
+            json!([true, false, "auto"])
+        );
+        assert_eq!(
+            properties["trigger_priority"]["default"],
+            json!(["termtint", "paths", "files", "dirnames"])
+        );
+        assert_eq!(
+            properties["auto"]["properties"]["hue_max"]["default"],
+            360.0
+        );
+        assert_eq!(properties["dimming"]["required"], json!(["start", "end"]));
+        assert_eq!(
+            properties["triggers"]["items"]["required"],
+            json!(["match"])
+        );
+        assert!(properties["auto"]["properties"]["preset"]
+            .get("default")
+            .is_none());
+    }
+}
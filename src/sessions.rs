@@ -0,0 +1,393 @@
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+
+use crate::context::Context;
+use crate::hyperlink;
+use crate::iterm;
+use crate::state::{
+    self, CleanSelection, ConfigSourceType, ConfigState, SessionEntry, SessionState,
+};
+use crate::user_config::UserConfig;
+
+/// Format a duration as a compact age, using the largest whole unit (e.g. "45s", "3h", "2d").
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86_399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
+fn source_type_name(source_type: &ConfigSourceType) -> &'static str {
+    match source_type {
+        ConfigSourceType::Explicit => "explicit",
+        ConfigSourceType::VsCode => "vscode",
+        ConfigSourceType::TriggerPath => "trigger-path",
+        ConfigSourceType::TriggerFile => "trigger-file",
+        ConfigSourceType::TriggerDirname => "trigger-dirname",
+        ConfigSourceType::Environment => "environment",
+        ConfigSourceType::KubeContext => "kube-context",
+    }
+}
+
+/// JSON fields describing an applied state record.
+fn applied_state_json(state: &ConfigState) -> serde_json::Value {
+    serde_json::json!({
+        "directory": state.root_dir().to_string_lossy(),
+        "source_type": source_type_name(&state.source_type),
+        "tab": state.tab.map(|tab| tab.to_string()),
+        "background": state.background.map(|background| background.to_string()),
+    })
+}
+
+/// Merge the fields of `extra` into the object `value`.
+fn extend_json(value: &mut serde_json::Value, extra: serde_json::Value) {
+    if let (Some(object), serde_json::Value::Object(extra)) = (value.as_object_mut(), extra) {
+        object.extend(extra);
+    }
+}
+
+/// Build the JSON description of a session for `sessions list --json`.
+fn session_json(session: &SessionEntry, current: bool, ttl: Option<Duration>) -> serde_json::Value {
+    let mut value = serde_json::json!({
+        "id": session.id,
+        "current": current,
+        "age_seconds": session.age.map(|age| age.as_secs()),
+        "stale": session.is_stale(ttl),
+    });
+    let fields = match &session.state {
+        SessionState::Applied(state) => {
+            let mut fields = serde_json::json!({ "status": "applied" });
+            extend_json(&mut fields, applied_state_json(state));
+            fields
+        }
+        SessionState::Cleared => serde_json::json!({ "status": "cleared" }),
+        SessionState::Unreadable(reason) => serde_json::json!({
+            "status": "unreadable",
+            "error": reason,
+        }),
+    };
+    extend_json(&mut value, fields);
+    value
+}
+
+/// Build the JSON for `termtint state --json`: the full state record, or an
+/// empty object when there is none.
+fn state_json(
+    session: Option<&str>,
+    state: Option<&ConfigState>,
+    updated_at: Option<u64>,
+) -> serde_json::Value {
+    let Some(state) = state else {
+        return serde_json::json!({});
+    };
+    let mut value = serde_json::json!({
+        "session": session,
+        "path": state.path.to_string_lossy(),
+        "mtime": state.mtime,
+        "dimmed": state.dimmed,
+        "depth": state.depth,
+        "tty": state.tty,
+        "error": state.last_error,
+        "updated_at": updated_at,
+    });
+    extend_json(&mut value, applied_state_json(state));
+    value
+}
+
+/// Print the current session's state record straight from disk, for status lines
+/// and other tooling. Deliberately skips the user config (beyond a `state_dir`
+/// key), the directory walk, and session cleanup so it stays cheap, and writes
+/// nothing.
+///
+/// # Arguments
+/// * `ctx` - The invocation context, for the session id and state directory
+/// * `json` - Print a JSON object instead of text
+pub fn cmd_state(ctx: &Context, json: bool) {
+    let session = state::session_id(ctx);
+    let (record, updated_at) = match state::peek_last_config_state(ctx) {
+        Some((record, path)) => (Some(record), state::state_updated_at(&path)),
+        None => (None, None),
+    };
+
+    let disabled = state::disabled_from_env(ctx);
+    if json {
+        let mut value = state_json(session.as_deref(), record.as_ref(), updated_at);
+        if disabled {
+            extend_json(&mut value, serde_json::json!({ "disabled": true }));
+        }
+        println!("{}", value);
+        return;
+    }
+
+    if disabled {
+        println!("disabled:    TERMTINT_DISABLE is set, apply does nothing");
+    }
+    let Some(record) = record else {
+        println!("No applied state for this session.");
+        return;
+    };
+    println!("directory:   {}", record.root_dir().display());
+    println!("source type: {}", source_type_name(&record.source_type));
+    if let (Some(tab), Some(background)) = (record.tab, record.background) {
+        println!("tab:         {}", tab);
+        println!("background:  {}", background);
+    }
+    if let Some(error) = &record.last_error {
+        println!("error:       {}", error);
+    }
+}
+
+/// List the terminal sessions that have termtint state.
+///
+/// # Arguments
+/// * `ctx` - The invocation context, which locates the sessions and the current one
+/// * `json` - Print a JSON array instead of a table
+/// * `user_config` - User configuration, for the session TTL and truecolor support in swatches
+pub fn cmd_sessions_list(ctx: &Context, json: bool, user_config: &UserConfig) {
+    let sessions = state::list_sessions(ctx);
+    let current = state::session_id(ctx);
+    let is_current = |session: &SessionEntry| current.as_deref() == Some(session.id.as_str());
+
+    if json {
+        let values: Vec<serde_json::Value> = sessions
+            .iter()
+            .map(|session| session_json(session, is_current(session), user_config.session_ttl))
+            .collect();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&values).unwrap_or_else(|_| "[]".to_string())
+        );
+        return;
+    }
+
+    if sessions.is_empty() {
+        println!("No sessions in {}", state::sessions_dir(ctx).display());
+        return;
+    }
+
+    let truecolor = iterm::Capabilities::for_config(user_config).truecolor;
+    let links = hyperlink::enabled(ctx, user_config.hyperlinks, io::stdout().is_terminal());
+    let id_width = sessions.iter().map(|s| s.id.len()).max().unwrap_or(0);
+
+    for session in &sessions {
+        let marker = if is_current(session) { "*" } else { " " };
+        let age = session
+            .age
+            .map(format_age)
+            .unwrap_or_else(|| "?".to_string());
+        let detail = match &session.state {
+            SessionState::Applied(state) => {
+                let swatch = match state.tab {
+                    Some(tab) => format!("{}  \x1b[0m {}", tab.ansi_background(truecolor), tab),
+                    None => "   (no color recorded)".to_string(),
+                };
+                format!("{} {}", swatch, hyperlink::path(&state.root_dir(), links))
+            }
+            SessionState::Cleared => "(cleared)".to_string(),
+            SessionState::Unreadable(reason) => format!("(unreadable: {})", reason),
+        };
+        let stale = if session.is_stale(user_config.session_ttl) {
+            "  [stale]"
+        } else {
+            ""
+        };
+        println!(
+            "{} {:<id_width$}  {:>4}  {}{}",
+            marker,
+            session.id,
+            age,
+            detail,
+            stale,
+            id_width = id_width
+        );
+    }
+
+    if current.is_none() {
+        println!("\n(current session could not be identified)");
+    }
+}
+
+/// Remove session state, listing what would be removed unless `force` is set.
+///
+/// # Arguments
+/// * `ctx` - The invocation context, which locates the sessions directory
+/// * `older_than` - Age such as "2h" or "3d"; defaults to the configured `session_ttl`
+/// * `all` - Select every session regardless of age
+/// * `force` - Actually remove the selected sessions instead of a dry run
+/// * `ttl` - The configured `session_ttl`; None when cleanup is disabled
+///
+/// # Returns
+/// * `Ok(())` on success
+/// * `Err(String)` if the age can't be parsed
+pub fn cmd_sessions_clean(
+    ctx: &Context,
+    older_than: Option<&str>,
+    all: bool,
+    force: bool,
+    ttl: Option<Duration>,
+) -> Result<(), String> {
+    let selection = if all {
+        CleanSelection::All
+    } else {
+        let max_age = match (older_than, ttl) {
+            (Some(value), _) => {
+                state::parse_duration(value).map_err(|e| format!("Error: {}", e))?
+            }
+            (None, Some(ttl)) => ttl,
+            (None, None) => {
+                println!("Session cleanup is disabled (session_ttl = \"never\").");
+                println!("Pass --older-than <AGE> or --all to select sessions.");
+                return Ok(());
+            }
+        };
+        CleanSelection::OlderThan(max_age)
+    };
+
+    let selected = state::clean_sessions(ctx, selection, !force);
+    if selected.is_empty() {
+        println!("No sessions to remove.");
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        if force {
+            "Removed sessions:"
+        } else {
+            "Would remove sessions:"
+        }
+    );
+    for session in &selected {
+        let age = session
+            .age
+            .map(format_age)
+            .unwrap_or_else(|| "?".to_string());
+        println!("  {} ({} old)", session.id, age);
+    }
+    if !force {
+        println!("\nDry run; pass --force to remove them.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RGB;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_secs(45)), "45s");
+        assert_eq!(format_age(Duration::from_secs(3 * 60 + 10)), "3m");
+        assert_eq!(format_age(Duration::from_secs(5 * 3600)), "5h");
+        assert_eq!(format_age(Duration::from_secs(2 * 86_400 + 100)), "2d");
+    }
+
+    #[test]
+    fn test_session_json() {
+        let session = SessionEntry {
+            id: "pts_3".to_string(),
+            age: Some(Duration::from_secs(90)),
+            state: SessionState::Applied(ConfigState {
+                path: PathBuf::from("/code/app/.termtint"),
+                mtime: 1,
+                source_type: ConfigSourceType::Explicit,
+                tab: Some(RGB {
+                    r: 0x34,
+                    g: 0x78,
+                    b: 0xf6,
+                }),
+                ..Default::default()
+            }),
+        };
+
+        let value = session_json(&session, true, Some(state::STALE_SESSION_AGE));
+
+        assert_eq!(value["id"], "pts_3");
+        assert_eq!(value["current"], true);
+        assert_eq!(value["age_seconds"], 90);
+        assert_eq!(value["stale"], false);
+        assert_eq!(value["status"], "applied");
+        assert_eq!(value["directory"], "/code/app");
+        assert_eq!(value["tab"], "#3478f6");
+        assert!(value["background"].is_null());
+    }
+
+    #[test]
+    fn test_state_json() {
+        assert_eq!(state_json(Some("pts_3"), None, None), serde_json::json!({}));
+
+        let state = ConfigState {
+            path: PathBuf::from("/code/lib"),
+            source_type: ConfigSourceType::TriggerFile,
+            depth: 1,
+            tab: Some(RGB {
+                r: 0x34,
+                g: 0x78,
+                b: 0xf6,
+            }),
+            background: Some(RGB {
+                r: 0x0b,
+                g: 0x18,
+                b: 0x30,
+            }),
+            tty: Some("/dev/ttys003".to_string()),
+            ..Default::default()
+        };
+
+        let value = state_json(Some("pts_3"), Some(&state), Some(1_700_000_000));
+
+        assert_eq!(value["session"], "pts_3");
+        assert_eq!(value["directory"], "/code/lib");
+        assert_eq!(value["source_type"], "trigger-file");
+        assert_eq!(value["tab"], "#3478f6");
+        assert_eq!(value["background"], "#0b1830");
+        assert_eq!(value["depth"], 1);
+        assert_eq!(value["tty"], "/dev/ttys003");
+        assert_eq!(value["updated_at"], 1_700_000_000);
+        assert!(value["error"].is_null());
+    }
+
+    #[test]
+    fn test_json_with_non_utf8_directory() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let state = ConfigState {
+            path: PathBuf::from(OsStr::from_bytes(b"/code/caf\xe9/.termtint")),
+            source_type: ConfigSourceType::Explicit,
+            ..Default::default()
+        };
+        let session = SessionEntry {
+            id: "pts_3".to_string(),
+            age: None,
+            state: SessionState::Applied(state.clone()),
+        };
+
+        let value = session_json(&session, true, None);
+        assert_eq!(value["directory"], "/code/caf\u{fffd}");
+
+        let value = state_json(Some("pts_3"), Some(&state), None);
+        assert_eq!(value["directory"], "/code/caf\u{fffd}");
+        assert_eq!(value["path"], "/code/caf\u{fffd}/.termtint");
+    }
+
+    #[test]
+    fn test_session_json_unreadable() {
+        let session = SessionEntry {
+            id: "broken".to_string(),
+            age: None,
+            state: SessionState::Unreadable("malformed last_config".to_string()),
+        };
+
+        let value = session_json(&session, false, None);
+
+        assert_eq!(value["status"], "unreadable");
+        assert_eq!(value["error"], "malformed last_config");
+        assert!(value["age_seconds"].is_null());
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::process::{Command, ExitStatus};
+
+use crate::config::{self, ColorConfig};
+use crate::context::Context;
+use crate::error::TermtintError;
+use crate::iterm;
+use crate::palette;
+use crate::state;
+use crate::user_config::UserConfig;
+
+/// Single-letter ssh options that take a value (from ssh(1)).
+const SSH_OPTIONS_WITH_VALUE: &str = "BbcDEeFIiJLlmOoPpQRSWw";
+
+/// Find the destination among ssh arguments: the first argument that is not an option
+/// or an option's value.
+pub fn find_destination(args: &[String]) -> Option<&str> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            return iter.next().map(|s| s.as_str());
+        }
+        let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) else {
+            return Some(arg);
+        };
+        // In a group like `-vp`, the first value-taking flag consumes the rest of the
+        // argument, or the next argument if nothing follows it
+        if let Some(pos) = flags.find(|c| SSH_OPTIONS_WITH_VALUE.contains(c)) {
+            if pos == flags.len() - 1 {
+                iter.next();
+            }
+        }
+    }
+    None
+}
+
+/// Extract the host name from an ssh destination (`host`, `user@host`, `ssh://user@host:port`).
+pub fn host_name(destination: &str) -> &str {
+    let (rest, is_uri) = match destination.strip_prefix("ssh://") {
+        Some(rest) => (rest, true),
+        None => (destination, false),
+    };
+    let host = rest.rsplit_once('@').map_or(rest, |(_, host)| host);
+    if is_uri {
+        host.split(':').next().unwrap_or(host)
+    } else {
+        host
+    }
+}
+
+/// Find the color override for a host in the `[hosts]` table.
+/// An exact match wins; otherwise the longest matching glob pattern is used.
+pub fn host_override<'a>(host: &str, hosts: &'a [(String, String)]) -> Option<&'a str> {
+    if let Some((_, color)) = hosts.iter().find(|(pattern, _)| pattern == host) {
+        return Some(color);
+    }
+    hosts
+        .iter()
+        .filter(|(pattern, _)| {
+            glob::Pattern::new(pattern)
+                .map(|p| p.matches(host))
+                .unwrap_or(false)
+        })
+        .max_by_key(|(pattern, _)| pattern.len())
+        .map(|(_, color)| color.as_str())
+}
+
+/// Resolve the colors for a remote host, from `[hosts]` or a hash of the host name.
+pub fn host_colors(host: &str, user_config: &UserConfig) -> Result<ColorConfig, TermtintError> {
+    match host_override(host, &user_config.hosts) {
+        Some(color) => config::parse_simple_color(color, user_config),
+        None => {
+            let mut hasher = DefaultHasher::new();
+            host.hash(&mut hasher);
+            Ok(config::color_from_hash(hasher.finish(), user_config))
+        }
+    }
+}
+
+/// Map an ssh exit status to our exit code, following the shell convention of
+/// 128 + signal number for signal-terminated processes.
+fn exit_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+/// Reset everything `cmd_ssh` applied, and forget the last applied config so the
+/// next prompt hook re-applies the local directory's colors.
+fn reset_after_ssh(ctx: &Context, badge: bool, user_config: &UserConfig) {
+    iterm::reset_colors();
+    if user_config.generate_palette {
+        iterm::reset_palette();
+    }
+    if badge {
+        iterm::clear_badge();
+    }
+    let _ = std::io::stdout().flush();
+    state::write_last_config_state(ctx, None);
+}
+
+/// Run ssh with colors derived from the remote host, resetting them when ssh exits.
+///
+/// # Arguments
+/// * `ctx` - The invocation context, whose session state is cleared afterwards
+/// * `args` - Arguments passed through to ssh untouched
+/// * `badge` - Also show the host name as an iTerm2 badge
+/// * `user_config` - User configuration for color generation and `[hosts]` overrides
+///
+/// # Returns
+/// * `Ok(code)` with ssh's exit code
+/// * `Err(String)` with error message if ssh could not be started
+pub fn cmd_ssh(
+    ctx: &Context,
+    args: &[String],
+    badge: bool,
+    user_config: &UserConfig,
+) -> Result<i32, String> {
+    let destination = find_destination(args).ok_or("Error: no ssh destination given")?;
+    let host = host_name(destination);
+    let colors = config::apply_temperature(
+        &host_colors(host, user_config)?,
+        user_config.temperature_shift,
+    );
+
+    iterm::apply_colors(&colors, iterm::Capabilities::for_config(user_config));
+    if user_config.generate_palette {
+        iterm::set_palette(&palette::generate_palette(&colors));
+    }
+    if badge {
+        iterm::set_badge(host);
+    }
+    let _ = std::io::stdout().flush();
+
+    let mut child = match Command::new("ssh").args(args).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            reset_after_ssh(ctx, badge, user_config);
+            return Err(format!("Error running ssh: {}", e));
+        }
+    };
+
+    // Ctrl-C is delivered to the whole foreground process group. Ignore it here (after
+    // spawning, so ssh keeps the default handlers) so we survive to reset the colors.
+    // SAFETY: signal() only swaps process-wide dispositions and the previous ones are restored.
+    let (previous_int, previous_quit) = unsafe {
+        (
+            libc::signal(libc::SIGINT, libc::SIG_IGN),
+            libc::signal(libc::SIGQUIT, libc::SIG_IGN),
+        )
+    };
+    let status = child.wait();
+    unsafe {
+        libc::signal(libc::SIGINT, previous_int);
+        libc::signal(libc::SIGQUIT, previous_quit);
+    }
+
+    reset_after_ssh(ctx, badge, user_config);
+
+    let status = status.map_err(|e| format!("Error waiting for ssh: {}", e))?;
+    Ok(exit_code(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn hosts(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter()
+            .map(|(p, c)| (p.to_string(), c.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_destination_simple() {
+        assert_eq!(find_destination(&args(&["prod-1"])), Some("prod-1"));
+        assert_eq!(
+            find_destination(&args(&["deploy@prod-1", "uptime"])),
+            Some("deploy@prod-1")
+        );
+    }
+
+    #[test]
+    fn test_find_destination_skips_options() {
+        assert_eq!(
+            find_destination(&args(&["-p", "2222", "-A", "prod-1", "uptime"])),
+            Some("prod-1")
+        );
+        assert_eq!(
+            find_destination(&args(&["-p2222", "prod-1"])),
+            Some("prod-1")
+        );
+        assert_eq!(
+            find_destination(&args(&["-vi", "~/.ssh/key", "prod-1"])),
+            Some("prod-1")
+        );
+        assert_eq!(
+            find_destination(&args(&["-o", "BatchMode=yes", "--", "prod-1"])),
+            Some("prod-1")
+        );
+    }
+
+    #[test]
+    fn test_find_destination_missing() {
+        assert_eq!(find_destination(&args(&["-p", "2222"])), None);
+        assert_eq!(find_destination(&[]), None);
+    }
+
+    #[test]
+    fn test_host_name() {
+        assert_eq!(host_name("prod-1"), "prod-1");
+        assert_eq!(host_name("deploy@prod-1"), "prod-1");
+        assert_eq!(host_name("ssh://deploy@prod-1:2222"), "prod-1");
+    }
+
+    #[test]
+    fn test_host_override_exact_beats_glob() {
+        let hosts = hosts(&[("prod-*", "#cc0000"), ("prod-canary", "#cc8800")]);
+
+        assert_eq!(host_override("prod-canary", &hosts), Some("#cc8800"));
+        assert_eq!(host_override("prod-1", &hosts), Some("#cc0000"));
+        assert_eq!(host_override("staging-1", &hosts), None);
+    }
+
+    #[test]
+    fn test_host_override_prefers_most_specific_glob() {
+        let hosts = hosts(&[("*", "#333333"), ("prod-db-*", "#880000")]);
+
+        assert_eq!(host_override("prod-db-2", &hosts), Some("#880000"));
+        assert_eq!(host_override("dev", &hosts), Some("#333333"));
+    }
+
+    #[test]
+    fn test_host_colors_override() {
+        let user_config = UserConfig {
+            hosts: hosts(&[("prod-*", "#cc0000")]),
+            ..UserConfig::default()
+        };
+        let colors = host_colors("prod-1", &user_config).unwrap();
+
+        assert_eq!(colors.tab, config::parse_color("#cc0000").unwrap());
+    }
+
+    #[test]
+    fn test_host_colors_invalid_override() {
+        let user_config = UserConfig {
+            hosts: hosts(&[("prod-*", "not-a-color")]),
+            ..UserConfig::default()
+        };
+
+        assert!(host_colors("prod-1", &user_config).is_err());
+    }
+
+    #[test]
+    fn test_host_colors_hash_is_deterministic() {
+        let user_config = UserConfig::default();
+
+        assert_eq!(
+            host_colors("staging", &user_config).unwrap(),
+            host_colors("staging", &user_config).unwrap()
+        );
+        assert_ne!(
+            host_colors("staging", &user_config).unwrap(),
+            host_colors("production", &user_config).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_exit_code_propagates() {
+        let status = Command::new("sh").args(["-c", "exit 3"]).status().unwrap();
+        assert_eq!(exit_code(status), 3);
+
+        let status = Command::new("sh")
+            .args(["-c", "kill -TERM $$"])
+            .status()
+            .unwrap();
+        assert_eq!(exit_code(status), 128 + libc::SIGTERM);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::context::Context;
+use crate::state::{self, ConfigState};
+
+/// The symbol shown in the prompt, colored with the applied tab color.
+const SYMBOL: &str = "●";
+
+/// The `[custom.termtint]` block printed by `termtint starship --config`.
+///
+/// `when = true` runs the command before every prompt; starship drops the
+/// module when the output is empty, and the `( )` group keeps the trailing
+/// space out of the prompt in that case.
+const STARSHIP_CONFIG: &str = r#"[custom.termtint]
+command = "termtint starship"
+when = true
+format = "($output )"
+description = "Tab color of the active termtint project"
+"#;
+
+/// Render the prompt segment for an applied state: the symbol in the recorded
+/// tab color, or an empty string when nothing is applied (no state, a cleared
+/// session, no recorded color, or `TERMTINT_DISABLE`).
+///
+/// Starship inserts a custom command's output as plain text rather than parsing
+/// it as a format string, so the color is a truecolor SGR sequence; starship
+/// wraps escape sequences in its output for the shell's prompt width handling.
+fn starship_output(state: Option<&ConfigState>, disabled: bool) -> String {
+    if disabled {
+        return String::new();
+    }
+    match state.and_then(|state| state.tab) {
+        Some(tab) => format!("\x1b[38;2;{};{};{}m{}\x1b[0m", tab.r, tab.g, tab.b, SYMBOL),
+        None => String::new(),
+    }
+}
+
+/// Print the starship segment for the current session, reading only its state
+/// record (no user config, directory walk, or cleanup) so it is cheap to run
+/// before every prompt.
+///
+/// Always exits 0, printing nothing when termtint has no colors applied: starship
+/// hides a custom module whose output is empty, so the segment just disappears.
+///
+/// # Arguments
+/// * `ctx` - The invocation context, for the session id and state directory
+/// * `config` - Print the `[custom.termtint]` block for starship.toml instead
+pub fn cmd_starship(ctx: &Context, config: bool) {
+    if config {
+        print!("{}", STARSHIP_CONFIG);
+        return;
+    }
+    let record = state::peek_last_config_state(ctx).map(|(record, _)| record);
+    let output = starship_output(record.as_ref(), state::disabled_from_env(ctx));
+    if !output.is_empty() {
+        println!("{}", output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RGB;
+    use crate::state::ConfigSourceType;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    fn applied_state(tab: Option<RGB>) -> ConfigState {
+        ConfigState {
+            path: PathBuf::from("/code/app/.termtint"),
+            mtime: 1,
+            source_type: ConfigSourceType::Explicit,
+            tab,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_starship_output() {
+        let tab = RGB {
+            r: 0x34,
+            g: 0x78,
+            b: 0xf6,
+        };
+        let state = applied_state(Some(tab));
+
+        assert_eq!(
+            starship_output(Some(&state), false),
+            "\x1b[38;2;52;120;246m●\x1b[0m"
+        );
+        assert_eq!(starship_output(Some(&state), true), "");
+        assert_eq!(starship_output(Some(&applied_state(None)), false), "");
+        assert_eq!(starship_output(None, false), "");
+    }
+
+    #[test]
+    fn test_starship_reads_session_state() {
+        let temp = TempDir::new().unwrap();
+        let ctx = Context::new(temp.path(), temp.path())
+            .with_var(
+                "TERMTINT_STATE_DIR",
+                &temp.path().join("state").to_string_lossy(),
+            )
+            .with_var("TERMTINT_SESSION", "tab-1");
+        let read = || {
+            let record = state::peek_last_config_state(&ctx).map(|(record, _)| record);
+            starship_output(record.as_ref(), false)
+        };
+        assert_eq!(read(), "");
+
+        let tab = RGB {
+            r: 0xff,
+            g: 0x55,
+            b: 0x00,
+        };
+        state::write_last_config_state(&ctx, Some(&applied_state(Some(tab))));
+        assert_eq!(read(), "\x1b[38;2;255;85;0m●\x1b[0m");
+
+        state::write_last_config_state(&ctx, None);
+        assert_eq!(read(), "");
+    }
+
+    #[test]
+    fn test_starship_config_parses() {
+        let config: toml::Table = STARSHIP_CONFIG.parse().unwrap();
+        let module = config["custom"]["termtint"].as_table().unwrap();
+        assert_eq!(module["command"].as_str(), Some("termtint starship"));
+        assert_eq!(module["when"].as_bool(), Some(true));
+    }
+}
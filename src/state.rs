@@ -1,67 +1,516 @@
+use std::collections::hash_map::DefaultHasher;
 use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-const STALE_SESSION_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+use crate::config::{matched_trigger_name, parse_color, ConfigSource, RGB};
+use crate::context::Context;
+use crate::error::TermtintError;
+use crate::user_config::UserConfig;
 
-/// Get the sessions directory path for a given home directory.
-fn sessions_dir_for_home(home: &Path) -> PathBuf {
-    home.join(".cache").join("termtint").join("sessions")
+/// Sessions whose state hasn't been written for this long are removed by cleanup.
+pub const STALE_SESSION_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Ages beyond this (a clock that was far off, or a timestamp of 0 from a damaged
+/// file) are clamped to it, so such sessions still count as stale without being
+/// shown with an absurd age.
+const MAX_PLAUSIBLE_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Automatic cleanup sweeps the sessions directory at most this often by default.
+pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Number of recently generated random hues to remember.
+#[cfg(feature = "reroll")]
+const RECENT_HUES_LIMIT: usize = 8;
+
+/// Whether `TERMTINT_NO_STATE` asks `apply` and `reset` to bypass state, like `--no-state`.
+pub fn no_state_from_env(ctx: &Context) -> bool {
+    env_flag_enabled(ctx.var("TERMTINT_NO_STATE"))
 }
 
-/// Get the sessions directory, creating it if it doesn't exist.
-fn get_sessions_dir() -> PathBuf {
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    let sessions_dir = sessions_dir_for_home(Path::new(&home));
-    let _ = fs::create_dir_all(&sessions_dir);
-    sessions_dir
+/// Whether `TERMTINT_DISABLE` turns `apply` (and the shell hooks) into a no-op.
+pub fn disabled_from_env(ctx: &Context) -> bool {
+    env_flag_enabled(ctx.var("TERMTINT_DISABLE"))
 }
 
-/// Clean up stale session directories older than STALE_SESSION_AGE.
-pub fn cleanup_stale_sessions() {
-    let sessions = get_sessions_dir();
-    cleanup_stale_sessions_in(&sessions);
+/// An environment flag is on when set to anything other than empty, "0", "false", "no", or "off".
+fn env_flag_enabled(value: Option<&str>) -> bool {
+    value.is_some_and(|value| {
+        !matches!(
+            value.trim().to_ascii_lowercase().as_str(),
+            "" | "0" | "false" | "no" | "off"
+        )
+    })
 }
 
-/// Clean up stale session directories in the given sessions directory.
-fn cleanup_stale_sessions_in(sessions_dir: &Path) {
-    let Ok(entries) = fs::read_dir(sessions_dir) else {
-        return;
+/// Get the default state directory for a given home directory.
+fn default_state_root(home: &Path) -> PathBuf {
+    home.join(".cache").join("termtint")
+}
+
+/// Choose the state directory: `TERMTINT_STATE_DIR` wins over the `state_dir`
+/// user-config key, which wins over ~/.cache/termtint. A leading `~/` is expanded.
+/// The config key is only read when the environment doesn't decide.
+fn resolve_state_root(
+    env_dir: Option<&str>,
+    configured_dir: impl FnOnce() -> Option<String>,
+    home: &Path,
+) -> PathBuf {
+    env_dir
+        .filter(|dir| !dir.trim().is_empty())
+        .map(str::to_string)
+        .or_else(|| configured_dir().filter(|dir| !dir.trim().is_empty()))
+        .map(|dir| match dir.strip_prefix("~/") {
+            Some(rest) => home.join(rest),
+            None => PathBuf::from(dir),
+        })
+        .unwrap_or_else(|| default_state_root(home))
+}
+
+/// Get the directory holding all of termtint's state and caches.
+pub fn state_root(ctx: &Context) -> PathBuf {
+    ctx.state_root
+        .get_or_init(|| {
+            resolve_state_root(
+                ctx.var("TERMTINT_STATE_DIR"),
+                || crate::user_config::configured_state_dir(ctx),
+                ctx.home_dir(),
+            )
+        })
+        .clone()
+}
+
+/// Resolve the state directory for `ctx` from a loaded user config's `state_dir`,
+/// so locating state afterwards doesn't read the config file again. Does nothing
+/// once the directory has been resolved.
+pub fn use_configured_state_dir(ctx: &Context, state_dir: Option<&str>) {
+    let _ = ctx.state_root.set(resolve_state_root(
+        ctx.var("TERMTINT_STATE_DIR"),
+        || state_dir.map(str::to_string),
+        ctx.home_dir(),
+    ));
+}
+
+/// Whether a directory exists (or can be created) and can be written to.
+fn is_writable_dir(dir: &Path) -> bool {
+    if fs::create_dir_all(dir).is_err() {
+        return false;
+    }
+    let Ok(c_path) = std::ffi::CString::new(dir.as_os_str().as_encoded_bytes()) else {
+        return false;
     };
+    // SAFETY: c_path is a valid NUL-terminated string for the duration of the call.
+    unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
+}
 
-    for entry in entries.flatten() {
-        let config_path = entry.path().join("last_config");
-        if let Ok(metadata) = fs::metadata(&config_path) {
-            if let Ok(modified) = metadata.modified() {
-                if SystemTime::now()
-                    .duration_since(modified)
-                    .map(|age| age > STALE_SESSION_AGE)
-                    .unwrap_or(false)
-                {
-                    let _ = fs::remove_dir_all(entry.path());
-                }
+/// Whether state can be persisted. When the state directory is unwritable (e.g. a
+/// read-only HOME in CI), state is neither read nor written and every `apply`
+/// emits colors.
+pub fn state_writable(ctx: &Context) -> bool {
+    *ctx.state_writable
+        .get_or_init(|| is_writable_dir(&state_root(ctx)))
+}
+
+/// Get the sessions directory path within a state directory.
+fn sessions_dir_in(root: &Path) -> PathBuf {
+    root.join("sessions")
+}
+
+/// Clean up session directories older than `ttl`, sweeping at most once per
+/// `interval` so a slow home directory isn't scanned on every prompt.
+/// A `ttl` of None disables cleanup.
+pub fn cleanup_stale_sessions(ctx: &Context, ttl: Option<Duration>, interval: Duration) -> Cleanup {
+    if ttl.is_none() {
+        return Cleanup::Disabled;
+    }
+    if !state_writable(ctx) {
+        return Cleanup::NotWritable;
+    }
+    match cleanup_stale_sessions_throttled(&state_root(ctx), ttl, interval, ctx.now) {
+        true => Cleanup::Swept,
+        false => Cleanup::Throttled,
+    }
+}
+
+/// What `cleanup_stale_sessions` did.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cleanup {
+    /// `session_ttl = "never"`
+    Disabled,
+    /// The state directory can't be written
+    NotWritable,
+    /// The last sweep was less than `cleanup_interval` ago
+    Throttled,
+    /// Stale sessions were swept
+    Swept,
+}
+
+/// The marker file whose mtime records the last cleanup sweep.
+fn cleanup_marker_path_in(root: &Path) -> PathBuf {
+    root.join("last_cleanup")
+}
+
+/// Sweep stale sessions under a state directory unless the last sweep was less
+/// than `interval` ago. Returns whether a sweep ran.
+fn cleanup_stale_sessions_throttled(
+    root: &Path,
+    ttl: Option<Duration>,
+    interval: Duration,
+    now: SystemTime,
+) -> bool {
+    let marker = cleanup_marker_path_in(root);
+    let last_sweep_age = fs::metadata(&marker)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| now.duration_since(modified).ok());
+    if last_sweep_age.is_some_and(|age| age < interval) {
+        return false;
+    }
+
+    // Claim the sweep before running it so concurrent applies mostly skip. Two
+    // processes may still both sweep; that's harmless, as removal tolerates
+    // entries that are already gone.
+    let _ = write_atomic(&marker, unix_time(now).to_string().as_bytes());
+
+    cleanup_stale_sessions_in(&sessions_dir_in(root), ttl, now);
+    true
+}
+
+/// Clean up stale session directories in the given sessions directory, along with
+/// the legacy shared state file next to it once that is older than `ttl` too.
+fn cleanup_stale_sessions_in(sessions_dir: &Path, ttl: Option<Duration>, now: SystemTime) {
+    if let Some(ttl) = ttl {
+        clean_sessions_in(sessions_dir, CleanSelection::OlderThan(ttl), false, now);
+
+        let legacy_path = sessions_dir.with_file_name("last_config");
+        let legacy_age = state_updated_at(&legacy_path).map(|at| age_since(at, now));
+        if legacy_age.is_some_and(|age| age > ttl) {
+            let _ = fs::remove_file(&legacy_path);
+        }
+    }
+}
+
+/// Which sessions `clean_sessions` removes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CleanSelection {
+    /// Sessions whose state is older than the given age
+    OlderThan(Duration),
+    /// Every session entry
+    All,
+}
+
+impl CleanSelection {
+    fn selects(&self, session: &SessionEntry) -> bool {
+        match self {
+            CleanSelection::OlderThan(max_age) => {
+                session.age.map(|age| age > *max_age).unwrap_or(false)
+            }
+            CleanSelection::All => true,
+        }
+    }
+}
+
+/// Remove the selected sessions, or only report them when `dry_run` is set.
+/// Returns the selected sessions.
+pub fn clean_sessions(
+    ctx: &Context,
+    selection: CleanSelection,
+    dry_run: bool,
+) -> Vec<SessionEntry> {
+    clean_sessions_in(&sessions_dir(ctx), selection, dry_run, ctx.now)
+}
+
+/// Remove the selected sessions in a given sessions directory.
+fn clean_sessions_in(
+    sessions_dir: &Path,
+    selection: CleanSelection,
+    dry_run: bool,
+    now: SystemTime,
+) -> Vec<SessionEntry> {
+    let selected: Vec<SessionEntry> = list_sessions_in(sessions_dir, now)
+        .into_iter()
+        .filter(|session| selection.selects(session))
+        .collect();
+
+    if !dry_run {
+        for session in &selected {
+            let path = sessions_dir.join(&session.id);
+            if fs::remove_dir_all(&path).is_err() {
+                // Stray files in the sessions directory aren't directories
+                let _ = fs::remove_file(&path);
             }
         }
     }
+    selected
+}
+
+/// Clear the applied state of every session other than `current`, recording a
+/// reset for each. Returns the sessions that had applied state, so the caller
+/// can reset their terminals.
+pub fn clear_other_sessions(ctx: &Context, current: Option<&str>) -> Vec<SessionEntry> {
+    if !state_writable(ctx) {
+        return Vec::new();
+    }
+    clear_other_sessions_in(&sessions_dir(ctx), current, ctx.now)
+}
+
+/// Clear the applied state of other sessions in a given sessions directory.
+fn clear_other_sessions_in(
+    sessions_dir: &Path,
+    current: Option<&str>,
+    now: SystemTime,
+) -> Vec<SessionEntry> {
+    let cleared: Vec<SessionEntry> = list_sessions_in(sessions_dir, now)
+        .into_iter()
+        .filter(|session| current != Some(session.id.as_str()))
+        .filter(|session| matches!(session.state, SessionState::Applied(_)))
+        .collect();
+
+    for session in &cleared {
+        let state_path = sessions_dir.join(&session.id).join("last_config");
+        write_last_config_state_to(&state_path, None, now);
+        record_reset_to(
+            &reset_record_path_for(&state_path),
+            ResetReason::Command,
+            now,
+        );
+    }
+    cleared
+}
+
+/// Parse a duration such as "90s", "30m", "2h", "3d", or "1w".
+pub fn parse_duration(s: &str) -> Result<Duration, TermtintError> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(|| {
+        TermtintError::Config(format!(
+            "missing unit in duration '{}' (use s, m, h, d, or w)",
+            s
+        ))
+    })?;
+    let (amount, unit) = s.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| {
+        TermtintError::Config(format!("invalid duration '{}', expected e.g. 2h or 3d", s))
+    })?;
+    let unit_secs = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(TermtintError::Config(format!(
+                "invalid unit in duration '{}' (use s, m, h, d, or w)",
+                s
+            )))
+        }
+    };
+    amount
+        .checked_mul(unit_secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| TermtintError::Config(format!("duration '{}' is too large", s)))
+}
+
+/// Format a duration using the largest unit that divides it evenly (e.g. "90m", "7d").
+/// The output round-trips through `parse_duration`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let units = [
+        (7 * 24 * 60 * 60, "w"),
+        (24 * 60 * 60, "d"),
+        (60 * 60, "h"),
+        (60, "m"),
+    ];
+    units
+        .iter()
+        .find(|(unit_secs, _)| secs > 0 && secs.is_multiple_of(*unit_secs))
+        .map(|(unit_secs, unit)| format!("{}{}", secs / unit_secs, unit))
+        .unwrap_or_else(|| format!("{}s", secs))
+}
+
+/// Get the sessions directory without creating it.
+pub fn sessions_dir(ctx: &Context) -> PathBuf {
+    sessions_dir_in(&state_root(ctx))
+}
+
+/// What a session directory holds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionState {
+    /// The session has applied a config
+    Applied(ConfigState),
+    /// The session's state was cleared (it left a termtint directory)
+    Cleared,
+    /// The session entry exists but can't be read, with the reason
+    Unreadable(String),
+}
+
+/// A session found in the sessions directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionEntry {
+    pub id: String,
+    /// Time since the session's state was last written, if known
+    pub age: Option<Duration>,
+    pub state: SessionState,
+}
+
+impl SessionEntry {
+    /// Whether the session is old enough to be removed by stale-session cleanup
+    /// with the given TTL. Nothing is stale when the TTL is None (cleanup disabled).
+    pub fn is_stale(&self, ttl: Option<Duration>) -> bool {
+        match (self.age, ttl) {
+            (Some(age), Some(ttl)) => age > ttl,
+            _ => false,
+        }
+    }
+}
+
+/// List all sessions in the sessions directory, sorted by id.
+pub fn list_sessions(ctx: &Context) -> Vec<SessionEntry> {
+    list_sessions_in(&sessions_dir(ctx), ctx.now)
+}
+
+/// List the sessions in a given sessions directory, sorted by id.
+/// Entries that can't be read are included as `SessionState::Unreadable`.
+fn list_sessions_in(sessions_dir: &Path, now: SystemTime) -> Vec<SessionEntry> {
+    let Ok(entries) = fs::read_dir(sessions_dir) else {
+        return Vec::new();
+    };
+
+    let mut sessions: Vec<SessionEntry> = entries
+        .flatten()
+        .map(|entry| {
+            let id = entry.file_name().to_string_lossy().into_owned();
+            let config_path = entry.path().join("last_config");
+            let updated_at = state_updated_at(&config_path).or_else(|| {
+                entry
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs())
+            });
+            let age = updated_at.map(|at| age_since(at, now));
+
+            let state = if !entry.path().is_dir() {
+                SessionState::Unreadable("not a directory".to_string())
+            } else if !config_path.exists() {
+                SessionState::Cleared
+            } else {
+                match read_last_config_state_from(&config_path) {
+                    Some(state) => SessionState::Applied(state),
+                    None => SessionState::Unreadable("malformed last_config".to_string()),
+                }
+            };
+
+            SessionEntry { id, age, state }
+        })
+        .collect();
+    sessions.sort_by(|a, b| a.id.cmp(&b.id));
+    sessions
+}
+
+/// Get the legacy shared state file path within a state directory.
+/// Used when no terminal session can be identified.
+fn state_file_path_in(root: &Path) -> PathBuf {
+    root.join("last_config")
+}
+
+/// Get the state file path for a terminal session.
+fn session_state_path_in(root: &Path, session: &str) -> PathBuf {
+    sessions_dir_in(root).join(session).join("last_config")
+}
+
+/// Get the path to the state file for the current terminal session,
+/// or the shared state file when the session can't be identified.
+pub fn state_file_path(ctx: &Context) -> PathBuf {
+    let root = state_root(ctx);
+    match session_id(ctx) {
+        Some(session) => session_state_path_in(&root, &session),
+        None => state_file_path_in(&root),
+    }
 }
 
-/// Get the state file path for a given home directory.
-fn state_file_path_for_home(home: &Path) -> PathBuf {
-    home.join(".cache").join("termtint").join("last_config")
+/// Identify the current terminal session, so each tab tracks its own applied config.
+/// Uses iTerm2's `ITERM_SESSION_ID`, then an explicit `TERMTINT_SESSION`, then the
+/// name of the controlling tty. Returns None outside a terminal.
+pub fn session_id(ctx: &Context) -> Option<String> {
+    session_id_from(
+        ctx.var("ITERM_SESSION_ID").map(str::to_string),
+        ctx.var("TERMTINT_SESSION").map(str::to_string),
+        tty_name(),
+    )
 }
 
-/// Get the path to the state file.
-pub fn state_file_path() -> PathBuf {
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    state_file_path_for_home(Path::new(&home))
+/// Pick the first non-empty session identifier and make it safe to use as a directory name.
+fn session_id_from(
+    iterm_session: Option<String>,
+    termtint_session: Option<String>,
+    tty: Option<String>,
+) -> Option<String> {
+    [iterm_session, termtint_session, tty]
+        .into_iter()
+        .flatten()
+        .map(|id| id.trim().to_string())
+        .find(|id| !id.is_empty())
+        .map(|id| sanitize_session_id(&id))
+}
+
+/// Replace characters that are awkward in file names (`/dev/pts/3` becomes `pts_3`).
+fn sanitize_session_id(id: &str) -> String {
+    id.strip_prefix("/dev/")
+        .unwrap_or(id)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Name of the terminal attached to stdin, if any.
+fn tty_name() -> Option<String> {
+    tty_name_of(libc::STDIN_FILENO)
+}
+
+/// Path of the controlling terminal, e.g. "/dev/ttys003", checking stdin, then
+/// stdout, then stderr. Returns None without a terminal (cron, CI, pipes).
+pub fn tty_identity() -> Option<String> {
+    [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO]
+        .into_iter()
+        .find_map(tty_name_of)
+}
+
+/// Name of the terminal attached to a file descriptor, if any.
+fn tty_name_of(fd: libc::c_int) -> Option<String> {
+    // SAFETY: ttyname returns either null or a pointer to a static NUL-terminated buffer,
+    // which is copied before any other call could overwrite it.
+    unsafe {
+        let name = libc::ttyname(fd);
+        if name.is_null() {
+            return None;
+        }
+        Some(
+            std::ffi::CStr::from_ptr(name)
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
 }
 
 /// Type of config source.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ConfigSourceType {
-    Explicit,    // .termtint file found
-    TriggerPath, // Directory matching a trigger path pattern (auto color)
-    TriggerFile, // Directory with trigger file (auto color)
+    Explicit,       // .termtint file found
+    VsCode,         // .vscode/settings.json with a Peacock color
+    TriggerPath,    // Directory matching a trigger path pattern (auto color)
+    TriggerFile,    // Directory with trigger file (auto color)
+    TriggerDirname, // Directory whose name matches a trigger name (auto color)
+    Environment,    // Matching [env_triggers] rule; the path holds the VAR=value assignment
+    KubeContext,    // Matching kube-context trigger; the path holds kube-context=NAME
 }
 
 /// State info for the last applied config.
@@ -70,18 +519,320 @@ pub struct ConfigState {
     pub path: PathBuf,
     pub mtime: u64,
     pub source_type: ConfigSourceType,
+    /// Whether time-of-day dimming was active when the colors were applied
+    pub dimmed: bool,
+    /// Directory levels below the config source, tracked only when `depth_ramp` is enabled
+    pub depth: u32,
+    /// Tab color that was applied, for display by `sessions list`
+    pub tab: Option<RGB>,
+    /// Background color that was applied
+    pub background: Option<RGB>,
+    /// Modification time of the user config file, so edits to it force a re-apply
+    pub config_mtime: u64,
+    /// Hash of an explicit `.termtint`'s contents (0 for trigger sources), so a
+    /// file deleted and restored with the same mtime is still detected as changed
+    pub content_hash: u64,
+    /// Controlling terminal of the process that applied the colors, so terminals
+    /// sharing a state file don't act on each other's state
+    pub tty: Option<String>,
+    /// Error from the latest apply attempt, which left the applied colors and the
+    /// rest of this state untouched
+    pub last_error: Option<String>,
+}
+
+/// A state for an explicit `.termtint` with nothing else recorded, for tests to
+/// fill in with struct update syntax.
+#[cfg(test)]
+impl Default for ConfigState {
+    fn default() -> Self {
+        ConfigState {
+            path: PathBuf::new(),
+            mtime: 0,
+            source_type: ConfigSourceType::Explicit,
+            dimmed: false,
+            depth: 0,
+            tab: None,
+            background: None,
+            config_mtime: 0,
+            content_hash: 0,
+            tty: None,
+            last_error: None,
+        }
+    }
+}
+
+impl ConfigState {
+    /// Whether this describes the same applied config as `other`, ignoring the
+    /// recorded colors (which are only known after colors are resolved), tty, and error.
+    /// When both sides have a content hash it decides whether a `.termtint` changed;
+    /// mtime is only compared against older state files that lack one.
+    pub fn matches(&self, other: &ConfigState) -> bool {
+        let mtime = if self.content_hash != 0 && other.content_hash != 0 {
+            other.mtime
+        } else {
+            self.mtime
+        };
+        ConfigState {
+            mtime,
+            tab: other.tab,
+            background: other.background,
+            tty: other.tty.clone(),
+            last_error: other.last_error.clone(),
+            ..self.clone()
+        } == *other
+    }
+
+    /// Describe the config that applying `source` would apply, before colors are
+    /// resolved. Returns None if an explicit `.termtint` (or VS Code settings file)
+    /// can no longer be read.
+    ///
+    /// Trigger sources record their matched directory with mtime 0: the auto color
+    /// depends only on that directory, so moving between its subdirectories yields
+    /// an identical state and nothing is re-emitted. Environment sources record the
+    /// variable's assignment, and kube context sources the context, so changing
+    /// either re-applies on the next prompt.
+    pub fn for_source(
+        source: &ConfigSource,
+        dimmed: bool,
+        depth: u32,
+        config_mtime: u64,
+    ) -> Option<ConfigState> {
+        let (path, mtime, content_hash, source_type) = match source {
+            // For explicit .termtint files, track the file's mtime and contents
+            ConfigSource::Termtint(path) => (
+                path.clone(),
+                get_file_mtime(path)?,
+                file_content_hash(path)?,
+                ConfigSourceType::Explicit,
+            ),
+            ConfigSource::VsCode(path) => (
+                path.clone(),
+                get_file_mtime(path)?,
+                file_content_hash(path)?,
+                ConfigSourceType::VsCode,
+            ),
+            ConfigSource::TriggerPath(dir_path) => {
+                (dir_path.clone(), 0, 0, ConfigSourceType::TriggerPath)
+            }
+            ConfigSource::TriggerFile(dir_path) => {
+                (dir_path.clone(), 0, 0, ConfigSourceType::TriggerFile)
+            }
+            ConfigSource::TriggerDirname(dir_path) => {
+                (dir_path.clone(), 0, 0, ConfigSourceType::TriggerDirname)
+            }
+            ConfigSource::Environment { assignment, .. } => (
+                PathBuf::from(assignment),
+                0,
+                0,
+                ConfigSourceType::Environment,
+            ),
+            ConfigSource::KubeContext { .. } => {
+                (source.root_dir(), 0, 0, ConfigSourceType::KubeContext)
+            }
+        };
+        Some(ConfigState {
+            path,
+            mtime,
+            source_type,
+            dimmed,
+            depth,
+            tab: None,
+            background: None,
+            config_mtime,
+            content_hash,
+            tty: None,
+            last_error: None,
+        })
+    }
+
+    /// Whether this state was written from a different terminal than `tty`.
+    /// Unknown ttys on either side (no terminal, older state files) never conflict.
+    pub fn is_from_other_tty(&self, tty: Option<&str>) -> bool {
+        matches!((self.tty.as_deref(), tty), (Some(recorded), Some(current)) if recorded != current)
+    }
+
+    /// The directory the colors were applied for: the `.termtint` file's directory,
+    /// the directory holding `.vscode/settings.json`, or the matched trigger
+    /// directory (the variable assignment for environment triggers).
+    pub fn root_dir(&self) -> PathBuf {
+        match self.source_type {
+            ConfigSourceType::Explicit => self
+                .path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| self.path.clone()),
+            ConfigSourceType::VsCode => self
+                .path
+                .parent()
+                .and_then(Path::parent)
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| self.path.clone()),
+            ConfigSourceType::TriggerPath
+            | ConfigSourceType::TriggerFile
+            | ConfigSourceType::TriggerDirname
+            | ConfigSourceType::Environment
+            | ConfigSourceType::KubeContext => self.path.clone(),
+        }
+    }
+
+    /// Fold the modification time of a file the colors also depend on (an
+    /// `.itermcolors` preset) into the content hash, so editing it re-applies the
+    /// way editing the `.termtint` does.
+    pub fn with_dependency(mut self, path: &Path) -> ConfigState {
+        if self.content_hash != 0 {
+            let mut hasher = DefaultHasher::new();
+            self.content_hash.hash(&mut hasher);
+            get_file_mtime(path).hash(&mut hasher);
+            self.content_hash = hasher.finish().max(1);
+        }
+        self
+    }
+
+    /// The recorded tab and background colors, if both were recorded.
+    pub fn applied_colors(&self) -> Option<(RGB, RGB)> {
+        Some((self.tab?, self.background?))
+    }
+
+    /// Whether applying this state's colors would leave the terminal as `last` left
+    /// it, e.g. moving from a nested override back to a parent with the same colors.
+    /// A changed user config never counts, since it may change what gets emitted.
+    pub fn shows_same_colors(&self, last: &ConfigState) -> bool {
+        self.config_mtime == last.config_mtime
+            && self.applied_colors().is_some()
+            && self.applied_colors() == last.applied_colors()
+    }
+}
+
+/// Read the last config state for the current session from disk, if any.
+pub fn read_last_config_state(ctx: &Context) -> Option<ConfigState> {
+    let _span = tracing::debug_span!("state").entered();
+    if !state_writable(ctx) {
+        tracing::debug!(root = %state_root(ctx).display(), "state directory not writable");
+        return None;
+    }
+    let root = state_root(ctx);
+    let legacy_path = state_file_path_in(&root);
+    let state = match session_id(ctx) {
+        Some(session) => read_session_state(
+            &session_state_path_in(&root, &session),
+            &legacy_path,
+            tty_identity().as_deref(),
+        ),
+        None => read_last_config_state_from(&legacy_path),
+    };
+    tracing::debug!(found = state.is_some(), "read state");
+    state
+}
+
+/// Read the current session's state without changing anything on disk, for
+/// `termtint state`: no directory is created, and state still in the legacy shared
+/// file is read where it is rather than moved. Returns the state and its file.
+pub fn peek_last_config_state(ctx: &Context) -> Option<(ConfigState, PathBuf)> {
+    peek_session_state(
+        &state_root(ctx),
+        session_id(ctx).as_deref(),
+        tty_identity().as_deref(),
+    )
+}
+
+fn peek_session_state(
+    root: &Path,
+    session: Option<&str>,
+    tty: Option<&str>,
+) -> Option<(ConfigState, PathBuf)> {
+    let legacy_path = state_file_path_in(root);
+    if let Some(session) = session {
+        let session_path = session_state_path_in(root, session);
+        if session_path.exists() {
+            let state = read_last_config_state_from(&session_path)?;
+            return Some((state, session_path));
+        }
+        // Not yet moved into the session by an apply (see `read_session_state`)
+        let legacy = read_last_config_state_from(&legacy_path)?;
+        return (!legacy.is_from_other_tty(tty)).then_some((legacy, legacy_path));
+    }
+    let state = read_last_config_state_from(&legacy_path)?;
+    Some((state, legacy_path))
+}
+
+/// Read a session's state, first settling the legacy shared state file: it is moved
+/// into the session if the session has no state of its own yet, and removed as an
+/// orphan once it does. Legacy state written by another terminal is left alone,
+/// since a terminal without a session id still uses it.
+fn read_session_state(
+    session_path: &Path,
+    legacy_path: &Path,
+    tty: Option<&str>,
+) -> Option<ConfigState> {
+    if legacy_path.exists() {
+        let ours = read_last_config_state_from(legacy_path)
+            .is_none_or(|legacy| !legacy.is_from_other_tty(tty));
+        if ours && session_path.exists() {
+            let _ = fs::remove_file(legacy_path);
+        } else if ours {
+            if let Some(parent) = session_path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            let _ = fs::rename(legacy_path, session_path);
+        }
+    }
+    read_last_config_state_from(session_path)
+}
+
+/// Marks a path written as the hex of its raw bytes by `encode_path`.
+const PATH_BYTES_PREFIX: &str = "bytes:";
+
+/// Write a path as a field of the line- and tab-based state and cache files.
+/// Paths that are UTF-8 without control characters or surrounding whitespace are
+/// written as they are; any other path (a directory name with invalid UTF-8, say)
+/// is written as `bytes:` and the hex of its raw bytes, so it reads back exactly
+/// rather than as a lossy string that never matches the real path again.
+pub fn encode_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    match path.to_str() {
+        Some(text)
+            if !text.chars().any(char::is_control)
+                && text.trim() == text
+                && !text.starts_with(PATH_BYTES_PREFIX) =>
+        {
+            text.to_string()
+        }
+        _ => {
+            let hex: String = path
+                .as_os_str()
+                .as_bytes()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            format!("{}{}", PATH_BYTES_PREFIX, hex)
+        }
+    }
 }
 
-/// Read the last config state from disk, if any.
-pub fn read_last_config_state() -> Option<ConfigState> {
-    read_last_config_state_from(&state_file_path())
+/// Read a path written by `encode_path`.
+pub fn decode_path(field: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    let bytes = field.strip_prefix(PATH_BYTES_PREFIX).and_then(|hex| {
+        (hex.len() % 2 == 0)
+            .then(|| {
+                (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+                    .collect::<Option<Vec<u8>>>()
+            })
+            .flatten()
+    });
+    match bytes {
+        Some(bytes) => PathBuf::from(std::ffi::OsString::from_vec(bytes)),
+        None => PathBuf::from(field),
+    }
 }
 
 /// Read the last config state from a specific file path.
 fn read_last_config_state_from(state_path: &Path) -> Option<ConfigState> {
     let content = fs::read_to_string(state_path).ok()?;
     let mut lines = content.lines();
-    let path = PathBuf::from(lines.next()?.trim());
+    let path = decode_path(lines.next()?.trim());
     let mtime = lines.next()?.trim().parse().ok()?;
     if path.as_os_str().is_empty() {
         return None;
@@ -92,38 +843,184 @@ fn read_last_config_state_from(state_path: &Path) -> Option<ConfigState> {
         .next()
         .and_then(|line| match line.trim() {
             "Explicit" => Some(ConfigSourceType::Explicit),
+            "VsCode" => Some(ConfigSourceType::VsCode),
             "TriggerPath" | "PathGlob" => Some(ConfigSourceType::TriggerPath),
             "TriggerFile" | "Triggered" => Some(ConfigSourceType::TriggerFile),
+            "TriggerDirname" => Some(ConfigSourceType::TriggerDirname),
+            "Environment" => Some(ConfigSourceType::Environment),
+            "KubeContext" => Some(ConfigSourceType::KubeContext),
             _ => None,
         })
         .unwrap_or(ConfigSourceType::Explicit);
+
+    // Optional key=value fields follow the positional lines
+    let mut dimmed = false;
+    let mut depth = 0;
+    let mut tab = None;
+    let mut background = None;
+    let mut config_mtime = 0;
+    let mut content_hash = 0;
+    let mut tty = None;
+    let mut last_error = None;
+    for line in lines {
+        if let Some((key, value)) = line.split_once('=') {
+            match key.trim() {
+                "dimmed" => dimmed = value.trim() == "true",
+                "depth" => depth = value.trim().parse().unwrap_or(0),
+                "tab" => tab = parse_color(value.trim()).ok(),
+                "background" => background = parse_color(value.trim()).ok(),
+                "config_mtime" => config_mtime = value.trim().parse().unwrap_or(0),
+                "content_hash" => content_hash = value.trim().parse().unwrap_or(0),
+                "tty" => tty = Some(value.trim().to_string()).filter(|t| !t.is_empty()),
+                "error" => last_error = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+
     Some(ConfigState {
         path,
         mtime,
         source_type,
+        dimmed,
+        depth,
+        tab,
+        background,
+        config_mtime,
+        content_hash,
+        tty,
+        last_error,
     })
 }
 
+/// A time as seconds since the epoch.
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Time elapsed since a timestamp (seconds since the epoch), tolerating clock
+/// skew: timestamps in the future count as just written, and implausibly large
+/// ages are clamped to MAX_PLAUSIBLE_AGE.
+fn age_since(timestamp: u64, now: SystemTime) -> Duration {
+    Duration::from_secs(unix_time(now).saturating_sub(timestamp)).min(MAX_PLAUSIBLE_AGE)
+}
+
+/// When a state file was last written, as seconds since the epoch. Prefers the
+/// `updated_at` stamp inside the file, taken from the writer's own clock, over
+/// the file mtime, which a network filesystem may set from the server's clock.
+pub fn state_updated_at(state_path: &Path) -> Option<u64> {
+    let stored = fs::read_to_string(state_path).ok().and_then(|content| {
+        content
+            .lines()
+            .skip(3)
+            .find_map(|line| line.trim().strip_prefix("updated_at="))
+            .and_then(|value| value.trim().parse().ok())
+    });
+    stored.or_else(|| get_file_mtime(state_path))
+}
+
 /// Get the modification time of a file as seconds since epoch.
 pub fn get_file_mtime(path: &std::path::Path) -> Option<u64> {
     fs::metadata(path)
         .ok()?
         .modified()
         .ok()?
-        .duration_since(std::time::UNIX_EPOCH)
+        .duration_since(UNIX_EPOCH)
         .ok()
         .map(|d| d.as_secs())
 }
 
+/// Describe where a state's colors came from, e.g. ".termtint" or "trigger file Cargo.toml".
+fn describe_source(state: &ConfigState, user_config: &UserConfig) -> String {
+    match state.source_type {
+        ConfigSourceType::Explicit => ".termtint".to_string(),
+        ConfigSourceType::VsCode => "vscode settings".to_string(),
+        ConfigSourceType::TriggerPath => "trigger path".to_string(),
+        ConfigSourceType::TriggerFile => match matched_trigger_name(&state.path, user_config) {
+            Some(name) => format!("trigger file {}", name),
+            None => "trigger file".to_string(),
+        },
+        ConfigSourceType::TriggerDirname => "directory name trigger".to_string(),
+        ConfigSourceType::Environment => format!("environment {}", state.path.display()),
+        ConfigSourceType::KubeContext => {
+            let path = state.path.to_string_lossy();
+            let context = path.strip_prefix("kube-context=").unwrap_or(&path);
+            format!("kube context {}", context)
+        }
+    }
+}
+
+/// Explain why `current` differs from the previously applied `last`, for verbose
+/// `apply` output. Returns None when nothing notable about the source changed.
+pub fn describe_transition(
+    last: &ConfigState,
+    current: &ConfigState,
+    user_config: &UserConfig,
+) -> Option<String> {
+    if last.source_type != current.source_type {
+        return Some(format!(
+            "switched from {} to {}",
+            describe_source(last, user_config),
+            describe_source(current, user_config)
+        ));
+    }
+    let global_source = matches!(
+        current.source_type,
+        ConfigSourceType::Environment | ConfigSourceType::KubeContext
+    );
+    if last.path != current.path && global_source {
+        return Some(format!(
+            "{} changed to {}",
+            last.path.display(),
+            current.path.display()
+        ));
+    }
+    if last.path != current.path {
+        return Some(format!(
+            "switched to {} in {}",
+            describe_source(current, user_config),
+            current.root_dir().display()
+        ));
+    }
+    let content_changed = if last.content_hash != 0 && current.content_hash != 0 {
+        last.content_hash != current.content_hash
+    } else {
+        last.mtime != current.mtime
+    };
+    if content_changed {
+        return Some(format!("{} changed", current.path.display()));
+    }
+    if last.config_mtime != current.config_mtime {
+        return Some("user config changed".to_string());
+    }
+    None
+}
+
+/// Hash a file's contents. Never returns 0, which marks "no content tracked".
+fn file_content_hash(path: &Path) -> Option<u64> {
+    let content = fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    Some(hasher.finish().max(1))
+}
+
 /// Write the current config state to disk.
 /// Pass None to clear the state (when leaving a termtint project).
-pub fn write_last_config_state(state: Option<&ConfigState>) {
-    write_last_config_state_to(&state_file_path(), state);
+pub fn write_last_config_state(ctx: &Context, state: Option<&ConfigState>) {
+    let _span = tracing::debug_span!("state").entered();
+    if !state_writable(ctx) {
+        return;
+    }
+    let path = state_file_path(ctx);
+    tracing::debug!(path = %path.display(), cleared = state.is_none(), "writing state");
+    write_last_config_state_to(&path, state, ctx.now);
 }
 
 /// Write the current config state to a specific file path.
 /// Pass None to clear the state (when leaving a termtint project).
-fn write_last_config_state_to(state_path: &Path, state: Option<&ConfigState>) {
+fn write_last_config_state_to(state_path: &Path, state: Option<&ConfigState>, now: SystemTime) {
     // Ensure parent directory exists
     if let Some(parent) = state_path.parent() {
         let _ = fs::create_dir_all(parent);
@@ -133,16 +1030,42 @@ fn write_last_config_state_to(state_path: &Path, state: Option<&ConfigState>) {
         Some(s) => {
             let source_type_str = match s.source_type {
                 ConfigSourceType::Explicit => "Explicit",
+                ConfigSourceType::VsCode => "VsCode",
                 ConfigSourceType::TriggerPath => "TriggerPath",
                 ConfigSourceType::TriggerFile => "TriggerFile",
+                ConfigSourceType::TriggerDirname => "TriggerDirname",
+                ConfigSourceType::Environment => "Environment",
+                ConfigSourceType::KubeContext => "KubeContext",
             };
-            let content = format!(
-                "{}\n{}\n{}",
-                s.path.to_string_lossy(),
-                s.mtime,
-                source_type_str
-            );
-            let _ = fs::write(state_path, content.as_bytes());
+            let mut content = format!("{}\n{}\n{}", encode_path(&s.path), s.mtime, source_type_str);
+            content.push_str(&format!("\nupdated_at={}", unix_time(now)));
+            if s.dimmed {
+                content.push_str("\ndimmed=true");
+            }
+            if s.depth > 0 {
+                content.push_str(&format!("\ndepth={}", s.depth));
+            }
+            if s.config_mtime > 0 {
+                content.push_str(&format!("\nconfig_mtime={}", s.config_mtime));
+            }
+            if s.content_hash != 0 {
+                content.push_str(&format!("\ncontent_hash={}", s.content_hash));
+            }
+            if let Some(tty) = &s.tty {
+                content.push_str(&format!("\ntty={}", tty));
+            }
+            if let Some(error) = &s.last_error {
+                // Keep the record line-based: collapse multi-line messages
+                let error: Vec<&str> = error.split_whitespace().collect();
+                content.push_str(&format!("\nerror={}", error.join(" ")));
+            }
+            if let Some(tab) = s.tab {
+                content.push_str(&format!("\ntab={}", tab));
+            }
+            if let Some(background) = s.background {
+                content.push_str(&format!("\nbackground={}", background));
+            }
+            let _ = write_atomic_synced(state_path, content.as_bytes());
         }
         None => {
             let _ = fs::remove_file(state_path);
@@ -150,247 +1073,1980 @@ fn write_last_config_state_to(state_path: &Path, state: Option<&ConfigState>) {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use tempfile::TempDir;
+/// How long `apply` waits for another process's state lock before skipping.
+pub const STATE_LOCK_TIMEOUT: Duration = Duration::from_millis(200);
 
-    #[test]
-    fn test_config_state_equality() {
-        let state1 = ConfigState {
-            path: PathBuf::from("/test/path"),
-            mtime: 12345,
-            source_type: ConfigSourceType::Explicit,
+/// An advisory lock on the current session's state, held until dropped.
+/// Serializes the read-compare-write sequence of concurrent `apply` runs.
+pub struct StateLock {
+    _file: fs::File,
+}
+
+/// Lock the current session's state, waiting up to `timeout`.
+/// Returns None if another process holds the lock, or the error creating the
+/// lock file when there's none to take (e.g. the session's directory isn't
+/// writable).
+pub fn lock_state(ctx: &Context, timeout: Duration) -> io::Result<Option<StateLock>> {
+    let _span = tracing::debug_span!("state").entered();
+    let lock = lock_state_at(&state_file_path(ctx), timeout);
+    match &lock {
+        Ok(lock) => tracing::debug!(acquired = lock.is_some(), "locking state"),
+        Err(e) => tracing::debug!(error = %e, "can't create the state lock file"),
+    }
+    lock
+}
+
+/// Lock the state file at `state_path` through a `.lock` file next to it.
+fn lock_state_at(state_path: &Path, timeout: Duration) -> io::Result<Option<StateLock>> {
+    if let Some(parent) = state_path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let mut lock_path = state_path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(PathBuf::from(lock_path))?;
+
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        // SAFETY: flock only reads the descriptor, which `file` keeps open for the
+        // duration of the call; the lock is released when the StateLock drops it.
+        let locked = unsafe {
+            use std::os::unix::io::AsRawFd;
+            libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) == 0
         };
+        if locked {
+            return Ok(Some(StateLock { _file: file }));
+        }
+        if std::time::Instant::now() >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
 
-        let state2 = ConfigState {
-            path: PathBuf::from("/test/path"),
-            mtime: 12345,
-            source_type: ConfigSourceType::Explicit,
+/// Why termtint last reset the terminal's colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResetReason {
+    /// `termtint reset`
+    Command,
+    /// `apply` left a directory whose colors had been applied
+    LeftProject,
+    /// `apply` found no config and had no applied state
+    NoConfig,
+}
+
+impl ResetReason {
+    /// Stable identifier used in the reset record and JSON output.
+    pub fn key(self) -> &'static str {
+        match self {
+            ResetReason::Command => "command",
+            ResetReason::LeftProject => "left-project",
+            ResetReason::NoConfig => "no-config",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<ResetReason> {
+        match key {
+            "command" => Some(ResetReason::Command),
+            "left-project" => Some(ResetReason::LeftProject),
+            "no-config" => Some(ResetReason::NoConfig),
+            _ => None,
+        }
+    }
+
+    /// Human-readable description, e.g. "left project".
+    pub fn description(self) -> &'static str {
+        match self {
+            ResetReason::Command => "explicit reset command",
+            ResetReason::LeftProject => "left project",
+            ResetReason::NoConfig => "no config found",
+        }
+    }
+}
+
+/// When and why the colors were last reset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResetRecord {
+    /// Seconds since the Unix epoch
+    pub at: u64,
+    pub reason: ResetReason,
+}
+
+impl ResetRecord {
+    /// Time elapsed between the reset and `now`, if the clock allows computing it.
+    pub fn age(&self, now: SystemTime) -> Option<Duration> {
+        let at = UNIX_EPOCH + Duration::from_secs(self.at);
+        now.duration_since(at).ok()
+    }
+}
+
+/// The reset record lives next to the state file, since resets clear the state.
+fn reset_record_path_for(state_path: &Path) -> PathBuf {
+    state_path.with_file_name("last_reset")
+}
+
+/// Remember that colors were just reset, for the current session.
+pub fn record_reset(ctx: &Context, reason: ResetReason) {
+    if !state_writable(ctx) {
+        return;
+    }
+    record_reset_to(
+        &reset_record_path_for(&state_file_path(ctx)),
+        reason,
+        ctx.now,
+    );
+}
+
+/// Remember a reset that found no config, unless the session already has a reset
+/// recorded. Prompts outside a project reset on every run, and each would
+/// otherwise replace the record of what cleared the colors (leaving a project, or
+/// `termtint reset`) and write state on the hot path.
+pub fn record_no_config_reset(ctx: &Context) {
+    if !state_writable(ctx) {
+        return;
+    }
+    record_no_config_reset_to(&reset_record_path_for(&state_file_path(ctx)), ctx.now);
+}
+
+fn record_no_config_reset_to(path: &Path, now: SystemTime) {
+    if read_last_reset_from(path).is_none() {
+        record_reset_to(path, ResetReason::NoConfig, now);
+    }
+}
+
+fn record_reset_to(path: &Path, reason: ResetReason, now: SystemTime) {
+    let at = unix_time(now);
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let _ = write_atomic(path, format!("{}\n{}", at, reason.key()).as_bytes());
+}
+
+/// Read the current session's last reset, if one was recorded.
+pub fn read_last_reset(ctx: &Context) -> Option<ResetRecord> {
+    read_last_reset_from(&reset_record_path_for(&state_file_path(ctx)))
+}
+
+fn read_last_reset_from(path: &Path) -> Option<ResetRecord> {
+    let content = fs::read_to_string(path).ok()?;
+    let mut lines = content.lines();
+    let at = lines.next()?.trim().parse().ok()?;
+    let reason = ResetReason::from_key(lines.next()?.trim())?;
+    Some(ResetRecord { at, reason })
+}
+
+/// The user config warnings already shown to a session, next to its state file.
+fn config_warnings_path_for(state_path: &Path) -> PathBuf {
+    state_path.with_file_name("config_warnings")
+}
+
+/// Record the user config's current warnings for the current session, returning
+/// whether they're news to it. The prompt hook shows them only then, so a broken
+/// config is reported once per session rather than at every prompt. Without
+/// writable state nothing is tracked and every warning is news.
+pub fn config_warnings_are_new(ctx: &Context, warnings: &[String]) -> bool {
+    if !state_writable(ctx) {
+        return !warnings.is_empty();
+    }
+    warnings_are_new_at(&config_warnings_path_for(&state_file_path(ctx)), warnings)
+}
+
+/// Record that the current session was warned that its state can't be kept,
+/// returning whether that's news to it, so the warning is shown once per session.
+/// The state directory can't hold the record, so it goes in `runtime_dir` (see
+/// `daemon::runtime_dir`), when that is private to this user. Without a session
+/// or a usable runtime directory the warning is always news.
+pub fn unwritable_warning_is_new(ctx: &Context, warning: &str, runtime_dir: &Path) -> bool {
+    use std::os::unix::fs::DirBuilderExt;
+    let Some(session) = session_id(ctx) else {
+        return true;
+    };
+    let private = fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(runtime_dir)
+        .and_then(|()| crate::daemon::check_private_dir(runtime_dir));
+    if private.is_err() {
+        return true;
+    }
+    let path = runtime_dir.join("unwritable_state").join(session);
+    warnings_are_new_at(&path, &[warning.to_string()])
+}
+
+/// Record `warnings` as the ones shown at `path`, returning whether they differ
+/// from those recorded before. No warnings clears the record.
+fn warnings_are_new_at(path: &Path, warnings: &[String]) -> bool {
+    if warnings.is_empty() {
+        // Forget them once fixed, so breaking the config again is reported again
+        let _ = fs::remove_file(path);
+        return false;
+    }
+    // One warning per line, like the state file's error field
+    let content: String = warnings
+        .iter()
+        .map(|warning| {
+            format!(
+                "{}\n",
+                warning.split_whitespace().collect::<Vec<_>>().join(" ")
+            )
+        })
+        .collect();
+    if fs::read_to_string(path).is_ok_and(|shown| shown == content) {
+        return false;
+    }
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let _ = write_atomic(path, content.as_bytes());
+    true
+}
+
+/// The hues recently rolled in a session, next to its state file.
+#[cfg(feature = "reroll")]
+fn recent_hues_path_for(state_path: &Path) -> PathBuf {
+    state_path.with_file_name("recent_hues")
+}
+
+/// Get the path to the current session's recent hues file, so rerolls in one
+/// tab spread away from that tab's earlier rolls.
+#[cfg(feature = "reroll")]
+fn recent_hues_path(ctx: &Context) -> PathBuf {
+    recent_hues_path_for(&state_file_path(ctx))
+}
+
+/// Read the most recently generated random hues, oldest first.
+#[cfg(feature = "reroll")]
+pub fn read_recent_hues(ctx: &Context) -> Vec<f32> {
+    read_recent_hues_from(&recent_hues_path(ctx))
+}
+
+/// Read recent hues from a specific file path, skipping malformed lines.
+#[cfg(feature = "reroll")]
+fn read_recent_hues_from(path: &Path) -> Vec<f32> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect()
+}
+
+/// Remember a generated random hue, keeping only the last RECENT_HUES_LIMIT entries.
+#[cfg(feature = "reroll")]
+pub fn record_recent_hue(ctx: &Context, hue: f32) {
+    record_recent_hue_to(&recent_hues_path(ctx), hue);
+}
+
+/// Append a hue to a specific recent hues file, trimming old entries.
+#[cfg(feature = "reroll")]
+fn record_recent_hue_to(path: &Path, hue: f32) {
+    let mut hues = read_recent_hues_from(path);
+    hues.push(hue);
+    let skip = hues.len().saturating_sub(RECENT_HUES_LIMIT);
+
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let content: String = hues[skip..].iter().map(|h| format!("{}\n", h)).collect();
+    let _ = write_atomic(path, content.as_bytes());
+}
+
+/// Write a file by writing a temporary file in the same directory and renaming it
+/// over the target, so readers never see a partially written file even if the
+/// process dies or another `apply` writes concurrently.
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    write_via_temp(path, content, false)
+}
+
+/// Like `write_atomic`, but fsyncs the temporary file before the rename so the
+/// new content survives a system crash, not just a killed process. Used for the
+/// state record and exports; caches and markers are cheap to rebuild and skip it.
+pub fn write_atomic_synced(path: &Path, content: &[u8]) -> io::Result<()> {
+    write_via_temp(path, content, true)
+}
+
+fn write_via_temp(path: &Path, content: &[u8], sync: bool) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    let result = fs::File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            if sync {
+                file.sync_all()?;
+            }
+            Ok(())
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::find_config_source;
+    use crate::user_config::{Trigger, TriggerKind};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_config_state_equality() {
+        let state1 = ConfigState {
+            path: PathBuf::from("/test/path"),
+            mtime: 12345,
+            source_type: ConfigSourceType::Explicit,
+            ..Default::default()
+        };
+
+        let state2 = ConfigState {
+            path: PathBuf::from("/test/path"),
+            mtime: 12345,
+            source_type: ConfigSourceType::Explicit,
+            ..Default::default()
+        };
+
+        assert_eq!(state1, state2);
+    }
+
+    #[test]
+    fn test_config_state_different_source_type() {
+        let state1 = ConfigState {
+            path: PathBuf::from("/test/path"),
+            mtime: 12345,
+            source_type: ConfigSourceType::Explicit,
+            ..Default::default()
+        };
+
+        let state2 = ConfigState {
+            path: PathBuf::from("/test/path"),
+            mtime: 12345,
+            source_type: ConfigSourceType::TriggerFile,
+            ..Default::default()
+        };
+
+        assert_ne!(state1, state2);
+    }
+
+    #[test]
+    fn test_write_and_read_state_explicit() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+
+        let state = ConfigState {
+            path: PathBuf::from("/test/config/.termtint"),
+            mtime: 67890,
+            source_type: ConfigSourceType::Explicit,
+            ..Default::default()
+        };
+
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+        let read_state = read_last_config_state_from(&state_path);
+
+        assert_eq!(read_state, Some(state));
+    }
+
+    #[test]
+    fn test_write_and_read_state_triggered() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+
+        let state = ConfigState {
+            path: PathBuf::from("/test/project"),
+            mtime: 12345,
+            source_type: ConfigSourceType::TriggerFile,
+            ..Default::default()
+        };
+
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+        let read_state = read_last_config_state_from(&state_path);
+
+        assert_eq!(read_state, Some(state));
+    }
+
+    #[test]
+    fn test_vscode_state() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+        let settings = temp
+            .path()
+            .join("app")
+            .join(".vscode")
+            .join("settings.json");
+        fs::create_dir_all(settings.parent().unwrap()).unwrap();
+        fs::write(&settings, r##"{"peacock.color": "#3478f6"}"##).unwrap();
+
+        let state =
+            ConfigState::for_source(&ConfigSource::VsCode(settings.clone()), false, 0, 0).unwrap();
+        assert_eq!(state.source_type, ConfigSourceType::VsCode);
+        assert_eq!(state.mtime, get_file_mtime(&settings).unwrap());
+        assert_ne!(state.content_hash, 0);
+        assert_eq!(state.root_dir(), temp.path().join("app"));
+
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+        assert_eq!(
+            read_last_config_state_from(&state_path),
+            Some(state.clone())
+        );
+
+        // Changing the color changes the recorded state
+        fs::write(&settings, r##"{"peacock.color": "#ff5500"}"##).unwrap();
+        let changed =
+            ConfigState::for_source(&ConfigSource::VsCode(settings), false, 0, 0).unwrap();
+        assert!(!changed.matches(&state));
+    }
+
+    #[test]
+    fn test_environment_state() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+        let source = |assignment: &str| ConfigSource::Environment {
+            rule: "AWS_PROFILE=prod*".to_string(),
+            assignment: assignment.to_string(),
+        };
+
+        let state = ConfigState::for_source(&source("AWS_PROFILE=prod-eu"), false, 0, 0).unwrap();
+        assert_eq!(state.source_type, ConfigSourceType::Environment);
+        assert_eq!(state.root_dir(), PathBuf::from("AWS_PROFILE=prod-eu"));
+
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+        let read_state = read_last_config_state_from(&state_path).unwrap();
+        assert_eq!(read_state, state);
+
+        // A new value for the variable is a different state
+        let changed = ConfigState::for_source(&source("AWS_PROFILE=prod-us"), false, 0, 0).unwrap();
+        assert!(!changed.matches(&read_state));
+        assert_eq!(
+            describe_transition(&read_state, &changed, &UserConfig::default()).as_deref(),
+            Some("AWS_PROFILE=prod-eu changed to AWS_PROFILE=prod-us")
+        );
+    }
+
+    #[test]
+    fn test_kube_context_state() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+        let source = |context: &str| ConfigSource::KubeContext {
+            pattern: "*prod*".to_string(),
+            context: context.to_string(),
+        };
+
+        let state = ConfigState::for_source(&source("prod-eu"), false, 0, 0).unwrap();
+        assert_eq!(state.source_type, ConfigSourceType::KubeContext);
+        assert_eq!(state.root_dir(), PathBuf::from("kube-context=prod-eu"));
+
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+        let read_state = read_last_config_state_from(&state_path).unwrap();
+        assert_eq!(read_state, state);
+
+        // Switching contexts is a different state, so the next prompt re-applies
+        let changed = ConfigState::for_source(&source("prod-us"), false, 0, 0).unwrap();
+        assert!(!changed.matches(&read_state));
+        assert_eq!(
+            describe_transition(&read_state, &changed, &UserConfig::default()).as_deref(),
+            Some("kube-context=prod-eu changed to kube-context=prod-us")
+        );
+        let explicit = ConfigState {
+            path: PathBuf::from("/work/.termtint"),
+            source_type: ConfigSourceType::Explicit,
+            ..state.clone()
+        };
+        assert_eq!(
+            describe_transition(&explicit, &state, &UserConfig::default()).as_deref(),
+            Some("switched from .termtint to kube context prod-eu")
+        );
+    }
+
+    #[test]
+    fn test_write_and_clear_state() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+
+        let state = ConfigState {
+            path: PathBuf::from("/test/path"),
+            mtime: 12345,
+            source_type: ConfigSourceType::Explicit,
+            ..Default::default()
+        };
+
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+        assert!(read_last_config_state_from(&state_path).is_some());
+
+        write_last_config_state_to(&state_path, None, SystemTime::now());
+        assert_eq!(read_last_config_state_from(&state_path), None);
+    }
+
+    #[test]
+    fn test_read_nonexistent_state() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+
+        let result = read_last_config_state_from(&state_path);
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_backwards_compatibility_missing_source_type() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+
+        // Create parent directory and write old format (without source_type)
+        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+        fs::write(&state_path, "/test/path\n12345").unwrap();
+
+        let state = read_last_config_state_from(&state_path).unwrap();
+
+        // Should default to Explicit
+        assert_eq!(state.path, PathBuf::from("/test/path"));
+        assert_eq!(state.mtime, 12345);
+        assert_eq!(state.source_type, ConfigSourceType::Explicit);
+    }
+
+    #[test]
+    fn test_read_malformed_state() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+
+        // Create parent directory and write malformed content
+        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+        fs::write(&state_path, "invalid").unwrap();
+
+        let result = read_last_config_state_from(&state_path);
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_read_state_empty_path() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+
+        // Create parent directory and write state with empty path
+        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+        fs::write(&state_path, "\n12345\nExplicit").unwrap();
+
+        let result = read_last_config_state_from(&state_path);
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_get_file_mtime_exists() {
+        let temp = TempDir::new().unwrap();
+        let test_file = temp.path().join("test.txt");
+        fs::write(&test_file, "content").unwrap();
+
+        let mtime = get_file_mtime(&test_file);
+        assert!(mtime.is_some());
+        assert!(mtime.unwrap() > 0);
+    }
+
+    #[test]
+    fn test_get_file_mtime_nonexistent() {
+        let temp = TempDir::new().unwrap();
+        let test_file = temp.path().join("nonexistent.txt");
+
+        let mtime = get_file_mtime(&test_file);
+        assert_eq!(mtime, None);
+    }
+
+    #[test]
+    fn test_default_state_paths() {
+        let temp = TempDir::new().unwrap();
+        let root = default_state_root(temp.path());
+
+        assert_eq!(root, temp.path().join(".cache").join("termtint"));
+        assert_eq!(state_file_path_in(&root), root.join("last_config"));
+        assert_eq!(
+            session_state_path_in(&root, "a"),
+            root.join("sessions").join("a").join("last_config")
+        );
+    }
+
+    #[test]
+    fn test_resolve_state_root() {
+        let home = Path::new("/home/user");
+        let some = |s: &str| Some(s.to_string());
+
+        assert_eq!(
+            resolve_state_root(None, || None, home),
+            PathBuf::from("/home/user/.cache/termtint")
+        );
+        assert_eq!(
+            resolve_state_root(None, || some("/tmp/tt"), home),
+            PathBuf::from("/tmp/tt")
+        );
+        // The environment variable wins over the config key, which isn't even read
+        assert_eq!(
+            resolve_state_root(Some("/run/tt"), || panic!("config read"), home),
+            PathBuf::from("/run/tt")
+        );
+        assert_eq!(
+            resolve_state_root(None, || some("~/state"), home),
+            PathBuf::from("/home/user/state")
+        );
+        // Empty values are ignored
+        assert_eq!(
+            resolve_state_root(Some(""), || some("/tmp/tt"), home),
+            PathBuf::from("/tmp/tt")
+        );
+    }
+
+    #[test]
+    fn test_state_paths_follow_context() {
+        let temp = TempDir::new().unwrap();
+        let ctx = Context::new(temp.path(), temp.path()).with_var("TERMTINT_SESSION", "tab-1");
+        let root = temp.path().join(".cache").join("termtint");
+        assert_eq!(state_root(&ctx), root);
+        assert_eq!(
+            state_file_path(&ctx),
+            root.join("sessions").join("tab-1").join("last_config")
+        );
+
+        let state_dir = temp.path().join("state");
+        let ctx = Context::new(temp.path(), temp.path())
+            .with_var("TERMTINT_STATE_DIR", &state_dir.to_string_lossy())
+            .with_var("ITERM_SESSION_ID", "w0t1p0:ABC")
+            .with_var("TERMTINT_DISABLE", "1");
+        assert_eq!(
+            state_file_path(&ctx),
+            state_dir
+                .join("sessions")
+                .join("w0t1p0_ABC")
+                .join("last_config")
+        );
+        assert!(disabled_from_env(&ctx));
+        assert!(!no_state_from_env(&ctx));
+    }
+
+    #[test]
+    fn test_unwritable_state_dir() {
+        let temp = TempDir::new().unwrap();
+        assert!(is_writable_dir(&temp.path().join("state")));
+        assert!(temp.path().join("state").is_dir());
+
+        // A directory that can't be created (its parent is a file), as with a
+        // read-only HOME
+        let blocker = temp.path().join("file");
+        fs::write(&blocker, "").unwrap();
+        assert!(!is_writable_dir(&blocker.join("state")));
+    }
+
+    #[test]
+    fn test_cleanup_stale_sessions() {
+        let temp = TempDir::new().unwrap();
+        let sessions_dir = sessions_dir_in(temp.path());
+
+        // Create fresh session (should not be deleted)
+        let fresh_session = sessions_dir.join("session1");
+        fs::create_dir_all(&fresh_session).unwrap();
+        fs::write(fresh_session.join("last_config"), "test").unwrap();
+
+        // Create stale session (should be deleted)
+        let stale_session = sessions_dir.join("session2");
+        fs::create_dir_all(&stale_session).unwrap();
+        let stale_file = stale_session.join("last_config");
+        fs::write(&stale_file, "test").unwrap();
+
+        // Set mtime to > 24 hours ago
+        let old_time = SystemTime::now() - Duration::from_secs(25 * 60 * 60);
+        let file_time = filetime::FileTime::from_system_time(old_time);
+        filetime::set_file_mtime(&stale_file, file_time).unwrap();
+
+        // Verify the mtime was actually set
+        let metadata = fs::metadata(&stale_file).unwrap();
+        let modified = metadata.modified().unwrap();
+        let age = SystemTime::now().duration_since(modified).unwrap();
+        assert!(
+            age > Duration::from_secs(24 * 60 * 60),
+            "File should be older than 24 hours"
+        );
+
+        cleanup_stale_sessions_in(&sessions_dir, Some(STALE_SESSION_AGE), SystemTime::now());
+
+        // Fresh session should still exist
+        assert!(
+            fresh_session.exists(),
+            "Fresh session should not be deleted"
+        );
+
+        // Stale session should be deleted
+        assert!(!stale_session.exists(), "Stale session should be deleted");
+    }
+
+    #[test]
+    fn test_cleanup_stale_sessions_without_state() {
+        let temp = TempDir::new().unwrap();
+        let sessions_dir = sessions_dir_in(temp.path());
+
+        // A session whose state was cleared leaves an empty directory behind
+        let cleared_session = sessions_dir.join("session1");
+        fs::create_dir_all(&cleared_session).unwrap();
+        let old_time = SystemTime::now() - Duration::from_secs(25 * 60 * 60);
+        filetime::set_file_mtime(
+            &cleared_session,
+            filetime::FileTime::from_system_time(old_time),
+        )
+        .unwrap();
+
+        cleanup_stale_sessions_in(&sessions_dir, Some(STALE_SESSION_AGE), SystemTime::now());
+
+        assert!(!cleared_session.exists());
+    }
+
+    fn applied_state(path: &str) -> ConfigState {
+        ConfigState {
+            path: PathBuf::from(path),
+            source_type: ConfigSourceType::TriggerFile,
+            tab: Some(RGB {
+                r: 0x34,
+                g: 0x78,
+                b: 0xf6,
+            }),
+            background: Some(RGB {
+                r: 0x0b,
+                g: 0x18,
+                b: 0x30,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_write_and_read_state_colors() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+        let state = applied_state("/test/project");
+
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+
+        let content = fs::read_to_string(&state_path).unwrap();
+        assert!(content.contains("tab=#3478f6"));
+        assert!(content.contains("background=#0b1830"));
+        assert_eq!(read_last_config_state_from(&state_path), Some(state));
+    }
+
+    #[test]
+    fn test_read_state_without_colors() {
+        // Files written before colors were recorded have neither field
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+        fs::write(&state_path, "/test/project\n0\nTriggerFile\ntab=#3478f6").unwrap();
+
+        let state = read_last_config_state_from(&state_path).unwrap();
+
+        assert_eq!(state.tab, applied_state("/test/project").tab);
+        assert_eq!(state.background, None);
+    }
+
+    #[test]
+    fn test_user_config_edit_changes_state() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+        let config_path = temp.path().join("config.toml");
+        fs::write(&config_path, "background_lightness = 0.18\n").unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        filetime::set_file_mtime(
+            &config_path,
+            filetime::FileTime::from_system_time(an_hour_ago),
+        )
+        .unwrap();
+
+        let state_for_config = || ConfigState {
+            config_mtime: get_file_mtime(&config_path).unwrap(),
+            ..applied_state("/test/project")
+        };
+        write_last_config_state_to(&state_path, Some(&state_for_config()), SystemTime::now());
+        let last = read_last_config_state_from(&state_path).unwrap();
+        assert!(state_for_config().matches(&last));
+
+        // Touching config.toml must not look "unchanged"
+        fs::write(&config_path, "background_lightness = 0.25\n").unwrap();
+        filetime::set_file_mtime(&config_path, filetime::FileTime::now()).unwrap();
+
+        assert!(!state_for_config().matches(&last));
+    }
+
+    #[test]
+    fn test_write_replaces_truncated_state() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+        // A write interrupted by a crash under the old non-atomic scheme
+        fs::write(&state_path, "/test/proj").unwrap();
+        assert_eq!(read_last_config_state_from(&state_path), None);
+
+        let state = applied_state("/test/project");
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+
+        assert_eq!(read_last_config_state_from(&state_path), Some(state));
+        let leftovers: Vec<_> = fs::read_dir(state_path.parent().unwrap())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn test_leftover_temp_files_are_ignored() {
+        let temp = TempDir::new().unwrap();
+        let sessions_dir = sessions_dir_in(temp.path());
+        let state_path = sessions_dir.join("a").join("last_config");
+        let state = applied_state("/test/project");
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+        // Temp file left by a process killed between write and rename
+        fs::write(
+            state_path.with_file_name(".last_config.4242.tmp"),
+            "/half/writ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_last_config_state_from(&state_path),
+            Some(state.clone())
+        );
+        let sessions = list_sessions_in(&sessions_dir, SystemTime::now());
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].state, SessionState::Applied(state));
+
+        // A later write still succeeds
+        write_last_config_state_to(
+            &state_path,
+            Some(&applied_state("/other")),
+            SystemTime::now(),
+        );
+        assert_eq!(
+            read_last_config_state_from(&state_path).map(|s| s.path),
+            Some(PathBuf::from("/other"))
+        );
+    }
+
+    #[test]
+    fn test_state_lock_times_out_while_held() {
+        let temp = TempDir::new().unwrap();
+        let state_path = session_state_path_in(temp.path(), "a");
+
+        let held = lock_state_at(&state_path, STATE_LOCK_TIMEOUT)
+            .unwrap()
+            .expect("lock should be free");
+        assert!(state_path.with_file_name("last_config.lock").exists());
+
+        let started = std::time::Instant::now();
+        assert!(lock_state_at(&state_path, Duration::from_millis(30))
+            .unwrap()
+            .is_none());
+        assert!(started.elapsed() >= Duration::from_millis(30));
+
+        // Other sessions have their own lock
+        let other = session_state_path_in(temp.path(), "b");
+        assert!(lock_state_at(&other, Duration::ZERO).unwrap().is_some());
+
+        drop(held);
+        assert!(lock_state_at(&state_path, Duration::ZERO)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_state_lock_file_that_cant_be_created() {
+        let temp = TempDir::new().unwrap();
+        // The session's directory can't be created over a regular file
+        fs::write(temp.path().join("sessions"), "").unwrap();
+        let state_path = session_state_path_in(temp.path(), "a");
+
+        assert!(lock_state_at(&state_path, Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_trigger_file_subdirectories_unchanged() {
+        let temp = TempDir::new().unwrap();
+        let project = temp.path().join("project");
+        let nested = project.join("src").join("bin");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(project.join("Cargo.toml"), "").unwrap();
+        let other = temp.path().join("other");
+        fs::create_dir_all(&other).unwrap();
+        fs::write(other.join("Cargo.toml"), "").unwrap();
+        let uc = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
+        let state_at = |dir: &Path| {
+            let source = find_config_source(dir, &uc).expect("trigger should match");
+            ConfigState::for_source(&source, false, 0, 7).unwrap()
+        };
+
+        // The first apply records the state (with its colors)...
+        let applied = ConfigState {
+            tab: Some(RGB { r: 1, g: 2, b: 3 }),
+            background: Some(RGB { r: 0, g: 0, b: 1 }),
+            ..state_at(&project)
+        };
+        assert_eq!(applied.source_type, ConfigSourceType::TriggerFile);
+
+        // ...and cd-ing around inside the project compares as unchanged
+        assert!(state_at(&project.join("src")).matches(&applied));
+        assert!(state_at(&nested).matches(&applied));
+        assert!(!state_at(&other).matches(&applied));
+    }
+
+    #[test]
+    fn test_trigger_path_subdirectories_unchanged() {
+        let temp = TempDir::new().unwrap();
+        let code = temp.path().join("code");
+        fs::create_dir_all(code.join("app").join("lib")).unwrap();
+        fs::create_dir_all(code.join("web")).unwrap();
+        let uc = UserConfig {
+            triggers: vec![Trigger::new(
+                TriggerKind::Path,
+                format!("{}/*", code.display()),
+            )],
+            ..UserConfig::default()
+        };
+        let state_at = |dir: &Path| {
+            let source = find_config_source(dir, &uc).expect("trigger path should match");
+            ConfigState::for_source(&source, false, 0, 0).unwrap()
+        };
+
+        let applied = state_at(&code.join("app"));
+
+        assert_eq!(applied.source_type, ConfigSourceType::TriggerPath);
+        assert!(state_at(&code.join("app").join("lib")).matches(&applied));
+        assert!(!state_at(&code.join("web")).matches(&applied));
+    }
+
+    #[test]
+    fn test_for_source_explicit_tracks_mtime() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join(".termtint");
+        fs::write(&path, "#ff0000").unwrap();
+
+        let state =
+            ConfigState::for_source(&ConfigSource::Termtint(path.clone()), true, 2, 0).unwrap();
+
+        assert_eq!(state.mtime, get_file_mtime(&path).unwrap());
+        assert_eq!(state.source_type, ConfigSourceType::Explicit);
+        assert!(state.dimmed);
+        assert_eq!(state.depth, 2);
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            ConfigState::for_source(&ConfigSource::Termtint(path), false, 0, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_root_dir() {
+        let mut state = ConfigState {
+            path: PathBuf::from("/code/app/.termtint"),
+            mtime: 1,
+            source_type: ConfigSourceType::Explicit,
+            ..Default::default()
+        };
+        assert_eq!(state.root_dir(), PathBuf::from("/code/app"));
+
+        state.path = PathBuf::from("/code/lib");
+        state.source_type = ConfigSourceType::TriggerFile;
+        assert_eq!(state.root_dir(), PathBuf::from("/code/lib"));
+    }
+
+    #[test]
+    fn test_restored_termtint_with_same_mtime_changes_state() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join(".termtint");
+        let source = ConfigSource::Termtint(path.clone());
+        let mtime = filetime::FileTime::from_unix_time(1_700_000_000, 0);
+        fs::write(&path, "#ff0000").unwrap();
+        filetime::set_file_mtime(&path, mtime).unwrap();
+        let applied = ConfigState::for_source(&source, false, 0, 0).unwrap();
+
+        // Deleted, then restored (e.g. by a checkout) with different contents
+        // but the same mtime
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "#00ff00").unwrap();
+        filetime::set_file_mtime(&path, mtime).unwrap();
+        let restored = ConfigState::for_source(&source, false, 0, 0).unwrap();
+
+        assert_eq!(restored.mtime, applied.mtime);
+        assert!(!restored.matches(&applied));
+
+        // Same contents restored again compares unchanged
+        fs::write(&path, "#ff0000").unwrap();
+        filetime::set_file_mtime(&path, mtime).unwrap();
+        assert!(ConfigState::for_source(&source, false, 0, 0)
+            .unwrap()
+            .matches(&applied));
+    }
+
+    #[test]
+    fn test_write_and_read_state_tty() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+        let state = ConfigState {
+            tty: Some("/dev/ttys003".to_string()),
+            ..applied_state("/test/project")
+        };
+
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+
+        assert!(fs::read_to_string(&state_path)
+            .unwrap()
+            .contains("tty=/dev/ttys003"));
+        assert_eq!(read_last_config_state_from(&state_path), Some(state));
+    }
+
+    #[test]
+    fn test_is_from_other_tty() {
+        let state = ConfigState {
+            tty: Some("/dev/ttys003".to_string()),
+            ..applied_state("/test/project")
+        };
+
+        assert!(state.is_from_other_tty(Some("/dev/ttys004")));
+        assert!(!state.is_from_other_tty(Some("/dev/ttys003")));
+        // Without a terminal, or with state from before ttys were recorded,
+        // the state is assumed to be ours
+        assert!(!state.is_from_other_tty(None));
+        assert!(!applied_state("/test/project").is_from_other_tty(Some("/dev/ttys004")));
+        // The tty doesn't affect change detection
+        assert!(state.matches(&applied_state("/test/project")));
+    }
+
+    #[test]
+    fn test_edit_without_mtime_change_detected() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join(".termtint");
+        let source = ConfigSource::Termtint(path.clone());
+        fs::write(&path, "#ff0000").unwrap();
+        let mtime = filetime::FileTime::from_last_modification_time(&fs::metadata(&path).unwrap());
+        let applied = ConfigState::for_source(&source, false, 0, 0).unwrap();
+
+        // Saved again within the same second (or with the mtime set back)
+        fs::write(&path, "#ff0001").unwrap();
+        filetime::set_file_mtime(&path, mtime).unwrap();
+
+        assert!(!ConfigState::for_source(&source, false, 0, 0)
+            .unwrap()
+            .matches(&applied));
+    }
+
+    #[test]
+    fn test_touch_without_content_change_unchanged() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join(".termtint");
+        let source = ConfigSource::Termtint(path.clone());
+        fs::write(&path, "#ff0000").unwrap();
+        filetime::set_file_mtime(&path, filetime::FileTime::from_unix_time(1_700_000_000, 0))
+            .unwrap();
+        let applied = ConfigState::for_source(&source, false, 0, 0).unwrap();
+
+        filetime::set_file_mtime(&path, filetime::FileTime::now()).unwrap();
+        let touched = ConfigState::for_source(&source, false, 0, 0).unwrap();
+
+        assert_ne!(touched.mtime, applied.mtime);
+        assert!(touched.matches(&applied));
+    }
+
+    #[test]
+    fn test_mtime_only_state_still_compared() {
+        // State files written before content hashes fall back to mtime comparison
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+        let path = temp.path().join(".termtint");
+        fs::write(&path, "#ff0000").unwrap();
+        let current =
+            ConfigState::for_source(&ConfigSource::Termtint(path.clone()), false, 0, 0).unwrap();
+        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+        fs::write(
+            &state_path,
+            format!("{}\n{}\nExplicit", path.display(), current.mtime),
+        )
+        .unwrap();
+
+        let legacy = read_last_config_state_from(&state_path).unwrap();
+
+        assert_eq!(legacy.content_hash, 0);
+        // Same mtime, but the hash is new information: re-apply once
+        assert!(!current.matches(&legacy));
+        let old_mtime = ConfigState {
+            mtime: current.mtime - 1,
+            ..legacy.clone()
+        };
+        assert!(!legacy.matches(&old_mtime));
+        assert!(legacy.matches(&legacy.clone()));
+    }
+
+    #[test]
+    fn test_record_and_read_reset() {
+        let temp = TempDir::new().unwrap();
+        let state_path = session_state_path_in(temp.path(), "a");
+        let path = reset_record_path_for(&state_path);
+        assert_eq!(path, state_path.with_file_name("last_reset"));
+        assert_eq!(read_last_reset_from(&path), None);
+
+        record_reset_to(&path, ResetReason::LeftProject, SystemTime::now());
+        let record = read_last_reset_from(&path).unwrap();
+        assert_eq!(record.reason, ResetReason::LeftProject);
+        assert!(record.age(SystemTime::now()).unwrap() < Duration::from_secs(60));
+
+        // The latest reset replaces the previous one
+        record_reset_to(&path, ResetReason::Command, SystemTime::now());
+        assert_eq!(
+            read_last_reset_from(&path).map(|r| r.reason),
+            Some(ResetReason::Command)
+        );
+    }
+
+    #[test]
+    fn test_no_config_reset_keeps_earlier_record() {
+        let temp = TempDir::new().unwrap();
+        let path = reset_record_path_for(&session_state_path_in(temp.path(), "a"));
+        let earlier = SystemTime::now() - Duration::from_secs(600);
+
+        // Prompts outside a project don't replace why the colors were cleared
+        record_reset_to(&path, ResetReason::LeftProject, earlier);
+        record_no_config_reset_to(&path, SystemTime::now());
+        let record = read_last_reset_from(&path).unwrap();
+        assert_eq!(record.reason, ResetReason::LeftProject);
+        assert_eq!(record.at, unix_time(earlier));
+
+        // A session that never had colors records the first one only
+        fs::remove_file(&path).unwrap();
+        record_no_config_reset_to(&path, earlier);
+        record_no_config_reset_to(&path, SystemTime::now());
+        let record = read_last_reset_from(&path).unwrap();
+        assert_eq!(record.reason, ResetReason::NoConfig);
+        assert_eq!(record.at, unix_time(earlier));
+    }
+
+    #[test]
+    fn test_config_warnings_shown_once_per_session() {
+        let temp = TempDir::new().unwrap();
+        let first = config_warnings_path_for(&session_state_path_in(temp.path(), "a"));
+        let second = config_warnings_path_for(&session_state_path_in(temp.path(), "b"));
+        let broken = vec![
+            "failed to parse config.toml (line 1: invalid string), using defaults".to_string(),
+        ];
+
+        assert!(warnings_are_new_at(&first, &broken));
+        assert!(!warnings_are_new_at(&first, &broken));
+        assert!(!warnings_are_new_at(&first, &broken));
+        // Other sessions see them once too
+        assert!(warnings_are_new_at(&second, &broken));
+
+        // A different problem is news, and so is the same one after a fix
+        let invalid = vec!["invalid color_format 'cmyk', using hex".to_string()];
+        assert!(warnings_are_new_at(&first, &invalid));
+        assert!(!warnings_are_new_at(&first, &[]));
+        assert!(!first.exists());
+        assert!(warnings_are_new_at(&first, &invalid));
+    }
+
+    #[test]
+    fn test_unwritable_warning_shown_once_per_session() {
+        let temp = TempDir::new().unwrap();
+        let runtime_dir = temp.path().join("run");
+        let tab =
+            |session| Context::new(temp.path(), temp.path()).with_var("TERMTINT_SESSION", session);
+        let warning = "state directory /ro/.cache/termtint is not writable, not tracking state";
+
+        assert!(unwritable_warning_is_new(&tab("a"), warning, &runtime_dir));
+        assert!(!unwritable_warning_is_new(&tab("a"), warning, &runtime_dir));
+        assert!(unwritable_warning_is_new(&tab("b"), warning, &runtime_dir));
+        // Another state directory is another warning
+        let other = "state directory /ro/state is not writable, not tracking state";
+        assert!(unwritable_warning_is_new(&tab("a"), other, &runtime_dir));
+
+        // A runtime directory others can use isn't trusted with the record
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&runtime_dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(unwritable_warning_is_new(&tab("a"), other, &runtime_dir));
+        assert!(unwritable_warning_is_new(&tab("a"), other, &runtime_dir));
+    }
+
+    #[test]
+    fn test_read_reset_malformed() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("last_reset");
+        fs::write(&path, "1700000000\nexploded").unwrap();
+        assert_eq!(read_last_reset_from(&path), None);
+        fs::write(&path, "yesterday\ncommand").unwrap();
+        assert_eq!(read_last_reset_from(&path), None);
+        fs::write(&path, "1700000000\nno-config").unwrap();
+        assert_eq!(
+            read_last_reset_from(&path),
+            Some(ResetRecord {
+                at: 1_700_000_000,
+                reason: ResetReason::NoConfig,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_error_then_fix() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+        let path = temp.path().join(".termtint");
+        let source = ConfigSource::Termtint(path.clone());
+        let uc = UserConfig::default();
+
+        // A good config is applied and recorded
+        fs::write(&path, "#3478f6").unwrap();
+        let colors = crate::config::parse_config(&path, &uc).unwrap();
+        let applied = ConfigState {
+            tab: Some(colors.tab),
+            background: Some(colors.background),
+            ..ConfigState::for_source(&source, false, 0, 0).unwrap()
+        };
+        write_last_config_state_to(&state_path, Some(&applied), SystemTime::now());
+
+        // A typo fails to parse: the recorded state is kept, with the error noted
+        fs::write(&path, "tab = \"#3478f6\"\nbackground = ").unwrap();
+        let broken = ConfigState::for_source(&source, false, 0, 0).unwrap();
+        let error = crate::config::parse_config(&path, &uc).unwrap_err();
+        let last = read_last_config_state_from(&state_path).unwrap();
+        write_last_config_state_to(
+            &state_path,
+            Some(&ConfigState {
+                last_error: Some(error.to_string()),
+                ..last.clone()
+            }),
+            SystemTime::now(),
+        );
+        let errored = read_last_config_state_from(&state_path).unwrap();
+        let message = errored.last_error.clone().unwrap();
+        assert!(message.starts_with("Failed to parse TOML"));
+        assert!(!message.contains('\n'));
+        assert_eq!(errored.applied_colors(), applied.applied_colors());
+        assert!(errored.matches(&applied));
+        // The broken file still differs, so each apply retries it
+        assert!(!broken.matches(&errored));
+
+        // Once fixed, the new state no longer carries the error
+        fs::write(&path, "#f63478").unwrap();
+        let fixed = ConfigState::for_source(&source, false, 0, 0).unwrap();
+        assert!(!fixed.matches(&errored));
+        assert!(crate::config::parse_config(&path, &uc).is_ok());
+        write_last_config_state_to(&state_path, Some(&fixed), SystemTime::now());
+        assert_eq!(
+            read_last_config_state_from(&state_path).unwrap().last_error,
+            None
+        );
+    }
+
+    #[test]
+    fn test_describe_transition() {
+        let uc = UserConfig {
+            triggers: vec![Trigger::new(TriggerKind::File, "Cargo.toml")],
+            ..UserConfig::default()
+        };
+        let temp = TempDir::new().unwrap();
+        let project = temp.path().join("project");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join("Cargo.toml"), "").unwrap();
+        let explicit_path = project.join(".termtint");
+        fs::write(&explicit_path, "#ff0000").unwrap();
+
+        let explicit =
+            ConfigState::for_source(&ConfigSource::Termtint(explicit_path.clone()), false, 0, 0)
+                .unwrap();
+        let trigger = ConfigState::for_source(
+            &ConfigSource::TriggerFile(project.to_path_buf()),
+            false,
+            0,
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            describe_transition(&explicit, &trigger, &uc).as_deref(),
+            Some("switched from .termtint to trigger file Cargo.toml")
+        );
+        assert_eq!(describe_transition(&explicit, &explicit, &uc), None);
+
+        let edited = ConfigState {
+            content_hash: explicit.content_hash.wrapping_add(1),
+            ..explicit.clone()
+        };
+        assert_eq!(
+            describe_transition(&explicit, &edited, &uc),
+            Some(format!("{} changed", explicit_path.display()))
+        );
+    }
+
+    #[test]
+    fn test_shows_same_colors() {
+        let parent = applied_state("/code/app");
+        let nested = ConfigState {
+            path: PathBuf::from("/code/app/experiments/.termtint"),
+            mtime: 12345,
+            source_type: ConfigSourceType::Explicit,
+            ..parent.clone()
+        };
+        assert!(!nested.matches(&parent));
+        assert!(nested.shows_same_colors(&parent));
+
+        let recolored = ConfigState {
+            tab: Some(RGB { r: 0, g: 0, b: 0 }),
+            ..nested.clone()
+        };
+        assert!(!recolored.shows_same_colors(&parent));
+
+        let config_edited = ConfigState {
+            config_mtime: 1,
+            ..nested.clone()
+        };
+        assert!(!config_edited.shows_same_colors(&parent));
+
+        // States without recorded colors (older state files) never match
+        let legacy = ConfigState {
+            tab: None,
+            background: None,
+            ..parent.clone()
+        };
+        assert!(!nested.shows_same_colors(&legacy));
+        assert!(!legacy.shows_same_colors(&legacy));
+    }
+
+    #[test]
+    fn test_config_state_matches_ignores_colors() {
+        let with_tab = applied_state("/test/project");
+        let without_tab = ConfigState {
+            tab: None,
+            background: None,
+            ..with_tab.clone()
+        };
+
+        assert!(without_tab.matches(&with_tab));
+        assert!(!without_tab.matches(&applied_state("/other/project")));
+    }
+
+    #[test]
+    fn test_list_sessions() {
+        let temp = TempDir::new().unwrap();
+        let sessions_dir = sessions_dir_in(temp.path());
+
+        let state = applied_state("/code/app");
+        write_last_config_state_to(
+            &sessions_dir.join("b").join("last_config"),
+            Some(&state),
+            SystemTime::now(),
+        );
+        fs::create_dir_all(sessions_dir.join("a")).unwrap();
+        fs::create_dir_all(sessions_dir.join("c")).unwrap();
+        fs::write(sessions_dir.join("c").join("last_config"), "").unwrap();
+        fs::write(sessions_dir.join("stray"), "").unwrap();
+
+        let sessions = list_sessions_in(&sessions_dir, SystemTime::now());
+        let ids: Vec<&str> = sessions.iter().map(|s| s.id.as_str()).collect();
+
+        assert_eq!(ids, ["a", "b", "c", "stray"]);
+        assert_eq!(sessions[0].state, SessionState::Cleared);
+        assert_eq!(sessions[1].state, SessionState::Applied(state));
+        assert!(matches!(sessions[2].state, SessionState::Unreadable(_)));
+        assert!(matches!(sessions[3].state, SessionState::Unreadable(_)));
+        assert!(sessions
+            .iter()
+            .all(|s| !s.is_stale(Some(STALE_SESSION_AGE))));
+    }
+
+    #[test]
+    fn test_list_sessions_flags_stale() {
+        let temp = TempDir::new().unwrap();
+        let sessions_dir = sessions_dir_in(temp.path());
+        let config_path = sessions_dir.join("old").join("last_config");
+        write_last_config_state_to(
+            &config_path,
+            Some(&applied_state("/code/app")),
+            SystemTime::now(),
+        );
+        backdate_state(&config_path, Duration::from_secs(25 * 60 * 60));
+
+        let sessions = list_sessions_in(&sessions_dir, SystemTime::now());
+
+        assert!(sessions[0].is_stale(Some(STALE_SESSION_AGE)));
+        assert!(!sessions[0].is_stale(Some(Duration::from_secs(7 * 86_400))));
+        assert!(!sessions[0].is_stale(None));
+        assert!(list_sessions_in(&temp.path().join("missing"), SystemTime::now()).is_empty());
+    }
+
+    #[test]
+    fn test_session_id_precedence() {
+        let id = |iterm: Option<&str>, explicit: Option<&str>, tty: Option<&str>| {
+            session_id_from(
+                iterm.map(String::from),
+                explicit.map(String::from),
+                tty.map(String::from),
+            )
         };
 
-        assert_eq!(state1, state2);
+        assert_eq!(
+            id(Some("w0t1p0:ABCD-12"), Some("mine"), Some("/dev/ttys003")),
+            Some("w0t1p0_ABCD-12".to_string())
+        );
+        assert_eq!(
+            id(None, Some("mine"), Some("/dev/ttys003")),
+            Some("mine".to_string())
+        );
+        assert_eq!(
+            id(Some(""), None, Some("/dev/pts/3")),
+            Some("pts_3".to_string())
+        );
+        assert_eq!(id(None, None, None), None);
     }
 
     #[test]
-    fn test_config_state_different_source_type() {
-        let state1 = ConfigState {
-            path: PathBuf::from("/test/path"),
-            mtime: 12345,
-            source_type: ConfigSourceType::Explicit,
-        };
+    fn test_sessions_have_separate_state() {
+        let temp = TempDir::new().unwrap();
+        let legacy_path = state_file_path_in(temp.path());
+        let tab_a = session_state_path_in(temp.path(), "a");
+        let tab_b = session_state_path_in(temp.path(), "b");
 
-        let state2 = ConfigState {
-            path: PathBuf::from("/test/path"),
-            mtime: 12345,
-            source_type: ConfigSourceType::TriggerFile,
+        let state_a = ConfigState {
+            path: PathBuf::from("/project/a/.termtint"),
+            mtime: 1,
+            source_type: ConfigSourceType::Explicit,
+            ..Default::default()
         };
+        write_last_config_state_to(&tab_a, Some(&state_a), SystemTime::now());
 
-        assert_ne!(state1, state2);
+        assert_eq!(
+            read_session_state(&tab_a, &legacy_path, None),
+            Some(state_a)
+        );
+        assert_eq!(read_session_state(&tab_b, &legacy_path, None), None);
     }
 
     #[test]
-    fn test_write_and_read_state_explicit() {
+    fn test_legacy_state_migrates_into_session() {
         let temp = TempDir::new().unwrap();
-        let state_path = state_file_path_for_home(temp.path());
-
+        let legacy_path = state_file_path_in(temp.path());
+        let session_path = session_state_path_in(temp.path(), "a");
         let state = ConfigState {
-            path: PathBuf::from("/test/config/.termtint"),
-            mtime: 67890,
+            path: PathBuf::from("/project/.termtint"),
+            mtime: 42,
             source_type: ConfigSourceType::Explicit,
+            ..Default::default()
         };
+        write_last_config_state_to(&legacy_path, Some(&state), SystemTime::now());
 
-        write_last_config_state_to(&state_path, Some(&state));
-        let read_state = read_last_config_state_from(&state_path);
-
-        assert_eq!(read_state, Some(state));
+        assert_eq!(
+            read_session_state(&session_path, &legacy_path, None),
+            Some(state)
+        );
+        assert!(session_path.exists());
+        assert!(
+            !legacy_path.exists(),
+            "Legacy state should be moved, not copied"
+        );
     }
 
     #[test]
-    fn test_write_and_read_state_triggered() {
+    fn test_peek_session_state_changes_nothing() {
         let temp = TempDir::new().unwrap();
-        let state_path = state_file_path_for_home(temp.path());
+        let root = temp.path().join("state");
+        assert_eq!(peek_session_state(&root, Some("a"), None), None);
+        assert!(!root.exists(), "peeking created the state directory");
+
+        let legacy_path = state_file_path_in(&root);
+        let session_path = session_state_path_in(&root, "a");
+        let legacy = applied_state("/old/project");
+        write_last_config_state_to(&legacy_path, Some(&legacy), SystemTime::now());
+        assert_eq!(
+            peek_session_state(&root, Some("a"), None),
+            Some((legacy.clone(), legacy_path.clone()))
+        );
+        assert_eq!(
+            peek_session_state(&root, None, None),
+            Some((legacy.clone(), legacy_path.clone()))
+        );
+        assert!(legacy_path.exists(), "legacy state should stay in place");
+        assert!(!session_path.exists());
 
-        let state = ConfigState {
-            path: PathBuf::from("/test/project"),
-            mtime: 12345,
-            source_type: ConfigSourceType::TriggerFile,
+        // Another terminal's legacy state isn't this session's
+        let other = ConfigState {
+            tty: Some("/dev/ttys002".to_string()),
+            ..legacy
         };
+        write_last_config_state_to(&legacy_path, Some(&other), SystemTime::now());
+        assert_eq!(
+            peek_session_state(&root, Some("a"), Some("/dev/ttys001")),
+            None
+        );
 
-        write_last_config_state_to(&state_path, Some(&state));
-        let read_state = read_last_config_state_from(&state_path);
-
-        assert_eq!(read_state, Some(state));
+        let state = applied_state("/new/project");
+        write_last_config_state_to(&session_path, Some(&state), SystemTime::now());
+        assert_eq!(
+            peek_session_state(&root, Some("a"), Some("/dev/ttys001")),
+            Some((state, session_path))
+        );
+        assert!(legacy_path.exists(), "orphaned legacy state should be left");
     }
 
     #[test]
-    fn test_write_and_clear_state() {
+    fn test_orphaned_legacy_state_removed() {
         let temp = TempDir::new().unwrap();
-        let state_path = state_file_path_for_home(temp.path());
+        let legacy_path = state_file_path_in(temp.path());
+        let session_path = session_state_path_in(temp.path(), "a");
+        write_last_config_state_to(
+            &legacy_path,
+            Some(&applied_state("/old/project")),
+            SystemTime::now(),
+        );
+        let session_state = applied_state("/new/project");
+        write_last_config_state_to(&session_path, Some(&session_state), SystemTime::now());
 
-        let state = ConfigState {
-            path: PathBuf::from("/test/path"),
-            mtime: 12345,
-            source_type: ConfigSourceType::Explicit,
+        assert_eq!(
+            read_session_state(&session_path, &legacy_path, Some("/dev/ttys001")),
+            Some(session_state)
+        );
+        assert!(
+            !legacy_path.exists(),
+            "Orphaned legacy state should be removed"
+        );
+    }
+
+    #[test]
+    fn test_legacy_state_from_other_tty_kept() {
+        let temp = TempDir::new().unwrap();
+        let legacy_path = state_file_path_in(temp.path());
+        let session_path = session_state_path_in(temp.path(), "a");
+        let other_terminal = ConfigState {
+            tty: Some("/dev/ttys002".to_string()),
+            ..applied_state("/old/project")
         };
+        write_last_config_state_to(&legacy_path, Some(&other_terminal), SystemTime::now());
 
-        write_last_config_state_to(&state_path, Some(&state));
-        assert!(read_last_config_state_from(&state_path).is_some());
+        // Neither migrated into this session nor removed
+        assert_eq!(
+            read_session_state(&session_path, &legacy_path, Some("/dev/ttys001")),
+            None
+        );
+        assert!(!session_path.exists());
+        assert!(legacy_path.exists());
 
-        write_last_config_state_to(&state_path, None);
-        assert_eq!(read_last_config_state_from(&state_path), None);
+        write_last_config_state_to(
+            &session_path,
+            Some(&applied_state("/new/project")),
+            SystemTime::now(),
+        );
+        read_session_state(&session_path, &legacy_path, Some("/dev/ttys001"));
+        assert!(legacy_path.exists());
     }
 
     #[test]
-    fn test_read_nonexistent_state() {
+    fn test_cleanup_removes_stale_legacy_state() {
         let temp = TempDir::new().unwrap();
-        let state_path = state_file_path_for_home(temp.path());
+        let sessions_dir = sessions_dir_in(temp.path());
+        let legacy_path = state_file_path_in(temp.path());
+        write_last_config_state_to(
+            &legacy_path,
+            Some(&applied_state("/old/project")),
+            SystemTime::now(),
+        );
 
-        let result = read_last_config_state_from(&state_path);
-        assert_eq!(result, None);
+        cleanup_stale_sessions_in(&sessions_dir, Some(STALE_SESSION_AGE), SystemTime::now());
+        assert!(legacy_path.exists(), "Fresh legacy state should be kept");
+
+        backdate_state(&legacy_path, Duration::from_secs(25 * 60 * 60));
+        cleanup_stale_sessions_in(&sessions_dir, None, SystemTime::now());
+        assert!(legacy_path.exists(), "Cleanup disabled by session_ttl");
+
+        cleanup_stale_sessions_in(&sessions_dir, Some(STALE_SESSION_AGE), SystemTime::now());
+        assert!(!legacy_path.exists());
     }
 
-    #[test]
-    fn test_backwards_compatibility_missing_source_type() {
-        let temp = TempDir::new().unwrap();
-        let state_path = state_file_path_for_home(temp.path());
+    /// Make a state file look like it was last written `age` ago, moving both its
+    /// stored `updated_at` stamp and its mtime.
+    fn backdate_state(state_path: &Path, age: Duration) {
+        let at = unix_time(SystemTime::now()) - age.as_secs();
+        let content: Vec<String> = fs::read_to_string(state_path)
+            .unwrap()
+            .lines()
+            .map(|line| match line.strip_prefix("updated_at=") {
+                Some(_) => format!("updated_at={}", at),
+                None => line.to_string(),
+            })
+            .collect();
+        fs::write(state_path, content.join("\n")).unwrap();
+        let time = SystemTime::now() - age;
+        filetime::set_file_mtime(state_path, filetime::FileTime::from_system_time(time)).unwrap();
+    }
 
-        // Create parent directory and write old format (without source_type)
-        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
-        fs::write(&state_path, "/test/path\n12345").unwrap();
+    /// Create a session directory whose state was last written `age` ago.
+    fn fabricate_session(sessions_dir: &Path, id: &str, age: Duration) {
+        let config_path = sessions_dir.join(id).join("last_config");
+        write_last_config_state_to(
+            &config_path,
+            Some(&applied_state("/code/app")),
+            SystemTime::now(),
+        );
+        backdate_state(&config_path, age);
+    }
 
-        let state = read_last_config_state_from(&state_path).unwrap();
+    #[test]
+    fn test_age_since_tolerates_clock_skew() {
+        let now = SystemTime::now();
+        let secs = unix_time(now);
+        assert_eq!(
+            age_since(secs - 2 * 3600, now),
+            Duration::from_secs(2 * 3600)
+        );
+        // Timestamps from the future count as just written
+        assert_eq!(age_since(secs + 86_400, now), Duration::ZERO);
+        // Implausible ages, e.g. from a timestamp of 0, are clamped
+        assert_eq!(age_since(secs - 10 * 365 * 86_400, now), MAX_PLAUSIBLE_AGE);
+        assert_eq!(age_since(0, now), MAX_PLAUSIBLE_AGE);
+    }
 
-        // Should default to Explicit
-        assert_eq!(state.path, PathBuf::from("/test/path"));
-        assert_eq!(state.mtime, 12345);
-        assert_eq!(state.source_type, ConfigSourceType::Explicit);
+    #[test]
+    fn test_cleanup_with_skewed_timestamps() {
+        let temp = TempDir::new().unwrap();
+        let sessions_dir = sessions_dir_in(temp.path());
+        let ttl = Some(STALE_SESSION_AGE);
+        let future = SystemTime::now() + Duration::from_secs(30 * 86_400);
+        let decade_ago = SystemTime::now() - Duration::from_secs(10 * 365 * 86_400);
+
+        // State files from before updated_at was stored, with skewed mtimes
+        for (id, time) in [("future", future), ("decade", decade_ago)] {
+            let config_path = sessions_dir.join(id).join("last_config");
+            fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+            fs::write(&config_path, "/code/app\n0\nExplicit").unwrap();
+            filetime::set_file_mtime(&config_path, filetime::FileTime::from_system_time(time))
+                .unwrap();
+        }
+        // Stored stamp is fresh even though the mtime is old (e.g. NFS server clock)
+        fabricate_session(&sessions_dir, "nfs", Duration::ZERO);
+        filetime::set_file_mtime(
+            sessions_dir.join("nfs").join("last_config"),
+            filetime::FileTime::from_system_time(decade_ago),
+        )
+        .unwrap();
+
+        let sessions = list_sessions_in(&sessions_dir, SystemTime::now());
+        let age_of = |id: &str| sessions.iter().find(|s| s.id == id).unwrap().age;
+        assert_eq!(age_of("future"), Some(Duration::ZERO));
+        assert_eq!(age_of("decade"), Some(MAX_PLAUSIBLE_AGE));
+        assert!(age_of("nfs").unwrap() < Duration::from_secs(60));
+
+        // Only the session clamped to the longest age is stale
+        cleanup_stale_sessions_in(&sessions_dir, ttl, SystemTime::now());
+        assert!(!sessions_dir.join("decade").exists());
+        assert_eq!(fs::read_dir(&sessions_dir).unwrap().count(), 2);
     }
 
     #[test]
-    fn test_read_malformed_state() {
+    fn test_state_updated_at_prefers_stored_stamp() {
         let temp = TempDir::new().unwrap();
-        let state_path = state_file_path_for_home(temp.path());
+        let path = temp.path().join("last_config");
+        write_last_config_state_to(&path, Some(&applied_state("/code/app")), SystemTime::now());
+        filetime::set_file_mtime(&path, filetime::FileTime::from_unix_time(1_000, 0)).unwrap();
 
-        // Create parent directory and write malformed content
-        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
-        fs::write(&state_path, "invalid").unwrap();
+        assert!(state_updated_at(&path).unwrap() >= unix_time(SystemTime::now()) - 60);
 
-        let result = read_last_config_state_from(&state_path);
-        assert_eq!(result, None);
+        fs::write(&path, "/code/app\n0\nExplicit").unwrap();
+        filetime::set_file_mtime(&path, filetime::FileTime::from_unix_time(1_000, 0)).unwrap();
+        assert_eq!(state_updated_at(&path), Some(1_000));
     }
 
     #[test]
-    fn test_read_state_empty_path() {
-        let temp = TempDir::new().unwrap();
-        let state_path = state_file_path_for_home(temp.path());
-
-        // Create parent directory and write state with empty path
-        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
-        fs::write(&state_path, "\n12345\nExplicit").unwrap();
+    fn test_env_flag_enabled() {
+        assert!(!env_flag_enabled(None));
+        for value in ["", "0", "false", "No", " off "] {
+            assert!(!env_flag_enabled(Some(value)), "{:?}", value);
+        }
+        for value in ["1", "true", "yes"] {
+            assert!(env_flag_enabled(Some(value)), "{:?}", value);
+        }
+    }
 
-        let result = read_last_config_state_from(&state_path);
-        assert_eq!(result, None);
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 3600)));
+        assert_eq!(parse_duration(" 3d "), Ok(Duration::from_secs(3 * 86_400)));
+        assert_eq!(parse_duration("1w"), Ok(Duration::from_secs(7 * 86_400)));
     }
 
     #[test]
-    fn test_get_file_mtime_exists() {
-        let temp = TempDir::new().unwrap();
-        let test_file = temp.path().join("test.txt");
-        fs::write(&test_file, "content").unwrap();
+    fn test_parse_duration_invalid() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("12").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("2y").is_err());
+        assert!(parse_duration("-2h").is_err());
+        assert!(parse_duration("99999999999999999999w").is_err());
+    }
 
-        let mtime = get_file_mtime(&test_file);
-        assert!(mtime.is_some());
-        assert!(mtime.unwrap() > 0);
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(STALE_SESSION_AGE), "1d");
+        assert_eq!(format_duration(Duration::from_secs(90 * 60)), "90m");
+        assert_eq!(format_duration(Duration::from_secs(14 * 86_400)), "2w");
+        assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+        for s in ["90s", "36h", "3d", "1w"] {
+            assert_eq!(format_duration(parse_duration(s).unwrap()), s);
+        }
     }
 
     #[test]
-    fn test_get_file_mtime_nonexistent() {
+    fn test_cleanup_stale_sessions_ttl_never() {
         let temp = TempDir::new().unwrap();
-        let test_file = temp.path().join("nonexistent.txt");
+        let sessions_dir = sessions_dir_in(temp.path());
+        fabricate_session(&sessions_dir, "ancient", Duration::from_secs(300 * 86_400));
 
-        let mtime = get_file_mtime(&test_file);
-        assert_eq!(mtime, None);
+        cleanup_stale_sessions_in(&sessions_dir, None, SystemTime::now());
+        assert!(sessions_dir.join("ancient").exists());
+
+        cleanup_stale_sessions_in(
+            &sessions_dir,
+            Some(Duration::from_secs(7 * 86_400)),
+            SystemTime::now(),
+        );
+        assert!(!sessions_dir.join("ancient").exists());
     }
 
     #[test]
-    fn test_state_file_path_for_home() {
+    fn test_clean_sessions_older_than() {
         let temp = TempDir::new().unwrap();
+        let sessions_dir = sessions_dir_in(temp.path());
+        fabricate_session(&sessions_dir, "fresh", Duration::from_secs(60));
+        fabricate_session(&sessions_dir, "hours", Duration::from_secs(3 * 3600));
+        fabricate_session(&sessions_dir, "days", Duration::from_secs(3 * 86_400));
+
+        let selection = CleanSelection::OlderThan(Duration::from_secs(2 * 3600));
+        let ids = |sessions: Vec<SessionEntry>| -> Vec<String> {
+            sessions.into_iter().map(|s| s.id).collect()
+        };
 
-        let path = state_file_path_for_home(temp.path());
+        // Dry run selects without removing
         assert_eq!(
-            path,
-            temp.path()
-                .join(".cache")
-                .join("termtint")
-                .join("last_config")
+            ids(clean_sessions_in(
+                &sessions_dir,
+                selection,
+                true,
+                SystemTime::now()
+            )),
+            ["days", "hours"]
         );
+        assert!(sessions_dir.join("hours").exists());
+
+        assert_eq!(
+            ids(clean_sessions_in(
+                &sessions_dir,
+                selection,
+                false,
+                SystemTime::now()
+            )),
+            ["days", "hours"]
+        );
+        assert!(sessions_dir.join("fresh").exists());
+        assert!(!sessions_dir.join("hours").exists());
+        assert!(!sessions_dir.join("days").exists());
     }
 
     #[test]
-    fn test_cleanup_stale_sessions() {
+    fn test_clean_sessions_all() {
         let temp = TempDir::new().unwrap();
-        let sessions_dir = sessions_dir_for_home(temp.path());
+        let sessions_dir = sessions_dir_in(temp.path());
+        fabricate_session(&sessions_dir, "fresh", Duration::from_secs(60));
+        fs::write(sessions_dir.join("stray"), "").unwrap();
 
-        // Create fresh session (should not be deleted)
-        let fresh_session = sessions_dir.join("session1");
-        fs::create_dir_all(&fresh_session).unwrap();
-        fs::write(fresh_session.join("last_config"), "test").unwrap();
+        let removed =
+            clean_sessions_in(&sessions_dir, CleanSelection::All, false, SystemTime::now());
 
-        // Create stale session (should be deleted)
-        let stale_session = sessions_dir.join("session2");
-        fs::create_dir_all(&stale_session).unwrap();
-        let stale_file = stale_session.join("last_config");
-        fs::write(&stale_file, "test").unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(fs::read_dir(&sessions_dir).unwrap().count(), 0);
+    }
 
-        // Set mtime to > 24 hours ago
-        let old_time = SystemTime::now() - Duration::from_secs(25 * 60 * 60);
-        let file_time = filetime::FileTime::from_system_time(old_time);
-        filetime::set_file_mtime(&stale_file, file_time).unwrap();
+    #[test]
+    fn test_clear_other_sessions() {
+        let temp = TempDir::new().unwrap();
+        let sessions_dir = sessions_dir_in(temp.path());
+        fabricate_session(&sessions_dir, "current", Duration::from_secs(60));
+        fabricate_session(&sessions_dir, "other", Duration::from_secs(60));
+        fs::create_dir_all(sessions_dir.join("cleared")).unwrap();
+
+        let cleared = clear_other_sessions_in(&sessions_dir, Some("current"), SystemTime::now());
+
+        let ids: Vec<&str> = cleared.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["other"]);
+        assert!(sessions_dir.join("current").join("last_config").exists());
+        let other_state = sessions_dir.join("other").join("last_config");
+        assert!(!other_state.exists());
+        let reset = read_last_reset_from(&reset_record_path_for(&other_state)).unwrap();
+        assert_eq!(reset.reason, ResetReason::Command);
+        // Sessions without applied state are left alone
+        assert!(!sessions_dir.join("cleared").join("last_reset").exists());
+    }
 
-        // Verify the mtime was actually set
-        let metadata = fs::metadata(&stale_file).unwrap();
-        let modified = metadata.modified().unwrap();
-        let age = SystemTime::now().duration_since(modified).unwrap();
-        assert!(
-            age > Duration::from_secs(24 * 60 * 60),
-            "File should be older than 24 hours"
-        );
+    #[test]
+    fn test_cleanup_stale_sessions_throttled() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let sessions_dir = sessions_dir_in(root);
+        let ttl = Some(STALE_SESSION_AGE);
+
+        assert!(cleanup_stale_sessions_throttled(
+            root,
+            ttl,
+            CLEANUP_INTERVAL,
+            SystemTime::now()
+        ));
+        fabricate_session(&sessions_dir, "old", Duration::from_secs(3 * 86_400));
+
+        // A second call right after the first skips the sweep
+        assert!(!cleanup_stale_sessions_throttled(
+            root,
+            ttl,
+            CLEANUP_INTERVAL,
+            SystemTime::now()
+        ));
+        assert!(sessions_dir.join("old").exists());
+
+        // Once the last sweep is older than the interval, the next call sweeps
+        let earlier = SystemTime::now() - Duration::from_secs(2 * 3600);
+        filetime::set_file_mtime(
+            cleanup_marker_path_in(root),
+            filetime::FileTime::from_system_time(earlier),
+        )
+        .unwrap();
+        assert!(cleanup_stale_sessions_throttled(
+            root,
+            ttl,
+            CLEANUP_INTERVAL,
+            SystemTime::now()
+        ));
+        assert!(!sessions_dir.join("old").exists());
+
+        // A zero interval sweeps every time
+        assert!(cleanup_stale_sessions_throttled(
+            root,
+            ttl,
+            Duration::ZERO,
+            SystemTime::now()
+        ));
+    }
 
-        cleanup_stale_sessions_in(&sessions_dir);
+    #[test]
+    fn test_cleanup_stale_sessions_no_sessions_dir() {
+        let temp = TempDir::new().unwrap();
+        let sessions_dir = sessions_dir_in(temp.path());
 
-        // Fresh session should still exist
-        assert!(
-            fresh_session.exists(),
-            "Fresh session should not be deleted"
+        // Should not panic when sessions dir doesn't exist
+        cleanup_stale_sessions_in(&sessions_dir, Some(STALE_SESSION_AGE), SystemTime::now());
+    }
+
+    #[test]
+    fn test_encode_path() {
+        use std::os::unix::ffi::OsStrExt;
+        let plain = Path::new("/home/me/Code/café project");
+        assert_eq!(encode_path(plain), "/home/me/Code/café project");
+
+        for path in [
+            Path::new(std::ffi::OsStr::from_bytes(b"/home/me/caf\xe9")),
+            Path::new("/home/me/tab\there"),
+            Path::new("/home/me/trailing "),
+            Path::new("bytes:2f"),
+        ] {
+            let encoded = encode_path(path);
+            assert!(encoded.starts_with("bytes:"), "{}", encoded);
+            assert!(encoded.is_ascii());
+            assert_eq!(decode_path(&encoded), path);
+        }
+        assert_eq!(
+            encode_path(Path::new(std::ffi::OsStr::from_bytes(b"/a\xff"))),
+            "bytes:2f61ff"
         );
 
-        // Stale session should be deleted
-        assert!(!stale_session.exists(), "Stale session should be deleted");
+        // Malformed hex is taken as a plain path
+        assert_eq!(decode_path("bytes:2f6"), Path::new("bytes:2f6"));
+        assert_eq!(decode_path("bytes:zz"), Path::new("bytes:zz"));
     }
 
     #[test]
-    fn test_cleanup_stale_sessions_no_sessions_dir() {
+    fn test_non_utf8_directory_state_round_trip() {
+        use std::os::unix::ffi::OsStrExt;
         let temp = TempDir::new().unwrap();
-        let sessions_dir = sessions_dir_for_home(temp.path());
+        let code = temp.path().join("code");
+        let latin1 = code.join(std::ffi::OsStr::from_bytes(b"caf\xe9"));
+        let other = code.join(std::ffi::OsStr::from_bytes(b"caf\xe8"));
+        // macOS (APFS) refuses names that aren't valid UTF-8, so there is
+        // nothing to mangle there
+        if fs::create_dir_all(&latin1).is_err() || fs::create_dir_all(&other).is_err() {
+            return;
+        }
+        let uc = UserConfig {
+            triggers: vec![Trigger::new(
+                TriggerKind::Path,
+                format!("{}/*", code.canonicalize().unwrap().display()),
+            )],
+            ..UserConfig::default()
+        };
+        let source = find_config_source(&latin1, &uc).expect("trigger should match");
+        let other_source = find_config_source(&other, &uc).expect("trigger should match");
 
-        // Should not panic when sessions dir doesn't exist
-        cleanup_stale_sessions_in(&sessions_dir);
+        // The directories hash by their raw bytes: stable, and distinct even though
+        // their lossy forms are the same
+        let color = crate::config::parse_config_source(&source, &uc).unwrap();
+        assert_eq!(
+            crate::config::parse_config_source(&source, &uc).unwrap(),
+            color
+        );
+        assert_eq!(
+            other_source.root_dir().to_string_lossy(),
+            source.root_dir().to_string_lossy()
+        );
+        assert_ne!(
+            crate::config::parse_config_source(&other_source, &uc).unwrap(),
+            color
+        );
+
+        // The recorded state reads back as the same directory, so the next apply
+        // sees the colors as already applied
+        let state = ConfigState {
+            tab: Some(color.tab),
+            background: Some(color.background),
+            ..ConfigState::for_source(&source, false, 0, 0).unwrap()
+        };
+        let state_path = state_file_path_in(temp.path());
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+        let read_state = read_last_config_state_from(&state_path).unwrap();
+        assert_eq!(read_state, state);
+        assert!(ConfigState::for_source(&source, false, 0, 0)
+            .unwrap()
+            .matches(&read_state));
+        assert!(!ConfigState::for_source(&other_source, false, 0, 0)
+            .unwrap()
+            .matches(&read_state));
     }
 
     #[test]
     fn test_write_and_read_state_trigger_path() {
         let temp = TempDir::new().unwrap();
-        let state_path = state_file_path_for_home(temp.path());
+        let state_path = state_file_path_in(temp.path());
 
         let state = ConfigState {
             path: PathBuf::from("/test/project"),
-            mtime: 0,
             source_type: ConfigSourceType::TriggerPath,
+            ..Default::default()
         };
 
-        write_last_config_state_to(&state_path, Some(&state));
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
 
         let read_state = read_last_config_state_from(&state_path);
 
@@ -403,14 +3059,140 @@ mod tests {
             path: PathBuf::from("/test/path"),
             mtime: 12345,
             source_type: ConfigSourceType::TriggerPath,
+            ..Default::default()
         };
 
         let state2 = ConfigState {
             path: PathBuf::from("/test/path"),
             mtime: 12345,
             source_type: ConfigSourceType::TriggerFile,
+            ..Default::default()
         };
 
         assert_ne!(state1, state2);
     }
+
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_record_and_read_recent_hues() {
+        let temp = TempDir::new().unwrap();
+        let tab =
+            |session| Context::new(temp.path(), temp.path()).with_var("TERMTINT_SESSION", session);
+        let path = recent_hues_path(&tab("tab-1"));
+        assert_eq!(
+            path,
+            state_root(&tab("tab-1"))
+                .join("sessions")
+                .join("tab-1")
+                .join("recent_hues")
+        );
+
+        assert!(read_recent_hues_from(&path).is_empty());
+
+        record_recent_hue_to(&path, 10.0);
+        record_recent_hue_to(&path, 200.5);
+
+        assert_eq!(read_recent_hues_from(&path), vec![10.0, 200.5]);
+
+        // Each session rolls away from its own hues
+        record_recent_hue(&tab("tab-2"), 90.0);
+        assert_eq!(read_recent_hues(&tab("tab-1")), vec![10.0, 200.5]);
+        assert_eq!(read_recent_hues(&tab("tab-2")), vec![90.0]);
+    }
+
+    #[cfg(feature = "reroll")]
+    #[test]
+    fn test_recent_hues_limited() {
+        let temp = TempDir::new().unwrap();
+        let path = recent_hues_path_for(&state_file_path_in(temp.path()));
+
+        for i in 0..(RECENT_HUES_LIMIT + 3) {
+            record_recent_hue_to(&path, i as f32);
+        }
+
+        let hues = read_recent_hues_from(&path);
+        assert_eq!(hues.len(), RECENT_HUES_LIMIT);
+        // Oldest entries are dropped first
+        assert_eq!(hues[0], 3.0);
+        assert_eq!(*hues.last().unwrap(), (RECENT_HUES_LIMIT + 2) as f32);
+    }
+
+    #[test]
+    fn test_write_and_read_state_dimmed() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+
+        let state = ConfigState {
+            path: PathBuf::from("/test/config/.termtint"),
+            mtime: 67890,
+            source_type: ConfigSourceType::Explicit,
+            dimmed: true,
+            ..Default::default()
+        };
+
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+        let read_state = read_last_config_state_from(&state_path);
+
+        assert_eq!(read_state, Some(state));
+    }
+
+    #[test]
+    fn test_dimming_bucket_changes_state() {
+        let day = ConfigState {
+            path: PathBuf::from("/test/path"),
+            mtime: 12345,
+            source_type: ConfigSourceType::Explicit,
+            ..Default::default()
+        };
+        let night = ConfigState {
+            dimmed: true,
+            depth: 0,
+            tab: None,
+            background: None,
+            ..day.clone()
+        };
+
+        // Crossing into the dimming window must not look "unchanged"
+        assert_ne!(day, night);
+    }
+
+    #[test]
+    fn test_write_and_read_state_depth() {
+        let temp = TempDir::new().unwrap();
+        let state_path = state_file_path_in(temp.path());
+
+        let state = ConfigState {
+            path: PathBuf::from("/test/config/.termtint"),
+            mtime: 67890,
+            source_type: ConfigSourceType::Explicit,
+            depth: 2,
+            ..Default::default()
+        };
+
+        write_last_config_state_to(&state_path, Some(&state), SystemTime::now());
+        let content = fs::read_to_string(&state_path).unwrap();
+        assert!(content.contains("depth=2"));
+
+        let read_state = read_last_config_state_from(&state_path);
+        assert_eq!(read_state, Some(state));
+    }
+
+    #[test]
+    fn test_depth_changes_state() {
+        let root = ConfigState {
+            path: PathBuf::from("/test/path"),
+            mtime: 12345,
+            source_type: ConfigSourceType::Explicit,
+            ..Default::default()
+        };
+        let nested = ConfigState {
+            depth: 1,
+            tab: None,
+            background: None,
+            ..root.clone()
+        };
+
+        // Moving between subdirectories must not look "unchanged" with a depth ramp
+        assert_ne!(root, nested);
+    }
 }
@@ -1,5 +1,14 @@
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::{parse_color, TriggerGlobCache, RGB};
+use crate::context::Context;
+use crate::error::TermtintError;
+use crate::schema::{self, ConfigField, AUTO_FIELDS, CONFIG_FIELDS};
+use crate::state::{self, format_duration, parse_duration, CLEANUP_INTERVAL, STALE_SESSION_AGE};
 
 /// Color format for displaying colors.
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
@@ -10,46 +19,678 @@ pub enum ColorFormat {
     Rgb,
 }
 
+/// Whether paths in command output are OSC 8 hyperlinks.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Hyperlinks {
+    /// Only when stdout is a terminal known to support them
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How backgrounds are derived from tab colors.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BackgroundMode {
+    /// Dark backgrounds for dark terminal themes
+    #[default]
+    Darken,
+    /// Light, subtly tinted backgrounds for light terminal themes
+    Lighten,
+}
+
+/// What auto colors for trigger-file directories are derived from.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AutoHashSource {
+    /// The canonical directory path
+    #[default]
+    Path,
+    /// The package name declared by the matched trigger file, falling back to the path
+    PackageName,
+}
+
+/// Where the upward search for a config source stops.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WalkBoundary {
+    /// Walk all the way to the filesystem root
+    #[default]
+    Root,
+    /// Stop after checking `$HOME`, for directories inside it
+    Home,
+    /// Stop before crossing onto a different filesystem
+    Device,
+}
+
+/// A kind of directory config source, as listed in `trigger_priority`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceKind {
+    /// Explicit `.termtint` files
+    Termtint,
+    /// Directories matching `trigger_paths`
+    Paths,
+    /// Directories containing one of `trigger_files`
+    Files,
+    /// Directories whose own name matches `trigger_dirnames`
+    Dirnames,
+}
+
+impl SourceKind {
+    /// The name used for this kind in `trigger_priority`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SourceKind::Termtint => "termtint",
+            SourceKind::Paths => "paths",
+            SourceKind::Files => "files",
+            SourceKind::Dirnames => "dirnames",
+        }
+    }
+}
+
+/// The order source kinds are checked in at each directory by default.
+pub const DEFAULT_TRIGGER_PRIORITY: [SourceKind; 4] = [
+    SourceKind::Termtint,
+    SourceKind::Paths,
+    SourceKind::Files,
+    SourceKind::Dirnames,
+];
+
+/// Parse a `trigger_priority` list. Kinds left out are checked last, in their
+/// default order, so listing only `["files"]` moves trigger files to the front.
+fn parse_trigger_priority(names: &[String]) -> Result<Vec<SourceKind>, TermtintError> {
+    let mut priority = Vec::new();
+    for name in names {
+        let kind = DEFAULT_TRIGGER_PRIORITY
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| TermtintError::Config(format!("unknown source '{}'", name)))?;
+        if !priority.contains(&kind) {
+            priority.push(kind);
+        }
+    }
+    for kind in DEFAULT_TRIGGER_PRIORITY {
+        if !priority.contains(&kind) {
+            priority.push(kind);
+        }
+    }
+    Ok(priority)
+}
+
+/// Whether `[env_triggers]` rules are checked before or after directory sources.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EnvTriggerPriority {
+    /// A matching environment rule overrides `.termtint` files and triggers
+    #[default]
+    Before,
+    /// Environment rules only apply where no directory source is found
+    After,
+}
+
+/// A curated bundle of auto-generation parameters, selected with `preset` under `[auto]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub hue_min: f32,
+    pub hue_max: f32,
+    pub saturation_min: f32,
+    pub saturation_max: f32,
+    pub lightness: f32,
+    pub background_lightness: f32,
+    pub background_saturation: f32,
+}
+
+/// Available presets. Explicit config keys override the preset's values.
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "vivid",
+        description: "Saturated mid-tones (close to the defaults)",
+        hue_min: 0.0,
+        hue_max: 360.0,
+        saturation_min: 0.75,
+        saturation_max: 0.95,
+        lightness: 0.55,
+        background_lightness: 0.18,
+        background_saturation: 1.0,
+    },
+    Preset {
+        name: "pastel",
+        description: "Soft, light tab colors with gently tinted backgrounds",
+        hue_min: 0.0,
+        hue_max: 360.0,
+        saturation_min: 0.45,
+        saturation_max: 0.65,
+        lightness: 0.75,
+        background_lightness: 0.22,
+        background_saturation: 0.6,
+    },
+    Preset {
+        name: "muted",
+        description: "Low-saturation colors that stay out of the way",
+        hue_min: 0.0,
+        hue_max: 360.0,
+        saturation_min: 0.25,
+        saturation_max: 0.45,
+        lightness: 0.5,
+        background_lightness: 0.15,
+        background_saturation: 0.5,
+    },
+    Preset {
+        name: "neon",
+        description: "Fully saturated, bright tabs over very dark backgrounds",
+        hue_min: 0.0,
+        hue_max: 360.0,
+        saturation_min: 0.95,
+        saturation_max: 1.0,
+        lightness: 0.6,
+        background_lightness: 0.12,
+        background_saturation: 1.0,
+    },
+];
+
+/// Look up a preset by name (case-insensitive).
+pub fn find_preset(name: &str) -> Option<&'static Preset> {
+    PRESETS
+        .iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
+
+/// What a trigger's pattern is matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerKind {
+    /// A file name (or glob) found in the directory
+    File,
+    /// A glob on the directory's full path
+    Path,
+    /// A glob on the directory's own name
+    Dirname,
+    /// A glob on the current kubectl context, regardless of directory
+    KubeContext,
+}
+
+impl TriggerKind {
+    /// The name used for the kind in `[[triggers]]` entries.
+    pub fn name(self) -> &'static str {
+        match self {
+            TriggerKind::File => "file",
+            TriggerKind::Path => "path",
+            TriggerKind::Dirname => "dirname",
+            TriggerKind::KubeContext => "kube-context",
+        }
+    }
+
+    fn parse(name: &str) -> Option<TriggerKind> {
+        match name.to_lowercase().as_str() {
+            "file" => Some(TriggerKind::File),
+            "path" => Some(TriggerKind::Path),
+            "dirname" => Some(TriggerKind::Dirname),
+            "kube-context" | "kube_context" => Some(TriggerKind::KubeContext),
+            _ => None,
+        }
+    }
+
+    /// The kind of a pattern given without one: patterns containing a `/` or
+    /// starting with `~`, optionally after a leading `!` negation, are paths;
+    /// anything else, including a glob such as `*.cabal`, is a file name.
+    pub fn for_pattern(pattern: &str) -> TriggerKind {
+        let pattern = pattern.strip_prefix('!').unwrap_or(pattern);
+        match pattern.contains('/') || pattern.starts_with('~') {
+            true => TriggerKind::Path,
+            false => TriggerKind::File,
+        }
+    }
+}
+
+/// A trigger from `[[triggers]]` or one of the legacy trigger lists.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trigger {
+    /// The file name, path glob, or directory name matched, depending on `kind`
+    pub pattern: String,
+    pub kind: TriggerKind,
+    /// Color for matched directories; None generates an auto color
+    pub color: Option<String>,
+    /// For file triggers, text the matched file must contain
+    pub contains: Option<String>,
+    /// For file triggers, a key (dotted for nested tables) the matched file must
+    /// define when parsed as TOML
+    pub toml_key: Option<String>,
+    /// Start of the hue band for auto colors in degrees (the `[auto]` hue_min when unset)
+    pub hue_min: Option<f32>,
+    /// End of the hue band in degrees; may be below hue_min to wrap through 0°
+    pub hue_max: Option<f32>,
+    /// Lightness for darkened backgrounds of matched directories (the global
+    /// background_lightness when unset)
+    pub background_lightness: Option<f32>,
+    /// Saturation for derived backgrounds (the global background_saturation when unset)
+    pub background_saturation: Option<f32>,
+}
+
+impl Trigger {
+    /// A trigger with no options of its own.
+    pub fn new(kind: TriggerKind, pattern: impl Into<String>) -> Trigger {
+        Trigger {
+            pattern: pattern.into(),
+            kind,
+            color: None,
+            contains: None,
+            toml_key: None,
+            hue_min: None,
+            hue_max: None,
+            background_lightness: None,
+            background_saturation: None,
+        }
+    }
+
+    /// Whether the trigger checks the contents of the file it matches.
+    pub fn has_content_condition(&self) -> bool {
+        self.contains.is_some() || self.toml_key.is_some()
+    }
+
+    /// Whether the trigger narrows the hue range of its auto colors.
+    pub fn has_hue_band(&self) -> bool {
+        self.hue_min.is_some() || self.hue_max.is_some()
+    }
+
+    /// Whether the trigger sets its own parameters for derived backgrounds.
+    pub fn has_background_params(&self) -> bool {
+        self.background_lightness.is_some() || self.background_saturation.is_some()
+    }
+}
+
+/// Time-of-day window during which applied colors are dimmed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DimmingConfig {
+    /// Window start in minutes after local midnight
+    pub start: u32,
+    /// Window end in minutes after local midnight (may be before start to wrap midnight)
+    pub end: u32,
+    /// Multiplier applied to derived lightness while the window is active
+    pub lightness_scale: f32,
+    /// Whether the tab color is dimmed as well as the background
+    pub dim_tab: bool,
+}
+
+impl DimmingConfig {
+    /// Check whether the dimming window contains the given minute of the day.
+    pub fn is_active_at(&self, minute_of_day: u32) -> bool {
+        if self.start <= self.end {
+            minute_of_day >= self.start && minute_of_day < self.end
+        } else {
+            minute_of_day >= self.start || minute_of_day < self.end
+        }
+    }
+}
+
+/// Parse a "HH:MM" time into minutes after midnight.
+fn parse_time_of_day(s: &str) -> Option<u32> {
+    let (hours, minutes) = s.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(hours * 60 + minutes)
+}
+
+/// Parse a `session_ttl` value: a duration such as "7d", or "never" (returns None).
+fn parse_session_ttl(s: &str) -> Result<Option<Duration>, TermtintError> {
+    if s.trim().eq_ignore_ascii_case("never") {
+        return Ok(None);
+    }
+    let ttl = parse_duration(s)?;
+    if ttl.is_zero() {
+        return Err(TermtintError::Config(
+            "must be greater than zero".to_string(),
+        ));
+    }
+    Ok(Some(ttl))
+}
+
+/// Read just the `state_dir` key from the user config file, without the warnings
+/// a full load prints. Used to locate state before (or without) loading the config;
+/// loading it resolves the state directory from the loaded value instead.
+pub fn configured_state_dir(ctx: &Context) -> Option<String> {
+    configured_state_dir_from(&config_file_path(ctx))
+}
+
+fn configured_state_dir_from(config_path: &Path) -> Option<String> {
+    let content = fs::read_to_string(config_path).ok()?;
+    // Most configs don't set it; those are never parsed
+    if !content.contains("state_dir") {
+        return None;
+    }
+    let toml_config: UserConfigToml = toml::from_str(&content).ok()?;
+    toml_config.state_dir.filter(|dir| !dir.trim().is_empty())
+}
+
 /// Get the config file path for a given home directory.
 fn config_file_path_for_home(home: &Path) -> PathBuf {
     home.join(".config").join("termtint").join("config.toml")
 }
 
-/// Get the path to the user config file.
-pub fn config_file_path() -> PathBuf {
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    config_file_path_for_home(Path::new(&home))
+/// Get the path to the user config file: the `--config` file, else the one named
+/// by `TERMTINT_CONFIG`, else `~/.config/termtint/config.toml`. A relative path is
+/// taken from the working directory.
+pub fn config_file_path(ctx: &Context) -> PathBuf {
+    let path = ctx.config_file.clone().or_else(|| {
+        ctx.var_os("TERMTINT_CONFIG")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    });
+    match path {
+        Some(path) if path.is_relative() => match ctx.current_dir() {
+            Ok(dir) => dir.join(path),
+            Err(_) => path,
+        },
+        Some(path) => path,
+        None => config_file_path_for_home(ctx.home_dir()),
+    }
 }
 
 /// User configuration for termtint behavior.
 #[derive(Debug, Clone)]
 pub struct UserConfig {
+    /// Name of the preset the auto parameters started from, if any
+    pub preset: Option<&'static str>,
     // Auto color generation parameters
     pub hue_min: f32,
     pub hue_max: f32,
     pub saturation_min: f32,
     pub saturation_max: f32,
     pub lightness: f32,
+    /// Minimum angular distance (degrees) between a random hue and recently generated hues
+    pub hue_spread: f32,
+    /// Minimum Oklab ΔE between the current color and a rerolled one
+    pub reroll_min_delta: f32,
+    /// Background lightness removed per directory level below the config source
+    pub depth_ramp: f32,
+    /// Weight of the nearest ancestor `.termtint` color when blending trigger-sourced colors
+    pub blend_with_parent: f32,
+    /// Mix the hostname into auto-color hashes so each machine gets different colors
+    pub hash_include_hostname: bool,
+    /// Whether trigger-file colors hash the directory path or the package name
+    pub auto_hash_source: AutoHashSource,
     pub background_lightness: f32,
     pub background_saturation: f32,
-    pub trigger_files: Vec<String>,
-    pub trigger_paths: Vec<String>,
+    pub background_mode: BackgroundMode,
+    pub background_lightness_light: f32,
+    /// Shift along the blue–orange axis applied to all colors before emission (-1.0 to 1.0)
+    pub temperature_shift: f32,
+    /// Snap emitted colors to the xterm 256-color palette
+    pub force_256: bool,
+    /// Emit a 16-color ANSI palette derived from the tab color
+    pub generate_palette: bool,
+    /// Generate neutral gray tab colors, distinguished only by lightness
+    pub monochrome: bool,
+    /// Lightest and darkest hash-derived gray lightness in monochrome mode
+    pub monochrome_lightness_min: f32,
+    pub monochrome_lightness_max: f32,
+    /// Colors that auto-generated colors must stay away from
+    pub reserved_colors: Vec<RGB>,
+    /// Minimum Oklab ΔE between auto-generated colors and reserved colors
+    pub reserved_min_delta: f32,
+    /// Triggers of every kind in configured order: `[[triggers]]` entries, then
+    /// the legacy `trigger_files`, `trigger_paths`, `trigger_dirnames` and
+    /// `[[triggers.file]]` keys
+    pub triggers: Vec<Trigger>,
+    /// Globs compiled from the path and dirname triggers, on first use
+    pub trigger_globs: TriggerGlobCache,
+    /// Whether glob file triggers match file names case-sensitively
+    pub trigger_files_case_sensitive: bool,
+    /// Path globs where trigger paths and trigger files are ignored
+    pub exclude_paths: Vec<String>,
+    /// Order the source kinds are checked in at each directory of the walk
+    pub trigger_priority: Vec<SourceKind>,
+    pub walk_boundary: WalkBoundary,
+    /// Parent levels the config search may ascend; None is unlimited
+    pub max_walk_depth: Option<usize>,
+    /// Stop the config search at the enclosing git repository root, and color
+    /// trigger-file directories by that root
+    pub respect_git_root: bool,
+    /// Use `peacock.color` from `.vscode/settings.json` where there's no `.termtint`
+    pub auto_from_vscode: bool,
+    /// Environment rules, as ("VAR=value-glob", color or "auto") pairs in key order
+    pub env_triggers: Vec<(String, String)>,
+    pub env_trigger_priority: EnvTriggerPriority,
     pub color_format: ColorFormat,
+    pub hyperlinks: Hyperlinks,
+    /// Age after which idle session state is removed; None disables cleanup
+    pub session_ttl: Option<Duration>,
+    /// Minimum time between automatic stale-session sweeps on `apply`
+    pub cleanup_interval: Duration,
+    /// Remember resolved auto colors per trigger directory instead of regenerating them
+    pub color_cache: bool,
+    /// Treat problems in the config file as errors instead of warnings
+    pub strict: bool,
+    /// Directory for state and caches instead of ~/.cache/termtint
+    /// (`TERMTINT_STATE_DIR` takes precedence)
+    pub state_dir: Option<String>,
+    pub dimming: Option<DimmingConfig>,
+    /// Color overrides for `termtint ssh`, as (host glob, color) pairs
+    pub hosts: Vec<(String, String)>,
+    /// Home directory that `~/` patterns and `walk_boundary = "home"` resolve
+    /// against, from the context the config was loaded in
+    pub home: Option<PathBuf>,
 }
 
 impl Default for UserConfig {
     fn default() -> Self {
         UserConfig {
+            preset: None,
             hue_min: 0.0,
             hue_max: 360.0,
             saturation_min: 0.7,
             saturation_max: 0.9,
             lightness: 0.55,
+            hue_spread: 30.0,
+            reroll_min_delta: 0.12,
+            depth_ramp: 0.0,
+            blend_with_parent: 0.0,
+            hash_include_hostname: false,
+            auto_hash_source: AutoHashSource::default(),
             background_lightness: 0.18,
             background_saturation: 1.0,
-            trigger_files: Vec::new(),
-            trigger_paths: Vec::new(),
+            background_mode: BackgroundMode::default(),
+            background_lightness_light: 0.93,
+            temperature_shift: 0.0,
+            force_256: false,
+            generate_palette: false,
+            monochrome: false,
+            monochrome_lightness_min: 0.35,
+            monochrome_lightness_max: 0.80,
+            reserved_colors: Vec::new(),
+            reserved_min_delta: 0.15,
+            triggers: Vec::new(),
+            trigger_globs: TriggerGlobCache::default(),
+            trigger_files_case_sensitive: true,
+            exclude_paths: Vec::new(),
+            trigger_priority: DEFAULT_TRIGGER_PRIORITY.to_vec(),
+            walk_boundary: WalkBoundary::default(),
+            max_walk_depth: None,
+            respect_git_root: false,
+            auto_from_vscode: false,
+            env_triggers: Vec::new(),
+            env_trigger_priority: EnvTriggerPriority::default(),
             color_format: ColorFormat::default(),
+            hyperlinks: Hyperlinks::default(),
+            session_ttl: Some(STALE_SESSION_AGE),
+            cleanup_interval: CLEANUP_INTERVAL,
+            color_cache: false,
+            strict: false,
+            state_dir: None,
+            dimming: None,
+            hosts: Vec::new(),
+            home: None,
+        }
+    }
+}
+
+impl UserConfig {
+    /// Length in degrees of the configured hue arc.
+    /// When hue_min > hue_max the arc wraps through 360°, e.g. 300 → 40 spans 100°.
+    pub fn hue_arc(&self) -> f32 {
+        if self.hue_min <= self.hue_max {
+            self.hue_max - self.hue_min
+        } else {
+            (360.0 - self.hue_min) + self.hue_max
+        }
+    }
+
+    /// Map a fraction (0.0 to 1.0) of the configured hue arc to a hue in degrees.
+    pub fn hue_at(&self, fraction: f32) -> f32 {
+        let hue = self.hue_min + fraction * self.hue_arc();
+        if self.hue_min <= self.hue_max {
+            hue
+        } else {
+            hue.rem_euclid(360.0)
+        }
+    }
+
+    /// The current value of a config key from `schema::CONFIG_FIELDS`, as it would
+    /// be written in the config file. None for tables and for keys that are unset.
+    pub fn field_value(&self, name: &str) -> Option<toml::Value> {
+        // Through the f32's shortest representation, so 0.18 doesn't show as 0.180000007
+        let number = |v: f32| toml::Value::Float(v.to_string().parse().unwrap_or(v as f64));
+        let text = |s: &str| toml::Value::String(s.to_string());
+        let list = |items: Vec<String>| {
+            toml::Value::Array(items.into_iter().map(toml::Value::String).collect())
+        };
+        let patterns = |kind| list(self.trigger_patterns(kind).map(String::from).collect());
+        Some(match name {
+            "background_lightness" => number(self.background_lightness),
+            "background_saturation" => number(self.background_saturation),
+            "background_mode" => text(match self.background_mode {
+                BackgroundMode::Darken => "darken",
+                BackgroundMode::Lighten => "lighten",
+            }),
+            "background_lightness_light" => number(self.background_lightness_light),
+            "temperature_shift" => number(self.temperature_shift),
+            "force_256" => self.force_256.into(),
+            "generate_palette" => self.generate_palette.into(),
+            "monochrome" => self.monochrome.into(),
+            "monochrome_lightness_min" => number(self.monochrome_lightness_min),
+            "monochrome_lightness_max" => number(self.monochrome_lightness_max),
+            "reserved_colors" => list(self.reserved_colors.iter().map(RGB::to_string).collect()),
+            "reserved_min_delta" => number(self.reserved_min_delta),
+            "trigger_files" => patterns(TriggerKind::File),
+            "trigger_files_case_sensitive" => self.trigger_files_case_sensitive.into(),
+            "trigger_paths" => patterns(TriggerKind::Path),
+            "trigger_dirnames" => patterns(TriggerKind::Dirname),
+            "exclude_paths" => list(self.exclude_paths.clone()),
+            "trigger_priority" => list(
+                self.trigger_priority
+                    .iter()
+                    .map(|kind| kind.name().to_string())
+                    .collect(),
+            ),
+            "walk_boundary" => text(match self.walk_boundary {
+                WalkBoundary::Root => "root",
+                WalkBoundary::Home => "home",
+                WalkBoundary::Device => "device",
+            }),
+            "max_walk_depth" => toml::Value::Integer(self.max_walk_depth.unwrap_or(0) as i64),
+            "respect_git_root" => self.respect_git_root.into(),
+            "auto_from_vscode" => self.auto_from_vscode.into(),
+            "env_trigger_priority" => text(match self.env_trigger_priority {
+                EnvTriggerPriority::Before => "before",
+                EnvTriggerPriority::After => "after",
+            }),
+            "color_format" => text(match self.color_format {
+                ColorFormat::Hex => "hex",
+                ColorFormat::Hsl => "hsl",
+                ColorFormat::Rgb => "rgb",
+            }),
+            "hyperlinks" => match self.hyperlinks {
+                Hyperlinks::Auto => text("auto"),
+                Hyperlinks::Always => true.into(),
+                Hyperlinks::Never => false.into(),
+            },
+            "session_ttl" => text(
+                &self
+                    .session_ttl
+                    .map_or("never".to_string(), format_duration),
+            ),
+            "cleanup_interval" => text(&format_duration(self.cleanup_interval)),
+            "color_cache" => self.color_cache.into(),
+            "strict" => self.strict.into(),
+            "state_dir" => text(self.state_dir.as_deref()?),
+            "preset" => text(self.preset?),
+            "hue_min" => number(self.hue_min),
+            "hue_max" => number(self.hue_max),
+            "saturation_min" => number(self.saturation_min),
+            "saturation_max" => number(self.saturation_max),
+            "lightness" => number(self.lightness),
+            "hue_spread" => number(self.hue_spread),
+            "reroll_min_delta" => number(self.reroll_min_delta),
+            "depth_ramp" => number(self.depth_ramp),
+            "blend_with_parent" => number(self.blend_with_parent),
+            "hash_include_hostname" => self.hash_include_hostname.into(),
+            "auto_hash_source" => text(match self.auto_hash_source {
+                AutoHashSource::Path => "path",
+                AutoHashSource::PackageName => "package-name",
+            }),
+            _ => return None,
+        })
+    }
+
+    /// Overwrite the auto-generation parameters with a preset's values.
+    pub fn apply_preset(&mut self, preset: &Preset) {
+        self.preset = Some(preset.name);
+        self.hue_min = preset.hue_min;
+        self.hue_max = preset.hue_max;
+        self.saturation_min = preset.saturation_min;
+        self.saturation_max = preset.saturation_max;
+        self.lightness = preset.lightness;
+        self.background_lightness = preset.background_lightness;
+        self.background_saturation = preset.background_saturation;
+    }
+
+    /// The triggers of one kind, in configured order.
+    pub fn triggers_of(&self, kind: TriggerKind) -> impl Iterator<Item = &Trigger> {
+        self.triggers
+            .iter()
+            .filter(move |trigger| trigger.kind == kind)
+    }
+
+    /// The patterns of the triggers of one kind, in configured order.
+    pub fn trigger_patterns(&self, kind: TriggerKind) -> impl Iterator<Item = &str> {
+        self.triggers_of(kind)
+            .map(|trigger| trigger.pattern.as_str())
+    }
+
+    /// The trigger of a kind with exactly this pattern.
+    pub fn trigger(&self, kind: TriggerKind, pattern: &str) -> Option<&Trigger> {
+        self.triggers
+            .iter()
+            .find(|trigger| trigger.kind == kind && trigger.pattern == pattern)
+    }
+
+    /// A copy of this config with the hue range narrowed to a trigger's band.
+    pub fn with_hue_band(&self, trigger: &Trigger) -> UserConfig {
+        UserConfig {
+            hue_min: trigger.hue_min.unwrap_or(self.hue_min),
+            hue_max: trigger.hue_max.unwrap_or(self.hue_max),
+            ..self.clone()
+        }
+    }
+
+    /// A copy of this config with a trigger's background lightness and saturation.
+    pub fn with_background_params(&self, trigger: &Trigger) -> UserConfig {
+        UserConfig {
+            background_lightness: trigger
+                .background_lightness
+                .unwrap_or(self.background_lightness),
+            background_saturation: trigger
+                .background_saturation
+                .unwrap_or(self.background_saturation),
+            ..self.clone()
+        }
+    }
+
+    /// Target lightness for derived backgrounds in the configured background mode.
+    pub fn derived_background_lightness(&self) -> f32 {
+        match self.background_mode {
+            BackgroundMode::Darken => self.background_lightness,
+            BackgroundMode::Lighten => self.background_lightness_light,
         }
     }
 }
@@ -62,17 +703,133 @@ struct UserConfigToml {
     #[serde(default)]
     background_saturation: Option<f32>,
     #[serde(default)]
+    background_mode: Option<String>,
+    #[serde(default)]
+    background_lightness_light: Option<f32>,
+    #[serde(default)]
+    temperature_shift: Option<f32>,
+    #[serde(default)]
+    force_256: Option<bool>,
+    #[serde(default)]
+    generate_palette: Option<bool>,
+    #[serde(default)]
+    monochrome: Option<bool>,
+    #[serde(default)]
+    monochrome_lightness_min: Option<f32>,
+    #[serde(default)]
+    monochrome_lightness_max: Option<f32>,
+    #[serde(default)]
+    reserved_colors: Option<Vec<String>>,
+    #[serde(default)]
+    reserved_min_delta: Option<f32>,
+    #[serde(default)]
     trigger_files: Option<Vec<String>>,
     #[serde(default)]
+    trigger_files_case_sensitive: Option<bool>,
+    #[serde(default)]
     trigger_paths: Option<Vec<String>>,
     #[serde(default)]
+    trigger_dirnames: Option<Vec<String>>,
+    #[serde(default)]
+    exclude_paths: Option<Vec<String>>,
+    #[serde(default)]
+    trigger_priority: Option<Vec<String>>,
+    #[serde(default)]
+    walk_boundary: Option<String>,
+    #[serde(default)]
+    max_walk_depth: Option<usize>,
+    #[serde(default)]
+    respect_git_root: Option<bool>,
+    #[serde(default)]
+    auto_from_vscode: Option<bool>,
+    #[serde(default)]
+    env_trigger_priority: Option<String>,
+    #[serde(default)]
     color_format: Option<String>,
+    /// `true`, `false`, or `"auto"`
+    #[serde(default)]
+    hyperlinks: Option<toml::Value>,
+    #[serde(default)]
+    session_ttl: Option<String>,
+    #[serde(default)]
+    cleanup_interval: Option<String>,
+    #[serde(default)]
+    color_cache: Option<bool>,
+    #[serde(default)]
+    strict: Option<bool>,
+    #[serde(default)]
+    state_dir: Option<String>,
     #[serde(default)]
     auto: Option<AutoConfig>,
+    #[serde(default)]
+    dimming: Option<DimmingToml>,
+    #[serde(default)]
+    hosts: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    env_triggers: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    triggers: Option<TriggersToml>,
+}
+
+/// `triggers` is either the `[[triggers]]` array or, in older configs, a table
+/// holding `[[triggers.file]]` entries.
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum TriggersToml {
+    Entries(Vec<TriggerToml>),
+    Legacy {
+        #[serde(default)]
+        file: Vec<TriggerFileToml>,
+    },
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct TriggerToml {
+    #[serde(rename = "match")]
+    pattern: String,
+    #[serde(default)]
+    kind: Option<String>,
+    #[serde(default)]
+    color: Option<String>,
+    #[serde(default)]
+    contains: Option<String>,
+    #[serde(default)]
+    toml_key: Option<String>,
+    #[serde(default)]
+    hue: Option<[f32; 2]>,
+    #[serde(default)]
+    hue_min: Option<f32>,
+    #[serde(default)]
+    hue_max: Option<f32>,
+    #[serde(default)]
+    background_lightness: Option<f32>,
+    #[serde(default)]
+    background_saturation: Option<f32>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct TriggerFileToml {
+    name: String,
+    #[serde(default)]
+    hue_min: Option<f32>,
+    #[serde(default)]
+    hue_max: Option<f32>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct DimmingToml {
+    start: String,
+    end: String,
+    #[serde(default)]
+    lightness_scale: Option<f32>,
+    #[serde(default)]
+    dim_tab: Option<bool>,
 }
 
 #[derive(Debug, serde::Deserialize)]
 struct AutoConfig {
+    #[serde(default)]
+    preset: Option<String>,
     #[serde(default)]
     hue_min: Option<f32>,
     #[serde(default)]
@@ -83,31 +840,121 @@ struct AutoConfig {
     saturation_max: Option<f32>,
     #[serde(default)]
     lightness: Option<f32>,
+    #[serde(default)]
+    hue_spread: Option<f32>,
+    #[serde(default)]
+    reroll_min_delta: Option<f32>,
+    #[serde(default)]
+    depth_ramp: Option<f32>,
+    #[serde(default)]
+    blend_with_parent: Option<f32>,
+    #[serde(default)]
+    hash_include_hostname: Option<bool>,
+    #[serde(default)]
+    auto_hash_source: Option<String>,
 }
 
-/// Load user configuration from ~/.config/termtint/config.toml.
-/// Returns default config if file doesn't exist or can't be parsed.
-pub fn load_user_config() -> UserConfig {
-    load_user_config_from(&config_file_path())
+/// Load user configuration from ~/.config/termtint/config.toml (or the file
+/// `config_file_path` names instead). Returns default config if file doesn't exist or can't be parsed,
+/// or an error for the problems `strict = true` doesn't allow.
+pub fn load_user_config(ctx: &Context) -> Result<UserConfig, TermtintError> {
+    let mut config = load_user_config_from(&config_file_path(ctx))?;
+    config.home = ctx.home.clone();
+    state::use_configured_state_dir(ctx, config.state_dir.as_deref());
+    Ok(config)
+}
+
+/// Load user configuration like `load_user_config`, returning its warnings
+/// instead of logging them, so the prompt hook can decide whether to show them.
+pub fn load_user_config_with_warnings(
+    ctx: &Context,
+) -> Result<(UserConfig, Vec<String>), TermtintError> {
+    let (mut config, warnings) = read_user_config(&config_file_path(ctx))?;
+    config.home = ctx.home.clone();
+    state::use_configured_state_dir(ctx, config.state_dir.as_deref());
+    Ok((config, warnings))
+}
+
+/// Load the configuration `apply --fast` uses: the built-in defaults, or the file
+/// named by `TERMTINT_FAST_CONFIG` when that is set. The regular config file isn't
+/// read. Returns the config, the file it came from, if any, and its warnings.
+pub fn load_fast_user_config(
+    ctx: &Context,
+) -> Result<(UserConfig, Option<PathBuf>, Vec<String>), TermtintError> {
+    let path = ctx
+        .var_os("TERMTINT_FAST_CONFIG")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from);
+    let (mut config, warnings) = match &path {
+        Some(path) => read_user_config(path)?,
+        None => (UserConfig::default(), Vec::new()),
+    };
+    config.home = ctx.home.clone();
+    state::use_configured_state_dir(ctx, config.state_dir.as_deref());
+    Ok((config, path, warnings))
 }
 
 /// Load user configuration from a specific file path.
 /// Returns default config if file doesn't exist or can't be parsed.
-fn load_user_config_from(config_path: &Path) -> UserConfig {
+fn load_user_config_from(config_path: &Path) -> Result<UserConfig, TermtintError> {
+    let (config, warnings) = read_user_config(config_path)?;
+    for warning in &warnings {
+        tracing::warn!("{}", warning);
+    }
+    Ok(config)
+}
+
+/// Load user configuration from a specific file path, returning the problems
+/// found in it instead of logging them, or the error for those `strict = true`
+/// doesn't allow.
+fn read_user_config(config_path: &Path) -> Result<(UserConfig, Vec<String>), TermtintError> {
+    let mut warnings = Vec::new();
+
     // Return default if file doesn't exist
     let Ok(content) = fs::read_to_string(config_path) else {
-        return UserConfig::default();
+        return Ok((UserConfig::default(), warnings));
     };
 
-    // Parse TOML
-    let Ok(toml_config): Result<UserConfigToml, _> = toml::from_str(&content) else {
-        eprintln!("termtint: warning: failed to parse user config, using defaults");
-        return UserConfig::default();
+    // Parse TOML, fixing out-of-range numbers before they're read
+    let parsed = content.parse::<toml::Table>().and_then(|mut table| {
+        let numbers = validate_numbers(&mut table, config_path);
+        toml::Value::Table(table)
+            .try_into::<UserConfigToml>()
+            // Deserializing the text instead says which line is wrong
+            .map_err(|e| {
+                toml::from_str::<UserConfigToml>(&content)
+                    .err()
+                    .unwrap_or(e)
+            })
+            .map(|toml_config| (toml_config, numbers))
+    });
+    let toml_config = match parsed {
+        Ok((toml_config, Ok(numbers))) => {
+            warnings.extend(numbers);
+            toml_config
+        }
+        Ok((_, Err(e))) => return Err(e),
+        Err(e) => {
+            warnings.push(format!(
+                "failed to parse {} ({}), using defaults",
+                config_path.display(),
+                describe_toml_error(&content, &e)
+            ));
+            return Ok((UserConfig::default(), warnings));
+        }
     };
 
     // Start with defaults
     let mut config = UserConfig::default();
 
+    // Expand the preset first so explicit keys below override it
+    if let Some(name) = toml_config.auto.as_ref().and_then(|a| a.preset.as_ref()) {
+        match find_preset(name) {
+            Some(preset) => config.apply_preset(preset),
+            None => warnings.push(format!("invalid preset '{}', ignoring", name)),
+        }
+    }
+
     // Apply top-level overrides
     if let Some(lightness) = toml_config.background_lightness {
         config.background_lightness = lightness;
@@ -115,11 +962,105 @@ fn load_user_config_from(config_path: &Path) -> UserConfig {
     if let Some(saturation) = toml_config.background_saturation {
         config.background_saturation = saturation.clamp(0.0, 1.0);
     }
-    if let Some(files) = toml_config.trigger_files {
-        config.trigger_files = files;
+    if let Some(mode_str) = toml_config.background_mode {
+        config.background_mode = match mode_str.to_lowercase().as_str() {
+            "darken" => BackgroundMode::Darken,
+            "lighten" => BackgroundMode::Lighten,
+            _ => {
+                warnings.push(format!(
+                    "invalid background_mode '{}', using darken",
+                    mode_str
+                ));
+                BackgroundMode::Darken
+            }
+        };
+    }
+    if let Some(lightness) = toml_config.background_lightness_light {
+        config.background_lightness_light = lightness;
+    }
+    if let Some(shift) = toml_config.temperature_shift {
+        config.temperature_shift = shift.clamp(-1.0, 1.0);
+    }
+    if let Some(force) = toml_config.force_256 {
+        config.force_256 = force;
+    }
+    if let Some(generate) = toml_config.generate_palette {
+        config.generate_palette = generate;
+    }
+    if let Some(monochrome) = toml_config.monochrome {
+        config.monochrome = monochrome;
+    }
+    if let Some(v) = toml_config.monochrome_lightness_min {
+        config.monochrome_lightness_min = v.clamp(0.0, 1.0);
+    }
+    if let Some(v) = toml_config.monochrome_lightness_max {
+        config.monochrome_lightness_max = v.clamp(0.0, 1.0);
+    }
+    if let Some(colors) = toml_config.reserved_colors {
+        config.reserved_colors = colors
+            .iter()
+            .filter_map(|color| match parse_color(color) {
+                Ok(rgb) => Some(rgb),
+                Err(e) => {
+                    warnings.push(format!("ignoring reserved color: {}", e));
+                    None
+                }
+            })
+            .collect();
+    }
+    if let Some(delta) = toml_config.reserved_min_delta {
+        config.reserved_min_delta = delta.max(0.0);
+    }
+    if let Some(v) = toml_config.trigger_files_case_sensitive {
+        config.trigger_files_case_sensitive = v;
     }
-    if let Some(paths) = toml_config.trigger_paths {
-        config.trigger_paths = paths;
+    if let Some(paths) = toml_config.exclude_paths {
+        config.exclude_paths = paths;
+    }
+    if let Some(names) = toml_config.trigger_priority {
+        match parse_trigger_priority(&names) {
+            Ok(priority) => config.trigger_priority = priority,
+            Err(e) => warnings.push(format!(
+                "invalid trigger_priority {:?} ({}), using the default order",
+                names, e
+            )),
+        }
+    }
+    if let Some(boundary_str) = toml_config.walk_boundary {
+        config.walk_boundary = match boundary_str.to_lowercase().as_str() {
+            "root" => WalkBoundary::Root,
+            "home" => WalkBoundary::Home,
+            "device" => WalkBoundary::Device,
+            _ => {
+                warnings.push(format!(
+                    "invalid walk_boundary '{}', using root",
+                    boundary_str
+                ));
+                WalkBoundary::Root
+            }
+        };
+    }
+    if let Some(depth) = toml_config.max_walk_depth {
+        config.max_walk_depth = (depth > 0).then_some(depth);
+    }
+    if let Some(v) = toml_config.respect_git_root {
+        config.respect_git_root = v;
+    }
+    if let Some(v) = toml_config.auto_from_vscode {
+        config.auto_from_vscode = v;
+    }
+    if let Some(priority_str) = toml_config.env_trigger_priority {
+        config.env_trigger_priority = match priority_str.to_lowercase().as_str() {
+            "before" => EnvTriggerPriority::Before,
+            "after" => EnvTriggerPriority::After,
+            _ => {
+                warnings.push(format!(
+                    "invalid env_trigger_priority '{}', using before",
+                    priority_str
+                ));
+                EnvTriggerPriority::Before
+            }
+        };
     }
     if let Some(format_str) = toml_config.color_format {
         config.color_format = match format_str.to_lowercase().as_str() {
@@ -127,14 +1068,64 @@ fn load_user_config_from(config_path: &Path) -> UserConfig {
             "rgb" => ColorFormat::Rgb,
             "hex" => ColorFormat::Hex,
             _ => {
-                eprintln!(
-                    "termtint: warning: invalid color_format '{}', using hex",
-                    format_str
-                );
+                warnings.push(format!("invalid color_format '{}', using hex", format_str));
                 ColorFormat::Hex
             }
         };
     }
+    if let Some(value) = toml_config.hyperlinks {
+        config.hyperlinks = match &value {
+            toml::Value::Boolean(true) => Hyperlinks::Always,
+            toml::Value::Boolean(false) => Hyperlinks::Never,
+            toml::Value::String(s) if s.eq_ignore_ascii_case("auto") => Hyperlinks::Auto,
+            _ => {
+                warnings.push(format!(
+                    "invalid hyperlinks {} (expected true, false, or \"auto\"), using auto",
+                    value
+                ));
+                Hyperlinks::Auto
+            }
+        };
+    }
+    if let Some(ttl_str) = toml_config.session_ttl {
+        config.session_ttl = match parse_session_ttl(&ttl_str) {
+            Ok(ttl) => ttl,
+            Err(e) => {
+                warnings.push(format!(
+                    "invalid session_ttl '{}' ({}), using {}",
+                    ttl_str,
+                    e,
+                    format_duration(STALE_SESSION_AGE)
+                ));
+                Some(STALE_SESSION_AGE)
+            }
+        };
+    }
+    if let Some(interval_str) = toml_config.cleanup_interval {
+        config.cleanup_interval = match parse_duration(&interval_str) {
+            Ok(interval) => interval,
+            Err(e) => {
+                warnings.push(format!(
+                    "invalid cleanup_interval '{}' ({}), using {}",
+                    interval_str,
+                    e,
+                    format_duration(CLEANUP_INTERVAL)
+                ));
+                CLEANUP_INTERVAL
+            }
+        };
+    }
+
+    if let Some(v) = toml_config.color_cache {
+        config.color_cache = v;
+    }
+    if let Some(v) = toml_config.strict {
+        config.strict = v;
+    }
+
+    if let Some(dir) = toml_config.state_dir {
+        config.state_dir = Some(dir).filter(|dir| !dir.trim().is_empty());
+    }
 
     // Apply auto section overrides
     if let Some(auto) = toml_config.auto {
@@ -153,75 +1144,444 @@ fn load_user_config_from(config_path: &Path) -> UserConfig {
         if let Some(v) = auto.lightness {
             config.lightness = v;
         }
-    }
-
-    config
-}
+        if let Some(v) = auto.hue_spread {
+            config.hue_spread = v.max(0.0);
+        }
+        if let Some(v) = auto.reroll_min_delta {
+            config.reroll_min_delta = v.max(0.0);
+        }
+        if let Some(v) = auto.depth_ramp {
+            config.depth_ramp = v.clamp(0.0, 1.0);
+        }
+        if let Some(v) = auto.blend_with_parent {
+            config.blend_with_parent = v.clamp(0.0, 1.0);
+        }
+        if let Some(v) = auto.hash_include_hostname {
+            config.hash_include_hostname = v;
+        }
+        if let Some(source_str) = auto.auto_hash_source {
+            config.auto_hash_source = match source_str.to_lowercase().as_str() {
+                "path" => AutoHashSource::Path,
+                "package-name" => AutoHashSource::PackageName,
+                _ => {
+                    warnings.push(format!(
+                        "invalid auto_hash_source '{}', using path",
+                        source_str
+                    ));
+                    AutoHashSource::Path
+                }
+            };
+        }
+    }
 
-/// Save trigger files to the user config, preserving other settings.
-pub fn save_trigger_files(trigger_files: &[String]) -> Result<(), String> {
-    let config_path = config_file_path();
+    // Apply [[triggers]] entries, then merge in the legacy keys as plain entries
+    let (entries, bands) = match toml_config.triggers {
+        Some(TriggersToml::Entries(entries)) => (entries, Vec::new()),
+        Some(TriggersToml::Legacy { file }) => (Vec::new(), file),
+        None => (Vec::new(), Vec::new()),
+    };
+    for entry in entries {
+        let pattern = entry.pattern.clone();
+        match parse_trigger(entry) {
+            Ok(trigger) => config.triggers.push(trigger),
+            Err(e) => warnings.push(format!(
+                "invalid trigger '{}' ({}), ignoring it",
+                pattern, e
+            )),
+        }
+    }
+    let legacy_lists = [
+        (TriggerKind::File, toml_config.trigger_files),
+        (TriggerKind::Path, toml_config.trigger_paths),
+        (TriggerKind::Dirname, toml_config.trigger_dirnames),
+    ];
+    for (kind, patterns) in legacy_lists {
+        for pattern in patterns.unwrap_or_default() {
+            if config.trigger(kind, &pattern).is_none() {
+                config.triggers.push(Trigger::new(kind, pattern));
+            }
+        }
+    }
+    for band in bands {
+        let position = config
+            .triggers
+            .iter()
+            .position(|trigger| trigger.kind == TriggerKind::File && trigger.pattern == band.name);
+        let trigger = match position {
+            Some(index) => &mut config.triggers[index],
+            None => {
+                config
+                    .triggers
+                    .push(Trigger::new(TriggerKind::File, band.name));
+                config.triggers.last_mut().unwrap()
+            }
+        };
+        trigger.hue_min = band.hue_min.map(|v| v.clamp(0.0, 360.0));
+        trigger.hue_max = band.hue_max.map(|v| v.clamp(0.0, 360.0));
+    }
 
-    // Create parent directories if needed
-    if let Some(parent) = config_path.parent() {
-        std::fs::create_dir_all(parent)
-            .map_err(|e| format!("Error creating config directory: {}", e))?;
+    // Apply hosts section
+    if let Some(hosts) = toml_config.hosts {
+        config.hosts = hosts.into_iter().collect();
     }
 
-    // Read existing config or start fresh
-    let mut table: toml::Table = if config_path.exists() {
-        let content = fs::read_to_string(&config_path)
-            .map_err(|e| format!("Error reading config file: {}", e))?;
-        toml::from_str(&content).unwrap_or_default()
-    } else {
-        toml::Table::new()
-    };
+    // Report every unparseable pattern at once so one typo doesn't hide the rest
+    if let Err(e) = drop_invalid_globs(&mut config) {
+        if config.strict {
+            return Err(e);
+        }
+        warnings.push(format!("{}, skipping them", e));
+    }
+
+    // Apply env_triggers section
+    if let Some(env_triggers) = toml_config.env_triggers {
+        config.env_triggers = env_triggers
+            .into_iter()
+            .filter(|(rule, color)| {
+                let valid = color.eq_ignore_ascii_case("auto") || parse_color(color).is_ok();
+                if !valid {
+                    warnings.push(format!(
+                        "invalid color '{}' for env trigger '{}', ignoring it",
+                        color, rule
+                    ));
+                }
+                valid
+            })
+            .collect();
+    }
+
+    // Apply dimming section
+    if let Some(dimming) = toml_config.dimming {
+        match (
+            parse_time_of_day(&dimming.start),
+            parse_time_of_day(&dimming.end),
+        ) {
+            (Some(start), Some(end)) => {
+                config.dimming = Some(DimmingConfig {
+                    start,
+                    end,
+                    lightness_scale: dimming.lightness_scale.unwrap_or(0.7).clamp(0.0, 1.0),
+                    dim_tab: dimming.dim_tab.unwrap_or(false),
+                });
+            }
+            _ => {
+                warnings.push(format!(
+                    "invalid [dimming] times '{}'-'{}', expected HH:MM",
+                    dimming.start, dimming.end
+                ));
+            }
+        }
+    }
+
+    Ok((config, warnings))
+}
+
+/// Keys that bound a range together: the table they're in ("" for the top
+/// level), the min and max keys, and their values in a loaded config.
+type RangePair = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&UserConfig) -> (f32, f32),
+);
+
+const RANGE_PAIRS: &[RangePair] = &[
+    ("auto", "saturation_min", "saturation_max", |config| {
+        (config.saturation_min, config.saturation_max)
+    }),
+    (
+        "",
+        "monochrome_lightness_min",
+        "monochrome_lightness_max",
+        |config| {
+            (
+                config.monochrome_lightness_min,
+                config.monochrome_lightness_max,
+            )
+        },
+    ),
+];
 
-    // Update trigger_files
-    let files_array: Vec<toml::Value> = trigger_files
+/// Fix the numbers in a parsed user config: clamp values outside their keys'
+/// ranges, and swap min/max pairs that are the wrong way around, counting a key
+/// the file leaves out as its preset's or default value. Hue bounds aren't
+/// pairs, since `hue_min > hue_max` wraps through 360. Returns each fix made.
+pub fn normalize_numbers(config: &mut toml::Table) -> Vec<NumberFix> {
+    let mut fixes: Vec<NumberFix> = schema::clamp_out_of_range(config)
         .iter()
-        .map(|s| toml::Value::String(s.clone()))
+        .map(|out_of_range| NumberFix {
+            problem: out_of_range.problem(),
+            action: format!("using {}", out_of_range.clamped()),
+        })
         .collect();
-    table.insert("trigger_files".to_string(), toml::Value::Array(files_array));
 
-    // Write back
-    let content =
-        toml::to_string_pretty(&table).map_err(|e| format!("Error serializing config: {}", e))?;
-    fs::write(&config_path, content).map_err(|e| format!("Error writing config file: {}", e))?;
+    let mut base = UserConfig::default();
+    let preset = config
+        .get("auto")
+        .and_then(|auto| auto.get("preset"))
+        .and_then(toml::Value::as_str)
+        .and_then(find_preset);
+    if let Some(preset) = preset {
+        base.apply_preset(preset);
+    }
+    for &(section, min_key, max_key, values) in RANGE_PAIRS {
+        let (table, prefix) = if section.is_empty() {
+            (Some(&mut *config), String::new())
+        } else {
+            let table = config.get_mut(section).and_then(toml::Value::as_table_mut);
+            (table, format!("{}.", section))
+        };
+        let Some(table) = table else {
+            continue;
+        };
+        let (base_min, base_max) = values(&base);
+        let number = |key: &str, base: f32| match table.get(key) {
+            Some(toml::Value::Float(value)) => Some(*value),
+            Some(toml::Value::Integer(value)) => Some(*value as f64),
+            Some(_) => None,
+            None => Some(f64::from(base)),
+        };
+        let (Some(min), Some(max)) = (number(min_key, base_min), number(max_key, base_max)) else {
+            continue;
+        };
+        if min > max {
+            fixes.push(NumberFix {
+                problem: format!(
+                    "{prefix}{min_key} = {} is above {prefix}{max_key} = {}",
+                    min as f32, max as f32
+                ),
+                action: "swapping them".to_string(),
+            });
+            table.insert(min_key.to_string(), toml::Value::Float(max));
+            table.insert(max_key.to_string(), toml::Value::Float(min));
+        }
+    }
+    fixes
+}
 
-    Ok(())
+/// A number `normalize_numbers` fixed: what was wrong with it, and what was
+/// done instead, e.g. `auto.lightness = 5.5 is outside 0 to 1` and `using 1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberFix {
+    pub problem: String,
+    pub action: String,
+}
+
+impl fmt::Display for NumberFix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", self.problem, self.action)
+    }
+}
+
+/// Fix the numbers in a parsed user config at `path`, returning the warning
+/// describing the fixes, or with `strict = true` an error naming the problems
+/// instead, since nothing gets fixed.
+fn validate_numbers(
+    config: &mut toml::Table,
+    path: &Path,
+) -> Result<Option<String>, TermtintError> {
+    let fixes = normalize_numbers(config);
+    if fixes.is_empty() {
+        return Ok(None);
+    }
+    let strict = config.get("strict").and_then(toml::Value::as_bool) == Some(true);
+    let described: Vec<String> = fixes
+        .iter()
+        .map(|fix| match strict {
+            true => fix.problem.clone(),
+            false => fix.to_string(),
+        })
+        .collect();
+    let message = format!(
+        "invalid values in {}: {}",
+        path.display(),
+        described.join("; ")
+    );
+    match strict {
+        true => Err(TermtintError::Config(message)),
+        false => Ok(Some(message)),
+    }
+}
+
+/// A TOML error on one line, with the line it's on: `line 3: invalid string`.
+fn describe_toml_error(content: &str, e: &toml::de::Error) -> String {
+    let message = e.message().split_whitespace().collect::<Vec<_>>().join(" ");
+    match e.span() {
+        Some(span) => {
+            let line = content[..span.start.min(content.len())]
+                .matches('\n')
+                .count()
+                + 1;
+            format!("line {}: {}", line, message)
+        }
+        None => message,
+    }
+}
+
+/// Save trigger files to the user config, preserving other settings.
+/// Check that a trigger or exclusion pattern is a valid glob. The `!` of a
+/// negated trigger path isn't part of the glob.
+pub fn validate_glob(pattern: &str) -> Result<(), TermtintError> {
+    glob::Pattern::new(pattern.strip_prefix('!').unwrap_or(pattern))
+        .map(|_| ())
+        .map_err(|e| TermtintError::Config(e.to_string()))
+}
+
+/// Remove the entries that aren't valid globs from the trigger and exclusion
+/// lists, describing them in a single error if there were any.
+fn drop_invalid_globs(config: &mut UserConfig) -> Result<(), TermtintError> {
+    let mut invalid = Vec::new();
+    let mut keep_valid = |key: &str, pattern: &str| match validate_glob(pattern) {
+        Ok(()) => true,
+        Err(e) => {
+            invalid.push(format!("{} '{}' ({})", key, pattern, e));
+            false
+        }
+    };
+    config.triggers.retain(|trigger| {
+        keep_valid(
+            &format!("{} trigger", trigger.kind.name()),
+            &trigger.pattern,
+        )
+    });
+    config
+        .exclude_paths
+        .retain(|pattern| keep_valid("exclude_paths", pattern));
+
+    match invalid.is_empty() {
+        true => Ok(()),
+        false => Err(TermtintError::Config(format!(
+            "invalid glob patterns: {}",
+            invalid.join(", ")
+        ))),
+    }
+}
+
+/// Parse a `[[triggers]]` entry. Without a `kind` the pattern decides it, as for
+/// `termtint trigger add`; a color of "auto" is the same as none.
+fn parse_trigger(entry: TriggerToml) -> Result<Trigger, TermtintError> {
+    let kind = match &entry.kind {
+        Some(name) => TriggerKind::parse(name)
+            .ok_or_else(|| TermtintError::Config(format!("unknown kind '{}'", name)))?,
+        None => TriggerKind::for_pattern(&entry.pattern),
+    };
+    let color = entry
+        .color
+        .filter(|color| !color.eq_ignore_ascii_case("auto"));
+    if let Some(color) = &color {
+        parse_color(color).map_err(|e| {
+            TermtintError::InvalidColor(format!("invalid color '{}': {}", color, e))
+        })?;
+    }
+    if kind != TriggerKind::File && (entry.contains.is_some() || entry.toml_key.is_some()) {
+        return Err(TermtintError::Config(
+            "contains and toml_key only apply to file triggers".to_string(),
+        ));
+    }
+    let (hue_min, hue_max) = match entry.hue {
+        Some([min, max]) => (Some(min), Some(max)),
+        None => (entry.hue_min, entry.hue_max),
+    };
+    Ok(Trigger {
+        pattern: entry.pattern,
+        kind,
+        color,
+        contains: entry.contains,
+        toml_key: entry.toml_key,
+        hue_min: hue_min.map(|v| v.clamp(0.0, 360.0)),
+        hue_max: hue_max.map(|v| v.clamp(0.0, 360.0)),
+        background_lightness: entry.background_lightness.map(|v| v.clamp(0.0, 1.0)),
+        background_saturation: entry.background_saturation.map(|v| v.clamp(0.0, 1.0)),
+    })
+}
+
+/// The `[[triggers]]` entry for a trigger, with only the options it sets.
+fn trigger_to_toml(trigger: &Trigger) -> toml::Table {
+    let mut entry = toml::Table::new();
+    entry.insert("match".to_string(), trigger.pattern.clone().into());
+    entry.insert("kind".to_string(), trigger.kind.name().into());
+    if let Some(color) = &trigger.color {
+        entry.insert("color".to_string(), color.clone().into());
+    }
+    if let Some(contains) = &trigger.contains {
+        entry.insert("contains".to_string(), contains.clone().into());
+    }
+    if let Some(key) = &trigger.toml_key {
+        entry.insert("toml_key".to_string(), key.clone().into());
+    }
+    match (trigger.hue_min, trigger.hue_max) {
+        (Some(min), Some(max)) => {
+            let hue = vec![toml::Value::from(min as f64), toml::Value::from(max as f64)];
+            entry.insert("hue".to_string(), toml::Value::Array(hue));
+        }
+        (min, max) => {
+            if let Some(min) = min {
+                entry.insert("hue_min".to_string(), (min as f64).into());
+            }
+            if let Some(max) = max {
+                entry.insert("hue_max".to_string(), (max as f64).into());
+            }
+        }
+    }
+    if let Some(lightness) = trigger.background_lightness {
+        entry.insert(
+            "background_lightness".to_string(),
+            (lightness as f64).into(),
+        );
+    }
+    if let Some(saturation) = trigger.background_saturation {
+        entry.insert(
+            "background_saturation".to_string(),
+            (saturation as f64).into(),
+        );
+    }
+    entry
 }
 
-/// Save trigger paths to the user config, preserving other settings.
-pub fn save_trigger_paths(trigger_paths: &[String]) -> Result<(), String> {
-    let config_path = config_file_path();
+/// Save the triggers to the user config as a `[[triggers]]` array, preserving
+/// other settings. The legacy trigger keys are folded into the array.
+pub fn save_triggers(ctx: &Context, triggers: &[Trigger]) -> Result<(), TermtintError> {
+    save_triggers_to(&config_file_path(ctx), triggers)
+}
 
+/// Save triggers to a specific config file path.
+fn save_triggers_to(config_path: &Path, triggers: &[Trigger]) -> Result<(), TermtintError> {
     // Create parent directories if needed
     if let Some(parent) = config_path.parent() {
         std::fs::create_dir_all(parent)
-            .map_err(|e| format!("Error creating config directory: {}", e))?;
+            .map_err(|e| TermtintError::io("Error creating config directory", &e))?;
     }
 
     // Read existing config or start fresh
     let mut table: toml::Table = if config_path.exists() {
-        let content = fs::read_to_string(&config_path)
-            .map_err(|e| format!("Error reading config file: {}", e))?;
+        let content = fs::read_to_string(config_path)
+            .map_err(|e| TermtintError::io("Error reading config file", &e))?;
         toml::from_str(&content).unwrap_or_default()
     } else {
         toml::Table::new()
     };
 
-    // Update trigger_paths
-    let paths_array: Vec<toml::Value> = trigger_paths
-        .iter()
-        .map(|s| toml::Value::String(s.clone()))
-        .collect();
-    table.insert("trigger_paths".to_string(), toml::Value::Array(paths_array));
+    for key in [
+        "trigger_files",
+        "trigger_paths",
+        "trigger_dirnames",
+        "triggers",
+    ] {
+        table.remove(key);
+    }
+    if !triggers.is_empty() {
+        let entries = triggers
+            .iter()
+            .map(|trigger| toml::Value::Table(trigger_to_toml(trigger)))
+            .collect();
+        table.insert("triggers".to_string(), toml::Value::Array(entries));
+    }
 
     // Write back
-    let content =
-        toml::to_string_pretty(&table).map_err(|e| format!("Error serializing config: {}", e))?;
-    fs::write(&config_path, content).map_err(|e| format!("Error writing config file: {}", e))?;
+    let content = toml::to_string_pretty(&table)
+        .map_err(|e| TermtintError::Config(format!("Error serializing config: {}", e)))?;
+    fs::write(config_path, content)
+        .map_err(|e| TermtintError::io("Error writing config file", &e))?;
 
     Ok(())
 }
@@ -230,7 +1590,7 @@ pub fn save_trigger_paths(trigger_paths: &[String]) -> Result<(), String> {
 pub fn default_config_toml() -> String {
     let defaults = UserConfig::default();
     format!(
-        r#"# termtint user configuration
+        r##"# termtint user configuration
 # Location: ~/.config/termtint/config.toml
 
 # Fixed lightness for darkened backgrounds (0.0 to 1.0)
@@ -240,21 +1600,112 @@ background_lightness = {:.2}
 # 1.0 = preserve original saturation, 0.0 = grayscale
 background_saturation = {:.2}
 
+# Background derivation: "darken" for dark themes, "lighten" for light themes
+background_mode = "darken"
+
+# Fixed lightness for lightened backgrounds when background_mode = "lighten"
+background_lightness_light = {:.2}
+
+# Shift all colors along the blue–orange axis: -1.0 (cooler) to 1.0 (warmer)
+temperature_shift = {:.1}
+
+# Approximate colors with the xterm 256-color palette (for terminals without truecolor)
+force_256 = false
+
+# Also set the 16 ANSI colors to a palette harmonized with the tab color
+generate_palette = false
+
+# Generate neutral gray tab colors, distinguished only by lightness
+monochrome = false
+
+# Lightness range for monochrome tab colors (0.0 to 1.0)
+monochrome_lightness_min = {:.2}
+monochrome_lightness_max = {:.2}
+
+# Colors that auto-generated colors keep away from, e.g. ["#ff0000"]
+reserved_colors = []
+
+# Minimum perceptual difference (Oklab ΔE) from reserved colors
+reserved_min_delta = {:.2}
+
 # Files that trigger automatic color generation when found
 # Examples: ["Cargo.toml", "package.json", "go.mod", "pyproject.toml"]
+# Globs such as "*.cabal" match any file in the directory
 trigger_files = []
 
+# Whether glob entries in trigger_files match case-sensitively
+trigger_files_case_sensitive = true
+
 # Path globs that trigger automatic color generation
 # Directories matching these patterns are treated as having 'auto' in .termtint
 # Supports ~ for home directory. Example: ["~/Code/*", "~/Projects/*"]
 trigger_paths = []
 
+# Directory names that trigger automatic color generation wherever they are
+# Matches the name only, exactly or as a glob. Example: ["prod", "release-*"]
+trigger_dirnames = []
+
+# Path globs where trigger paths and trigger files are ignored (.termtint still applies)
+# Example: ["~/Code/scratch", "~/Code/archive/**"]
+exclude_paths = []
+
+# Order sources are checked in at each directory, nearest directory first:
+# "termtint" (.termtint files), "paths" (trigger_paths), "files" (trigger_files),
+# "dirnames" (trigger_dirnames)
+trigger_priority = ["termtint", "paths", "files", "dirnames"]
+
+# Where the search for .termtint and trigger files stops: "root" (the filesystem
+# root), "home" ($HOME, for directories inside it), or "device" (the filesystem
+# the search started on)
+walk_boundary = "root"
+
+# How many parent directories the search may climb (0 = unlimited)
+max_walk_depth = 0
+
+# Treat the enclosing git repository as the project: the search stops at the
+# repository root, and trigger files anywhere inside it color the whole repository
+respect_git_root = false
+
+# Where a directory has no .termtint, use the Peacock color ("peacock.color") from
+# its .vscode/settings.json as the tab color
+auto_from_vscode = false
+
+# Whether [env_triggers] rules are checked "before" directory sources (so they
+# override .termtint files and triggers) or "after" (only where none is found)
+env_trigger_priority = "before"
+
 # Color format for display: "hex", "hsl", or "rgb"
 color_format = "hex"
 
+# Show paths in inspect and sessions list as clickable links: true, false, or
+# "auto" (only in terminals known to support OSC 8 hyperlinks)
+hyperlinks = "auto"
+
+# How long an idle terminal session's state is kept, e.g. "24h" or "7d"
+# ("never" disables automatic cleanup)
+session_ttl = "24h"
+
+# How often apply checks for stale sessions ("0s" checks on every apply)
+cleanup_interval = "1h"
+
+# Cache resolved auto colors per trigger directory (clear with `termtint cache clear`)
+color_cache = false
+
+# Treat problems in this file, such as invalid glob patterns, as errors
+# instead of warnings
+strict = false
+
+# Directory for termtint's state and caches (default: ~/.cache/termtint)
+# The TERMTINT_STATE_DIR environment variable takes precedence
+# state_dir = "~/.cache/termtint"
+
 # Auto color generation parameters
 [auto]
-# Hue range in degrees (0.0 to 360.0)
+# Start from a preset: "vivid", "pastel", "muted", or "neon"
+# (explicit keys below override the preset's values)
+# preset = "vivid"
+
+# Hue range in degrees (0.0 to 360.0); hue_min > hue_max wraps through 360
 hue_min = {:.1}
 hue_max = {:.1}
 
@@ -264,210 +1715,347 @@ saturation_max = {:.1}
 
 # Lightness for generated tab colors (0.0 to 1.0)
 lightness = {:.2}
-"#,
+
+# Minimum hue distance in degrees between consecutive random colors (0 disables)
+hue_spread = {:.1}
+
+# Minimum perceptual difference (Oklab ΔE) between rerolled colors (0 disables)
+reroll_min_delta = {:.2}
+
+# Darken the background by this much lightness per directory level below
+# the config source (0 disables, e.g. 0.02)
+depth_ramp = {:.2}
+
+# Blend trigger-file/path colors with the nearest ancestor .termtint color
+# 0.0 = disabled, 0.6 = 60% parent color, 1.0 = parent color only
+blend_with_parent = {:.1}
+
+# Mix the hostname into auto colors so the same project differs per machine
+# (intentionally breaks cross-machine color stability)
+hash_include_hostname = false
+
+# What trigger-file colors are derived from: "path" or "package-name"
+# ("package-name" reads Cargo.toml, package.json, or go.mod and falls back to the path)
+auto_hash_source = "path"
+
+# Dim applied colors during a time-of-day window (uncomment to enable)
+# [dimming]
+# start = "21:00"
+# end = "07:00"
+# lightness_scale = 0.7
+# dim_tab = false
+
+# Colors for 'termtint ssh' by host glob; other hosts get a hash-based color
+# [hosts]
+# "prod-*" = "#cc0000"
+
+# Colors by environment variable, as "VAR=value-glob" = color or "auto"
+# ("VAR" alone matches any non-empty value; rules are checked in key order)
+# [env_triggers]
+# "AWS_PROFILE=prod*" = "#cc0000"
+# "KUBECONFIG=*prod*" = "auto"
+
+# Triggers with options of their own ("kind" is "file", "path", "dirname", or
+# "kube-context" for the current kubectl context, and defaults to path for
+# patterns with a / or ~, file otherwise), e.g. Rust projects in oranges. These
+# are checked before the trigger lists above.
+# [[triggers]]
+# match = "Cargo.toml"
+# kind = "file"
+# color = "auto"
+# hue = [15.0, 45.0]
+# toml_key = "workspace"  # Only files defining this key (or contains = "text")
+# background_lightness = 0.22  # Background parameters for this trigger's colors
+# background_saturation = 0.8
+"##,
         defaults.background_lightness,
         defaults.background_saturation,
+        defaults.background_lightness_light,
+        defaults.temperature_shift,
+        defaults.monochrome_lightness_min,
+        defaults.monochrome_lightness_max,
+        defaults.reserved_min_delta,
         defaults.hue_min,
         defaults.hue_max,
         defaults.saturation_min,
         defaults.saturation_max,
-        defaults.lightness
+        defaults.lightness,
+        defaults.hue_spread,
+        defaults.reroll_min_delta,
+        defaults.depth_ramp,
+        defaults.blend_with_parent
     )
 }
 
-/// Template for a config field, used for upgrading existing configs.
-struct FieldTemplate {
-    /// Field name (e.g., "background_saturation")
-    name: &'static str,
-    /// Template lines including comment and commented-out default value
-    template: &'static str,
-    /// Whether this field belongs in the `[auto]` section
-    in_auto_section: bool,
-}
-
-/// All known config fields with their templates.
-/// These are used to add missing fields to existing config files.
-const FIELD_TEMPLATES: &[FieldTemplate] = &[
-    // Top-level fields (in order they should appear)
-    FieldTemplate {
-        name: "background_lightness",
-        template: "# Fixed lightness for darkened backgrounds (0.0 to 1.0)\n# background_lightness = 0.18",
-        in_auto_section: false,
-    },
-    FieldTemplate {
-        name: "background_saturation",
-        template: "# Saturation multiplier for backgrounds (0.0 to 1.0)\n# 1.0 = preserve original saturation, 0.0 = grayscale\n# background_saturation = 1.00",
-        in_auto_section: false,
-    },
-    FieldTemplate {
-        name: "trigger_files",
-        template: "# Files that trigger automatic color generation when found\n# Examples: [\"Cargo.toml\", \"package.json\", \"go.mod\", \"pyproject.toml\"]\n# trigger_files = []",
-        in_auto_section: false,
-    },
-    FieldTemplate {
-        name: "trigger_paths",
-        template: "# Path globs that trigger automatic color generation\n# Directories matching these patterns are treated as having 'auto' in .termtint\n# Supports ~ for home directory. Example: [\"~/Code/*\", \"~/Projects/*\"]\n# trigger_paths = []",
-        in_auto_section: false,
-    },
-    FieldTemplate {
-        name: "color_format",
-        template: "# Color format for display: \"hex\", \"hsl\", or \"rgb\"\n# color_format = \"hex\"",
-        in_auto_section: false,
-    },
-    // [auto] section fields
-    FieldTemplate {
-        name: "hue_min",
-        template: "# Hue range in degrees (0.0 to 360.0)\n# hue_min = 0.0",
-        in_auto_section: true,
-    },
-    FieldTemplate {
-        name: "hue_max",
-        template: "# hue_max = 360.0",
-        in_auto_section: true,
-    },
-    FieldTemplate {
-        name: "saturation_min",
-        template: "# Saturation range (0.0 to 1.0)\n# saturation_min = 0.7",
-        in_auto_section: true,
-    },
-    FieldTemplate {
-        name: "saturation_max",
-        template: "# saturation_max = 0.9",
-        in_auto_section: true,
-    },
-    FieldTemplate {
-        name: "lightness",
-        template: "# Lightness for generated tab colors (0.0 to 1.0)\n# lightness = 0.55",
-        in_auto_section: true,
-    },
-];
+/// A table header line's name: `auto` for `[auto]`, `triggers` for `[[triggers]]`.
+fn section_header(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let inner = match line.strip_prefix("[[") {
+        Some(rest) => rest.split_once("]]")?.0,
+        None => line.strip_prefix('[')?.split_once(']')?.0,
+    };
+    Some(inner.trim())
+}
 
-/// Detect which config fields are present in the content.
-/// Returns (set of field names, whether `[auto]` section exists, line number of `[auto]` header).
-fn detect_present_fields(
-    content: &str,
-) -> (std::collections::HashSet<String>, bool, Option<usize>) {
-    use std::collections::HashSet;
-
-    let known_fields: HashSet<&str> = FIELD_TEMPLATES.iter().map(|f| f.name).collect();
-    let mut found_fields = HashSet::new();
-    let mut has_auto_section = false;
-    let mut auto_section_line = None;
-
-    for (line_num, line) in content.lines().enumerate() {
-        let trimmed = line.trim();
-
-        // Check for [auto] section header
-        if trimmed == "[auto]" {
-            has_auto_section = true;
-            auto_section_line = Some(line_num);
-            continue;
-        }
+/// The name a key line assigns, whether set or commented out (`# key = value`).
+fn assigned_key(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('#').map_or(line, str::trim_start);
+    let (key, _) = line.split_once('=')?;
+    Some(key.trim())
+}
 
-        // Check for field assignment (active or commented)
-        // Handles both "field = value" and "# field = value"
-        let check_line = if let Some(stripped) = trimmed.strip_prefix('#') {
-            stripped.trim_start()
-        } else {
-            trimmed
-        };
+/// Where a config file's top-level keys and `[auto]` table end, and which known
+/// keys each already has (set or commented out).
+struct ConfigLayout {
+    top_level: std::collections::HashSet<String>,
+    auto: std::collections::HashSet<String>,
+    /// The line missing top-level keys go before: the first table header, or
+    /// rather the comments describing it
+    top_level_end: usize,
+    /// The line missing `[auto]` keys go before: the next table header after
+    /// `[auto]` (including commented-out ones like `# [dimming]`) or the
+    /// comments describing it. None without an `[auto]` table.
+    auto_end: Option<usize>,
+}
 
-        if let Some(eq_pos) = check_line.find('=') {
-            let field_name = check_line[..eq_pos].trim();
-            if known_fields.contains(field_name) {
-                found_fields.insert(field_name.to_string());
+impl ConfigLayout {
+    fn scan(lines: &[&str]) -> ConfigLayout {
+        let known = |fields: &[ConfigField], key: &str| {
+            fields
+                .iter()
+                .any(|field| field.is_value() && field.name == key)
+        };
+        let mut layout = ConfigLayout {
+            top_level: Default::default(),
+            auto: Default::default(),
+            top_level_end: lines.len(),
+            auto_end: None,
+        };
+        // The table the keys being read belong to: None at the top level
+        let mut section: Option<&str> = None;
+        let mut first_header = None;
+        let mut auto_boundary = None;
+
+        for (i, line) in lines.iter().enumerate() {
+            if let Some(name) = section_header(line) {
+                first_header.get_or_insert(i);
+                if section == Some("auto") {
+                    auto_boundary.get_or_insert(i);
+                }
+                section = Some(name);
+                if name == "auto" {
+                    layout.auto_end = Some(lines.len());
+                    auto_boundary = None;
+                }
+                continue;
             }
+            let commented_header = line
+                .trim()
+                .strip_prefix('#')
+                .and_then(section_header)
+                .is_some();
+            if commented_header && section == Some("auto") {
+                auto_boundary.get_or_insert(i);
+            }
+            let Some(key) = assigned_key(line) else {
+                continue;
+            };
+            match section {
+                None if known(CONFIG_FIELDS, key) => {
+                    layout.top_level.insert(key.to_string());
+                }
+                Some("auto") if auto_boundary.is_none() && known(AUTO_FIELDS, key) => {
+                    layout.auto.insert(key.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(header) = first_header {
+            layout.top_level_end = section_start(lines, header);
+        }
+        if let (Some(_), Some(boundary)) = (layout.auto_end, auto_boundary) {
+            layout.auto_end = Some(section_start(lines, boundary));
+        }
+        layout
+    }
+}
+
+/// The first line of the comment block (and blank lines) leading up to the
+/// header at `header`, so keys inserted before a table don't split it from its
+/// description.
+fn section_start(lines: &[&str], header: usize) -> usize {
+    let mut start = header;
+    while start > 0 && lines[start - 1].trim_start().starts_with('#') {
+        start -= 1;
+    }
+    while start > 0 && lines[start - 1].trim().is_empty() {
+        start -= 1;
+    }
+    start
+}
+
+/// Insert the commented-out templates for `fields` at line `at`, separated from
+/// the surrounding lines by blank lines.
+fn insert_templates(lines: &mut Vec<String>, at: usize, fields: &[&ConfigField]) {
+    let mut block: Vec<String> = Vec::new();
+    if at > 0 && !lines[at - 1].trim().is_empty() && section_header(&lines[at - 1]).is_none() {
+        block.push(String::new());
+    }
+    for (i, spec) in fields.iter().enumerate() {
+        if i > 0 {
+            block.push(String::new());
+        }
+        block.extend(spec.template().lines().map(|s| s.to_string()));
+    }
+    if lines.get(at).is_some_and(|line| !line.trim().is_empty()) {
+        block.push(String::new());
+    }
+    lines.splice(at..at, block);
+}
+
+/// What `termtint config --check` found wrong with a config file.
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigCheck {
+    /// Keys termtint doesn't recognize
+    pub unknown_keys: Vec<schema::UnknownKey>,
+    /// The fixes loading the file makes to its numbers, as `normalize_numbers`
+    /// describes them
+    pub invalid_values: Vec<String>,
+}
+
+impl ConfigCheck {
+    pub fn is_ok(&self) -> bool {
+        self.unknown_keys.is_empty() && self.invalid_values.is_empty()
+    }
+}
+
+/// Check the config file at `path` for keys termtint doesn't recognize and
+/// numbers it would have to fix. A missing file is fine; one that isn't valid
+/// TOML is an error.
+pub fn check_config_file(path: &Path) -> Result<ConfigCheck, TermtintError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ConfigCheck::default()),
+        Err(e) => {
+            return Err(TermtintError::io(
+                format!("Error reading {}", path.display()),
+                &e,
+            ))
         }
+    };
+    let mut table: toml::Table = content.parse().map_err(|e| {
+        TermtintError::parse(
+            Some(path.to_path_buf()),
+            format!("Error parsing {}: {}", path.display(), e),
+        )
+    })?;
+    Ok(ConfigCheck {
+        unknown_keys: schema::unknown_keys(&table),
+        invalid_values: normalize_numbers(&mut table)
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    })
+}
+
+/// The first line of the comment block `upgrade_config` lists unrecognized keys in.
+const UNKNOWN_KEYS_HEADER: &str =
+    "# Unrecognized keys, which termtint ignores (typos, or keys from a newer version):";
+
+/// The comment block listing `unknown` keys, or nothing when there are none.
+fn unknown_keys_block(unknown: &[schema::UnknownKey]) -> Vec<String> {
+    if unknown.is_empty() {
+        return Vec::new();
     }
+    std::iter::once(UNKNOWN_KEYS_HEADER.to_string())
+        .chain(unknown.iter().map(|key| format!("#   {}", key)))
+        .collect()
+}
 
-    (found_fields, has_auto_section, auto_section_line)
+/// The lines of an earlier upgrade's unrecognized keys block, with the blank line
+/// before it.
+fn find_unknown_keys_block(lines: &[&str]) -> Option<std::ops::Range<usize>> {
+    let header = lines.iter().position(|line| *line == UNKNOWN_KEYS_HEADER)?;
+    let end = header
+        + 1
+        + lines[header + 1..]
+            .iter()
+            .take_while(|line| line.starts_with("#   "))
+            .count();
+    let start = match header.checked_sub(1) {
+        Some(blank) if lines[blank].trim().is_empty() => blank,
+        _ => header,
+    };
+    Some(start..end)
 }
 
 /// Upgrade an existing config file by adding missing fields as commented-out defaults.
 /// Preserves all existing content and only adds fields that are completely absent.
+///
+/// Missing top-level keys go before the first table, and missing `[auto]` keys at
+/// the end of the `[auto]` table wherever it is in the file, so neither lands in
+/// another table. Keys termtint doesn't know are left in place and listed in a
+/// comment block at the end, which later upgrades keep up to date.
 pub fn upgrade_config(content: &str) -> String {
-    let (found_fields, _has_auto_section, auto_section_line) = detect_present_fields(content);
+    let mut original: Vec<&str> = content.lines().collect();
+    // Unparseable files keep any earlier list, since it can't be checked
+    let mut unknown_block = Vec::new();
+    let mut unknown_changed = false;
+    if let Ok(table) = content.parse::<toml::Table>() {
+        unknown_block = unknown_keys_block(&schema::unknown_keys(&table));
+        let old_block = find_unknown_keys_block(&original).unwrap_or(0..0);
+        let old_lines = original[old_block.clone()]
+            .iter()
+            .filter(|line| !line.trim().is_empty());
+        unknown_changed = !old_lines.eq(unknown_block.iter());
+        // Taken out and added back at the end, after any keys added below
+        original.drain(old_block);
+    }
+    let layout = ConfigLayout::scan(&original);
 
     // Find missing fields
-    let missing_top_level: Vec<&FieldTemplate> = FIELD_TEMPLATES
+    let missing_top_level: Vec<&ConfigField> = CONFIG_FIELDS
         .iter()
-        .filter(|f| !f.in_auto_section && !found_fields.contains(f.name))
+        .filter(|f| f.is_value() && !layout.top_level.contains(f.name))
         .collect();
 
-    let missing_auto: Vec<&FieldTemplate> = FIELD_TEMPLATES
+    let missing_auto: Vec<&ConfigField> = AUTO_FIELDS
         .iter()
-        .filter(|f| f.in_auto_section && !found_fields.contains(f.name))
+        .filter(|f| f.is_value() && !layout.auto.contains(f.name))
         .collect();
 
     // If nothing is missing, return original content
-    if missing_top_level.is_empty() && missing_auto.is_empty() {
+    if missing_top_level.is_empty() && missing_auto.is_empty() && !unknown_changed {
         return content.to_string();
     }
 
-    let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
+    let mut lines: Vec<String> = original.iter().map(|s| s.to_string()).collect();
 
-    // Insert top-level fields before [auto] section or at end
+    // [auto] comes after the top-level keys, so it's filled in first to keep the
+    // top-level insertion point where it was
+    if let Some(auto_end) = layout.auto_end.filter(|_| !missing_auto.is_empty()) {
+        insert_templates(&mut lines, auto_end, &missing_auto);
+    }
     if !missing_top_level.is_empty() {
-        let insert_point = auto_section_line.unwrap_or(lines.len());
-
-        let mut to_insert: Vec<String> = Vec::new();
-
-        // Add blank line separator if needed
-        if insert_point > 0
-            && !lines
-                .get(insert_point.saturating_sub(1))
-                .map(|s| s.trim().is_empty())
-                .unwrap_or(true)
-        {
-            to_insert.push(String::new());
-        }
-
-        for (i, spec) in missing_top_level.iter().enumerate() {
-            if i > 0 {
-                to_insert.push(String::new());
-            }
-            to_insert.extend(spec.template.lines().map(|s| s.to_string()));
-        }
-
-        // Add trailing blank line if inserting before [auto]
-        if auto_section_line.is_some() {
-            to_insert.push(String::new());
-        }
-
-        // Insert the lines
-        for (i, line) in to_insert.into_iter().enumerate() {
-            lines.insert(insert_point + i, line);
-        }
+        insert_templates(&mut lines, layout.top_level_end, &missing_top_level);
     }
-
-    // Insert [auto] section fields
-    if !missing_auto.is_empty() {
-        // Recalculate auto section position after possible top-level insertions
-        let (_, has_auto_now, _auto_line_now) = detect_present_fields(&lines.join("\n"));
-
-        if !has_auto_now {
-            // Need to create [auto] section
-            if !lines.last().map(|s| s.trim().is_empty()).unwrap_or(true) {
-                lines.push(String::new());
-            }
-            lines.push("# Auto color generation parameters".to_string());
-            lines.push("[auto]".to_string());
-        }
-
-        // Find end of [auto] section (end of file since it's the last section)
-        let auto_end = lines.len();
-
-        let mut to_insert: Vec<String> = Vec::new();
-        for (i, spec) in missing_auto.iter().enumerate() {
-            if i > 0 {
-                to_insert.push(String::new());
-            }
-            to_insert.extend(spec.template.lines().map(|s| s.to_string()));
+    if layout.auto_end.is_none() && !missing_auto.is_empty() {
+        // Need to create [auto] section
+        if !lines.last().map(|s| s.trim().is_empty()).unwrap_or(true) {
+            lines.push(String::new());
         }
-
-        for line in to_insert {
-            lines.insert(auto_end, line);
+        lines.push("# Auto color generation parameters".to_string());
+        lines.push("[auto]".to_string());
+        let end = lines.len();
+        insert_templates(&mut lines, end, &missing_auto);
+    }
+    if !unknown_block.is_empty() {
+        if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+            lines.push(String::new());
         }
+        lines.extend(unknown_block);
     }
 
     // Ensure file ends with newline
@@ -485,6 +2073,10 @@ mod tests {
     use std::fs;
     use tempfile::TempDir;
 
+    fn patterns(config: &UserConfig, kind: TriggerKind) -> Vec<&str> {
+        config.trigger_patterns(kind).collect()
+    }
+
     #[test]
     fn test_default_config() {
         let config = UserConfig::default();
@@ -495,8 +2087,8 @@ mod tests {
         assert_eq!(config.lightness, 0.55);
         assert_eq!(config.background_lightness, 0.18);
         assert_eq!(config.background_saturation, 1.0);
-        assert!(config.trigger_files.is_empty());
-        assert!(config.trigger_paths.is_empty());
+        assert!(patterns(&config, TriggerKind::File).is_empty());
+        assert!(patterns(&config, TriggerKind::Path).is_empty());
     }
 
     #[test]
@@ -504,12 +2096,12 @@ mod tests {
         let temp = TempDir::new().unwrap();
         let config_path = config_file_path_for_home(temp.path());
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         // Should return defaults
         assert_eq!(config.hue_min, 0.0);
         assert_eq!(config.background_lightness, 0.18);
-        assert!(config.trigger_files.is_empty());
+        assert!(patterns(&config, TriggerKind::File).is_empty());
     }
 
     #[test]
@@ -519,11 +2111,11 @@ mod tests {
         fs::create_dir_all(config_path.parent().unwrap()).unwrap();
         fs::write(&config_path, "").unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         // Should return defaults
         assert_eq!(config.background_lightness, 0.18);
-        assert!(config.trigger_files.is_empty());
+        assert!(patterns(&config, TriggerKind::File).is_empty());
     }
 
     #[test]
@@ -537,12 +2129,12 @@ background_lightness = 0.15
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         // Should override only specified values
         assert_eq!(config.background_lightness, 0.15);
         assert_eq!(config.hue_min, 0.0); // default
-        assert!(config.trigger_files.is_empty()); // default
+        assert!(patterns(&config, TriggerKind::File).is_empty()); // default
     }
 
     #[test]
@@ -564,11 +2156,11 @@ lightness = 0.50
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         assert_eq!(config.background_lightness, 0.12);
         assert_eq!(
-            config.trigger_files,
+            patterns(&config, TriggerKind::File),
             vec!["Cargo.toml", "package.json", "pyproject.toml"]
         );
         assert_eq!(config.hue_min, 10.0);
@@ -591,7 +2183,7 @@ hue_max = 240.0
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         assert_eq!(config.hue_min, 120.0);
         assert_eq!(config.hue_max, 240.0);
@@ -608,11 +2200,11 @@ hue_max = 240.0
         // Invalid TOML
         fs::write(&config_path, "not valid toml {[}]").unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         // Should return defaults on parse error
         assert_eq!(config.background_lightness, 0.18);
-        assert!(config.trigger_files.is_empty());
+        assert!(patterns(&config, TriggerKind::File).is_empty());
     }
 
     #[test]
@@ -626,9 +2218,9 @@ trigger_files = []
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
-        assert!(config.trigger_files.is_empty());
+        assert!(patterns(&config, TriggerKind::File).is_empty());
     }
 
     #[test]
@@ -645,6 +2237,34 @@ trigger_files = []
         );
     }
 
+    #[test]
+    fn test_config_file_path_precedence() {
+        let home = Path::new("/home/me");
+        let ctx = Context::new(home, Path::new("/work"));
+        assert_eq!(
+            config_file_path(&ctx),
+            home.join(".config/termtint/config.toml")
+        );
+
+        // TERMTINT_CONFIG overrides the default, relative to the working directory
+        let ctx = ctx.with_var("TERMTINT_CONFIG", "demo.toml");
+        assert_eq!(config_file_path(&ctx), Path::new("/work/demo.toml"));
+        let ctx = ctx.with_var("TERMTINT_CONFIG", "/etc/termtint.toml");
+        assert_eq!(config_file_path(&ctx), Path::new("/etc/termtint.toml"));
+
+        // --config overrides both
+        let mut ctx = ctx;
+        ctx.config_file = Some(PathBuf::from("./flag.toml"));
+        assert_eq!(config_file_path(&ctx), Path::new("/work/./flag.toml"));
+
+        // An empty TERMTINT_CONFIG is ignored
+        let ctx = Context::new(home, Path::new("/work")).with_var("TERMTINT_CONFIG", "");
+        assert_eq!(
+            config_file_path(&ctx),
+            home.join(".config/termtint/config.toml")
+        );
+    }
+
     #[test]
     fn test_default_config_toml() {
         let toml = default_config_toml();
@@ -652,13 +2272,46 @@ trigger_files = []
         // Should contain all expected sections
         assert!(toml.contains("background_lightness = 0.18"));
         assert!(toml.contains("background_saturation = 1.00"));
+        assert!(toml.contains("background_mode = \"darken\""));
+        assert!(toml.contains("background_lightness_light = 0.93"));
+        assert!(toml.contains("temperature_shift = 0.0"));
+        assert!(toml.contains("force_256 = false"));
+        assert!(toml.contains("generate_palette = false"));
+        assert!(toml.contains("monochrome = false"));
+        assert!(toml.contains("monochrome_lightness_min = 0.35"));
+        assert!(toml.contains("monochrome_lightness_max = 0.80"));
+        assert!(toml.contains("reserved_colors = []"));
+        assert!(toml.contains("reserved_min_delta = 0.15"));
         assert!(toml.contains("trigger_files = []"));
+        assert!(toml.contains("trigger_files_case_sensitive = true"));
+        assert!(toml.contains("exclude_paths = []"));
+        assert!(toml.contains("walk_boundary = \"root\""));
+        assert!(
+            toml.contains("trigger_priority = [\"termtint\", \"paths\", \"files\", \"dirnames\"]")
+        );
+        assert!(toml.contains("trigger_dirnames = []"));
+        assert!(toml.contains("max_walk_depth = 0"));
+        assert!(toml.contains("respect_git_root = false"));
+        assert!(toml.contains("auto_from_vscode = false"));
+        assert!(toml.contains("env_trigger_priority = \"before\""));
+        assert!(toml.contains("hyperlinks = \"auto\""));
+        assert!(toml.contains("session_ttl = \"24h\""));
+        assert!(toml.contains("cleanup_interval = \"1h\""));
+        assert!(toml.contains("color_cache = false"));
+        assert!(toml.contains("strict = false"));
+        assert!(toml.contains("# state_dir = \"~/.cache/termtint\""));
         assert!(toml.contains("[auto]"));
+        assert!(toml.contains("# preset = \"vivid\""));
         assert!(toml.contains("hue_min = 0.0"));
         assert!(toml.contains("hue_max = 360.0"));
         assert!(toml.contains("saturation_min = 0.7"));
         assert!(toml.contains("saturation_max = 0.9"));
         assert!(toml.contains("lightness = 0.55"));
+        assert!(toml.contains("hue_spread = 30.0"));
+        assert!(toml.contains("depth_ramp = 0.00"));
+        assert!(toml.contains("blend_with_parent = 0.0"));
+        assert!(toml.contains("hash_include_hostname = false"));
+        assert!(toml.contains("auto_hash_source = \"path\""));
         assert!(toml.contains("color_format"));
 
         // Should contain helpful comments
@@ -683,7 +2336,32 @@ trigger_files = []
             parsed_toml.background_saturation.unwrap(),
             defaults.background_saturation
         );
-        assert_eq!(parsed_toml.trigger_files.unwrap(), defaults.trigger_files);
+        assert_eq!(
+            parsed_toml.trigger_files.unwrap(),
+            patterns(&defaults, TriggerKind::File)
+        );
+        assert_eq!(
+            parsed_toml.trigger_files_case_sensitive.unwrap(),
+            defaults.trigger_files_case_sensitive
+        );
+        assert_eq!(
+            parsed_toml.monochrome_lightness_min.unwrap(),
+            defaults.monochrome_lightness_min
+        );
+        assert_eq!(
+            parsed_toml.monochrome_lightness_max.unwrap(),
+            defaults.monochrome_lightness_max
+        );
+        assert_eq!(
+            parse_session_ttl(&parsed_toml.session_ttl.unwrap()),
+            Ok(defaults.session_ttl)
+        );
+        assert_eq!(
+            parse_duration(&parsed_toml.cleanup_interval.unwrap()),
+            Ok(defaults.cleanup_interval)
+        );
+        assert_eq!(parsed_toml.color_cache.unwrap(), defaults.color_cache);
+        assert_eq!(parsed_toml.strict.unwrap(), defaults.strict);
 
         let auto = parsed_toml.auto.expect("auto section should be present");
         assert_eq!(auto.hue_min.unwrap(), defaults.hue_min);
@@ -691,6 +2369,10 @@ trigger_files = []
         assert_eq!(auto.saturation_min.unwrap(), defaults.saturation_min);
         assert_eq!(auto.saturation_max.unwrap(), defaults.saturation_max);
         assert_eq!(auto.lightness.unwrap(), defaults.lightness);
+        assert_eq!(auto.hue_spread.unwrap(), defaults.hue_spread);
+        assert_eq!(auto.reroll_min_delta.unwrap(), defaults.reroll_min_delta);
+        assert_eq!(auto.depth_ramp.unwrap(), defaults.depth_ramp);
+        assert_eq!(auto.blend_with_parent.unwrap(), defaults.blend_with_parent);
     }
 
     #[test]
@@ -704,7 +2386,7 @@ color_format = "hex"
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         assert!(matches!(config.color_format, ColorFormat::Hex));
     }
@@ -720,7 +2402,7 @@ color_format = "hsl"
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         assert!(matches!(config.color_format, ColorFormat::Hsl));
     }
@@ -736,7 +2418,7 @@ color_format = "rgb"
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         assert!(matches!(config.color_format, ColorFormat::Rgb));
     }
@@ -752,7 +2434,7 @@ color_format = "invalid"
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         // Should fall back to hex (default) on invalid format
         assert!(matches!(config.color_format, ColorFormat::Hex));
@@ -769,7 +2451,7 @@ color_format = "HSL"
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         // Should handle uppercase
         assert!(matches!(config.color_format, ColorFormat::Hsl));
@@ -786,7 +2468,7 @@ background_saturation = 0.5
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         assert_eq!(config.background_saturation, 0.5);
         // Other values should be defaults
@@ -805,7 +2487,7 @@ background_saturation = 2.0
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
         assert_eq!(config.background_saturation, 1.0);
     }
@@ -821,31 +2503,294 @@ trigger_paths = ["~/Code/*", "~/Projects/*"]
 "#;
         fs::write(&config_path, content).unwrap();
 
-        let config = load_user_config_from(&config_path);
+        let config = load_user_config_from(&config_path).unwrap();
 
-        assert_eq!(config.trigger_paths, vec!["~/Code/*", "~/Projects/*"]);
+        assert_eq!(
+            patterns(&config, TriggerKind::Path),
+            vec!["~/Code/*", "~/Projects/*"]
+        );
     }
 
     #[test]
-    fn test_default_config_has_empty_trigger_paths() {
-        let config = UserConfig::default();
-        assert!(config.trigger_paths.is_empty());
+    fn test_load_config_with_trigger_dirnames() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        fs::write(
+            &config_path,
+            "trigger_dirnames = [\"prod\", \"release-*\"]\n",
+        )
+        .unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(
+            patterns(&config, TriggerKind::Dirname),
+            vec!["prod", "release-*"]
+        );
+        assert!(patterns(&UserConfig::default(), TriggerKind::Dirname).is_empty());
     }
 
-    // Tests for upgrade_config functionality
+    #[test]
+    fn test_validate_glob() {
+        for pattern in [
+            "~/Code/*",
+            "!~/Code/tmp/*",
+            "*.cabal",
+            "release-[0-9]*",
+            "~/a/**",
+        ] {
+            assert_eq!(validate_glob(pattern), Ok(()), "{}", pattern);
+        }
+        for pattern in [
+            "~/Code/[",
+            "!~/Code/[",
+            "[a-",
+            "~/Code/***",
+            "~/Code/a**",
+            "[]",
+        ] {
+            assert!(validate_glob(pattern).is_err(), "{}", pattern);
+        }
+    }
 
     #[test]
-    fn test_upgrade_adds_missing_field() {
-        // Config with only background_lightness - should add background_saturation
-        let content = r#"background_lightness = 0.15
-"#;
-        let upgraded = upgrade_config(content);
+    fn test_load_config_skips_invalid_globs() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
 
-        // Should contain the original value
-        assert!(upgraded.contains("background_lightness = 0.15"));
+        fs::write(
+            &config_path,
+            r#"trigger_files = ["Cargo.toml", "*.[ch"]
+trigger_paths = ["~/Code/[", "~/Code/*", "!~/Code/tmp/***"]
+trigger_dirnames = ["prod", "release-[0-"]
+exclude_paths = ["~/Code/a**", "~/Code/archive/**"]
 
-        // Should add background_saturation as a comment
-        assert!(upgraded.contains("# background_saturation = 1.00"));
+[[triggers.file]]
+name = "[abc"
+"#,
+        )
+        .unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(patterns(&config, TriggerKind::File), vec!["Cargo.toml"]);
+        assert_eq!(patterns(&config, TriggerKind::Path), vec!["~/Code/*"]);
+        assert_eq!(patterns(&config, TriggerKind::Dirname), vec!["prod"]);
+        assert_eq!(config.exclude_paths, vec!["~/Code/archive/**"]);
+        assert!(config.trigger(TriggerKind::File, "[abc").is_none());
+    }
+
+    #[test]
+    fn test_drop_invalid_globs_reports_every_pattern() {
+        let mut config = UserConfig {
+            triggers: vec![
+                Trigger::new(TriggerKind::Path, "~/Code/["),
+                Trigger::new(TriggerKind::Path, "~/Code/*"),
+            ],
+            exclude_paths: vec!["~/a**".to_string()],
+            ..UserConfig::default()
+        };
+
+        let error = drop_invalid_globs(&mut config).unwrap_err().to_string();
+
+        assert!(error.contains("path trigger '~/Code/['"));
+        assert!(error.contains("exclude_paths '~/a**'"));
+        assert!(!error.contains("~/Code/*"));
+        assert_eq!(drop_invalid_globs(&mut config), Ok(()));
+    }
+
+    #[test]
+    fn test_load_config_with_strict() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        fs::write(
+            &config_path,
+            "strict = true\ntrigger_paths = [\"~/Code/*\"]\n",
+        )
+        .unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert!(config.strict);
+        assert!(!UserConfig::default().strict);
+
+        // An invalid glob is then an error for the caller to report
+        fs::write(
+            &config_path,
+            "strict = true\ntrigger_paths = [\"~/Code/[\"]\n",
+        )
+        .unwrap();
+        let error = load_user_config_from(&config_path).unwrap_err();
+        assert_eq!(error.exit_code(), crate::error::EXIT_CONFIG);
+        assert!(!error.to_string().contains("skipping"));
+    }
+
+    #[test]
+    fn test_load_config_with_exclude_paths() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        fs::write(
+            &config_path,
+            "exclude_paths = [\"~/Code/scratch\", \"~/Code/archive/**\"]\n",
+        )
+        .unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(
+            config.exclude_paths,
+            vec!["~/Code/scratch", "~/Code/archive/**"]
+        );
+        assert!(UserConfig::default().exclude_paths.is_empty());
+    }
+
+    #[test]
+    fn test_parse_trigger_priority() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            parse_trigger_priority(&names(&["termtint", "paths", "files", "dirnames"])),
+            Ok(DEFAULT_TRIGGER_PRIORITY.to_vec())
+        );
+        assert_eq!(
+            parse_trigger_priority(&names(&["Files", "termtint", "paths"])),
+            Ok(vec![
+                SourceKind::Files,
+                SourceKind::Termtint,
+                SourceKind::Paths,
+                SourceKind::Dirnames
+            ])
+        );
+        // Omitted kinds follow in the default order; duplicates are ignored
+        assert_eq!(
+            parse_trigger_priority(&names(&["files", "files"])),
+            Ok(vec![
+                SourceKind::Files,
+                SourceKind::Termtint,
+                SourceKind::Paths,
+                SourceKind::Dirnames
+            ])
+        );
+        assert!(parse_trigger_priority(&names(&["dirs"])).is_err());
+    }
+
+    #[test]
+    fn test_load_config_with_trigger_priority() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        fs::write(&config_path, "trigger_priority = [\"files\", \"paths\"]\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path)
+                .unwrap()
+                .trigger_priority,
+            vec![
+                SourceKind::Files,
+                SourceKind::Paths,
+                SourceKind::Termtint,
+                SourceKind::Dirnames
+            ]
+        );
+
+        fs::write(&config_path, "trigger_priority = [\"files\", \"dirs\"]\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path)
+                .unwrap()
+                .trigger_priority,
+            DEFAULT_TRIGGER_PRIORITY.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_load_config_with_max_walk_depth() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        fs::write(&config_path, "max_walk_depth = 8\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path).unwrap().max_walk_depth,
+            Some(8)
+        );
+
+        // Zero means unlimited, like leaving it out
+        fs::write(&config_path, "max_walk_depth = 0\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path).unwrap().max_walk_depth,
+            None
+        );
+        assert_eq!(UserConfig::default().max_walk_depth, None);
+    }
+
+    #[test]
+    fn test_load_config_with_respect_git_root() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        fs::write(&config_path, "respect_git_root = true\n").unwrap();
+
+        assert!(
+            load_user_config_from(&config_path)
+                .unwrap()
+                .respect_git_root
+        );
+        assert!(!UserConfig::default().respect_git_root);
+    }
+
+    #[test]
+    fn test_load_config_with_walk_boundary() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        fs::write(&config_path, "walk_boundary = \"home\"\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path).unwrap().walk_boundary,
+            WalkBoundary::Home
+        );
+
+        fs::write(&config_path, "walk_boundary = \"Device\"\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path).unwrap().walk_boundary,
+            WalkBoundary::Device
+        );
+
+        fs::write(&config_path, "walk_boundary = \"mountpoint\"\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path).unwrap().walk_boundary,
+            WalkBoundary::Root
+        );
+    }
+
+    #[test]
+    fn test_default_config_has_empty_trigger_paths() {
+        let config = UserConfig::default();
+        assert!(patterns(&config, TriggerKind::Path).is_empty());
+    }
+
+    // Tests for upgrade_config functionality
+
+    #[test]
+    fn test_upgrade_adds_missing_field() {
+        // Config with only background_lightness - should add background_saturation
+        let content = r#"background_lightness = 0.15
+"#;
+        let upgraded = upgrade_config(content);
+
+        // Should contain the original value
+        assert!(upgraded.contains("background_lightness = 0.15"));
+
+        // Should add background_saturation as a comment
+        assert!(upgraded.contains("# background_saturation = 1.00"));
     }
 
     #[test]
@@ -940,16 +2885,48 @@ hue_max = 60.0
         // A config with all fields present (active)
         let content = r#"background_lightness = 0.18
 background_saturation = 1.00
+background_mode = "darken"
+background_lightness_light = 0.93
+temperature_shift = 0.0
+force_256 = false
+generate_palette = false
+monochrome = false
+monochrome_lightness_min = 0.35
+monochrome_lightness_max = 0.80
+reserved_colors = []
+reserved_min_delta = 0.15
 trigger_files = []
+trigger_files_case_sensitive = true
 trigger_paths = []
+trigger_dirnames = []
+exclude_paths = []
+trigger_priority = ["termtint", "paths", "files", "dirnames"]
+walk_boundary = "root"
+max_walk_depth = 0
+respect_git_root = false
+auto_from_vscode = false
+env_trigger_priority = "before"
 color_format = "hex"
+hyperlinks = "auto"
+session_ttl = "24h"
+cleanup_interval = "1h"
+color_cache = false
+strict = false
+# state_dir = "~/.cache/termtint"
 
 [auto]
+preset = "vivid"
 hue_min = 0.0
 hue_max = 360.0
 saturation_min = 0.7
 saturation_max = 0.9
 lightness = 0.55
+hue_spread = 30.0
+reroll_min_delta = 0.12
+depth_ramp = 0.00
+blend_with_parent = 0.0
+hash_include_hostname = false
+auto_hash_source = "path"
 "#;
         let upgraded = upgrade_config(content);
 
@@ -982,4 +2959,1094 @@ background_saturation = 0.6
         assert!(upgraded.contains("background_lightness = 0.15"));
         assert!(upgraded.contains("background_saturation = 0.6"));
     }
+
+    #[test]
+    fn test_upgrade_default_config_unchanged() {
+        let content = default_config_toml();
+        assert_eq!(upgrade_config(&content), content);
+    }
+
+    #[test]
+    fn test_upgrade_auto_followed_by_table() {
+        let content = r#"color_format = "hex"
+
+[auto]
+hue_min = 30.0
+
+# Project themes
+[themes.dark]
+lightness = 0.3
+"#;
+        let upgraded = upgrade_config(content);
+        let auto = upgraded.find("[auto]").unwrap();
+        let themes = upgraded.find("# Project themes").unwrap();
+
+        // Missing [auto] keys go inside [auto], before the next table and its
+        // comment; the next table's `lightness` isn't [auto]'s
+        for key in ["# saturation_min = 0.7", "# lightness = 0.55", "# preset ="] {
+            let at = upgraded.find(key).unwrap();
+            assert!(auto < at && at < themes, "{} misplaced:\n{}", key, upgraded);
+        }
+        // `themes` isn't a termtint table, so it's listed after it
+        assert!(upgraded.ends_with(&format!(
+            "\n\n# Project themes\n[themes.dark]\nlightness = 0.3\n\n{}\n#   themes\n",
+            UNKNOWN_KEYS_HEADER
+        )));
+        assert!(upgraded.contains("[auto]\nhue_min = 30.0\n\n"));
+        // Missing top-level keys stay at the top level
+        assert!(upgraded.find("# background_lightness = 0.18").unwrap() < auto);
+
+        // The inserted keys, uncommented, land in the tables they belong to
+        let table: toml::Table = upgraded
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(rest)
+                    if rest.split_once(" = ").is_some_and(|(key, _)| {
+                        CONFIG_FIELDS
+                            .iter()
+                            .chain(AUTO_FIELDS)
+                            .any(|f| f.name == key)
+                    }) =>
+                {
+                    rest
+                }
+                _ => line,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            .parse()
+            .unwrap();
+        assert!(table["auto"].get("saturation_min").is_some());
+        assert!(table["themes"]["dark"].get("saturation_min").is_none());
+        assert!(table.get("background_lightness").is_some());
+    }
+
+    #[test]
+    fn test_upgrade_tables_in_reversed_order() {
+        let content = r#"# My config
+background_lightness = 0.15
+
+[dimming]
+start = "21:00"
+end = "07:00"
+
+# Auto color generation parameters
+[auto]
+hue_min = 30.0
+"#;
+        let upgraded = upgrade_config(content);
+        let dimming = upgraded.find("[dimming]").unwrap();
+        let auto = upgraded.find("[auto]").unwrap();
+
+        let top_level = upgraded.find("# background_saturation = 1.00").unwrap();
+        assert!(top_level < dimming, "{}", upgraded);
+        assert!(upgraded
+            .contains("background_lightness = 0.15\n\n# Saturation multiplier for backgrounds"));
+        assert!(upgraded.contains("\n\n[dimming]\nstart = \"21:00\""));
+        assert!(upgraded.contains("end = \"07:00\"\n\n# Auto color generation parameters\n[auto]"));
+        let saturation_min = upgraded.find("# saturation_min = 0.7").unwrap();
+        assert!(auto < saturation_min, "{}", upgraded);
+        assert_eq!(upgraded.matches("[auto]").count(), 1);
+
+        // Templates keep their comment lines in order
+        assert!(
+            upgraded.contains("# Saturation range (0.0 to 1.0)\n# saturation_min = 0.7\n"),
+            "{}",
+            upgraded
+        );
+    }
+
+    #[test]
+    fn test_parse_failure_warning_names_file_and_error() {
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join("config.toml");
+        fs::write(&config_path, "color_format = \"hex\"\nstrict = \n").unwrap();
+
+        let (config, warnings) = read_user_config(&config_path).unwrap();
+        assert_eq!(config.color_format, ColorFormat::Hex);
+        assert_eq!(warnings.len(), 1);
+        let expected = format!("failed to parse {} (line 2: ", config_path.display());
+        assert!(warnings[0].starts_with(&expected), "{}", warnings[0]);
+        assert!(
+            warnings[0].ends_with("), using defaults"),
+            "{}",
+            warnings[0]
+        );
+        assert!(!warnings[0].contains('\n'));
+
+        fs::write(&config_path, "color_format = \"cmyk\"\n").unwrap();
+        let (_, warnings) = read_user_config(&config_path).unwrap();
+        assert_eq!(warnings, vec!["invalid color_format 'cmyk', using hex"]);
+    }
+
+    #[test]
+    fn test_upgrade_lists_typo_keys() {
+        let defaults = default_config_toml().replace("\nbackground_lightness = 0.18\n", "\n");
+        let content = format!("backround_lightness = 0.2\n{}", defaults);
+        let upgraded = upgrade_config(&content);
+
+        // The typo stays where it was, and is listed at the end
+        assert!(upgraded.starts_with("backround_lightness = 0.2\n"));
+        assert!(upgraded.ends_with(&format!(
+            "\n\n{}\n#   backround_lightness (did you mean background_lightness?)\n",
+            UNKNOWN_KEYS_HEADER
+        )));
+        assert_eq!(upgrade_config(&upgraded), upgraded);
+
+        // Fixing the typo drops the list on the next upgrade
+        let fixed = upgraded.replacen("backround_lightness", "background_lightness", 1);
+        let refreshed = upgrade_config(&fixed);
+        assert!(!refreshed.contains(UNKNOWN_KEYS_HEADER), "{}", refreshed);
+        let listed = fixed.find(UNKNOWN_KEYS_HEADER).unwrap();
+        assert_eq!(refreshed, fixed[..listed - 1]);
+    }
+
+    #[test]
+    fn test_upgrade_lists_keys_from_newer_versions() {
+        let content = r#"notify_on_change = true
+
+[auto]
+hue_min = 30.0
+hue_curve = "ease"
+"#;
+        let upgraded = upgrade_config(content);
+
+        assert!(upgraded.ends_with(&format!(
+            "\n\n{}\n#   auto.hue_curve\n#   notify_on_change\n",
+            UNKNOWN_KEYS_HEADER
+        )));
+        // Missing [auto] keys still go into [auto], ahead of the list
+        let saturation_min = upgraded.find("# saturation_min = 0.7").unwrap();
+        assert!(saturation_min < upgraded.find(UNKNOWN_KEYS_HEADER).unwrap());
+        assert_eq!(upgrade_config(&upgraded), upgraded);
+    }
+
+    #[test]
+    fn test_check_config_file() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("config.toml");
+        assert_eq!(check_config_file(&path), Ok(ConfigCheck::default()));
+
+        fs::write(&path, "colour_format = \"hsl\"\nstrict = true\n").unwrap();
+        let unknown = check_config_file(&path).unwrap().unknown_keys;
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(
+            unknown[0].to_string(),
+            "colour_format (did you mean color_format?)"
+        );
+
+        fs::write(&path, "lightness = 0.5\n\n[auto]\nlightness = 5.5\n").unwrap();
+        let check = check_config_file(&path).unwrap();
+        assert_eq!(check.unknown_keys[0].path, "lightness");
+        assert_eq!(
+            check.invalid_values,
+            vec!["auto.lightness = 5.5 is outside 0 to 1, using 1"]
+        );
+
+        fs::write(&path, "strict = \n").unwrap();
+        let error = check_config_file(&path).unwrap_err();
+        assert_eq!(error.exit_code(), crate::error::EXIT_PARSE);
+    }
+
+    #[test]
+    fn test_normalize_inverted_pairs() {
+        let normalize = |content: &str| {
+            let mut table: toml::Table = toml::from_str(content).unwrap();
+            let fixes: Vec<String> = normalize_numbers(&mut table)
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            (table, fixes)
+        };
+
+        let (table, fixes) = normalize(
+            "monochrome_lightness_min = 0.9\nmonochrome_lightness_max = 0.2\n\n[auto]\nsaturation_min = 0.9\nsaturation_max = 0.5\n",
+        );
+        assert_eq!(
+            fixes,
+            [
+                "auto.saturation_min = 0.9 is above auto.saturation_max = 0.5, swapping them",
+                "monochrome_lightness_min = 0.9 is above monochrome_lightness_max = 0.2, swapping them",
+            ]
+        );
+        assert_eq!(table["auto"]["saturation_min"].as_float(), Some(0.5));
+        assert_eq!(table["auto"]["saturation_max"].as_float(), Some(0.9));
+        assert_eq!(table["monochrome_lightness_min"].as_float(), Some(0.2));
+
+        // A key left out counts as its default (saturation_max = 0.9)...
+        let (table, fixes) = normalize("[auto]\nsaturation_min = 0.95\n");
+        assert_eq!(
+            fixes,
+            ["auto.saturation_min = 0.95 is above auto.saturation_max = 0.9, swapping them"]
+        );
+        assert_eq!(table["auto"]["saturation_max"].as_float(), Some(0.95));
+        // ...or its preset's (neon's saturation_max is 1.0)
+        let (_, fixes) = normalize("[auto]\npreset = \"neon\"\nsaturation_min = 0.95\n");
+        assert_eq!(fixes, Vec::<String>::new());
+
+        // Inverted hue bounds wrap through 360, and equal bounds are a range of one
+        let (_, fixes) = normalize(
+            "[auto]\nhue_min = 300.0\nhue_max = 40.0\nsaturation_min = 0.8\nsaturation_max = 0.8\n",
+        );
+        assert_eq!(fixes, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_load_config_clamps_numbers() {
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join("config.toml");
+        fs::write(
+            &config_path,
+            "background_lightness = 1.8\n\n[auto]\nlightness = 5.5\nsaturation_min = 0.9\nsaturation_max = 0.5\n",
+        )
+        .unwrap();
+
+        let (config, warnings) = read_user_config(&config_path).unwrap();
+        assert_eq!(config.background_lightness, 1.0);
+        assert_eq!(config.lightness, 1.0);
+        assert_eq!((config.saturation_min, config.saturation_max), (0.5, 0.9));
+        // One warning for all of them
+        assert_eq!(
+            warnings,
+            [format!(
+                "invalid values in {}: auto.lightness = 5.5 is outside 0 to 1, using 1; \
+                 background_lightness = 1.8 is outside 0 to 1, using 1; \
+                 auto.saturation_min = 0.9 is above auto.saturation_max = 0.5, swapping them",
+                config_path.display()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_validate_numbers_strict() {
+        let path = Path::new("/home/me/.config/termtint/config.toml");
+        let mut table: toml::Table = toml::from_str("[auto]\nlightness = 0.5\n").unwrap();
+        assert_eq!(validate_numbers(&mut table, path), Ok(None));
+
+        let mut table: toml::Table = toml::from_str("[auto]\nlightness = 5.5\n").unwrap();
+        assert!(validate_numbers(&mut table, path).unwrap().is_some());
+
+        let mut table: toml::Table =
+            toml::from_str("strict = true\n\n[auto]\nlightness = 5.5\n").unwrap();
+        let error = validate_numbers(&mut table, path).unwrap_err();
+        assert_eq!(error.exit_code(), crate::error::EXIT_CONFIG);
+        assert_eq!(
+            error.to_string(),
+            "invalid values in /home/me/.config/termtint/config.toml: auto.lightness = 5.5 is outside 0 to 1"
+        );
+
+        let mut table: toml::Table =
+            toml::from_str("strict = true\n\n[auto]\nsaturation_min = 0.9\nsaturation_max = 0.5\n")
+                .unwrap();
+        let error = validate_numbers(&mut table, path).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid values in /home/me/.config/termtint/config.toml: auto.saturation_min = 0.9 is above auto.saturation_max = 0.5"
+        );
+    }
+
+    #[test]
+    fn test_load_config_with_lighten_mode() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r#"
+background_mode = "lighten"
+background_lightness_light = 0.9
+"#;
+        fs::write(&config_path, content).unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(config.background_mode, BackgroundMode::Lighten);
+        assert_eq!(config.background_lightness_light, 0.9);
+    }
+
+    #[test]
+    fn test_load_config_with_invalid_background_mode() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        fs::write(&config_path, "background_mode = \"sideways\"\n").unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(config.background_mode, BackgroundMode::Darken);
+    }
+
+    #[test]
+    fn test_load_config_with_auto_hash_source() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        fs::write(
+            &config_path,
+            "[auto]\nauto_hash_source = \"package-name\"\n",
+        )
+        .unwrap();
+        let config = load_user_config_from(&config_path).unwrap();
+        assert_eq!(config.auto_hash_source, AutoHashSource::PackageName);
+
+        fs::write(&config_path, "[auto]\nauto_hash_source = \"inode\"\n").unwrap();
+        let config = load_user_config_from(&config_path).unwrap();
+        assert_eq!(config.auto_hash_source, AutoHashSource::Path);
+    }
+
+    #[test]
+    fn test_parse_time_of_day() {
+        assert_eq!(parse_time_of_day("00:00"), Some(0));
+        assert_eq!(parse_time_of_day("07:30"), Some(450));
+        assert_eq!(parse_time_of_day("21:00"), Some(1260));
+        assert_eq!(parse_time_of_day("24:00"), None);
+        assert_eq!(parse_time_of_day("12:60"), None);
+        assert_eq!(parse_time_of_day("noon"), None);
+    }
+
+    #[test]
+    fn test_dimming_window_wraps_midnight() {
+        let dimming = DimmingConfig {
+            start: 21 * 60,
+            end: 7 * 60,
+            lightness_scale: 0.7,
+            dim_tab: false,
+        };
+
+        assert!(dimming.is_active_at(22 * 60));
+        assert!(dimming.is_active_at(0));
+        assert!(dimming.is_active_at(6 * 60 + 59));
+        assert!(!dimming.is_active_at(7 * 60));
+        assert!(!dimming.is_active_at(12 * 60));
+        assert!(dimming.is_active_at(21 * 60));
+    }
+
+    #[test]
+    fn test_dimming_window_same_day() {
+        let dimming = DimmingConfig {
+            start: 13 * 60,
+            end: 14 * 60,
+            lightness_scale: 0.7,
+            dim_tab: false,
+        };
+
+        assert!(dimming.is_active_at(13 * 60 + 30));
+        assert!(!dimming.is_active_at(12 * 60));
+        assert!(!dimming.is_active_at(14 * 60));
+    }
+
+    #[test]
+    fn test_load_config_with_dimming() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r#"
+[dimming]
+start = "21:00"
+end = "07:00"
+lightness_scale = 0.5
+"#;
+        fs::write(&config_path, content).unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        let dimming = config.dimming.expect("dimming should be configured");
+        assert_eq!(dimming.start, 1260);
+        assert_eq!(dimming.end, 420);
+        assert_eq!(dimming.lightness_scale, 0.5);
+        assert!(!dimming.dim_tab);
+    }
+
+    #[test]
+    fn test_presets_are_in_range() {
+        for preset in PRESETS {
+            for hue in [preset.hue_min, preset.hue_max] {
+                assert!((0.0..=360.0).contains(&hue), "{}", preset.name);
+            }
+            for value in [
+                preset.saturation_min,
+                preset.saturation_max,
+                preset.lightness,
+                preset.background_lightness,
+                preset.background_saturation,
+            ] {
+                assert!((0.0..=1.0).contains(&value), "{}", preset.name);
+            }
+            assert!(
+                preset.saturation_min <= preset.saturation_max,
+                "{}",
+                preset.name
+            );
+            // Backgrounds should stay darker than tabs
+            assert!(
+                preset.background_lightness < preset.lightness,
+                "{}",
+                preset.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_preset() {
+        assert_eq!(find_preset("Pastel").unwrap().name, "pastel");
+        assert!(find_preset("rainbow").is_none());
+    }
+
+    #[test]
+    fn test_load_config_with_preset() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r#"
+background_lightness = 0.1
+
+[auto]
+preset = "pastel"
+lightness = 0.7
+"#;
+        fs::write(&config_path, content).unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+        let pastel = find_preset("pastel").unwrap();
+
+        assert_eq!(config.saturation_min, pastel.saturation_min);
+        assert_eq!(config.background_saturation, pastel.background_saturation);
+        // Explicit keys win over the preset
+        assert_eq!(config.lightness, 0.7);
+        assert_eq!(config.background_lightness, 0.1);
+    }
+
+    #[test]
+    fn test_load_config_with_invalid_preset() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        fs::write(&config_path, "[auto]\npreset = \"rainbow\"\n").unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+        let defaults = UserConfig::default();
+
+        assert_eq!(config.saturation_min, defaults.saturation_min);
+        assert_eq!(config.lightness, defaults.lightness);
+    }
+
+    #[test]
+    fn test_load_config_with_monochrome() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r#"
+monochrome = true
+monochrome_lightness_min = 0.2
+monochrome_lightness_max = 1.5
+"#;
+        fs::write(&config_path, content).unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert!(config.monochrome);
+        assert_eq!(config.monochrome_lightness_min, 0.2);
+        assert_eq!(config.monochrome_lightness_max, 1.0);
+    }
+
+    #[test]
+    fn test_parse_session_ttl() {
+        assert_eq!(
+            parse_session_ttl("7d"),
+            Ok(Some(Duration::from_secs(7 * 86_400)))
+        );
+        assert_eq!(
+            parse_session_ttl("36h"),
+            Ok(Some(Duration::from_secs(36 * 3600)))
+        );
+        assert_eq!(parse_session_ttl("never"), Ok(None));
+        assert_eq!(parse_session_ttl(" Never "), Ok(None));
+        assert!(parse_session_ttl("0h").is_err());
+        assert!(parse_session_ttl("soon").is_err());
+        assert!(parse_session_ttl("7").is_err());
+    }
+
+    #[test]
+    fn test_configured_state_dir() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        assert_eq!(configured_state_dir_from(&config_path), None);
+
+        fs::write(&config_path, "state_dir = \"/tmp/termtint-state\"\n").unwrap();
+        assert_eq!(
+            configured_state_dir_from(&config_path).as_deref(),
+            Some("/tmp/termtint-state")
+        );
+        assert_eq!(
+            load_user_config_from(&config_path)
+                .unwrap()
+                .state_dir
+                .as_deref(),
+            Some("/tmp/termtint-state")
+        );
+
+        fs::write(&config_path, "state_dir = \"\"\n").unwrap();
+        assert_eq!(configured_state_dir_from(&config_path), None);
+        assert_eq!(load_user_config_from(&config_path).unwrap().state_dir, None);
+    }
+
+    #[test]
+    fn test_loaded_state_dir_is_per_context() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::write(&config_path, "state_dir = \"/tmp/termtint-state\"\n").unwrap();
+
+        let ctx = Context::new(temp.path(), temp.path());
+        load_user_config(&ctx).unwrap();
+        assert_eq!(state::state_root(&ctx), Path::new("/tmp/termtint-state"));
+
+        // Another context (a later test, or a daemon request after the config
+        // changed) resolves from the config as it is now
+        fs::write(&config_path, "state_dir = \"~/state\"\n").unwrap();
+        let ctx = Context::new(temp.path(), temp.path());
+        load_user_config(&ctx).unwrap();
+        assert_eq!(state::state_root(&ctx), temp.path().join("state"));
+
+        // The fast config has its own state_dir, or none
+        let ctx = Context::new(temp.path(), temp.path());
+        load_fast_user_config(&ctx).unwrap();
+        assert_eq!(
+            state::state_root(&ctx),
+            temp.path().join(".cache").join("termtint")
+        );
+    }
+
+    #[test]
+    fn test_load_config_with_session_ttl() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        assert_eq!(
+            load_user_config_from(&config_path).unwrap().session_ttl,
+            Some(STALE_SESSION_AGE)
+        );
+
+        fs::write(&config_path, "session_ttl = \"7d\"\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path).unwrap().session_ttl,
+            Some(Duration::from_secs(7 * 86_400))
+        );
+
+        fs::write(&config_path, "session_ttl = \"never\"\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path).unwrap().session_ttl,
+            None
+        );
+
+        // Invalid values fall back to the default
+        fs::write(&config_path, "session_ttl = \"forever\"\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path).unwrap().session_ttl,
+            Some(STALE_SESSION_AGE)
+        );
+    }
+
+    #[test]
+    fn test_load_config_with_cleanup_interval() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        assert_eq!(
+            load_user_config_from(&config_path)
+                .unwrap()
+                .cleanup_interval,
+            CLEANUP_INTERVAL
+        );
+
+        fs::write(&config_path, "cleanup_interval = \"0s\"\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path)
+                .unwrap()
+                .cleanup_interval,
+            Duration::ZERO
+        );
+
+        fs::write(&config_path, "cleanup_interval = \"hourly\"\n").unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path)
+                .unwrap()
+                .cleanup_interval,
+            CLEANUP_INTERVAL
+        );
+    }
+
+    #[test]
+    fn test_load_config_with_trigger_bands() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r#"
+trigger_files = ["Cargo.toml", "go.mod"]
+
+[[triggers.file]]
+name = "Cargo.toml"
+hue_min = 15.0
+hue_max = 45.0
+
+[[triggers.file]]
+name = "pyproject.toml"
+hue_min = 200.0
+hue_max = 400.0
+"#;
+        fs::write(&config_path, content).unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(
+            patterns(&config, TriggerKind::File),
+            ["Cargo.toml", "go.mod", "pyproject.toml"]
+        );
+        let cargo = config.trigger(TriggerKind::File, "Cargo.toml").unwrap();
+        assert_eq!((cargo.hue_min, cargo.hue_max), (Some(15.0), Some(45.0)));
+        let pyproject = config.trigger(TriggerKind::File, "pyproject.toml").unwrap();
+        assert_eq!(pyproject.hue_max, Some(360.0));
+        let go = config.trigger(TriggerKind::File, "go.mod").unwrap();
+        assert!(!go.has_hue_band());
+    }
+
+    #[test]
+    fn test_load_config_with_triggers_array() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r##"
+trigger_files = ["Cargo.toml", "go.mod"]
+trigger_dirnames = ["prod"]
+
+[[triggers]]
+match = "Cargo.toml"
+kind = "file"
+hue = [20, 50]
+
+[[triggers]]
+match = "~/Code/work/*"
+color = "#cc0000"
+
+[[triggers]]
+match = "release-*"
+kind = "dirname"
+color = "auto"
+
+[[triggers]]
+match = "flake.nix"
+kind = "folder"
+
+[[triggers]]
+match = "~/Code/bad/*"
+color = "not-a-color"
+"##;
+        fs::write(&config_path, content).unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(
+            config.triggers,
+            vec![
+                Trigger {
+                    hue_min: Some(20.0),
+                    hue_max: Some(50.0),
+                    ..Trigger::new(TriggerKind::File, "Cargo.toml")
+                },
+                Trigger {
+                    color: Some("#cc0000".to_string()),
+                    ..Trigger::new(TriggerKind::Path, "~/Code/work/*")
+                },
+                Trigger::new(TriggerKind::Dirname, "release-*"),
+                // Legacy entries follow, without repeating Cargo.toml
+                Trigger::new(TriggerKind::File, "go.mod"),
+                Trigger::new(TriggerKind::Dirname, "prod"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_config_with_content_triggers() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r#"
+[[triggers]]
+match = "Cargo.toml"
+toml_key = "workspace"
+
+[[triggers]]
+match = "package.json"
+contains = '"workspaces"'
+
+[[triggers]]
+match = "~/Code/*"
+contains = "anything"
+
+[[triggers]]
+match = "go.mod"
+background_lightness = 0.24
+background_saturation = 1.5
+"#;
+        fs::write(&config_path, content).unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(
+            config.triggers,
+            vec![
+                Trigger {
+                    toml_key: Some("workspace".to_string()),
+                    ..Trigger::new(TriggerKind::File, "Cargo.toml")
+                },
+                Trigger {
+                    contains: Some("\"workspaces\"".to_string()),
+                    ..Trigger::new(TriggerKind::File, "package.json")
+                },
+                Trigger {
+                    background_lightness: Some(0.24),
+                    background_saturation: Some(1.0),
+                    ..Trigger::new(TriggerKind::File, "go.mod")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trigger_kind_for_pattern() {
+        assert_eq!(TriggerKind::for_pattern("Cargo.toml"), TriggerKind::File);
+        assert_eq!(TriggerKind::for_pattern("*.cabal"), TriggerKind::File);
+        assert_eq!(TriggerKind::for_pattern("~/Code/*"), TriggerKind::Path);
+        assert_eq!(TriggerKind::for_pattern("/srv/*"), TriggerKind::Path);
+        assert_eq!(TriggerKind::for_pattern("!~/Code/tmp"), TriggerKind::Path);
+        assert_eq!(
+            TriggerKind::parse("kube-context"),
+            Some(TriggerKind::KubeContext)
+        );
+        assert_eq!(
+            TriggerKind::parse("kube_context"),
+            Some(TriggerKind::KubeContext)
+        );
+    }
+
+    #[test]
+    fn test_save_triggers_migrates_legacy_keys() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r#"
+background_lightness = 0.12
+trigger_files = ["Cargo.toml", "go.mod"]
+trigger_paths = ["~/Code/*", "!~/Code/tmp"]
+trigger_dirnames = ["prod"]
+
+[auto]
+hue_min = 10.0
+
+[[triggers.file]]
+name = "Cargo.toml"
+hue_min = 15.0
+hue_max = 45.0
+"#;
+        fs::write(&config_path, content).unwrap();
+        let loaded = load_user_config_from(&config_path).unwrap();
+
+        save_triggers_to(&config_path, &loaded.triggers).unwrap();
+        let saved = fs::read_to_string(&config_path).unwrap();
+        let reloaded = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(reloaded.triggers, loaded.triggers);
+        assert_eq!(reloaded.background_lightness, 0.12);
+        assert_eq!(reloaded.hue_min, 10.0);
+        assert!(saved.contains("[[triggers]]"));
+        assert!(!saved.contains("trigger_files"));
+        assert!(!saved.contains("trigger_paths"));
+        assert!(!saved.contains("[[triggers.file]]"));
+    }
+
+    #[test]
+    fn test_save_triggers_round_trip_options() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        let triggers = vec![
+            Trigger {
+                color: Some("#cc0000".to_string()),
+                ..Trigger::new(TriggerKind::Path, "~/Code/work/*")
+            },
+            Trigger {
+                hue_min: Some(200.0),
+                background_lightness: Some(0.25),
+                background_saturation: Some(0.5),
+                ..Trigger::new(TriggerKind::File, "pyproject.toml")
+            },
+            Trigger {
+                hue_min: Some(22.5),
+                hue_max: Some(45.0),
+                ..Trigger::new(TriggerKind::Dirname, "release-*")
+            },
+        ];
+
+        save_triggers_to(&config_path, &triggers).unwrap();
+        assert_eq!(
+            load_user_config_from(&config_path).unwrap().triggers,
+            triggers
+        );
+
+        // Removing the last trigger removes the array
+        save_triggers_to(&config_path, &[]).unwrap();
+        let saved = fs::read_to_string(&config_path).unwrap();
+        assert!(!saved.contains("triggers"));
+        assert!(load_user_config_from(&config_path)
+            .unwrap()
+            .triggers
+            .is_empty());
+    }
+
+    #[test]
+    fn test_with_hue_band() {
+        let config = UserConfig::default();
+        let band = Trigger {
+            hue_min: Some(60.0),
+            ..Trigger::new(TriggerKind::File, "package.json")
+        };
+        let banded = config.with_hue_band(&band);
+
+        assert_eq!(banded.hue_min, 60.0);
+        assert_eq!(banded.hue_max, config.hue_max);
+        assert_eq!(banded.lightness, config.lightness);
+    }
+
+    #[test]
+    fn test_load_config_with_hosts() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r##"
+[hosts]
+"prod-*" = "#cc0000"
+staging = "#cc8800"
+"##;
+        fs::write(&config_path, content).unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(
+            config.hosts,
+            vec![
+                ("prod-*".to_string(), "#cc0000".to_string()),
+                ("staging".to_string(), "#cc8800".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_config_with_env_triggers() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r##"
+env_trigger_priority = "after"
+
+[env_triggers]
+"KUBECONFIG=*prod*" = "auto"
+"AWS_PROFILE=prod*" = "#cc0000"
+"AWS_REGION=us-*" = "not-a-color"
+"##;
+        fs::write(&config_path, content).unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        // Key order, without the rule whose color is invalid
+        assert_eq!(
+            config.env_triggers,
+            vec![
+                ("AWS_PROFILE=prod*".to_string(), "#cc0000".to_string()),
+                ("KUBECONFIG=*prod*".to_string(), "auto".to_string()),
+            ]
+        );
+        assert_eq!(config.env_trigger_priority, EnvTriggerPriority::After);
+        assert_eq!(
+            UserConfig::default().env_trigger_priority,
+            EnvTriggerPriority::Before
+        );
+    }
+
+    #[test]
+    fn test_load_config_with_invalid_dimming_times() {
+        let temp = TempDir::new().unwrap();
+        let config_path = config_file_path_for_home(temp.path());
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+
+        let content = r#"
+[dimming]
+start = "9pm"
+end = "07:00"
+"#;
+        fs::write(&config_path, content).unwrap();
+
+        let config = load_user_config_from(&config_path).unwrap();
+
+        assert_eq!(config.dimming, None);
+    }
+
+    #[test]
+    fn test_hue_arc_normal_range() {
+        let config = UserConfig {
+            hue_min: 120.0,
+            hue_max: 240.0,
+            ..UserConfig::default()
+        };
+        assert_eq!(config.hue_arc(), 120.0);
+        assert_eq!(config.hue_at(0.0), 120.0);
+        assert_eq!(config.hue_at(0.5), 180.0);
+        assert_eq!(config.hue_at(1.0), 240.0);
+    }
+
+    #[test]
+    fn test_hue_arc_wraparound() {
+        let config = UserConfig {
+            hue_min: 300.0,
+            hue_max: 40.0,
+            ..UserConfig::default()
+        };
+        assert_eq!(config.hue_arc(), 100.0);
+        assert_eq!(config.hue_at(0.0), 300.0);
+        assert_eq!(config.hue_at(0.5), 350.0);
+        assert_eq!(config.hue_at(0.6), 0.0);
+        assert_eq!(config.hue_at(1.0), 40.0);
+    }
+
+    #[test]
+    fn test_load_config_with_hyperlinks() {
+        let temp = TempDir::new().unwrap();
+        let config_path = temp.path().join("config.toml");
+        assert_eq!(UserConfig::default().hyperlinks, Hyperlinks::Auto);
+
+        for (value, expected) in [
+            ("true", Hyperlinks::Always),
+            ("false", Hyperlinks::Never),
+            ("\"Auto\"", Hyperlinks::Auto),
+            ("\"sometimes\"", Hyperlinks::Auto),
+        ] {
+            fs::write(&config_path, format!("hyperlinks = {}\n", value)).unwrap();
+            assert_eq!(
+                load_user_config_from(&config_path).unwrap().hyperlinks,
+                expected
+            );
+        }
+    }
+
+    /// The keys a struct deserializes from, read from the field list serde
+    /// passes to `deserialize_struct`.
+    fn struct_keys<'de, T: serde::Deserialize<'de>>() -> Vec<&'static str> {
+        use serde::de::{self, Visitor};
+
+        struct Keys<'a>(&'a mut Vec<&'static str>);
+        impl<'de> de::Deserializer<'de> for Keys<'_> {
+            type Error = de::value::Error;
+
+            fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+                Err(de::Error::custom("not a struct"))
+            }
+
+            fn deserialize_struct<V: Visitor<'de>>(
+                self,
+                _name: &'static str,
+                fields: &'static [&'static str],
+                _visitor: V,
+            ) -> Result<V::Value, Self::Error> {
+                self.0.extend(fields);
+                Err(de::Error::custom("keys read"))
+            }
+
+            serde::forward_to_deserialize_any! {
+                bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+                bytes byte_buf option unit unit_struct newtype_struct seq tuple
+                tuple_struct map enum identifier ignored_any
+            }
+        }
+
+        let mut keys = Vec::new();
+        let _ = T::deserialize(Keys(&mut keys));
+        keys.sort_unstable();
+        keys
+    }
+
+    fn schema_keys(fields: &[ConfigField]) -> Vec<&'static str> {
+        let mut keys: Vec<&str> = fields.iter().map(|field| field.name).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    fn schema_table(name: &str) -> &'static [ConfigField] {
+        CONFIG_FIELDS
+            .iter()
+            .find(|field| field.name == name)
+            .unwrap()
+            .fields()
+    }
+
+    #[test]
+    fn test_schema_covers_every_config_key() {
+        assert_eq!(schema_keys(CONFIG_FIELDS), struct_keys::<UserConfigToml>());
+        assert_eq!(
+            schema_keys(schema_table("auto")),
+            struct_keys::<AutoConfig>()
+        );
+        assert_eq!(schema_table("auto").len(), AUTO_FIELDS.len());
+        assert_eq!(
+            schema_keys(schema_table("dimming")),
+            struct_keys::<DimmingToml>()
+        );
+        assert_eq!(
+            schema_keys(schema_table("triggers")),
+            struct_keys::<TriggerToml>()
+        );
+    }
+
+    #[test]
+    fn test_schema_defaults_match_user_config() {
+        let defaults = UserConfig::default();
+        for field in CONFIG_FIELDS.iter().chain(AUTO_FIELDS) {
+            if !field.is_value() {
+                continue;
+            }
+            let value = defaults.field_value(field.name);
+            if matches!(field.name, "state_dir" | "preset") {
+                // Unset by default; state_dir's default is the state directory fallback
+                assert_eq!(value, None, "{}", field.name);
+                continue;
+            }
+            let documented: toml::Table =
+                toml::from_str(&format!("value = {}", field.default.unwrap())).unwrap();
+            let documented = documented.get("value");
+            if let (Some(toml::Value::String(value)), Some(toml::Value::String(documented))) =
+                (&value, documented)
+            {
+                // Durations can be written in more than one unit ("1d" is "24h")
+                if let (Ok(value), Ok(documented)) =
+                    (parse_duration(value), parse_duration(documented))
+                {
+                    assert_eq!(value, documented, "{}", field.name);
+                    continue;
+                }
+            }
+            assert_eq!(value.as_ref(), documented, "{}", field.name);
+        }
+    }
 }
@@ -0,0 +1,157 @@
+//! Timing guard for the hot path: `termtint apply` runs on every `cd`, and most
+//! runs find the colors already applied.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+/// Unchanged applies averaging more than this fail the test. It is far above a
+/// normal run so a slow CI machine doesn't trip it, but catches a regression that
+/// adds real work (like a directory sweep or repeated config parsing) to every run.
+const UNCHANGED_APPLY_BUDGET: Duration = Duration::from_millis(50);
+
+const RUNS: u32 = 50;
+
+/// A `termtint apply` run with `args` in `dir`, isolated from the caller's environment.
+fn apply_command(home: &Path, dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_termtint"));
+    command
+        .arg("apply")
+        .args(args)
+        .current_dir(dir)
+        .env("HOME", home)
+        .env("TERMTINT_SESSION", "timing")
+        .env_remove("TERMTINT_STATE_DIR")
+        .env_remove("TERMTINT_NO_STATE")
+        .env_remove("TERMTINT_DISABLE")
+        .env_remove("TERMTINT_FAST_CONFIG")
+        .env_remove("KUBECONFIG");
+    command
+}
+
+fn apply(home: &Path, dir: &Path) -> Output {
+    apply_command(home, dir, &[])
+        .output()
+        .expect("failed to run termtint")
+}
+
+/// A home directory with a user config and a nested project directory colored by
+/// its `.termtint`. Returns the home and the nested directory.
+fn project_fixture(temp: &TempDir) -> (PathBuf, PathBuf) {
+    let home = temp.path().join("home");
+    let config_dir = home.join(".config").join("termtint");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(
+        config_dir.join("config.toml"),
+        "trigger_files = [\"Cargo.toml\", \"package.json\"]\n",
+    )
+    .unwrap();
+    let project = home.join("code").join("project");
+    let nested = project.join("src").join("deeply").join("nested");
+    fs::create_dir_all(&nested).unwrap();
+    fs::write(project.join(".termtint"), "#336699\n").unwrap();
+    (home, nested)
+}
+
+/// Average wall time of `RUNS` runs of `command`, each of which must emit nothing.
+fn time_unchanged_runs(command: &mut Command) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let output = command.output().expect("failed to run termtint");
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty(), "unchanged applies emit nothing");
+    }
+    start.elapsed() / RUNS
+}
+
+#[test]
+fn test_unchanged_apply_is_fast() {
+    let temp = TempDir::new().unwrap();
+    let (home, nested) = project_fixture(&temp);
+
+    let first = apply(&home, &nested);
+    assert!(first.status.success());
+    assert!(
+        first.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&first.stderr)
+    );
+    assert!(!first.stdout.is_empty(), "the first apply emits colors");
+
+    let average = time_unchanged_runs(&mut apply_command(&home, &nested, &[]));
+    eprintln!(
+        "unchanged apply: {:?} on average over {} runs",
+        average, RUNS
+    );
+    assert!(
+        average < UNCHANGED_APPLY_BUDGET,
+        "unchanged apply took {:?} on average, over the {:?} budget",
+        average,
+        UNCHANGED_APPLY_BUDGET
+    );
+}
+
+#[test]
+fn test_fast_apply_uses_fast_config_only() {
+    let temp = TempDir::new().unwrap();
+    let (home, _) = project_fixture(&temp);
+    // Colored only by the user config's trigger_files
+    let crate_dir = home.join("code").join("crate");
+    fs::create_dir_all(&crate_dir).unwrap();
+    fs::write(crate_dir.join("Cargo.toml"), "[package]\n").unwrap();
+
+    let fast = |fast_config: Option<&Path>| {
+        let mut command = apply_command(&home, &crate_dir, &["--fast", "--force"]);
+        if let Some(path) = fast_config {
+            command.env("TERMTINT_FAST_CONFIG", path);
+        }
+        let output = command.output().expect("failed to run termtint");
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    // The regular user config isn't read, so the trigger file is ignored
+    assert!(!fast(None).contains("bg;red"), "expected only a reset");
+
+    let fast_config = temp.path().join("fast.toml");
+    fs::write(&fast_config, "trigger_files = [\"Cargo.toml\"]\n").unwrap();
+    assert!(fast(Some(&fast_config)).contains("bg;red"));
+}
+
+#[test]
+fn test_fast_apply_skips_unchanged() {
+    let temp = TempDir::new().unwrap();
+    let (home, nested) = project_fixture(&temp);
+
+    let first = apply_command(&home, &nested, &["--fast"]).output().unwrap();
+    assert!(first.status.success());
+    assert!(!first.stdout.is_empty(), "the first apply emits colors");
+
+    let second = apply_command(&home, &nested, &["--fast"]).output().unwrap();
+    assert!(second.stdout.is_empty(), "unchanged applies emit nothing");
+}
+
+/// Compare unchanged `apply` and `apply --fast`. Run with
+/// `cargo test --release --test apply_timing -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_fast_apply() {
+    let temp = TempDir::new().unwrap();
+    let (home, nested) = project_fixture(&temp);
+
+    assert!(apply(&home, &nested).status.success());
+    let normal = time_unchanged_runs(&mut apply_command(&home, &nested, &[]));
+    assert!(apply_command(&home, &nested, &["--fast"])
+        .output()
+        .unwrap()
+        .status
+        .success());
+    let fast = time_unchanged_runs(&mut apply_command(&home, &nested, &["--fast"]));
+    eprintln!(
+        "unchanged apply: {:?}, apply --fast: {:?} ({} runs each)",
+        normal, fast, RUNS
+    );
+}
@@ -0,0 +1,154 @@
+//! `TERMTINT_CAPTURE` and `--capture`: the escape sequences termtint emits are
+//! appended to a file, so they can be checked without a terminal.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+/// A home directory with a project colored by its `.termtint`.
+fn fixture(temp: &TempDir) -> (PathBuf, PathBuf) {
+    let home = temp.path().join("home");
+    let project = home.join("code").join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join(".termtint"), "#336699\n").unwrap();
+    (home, project)
+}
+
+/// A `termtint` run with `args` in `dir`, isolated from the caller's environment.
+fn termtint(home: &Path, dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_termtint"));
+    command
+        .args(args)
+        .current_dir(dir)
+        .env("HOME", home)
+        .env("TERMTINT_SESSION", "capture-test")
+        .env_remove("ITERM_SESSION_ID")
+        .env_remove("TERMTINT_CAPTURE")
+        .env_remove("TERMTINT_STATE_DIR")
+        .env_remove("TERMTINT_NO_STATE")
+        .env_remove("TERMTINT_DISABLE")
+        .env_remove("TERMTINT_LOG")
+        .env_remove("KUBECONFIG");
+    command
+}
+
+fn run(command: &mut Command) -> Output {
+    let output = command.output().expect("failed to run termtint");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// The `# ` lines of a capture file: the sequences spelled out with escapes.
+fn escaped_lines(capture: &Path) -> Vec<String> {
+    fs::read_to_string(capture)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.strip_prefix("# "))
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn test_apply_captures_sequences() {
+    let temp = TempDir::new().unwrap();
+    let (home, project) = fixture(&temp);
+    let capture = temp.path().join("capture.log");
+
+    let output = run(termtint(&home, &project, &["apply"]).env("TERMTINT_CAPTURE", &capture));
+    let content = fs::read(&capture).unwrap();
+    assert!(!content.is_empty());
+    // Everything written to the terminal is in the file, raw and escaped
+    let emitted = String::from_utf8(output.stdout).unwrap();
+    let raw: String = String::from_utf8(content)
+        .unwrap()
+        .lines()
+        .filter(|line| !line.starts_with("# "))
+        .collect();
+    assert_eq!(raw, emitted);
+    let lines = escaped_lines(&capture);
+    assert_eq!(
+        lines[..3],
+        [
+            "\\x1b]6;1;bg;red;brightness;51\\x07",
+            "\\x1b]6;1;bg;green;brightness;102\\x07",
+            "\\x1b]6;1;bg;blue;brightness;153\\x07",
+        ]
+    );
+    assert!(lines[3].starts_with("\\x1b]11;rgb:"), "{:?}", lines);
+    assert_eq!(lines.len(), 4);
+
+    // Unchanged colors emit nothing, so nothing more is captured
+    run(termtint(&home, &project, &["apply"]).env("TERMTINT_CAPTURE", &capture));
+    assert_eq!(escaped_lines(&capture).len(), 4);
+}
+
+#[test]
+fn test_capture_only() {
+    let temp = TempDir::new().unwrap();
+    let (home, project) = fixture(&temp);
+    let capture = temp.path().join("capture.log");
+    let capture_arg = capture.to_str().unwrap();
+
+    let output = run(&mut termtint(
+        &home,
+        &project,
+        &["apply", "--capture", capture_arg, "--capture-only"],
+    ));
+    assert!(output.stdout.is_empty());
+    assert_eq!(escaped_lines(&capture).len(), 4);
+
+    // Other commands' sequences are captured too, appended to the same file
+    let output = run(&mut termtint(
+        &home,
+        &project,
+        &["reset", "--capture", capture_arg, "--capture-only"],
+    ));
+    assert!(output.stdout.is_empty());
+    let lines = escaped_lines(&capture);
+    assert_eq!(
+        lines[4..],
+        ["\\x1b]6;1;bg;*;default\\x07", "\\x1b]111\\x07"]
+    );
+}
+
+#[test]
+fn test_capture_palette() {
+    let temp = TempDir::new().unwrap();
+    let (home, project) = fixture(&temp);
+    let config_dir = home.join(".config").join("termtint");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(config_dir.join("config.toml"), "generate_palette = true\n").unwrap();
+    let capture = temp.path().join("capture.log");
+
+    run(termtint(&home, &project, &["apply", "--capture-only"]).env("TERMTINT_CAPTURE", &capture));
+    let palette: Vec<String> = escaped_lines(&capture)
+        .into_iter()
+        .filter(|line| line.starts_with("\\x1b]4;"))
+        .collect();
+    assert_eq!(palette.len(), 16);
+    assert!(palette[15].starts_with("\\x1b]4;15;rgb:"));
+}
+
+#[test]
+fn test_apply_without_a_lock_file_emits_every_time() {
+    let temp = TempDir::new().unwrap();
+    let (home, project) = fixture(&temp);
+    let state_dir = temp.path().join("state");
+    fs::create_dir_all(&state_dir).unwrap();
+    // No session directory, and so no lock file, can be created under this
+    fs::write(state_dir.join("sessions"), "").unwrap();
+    let capture = temp.path().join("capture.log");
+
+    for _ in 0..2 {
+        run(termtint(&home, &project, &["apply", "--capture-only"])
+            .env("TERMTINT_STATE_DIR", &state_dir)
+            .env("TERMTINT_CAPTURE", &capture));
+    }
+    assert_eq!(escaped_lines(&capture).len(), 8);
+}
@@ -0,0 +1,145 @@
+//! `--config` and `TERMTINT_CONFIG`: every command reads (and `trigger add`
+//! writes) an alternate user config file instead of the one in the home directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+/// A home directory with a user config, a project with no `.termtint` inside it,
+/// and an alternate config next to the project.
+fn fixture(temp: &TempDir) -> (PathBuf, PathBuf, PathBuf) {
+    let home = temp.path().join("home");
+    let project = home.join("code").join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join("Cargo.toml"), "[package]\n").unwrap();
+    let config_dir = home.join(".config").join("termtint");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(config_dir.join("config.toml"), "color_format = \"hex\"\n").unwrap();
+    let demo = project.join("demo.toml");
+    fs::write(&demo, "color_format = \"rgb\"\n").unwrap();
+    (home, project, demo)
+}
+
+/// A `termtint` run with `args` in `dir`, isolated from the caller's environment.
+fn termtint(home: &Path, dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_termtint"));
+    command
+        .args(args)
+        .current_dir(dir)
+        .env("HOME", home)
+        .env("TERMTINT_SESSION", "config-override-test")
+        .env_remove("ITERM_SESSION_ID")
+        .env_remove("TERMTINT_CONFIG")
+        .env_remove("TERMTINT_CAPTURE")
+        .env_remove("TERMTINT_STATE_DIR")
+        .env_remove("TERMTINT_NO_STATE")
+        .env_remove("TERMTINT_DISABLE")
+        .env_remove("TERMTINT_LOG")
+        .env_remove("KUBECONFIG");
+    command
+}
+
+fn run(command: &mut Command) -> Output {
+    let output = command.output().expect("failed to run termtint");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn stdout(output: Output) -> String {
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_config_path_precedence() {
+    let temp = TempDir::new().unwrap();
+    let (home, project, demo) = fixture(&temp);
+    let default = home.join(".config").join("termtint").join("config.toml");
+    let path = |command: &mut Command| stdout(run(command)).trim_end().to_string();
+
+    assert_eq!(
+        path(&mut termtint(&home, &project, &["config", "--path"])),
+        default.display().to_string()
+    );
+    let other = temp.path().join("other.toml");
+    assert_eq!(
+        path(termtint(&home, &project, &["config", "--path"]).env("TERMTINT_CONFIG", &other)),
+        other.display().to_string()
+    );
+    // The flag wins over the environment, and is taken from the working directory
+    assert_eq!(
+        path(
+            termtint(
+                &home,
+                &project,
+                &["--config", "demo.toml", "config", "--path"]
+            )
+            .env("TERMTINT_CONFIG", &other)
+        ),
+        project.join("demo.toml").display().to_string()
+    );
+    assert!(demo.exists());
+}
+
+#[test]
+fn test_config_shows_alternate_file() {
+    let temp = TempDir::new().unwrap();
+    let (home, project, demo) = fixture(&temp);
+
+    let shown = stdout(run(&mut termtint(&home, &project, &["config"])));
+    assert!(shown.contains("hex"), "{}", shown);
+    let shown = stdout(run(
+        termtint(&home, &project, &["config"]).env("TERMTINT_CONFIG", &demo)
+    ));
+    assert!(shown.contains(&demo.display().to_string()), "{}", shown);
+    assert!(shown.contains("rgb"), "{}", shown);
+}
+
+#[test]
+fn test_trigger_add_writes_alternate_file() {
+    let temp = TempDir::new().unwrap();
+    let (home, project, demo) = fixture(&temp);
+    let default = home.join(".config").join("termtint").join("config.toml");
+    let before = fs::read_to_string(&default).unwrap();
+
+    run(&mut termtint(
+        &home,
+        &project,
+        &["--config", "demo.toml", "trigger", "add", "Cargo.toml"],
+    ));
+    assert_eq!(fs::read_to_string(&default).unwrap(), before);
+    let written = fs::read_to_string(&demo).unwrap();
+    assert!(written.contains("Cargo.toml"), "{}", written);
+    assert!(written.contains("color_format = \"rgb\""), "{}", written);
+
+    // The trigger applies only with the alternate config
+    let listed = stdout(run(&mut termtint(
+        &home,
+        &project,
+        &["--config", "demo.toml", "trigger", "list"],
+    )));
+    assert!(listed.contains("Cargo.toml"), "{}", listed);
+    let applied = run(&mut termtint(
+        &home,
+        &project,
+        &["--config", "demo.toml", "apply", "--no-state"],
+    ));
+    assert!(!applied.stdout.is_empty());
+    let applied = run(&mut termtint(&home, &project, &["apply", "--no-state"]));
+    assert!(
+        !String::from_utf8_lossy(&applied.stdout).contains("brightness"),
+        "default config has no trigger"
+    );
+
+    run(&mut termtint(
+        &home,
+        &project,
+        &["--config", "demo.toml", "trigger", "remove", "Cargo.toml"],
+    ));
+    assert!(!fs::read_to_string(&demo).unwrap().contains("Cargo.toml"));
+}
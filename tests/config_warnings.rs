@@ -0,0 +1,135 @@
+//! The prompt hook reports a broken user config once per terminal session instead
+//! of at every prompt.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tempfile::TempDir;
+
+/// A home directory with an unparseable user config and a project inside it.
+/// Returns the home, the project, and the config file.
+fn fixture(temp: &TempDir) -> (PathBuf, PathBuf, PathBuf) {
+    let home = temp.path().join("home");
+    let project = home.join("code").join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join(".termtint"), "#336699\n").unwrap();
+    let config_dir = home.join(".config").join("termtint");
+    fs::create_dir_all(&config_dir).unwrap();
+    let config = config_dir.join("config.toml");
+    fs::write(&config, "color_format = \"hex\"\nstrict = \n").unwrap();
+    (home, project, config)
+}
+
+/// Run `termtint apply` with `args` in `project` for `session`, returning stderr.
+fn apply(home: &Path, project: &Path, session: &str, args: &[&str], log: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_termtint"))
+        .arg("apply")
+        .args(args)
+        .current_dir(project)
+        .env("HOME", home)
+        .env("TERMTINT_SESSION", session)
+        .env("TERMTINT_LOG", log)
+        .env_remove("ITERM_SESSION_ID")
+        .env_remove("TERMTINT_STATE_DIR")
+        .env_remove("TERMTINT_NO_STATE")
+        .env_remove("TERMTINT_DISABLE")
+        .env_remove("TERMTINT_LOG_FILE")
+        .env_remove("TERMTINT_FAST_CONFIG")
+        .env_remove("KUBECONFIG")
+        .output()
+        .expect("failed to run termtint");
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_parse_warning_once_per_session() {
+    let temp = TempDir::new().unwrap();
+    let (home, project, config) = fixture(&temp);
+    let warning = format!(
+        "termtint: warning: failed to parse {} (line 2: ",
+        config.display()
+    );
+
+    let first = apply(&home, &project, "tab-1", &[], "warn");
+    assert!(first.starts_with(&warning), "{}", first);
+    assert_eq!(first.lines().count(), 1, "{}", first);
+    assert_eq!(apply(&home, &project, "tab-1", &[], "warn"), "");
+    assert_eq!(apply(&home, &project, "tab-1", &[], "warn"), "");
+
+    // Still available through the log, and always shown when asked for
+    let logged = apply(&home, &project, "tab-1", &[], "info");
+    assert!(
+        logged.contains("(already reported in this session)"),
+        "{}",
+        logged
+    );
+    let verbose = apply(&home, &project, "tab-1", &["--verbose"], "warn");
+    assert!(verbose.contains(&warning), "{}", verbose);
+
+    // Each session gets told once
+    assert!(apply(&home, &project, "tab-2", &[], "warn").starts_with(&warning));
+    assert_eq!(apply(&home, &project, "tab-2", &[], "warn"), "");
+
+    // Fixing the config and breaking it again is reported again
+    fs::write(&config, "color_format = \"hex\"\n").unwrap();
+    assert_eq!(apply(&home, &project, "tab-1", &[], "warn"), "");
+    fs::write(&config, "color_format = \"cmyk\"\n").unwrap();
+    assert_eq!(
+        apply(&home, &project, "tab-1", &[], "warn"),
+        "termtint: warning: invalid color_format 'cmyk', using hex\n"
+    );
+    assert_eq!(apply(&home, &project, "tab-1", &[], "warn"), "");
+}
+
+#[test]
+fn test_config_command_always_warns() {
+    let temp = TempDir::new().unwrap();
+    let (home, project, config) = fixture(&temp);
+    assert!(!apply(&home, &project, "tab-1", &[], "warn").is_empty());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_termtint"))
+        .arg("config")
+        .current_dir(&project)
+        .env("HOME", &home)
+        .env("TERMTINT_SESSION", "tab-1")
+        .env("TERMTINT_LOG", "warn")
+        .env_remove("TERMTINT_STATE_DIR")
+        .env_remove("TERMTINT_LOG_FILE")
+        .output()
+        .expect("failed to run termtint");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("failed to parse {}", config.display())),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_strict_config_is_an_error() {
+    let temp = TempDir::new().unwrap();
+    let (home, project, config) = fixture(&temp);
+    fs::write(&config, "strict = true\n\n[auto]\nlightness = 5.5\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_termtint"))
+        .arg("apply")
+        .current_dir(&project)
+        .env("HOME", &home)
+        .env("TERMTINT_SESSION", "tab-1")
+        .env_remove("TERMTINT_STATE_DIR")
+        .env_remove("TERMTINT_LOG_FILE")
+        .env_remove("TERMTINT_FAST_CONFIG")
+        .output()
+        .expect("failed to run termtint");
+    assert_eq!(output.status.code(), Some(6));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "termtint: error: invalid values in {}: auto.lightness = 5.5 is outside 0 to 1\n",
+            config.display()
+        )
+    );
+    assert!(output.stdout.is_empty());
+}
@@ -0,0 +1,32 @@
+//! Guards the feature matrix: the default build must include every optional
+//! feature, so `cargo install termtint` always gets the full tool.
+
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn test_default_features_include_every_feature() {
+    let manifest_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    let manifest: toml::Table = fs::read_to_string(&manifest_path)
+        .expect("failed to read Cargo.toml")
+        .parse()
+        .expect("Cargo.toml is not valid TOML");
+
+    let features = manifest["features"]
+        .as_table()
+        .expect("Cargo.toml has no [features] table");
+    let default: Vec<&str> = features["default"]
+        .as_array()
+        .expect("default feature is not a list")
+        .iter()
+        .filter_map(|f| f.as_str())
+        .collect();
+
+    for name in features.keys().filter(|name| *name != "default") {
+        assert!(
+            default.contains(&name.as_str()),
+            "feature '{}' is missing from the default feature list",
+            name
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! The bash hook from `termtint hook bash`, sourced with a stand-in `termtint` on
+//! `PATH` that records whether the hook ran it.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+use tempfile::TempDir;
+
+/// Source the bash hook with `TERMTINT_DISABLE` set to `value` (or unset), and
+/// report whether it ran `termtint apply`.
+fn hook_runs_apply(value: Option<&str>) -> bool {
+    let temp = TempDir::new().unwrap();
+    let hook = Command::new(env!("CARGO_BIN_EXE_termtint"))
+        .args(["hook", "bash"])
+        .output()
+        .expect("failed to run termtint");
+    assert!(hook.status.success());
+    fs::write(temp.path().join("hook.bash"), &hook.stdout).unwrap();
+
+    let stub = temp.path().join("termtint");
+    let ran = temp.path().join("ran");
+    fs::write(&stub, format!("#!/bin/sh\ntouch '{}'\n", ran.display())).unwrap();
+    fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut command = Command::new("bash");
+    command
+        .args(["-c", r#"PATH="$1:$PATH"; source "$1/hook.bash""#, "bash"])
+        .arg(temp.path())
+        .env_remove("PROMPT_COMMAND");
+    match value {
+        Some(value) => command.env("TERMTINT_DISABLE", value),
+        None => command.env_remove("TERMTINT_DISABLE"),
+    };
+    let status = command.status().expect("failed to run bash");
+    assert!(status.success());
+    ran.exists()
+}
+
+#[test]
+fn test_hook_disable_values_match_apply() {
+    for value in [
+        None,
+        Some(""),
+        Some("0"),
+        Some("false"),
+        Some("No"),
+        Some("OFF"),
+    ] {
+        assert!(hook_runs_apply(value), "{:?}", value);
+    }
+    for value in ["1", "true", "yes"] {
+        assert!(!hook_runs_apply(Some(value)), "{:?}", value);
+    }
+}
+
+#[test]
+fn test_hook_leaves_padded_values_to_apply() {
+    // `apply` trims the value itself, so " off " must reach it
+    assert!(hook_runs_apply(Some(" off ")));
+}
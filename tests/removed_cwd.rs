@@ -0,0 +1,85 @@
+//! `termtint apply` from a working directory another process has deleted, as the
+//! prompt hook runs it after `rm -rf` of the directory the shell is in.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+/// A project colored by its `.termtint`, with a `build` directory inside it.
+fn fixture(temp: &TempDir) -> (PathBuf, PathBuf) {
+    let home = temp.path().join("home");
+    let project = home.join("code").join("project");
+    fs::create_dir_all(project.join("build")).unwrap();
+    fs::write(project.join(".termtint"), "#336699\n").unwrap();
+    (home, project)
+}
+
+/// Run `termtint apply` with `args` from inside `dir` after deleting it, the way
+/// a shell sitting in it would: `$PWD` still names it (unless `keep_pwd` is off).
+fn apply_in_removed(home: &Path, dir: &Path, args: &[&str], keep_pwd: bool) -> Output {
+    let script = if keep_pwd {
+        r#"cd "$1" && export PWD && rm -rf "$1" && shift && exec "$@""#
+    } else {
+        r#"cd "$1" && rm -rf "$1" && unset PWD && shift && exec "$@""#
+    };
+    Command::new("sh")
+        .args(["-c", script, "sh"])
+        .arg(dir)
+        .arg(env!("CARGO_BIN_EXE_termtint"))
+        .arg("apply")
+        .args(args)
+        .env("HOME", home)
+        .env("TERMTINT_SESSION", "removed-cwd")
+        .env_remove("ITERM_SESSION_ID")
+        .env_remove("TERMTINT_STATE_DIR")
+        .env_remove("TERMTINT_NO_STATE")
+        .env_remove("TERMTINT_DISABLE")
+        .env_remove("TERMTINT_LOG")
+        .env_remove("KUBECONFIG")
+        .output()
+        .expect("failed to run termtint")
+}
+
+#[test]
+fn test_apply_resolves_from_nearest_ancestor() {
+    let temp = TempDir::new().unwrap();
+    let (home, project) = fixture(&temp);
+
+    let output = apply_in_removed(&home, &project.join("build"), &["--verbose"], true);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("bg;red;brightness;51"),
+        "expected the project's colors"
+    );
+    assert!(
+        stderr.contains(&format!(
+            "build no longer exists, resolving from {}",
+            project.display()
+        )),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_apply_without_pwd_is_quiet() {
+    let temp = TempDir::new().unwrap();
+    let (home, project) = fixture(&temp);
+
+    let output = apply_in_removed(&home, &project.join("build"), &[], false);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert!(output.stdout.is_empty());
+
+    // Verbose runs still say why nothing happened
+    fs::create_dir(project.join("build")).unwrap();
+    let output = apply_in_removed(&home, &project.join("build"), &["--verbose"], false);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Error getting current directory"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
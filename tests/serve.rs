@@ -0,0 +1,283 @@
+//! End-to-end tests for `termtint serve` and `apply --via-daemon`: the daemon
+//! answers real clients, follows config edits, and removes its socket on exit.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+/// How long to wait for the daemon to start listening or to exit.
+const WAIT: Duration = Duration::from_secs(10);
+
+/// A `termtint` run with `args` in `dir`, isolated from the caller's environment
+/// and pointed at the test's socket.
+fn termtint(home: &Path, socket: &Path, dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_termtint"));
+    command
+        .args(args)
+        .current_dir(dir)
+        .env("HOME", home)
+        .env("TERMTINT_SOCKET", socket)
+        .env("TERMTINT_SESSION", "serve-test")
+        .env_remove("ITERM_SESSION_ID")
+        .env_remove("TERMTINT_STATE_DIR")
+        .env_remove("TERMTINT_NO_STATE")
+        .env_remove("TERMTINT_DISABLE")
+        .env_remove("TERMTINT_LOG")
+        .env_remove("KUBECONFIG");
+    command
+}
+
+/// A home directory with a project colored by its `.termtint`, and the socket path.
+fn fixture(temp: &TempDir) -> (PathBuf, PathBuf, PathBuf) {
+    let home = temp.path().join("home");
+    let project = home.join("code").join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join(".termtint"), "#336699\n").unwrap();
+    (home, project, temp.path().join("run").join("termtint.sock"))
+}
+
+/// Start `termtint serve` and wait until its socket accepts connections.
+fn start_daemon(home: &Path, socket: &Path) -> Child {
+    let mut daemon = termtint(home, socket, home, &["serve"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to start termtint serve");
+    let start = Instant::now();
+    while std::os::unix::net::UnixStream::connect(socket).is_err() {
+        if let Some(status) = daemon.try_wait().unwrap() {
+            panic!("termtint serve exited early: {}", status);
+        }
+        assert!(start.elapsed() < WAIT, "termtint serve never listened");
+        thread::sleep(Duration::from_millis(10));
+    }
+    daemon
+}
+
+/// Wait for the daemon to exit, returning whether it exited successfully.
+fn wait_for_exit(daemon: &mut Child) -> bool {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = daemon.try_wait().unwrap() {
+            return status.success();
+        }
+        if start.elapsed() > WAIT {
+            let _ = daemon.kill();
+            panic!("termtint serve didn't exit");
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_apply_via_daemon() {
+    let temp = TempDir::new().unwrap();
+    let (home, project, socket) = fixture(&temp);
+    let mut daemon = start_daemon(&home, &socket);
+    let apply = || {
+        stdout(
+            &termtint(&home, &socket, &project, &["apply", "--via-daemon"])
+                .output()
+                .unwrap(),
+        )
+    };
+
+    let first = apply();
+    assert!(first.contains("bg;red;brightness;51"), "{:?}", first);
+    assert_eq!(apply(), "", "unchanged applies emit nothing");
+
+    // The daemon's state is the session's state: an in-process apply sees the
+    // colors as already applied
+    let in_process = termtint(&home, &socket, &project, &["apply"])
+        .output()
+        .unwrap();
+    assert_eq!(stdout(&in_process), "");
+
+    // Editing the user config is picked up without restarting the daemon
+    let config_dir = home.join(".config").join("termtint");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(config_dir.join("config.toml"), "generate_palette = true\n").unwrap();
+    let palette = apply();
+    assert!(
+        palette.contains("]4;"),
+        "expected palette sequences: {:?}",
+        palette
+    );
+
+    let status = stdout(
+        &termtint(&home, &socket, &home, &["serve", "--status"])
+            .output()
+            .unwrap(),
+    );
+    assert!(status.contains("loaded 2 times"), "{}", status);
+
+    let stop = termtint(&home, &socket, &home, &["serve", "--stop"])
+        .output()
+        .unwrap();
+    assert_eq!(stdout(&stop), "stopping\n");
+    assert!(wait_for_exit(&mut daemon));
+    assert!(!socket.exists(), "socket left behind");
+}
+
+#[test]
+fn test_apply_via_daemon_without_daemon() {
+    let temp = TempDir::new().unwrap();
+    let (home, project, socket) = fixture(&temp);
+
+    let output = termtint(&home, &socket, &project, &["apply", "--via-daemon"])
+        .output()
+        .unwrap();
+    assert!(stdout(&output).contains("bg;red;brightness;51"));
+
+    let status = termtint(&home, &socket, &home, &["serve", "--status"])
+        .output()
+        .unwrap();
+    assert_eq!(status.status.code(), Some(7));
+}
+
+#[test]
+fn test_daemon_stops_on_sigterm() {
+    let temp = TempDir::new().unwrap();
+    let (home, _, socket) = fixture(&temp);
+    let mut daemon = start_daemon(&home, &socket);
+
+    // A second daemon on the same socket refuses to start
+    let second = termtint(&home, &socket, &home, &["serve"])
+        .output()
+        .unwrap();
+    assert_eq!(second.status.code(), Some(7));
+    assert!(socket.exists());
+
+    // SAFETY: kill has no memory-safety preconditions.
+    unsafe { libc::kill(daemon.id() as libc::pid_t, libc::SIGTERM) };
+    assert!(wait_for_exit(&mut daemon));
+    assert!(!socket.exists(), "socket left behind");
+}
+
+/// Stop the daemon with `serve --stop` and wait for it to exit.
+fn stop_daemon(home: &Path, socket: &Path, daemon: &mut Child) {
+    let stop = termtint(home, socket, home, &["serve", "--stop"])
+        .output()
+        .unwrap();
+    assert_eq!(stdout(&stop), "stopping\n");
+    assert!(wait_for_exit(daemon));
+}
+
+#[test]
+fn test_apply_via_daemon_falls_back_for_options() {
+    let temp = TempDir::new().unwrap();
+    let (home, project, socket) = fixture(&temp);
+    let mut daemon = start_daemon(&home, &socket);
+    let apply = |args: &[&str]| {
+        let mut all = vec!["apply", "--via-daemon"];
+        all.extend_from_slice(args);
+        termtint(&home, &socket, &project, &all).output().unwrap()
+    };
+
+    assert!(stdout(&apply(&[])).contains("bg;red;brightness;51"));
+    // The daemon answers with nothing, as the colors are applied; the options
+    // only take effect in-process
+    assert_eq!(stdout(&apply(&[])), "");
+    assert!(stdout(&apply(&["--force"])).contains("bg;red;brightness;51"));
+    let verbose = apply(&["--verbose"]);
+    assert_eq!(stdout(&verbose), "");
+    assert!(!verbose.stderr.is_empty(), "expected verbose output");
+    let explained = apply(&["--explain"]);
+    assert!(
+        String::from_utf8_lossy(&explained.stderr).contains("Session cleanup"),
+        "{}",
+        String::from_utf8_lossy(&explained.stderr)
+    );
+
+    stop_daemon(&home, &socket, &mut daemon);
+}
+
+#[test]
+fn test_apply_via_daemon_uses_client_config() {
+    let temp = TempDir::new().unwrap();
+    let (home, project, socket) = fixture(&temp);
+    let mut daemon = start_daemon(&home, &socket);
+    let demo = project.join("demo.toml");
+    fs::write(&demo, "generate_palette = true\n").unwrap();
+
+    // The daemon has the default config, without a palette
+    let output = termtint(
+        &home,
+        &socket,
+        &project,
+        &["--config", "demo.toml", "apply", "--via-daemon"],
+    )
+    .output()
+    .unwrap();
+    assert!(stdout(&output).contains("]4;"), "expected the demo config");
+
+    let output = termtint(&home, &socket, &project, &["apply", "--via-daemon"])
+        .env("TERMTINT_SESSION", "serve-test-2")
+        .env("TERMTINT_CONFIG", &demo)
+        .output()
+        .unwrap();
+    assert!(stdout(&output).contains("]4;"), "expected the demo config");
+
+    // Without it, the daemon answers
+    let output = termtint(&home, &socket, &project, &["apply", "--via-daemon"])
+        .env("TERMTINT_SESSION", "serve-test-3")
+        .output()
+        .unwrap();
+    let output = stdout(&output);
+    assert!(output.contains("bg;red;brightness;51"), "{:?}", output);
+    assert!(!output.contains("]4;"), "{:?}", output);
+
+    stop_daemon(&home, &socket, &mut daemon);
+}
+
+#[test]
+fn test_daemon_rereads_trigger_contents_per_request() {
+    let temp = TempDir::new().unwrap();
+    let (home, _, socket) = fixture(&temp);
+    let config_dir = home.join(".config").join("termtint");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(
+        config_dir.join("config.toml"),
+        "[[triggers]]\nmatch = \"workspace.toml\"\nkind = \"file\"\ntoml_key = \"workspace\"\ncolor = \"#cc0000\"\n",
+    )
+    .unwrap();
+    let project = home.join("code").join("members");
+    fs::create_dir_all(&project).unwrap();
+    let manifest = project.join("workspace.toml");
+    // Edits that keep the modification time, as a coarse-grained filesystem would
+    let write_manifest = |content: &str| {
+        fs::write(&manifest, content).unwrap();
+        filetime::set_file_mtime(&manifest, filetime::FileTime::from_unix_time(1_000_000, 0))
+            .unwrap();
+    };
+    let mut daemon = start_daemon(&home, &socket);
+    let apply = || {
+        stdout(
+            &termtint(&home, &socket, &project, &["apply", "--via-daemon"])
+                .output()
+                .unwrap(),
+        )
+    };
+
+    write_manifest("[package]\n");
+    assert!(!apply().contains("bg;red;brightness;204"));
+
+    write_manifest("[workspace]\n");
+    let output = apply();
+    assert!(output.contains("bg;red;brightness;204"), "{:?}", output);
+
+    stop_daemon(&home, &socket, &mut daemon);
+}